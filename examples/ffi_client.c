@@ -0,0 +1,34 @@
+#include <stdio.h>
+
+#include "../include/drow.h"
+
+static void print_object(const char *path, uint64_t base, void *user_data) {
+    int *count = user_data;
+    printf("%d: %s at 0x%lx\n", (*count)++, path, (unsigned long) base);
+}
+
+int main(int argc, char **argv) {
+    const char *path = argc > 1 ? argv[1] : "libexample.so";
+    drow_loader *loader = drow_loader_new();
+    if (loader == NULL) {
+        fprintf(stderr, "drow_loader_new: %s\n", drow_last_error_message());
+        return 1;
+    }
+    drow_handle *handle;
+    if (drow_load(loader, path, &handle) != DROW_OK) {
+        fprintf(stderr, "drow_load: %s\n", drow_last_error_message());
+        drow_loader_free(loader);
+        return 1;
+    }
+    int count = 0;
+    drow_list_objects(handle, print_object, &count);
+    uint64_t address;
+    if (drow_lookup_symbol(handle, "my_strlen", &address) == DROW_OK) {
+        int (*my_strlen)(const char *) = (int (*)(const char *)) address;
+        printf("my_strlen(\"drow\") = %d\n", my_strlen("drow"));
+    } else {
+        printf("drow_lookup_symbol: %s\n", drow_last_error_message());
+    }
+    drow_loader_free(loader);
+    return 0;
+}
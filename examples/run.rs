@@ -0,0 +1,13 @@
+//! Loads an executable with its dependencies and runs it in the current process.
+//!
+//! cargo run --example run -- <file>
+
+use drow::loader::Elf64Loader;
+use drow::DrowError;
+
+fn main() -> Result<(), DrowError> {
+    let path = std::env::args().nth(1).expect("Usage: run <file>");
+    let loader = Elf64Loader::with_defaults()?;
+    loader.load_file(&path)?;
+    loader.execute_same_process()
+}
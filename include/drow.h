@@ -0,0 +1,61 @@
+/*
+ * C API of drow, built into libdrow.so with `cargo build --features ffi`.
+ *
+ * Strings passed in and out are UTF-8 and NUL terminated; those passed in stay owned by the
+ * caller. Handles belong to the loader that returned them and are freed with it. A function
+ * failing returns a status other than DROW_OK and leaves a message that
+ * drow_last_error_message returns, also when drow panicked, which never unwinds into the caller.
+ */
+
+#ifndef DROW_H
+#define DROW_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define DROW_OK 0
+/* A null pointer or a string that is not UTF-8 was passed. */
+#define DROW_ERROR_INVALID_ARGUMENT 1
+/* drow failed, as the message tells. */
+#define DROW_ERROR_LOAD 2
+/* The symbol looked up is defined nowhere in the scope of the handle. */
+#define DROW_ERROR_NOT_FOUND 3
+#define DROW_ERROR_PANIC 4
+
+typedef struct DrowLoader drow_loader;
+typedef struct DrowHandle drow_handle;
+
+/* Called with the path and load bias of each object. The path is valid during the call only. */
+typedef void (*drow_object_callback)(const char *path, uint64_t base, void *user_data);
+
+/* A loader searching the system library cache and LD_LIBRARY_PATH, null when it cannot be
+ * created. */
+drow_loader *drow_loader_new(void);
+
+/* Unmaps everything the loader loaded and frees it with its handles. Null is ignored. */
+void drow_loader_free(drow_loader *loader);
+
+/* Loads the file at path, or the library of that name when it has no '/', with its
+ * dependencies, and stores a handle to it in *handle. The objects are mapped and relocated,
+ * their init functions are not run. */
+int drow_load(drow_loader *loader, const char *path, drow_handle **handle);
+
+/* Looks name, optionally versioned as name@VERSION, up in the object of handle and then in its
+ * dependencies, breadth first, and stores its address in *address. */
+int drow_lookup_symbol(const drow_handle *handle, const char *name, uint64_t *address);
+
+/* Calls callback with the object of handle and then each of its dependencies, breadth first. */
+int drow_list_objects(const drow_handle *handle, drow_object_callback callback, void *user_data);
+
+/* The message of the last call that failed on the calling thread, null when none did. Owned by
+ * drow, valid until another call fails on the thread. */
+const char *drow_last_error_message(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
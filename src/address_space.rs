@@ -0,0 +1,248 @@
+//! Picks the base addresses of the objects, around what the process has mapped already: drow's
+//! own executable, heap, libraries and stack, and the vDSO.
+
+use crate::DrowError;
+
+const PROC_SELF_MAPS: &str = "/proc/self/maps";
+
+/// Not always listed in /proc/self/maps, but never available for mapping.
+const VSYSCALL: (u64, u64) = (0xffff_ffff_ff60_0000, 0xffff_ffff_ff60_1000);
+
+/// End of the lower half of the x86-64 address space, the part user space can map.
+pub const USER_SPACE_END: u64 = 0x7fff_ffff_f000;
+
+/// Where objects with 32-bit absolute relocations are placed from, above the conventional base
+/// of non-PIE executables.
+const LOW_WINDOW_START: u64 = 0x40_0000;
+
+/// End of the low window. Below 2 GiB every address fits in both R_X86_64_32 and R_X86_64_32S,
+/// the range MAP_32BIT hands out as well.
+pub const LOW_WINDOW_END: u64 = 0x8000_0000;
+
+/// The ranges of /proc/self/maps, as `(start, end)` pairs.
+pub fn parse_maps(content: &str) -> Vec<(u64, u64)> {
+    content
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .filter_map(|range| range.split_once('-'))
+        .filter_map(|(start, end)| {
+            let start = u64::from_str_radix(start, 16).ok()?;
+            let end = u64::from_str_radix(end, 16).ok()?;
+            Some((start, end))
+        })
+        .collect()
+}
+
+fn read_maps() -> Vec<(u64, u64)> {
+    match std::fs::read_to_string(PROC_SELF_MAPS) {
+        Ok(content) => parse_maps(&content),
+        Err(err) => {
+            warn!(
+                "Unable to read {}, objects may be mapped over drow: {}",
+                PROC_SELF_MAPS, err
+            );
+            Vec::new()
+        }
+    }
+}
+
+/// Sorts `ranges` and merges the overlapping or adjacent ones.
+fn merge(mut ranges: Vec<(u64, u64)>) -> Vec<(u64, u64)> {
+    ranges.retain(|(start, end)| start < end);
+    ranges.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// Hands out base addresses upwards from a start address, skipping the occupied ranges. These
+/// are read from /proc/self/maps on the first reservation, unless given upfront. Objects that
+/// must sit below 2 GiB are given bases from a separate low window.
+pub struct AddressSpace {
+    next: u64,
+    next_low: u64,
+    page_size: u64,
+    occupied: Option<Vec<(u64, u64)>>,
+}
+
+impl AddressSpace {
+    pub fn new(start: u64, page_size: u64) -> AddressSpace {
+        AddressSpace {
+            next: start,
+            next_low: LOW_WINDOW_START,
+            page_size,
+            occupied: None,
+        }
+    }
+
+    /// Like [`AddressSpace::new`], taking `occupied` as what the process has mapped instead of
+    /// reading /proc/self/maps.
+    pub fn with_occupied(
+        start: u64,
+        page_size: u64,
+        mut occupied: Vec<(u64, u64)>,
+    ) -> AddressSpace {
+        occupied.push(VSYSCALL);
+        AddressSpace {
+            occupied: Some(merge(occupied)),
+            ..AddressSpace::new(start, page_size)
+        }
+    }
+
+    /// Where the search for the next base starts.
+    pub fn next(&self) -> u64 {
+        self.next
+    }
+
+    fn occupied(&mut self) -> &mut Vec<(u64, u64)> {
+        self.occupied.get_or_insert_with(|| {
+            let mut occupied = read_maps();
+            occupied.push(VSYSCALL);
+            merge(occupied)
+        })
+    }
+
+    /// The lowest base, at or above the next one and a multiple of both `alignment` and the page
+    /// size, that puts the range from `low` to `high` of an object clear of the occupied ranges.
+    /// The range is taken, and the search for the next base resumes after it. `path` names the
+    /// object in the error when no gap is large enough.
+    pub fn reserve(
+        &mut self,
+        path: &str,
+        low: u64,
+        high: u64,
+        alignment: u64,
+    ) -> Result<u64, DrowError> {
+        let base = self.reserve_below(path, self.next, low, high, alignment, USER_SPACE_END)?;
+        self.next = round_up(base + high, self.page_size).unwrap_or(USER_SPACE_END);
+        Ok(base)
+    }
+
+    /// Like [`AddressSpace::reserve`], but in the low window, so that the whole object ends
+    /// below [`LOW_WINDOW_END`].
+    pub fn reserve_low(
+        &mut self,
+        path: &str,
+        low: u64,
+        high: u64,
+        alignment: u64,
+    ) -> Result<u64, DrowError> {
+        let base = self.reserve_below(path, self.next_low, low, high, alignment, LOW_WINDOW_END)?;
+        self.next_low = round_up(base + high, self.page_size).unwrap_or(LOW_WINDOW_END);
+        Ok(base)
+    }
+
+    /// Takes the range from `low` to `high` of an object placed at exactly `base`, failing when
+    /// `base` is not page aligned or the range overlaps an occupied one. Later bases are still
+    /// searched from where they were.
+    pub fn reserve_at(
+        &mut self,
+        path: &str,
+        base: u64,
+        low: u64,
+        high: u64,
+    ) -> Result<u64, DrowError> {
+        let range = base
+            .checked_add(low)
+            .zip(base.checked_add(high))
+            .filter(|(_, end)| *end <= USER_SPACE_END);
+        let (start, end) = match range {
+            Some(range) if base.is_multiple_of(self.page_size) => range,
+            _ => {
+                return Err(DrowError::NotLoadable {
+                    path: path.to_string(),
+                    reason: format!("{:#X} is not a usable base address", base),
+                })
+            }
+        };
+        let occupied = self.occupied();
+        if let Some((occupied_start, occupied_end)) = occupied
+            .iter()
+            .find(|(occupied_start, occupied_end)| start < *occupied_end && *occupied_start < end)
+        {
+            return Err(DrowError::NotLoadable {
+                path: path.to_string(),
+                reason: format!(
+                    "{:#X}-{:#X} overlaps the mapping at {:#X}-{:#X}",
+                    start, end, occupied_start, occupied_end
+                ),
+            });
+        }
+        occupied.push((start, end));
+        *occupied = merge(std::mem::take(occupied));
+        Ok(base)
+    }
+
+    fn reserve_below(
+        &mut self,
+        path: &str,
+        next: u64,
+        low: u64,
+        high: u64,
+        alignment: u64,
+        limit: u64,
+    ) -> Result<u64, DrowError> {
+        let alignment = alignment.max(self.page_size);
+        let base = round_up(next, alignment)
+            .and_then(|base| find_base(self.occupied(), base, low, high, alignment, limit));
+        let base = base.ok_or_else(|| DrowError::NotLoadable {
+            path: path.to_string(),
+            reason: if limit == USER_SPACE_END {
+                format!(
+                    "no free range of {:#X} bytes above {:#X}",
+                    high.saturating_sub(low),
+                    next
+                )
+            } else {
+                format!(
+                    "no free range of {:#X} bytes between {:#X} and {:#X}",
+                    high.saturating_sub(low),
+                    next,
+                    limit
+                )
+            },
+        })?;
+        let occupied = self.occupied();
+        occupied.push((base + low, base + high));
+        *occupied = merge(std::mem::take(occupied));
+        Ok(base)
+    }
+}
+
+fn round_up(value: u64, alignment: u64) -> Option<u64> {
+    match value % alignment {
+        0 => Some(value),
+        remainder => value.checked_add(alignment - remainder),
+    }
+}
+
+/// Moves `base` up past every occupied range the object would overlap, until it fits or would
+/// end above `limit`.
+fn find_base(
+    occupied: &[(u64, u64)],
+    mut base: u64,
+    low: u64,
+    high: u64,
+    alignment: u64,
+    limit: u64,
+) -> Option<u64> {
+    loop {
+        let start = base.checked_add(low)?;
+        let end = base.checked_add(high)?;
+        if end > limit {
+            return None;
+        }
+        match occupied
+            .iter()
+            .find(|(occupied_start, occupied_end)| start < *occupied_end && *occupied_start < end)
+        {
+            Some((_, occupied_end)) => base = round_up(occupied_end - low, alignment)?,
+            None => return Some(base),
+        }
+    }
+}
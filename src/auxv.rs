@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+pub const AT_NULL: u64 = 0;
+pub const AT_PHDR: u64 = 3;
+pub const AT_PHENT: u64 = 4;
+pub const AT_PHNUM: u64 = 5;
+pub const AT_PAGESZ: u64 = 6;
+pub const AT_HWCAP: u64 = 16;
+pub const AT_SECURE: u64 = 23;
+pub const AT_RANDOM: u64 = 25;
+pub const AT_HWCAP2: u64 = 26;
+pub const AT_SYSINFO_EHDR: u64 = 33;
+
+const PROC_SELF_AUXV: &str = "/proc/self/auxv";
+
+#[cfg(target_env = "gnu")]
+const KNOWN_TYPES: [u64; 6] = [
+    AT_PAGESZ,
+    AT_HWCAP,
+    AT_SECURE,
+    AT_RANDOM,
+    AT_HWCAP2,
+    AT_SYSINFO_EHDR,
+];
+
+static CURRENT: OnceLock<AuxiliaryVector> = OnceLock::new();
+
+pub struct AuxiliaryVector {
+    entries: HashMap<u64, u64>,
+}
+
+impl AuxiliaryVector {
+    pub fn parse(bytes: &[u8]) -> AuxiliaryVector {
+        let mut entries = HashMap::new();
+        for entry in bytes.chunks_exact(16) {
+            let mut entry_type = [0u8; 8];
+            let mut value = [0u8; 8];
+            entry_type.copy_from_slice(&entry[..8]);
+            value.copy_from_slice(&entry[8..]);
+            let entry_type = u64::from_ne_bytes(entry_type);
+            if entry_type == AT_NULL {
+                break;
+            }
+            entries.insert(entry_type, u64::from_ne_bytes(value));
+        }
+        AuxiliaryVector { entries }
+    }
+
+    pub fn from_proc() -> Result<AuxiliaryVector, String> {
+        std::fs::read(PROC_SELF_AUXV)
+            .map(|bytes| AuxiliaryVector::parse(&bytes))
+            .map_err(|err| format!("Unable to read {}: {}", PROC_SELF_AUXV, err))
+    }
+
+    #[cfg(target_env = "gnu")]
+    fn from_getauxval() -> AuxiliaryVector {
+        let mut entries = HashMap::new();
+        for entry_type in KNOWN_TYPES.iter() {
+            unsafe {
+                *libc::__errno_location() = 0;
+                let value = libc::getauxval(*entry_type);
+                if value != 0 || *libc::__errno_location() != libc::ENOENT {
+                    entries.insert(*entry_type, value);
+                }
+            }
+        }
+        AuxiliaryVector { entries }
+    }
+
+    /// getauxval first, then /proc/self/auxv for the types it did not report, as when the
+    /// process was started by a loader that passed glibc no auxiliary vector.
+    #[cfg(target_env = "gnu")]
+    fn load_current() -> AuxiliaryVector {
+        let mut current = AuxiliaryVector::from_getauxval();
+        if KNOWN_TYPES
+            .iter()
+            .all(|entry_type| current.entries.contains_key(entry_type))
+        {
+            return current;
+        }
+        match AuxiliaryVector::from_proc() {
+            Ok(fallback) => {
+                for entry_type in KNOWN_TYPES.iter() {
+                    if let (None, Some(value)) =
+                        (current.get(*entry_type), fallback.get(*entry_type))
+                    {
+                        debug!(
+                            "{:#x} of the auxiliary vector read from {}",
+                            entry_type, PROC_SELF_AUXV
+                        );
+                        current.entries.insert(*entry_type, value);
+                    }
+                }
+            }
+            Err(err) => debug!("{}", err),
+        }
+        current
+    }
+
+    #[cfg(not(target_env = "gnu"))]
+    fn load_current() -> AuxiliaryVector {
+        AuxiliaryVector::from_proc().unwrap_or_else(|err| {
+            warn!("{}", err);
+            AuxiliaryVector {
+                entries: HashMap::new(),
+            }
+        })
+    }
+
+    pub fn get(&self, entry_type: u64) -> Option<u64> {
+        self.entries.get(&entry_type).cloned()
+    }
+
+    pub fn page_size(&self) -> Option<u64> {
+        self.get(AT_PAGESZ)
+    }
+
+    pub fn hwcap(&self) -> Option<u64> {
+        self.get(AT_HWCAP)
+    }
+
+    pub fn hwcap2(&self) -> Option<u64> {
+        self.get(AT_HWCAP2)
+    }
+
+    pub fn secure(&self) -> Option<bool> {
+        self.get(AT_SECURE).map(|value| value != 0)
+    }
+
+    pub fn sysinfo_ehdr(&self) -> Option<u64> {
+        self.get(AT_SYSINFO_EHDR)
+    }
+
+    pub fn random(&self) -> Option<u64> {
+        self.get(AT_RANDOM)
+    }
+}
+
+pub fn current() -> &'static AuxiliaryVector {
+    CURRENT.get_or_init(AuxiliaryVector::load_current)
+}
@@ -0,0 +1,215 @@
+use crate::cli::Config;
+use crate::report::json_string;
+use crate::{loader_builder, open};
+use drow::loader::DependenciesResolver;
+use drow::table::Table;
+use drow::{warn, DrowError, Elf64Metadata};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+struct Statistics {
+    mean: f64,
+    median: f64,
+    stddev: f64,
+}
+
+impl Statistics {
+    /// Sample statistics of `values`, which must not be empty.
+    fn of(values: &[f64]) -> Statistics {
+        let count = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / count;
+        let mut sorted = values.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let middle = sorted.len() / 2;
+        let median = if sorted.len().is_multiple_of(2) {
+            (sorted[middle - 1] + sorted[middle]) / 2.0
+        } else {
+            sorted[middle]
+        };
+        let variance = if values.len() > 1 {
+            values
+                .iter()
+                .map(|value| (value - mean) * (value - mean))
+                .sum::<f64>()
+                / (count - 1.0)
+        } else {
+            0.0
+        };
+        Statistics {
+            mean,
+            median,
+            stddev: variance.sqrt(),
+        }
+    }
+}
+
+/// Durations of one phase over all iterations, in microseconds.
+struct Phase {
+    name: &'static str,
+    description: &'static str,
+    samples: Vec<f64>,
+}
+
+impl Phase {
+    fn new(name: &'static str, description: &'static str) -> Phase {
+        Phase {
+            name,
+            description,
+            samples: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, duration: Duration) {
+        self.samples.push(duration.as_secs_f64() * 1e6);
+    }
+}
+
+struct Bench<'a> {
+    config: &'a Config,
+    file_path: &'a String,
+    resolver: Option<DependenciesResolver>,
+    exit_status: Option<i32>,
+}
+
+impl Bench<'_> {
+    /// Parses and loads the file without running it, then unmaps everything. Returns the time
+    /// of each loader phase and of the whole load.
+    fn load(&mut self) -> Result<[Duration; 4], DrowError> {
+        let started = Instant::now();
+        let mut reader = open(self.file_path)?;
+        let elf_metadata = Elf64Metadata::load(self.file_path, &mut reader)?;
+        let builder = loader_builder(self.config);
+        let resolver = self
+            .resolver
+            .take()
+            .unwrap_or_else(|| builder.dependencies_resolver());
+        let elf_loader = builder.build_with(resolver)?;
+        let result = self
+            .config
+            .preload
+            .iter()
+            .try_for_each(|library| elf_loader.preload(library))
+            .and_then(|_| elf_loader.load(&elf_metadata));
+        let total = started.elapsed();
+        let phases = elf_loader.phase_times();
+        self.resolver = Some(elf_loader.into_dependencies_resolver());
+        result?;
+        Ok([phases.resolve, phases.map, phases.relocate, total])
+    }
+
+    /// Runs the file through the system loader with the program arguments given after `--`.
+    fn exec(&mut self) -> Result<Duration, DrowError> {
+        let started = Instant::now();
+        let status = Command::new(self.file_path)
+            .args(self.config.program_arguments.iter())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_err(|source| DrowError::Io {
+                path: self.file_path.clone(),
+                source,
+            })?;
+        let elapsed = started.elapsed();
+        if self.exit_status.is_none() {
+            let code = status.code().unwrap_or(-1);
+            if code != 0 {
+                warn!(
+                    "{} exited with status {} under the system loader, pass arguments after -- \
+                     that make it exit early",
+                    self.file_path, code
+                );
+            }
+            self.exit_status = Some(code);
+        }
+        Ok(elapsed)
+    }
+}
+
+fn print_table(phases: &[Phase], color: bool) {
+    let mut table = Table::new(&["Phase", "Mean (us)", "Median (us)", "Stddev (us)"]);
+    for phase in phases.iter() {
+        let statistics = Statistics::of(&phase.samples);
+        table.add_row(vec![
+            phase.description.to_string(),
+            format!("{:.1}", statistics.mean),
+            format!("{:.1}", statistics.median),
+            format!("{:.1}", statistics.stddev),
+        ]);
+    }
+    print!("{}", table.render(color));
+    println!();
+    let (load, exec) = (&phases[phases.len() - 2], &phases[phases.len() - 1]);
+    let exec_mean = Statistics::of(&exec.samples).mean;
+    let mut comparison = Table::new(&["Loader", "Mean (us)", "Relative"]);
+    for (loader, phase) in [("drow", load), ("system", exec)].iter() {
+        let mean = Statistics::of(&phase.samples).mean;
+        comparison.add_row(vec![
+            loader.to_string(),
+            format!("{:.1}", mean),
+            format!("{:.2}x", mean / exec_mean),
+        ]);
+    }
+    print!("{}", comparison.render(color));
+}
+
+fn print_json(config: &Config, file_path: &str, phases: &[Phase]) {
+    let arguments: Vec<String> = config
+        .program_arguments
+        .iter()
+        .map(|argument| json_string(argument))
+        .collect();
+    let phases: Vec<String> = phases
+        .iter()
+        .map(|phase| {
+            let statistics = Statistics::of(&phase.samples);
+            format!(
+                "    {{\"phase\": {}, \"mean_us\": {:.3}, \"median_us\": {:.3}, \"stddev_us\": {:.3}}}",
+                json_string(phase.name),
+                statistics.mean,
+                statistics.median,
+                statistics.stddev
+            )
+        })
+        .collect();
+    println!("{{");
+    println!("  \"file\": {},", json_string(file_path));
+    println!("  \"arguments\": [{}],", arguments.join(", "));
+    println!("  \"iterations\": {},", config.iterations);
+    println!("  \"phases\": [\n{}\n  ]", phases.join(",\n"));
+    println!("}}");
+}
+
+/// Times loading `file_path` with drow against running it through the system loader. One
+/// untimed run of each warms the page cache and drow's library cache first.
+pub fn run(config: &Config, file_path: &String, color: bool) -> Result<i32, DrowError> {
+    let mut bench = Bench {
+        config,
+        file_path,
+        resolver: None,
+        exit_status: None,
+    };
+    let mut phases = [
+        Phase::new("resolve", "Resolve dependencies"),
+        Phase::new("map", "Map segments"),
+        Phase::new("relocate", "Relocate"),
+        Phase::new("load", "drow load, total"),
+        Phase::new("exec", "System loader, execve to exit"),
+    ];
+    bench.load()?;
+    bench.exec()?;
+    for _ in 0..config.iterations {
+        let durations = bench.load()?;
+        for (phase, duration) in phases.iter_mut().zip(durations.iter()) {
+            phase.add(*duration);
+        }
+        let exec = bench.exec()?;
+        phases[4].add(exec);
+    }
+    if config.json {
+        print_json(config, file_path, &phases);
+    } else {
+        print_table(&phases, color);
+    }
+    Ok(0)
+}
@@ -0,0 +1,317 @@
+//! Replay bundles: copies of every file a load read, with the command line and environment it
+//! ran with, so the same load can be done elsewhere from the bundle alone. The bundle file lists
+//! them as `key = value` lines:
+//!
+//! ```text
+//! version = 0.1.0
+//! base = 0x20000
+//! argument = run
+//! environment = LD_LIBRARY_PATH=/opt/app/lib
+//! program = /opt/app/bin/app files/app sha256=<digest>
+//! cache = files/ld.so.cache sha256=<digest>
+//! library = libfoo.so.1 files/libfoo.so.1 sha256=<digest>
+//! ```
+//!
+//! Paths of copies are relative to the bundle directory, so it can be moved.
+
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use crate::error::DrowError;
+use crate::manifest::{self, Manifest};
+use crate::sha256;
+
+/// Name of the bundle file in the bundle directory.
+pub const BUNDLE_FILE: &str = "bundle";
+const FILES_DIRECTORY: &str = "files";
+
+/// A copy in the bundle, relative to the bundle directory, with its digest.
+#[derive(Clone, Debug)]
+pub struct BundledFile {
+    pub path: String,
+    pub sha256: [u8; 32],
+}
+
+#[derive(Clone, Debug)]
+pub struct Bundle {
+    pub directory: PathBuf,
+    /// Version of drow that recorded the bundle.
+    pub version: String,
+    /// Address the first object was mapped at.
+    pub base: u64,
+    pub arguments: Vec<String>,
+    pub environment: Vec<(String, String)>,
+    /// Path the program was loaded from, and its copy.
+    pub program: (String, BundledFile),
+    /// The library cache, when libraries were looked up in it.
+    pub cache: Option<BundledFile>,
+    /// Libraries by the names they were required or preloaded by.
+    pub libraries: Vec<(String, BundledFile)>,
+}
+
+fn digest(path: &Path) -> Result<[u8; 32], DrowError> {
+    let io_error = |source| DrowError::Io {
+        path: path.display().to_string(),
+        source,
+    };
+    sha256::digest(File::open(path).map_err(io_error)?).map_err(io_error)
+}
+
+/// Copies files into the `files` directory of a bundle, each once, keeping their file names
+/// unless two share one.
+struct Copier {
+    directory: PathBuf,
+    copied: Vec<(String, BundledFile)>,
+    names: HashSet<String>,
+}
+
+impl Copier {
+    fn copy(&mut self, source: &str) -> Result<BundledFile, DrowError> {
+        if let Some((_, copy)) = self.copied.iter().find(|(copied, _)| copied == source) {
+            return Ok(copy.clone());
+        }
+        let file_name = Path::new(source)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| String::from("file"));
+        let mut name = file_name.clone();
+        let mut suffix = 1;
+        while !self.names.insert(name.clone()) {
+            suffix += 1;
+            name = format!("{}.{}", file_name, suffix);
+        }
+        let relative = format!("{}/{}", FILES_DIRECTORY, name);
+        let destination = self.directory.join(&relative);
+        fs::copy(source, &destination).map_err(|source_error| DrowError::Io {
+            path: source.to_string(),
+            source: source_error,
+        })?;
+        let copy = BundledFile {
+            path: relative,
+            sha256: digest(&destination)?,
+        };
+        self.copied.push((source.to_string(), copy.clone()));
+        Ok(copy)
+    }
+}
+
+impl Bundle {
+    /// Copies the program, the library cache and the libraries, given as (name, path), into
+    /// `directory` and writes the bundle file listing them.
+    pub fn record(
+        directory: &str,
+        program: &str,
+        cache: Option<&str>,
+        libraries: &[(String, String)],
+        arguments: &[String],
+        environment: &[(String, String)],
+        base: u64,
+    ) -> Result<Bundle, DrowError> {
+        let directory = PathBuf::from(directory);
+        fs::create_dir_all(directory.join(FILES_DIRECTORY)).map_err(|source| DrowError::Io {
+            path: directory.display().to_string(),
+            source,
+        })?;
+        let mut copier = Copier {
+            directory: directory.clone(),
+            copied: Vec::new(),
+            names: HashSet::new(),
+        };
+        let program = (program.to_string(), copier.copy(program)?);
+        let cache = cache.map(|path| copier.copy(path)).transpose()?;
+        let mut names = HashSet::new();
+        let mut bundled = Vec::new();
+        for (name, path) in libraries.iter() {
+            if names.insert(name.clone()) {
+                bundled.push((name.clone(), copier.copy(path)?));
+            }
+        }
+        let bundle = Bundle {
+            directory,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            base,
+            arguments: arguments.to_vec(),
+            environment: environment.to_vec(),
+            program,
+            cache,
+            libraries: bundled,
+        };
+        let path = bundle.directory.join(BUNDLE_FILE);
+        fs::write(&path, bundle.to_text()).map_err(|source| DrowError::Io {
+            path: path.display().to_string(),
+            source,
+        })?;
+        Ok(bundle)
+    }
+
+    fn to_text(&self) -> String {
+        let file =
+            |file: &BundledFile| format!("{} sha256={}", file.path, manifest::hex(&file.sha256));
+        let mut text = String::from("# drow replay bundle\n");
+        text.push_str(&format!("version = {}\n", self.version));
+        text.push_str(&format!("base = {:#x}\n", self.base));
+        for argument in self.arguments.iter() {
+            text.push_str(&format!("argument = {}\n", argument));
+        }
+        for (name, value) in self.environment.iter() {
+            text.push_str(&format!("environment = {}={}\n", name, value));
+        }
+        text.push_str(&format!(
+            "program = {} {}\n",
+            self.program.0,
+            file(&self.program.1)
+        ));
+        if let Some(cache) = self.cache.as_ref() {
+            text.push_str(&format!("cache = {}\n", file(cache)));
+        }
+        for (name, library) in self.libraries.iter() {
+            text.push_str(&format!("library = {} {}\n", name, file(library)));
+        }
+        text
+    }
+
+    /// Reads the bundle in `directory` and checks every copy against its digest.
+    pub fn load(directory: &str) -> Result<Bundle, DrowError> {
+        let path = Path::new(directory).join(BUNDLE_FILE);
+        let text = fs::read_to_string(&path).map_err(|source| DrowError::Io {
+            path: path.display().to_string(),
+            source,
+        })?;
+        let bundle = Bundle::parse(directory, &text)?;
+        let files = std::iter::once(&bundle.program.1)
+            .chain(bundle.cache.iter())
+            .chain(bundle.libraries.iter().map(|(_, file)| file));
+        for file in files {
+            let actual = digest(&bundle.directory.join(&file.path))?;
+            if actual != file.sha256 {
+                return Err(DrowError::Bundle {
+                    path: directory.to_string(),
+                    reason: format!(
+                        "{} has SHA-256 {}, expected {}",
+                        file.path,
+                        manifest::hex(&actual),
+                        manifest::hex(&file.sha256)
+                    ),
+                });
+            }
+        }
+        Ok(bundle)
+    }
+
+    pub fn parse(directory: &str, text: &str) -> Result<Bundle, DrowError> {
+        let error = |line: usize, reason: String| DrowError::Bundle {
+            path: directory.to_string(),
+            reason: format!("line {}: {}", line, reason),
+        };
+        let bundled = |line: usize, fields: &[&str]| match fields {
+            [path, digest] => digest
+                .strip_prefix("sha256=")
+                .and_then(manifest::parse_digest)
+                .map(|sha256| BundledFile {
+                    path: path.to_string(),
+                    sha256,
+                })
+                .ok_or_else(|| {
+                    error(
+                        line,
+                        format!("expected sha256=<64 hex digits>, found {}", digest),
+                    )
+                }),
+            _ => Err(error(line, String::from("expected <path> sha256=<digest>"))),
+        };
+        let mut version = None;
+        let mut base = None;
+        let mut arguments = Vec::new();
+        let mut environment = Vec::new();
+        let mut program = None;
+        let mut cache = None;
+        let mut libraries = Vec::new();
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+                .ok_or_else(|| error(line_number, String::from("expected key = value")))?;
+            let fields: Vec<&str> = value.split_whitespace().collect();
+            match key {
+                "version" => version = Some(value.to_string()),
+                "base" => {
+                    let digits = value.trim_start_matches("0x");
+                    base = Some(u64::from_str_radix(digits, 16).map_err(|_| {
+                        error(line_number, format!("invalid base address {}", value))
+                    })?);
+                }
+                "argument" => arguments.push(value.to_string()),
+                "environment" => {
+                    let (name, value) = value
+                        .split_once('=')
+                        .ok_or_else(|| error(line_number, String::from("expected NAME=value")))?;
+                    environment.push((name.to_string(), value.to_string()));
+                }
+                "program" => match fields.split_first() {
+                    Some((original, rest)) => {
+                        program = Some((original.to_string(), bundled(line_number, rest)?))
+                    }
+                    None => return Err(error(line_number, String::from("expected a path"))),
+                },
+                "cache" => cache = Some(bundled(line_number, &fields)?),
+                "library" => match fields.split_first() {
+                    Some((name, rest)) => {
+                        libraries.push((name.to_string(), bundled(line_number, rest)?))
+                    }
+                    None => return Err(error(line_number, String::from("expected a name"))),
+                },
+                _ => return Err(error(line_number, format!("unknown key {}", key))),
+            }
+        }
+        let missing = |key: &str| DrowError::Bundle {
+            path: directory.to_string(),
+            reason: format!("no {} line", key),
+        };
+        Ok(Bundle {
+            directory: PathBuf::from(directory),
+            version: version.ok_or_else(|| missing("version"))?,
+            base: base.ok_or_else(|| missing("base"))?,
+            arguments,
+            environment,
+            program: program.ok_or_else(|| missing("program"))?,
+            cache,
+            libraries,
+        })
+    }
+
+    fn absolute(&self, file: &BundledFile) -> String {
+        let path = self.directory.join(&file.path);
+        fs::canonicalize(&path)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    }
+
+    /// The copy of the program.
+    pub fn program_path(&self) -> String {
+        self.absolute(&self.program.1)
+    }
+
+    /// Maps the names of the libraries to their copies, so they are never searched for.
+    pub fn manifest(&self) -> Result<Manifest, DrowError> {
+        let mut text = String::new();
+        for (name, library) in self.libraries.iter() {
+            text.push_str(&format!(
+                "{} = {} sha256={}\n",
+                name,
+                self.absolute(library),
+                manifest::hex(&library.sha256)
+            ));
+        }
+        Manifest::parse(
+            &self.directory.join(BUNDLE_FILE).display().to_string(),
+            &text,
+        )
+    }
+}
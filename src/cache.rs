@@ -1,17 +1,29 @@
-use crate::syscall;
-use libc::{perror, size_t, stat};
+use crate::error::DrowError;
+use crate::{syscall, MACHINE_AARCH64, MACHINE_X86_64};
+use libc::size_t;
 use std::collections::HashMap;
-use std::ffi::CString;
 use std::mem::size_of;
-use std::{mem, ptr};
+use std::ptr;
 
 pub struct LibraryCache {
     cache: HashMap<String, Vec<String>>,
+    /// The flags of each path in `cache`, in the same order.
+    flags: HashMap<String, Vec<i32>>,
 }
 
+pub const DEFAULT_CACHE_PATH: &str = "/etc/ld.so.cache";
+
 const CACHE_MAGIC_NEW: &str = "glibc-ld.so.cache";
 const CACHE_VERSION: &str = "1.1";
 
+/// The kind of library an entry is, in the low byte of its flags.
+const FLAG_TYPE_MASK: i32 = 0x00FF;
+const FLAG_ELF_LIBC6: i32 = 0x0003;
+/// The ABI an entry is for, in the second byte of its flags. 32-bit x86 has none.
+const FLAG_REQUIRED_MASK: i32 = 0xFF00;
+const FLAG_X8664_LIB64: i32 = 0x0300;
+const FLAG_AARCH64_LIB64: i32 = 0x0A00;
+
 #[repr(C)]
 #[derive(Copy, Clone)]
 struct CacheEntry {
@@ -27,16 +39,52 @@ impl LibraryCache {
         self.cache.get(key)
     }
 
-    fn new() -> LibraryCache {
+    /// The paths of `key` whose entries are for 64-bit libc6 objects of `machine`, the ones an
+    /// object of that machine can load. The cache of a multilib host lists 32-bit libraries
+    /// under the same names. Machines the flags do not tell apart keep every path.
+    pub fn find_for_machine(&self, key: &str, machine: u16) -> Vec<String> {
+        let required = match machine {
+            MACHINE_X86_64 => FLAG_X8664_LIB64,
+            MACHINE_AARCH64 => FLAG_AARCH64_LIB64,
+            _ => return self.cache.get(key).cloned().unwrap_or_default(),
+        };
+        let (paths, flags) = match (self.cache.get(key), self.flags.get(key)) {
+            (Some(paths), Some(flags)) => (paths, flags),
+            _ => return Vec::new(),
+        };
+        paths
+            .iter()
+            .zip(flags.iter())
+            .filter(|(path, flags)| {
+                let matches = **flags & FLAG_TYPE_MASK == FLAG_ELF_LIBC6
+                    && **flags & FLAG_REQUIRED_MASK == required;
+                if !matches {
+                    debug!(
+                        "Skipping cache entry {} of {}: flags {:#x}",
+                        path, key, flags
+                    );
+                }
+                matches
+            })
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.cache.keys()
+    }
+
+    pub fn empty() -> LibraryCache {
         LibraryCache {
             cache: HashMap::new(),
+            flags: HashMap::new(),
         }
     }
 
-    unsafe fn compare_bytes(vector: &Vec<u8>, pointer: *const u8) -> bool {
+    unsafe fn compare_bytes(vector: &[u8], pointer: *const u8) -> bool {
         let mut result = true;
-        for x in 0..vector.len() {
-            if vector[x] != *pointer.offset(x as isize) {
+        for (x, byte) in vector.iter().enumerate() {
+            if *byte != *pointer.add(x) {
                 result = false;
                 break;
             }
@@ -51,89 +99,103 @@ impl LibraryCache {
             buffer.push(*curr);
             curr = curr.add(1);
         }
-        std::str::from_utf8(&buffer[..]).unwrap().to_string()
+        String::from_utf8_lossy(&buffer).into_owned()
     }
 
-    pub fn load(path: &str) -> Result<LibraryCache, String> {
-        println!("Loading cache file: {}", path);
-        let mut library_cache = LibraryCache::new();
-        let mut result = Result::Err("Unable to load cache".to_string());
+    fn close(file_descriptor: i32) {
+        if let Err(errno) = syscall::close_checked(file_descriptor) {
+            warn!("close({}) failed: {}", file_descriptor, errno);
+        }
+    }
+
+    pub fn load(path: &str) -> Result<LibraryCache, DrowError> {
+        info!("Loading cache file: {}", path);
+        let mut library_cache = LibraryCache::empty();
+        let result;
         let cache_magic_new: Vec<u8> = CACHE_MAGIC_NEW.chars().map(|ch| ch as u8).collect();
         let cache_version: Vec<u8> = CACHE_VERSION.chars().map(|ch| ch as u8).collect();
-        let c_path = CString::new(path).unwrap();
-        let file_descriptor = unsafe { syscall::open(c_path.as_ptr(), libc::O_RDONLY) };
-        if file_descriptor < 0 {
-            result = Result::Err("Unable to open cache file".to_string());
-            unsafe {
-                let error_location = libc::__errno_location();
-                perror(error_location as *const libc::c_char);
+        let file_descriptor = syscall::open_file(path)?;
+        let file_size = match syscall::get_file_size(file_descriptor) {
+            Ok(file_size) => file_size,
+            Err(errno) => {
+                LibraryCache::close(file_descriptor);
+                return Err(DrowError::Io {
+                    path: path.to_string(),
+                    source: errno.into(),
+                });
             }
-        } else {
-            let file_size = syscall::get_file_size(file_descriptor);
-            println!("Cache file size: {}", file_size);
-            unsafe {
-                let file_ptr: *const libc::c_void = syscall::mmap(
-                    ptr::null(),
-                    file_size as size_t,
-                    libc::PROT_READ,
-                    libc::MAP_PRIVATE,
-                    file_descriptor,
-                    0,
-                );
-                if file_ptr != libc::MAP_FAILED {
-                    let mut elem_ptr: *const libc::c_void = file_ptr.clone();
-                    if LibraryCache::compare_bytes(&cache_magic_new, elem_ptr as *const u8) {
-                        println!("Proper cache magic detected: {}", CACHE_MAGIC_NEW);
-                    } else {
-                        println!("Wrong cache magic detected, should be: {}", CACHE_MAGIC_NEW);
-                    }
-                    elem_ptr = elem_ptr.offset(cache_magic_new.len() as isize);
-                    if LibraryCache::compare_bytes(&cache_version, elem_ptr as *const u8) {
-                        println!("Proper cache version detected: {}", CACHE_VERSION);
-                    } else {
-                        println!("Wrong cache version detected, should be: {}", CACHE_VERSION);
-                    }
-                    println!("Magic number len: {}", cache_magic_new.len());
-                    println!("Version len: {}", cache_version.len());
-                    elem_ptr = elem_ptr.offset(cache_version.len() as isize);
-                    let number_of_entries: u32 = ptr::read_unaligned(elem_ptr as *const _);
-                    elem_ptr = elem_ptr.offset(size_of::<u32>() as isize);
-                    let string_table_size: u32 = ptr::read_unaligned(elem_ptr as *const _);
-                    elem_ptr = elem_ptr.offset((size_of::<u32>() * 6) as isize);
-                    let entries_offset = (elem_ptr as u64) - (file_ptr as u64);
-                    println!("Entries start at offset: {}", entries_offset);
-                    println!("Number of cache entries: {}", number_of_entries);
-                    println!("String table size: {}", string_table_size);
-                    let mut cache_entries: Vec<CacheEntry> = Vec::new();
-                    for _ in 0..number_of_entries {
-                        let entry: CacheEntry = ptr::read_unaligned(elem_ptr as *const _);
-                        cache_entries.push(entry.clone());
-                        elem_ptr = elem_ptr.offset(size_of::<CacheEntry>() as isize);
-                    }
-                    let string_table_offset = (elem_ptr as u64) - (file_ptr as u64);
-                    println!("String table starts at offset: {:#X}", string_table_offset);
-                    for entry in cache_entries.iter() {
-                        let key_string_pointer = file_ptr.offset(entry.key as isize);
-                        let value_string_pointer = file_ptr.offset(entry.value as isize);
-                        let key = LibraryCache::pointer_to_string(key_string_pointer as *const u8);
-                        let value =
-                            LibraryCache::pointer_to_string(value_string_pointer as *const u8);
-                        if let Some(entry) = library_cache.cache.get_mut(&key) {
-                            entry.push(value);
-                        } else {
-                            let mut libraries = Vec::new();
-                            libraries.push(value);
-                            library_cache.cache.insert(key, libraries);
-                        }
-                    }
-                    syscall::munmap(file_ptr, file_size as size_t);
-                    result = Ok(library_cache);
+        };
+        debug!("Cache file size: {}", file_size);
+        let mapping = syscall::mmap_checked(
+            ptr::null(),
+            file_size as size_t,
+            libc::PROT_READ,
+            libc::MAP_PRIVATE,
+            file_descriptor,
+            0,
+        );
+        match mapping {
+            Ok(file_ptr) => unsafe {
+                let mut elem_ptr: *const libc::c_void = file_ptr;
+                if LibraryCache::compare_bytes(&cache_magic_new, elem_ptr as *const u8) {
+                    debug!("Proper cache magic detected: {}", CACHE_MAGIC_NEW);
+                } else {
+                    warn!("Wrong cache magic detected, should be: {}", CACHE_MAGIC_NEW);
+                }
+                elem_ptr = elem_ptr.add(cache_magic_new.len());
+                if LibraryCache::compare_bytes(&cache_version, elem_ptr as *const u8) {
+                    debug!("Proper cache version detected: {}", CACHE_VERSION);
                 } else {
-                    result = Result::Err("Unable to mmap file".to_string());
+                    warn!("Wrong cache version detected, should be: {}", CACHE_VERSION);
+                }
+                trace!("Magic number len: {}", cache_magic_new.len());
+                trace!("Version len: {}", cache_version.len());
+                elem_ptr = elem_ptr.add(cache_version.len());
+                let number_of_entries: u32 = ptr::read_unaligned(elem_ptr as *const _);
+                elem_ptr = elem_ptr.add(size_of::<u32>());
+                let string_table_size: u32 = ptr::read_unaligned(elem_ptr as *const _);
+                elem_ptr = elem_ptr.add(size_of::<u32>() * 6);
+                let entries_offset = (elem_ptr as u64) - (file_ptr as u64);
+                debug!("Entries start at offset: {}", entries_offset);
+                debug!("Number of cache entries: {}", number_of_entries);
+                debug!("String table size: {}", string_table_size);
+                let mut cache_entries: Vec<CacheEntry> = Vec::new();
+                for _ in 0..number_of_entries {
+                    let entry: CacheEntry = ptr::read_unaligned(elem_ptr as *const _);
+                    cache_entries.push(entry);
+                    elem_ptr = elem_ptr.add(size_of::<CacheEntry>());
+                }
+                let string_table_offset = (elem_ptr as u64) - (file_ptr as u64);
+                debug!("String table starts at offset: {:#X}", string_table_offset);
+                for entry in cache_entries.iter() {
+                    let key_string_pointer = file_ptr.offset(entry.key as isize);
+                    let value_string_pointer = file_ptr.offset(entry.value as isize);
+                    let key = LibraryCache::pointer_to_string(key_string_pointer as *const u8);
+                    let value = LibraryCache::pointer_to_string(value_string_pointer as *const u8);
+                    library_cache
+                        .flags
+                        .entry(key.clone())
+                        .or_default()
+                        .push(entry.flags);
+                    library_cache.cache.entry(key).or_default().push(value);
+                }
+                if let Err(errno) = syscall::munmap_checked(file_ptr, file_size as size_t) {
+                    warn!(
+                        "munmap({:#X}, {}) failed: {}",
+                        file_ptr as u64, file_size, errno
+                    );
                 }
-                syscall::close(file_descriptor);
+                result = Ok(library_cache);
+            },
+            Err(errno) => {
+                result = Err(DrowError::MapFailed {
+                    address: 0,
+                    length: file_size as u64,
+                    source: errno.into(),
+                });
             }
         }
+        LibraryCache::close(file_descriptor);
         result
     }
 }
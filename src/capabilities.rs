@@ -0,0 +1,374 @@
+//! What the loader implements, in one place: the relocation types it applies and how far it
+//! supports each feature a file may need. `analyze` checks a program and its libraries against
+//! these tables before anything is mapped, for `drow run --check`. A loader change that makes a
+//! feature work updates its entry here, and `Elf64Loader` refuses relocations by the same table.
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+use crate::libc_flavor::{is_interpreter, LibcFlavor};
+use crate::versions;
+use crate::{
+    machine_relocation_type_name, Elf64Metadata, MACHINE_AARCH64, MACHINE_X86_64,
+    RELOCATION_AARCH64_ABS64, RELOCATION_AARCH64_COPY, RELOCATION_AARCH64_GLOB_DAT,
+    RELOCATION_AARCH64_IRELATIVE, RELOCATION_AARCH64_JUMP_SLOT, RELOCATION_AARCH64_NONE,
+    RELOCATION_AARCH64_RELATIVE, RELOCATION_X86_64_32, RELOCATION_X86_64_32S, RELOCATION_X86_64_64,
+    RELOCATION_X86_64_COPY, RELOCATION_X86_64_DPTMOD64, RELOCATION_X86_64_DTPOFF64,
+    RELOCATION_X86_64_GLOB_DAT, RELOCATION_X86_64_IRELATIV, RELOCATION_X86_64_JUMP_SLOT,
+    RELOCATION_X86_64_NONE, RELOCATION_X86_64_RELATIVE, RELOCATION_X86_64_TPOFF64,
+};
+
+/// The (machine, type) of each relocation the loader applies.
+pub const SUPPORTED_RELOCATIONS: &[(u16, u64)] = &[
+    (MACHINE_X86_64, RELOCATION_X86_64_NONE),
+    (MACHINE_X86_64, RELOCATION_X86_64_64),
+    (MACHINE_X86_64, RELOCATION_X86_64_32),
+    (MACHINE_X86_64, RELOCATION_X86_64_32S),
+    (MACHINE_X86_64, RELOCATION_X86_64_COPY),
+    (MACHINE_X86_64, RELOCATION_X86_64_GLOB_DAT),
+    (MACHINE_X86_64, RELOCATION_X86_64_JUMP_SLOT),
+    (MACHINE_X86_64, RELOCATION_X86_64_RELATIVE),
+    (MACHINE_X86_64, RELOCATION_X86_64_IRELATIV),
+    (MACHINE_X86_64, RELOCATION_X86_64_DPTMOD64),
+    (MACHINE_X86_64, RELOCATION_X86_64_DTPOFF64),
+    (MACHINE_X86_64, RELOCATION_X86_64_TPOFF64),
+    (MACHINE_AARCH64, RELOCATION_AARCH64_NONE),
+    (MACHINE_AARCH64, RELOCATION_AARCH64_ABS64),
+    (MACHINE_AARCH64, RELOCATION_AARCH64_COPY),
+    (MACHINE_AARCH64, RELOCATION_AARCH64_GLOB_DAT),
+    (MACHINE_AARCH64, RELOCATION_AARCH64_JUMP_SLOT),
+    (MACHINE_AARCH64, RELOCATION_AARCH64_RELATIVE),
+    (MACHINE_AARCH64, RELOCATION_AARCH64_IRELATIVE),
+];
+
+pub fn supports_relocation(machine: u16, relocation_type: u64) -> bool {
+    SUPPORTED_RELOCATIONS.contains(&(machine, relocation_type))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Support {
+    Supported,
+    /// Works for the common cases, the program may still fail.
+    Partial,
+    Unsupported,
+}
+
+impl Display for Support {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Support::Supported => "supported",
+            Support::Partial => "partial",
+            Support::Unsupported => "unsupported",
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Feature {
+    /// Relocation types outside `SUPPORTED_RELOCATIONS`.
+    UnsupportedRelocations,
+    /// Code for another machine than x86-64.
+    ForeignMachine,
+    /// TLS reached through `__tls_get_addr`.
+    DynamicTls,
+    /// TLS at a fixed offset from the thread pointer: the PT_TLS of the program, TPOFF
+    /// relocations or DF_STATIC_TLS.
+    StaticTls,
+    /// DT_TEXTREL or DF_TEXTREL.
+    TextRelocations,
+    IndirectFunctions,
+    /// A PT_INTERP of glibc or musl, whose loader drow stands in for.
+    KnownInterpreter,
+    UnknownInterpreter,
+    /// Symbol versions the libraries found lack.
+    MissingVersions,
+    /// Missing symbol versions bound to older ones, as --best-effort does.
+    VersionFallbacks,
+    UnresolvedLibraries,
+}
+
+/// How far the loader supports each feature, and why.
+pub const FEATURES: &[(Feature, Support, &str)] = &[
+    (
+        Feature::UnsupportedRelocations,
+        Support::Unsupported,
+        "the loader does not apply these relocation types",
+    ),
+    (
+        Feature::ForeignMachine,
+        Support::Unsupported,
+        "objects of other machines are mapped and relocated, only x86-64 code runs",
+    ),
+    (
+        Feature::DynamicTls,
+        Support::Supported,
+        "blocks are allocated on the first __tls_get_addr call",
+    ),
+    (
+        Feature::StaticTls,
+        Support::Partial,
+        "objects loaded with the program share one static TLS area, for the thread that starts it",
+    ),
+    (
+        Feature::TextRelocations,
+        Support::Unsupported,
+        "segments are mapped with their final protection before relocations write to them",
+    ),
+    (
+        Feature::IndirectFunctions,
+        Support::Supported,
+        "resolvers run at load time with the CPU features drow reports",
+    ),
+    (
+        Feature::KnownInterpreter,
+        Support::Partial,
+        "drow stands in for the C library's loader with shims for what libc asks of it",
+    ),
+    (
+        Feature::UnknownInterpreter,
+        Support::Partial,
+        "drow does not know what the interpreter provides to the program",
+    ),
+    (
+        Feature::MissingVersions,
+        Support::Unsupported,
+        "the load is refused, --best-effort binds older versions instead",
+    ),
+    (
+        Feature::VersionFallbacks,
+        Support::Partial,
+        "symbols are bound to older versions, which may not behave as expected",
+    ),
+    (
+        Feature::UnresolvedLibraries,
+        Support::Unsupported,
+        "every DT_NEEDED library must be found",
+    ),
+];
+
+impl Feature {
+    pub fn support(self) -> Support {
+        FEATURES
+            .iter()
+            .find(|(feature, _, _)| *feature == self)
+            .map(|(_, support, _)| *support)
+            .unwrap_or(Support::Unsupported)
+    }
+
+    /// Why the loader supports the feature as far as it does.
+    pub fn reason(self) -> &'static str {
+        FEATURES
+            .iter()
+            .find(|(feature, _, _)| *feature == self)
+            .map(|(_, _, reason)| *reason)
+            .unwrap_or("")
+    }
+}
+
+impl Display for Feature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Feature::UnsupportedRelocations => "relocation types",
+            Feature::ForeignMachine => "foreign machine",
+            Feature::DynamicTls => "dynamic TLS",
+            Feature::StaticTls => "static TLS",
+            Feature::TextRelocations => "text relocations",
+            Feature::IndirectFunctions => "indirect functions",
+            Feature::KnownInterpreter => "interpreter",
+            Feature::UnknownInterpreter => "unknown interpreter",
+            Feature::MissingVersions => "missing symbol versions",
+            Feature::VersionFallbacks => "symbol version fallbacks",
+            Feature::UnresolvedLibraries => "unresolved libraries",
+        })
+    }
+}
+
+/// A feature one object needs, with what in the object needs it.
+#[derive(Clone, Debug)]
+pub struct Requirement {
+    pub feature: Feature,
+    pub object: String,
+    pub detail: String,
+}
+
+pub struct CapabilityReport {
+    pub requirements: Vec<Requirement>,
+}
+
+impl CapabilityReport {
+    /// The requirements the loader does not support, which keep the program from working.
+    pub fn blockers(&self) -> impl Iterator<Item = &Requirement> {
+        self.requirements
+            .iter()
+            .filter(|requirement| requirement.feature.support() == Support::Unsupported)
+    }
+
+    pub fn likely_to_work(&self) -> bool {
+        self.blockers().next().is_none()
+    }
+
+    /// "likely to work", or "will not work because" and the features that keep it from working
+    /// with the objects needing them.
+    pub fn verdict(&self) -> String {
+        let mut blockers: BTreeMap<Feature, Vec<&str>> = BTreeMap::new();
+        for requirement in self.blockers() {
+            let objects = blockers.entry(requirement.feature).or_default();
+            if !objects.contains(&requirement.object.as_str()) {
+                objects.push(&requirement.object);
+            }
+        }
+        if blockers.is_empty() {
+            return String::from("likely to work");
+        }
+        let reasons: Vec<String> = blockers
+            .iter()
+            .map(|(feature, objects)| format!("{} ({})", feature, objects.join(", ")))
+            .collect();
+        format!("will not work because of {}", reasons.join(", "))
+    }
+}
+
+/// The TLS `elf_metadata` needs, static or dynamic. Only the program can reach its own PT_TLS
+/// at a fixed offset without saying so.
+fn tls_requirement(elf_metadata: &Elf64Metadata, program: bool) -> Option<(Feature, String)> {
+    let static_relocations = elf_metadata
+        .relocations
+        .iter()
+        .filter(|rela| {
+            rela.machine == MACHINE_X86_64 && rela.relocation_type == RELOCATION_X86_64_TPOFF64
+        })
+        .count();
+    let has_tls = !elf_metadata.tls_segments.is_empty();
+    if static_relocations > 0 {
+        Some((
+            Feature::StaticTls,
+            format!("{} R_X86_64_TPOFF64 relocation(s)", static_relocations),
+        ))
+    } else if elf_metadata.dynamic.static_tls() {
+        Some((Feature::StaticTls, String::from("DF_STATIC_TLS")))
+    } else if has_tls && program {
+        Some((
+            Feature::StaticTls,
+            String::from("PT_TLS of the program, reached by offset from the thread pointer"),
+        ))
+    } else if has_tls {
+        Some((Feature::DynamicTls, String::from("PT_TLS")))
+    } else {
+        None
+    }
+}
+
+/// The features `elf_metadata` needs on its own.
+fn object_requirements(elf_metadata: &Elf64Metadata, program: bool) -> Vec<(Feature, String)> {
+    let mut result = Vec::new();
+    let machine = elf_metadata.elf_header.e_machine;
+    if machine != MACHINE_X86_64 {
+        result.push((
+            Feature::ForeignMachine,
+            format!("e_machine {:#04X}", machine),
+        ));
+    }
+    let mut unsupported: BTreeMap<&str, usize> = BTreeMap::new();
+    for rela in elf_metadata.relocations.iter() {
+        if !supports_relocation(rela.machine, rela.relocation_type) {
+            *unsupported
+                .entry(machine_relocation_type_name(
+                    rela.machine,
+                    rela.relocation_type,
+                ))
+                .or_default() += 1;
+        }
+    }
+    if !unsupported.is_empty() {
+        let types: Vec<String> = unsupported
+            .iter()
+            .map(|(name, count)| format!("{} x{}", name, count))
+            .collect();
+        result.push((Feature::UnsupportedRelocations, types.join(", ")));
+    }
+    if let Some(tls) = tls_requirement(elf_metadata, program) {
+        result.push(tls);
+    }
+    if elf_metadata.dynamic.writes_text() {
+        result.push((Feature::TextRelocations, String::from("DT_TEXTREL")));
+    }
+    let indirect = elf_metadata
+        .relocations
+        .iter()
+        .filter(|rela| {
+            matches!(
+                (rela.machine, rela.relocation_type),
+                (MACHINE_X86_64, RELOCATION_X86_64_IRELATIV)
+                    | (MACHINE_AARCH64, RELOCATION_AARCH64_IRELATIVE)
+            )
+        })
+        .count();
+    if indirect > 0 {
+        result.push((
+            Feature::IndirectFunctions,
+            format!("{} IRELATIVE relocation(s)", indirect),
+        ));
+    }
+    result
+}
+
+/// The features `program` and `libraries`, the objects found for its dependencies, need, and
+/// the `unresolved` dependencies. `best_effort` is the option of that name, which makes
+/// missing symbol versions fall back to older ones.
+pub fn analyze(
+    program: &Elf64Metadata,
+    libraries: &[Elf64Metadata],
+    unresolved: &[String],
+    best_effort: bool,
+) -> CapabilityReport {
+    let mut requirements = Vec::new();
+    let interpreter = program.interpreter.clone();
+    if let Some(interpreter) = interpreter.clone() {
+        let feature = match LibcFlavor::detect(Some(&interpreter), []) {
+            LibcFlavor::Unknown => Feature::UnknownInterpreter,
+            _ => Feature::KnownInterpreter,
+        };
+        requirements.push(Requirement {
+            feature,
+            object: program.file_path.clone(),
+            detail: interpreter,
+        });
+    }
+    // The glibc loader is left out of the load, drow stands in for it.
+    let left_out = LibcFlavor::detect(interpreter.as_deref(), libraries)
+        .left_out_interpreter(interpreter.as_deref());
+    let objects: Vec<&Elf64Metadata> = std::iter::once(program)
+        .chain(libraries)
+        .filter(|object| {
+            !left_out.is_some_and(|interpreter| is_interpreter(&object.file_path, interpreter))
+        })
+        .collect();
+    for (index, object) in objects.iter().enumerate() {
+        for (feature, detail) in object_requirements(object, index == 0) {
+            requirements.push(Requirement {
+                feature,
+                object: object.file_path.clone(),
+                detail,
+            });
+        }
+    }
+    for missing in versions::missing_versions(&objects) {
+        requirements.push(Requirement {
+            feature: if best_effort {
+                Feature::VersionFallbacks
+            } else {
+                Feature::MissingVersions
+            },
+            object: missing.requester.clone(),
+            detail: format!("{} from {}", missing.version, missing.provider),
+        });
+    }
+    if !unresolved.is_empty() {
+        requirements.push(Requirement {
+            feature: Feature::UnresolvedLibraries,
+            object: program.file_path.clone(),
+            detail: unresolved.join(", "),
+        });
+    }
+    CapabilityReport { requirements }
+}
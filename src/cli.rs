@@ -0,0 +1,1164 @@
+use crate::config_file::{self, ConfigFile, Value};
+use crate::settings::{self, Source, CONFIG_VARIABLE, SETTINGS};
+use drow::bundle::Bundle;
+use drow::cpu_features::CpuFeatures;
+use drow::libc_flavor::LibcFlavor;
+use drow::loader::LoadOptions;
+use drow::log::Level;
+use drow::manifest::Manifest;
+use drow::object_rules::{ObjectRule, ObjectRules};
+use drow::pause::PauseMode;
+use drow::summary::SummaryFormat;
+use drow::table::ColorMode;
+use drow::warn;
+use std::env;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Command {
+    Inspect,
+    Resolve,
+    Run,
+    Shell,
+    Bench,
+    Edit,
+}
+
+struct CommandSpec {
+    command: Command,
+    name: &'static str,
+    usage: &'static str,
+    help: &'static str,
+}
+
+const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        command: Command::Inspect,
+        name: "inspect",
+        usage: "inspect <file>...",
+        help: "Print ELF metadata, or one summary line per file with --summary",
+    },
+    CommandSpec {
+        command: Command::Resolve,
+        name: "resolve",
+        usage: "resolve <file>...",
+        help: "Resolve the dependency tree of a file",
+    },
+    CommandSpec {
+        command: Command::Run,
+        name: "run",
+        usage: "run <file>... [-- args]",
+        help: "Load a file with its dependencies and run it",
+    },
+    CommandSpec {
+        command: Command::Shell,
+        name: "shell",
+        usage: "shell <file>",
+        help: "Read inspection commands for a file from stdin",
+    },
+    CommandSpec {
+        command: Command::Bench,
+        name: "bench",
+        usage: "bench <file> [-- args]",
+        help: "Time loading a file against running it with the system loader",
+    },
+    CommandSpec {
+        command: Command::Edit,
+        name: "edit",
+        usage: "edit <file> --output=PATH",
+        help: "Write a copy of a file with its rpath or sections changed",
+    },
+];
+
+const ALL: &[Command] = &[
+    Command::Inspect,
+    Command::Resolve,
+    Command::Run,
+    Command::Shell,
+    Command::Bench,
+    Command::Edit,
+];
+
+struct OptionSpec {
+    name: &'static str,
+    short: Option<char>,
+    /// A placeholder in brackets makes the value optional, and only given after `=`.
+    value: Option<&'static str>,
+    commands: &'static [Command],
+    help: &'static str,
+}
+
+const OPTIONS: &[OptionSpec] = &[
+    OptionSpec {
+        name: "help",
+        short: Some('h'),
+        value: None,
+        commands: ALL,
+        help: "Print this help",
+    },
+    OptionSpec {
+        name: "quiet",
+        short: Some('q'),
+        value: None,
+        commands: ALL,
+        help: "Log errors only",
+    },
+    OptionSpec {
+        name: "verbose",
+        short: Some('v'),
+        value: None,
+        commands: ALL,
+        help: "Log more details, repeat for debug and trace output",
+    },
+    OptionSpec {
+        name: "log-file",
+        short: None,
+        value: Some("PATH"),
+        commands: ALL,
+        help: "Append diagnostics to PATH instead of stderr",
+    },
+    OptionSpec {
+        name: "config",
+        short: None,
+        value: Some("PATH"),
+        commands: ALL,
+        help: "Read settings from PATH instead of $DROW_CONFIG or ~/.config/drow/config",
+    },
+    OptionSpec {
+        name: "show-config",
+        short: None,
+        value: None,
+        commands: ALL,
+        help: "Print the effective settings and where each comes from",
+    },
+    OptionSpec {
+        name: "color",
+        short: None,
+        value: Some("WHEN"),
+        commands: ALL,
+        help: "Colorize output: auto, always or never",
+    },
+    OptionSpec {
+        name: "summary",
+        short: None,
+        value: None,
+        commands: &[Command::Inspect],
+        help: "Print one summary line per file",
+    },
+    OptionSpec {
+        name: "summary-format",
+        short: None,
+        value: Some("FORMAT"),
+        commands: &[Command::Inspect],
+        help: "Summary format: plain, csv or tsv",
+    },
+    OptionSpec {
+        name: "debuginfo-dir",
+        short: None,
+        value: Some("DIR"),
+        commands: &[Command::Inspect, Command::Shell],
+        help: "Search DIR for debug files instead of /usr/lib/debug, repeatable",
+    },
+    OptionSpec {
+        name: "stats",
+        short: None,
+        value: None,
+        commands: &[Command::Inspect, Command::Run],
+        help: "Report string table reads, prefaulted pages, reference counts and symbol lookups",
+    },
+    OptionSpec {
+        name: "progress",
+        short: None,
+        value: None,
+        commands: &[Command::Run],
+        help: "Show the progress of the load on stderr when it is a terminal",
+    },
+    OptionSpec {
+        name: "prelink-cache",
+        short: None,
+        value: Some("DIR"),
+        commands: &[Command::Run],
+        help: "Replay symbol relocations saved in DIR by the previous load of the same files",
+    },
+    OptionSpec {
+        name: "dep-graph",
+        short: None,
+        value: Some("PATH"),
+        commands: &[Command::Resolve],
+        help: "Write the dependency graph to PATH in DOT format",
+    },
+    OptionSpec {
+        name: "sysroot",
+        short: None,
+        value: Some("DIR"),
+        commands: &[
+            Command::Resolve,
+            Command::Run,
+            Command::Shell,
+            Command::Bench,
+        ],
+        help: "Resolve libraries against the root filesystem at DIR",
+    },
+    OptionSpec {
+        name: "offline",
+        short: None,
+        value: None,
+        commands: &[
+            Command::Resolve,
+            Command::Run,
+            Command::Shell,
+            Command::Bench,
+        ],
+        help: "Resolve libraries only below the --search-dir directories, never from the host",
+    },
+    OptionSpec {
+        name: "search-dir",
+        short: None,
+        value: Some("DIR"),
+        commands: &[
+            Command::Resolve,
+            Command::Run,
+            Command::Shell,
+            Command::Bench,
+        ],
+        help: "Directory searched recursively in offline mode, repeatable",
+    },
+    OptionSpec {
+        name: "manifest",
+        short: None,
+        value: Some("FILE"),
+        commands: &[
+            Command::Resolve,
+            Command::Run,
+            Command::Shell,
+            Command::Bench,
+        ],
+        help: "Resolve libraries only from the soname = path lines of FILE",
+    },
+    OptionSpec {
+        name: "manifest-fallback",
+        short: None,
+        value: None,
+        commands: &[
+            Command::Resolve,
+            Command::Run,
+            Command::Shell,
+            Command::Bench,
+        ],
+        help: "Search for the libraries the manifest does not map",
+    },
+    OptionSpec {
+        name: "fuzzy-soname",
+        short: None,
+        value: None,
+        commands: &[
+            Command::Resolve,
+            Command::Run,
+            Command::Shell,
+            Command::Bench,
+        ],
+        help: "Fall back to another version of a library missing under its exact name",
+    },
+    OptionSpec {
+        name: "dump-got",
+        short: None,
+        value: None,
+        commands: &[Command::Run],
+        help: "Dump the GOT after relocation",
+    },
+    OptionSpec {
+        name: "diff-image",
+        short: None,
+        value: None,
+        commands: &[Command::Run],
+        help: "Print the bytes relocation changed in each object, with the relocations writing them",
+    },
+    OptionSpec {
+        name: "check",
+        short: None,
+        value: None,
+        commands: &[Command::Run],
+        help: "Report the features the program needs that drow lacks, and exit without running it",
+    },
+    OptionSpec {
+        name: "maps",
+        short: None,
+        value: None,
+        commands: &[Command::Run],
+        help: "Print the memory layout before running",
+    },
+    OptionSpec {
+        name: "fd",
+        short: None,
+        value: Some("N"),
+        commands: &[Command::Inspect, Command::Resolve, Command::Run],
+        help: "Read the file from descriptor N, left open by the caller, instead of a path",
+    },
+    OptionSpec {
+        name: "from-memory",
+        short: None,
+        value: None,
+        commands: &[Command::Run],
+        help: "Map the file from a sealed in-memory copy",
+    },
+    OptionSpec {
+        name: "prefault",
+        short: None,
+        value: None,
+        commands: &[Command::Run, Command::Bench],
+        help: "Prefault readable file-backed pages after mapping",
+    },
+    OptionSpec {
+        name: "advise-sequential",
+        short: None,
+        value: None,
+        commands: &[Command::Run, Command::Bench],
+        help: "Advise sequential access on mapped segments",
+    },
+    OptionSpec {
+        name: "strict",
+        short: None,
+        value: None,
+        commands: &[Command::Run, Command::Bench],
+        help: "Fail on COPY relocations, undefined symbols and inconsistent section headers",
+    },
+    OptionSpec {
+        name: "force",
+        short: None,
+        value: None,
+        commands: &[Command::Run, Command::Bench],
+        help: "Load objects with malformed relocations, skipping those relocations",
+    },
+    OptionSpec {
+        name: "best-effort",
+        short: None,
+        value: None,
+        commands: &[Command::Run, Command::Bench],
+        help: "Bind symbols of versions missing from their libraries to older versions",
+    },
+    OptionSpec {
+        name: "debug",
+        short: None,
+        value: Some("CATEGORY[,CATEGORY]"),
+        commands: &[Command::Run, Command::Bench],
+        help: "Print what the loader decides, like LD_DEBUG: bindings, reloc, symbols",
+    },
+    OptionSpec {
+        name: "cpu-features",
+        short: None,
+        value: Some("FEATURES"),
+        commands: &[Command::Resolve, Command::Run, Command::Bench],
+        help: "CPU features for glibc-hwcaps and ifunc resolvers reading hwcap, not those of \
+               glibc: baseline, native or a list",
+    },
+    OptionSpec {
+        name: "object-rule",
+        short: None,
+        value: Some("GLOB:KEY=VALUE[,KEY=VALUE]"),
+        commands: &[Command::Run, Command::Bench],
+        help: "Override skip-init, bind-now, no-relro, base or replace for matching objects, repeatable",
+    },
+    OptionSpec {
+        name: "stub",
+        short: None,
+        value: Some("SONAME"),
+        commands: &[Command::Run, Command::Bench],
+        help: "Do not load SONAME, bind the symbols imported from it to traps, repeatable",
+    },
+    OptionSpec {
+        name: "no-noexec-fallback",
+        short: None,
+        value: None,
+        commands: &[Command::Run, Command::Bench],
+        help: "Fail instead of mapping files on noexec mounts from an in-memory copy",
+    },
+    OptionSpec {
+        name: "no-crash-handler",
+        short: None,
+        value: None,
+        commands: &[Command::Run],
+        help: "Let the program die of fatal signals without a symbolized backtrace",
+    },
+    OptionSpec {
+        name: "rss-report",
+        short: None,
+        value: None,
+        commands: &[Command::Run],
+        help: "Report the resident and dirty bytes of each object, read from /proc/self/smaps",
+    },
+    OptionSpec {
+        name: "report",
+        short: None,
+        value: Some("PATH|fd:N"),
+        commands: &[Command::Run],
+        help: "Write a JSON report of the loaded objects before running the program",
+    },
+    OptionSpec {
+        name: "record",
+        short: None,
+        value: Some("DIR"),
+        commands: &[Command::Run],
+        help: "Copy the files the load read, with the command line and environment, into DIR",
+    },
+    OptionSpec {
+        name: "replay",
+        short: None,
+        value: Some("DIR"),
+        commands: &[Command::Run],
+        help: "Load the program recorded in DIR from the copies there, at the recorded base",
+    },
+    OptionSpec {
+        name: "fork",
+        short: None,
+        value: None,
+        commands: &[Command::Run],
+        help: "Run the program in a child process instead of in drow",
+    },
+    OptionSpec {
+        name: "each",
+        short: None,
+        value: None,
+        commands: &[Command::Run],
+        help: "Run several files in turn, each in a child process",
+    },
+    OptionSpec {
+        name: "pause-after-load",
+        short: None,
+        value: Some("[HOW]"),
+        commands: &[Command::Run],
+        help: "Print the pid after loading, then wait for SIGUSR1 (signal, the default) or a \
+               line on stdin (stdin) before starting",
+    },
+    OptionSpec {
+        name: "no-exec",
+        short: None,
+        value: None,
+        commands: &[Command::Run],
+        help: "Stop after loading and reporting, without running the program",
+    },
+    OptionSpec {
+        name: "stack-size",
+        short: None,
+        value: Some("BYTES"),
+        commands: &[Command::Run],
+        help: "Size of the program stack",
+    },
+    OptionSpec {
+        name: "base-address",
+        short: None,
+        value: Some("ADDRESS"),
+        commands: &[Command::Run, Command::Bench],
+        help: "Address at which the first object is mapped",
+    },
+    OptionSpec {
+        name: "max-object-size",
+        short: None,
+        value: Some("BYTES"),
+        commands: &[Command::Run, Command::Bench],
+        help: "Refuse objects whose segments span more address space than this",
+    },
+    OptionSpec {
+        name: "libc",
+        short: None,
+        value: Some("FLAVOR"),
+        commands: &[Command::Run, Command::Bench],
+        help: "Apply the shims of glibc, musl or none instead of detecting the C library",
+    },
+    OptionSpec {
+        name: "argv0",
+        short: None,
+        value: Some("NAME"),
+        commands: &[Command::Run],
+        help: "Program name reported by __progname and program_invocation_name",
+    },
+    OptionSpec {
+        name: "iterations",
+        short: None,
+        value: Some("N"),
+        commands: &[Command::Bench],
+        help: "Number of timed runs, 10 by default",
+    },
+    OptionSpec {
+        name: "json",
+        short: None,
+        value: None,
+        commands: &[Command::Bench],
+        help: "Print the results as JSON",
+    },
+    OptionSpec {
+        name: "set-rpath",
+        short: None,
+        value: Some("PATH"),
+        commands: &[Command::Edit],
+        help: "Set DT_RUNPATH, or replace an existing DT_RPATH, with PATH",
+    },
+    OptionSpec {
+        name: "remove-section",
+        short: None,
+        value: Some("NAME"),
+        commands: &[Command::Edit],
+        help: "Remove a section that is not mapped at run time, can be repeated",
+    },
+    OptionSpec {
+        name: "output",
+        short: None,
+        value: Some("PATH"),
+        commands: &[Command::Edit],
+        help: "Path of the edited copy",
+    },
+];
+
+pub struct Config {
+    pub command: Command,
+    pub paths: Vec<String>,
+    pub program_arguments: Vec<String>,
+    pub color: ColorMode,
+    pub summary: Option<SummaryFormat>,
+    pub debuginfo_dirs: Vec<String>,
+    pub stats: bool,
+    pub progress: bool,
+    pub prelink_cache: Option<String>,
+    pub dep_graph: Option<String>,
+    pub dump_got: bool,
+    pub diff_image: bool,
+    pub check: bool,
+    pub maps: bool,
+    pub from_memory: bool,
+    /// See --fd.
+    pub fd: Option<i32>,
+    pub fork: bool,
+    pub each: bool,
+    pub no_exec: bool,
+    pub load_options: LoadOptions,
+    pub log_level: Level,
+    pub log_file: Option<String>,
+    pub search_paths: Vec<String>,
+    pub preload: Vec<String>,
+    pub bind_now: bool,
+    pub sysroot: Option<String>,
+    pub offline: bool,
+    pub search_dirs: Vec<String>,
+    pub manifest: Option<Manifest>,
+    pub manifest_fallback: bool,
+    pub fuzzy_soname: bool,
+    pub object_rules: ObjectRules,
+    pub stubs: Vec<String>,
+    pub libc: Option<LibcFlavor>,
+    pub argv0: Option<String>,
+    pub show_config: bool,
+    pub iterations: usize,
+    pub json: bool,
+    pub report: Option<String>,
+    pub rss_report: bool,
+    pub record: Option<String>,
+    pub replay: Option<Bundle>,
+    pub set_rpath: Option<String>,
+    pub remove_sections: Vec<String>,
+    pub output: Option<String>,
+    sources: Vec<(&'static str, Source)>,
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, left) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, right) in b.iter().enumerate() {
+            let substitution = previous[j] + if left == *right { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+pub fn suggestion<'a>(value: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .map(|candidate| (edit_distance(value, candidate), candidate))
+        .filter(|(distance, candidate)| *distance <= 2.max(candidate.len() / 3))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+pub fn with_suggestion(message: String, suggestion: Option<&str>) -> String {
+    match suggestion {
+        Some(candidate) => format!("{}, did you mean {}?", message, candidate),
+        None => message,
+    }
+}
+
+fn optional(placeholder: &str) -> bool {
+    placeholder.starts_with('[')
+}
+
+fn parse_number(name: &str, value: &str) -> Result<u64, String> {
+    settings::parse_size(value).map_err(|_| format!("Invalid value {} for --{}", value, name))
+}
+
+/// Turns on the debug categories listed in `value`.
+fn debug_categories(value: &str, load_options: &mut LoadOptions) -> Result<(), String> {
+    for category in value.split(',').filter(|category| !category.is_empty()) {
+        match category {
+            "bindings" => load_options.debug_bindings = true,
+            "reloc" => load_options.debug_relocations = true,
+            "symbols" => load_options.debug_symbols = true,
+            other => {
+                return Err(format!(
+                    "Unknown debug category: {}, expected bindings, reloc or symbols",
+                    other
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+fn type_error(key: &str, expected: &str, value: &Value) -> String {
+    format!("{} expects {}, found {}", key, expected, value.type_name())
+}
+
+fn string(key: &str, value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(value) => Ok(value.clone()),
+        _ => Err(type_error(key, "a string", value)),
+    }
+}
+
+fn strings(key: &str, value: &Value) -> Result<Vec<String>, String> {
+    match value {
+        Value::Array(values) => values.iter().map(|value| string(key, value)).collect(),
+        _ => Err(type_error(key, "an array of strings", value)),
+    }
+}
+
+fn integer(key: &str, value: &Value) -> Result<u64, String> {
+    match value {
+        Value::Integer(value) => Ok(*value),
+        _ => Err(type_error(key, "an integer", value)),
+    }
+}
+
+fn boolean(key: &str, value: &Value) -> Result<bool, String> {
+    match value {
+        Value::Boolean(value) => Ok(*value),
+        _ => Err(type_error(key, "a boolean", value)),
+    }
+}
+
+pub fn usage() -> String {
+    let mut usage = String::from("Usage: drow <command> [options]\n\nCommands:\n");
+    for spec in COMMANDS.iter() {
+        usage.push_str(&format!("  {:<30}{}\n", spec.usage, spec.help));
+    }
+    usage.push_str("\nOptions:\n");
+    for spec in OPTIONS.iter() {
+        let long = match spec.value {
+            Some(value) if optional(value) => format!("--{}[={}", spec.name, &value[1..]),
+            Some(value) => format!("--{}={}", spec.name, value),
+            None => format!("--{}", spec.name),
+        };
+        let flag = match spec.short {
+            Some(short) => format!("-{}, {}", short, long),
+            None => format!("    {}", long),
+        };
+        let commands = if spec.commands.len() == ALL.len() {
+            String::new()
+        } else {
+            let names: Vec<&str> = COMMANDS
+                .iter()
+                .filter(|command| spec.commands.contains(&command.command))
+                .map(|command| command.name)
+                .collect();
+            format!(" ({})", names.join(", "))
+        };
+        usage.push_str(&format!("  {:<30}{}{}\n", flag, spec.help, commands));
+    }
+    usage.push_str(concat!(
+        "\nSettings:\n",
+        "  Read from key = value lines in the config file, then from the environment, then from\n",
+        "  command line options, each overriding the previous one\n",
+    ));
+    for setting in SETTINGS.iter() {
+        usage.push_str(&format!(
+            "  {:<14}{:<20}{}\n",
+            setting.key, setting.variable, setting.help
+        ));
+    }
+    usage.push_str(concat!(
+        "\nExit status:\n",
+        "  The status of the loaded program, or 128 + signal if it was killed\n",
+        "  125  Invalid command line\n",
+        "  126  The file or one of its dependencies could not be loaded\n",
+        "  127  The file or one of its dependencies was not found\n",
+    ));
+    usage
+}
+
+impl Config {
+    fn new(command: Command) -> Config {
+        Config {
+            command,
+            paths: Vec::new(),
+            program_arguments: Vec::new(),
+            color: ColorMode::Auto,
+            summary: None,
+            debuginfo_dirs: Vec::new(),
+            stats: false,
+            progress: false,
+            prelink_cache: None,
+            dep_graph: None,
+            dump_got: false,
+            diff_image: false,
+            check: false,
+            maps: false,
+            from_memory: false,
+            fd: None,
+            fork: false,
+            each: false,
+            no_exec: false,
+            load_options: LoadOptions::default(),
+            log_level: Level::Warn,
+            log_file: None,
+            search_paths: Vec::new(),
+            preload: Vec::new(),
+            bind_now: true,
+            sysroot: None,
+            offline: false,
+            search_dirs: Vec::new(),
+            manifest: None,
+            manifest_fallback: false,
+            fuzzy_soname: false,
+            object_rules: ObjectRules::default(),
+            stubs: Vec::new(),
+            libc: None,
+            argv0: None,
+            show_config: false,
+            iterations: 10,
+            json: false,
+            report: None,
+            rss_report: false,
+            record: None,
+            replay: None,
+            set_rpath: None,
+            remove_sections: Vec::new(),
+            output: None,
+            sources: Vec::new(),
+        }
+    }
+
+    fn set_source(&mut self, key: &'static str, source: Source) {
+        self.sources.retain(|(existing, _)| *existing != key);
+        self.sources.push((key, source));
+    }
+
+    fn source(&self, key: &str) -> Source {
+        self.sources
+            .iter()
+            .find(|(existing, _)| *existing == key)
+            .map(|(_, source)| source.clone())
+            .unwrap_or(Source::Default)
+    }
+
+    fn apply_file(&mut self, file: &ConfigFile) -> Result<(), String> {
+        for entry in file.entries.iter() {
+            let key = entry.key.as_str();
+            let setting = settings::find(key).ok_or_else(|| {
+                let message = with_suggestion(
+                    format!("Unknown key {}", key),
+                    suggestion(key, SETTINGS.iter().map(|setting| setting.key)),
+                );
+                file.error(entry.line, entry.key_column, message)
+                    .to_string()
+            })?;
+            self.apply_value(setting.key, &entry.value, Source::File(file.path.clone()))
+                .map_err(|message| {
+                    file.error(entry.line, entry.value_column, message)
+                        .to_string()
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Applies the DROW_ variables among `variables` and warns about unknown ones.
+    fn apply_environment(&mut self, variables: &[(String, String)]) -> Result<(), String> {
+        for (name, text) in variables.iter() {
+            if !name.starts_with("DROW_") || name == CONFIG_VARIABLE {
+                continue;
+            }
+            let setting = match SETTINGS.iter().find(|setting| setting.variable == name) {
+                Some(setting) => setting,
+                None => {
+                    warn!(
+                        "{}",
+                        with_suggestion(
+                            format!("Ignoring unknown environment variable {}", name),
+                            suggestion(name, SETTINGS.iter().map(|setting| setting.variable)),
+                        )
+                    );
+                    continue;
+                }
+            };
+            let value = settings::parse_variable(setting, text)
+                .map_err(|message| format!("{}: {}", name, message))?;
+            self.apply_value(setting.key, &value, Source::Environment(setting.variable))
+                .map_err(|message| format!("{}: {}", name, message))?;
+        }
+        Ok(())
+    }
+
+    fn apply_value(
+        &mut self,
+        key: &'static str,
+        value: &Value,
+        source: Source,
+    ) -> Result<(), String> {
+        match key {
+            "search_paths" => self.search_paths = strings(key, value)?,
+            "preload" => self.preload = strings(key, value)?,
+            "stack_size" => self.load_options.stack_size = integer(key, value)? as usize,
+            "base" => self.load_options.base_address = integer(key, value)?,
+            "max_object_size" => self.load_options.max_object_size = integer(key, value)?,
+            "bind_now" => self.bind_now = boolean(key, value)?,
+            "log_level" => self.log_level = Level::parse(&string(key, value)?)?,
+            "log_file" => self.log_file = Some(string(key, value)?),
+            "sysroot" => self.sysroot = Some(string(key, value)?),
+            _ => return Ok(()),
+        }
+        self.set_source(key, source);
+        Ok(())
+    }
+
+    fn value(&self, key: &str) -> Option<Value> {
+        let strings = |values: &[String]| {
+            Value::Array(
+                values
+                    .iter()
+                    .map(|value| Value::String(value.clone()))
+                    .collect(),
+            )
+        };
+        match key {
+            "search_paths" => Some(strings(&self.search_paths)),
+            "preload" => Some(strings(&self.preload)),
+            "stack_size" => Some(Value::Integer(self.load_options.stack_size as u64)),
+            "base" => Some(Value::Integer(self.load_options.base_address)),
+            "max_object_size" => Some(Value::Integer(self.load_options.max_object_size)),
+            "bind_now" => Some(Value::Boolean(self.bind_now)),
+            "log_level" => Some(Value::String(self.log_level.to_string().to_lowercase())),
+            "log_file" => self.log_file.clone().map(Value::String),
+            "sysroot" => self.sysroot.clone().map(Value::String),
+            _ => None,
+        }
+    }
+
+    /// The effective settings in config file syntax, each commented with its source.
+    pub fn show(&self) -> String {
+        let mut result = String::new();
+        for setting in SETTINGS.iter() {
+            let line = match self.value(setting.key) {
+                Some(value) => format!("{} = {}", setting.key, value),
+                None => format!("# {} is not set", setting.key),
+            };
+            result.push_str(&format!("{:<40} # {}\n", line, self.source(setting.key)));
+        }
+        result
+    }
+
+    /// Returns `Ok(None)` when help was requested.
+    pub fn parse(args: &[String]) -> Result<Option<Config>, String> {
+        let mut command: Option<Command> = None;
+        let mut paths = Vec::new();
+        let mut program_arguments = None;
+        let mut options: Vec<(&OptionSpec, Option<String>)> = Vec::new();
+        let mut remaining = args.iter().skip(1);
+        while let Some(arg) = remaining.next() {
+            if arg == "--" {
+                program_arguments = Some(remaining.by_ref().cloned().collect::<Vec<String>>());
+                break;
+            }
+            let flag = match arg.strip_prefix("--") {
+                Some(flag) => flag,
+                None if arg.len() > 1 && arg.starts_with('-') => {
+                    for short in arg.chars().skip(1) {
+                        let spec = OPTIONS
+                            .iter()
+                            .find(|spec| spec.short == Some(short) && spec.value.is_none())
+                            .ok_or_else(|| format!("Unknown option -{}", short))?;
+                        if spec.name == "help" {
+                            return Ok(None);
+                        }
+                        options.push((spec, None));
+                    }
+                    continue;
+                }
+                None => {
+                    if command.is_some() {
+                        paths.push(arg.clone());
+                        continue;
+                    }
+                    let spec = COMMANDS
+                        .iter()
+                        .find(|spec| spec.name == arg)
+                        .ok_or_else(|| {
+                            with_suggestion(
+                                format!("Unknown command {}", arg),
+                                suggestion(arg, COMMANDS.iter().map(|spec| spec.name)),
+                            )
+                        })?;
+                    command = Some(spec.command);
+                    continue;
+                }
+            };
+            let (name, inline_value) = match flag.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (flag, None),
+            };
+            let spec = OPTIONS
+                .iter()
+                .find(|spec| spec.name == name)
+                .ok_or_else(|| {
+                    with_suggestion(
+                        format!("Unknown option --{}", name),
+                        suggestion(name, OPTIONS.iter().map(|spec| spec.name))
+                            .map(|candidate| format!("--{}", candidate))
+                            .as_deref(),
+                    )
+                })?;
+            let value = match (spec.value, inline_value) {
+                (None, Some(_)) => return Err(format!("Option --{} takes no value", name)),
+                (None, None) => None,
+                (Some(_), Some(value)) => Some(value),
+                (Some(placeholder), None) if optional(placeholder) => None,
+                (Some(placeholder), None) => Some(
+                    remaining
+                        .next()
+                        .cloned()
+                        .ok_or_else(|| format!("Option --{} expects {}", name, placeholder))?,
+                ),
+            };
+            if spec.name == "help" {
+                return Ok(None);
+            }
+            options.push((spec, value));
+        }
+        let command = command.ok_or_else(|| {
+            String::from("A command is required: inspect, resolve, run, shell, bench or edit")
+        })?;
+        let command_name = COMMANDS
+            .iter()
+            .find(|spec| spec.command == command)
+            .map(|spec| spec.name)
+            .unwrap_or_default();
+        let mut config = Config::new(command);
+        let variables: Vec<(String, String)> = env::vars().collect();
+        let config_path = options
+            .iter()
+            .find(|(spec, _)| spec.name == "config")
+            .and_then(|(_, value)| value.clone())
+            .or_else(|| {
+                variables
+                    .iter()
+                    .find(|(name, _)| name == CONFIG_VARIABLE)
+                    .map(|(_, value)| value.clone())
+            });
+        let config_file = match config_path {
+            Some(path) => config_file::load(&path, true)?,
+            None => match config_file::default_path() {
+                Some(path) => config_file::load(&path.to_string_lossy(), false)?,
+                None => None,
+            },
+        };
+        if let Some(file) = config_file.as_ref() {
+            config.apply_file(file)?;
+        }
+        config.apply_environment(&variables)?;
+        let mut verbosity = 0;
+        for (spec, value) in options.into_iter() {
+            if !spec.commands.contains(&command) {
+                return Err(format!(
+                    "Option --{} is not supported by {}",
+                    spec.name, command_name
+                ));
+            }
+            let value = value.unwrap_or_default();
+            match spec.name {
+                "quiet" => verbosity = -1,
+                "verbose" => verbosity = verbosity.max(0) + 1,
+                "log-file" => {
+                    config.log_file = Some(value);
+                    config.set_source("log_file", Source::CommandLine);
+                }
+                "show-config" => config.show_config = true,
+                "color" => config.color = ColorMode::parse(&value)?,
+                "summary" => {
+                    config.summary = config.summary.or(Some(SummaryFormat::Plain));
+                }
+                "summary-format" => config.summary = Some(SummaryFormat::parse(&value)?),
+                "debuginfo-dir" => config.debuginfo_dirs.push(value),
+                "stats" => config.stats = true,
+                "progress" => config.progress = true,
+                "prelink-cache" => config.prelink_cache = Some(value),
+                "dep-graph" => config.dep_graph = Some(value),
+                "sysroot" => {
+                    config.sysroot = Some(value);
+                    config.set_source("sysroot", Source::CommandLine);
+                }
+                "offline" => config.offline = true,
+                "search-dir" => config.search_dirs.push(value),
+                "manifest" => {
+                    config.manifest = Some(Manifest::load(&value).map_err(|err| err.to_string())?)
+                }
+                "manifest-fallback" => config.manifest_fallback = true,
+                "fuzzy-soname" => config.fuzzy_soname = true,
+                "libc" => config.libc = Some(LibcFlavor::parse(&value)?),
+                "argv0" => config.argv0 = Some(value),
+                "dump-got" => config.dump_got = true,
+                "diff-image" => config.diff_image = true,
+                "check" => config.check = true,
+                "maps" => config.maps = true,
+                "from-memory" => config.from_memory = true,
+                "fd" => {
+                    let fd = value
+                        .parse::<i32>()
+                        .ok()
+                        .filter(|fd| *fd >= 0)
+                        .ok_or_else(|| format!("Invalid value {} for --fd", value))?;
+                    config.fd = Some(fd);
+                }
+                "prefault" => config.load_options.prefault = true,
+                "advise-sequential" => config.load_options.advise_sequential = true,
+                "no-noexec-fallback" => config.load_options.noexec_fallback = false,
+                "strict" => config.load_options.strict = true,
+                "force" => config.load_options.force = true,
+                "best-effort" => config.load_options.best_effort = true,
+                "debug" => debug_categories(&value, &mut config.load_options)?,
+                "cpu-features" => config.load_options.cpu_features = CpuFeatures::parse(&value)?,
+                "object-rule" => config.object_rules.push(ObjectRule::parse(&value)?),
+                "stub" => config.stubs.push(value),
+                "no-crash-handler" => config.load_options.crash_handler = false,
+                "fork" => config.fork = true,
+                "each" => {
+                    config.each = true;
+                    config.fork = true;
+                }
+                "pause-after-load" => {
+                    config.load_options.pause_after_load = Some(if value.is_empty() {
+                        PauseMode::Signal
+                    } else {
+                        PauseMode::parse(&value)?
+                    })
+                }
+                "no-exec" => config.no_exec = true,
+                "stack-size" => {
+                    config.load_options.stack_size = parse_number(spec.name, &value)? as usize;
+                    config.set_source("stack_size", Source::CommandLine);
+                }
+                "iterations" => {
+                    config.iterations = parse_number(spec.name, &value)? as usize;
+                    if config.iterations == 0 {
+                        return Err(String::from("--iterations must be at least 1"));
+                    }
+                }
+                "json" => config.json = true,
+                "report" => config.report = Some(value),
+                "rss-report" => config.rss_report = true,
+                "record" => config.record = Some(value),
+                "replay" => {
+                    config.replay = Some(Bundle::load(&value).map_err(|err| err.to_string())?)
+                }
+                "set-rpath" => config.set_rpath = Some(value),
+                "remove-section" => config.remove_sections.push(value),
+                "output" => config.output = Some(value),
+                "base-address" => {
+                    config.load_options.base_address = parse_number(spec.name, &value)?;
+                    config.set_source("base", Source::CommandLine);
+                }
+                "max-object-size" => {
+                    config.load_options.max_object_size = parse_number(spec.name, &value)?;
+                    config.set_source("max_object_size", Source::CommandLine);
+                }
+                _ => {}
+            }
+        }
+        if verbosity != 0 {
+            config.log_level = Level::from_verbosity(verbosity);
+            config.set_source("log_level", Source::CommandLine);
+        }
+        if let Some(arguments) = program_arguments {
+            if command != Command::Run && command != Command::Bench {
+                return Err(format!("{} does not take program arguments", command_name));
+            }
+            config.program_arguments = arguments;
+        }
+        if let Some(bundle) = config.replay.clone() {
+            if !paths.is_empty() {
+                return Err(String::from(
+                    "--replay loads the recorded program, not a file",
+                ));
+            }
+            if config.record.is_some() {
+                return Err(String::from("--record and --replay are exclusive"));
+            }
+            paths.push(bundle.program_path());
+            config.manifest = Some(bundle.manifest().map_err(|err| err.to_string())?);
+            config.manifest_fallback = false;
+            config.offline = true;
+            config.search_dirs = vec![bundle.directory.display().to_string()];
+            config.load_options.base_address = bundle.base;
+            config.set_source("base", Source::CommandLine);
+            if config.argv0.is_none() {
+                config.argv0 = Some(bundle.program.0.clone());
+            }
+        }
+        if config.fd.is_some() {
+            if !paths.is_empty() {
+                return Err(String::from(
+                    "--fd reads the file from a descriptor, not a path",
+                ));
+            }
+            if config.summary.is_some() {
+                return Err(String::from("--summary does not read from --fd"));
+            }
+        }
+        if !config.show_config {
+            config.check_path_count(paths.len() + config.fd.iter().count())?;
+            if command == Command::Edit && config.output.is_none() {
+                return Err(String::from("edit requires --output"));
+            }
+            if config.offline && config.search_dirs.is_empty() {
+                return Err(String::from("--offline requires --search-dir"));
+            }
+            if !config.offline && !config.search_dirs.is_empty() {
+                return Err(String::from("--search-dir requires --offline"));
+            }
+            if config.no_exec && config.load_options.pause_after_load.is_some() {
+                return Err(String::from(
+                    "--pause-after-load and --no-exec are exclusive",
+                ));
+            }
+            if config.manifest_fallback && config.manifest.is_none() {
+                return Err(String::from("--manifest-fallback requires --manifest"));
+            }
+        }
+        config.paths = paths;
+        Ok(Some(config))
+    }
+
+    /// Also called once `-` has been expanded to the paths read from stdin, or to the file piped
+    /// to it.
+    pub fn check_path_count(&self, count: usize) -> Result<(), String> {
+        match (count, self.command) {
+            (0, _) => Err(String::from("A file path is required")),
+            (1, _) => Ok(()),
+            (_, Command::Run) if !self.each => {
+                Err(String::from("run accepts several files only with --each"))
+            }
+            (_, Command::Shell) => Err(String::from("shell accepts a single file")),
+            (_, Command::Bench) => Err(String::from("bench accepts a single file")),
+            (_, Command::Edit) => Err(String::from("edit accepts a single file")),
+            (_, Command::Resolve) if self.dep_graph.is_some() => {
+                Err(String::from("--dep-graph accepts a single file"))
+            }
+            (_, _) => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Value {
+    String(String),
+    Integer(u64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Boolean(_) => "boolean",
+            Value::Array(_) => "array",
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::String(value) => write!(
+                f,
+                "\"{}\"",
+                value
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n")
+                    .replace('\t', "\\t")
+            ),
+            Value::Integer(value) => write!(f, "{}", value),
+            Value::Boolean(value) => write!(f, "{}", value),
+            Value::Array(values) => {
+                let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+                write!(f, "[{}]", values.join(", "))
+            }
+        }
+    }
+}
+
+pub struct Entry {
+    pub key: String,
+    pub value: Value,
+    pub line: usize,
+    pub key_column: usize,
+    pub value_column: usize,
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    pub path: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.path, self.line, self.column, self.message
+        )
+    }
+}
+
+pub struct ConfigFile {
+    pub path: String,
+    pub entries: Vec<Entry>,
+}
+
+impl ConfigFile {
+    pub fn error(&self, line: usize, column: usize, message: String) -> ConfigError {
+        ConfigError {
+            path: self.path.clone(),
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME/drow/config`, falling back to `~/.config/drow/config`.
+pub fn default_path() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("drow").join("config"))
+}
+
+/// Reads `path`. A missing file is only an error when `required` is set.
+pub fn load(path: &str, required: bool) -> Result<Option<ConfigFile>, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == ErrorKind::NotFound && !required => return Ok(None),
+        Err(err) => return Err(format!("Unable to read config file {}: {}", path, err)),
+    };
+    let entries = parse(&text).map_err(|err| {
+        ConfigError {
+            path: path.to_string(),
+            ..err
+        }
+        .to_string()
+    })?;
+    Ok(Some(ConfigFile {
+        path: path.to_string(),
+        entries,
+    }))
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> ConfigError {
+        ConfigError {
+            path: String::new(),
+            line: self.line,
+            column: self.position + 1,
+            message: message.to_string(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ') | Some('\t')) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ConfigError> {
+        if self.peek() == Some(expected) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", expected)))
+        }
+    }
+
+    fn key(&mut self) -> Result<String, ConfigError> {
+        let start = self.position;
+        while matches!(self.peek(), Some(ch) if ch.is_ascii_alphanumeric() || ch == '_' || ch == '-')
+        {
+            self.position += 1;
+        }
+        if start == self.position {
+            return Err(self.error("expected a key"));
+        }
+        Ok(self.chars[start..self.position].iter().collect())
+    }
+
+    fn value(&mut self) -> Result<Value, ConfigError> {
+        match self.peek() {
+            Some('"') => self.string(),
+            Some('[') => self.array(),
+            Some(ch) if ch.is_ascii_digit() => self.integer(),
+            Some(ch) if ch.is_ascii_alphabetic() => {
+                let start = self.position;
+                let word = self.key()?;
+                match word.as_str() {
+                    "true" => Ok(Value::Boolean(true)),
+                    "false" => Ok(Value::Boolean(false)),
+                    _ => {
+                        self.position = start;
+                        Err(self.error(&format!("unexpected {}, strings must be quoted", word)))
+                    }
+                }
+            }
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("missing value")),
+        }
+    }
+
+    fn string(&mut self) -> Result<Value, ConfigError> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            match self.peek() {
+                Some('"') => {
+                    self.position += 1;
+                    return Ok(Value::String(value));
+                }
+                Some('\\') => {
+                    self.position += 1;
+                    let escaped = match self.peek() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        _ => return Err(self.error("invalid escape sequence")),
+                    };
+                    value.push(escaped);
+                    self.position += 1;
+                }
+                Some(ch) => {
+                    value.push(ch);
+                    self.position += 1;
+                }
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn integer(&mut self) -> Result<Value, ConfigError> {
+        let start = self.position;
+        while matches!(self.peek(), Some(ch) if ch.is_ascii_alphanumeric() || ch == '_') {
+            self.position += 1;
+        }
+        let literal: String = self.chars[start..self.position]
+            .iter()
+            .filter(|ch| **ch != '_')
+            .collect();
+        let parsed = match literal.strip_prefix("0x") {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => literal.parse::<u64>(),
+        };
+        parsed.map(Value::Integer).map_err(|_| {
+            self.position = start;
+            self.error(&format!("invalid integer {}", literal))
+        })
+    }
+
+    fn array(&mut self) -> Result<Value, ConfigError> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(']') {
+                self.position += 1;
+                return Ok(Value::Array(values));
+            }
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some(']') => {}
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), ConfigError> {
+        self.skip_whitespace();
+        match self.peek() {
+            None | Some('#') => Ok(()),
+            Some(_) => Err(self.error("unexpected characters after value")),
+        }
+    }
+
+    fn entry(&mut self) -> Result<Option<Entry>, ConfigError> {
+        self.skip_whitespace();
+        match self.peek() {
+            None | Some('#') => return Ok(None),
+            Some('[') => return Err(self.error("sections are not supported")),
+            _ => {}
+        }
+        let key_column = self.position + 1;
+        let key = self.key()?;
+        self.skip_whitespace();
+        self.expect('=')?;
+        self.skip_whitespace();
+        let value_column = self.position + 1;
+        let value = self.value()?;
+        self.end_of_line()?;
+        Ok(Some(Entry {
+            key,
+            value,
+            line: self.line,
+            key_column,
+            value_column,
+        }))
+    }
+}
+
+/// Parses `key = value` lines. Values are quoted strings, integers, booleans or single-line
+/// arrays of those, and `#` starts a comment.
+pub fn parse(text: &str) -> Result<Vec<Entry>, ConfigError> {
+    let mut entries: Vec<Entry> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let mut parser = Parser {
+            chars: line.chars().collect(),
+            position: 0,
+            line: index + 1,
+        };
+        if let Some(entry) = parser.entry()? {
+            if entries.iter().any(|existing| existing.key == entry.key) {
+                return Err(ConfigError {
+                    path: String::new(),
+                    line: entry.line,
+                    column: entry.key_column,
+                    message: format!("duplicate key {}", entry.key),
+                });
+            }
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
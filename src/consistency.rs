@@ -0,0 +1,225 @@
+//! Cross-checks of the section headers against the program headers, run by `--strict`.
+//!
+//! The loader maps segments, zeroes their memory past the file-backed part and reads the dynamic
+//! array through PT_DYNAMIC, so sections that disagree with the segments are ignored otherwise.
+
+use crate::string_tables::StringTable;
+use crate::{
+    Elf64Metadata, Elf64ProgramHeader, Elf64SectionHeader, ELF64_SECTION_HEADER_DYNAMIC,
+    ELF64_SECTION_HEADER_NO_BITS, PROGRAM_HEADER_TYPE_DYNAMIC, PROGRAM_HEADER_TYPE_LOADABLE,
+};
+
+fn describe_segment(segment: &Elf64ProgramHeader) -> String {
+    format!(
+        "the PT_LOAD at {:#x}-{:#x} ({}{}{}, file-backed to {:#x})",
+        segment.p_virtual_address,
+        segment.p_virtual_address + segment.p_memory_size,
+        if segment.read() { 'R' } else { '-' },
+        if segment.write() { 'W' } else { '-' },
+        if segment.execute() { 'X' } else { '-' },
+        segment.p_virtual_address + segment.p_file_size,
+    )
+}
+
+struct Checker<'a> {
+    elf_metadata: &'a Elf64Metadata,
+    names: Option<&'a StringTable>,
+    violations: Vec<String>,
+}
+
+impl<'a> Checker<'a> {
+    fn section_name(&self, index: usize, section: &Elf64SectionHeader) -> String {
+        match self.names.and_then(|names| names.get(section.sh_name).ok()) {
+            Some(name) if !name.is_empty() => format!("[{}] {}", index, name),
+            _ => format!("[{}]", index),
+        }
+    }
+
+    fn loadable(&self) -> impl Iterator<Item = &'a Elf64ProgramHeader> + Clone {
+        let elf_metadata: &'a Elf64Metadata = self.elf_metadata;
+        elf_metadata
+            .program_headers
+            .iter()
+            .filter(|h| h.p_type == PROGRAM_HEADER_TYPE_LOADABLE)
+    }
+
+    /// The PT_LOAD whose memory holds `address`.
+    fn segment_at(&self, address: u64) -> Option<&'a Elf64ProgramHeader> {
+        self.loadable().find(|segment| {
+            segment.p_virtual_address <= address
+                && address < segment.p_virtual_address + segment.p_memory_size
+        })
+    }
+
+    /// An allocated section must lie in a PT_LOAD allowing what its flags do. Its file content
+    /// must be where the segment maps it from, and a NOBITS section must not cover file content.
+    fn check_section(&mut self, index: usize, section: &Elf64SectionHeader) {
+        let name = self.section_name(index, section);
+        let start = section.sh_virtual_address;
+        let end = start + section.sh_size;
+        if section.thread_local() {
+            // TLS sections are templates, .tbss takes no room in the segment that holds it.
+            match self.elf_metadata.tls_segments.first() {
+                Some(tls)
+                    if tls.virtual_address <= start
+                        && end <= tls.virtual_address + tls.memory_size => {}
+                Some(tls) => self.violations.push(format!(
+                    "TLS section {} at {:#x}-{:#x} is outside PT_TLS at {:#x}-{:#x}",
+                    name,
+                    start,
+                    end,
+                    tls.virtual_address,
+                    tls.virtual_address + tls.memory_size
+                )),
+                None => self.violations.push(format!(
+                    "TLS section {} at {:#x}-{:#x} has no PT_TLS",
+                    name, start, end
+                )),
+            }
+            return;
+        }
+        let segment = match self.segment_at(start) {
+            Some(segment) => segment,
+            None => {
+                self.violations.push(format!(
+                    "section {} at {:#x}-{:#x} is not in any PT_LOAD",
+                    name, start, end
+                ));
+                return;
+            }
+        };
+        let segment_end = segment.p_virtual_address + segment.p_memory_size;
+        let file_end = segment.p_virtual_address + segment.p_file_size;
+        if end > segment_end {
+            self.violations.push(format!(
+                "section {} at {:#x}-{:#x} extends past {}",
+                name,
+                start,
+                end,
+                describe_segment(segment)
+            ));
+        }
+        if section.writable() && !segment.write() {
+            self.violations.push(format!(
+                "section {} at {:#x} is writable, {} is not",
+                name,
+                start,
+                describe_segment(segment)
+            ));
+        }
+        if section.executable() && !segment.execute() {
+            self.violations.push(format!(
+                "section {} at {:#x} is executable, {} is not",
+                name,
+                start,
+                describe_segment(segment)
+            ));
+        }
+        if section.sh_type == ELF64_SECTION_HEADER_NO_BITS {
+            if start < file_end {
+                self.violations.push(format!(
+                    "NOBITS section {} at {:#x}-{:#x} overlaps the file content of {}",
+                    name,
+                    start,
+                    end,
+                    describe_segment(segment)
+                ));
+            }
+        } else if end > file_end {
+            self.violations.push(format!(
+                "section {} at {:#x}-{:#x} has file content past {}",
+                name,
+                start,
+                end,
+                describe_segment(segment)
+            ));
+        } else if section.sh_offset.wrapping_sub(segment.p_offset)
+            != start - segment.p_virtual_address
+        {
+            self.violations.push(format!(
+                "section {} at {:#x} has file offset {:#x}, {} maps {:#x} there",
+                name,
+                start,
+                section.sh_offset,
+                describe_segment(segment),
+                segment.p_offset + start - segment.p_virtual_address
+            ));
+        }
+    }
+
+    /// PT_DYNAMIC and the dynamic section must describe the same bytes.
+    fn check_dynamic(&mut self) {
+        let segment = self
+            .elf_metadata
+            .program_headers
+            .iter()
+            .find(|h| h.p_type == PROGRAM_HEADER_TYPE_DYNAMIC);
+        let section = self
+            .elf_metadata
+            .section_headers
+            .iter()
+            .enumerate()
+            .find(|(_, h)| h.sh_type == ELF64_SECTION_HEADER_DYNAMIC);
+        match (segment, section) {
+            (Some(segment), Some((index, section))) => {
+                if segment.p_virtual_address != section.sh_virtual_address
+                    || segment.p_offset != section.sh_offset
+                    || segment.p_file_size != section.sh_size
+                {
+                    self.violations.push(format!(
+                        "PT_DYNAMIC at {:#x} (file offset {:#x}, {} bytes) differs from dynamic \
+                         section {} at {:#x} (file offset {:#x}, {} bytes)",
+                        segment.p_virtual_address,
+                        segment.p_offset,
+                        segment.p_file_size,
+                        self.section_name(index, section),
+                        section.sh_virtual_address,
+                        section.sh_offset,
+                        section.sh_size
+                    ));
+                }
+            }
+            (Some(segment), None) => self.violations.push(format!(
+                "PT_DYNAMIC at {:#x} has no dynamic section",
+                segment.p_virtual_address
+            )),
+            (None, Some((index, section))) => self.violations.push(format!(
+                "dynamic section {} at {:#x} has no PT_DYNAMIC",
+                self.section_name(index, section),
+                section.sh_virtual_address
+            )),
+            (None, None) => {}
+        }
+    }
+
+    /// The tables the dynamic entries point to must be mapped.
+    fn check_dynamic_addresses(&mut self) {
+        for (tag, address) in self.elf_metadata.dynamic.addresses.iter() {
+            if self.segment_at(*address).is_none() {
+                self.violations
+                    .push(format!("{} {:#x} is not in any PT_LOAD", tag, address));
+            }
+        }
+    }
+}
+
+/// The ways the section headers of `elf_metadata` disagree with its program headers, each with
+/// the values of both. `names` is the section name string table, sections are numbered without.
+/// Files without section headers have nothing to disagree with.
+pub fn violations(elf_metadata: &Elf64Metadata, names: Option<&StringTable>) -> Vec<String> {
+    let mut checker = Checker {
+        elf_metadata,
+        names,
+        violations: Vec::new(),
+    };
+    for (index, section) in elf_metadata.section_headers.iter().enumerate() {
+        if index > 0 && section.allocated_in_memory() && section.sh_size > 0 {
+            checker.check_section(index, section);
+        }
+    }
+    if !elf_metadata.section_headers.is_empty() {
+        checker.check_dynamic();
+    }
+    checker.check_dynamic_addresses();
+    checker.violations
+}
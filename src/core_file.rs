@@ -0,0 +1,329 @@
+use std::io::{Read, Seek};
+
+use crate::error::DrowError;
+use crate::notes::{read_notes, read_u32, read_u64};
+use crate::table::{flag_letters, header, Table};
+use crate::{Elf64Metadata, PROGRAM_HEADER_TYPE_LOADABLE, PROGRAM_HEADER_TYPE_NOTE};
+
+const NOTE_PRSTATUS: u32 = 1;
+const NOTE_PRPSINFO: u32 = 3;
+const NOTE_AUXV: u32 = 6;
+const NOTE_FILE: u32 = 0x4649_4c45;
+
+const PRSTATUS_SIGNAL_OFFSET: usize = 12;
+const PRSTATUS_PID_OFFSET: usize = 32;
+const PRSTATUS_PARENT_PID_OFFSET: usize = 36;
+const PRSTATUS_REGISTERS_OFFSET: usize = 112;
+const PRPSINFO_PID_OFFSET: usize = 24;
+const PRPSINFO_FILE_NAME_OFFSET: usize = 40;
+const PRPSINFO_FILE_NAME_SIZE: usize = 16;
+const PRPSINFO_ARGUMENTS_OFFSET: usize = 56;
+const PRPSINFO_ARGUMENTS_SIZE: usize = 80;
+
+const REGISTER_NAMES: [&str; 27] = [
+    "r15", "r14", "r13", "r12", "rbp", "rbx", "r11", "r10", "r9", "r8", "rax", "rcx", "rdx", "rsi",
+    "rdi", "orig_rax", "rip", "cs", "eflags", "rsp", "ss", "fs_base", "gs_base", "ds", "es", "fs",
+    "gs",
+];
+
+pub struct CoreMapping {
+    pub virtual_address: u64,
+    pub memory_size: u64,
+    pub file_size: u64,
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+pub struct CoreThread {
+    pub signal: u16,
+    pub pid: i32,
+    pub parent_pid: i32,
+    pub registers: Vec<(&'static str, u64)>,
+}
+
+pub struct CoreProcess {
+    pub pid: i32,
+    pub file_name: String,
+    pub arguments: String,
+}
+
+pub struct CoreMappedFile {
+    pub start: u64,
+    pub end: u64,
+    pub page_offset: u64,
+    pub path: String,
+}
+
+pub struct CoreFile {
+    pub mappings: Vec<CoreMapping>,
+    pub threads: Vec<CoreThread>,
+    pub process: Option<CoreProcess>,
+    pub files: Vec<CoreMappedFile>,
+    pub auxiliary_vector: Vec<(u64, u64)>,
+}
+
+fn malformed(what: String) -> DrowError {
+    DrowError::Malformed { what, offset: None }
+}
+
+fn c_string(content: &[u8]) -> String {
+    let end = content
+        .iter()
+        .position(|b| *b == 0)
+        .unwrap_or(content.len());
+    String::from_utf8_lossy(&content[..end]).to_string()
+}
+
+fn auxiliary_vector_name(entry_type: u64) -> String {
+    match entry_type {
+        3 => String::from("AT_PHDR"),
+        4 => String::from("AT_PHENT"),
+        5 => String::from("AT_PHNUM"),
+        6 => String::from("AT_PAGESZ"),
+        7 => String::from("AT_BASE"),
+        8 => String::from("AT_FLAGS"),
+        9 => String::from("AT_ENTRY"),
+        11 => String::from("AT_UID"),
+        12 => String::from("AT_EUID"),
+        13 => String::from("AT_GID"),
+        14 => String::from("AT_EGID"),
+        15 => String::from("AT_PLATFORM"),
+        16 => String::from("AT_HWCAP"),
+        17 => String::from("AT_CLKTCK"),
+        23 => String::from("AT_SECURE"),
+        25 => String::from("AT_RANDOM"),
+        26 => String::from("AT_HWCAP2"),
+        31 => String::from("AT_EXECFN"),
+        33 => String::from("AT_SYSINFO_EHDR"),
+        51 => String::from("AT_MINSIGSTKSZ"),
+        other => other.to_string(),
+    }
+}
+
+impl CoreThread {
+    fn parse(description: &[u8]) -> Result<CoreThread, DrowError> {
+        let registers_end = PRSTATUS_REGISTERS_OFFSET + REGISTER_NAMES.len() * 8;
+        if description.len() < registers_end {
+            return Err(malformed(format!(
+                "NT_PRSTATUS note too short: {} bytes",
+                description.len()
+            )));
+        }
+        let registers = REGISTER_NAMES
+            .iter()
+            .enumerate()
+            .map(|(index, name)| {
+                (
+                    *name,
+                    read_u64(description, PRSTATUS_REGISTERS_OFFSET + index * 8),
+                )
+            })
+            .collect();
+        Ok(CoreThread {
+            signal: u16::from_le_bytes([
+                description[PRSTATUS_SIGNAL_OFFSET],
+                description[PRSTATUS_SIGNAL_OFFSET + 1],
+            ]),
+            pid: read_u32(description, PRSTATUS_PID_OFFSET) as i32,
+            parent_pid: read_u32(description, PRSTATUS_PARENT_PID_OFFSET) as i32,
+            registers,
+        })
+    }
+}
+
+impl CoreProcess {
+    fn parse(description: &[u8]) -> Result<CoreProcess, DrowError> {
+        let arguments_end = PRPSINFO_ARGUMENTS_OFFSET + PRPSINFO_ARGUMENTS_SIZE;
+        if description.len() < arguments_end {
+            return Err(malformed(format!(
+                "NT_PRPSINFO note too short: {} bytes",
+                description.len()
+            )));
+        }
+        Ok(CoreProcess {
+            pid: read_u32(description, PRPSINFO_PID_OFFSET) as i32,
+            file_name: c_string(
+                &description[PRPSINFO_FILE_NAME_OFFSET
+                    ..PRPSINFO_FILE_NAME_OFFSET + PRPSINFO_FILE_NAME_SIZE],
+            ),
+            arguments: c_string(&description[PRPSINFO_ARGUMENTS_OFFSET..arguments_end]),
+        })
+    }
+}
+
+impl CoreMappedFile {
+    fn parse(description: &[u8]) -> Result<Vec<CoreMappedFile>, DrowError> {
+        if description.len() < 16 {
+            return Err(malformed(String::from("NT_FILE note too short")));
+        }
+        let count = read_u64(description, 0);
+        let page_size = read_u64(description, 8);
+        let too_short = || malformed(format!("NT_FILE note too short for {} entries", count));
+        let names_start = count
+            .checked_mul(24)
+            .and_then(|size| size.checked_add(16))
+            .filter(|end| *end <= description.len() as u64)
+            .ok_or_else(too_short)? as usize;
+        let count = count as usize;
+        let mut names = description[names_start..].split(|b| *b == 0);
+        let mut files = Vec::new();
+        for index in 0..count {
+            let entry = 16 + index * 24;
+            let path = names
+                .next()
+                .map(|name| String::from_utf8_lossy(name).to_string())
+                .ok_or_else(|| {
+                    malformed(format!(
+                        "NT_FILE note is missing the path of entry {}",
+                        index
+                    ))
+                })?;
+            let page_offset = read_u64(description, entry + 16)
+                .checked_mul(page_size)
+                .ok_or_else(|| {
+                    malformed(format!(
+                        "NT_FILE entry {} has a file offset past 2^64",
+                        index
+                    ))
+                })?;
+            files.push(CoreMappedFile {
+                start: read_u64(description, entry),
+                end: read_u64(description, entry + 8),
+                page_offset,
+                path,
+            });
+        }
+        Ok(files)
+    }
+}
+
+impl CoreFile {
+    pub fn load<T: Read + Seek>(
+        elf_metadata: &Elf64Metadata,
+        reader: &mut T,
+    ) -> Result<CoreFile, DrowError> {
+        let mut core_file = CoreFile {
+            mappings: Vec::new(),
+            threads: Vec::new(),
+            process: None,
+            files: Vec::new(),
+            auxiliary_vector: Vec::new(),
+        };
+        for program_header in elf_metadata.program_headers.iter() {
+            if program_header.p_type == PROGRAM_HEADER_TYPE_LOADABLE {
+                core_file.mappings.push(CoreMapping {
+                    virtual_address: program_header.p_virtual_address,
+                    memory_size: program_header.p_memory_size,
+                    file_size: program_header.p_file_size,
+                    read: program_header.read(),
+                    write: program_header.write(),
+                    execute: program_header.execute(),
+                });
+            }
+            if program_header.p_type != PROGRAM_HEADER_TYPE_NOTE {
+                continue;
+            }
+            let notes =
+                read_notes(reader, program_header).map_err(|what| DrowError::Malformed {
+                    what,
+                    offset: Some(program_header.p_offset),
+                })?;
+            for note in notes {
+                if note.name != "CORE" {
+                    continue;
+                }
+                match note.note_type {
+                    NOTE_PRSTATUS => core_file
+                        .threads
+                        .push(CoreThread::parse(&note.description)?),
+                    NOTE_PRPSINFO => {
+                        core_file.process = Some(CoreProcess::parse(&note.description)?)
+                    }
+                    NOTE_FILE => core_file
+                        .files
+                        .append(&mut CoreMappedFile::parse(&note.description)?),
+                    NOTE_AUXV => {
+                        for entry in note.description.chunks_exact(16) {
+                            let entry_type = read_u64(entry, 0);
+                            if entry_type == 0 {
+                                break;
+                            }
+                            core_file
+                                .auxiliary_vector
+                                .push((entry_type, read_u64(entry, 8)));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(core_file)
+    }
+
+    pub fn print(&self, color: bool) {
+        if let Some(process) = self.process.as_ref() {
+            println!("{}", header("Process", color));
+            println!("PID: {}", process.pid);
+            println!("Command: {}", process.file_name);
+            println!("Arguments: {}", process.arguments);
+        }
+        for thread in self.threads.iter() {
+            println!(
+                "{}",
+                header(
+                    format!("Thread {} (parent {})", thread.pid, thread.parent_pid).as_str(),
+                    color
+                )
+            );
+            println!("Signal: {}", thread.signal);
+            let mut registers = Table::new(&["Register", "Value"]);
+            for (name, value) in thread.registers.iter() {
+                registers.add_row(vec![name.to_string(), format!("{:016X}", value)]);
+            }
+            print!("{}", registers.render(color));
+        }
+        println!("{}", header("Memory mappings", color));
+        let mut mappings = Table::new(&["VirtAddr", "MemSize", "FileSize", "Flags"]);
+        for mapping in self.mappings.iter() {
+            mappings.add_row(vec![
+                format!("{:016X}", mapping.virtual_address),
+                format!("{:#X}", mapping.memory_size),
+                format!("{:#X}", mapping.file_size),
+                flag_letters(
+                    &[
+                        ('R', mapping.read),
+                        ('W', mapping.write),
+                        ('X', mapping.execute),
+                    ],
+                    color,
+                ),
+            ]);
+        }
+        print!("{}", mappings.render(color));
+        if !self.files.is_empty() {
+            println!("{}", header("Mapped files", color));
+            let mut files = Table::new(&["Start", "End", "Offset", "Path"]);
+            for file in self.files.iter() {
+                files.add_row(vec![
+                    format!("{:016X}", file.start),
+                    format!("{:016X}", file.end),
+                    format!("{:#X}", file.page_offset),
+                    file.path.clone(),
+                ]);
+            }
+            print!("{}", files.render(color));
+        }
+        if !self.auxiliary_vector.is_empty() {
+            println!("{}", header("Auxiliary vector", color));
+            let mut auxiliary_vector = Table::new(&["Type", "Value"]);
+            for (entry_type, value) in self.auxiliary_vector.iter() {
+                auxiliary_vector.add_row(vec![
+                    auxiliary_vector_name(*entry_type),
+                    format!("{:#X}", value),
+                ]);
+            }
+            print!("{}", auxiliary_vector.render(color));
+        }
+    }
+}
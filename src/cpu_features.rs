@@ -0,0 +1,302 @@
+//! The CPU features reported to ifunc resolvers and used to pick glibc-hwcaps subdirectories,
+//! detected with cpuid and optionally masked to force the baseline implementations.
+//!
+//! Masking only reaches resolvers that read their arguments. The resolvers of the x86-64 glibc
+//! take none and read the features the host dynamic loader stored in `_rtld_global_ro`, which
+//! drow hands to glibc unchanged, so they keep picking the native implementations.
+
+use std::arch::x86_64::{__cpuid, __cpuid_count, _xgetbv};
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+
+use crate::auxv;
+
+#[derive(Clone, Copy)]
+enum Register {
+    Ebx,
+    Ecx,
+    Edx,
+}
+
+/// A feature and where cpuid reports it: leaf, register and bit.
+struct Feature {
+    name: &'static str,
+    leaf: u32,
+    register: Register,
+    bit: u32,
+}
+
+const fn feature(name: &'static str, leaf: u32, register: Register, bit: u32) -> Feature {
+    Feature {
+        name,
+        leaf,
+        register,
+        bit,
+    }
+}
+
+/// The bit of a feature in `CpuFeatures` is its index here.
+const FEATURES: [Feature; 32] = [
+    feature("cmov", 1, Register::Edx, 15),
+    feature("mmx", 1, Register::Edx, 23),
+    feature("fxsr", 1, Register::Edx, 24),
+    feature("sse", 1, Register::Edx, 25),
+    feature("sse2", 1, Register::Edx, 26),
+    feature("sse3", 1, Register::Ecx, 0),
+    feature("pclmulqdq", 1, Register::Ecx, 1),
+    feature("ssse3", 1, Register::Ecx, 9),
+    feature("fma", 1, Register::Ecx, 12),
+    feature("cx16", 1, Register::Ecx, 13),
+    feature("sse4_1", 1, Register::Ecx, 19),
+    feature("sse4_2", 1, Register::Ecx, 20),
+    feature("movbe", 1, Register::Ecx, 22),
+    feature("popcnt", 1, Register::Ecx, 23),
+    feature("aes", 1, Register::Ecx, 25),
+    feature("xsave", 1, Register::Ecx, 26),
+    feature("avx", 1, Register::Ecx, 28),
+    feature("f16c", 1, Register::Ecx, 29),
+    feature("rdrand", 1, Register::Ecx, 30),
+    feature("bmi1", 7, Register::Ebx, 3),
+    feature("avx2", 7, Register::Ebx, 5),
+    feature("bmi2", 7, Register::Ebx, 8),
+    feature("erms", 7, Register::Ebx, 9),
+    feature("avx512f", 7, Register::Ebx, 16),
+    feature("avx512dq", 7, Register::Ebx, 17),
+    feature("avx512cd", 7, Register::Ebx, 28),
+    feature("avx512bw", 7, Register::Ebx, 30),
+    feature("avx512vl", 7, Register::Ebx, 31),
+    feature("fsrm", 7, Register::Edx, 4),
+    feature("lahf_lm", 0x8000_0001, Register::Ecx, 0),
+    feature("lzcnt", 0x8000_0001, Register::Ecx, 5),
+    feature("osxsave", 1, Register::Ecx, 27),
+];
+
+/// Every x86-64 CPU has these.
+const BASELINE: [&str; 5] = ["cmov", "mmx", "fxsr", "sse", "sse2"];
+
+/// Need the operating system to save the AVX registers, as reported by xgetbv.
+const AVX_FEATURES: [&str; 5] = ["avx", "avx2", "fma", "f16c", "xsave"];
+const AVX512_FEATURES: [&str; 5] = ["avx512f", "avx512dq", "avx512cd", "avx512bw", "avx512vl"];
+
+/// The x86-64 microarchitecture levels glibc-hwcaps subdirectories are named after, highest
+/// first, with the features each adds to the levels below it.
+const LEVELS: [(&str, &[&str]); 3] = [
+    (
+        "x86-64-v4",
+        &["avx512f", "avx512dq", "avx512cd", "avx512bw", "avx512vl"],
+    ),
+    (
+        "x86-64-v3",
+        &[
+            "avx", "avx2", "bmi1", "bmi2", "f16c", "fma", "lzcnt", "movbe", "xsave",
+        ],
+    ),
+    (
+        "x86-64-v2",
+        &[
+            "cx16", "lahf_lm", "popcnt", "sse3", "sse4_1", "sse4_2", "ssse3",
+        ],
+    ),
+];
+
+/// Set in the hwcap argument when the second argument of a resolver is valid, like
+/// `_IFUNC_ARG_HWCAP` of the aarch64 glibc.
+const IFUNC_ARG_HWCAP: u64 = 1 << 62;
+
+fn mask(names: &[&str]) -> u64 {
+    names
+        .iter()
+        .filter_map(|name| FEATURES.iter().position(|feature| feature.name == *name))
+        .fold(0, |mask, index| mask | 1 << index)
+}
+
+fn detect() -> u64 {
+    let maximum_leaf = __cpuid(0).eax;
+    let maximum_extended_leaf = __cpuid(0x8000_0000).eax;
+    let mut detected = 0;
+    for (index, feature) in FEATURES.iter().enumerate() {
+        let available = if feature.leaf >= 0x8000_0000 {
+            feature.leaf <= maximum_extended_leaf
+        } else {
+            feature.leaf <= maximum_leaf
+        };
+        if !available {
+            continue;
+        }
+        let result = __cpuid_count(feature.leaf, 0);
+        let register = match feature.register {
+            Register::Ebx => result.ebx,
+            Register::Ecx => result.ecx,
+            Register::Edx => result.edx,
+        };
+        if register & (1 << feature.bit) != 0 {
+            detected |= 1 << index;
+        }
+    }
+    // The registers are only usable when the kernel saves them, like glibc checks.
+    let enabled_state = if detected & mask(&["osxsave"]) != 0 {
+        unsafe { _xgetbv(0) }
+    } else {
+        0
+    };
+    if enabled_state & 0x6 != 0x6 {
+        detected &= !mask(&AVX_FEATURES) & !mask(&AVX512_FEATURES);
+    } else if enabled_state & 0xe6 != 0xe6 {
+        detected &= !mask(&AVX512_FEATURES);
+    }
+    detected
+}
+
+static NATIVE: OnceLock<u64> = OnceLock::new();
+
+/// A set of features, at most those of the CPU.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CpuFeatures {
+    mask: u64,
+}
+
+impl CpuFeatures {
+    /// Everything the CPU supports.
+    pub fn native() -> CpuFeatures {
+        CpuFeatures {
+            mask: *NATIVE.get_or_init(detect),
+        }
+    }
+
+    /// The features every x86-64 CPU has, which makes resolvers pick their generic paths.
+    pub fn baseline() -> CpuFeatures {
+        CpuFeatures {
+            mask: CpuFeatures::native().mask & mask(&BASELINE),
+        }
+    }
+
+    /// Parses `baseline`, `native` or a comma-separated list of feature names, the latter
+    /// keeping only the listed features the CPU supports.
+    pub fn parse(value: &str) -> Result<CpuFeatures, String> {
+        match value {
+            "baseline" => return Ok(CpuFeatures::baseline()),
+            "native" => return Ok(CpuFeatures::native()),
+            _ => {}
+        }
+        let mut listed = 0;
+        for name in value.split(',').filter(|name| !name.is_empty()) {
+            match FEATURES.iter().position(|feature| feature.name == name) {
+                Some(index) => listed |= 1 << index,
+                None => {
+                    return Err(format!(
+                        "Unknown CPU feature: {}, expected baseline, native or some of {}",
+                        name,
+                        FEATURES
+                            .iter()
+                            .map(|feature| feature.name)
+                            .collect::<Vec<&str>>()
+                            .join(", ")
+                    ))
+                }
+            }
+        }
+        let native = CpuFeatures::native();
+        let missing = listed & !native.mask;
+        if missing != 0 {
+            warn!(
+                "The CPU does not support {}, leaving it out",
+                CpuFeatures { mask: missing }
+            );
+        }
+        Ok(CpuFeatures {
+            mask: listed & native.mask,
+        })
+    }
+
+    /// AT_HWCAP as the kernel reports it, cpuid leaf 1 edx on x86-64, without the bits of masked
+    /// features. Read from cpuid, as getauxval of glibc returns bits of its own for it.
+    pub fn hwcap(&self) -> u64 {
+        let hwcap = __cpuid(1).edx as u64;
+        FEATURES
+            .iter()
+            .enumerate()
+            .filter(|(_, feature)| feature.leaf == 1 && matches!(feature.register, Register::Edx))
+            .filter(|(index, _)| self.mask & (1 << index) == 0)
+            .fold(hwcap, |hwcap, (_, feature)| hwcap & !(1 << feature.bit))
+    }
+
+    /// The glibc-hwcaps subdirectories the features allow, the most capable first.
+    pub fn hwcaps_subdirectories(&self) -> Vec<String> {
+        let mut required = 0;
+        let mut subdirectories = Vec::new();
+        for (level, features) in LEVELS.iter().rev() {
+            required |= mask(features);
+            if self.mask & required != required {
+                break;
+            }
+            subdirectories.insert(0, format!("glibc-hwcaps/{}", level));
+        }
+        subdirectories
+    }
+}
+
+impl Default for CpuFeatures {
+    fn default() -> Self {
+        CpuFeatures::native()
+    }
+}
+
+impl Display for CpuFeatures {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<&str> = FEATURES
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| self.mask & (1 << index) != 0)
+            .map(|(_, feature)| feature.name)
+            .collect();
+        write!(f, "{}", names.join(","))
+    }
+}
+
+/// The second argument of a resolver: the layout of `__ifunc_arg_t` of the aarch64 glibc,
+/// followed by the features as a mask of `CpuFeatures` bits, in the order `--cpu-features`
+/// lists them. Atomics, as resolvers read it through a pointer to the static.
+#[repr(C)]
+pub(crate) struct ResolverArguments {
+    size: AtomicU64,
+    hwcap: AtomicU64,
+    hwcap2: AtomicU64,
+    features: AtomicU64,
+}
+
+static RESOLVER_ARGUMENTS: ResolverArguments = ResolverArguments {
+    size: AtomicU64::new(std::mem::size_of::<ResolverArguments>() as u64),
+    hwcap: AtomicU64::new(0),
+    hwcap2: AtomicU64::new(0),
+    features: AtomicU64::new(0),
+};
+
+/// Reports `features` to the resolvers called from now on. Resolvers run in the process that
+/// loaded them, so the arguments are shared by all loaders.
+pub(crate) fn install(features: CpuFeatures) {
+    RESOLVER_ARGUMENTS
+        .hwcap
+        .store(features.hwcap(), Ordering::SeqCst);
+    RESOLVER_ARGUMENTS
+        .hwcap2
+        .store(auxv::current().hwcap2().unwrap_or(0), Ordering::SeqCst);
+    RESOLVER_ARGUMENTS
+        .features
+        .store(features.mask, Ordering::SeqCst);
+}
+
+/// Calls the ifunc resolver at `address` with the hwcap and the arguments, which resolvers
+/// taking no arguments, like those of the x86-64 glibc, ignore.
+///
+/// # Safety
+///
+/// `address` must be a resolver.
+pub(crate) unsafe fn resolve(address: u64) -> u64 {
+    let resolver = std::mem::transmute::<
+        *const (),
+        unsafe extern "C" fn(u64, *const ResolverArguments) -> u64,
+    >(address as *const ());
+    let hwcap = RESOLVER_ARGUMENTS.hwcap.load(Ordering::SeqCst) | IFUNC_ARG_HWCAP;
+    resolver(hwcap, &RESOLVER_ARGUMENTS)
+}
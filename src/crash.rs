@@ -0,0 +1,188 @@
+//! Catches the fault that kills the loaded program, so it can be reported against the load map
+//! instead of as a bare signal number.
+
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+use crate::loader::Elf64Loader;
+use crate::syscall;
+use crate::tls::ThreadPointerGuard;
+
+const SIGNALS: [i32; 5] = [
+    libc::SIGSEGV,
+    libc::SIGBUS,
+    libc::SIGILL,
+    libc::SIGFPE,
+    libc::SIGABRT,
+];
+
+/// Frames walked through the frame pointer chain, the faulting one included.
+const MAX_FRAMES: usize = 32;
+
+/// Large enough for the handler and the formatting of the report in same-process mode.
+const ALTERNATE_STACK_SIZE: usize = 256 * 1024;
+
+// Statics, as the child shares drow's memory and is gone by the time its fault is reported.
+static SIGNAL: AtomicI32 = AtomicI32::new(0);
+static ADDRESS: AtomicU64 = AtomicU64::new(0);
+static FRAMES: [AtomicU64; MAX_FRAMES] = [const { AtomicU64::new(0) }; MAX_FRAMES];
+static FRAME_COUNT: AtomicUsize = AtomicUsize::new(0);
+/// The stack of the program, bounding the frame pointer chain.
+static STACK_START: AtomicU64 = AtomicU64::new(0);
+static STACK_END: AtomicU64 = AtomicU64::new(0);
+/// Set in same-process mode, where nothing waits for the program and the handler reports the
+/// fault itself.
+static REPORTER: AtomicPtr<Elf64Loader> = AtomicPtr::new(ptr::null_mut());
+static ARMED: AtomicBool = AtomicBool::new(false);
+static ALTERNATE_STACK: OnceLock<usize> = OnceLock::new();
+
+#[derive(Clone, Debug)]
+pub struct Crash {
+    pub signal: i32,
+    /// Address of the faulting instruction, then the return addresses of its callers.
+    pub frames: Vec<u64>,
+    /// Address the instruction accessed, for SIGSEGV and SIGBUS.
+    pub address: u64,
+}
+
+impl Crash {
+    pub fn signal_name(&self) -> String {
+        match self.signal {
+            libc::SIGSEGV => String::from("SIGSEGV"),
+            libc::SIGBUS => String::from("SIGBUS"),
+            libc::SIGILL => String::from("SIGILL"),
+            libc::SIGFPE => String::from("SIGFPE"),
+            libc::SIGABRT => String::from("SIGABRT"),
+            other => format!("signal {}", other),
+        }
+    }
+
+    pub fn memory_access(&self) -> bool {
+        matches!(self.signal, libc::SIGSEGV | libc::SIGBUS)
+    }
+
+    pub fn instruction(&self) -> u64 {
+        self.frames.first().copied().unwrap_or(0)
+    }
+}
+
+/// Follows the saved frame pointers from `frame_pointer`, as long as they stay inside the
+/// program stack and grow towards its end. Best effort: code built without frame pointers ends
+/// the walk early or skips callers.
+unsafe fn walk_frames(instruction: u64, mut frame_pointer: u64) {
+    let start = STACK_START.load(Ordering::SeqCst);
+    let end = STACK_END.load(Ordering::SeqCst);
+    FRAMES[0].store(instruction, Ordering::SeqCst);
+    let mut count = 1;
+    while count < MAX_FRAMES && frame_pointer >= start && frame_pointer + 16 <= end {
+        let frame = frame_pointer as *const u64;
+        let return_address = ptr::read_unaligned(frame.add(1));
+        if return_address == 0 {
+            break;
+        }
+        FRAMES[count].store(return_address, Ordering::SeqCst);
+        count += 1;
+        let next = ptr::read_unaligned(frame);
+        if next <= frame_pointer {
+            break;
+        }
+        frame_pointer = next;
+    }
+    FRAME_COUNT.store(count, Ordering::SeqCst);
+}
+
+extern "C" fn handler(signal: i32, info: *mut libc::siginfo_t, context: *mut libc::c_void) {
+    let _thread_pointer = ThreadPointerGuard::drow();
+    unsafe {
+        let context = context as *const libc::ucontext_t;
+        let registers = &(*context).uc_mcontext.gregs;
+        walk_frames(
+            registers[libc::REG_RIP as usize] as u64,
+            registers[libc::REG_RBP as usize] as u64,
+        );
+        ADDRESS.store((*info).si_addr() as u64, Ordering::SeqCst);
+        SIGNAL.store(signal, Ordering::SeqCst);
+        let reporter = REPORTER.load(Ordering::SeqCst);
+        if !reporter.is_null() {
+            if let Some(crash) = take(signal) {
+                error!("{}", (*reporter).describe_crash(&crash));
+            }
+        }
+        // The handler resets itself, so the signal now takes its default action and the exit
+        // status is the one the program would have had.
+        syscall::kill_self(signal);
+    }
+}
+
+/// Prepares the handler for a program running on the stack between `stack_start` and
+/// `stack_end`, reporting through `reporter` when there is one. The handler is installed
+/// separately by the process that runs the program.
+pub fn arm(reporter: Option<&Elf64Loader>, stack_start: u64, stack_end: u64) {
+    SIGNAL.store(0, Ordering::SeqCst);
+    STACK_START.store(stack_start, Ordering::SeqCst);
+    STACK_END.store(stack_end, Ordering::SeqCst);
+    let reporter = reporter
+        .map(|loader| loader as *const Elf64Loader as *mut Elf64Loader)
+        .unwrap_or(ptr::null_mut());
+    REPORTER.store(reporter, Ordering::SeqCst);
+    ALTERNATE_STACK.get_or_init(|| {
+        Box::leak(vec![0u8; ALTERNATE_STACK_SIZE].into_boxed_slice()).as_mut_ptr() as usize
+    });
+    ARMED.store(true, Ordering::SeqCst);
+}
+
+pub fn disarm() {
+    ARMED.store(false, Ordering::SeqCst);
+}
+
+/// Disarms the handler when it reports through `loader`, which is going away.
+pub fn detach(loader: &Elf64Loader) {
+    let loader = loader as *const Elf64Loader as *mut Elf64Loader;
+    if REPORTER
+        .compare_exchange(loader, ptr::null_mut(), Ordering::SeqCst, Ordering::SeqCst)
+        .is_ok()
+    {
+        disarm();
+    }
+}
+
+/// Installs the handler for the fatal signals of the calling process when armed, on an
+/// alternate stack so a smashed program stack still gets reported. Meant to run right before
+/// the entry point, the program being free to replace it.
+pub fn install() {
+    if !ARMED.load(Ordering::SeqCst) {
+        return;
+    }
+    let mut flags = libc::SA_SIGINFO | libc::SA_RESETHAND | libc::SA_NODEFER;
+    if let Some(address) = ALTERNATE_STACK.get() {
+        match syscall::sigaltstack_checked(*address as *mut libc::c_void, ALTERNATE_STACK_SIZE) {
+            Ok(()) => flags |= libc::SA_ONSTACK,
+            Err(errno) => warn!("Unable to set up the signal stack: {}", errno),
+        }
+    }
+    for signal in SIGNALS.iter() {
+        let installed = unsafe {
+            syscall::sigaction_checked(*signal, handler as *const () as libc::sighandler_t, flags)
+        };
+        if let Err(errno) = installed {
+            warn!("Unable to catch signal {}: {}", signal, errno);
+        }
+    }
+}
+
+/// The fault recorded by the handler, when it is the one that delivered `signal`.
+pub fn take(signal: i32) -> Option<Crash> {
+    if SIGNAL.swap(0, Ordering::SeqCst) != signal {
+        return None;
+    }
+    let count = FRAME_COUNT.load(Ordering::SeqCst).min(MAX_FRAMES);
+    Some(Crash {
+        signal,
+        frames: FRAMES[..count]
+            .iter()
+            .map(|frame| frame.load(Ordering::SeqCst))
+            .collect(),
+        address: ADDRESS.load(Ordering::SeqCst),
+    })
+}
@@ -0,0 +1,43 @@
+use std::io::{self, Read};
+
+const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+const fn table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut index = 0;
+    while index < 256 {
+        let mut value = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            value = if value & 1 != 0 {
+                (value >> 1) ^ POLYNOMIAL
+            } else {
+                value >> 1
+            };
+            bit += 1;
+        }
+        table[index] = value;
+        index += 1;
+    }
+    table
+}
+
+const TABLE: [u32; 256] = table();
+
+/// The CRC-32 of everything `reader` returns, as stored in .gnu_debuglink.
+pub fn checksum(mut reader: impl Read) -> io::Result<u32> {
+    let mut crc = !0u32;
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        for byte in buffer[..read].iter() {
+            crc = TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+        }
+    }
+    Ok(!crc)
+}
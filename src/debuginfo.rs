@@ -0,0 +1,212 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use crate::crc32;
+use crate::notes::read_u32;
+use crate::offset_reader::{read_segment, OffsetReader};
+use crate::string_tables::StringTable;
+use crate::summary::read_build_id;
+use crate::Elf64Metadata;
+
+/// Where distributions install detached debug files.
+pub const DEFAULT_DEBUGINFO_DIR: &str = "/usr/lib/debug";
+
+const DEBUG_LINK_SECTION: &str = ".gnu_debuglink";
+
+/// The content of .gnu_debuglink: the file name of the debug file and the CRC-32 of its bytes.
+pub struct DebugLink {
+    pub name: String,
+    pub crc: u32,
+}
+
+enum Check {
+    BuildId(String),
+    Crc(u32),
+}
+
+pub fn read_debug_link<T: Read + Seek>(
+    elf_metadata: &Elf64Metadata,
+    reader: &mut T,
+) -> Result<Option<DebugLink>, String> {
+    let names_index = elf_metadata.elf_header.e_section_name_string_table_index as usize;
+    let names = match elf_metadata.section_headers.get(names_index) {
+        Some(header) => StringTable::load(header, reader).map_err(|err| err.to_string())?,
+        None => return Ok(None),
+    };
+    let section = elf_metadata
+        .section_headers
+        .iter()
+        .find(|header| names.get(header.sh_name).ok() == Some(DEBUG_LINK_SECTION));
+    let section = match section {
+        Some(section) => section,
+        None => return Ok(None),
+    };
+    let content =
+        read_segment(reader, section.sh_offset, section.sh_size).map_err(|err| err.to_string())?;
+    let name_end = match content.iter().position(|b| *b == 0) {
+        Some(end) => end,
+        None => return Err(format!("{} without a terminated name", DEBUG_LINK_SECTION)),
+    };
+    let crc_offset = (name_end + 4) & !3;
+    if crc_offset + 4 > content.len() {
+        return Err(format!("{} without a CRC", DEBUG_LINK_SECTION));
+    }
+    Ok(Some(DebugLink {
+        name: String::from_utf8_lossy(&content[..name_end]).to_string(),
+        crc: read_u32(&content, crc_offset),
+    }))
+}
+
+/// Paths where the debug file of `elf_metadata` may be, each with how to tell it is the right
+/// one. `directories` replace /usr/lib/debug when not empty.
+fn candidates<T: Read + Seek>(
+    elf_metadata: &Elf64Metadata,
+    reader: &mut T,
+    directories: &[String],
+) -> Result<Vec<(String, Check)>, String> {
+    let default_directories = [DEFAULT_DEBUGINFO_DIR.to_string()];
+    let directories = if directories.is_empty() {
+        &default_directories[..]
+    } else {
+        directories
+    };
+    let mut result = Vec::new();
+    if let Some(build_id) = read_build_id(elf_metadata, reader)?.filter(|id| id.len() > 2) {
+        for directory in directories.iter() {
+            result.push((
+                format!(
+                    "{}/.build-id/{}/{}.debug",
+                    directory,
+                    &build_id[..2],
+                    &build_id[2..]
+                ),
+                Check::BuildId(build_id.clone()),
+            ));
+        }
+    }
+    if let Some(link) = read_debug_link(elf_metadata, reader)? {
+        let file_directory = Path::new(&elf_metadata.file_path)
+            .canonicalize()
+            .ok()
+            .and_then(|path| path.parent().map(|parent| parent.to_path_buf()))
+            .and_then(|parent| parent.to_str().map(String::from))
+            .unwrap_or_else(|| String::from("."));
+        result.push((
+            format!("{}/{}", file_directory, link.name),
+            Check::Crc(link.crc),
+        ));
+        result.push((
+            format!("{}/.debug/{}", file_directory, link.name),
+            Check::Crc(link.crc),
+        ));
+        for directory in directories.iter() {
+            result.push((
+                format!("{}{}/{}", directory, file_directory, link.name),
+                Check::Crc(link.crc),
+            ));
+            result.push((format!("{}/{}", directory, link.name), Check::Crc(link.crc)));
+        }
+    }
+    Ok(result)
+}
+
+fn matches(path: &str, check: &Check) -> Result<bool, String> {
+    match check {
+        Check::Crc(expected) => {
+            let file = File::open(path).map_err(|err| err.to_string())?;
+            let crc = crc32::checksum(file).map_err(|err| err.to_string())?;
+            if crc != *expected {
+                debug!(
+                    "{}: CRC {:08x} does not match the debug link CRC {:08x}",
+                    path, crc, expected
+                );
+            }
+            Ok(crc == *expected)
+        }
+        Check::BuildId(expected) => {
+            let mut reader = OffsetReader::open(path).map_err(|err| err.to_string())?;
+            let metadata = Elf64Metadata::load(&path.to_string(), &mut reader)
+                .map_err(|err| err.to_string())?;
+            let build_id = read_build_id(&metadata, &mut reader)?;
+            Ok(build_id.as_ref() == Some(expected))
+        }
+    }
+}
+
+/// The path of the detached debug file of `elf_metadata`, found by build-id or through
+/// .gnu_debuglink and verified against it.
+pub fn find<T: Read + Seek>(
+    elf_metadata: &Elf64Metadata,
+    reader: &mut T,
+    directories: &[String],
+) -> Result<Option<String>, String> {
+    for (path, check) in candidates(elf_metadata, reader, directories)? {
+        if !Path::new(&path).is_file() {
+            continue;
+        }
+        match matches(&path, &check) {
+            Ok(true) => return Ok(Some(path)),
+            Ok(false) => debug!("Ignoring {}: it belongs to another file", path),
+            Err(err) => debug!("Ignoring {}: {}", path, err),
+        }
+    }
+    Ok(None)
+}
+
+/// Adds the .symtab entries of the debug file at `debug_path` missing from the symbol table of
+/// `elf_metadata`, returning how many were added. Meant for printing and symbolization only,
+/// the loader never sees these symbols.
+pub fn merge_symbols(elf_metadata: &mut Elf64Metadata, debug_path: &str) -> Result<usize, String> {
+    let mut reader = OffsetReader::open(debug_path).map_err(|err| err.to_string())?;
+    let debug_metadata =
+        Elf64Metadata::load(&debug_path.to_string(), &mut reader).map_err(|err| err.to_string())?;
+    let known: HashSet<(String, u64)> = elf_metadata
+        .symbol_table
+        .iter()
+        .map(|symbol| (symbol.symbol_name.clone(), symbol.value))
+        .collect();
+    let missing: Vec<_> = debug_metadata
+        .symbol_table
+        .into_iter()
+        .filter(|symbol| !symbol.symbol_name.is_empty())
+        .filter(|symbol| !known.contains(&(symbol.symbol_name.clone(), symbol.value)))
+        .collect();
+    let added = missing.len();
+    elf_metadata.symbol_table.extend(missing);
+    Ok(added)
+}
+
+/// Finds the debug file of `elf_metadata` and merges its symbols, returning its path. Failures
+/// are only logged, as the file is still usable without them.
+pub fn load_symbols<T: Read + Seek>(
+    elf_metadata: &mut Elf64Metadata,
+    reader: &mut T,
+    directories: &[String],
+) -> Option<String> {
+    let path = match find(elf_metadata, reader, directories) {
+        Ok(Some(path)) => path,
+        Ok(None) => {
+            debug!("No debuginfo found for {}", elf_metadata.file_path);
+            return None;
+        }
+        Err(err) => {
+            warn!(
+                "Unable to look up debuginfo for {}: {}",
+                elf_metadata.file_path, err
+            );
+            return None;
+        }
+    };
+    match merge_symbols(elf_metadata, &path) {
+        Ok(added) => {
+            info!("Debuginfo {}: {} symbol(s) added", path, added);
+            Some(path)
+        }
+        Err(err) => {
+            warn!("Unable to read debuginfo {}: {}", path, err);
+            None
+        }
+    }
+}
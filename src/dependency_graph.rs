@@ -0,0 +1,123 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+
+use crate::loader::{DependenciesResolver, LibraryOrigin};
+use crate::offset_reader::OffsetReader;
+use crate::{DrowError, Elf64Metadata};
+
+pub struct DependencyNode {
+    pub name: String,
+    pub path: Option<String>,
+    pub origin: LibraryOrigin,
+}
+
+pub struct DependencyGraph {
+    pub nodes: Vec<DependencyNode>,
+    pub edges: Vec<(usize, usize)>,
+}
+
+impl DependencyGraph {
+    fn file_name(path: &str) -> String {
+        path.rsplit('/').next().unwrap_or(path).to_string()
+    }
+
+    pub fn build(
+        resolver: &mut DependenciesResolver,
+        elf_metadata: &Elf64Metadata,
+    ) -> Result<Self, DrowError> {
+        let mut graph = DependencyGraph {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+        };
+        let mut path_nodes: HashMap<String, usize> = HashMap::new();
+        let mut missing_nodes: HashMap<String, usize> = HashMap::new();
+        graph.nodes.push(DependencyNode {
+            name: DependencyGraph::file_name(&elf_metadata.file_path),
+            path: Some(elf_metadata.file_path.clone()),
+            origin: LibraryOrigin::Input,
+        });
+        path_nodes.insert(elf_metadata.file_path.clone(), 0);
+        let mut queue: VecDeque<(usize, Elf64Metadata)> = VecDeque::new();
+        queue.push_back((0, elf_metadata.clone()));
+        while let Some((parent, metadata)) = queue.pop_front() {
+            for library in metadata.dynamic.unique_required_libraries() {
+                let (paths, origin) =
+                    resolver.resolve_path_with_origin(library, metadata.elf_header.e_machine)?;
+                if paths.is_empty() {
+                    let node = *missing_nodes.entry(library.clone()).or_insert_with(|| {
+                        graph.nodes.push(DependencyNode {
+                            name: library.clone(),
+                            path: None,
+                            origin: LibraryOrigin::Missing,
+                        });
+                        graph.nodes.len() - 1
+                    });
+                    graph.edges.push((parent, node));
+                }
+                for path in paths.iter() {
+                    if let Some(node) = path_nodes.get(path) {
+                        graph.edges.push((parent, *node));
+                        continue;
+                    }
+                    graph.nodes.push(DependencyNode {
+                        name: library.clone(),
+                        path: Some(path.clone()),
+                        origin,
+                    });
+                    let node = graph.nodes.len() - 1;
+                    path_nodes.insert(path.clone(), node);
+                    graph.edges.push((parent, node));
+                    if let Ok(mut reader) = OffsetReader::open(path) {
+                        if let Ok(child) = Elf64Metadata::load(path, &mut reader) {
+                            queue.push_back((node, child));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(graph)
+    }
+
+    fn color(origin: LibraryOrigin) -> &'static str {
+        match origin {
+            LibraryOrigin::Input => "black",
+            LibraryOrigin::Cache => "blue",
+            LibraryOrigin::LdPath => "darkgreen",
+            LibraryOrigin::Default => "darkorange",
+            LibraryOrigin::Substituted => "purple",
+            LibraryOrigin::Manifest => "teal",
+            LibraryOrigin::SearchDirectory => "darkcyan",
+            LibraryOrigin::Missing => "red",
+        }
+    }
+
+    fn escape(value: &str) -> String {
+        value.replace('\\', "\\\\").replace('"', "\\\"")
+    }
+
+    pub fn write_dot<W: Write>(&self, out: &mut W) -> std::io::Result<()> {
+        writeln!(out, "digraph dependencies {{")?;
+        for (index, node) in self.nodes.iter().enumerate() {
+            let label = match node.path.as_ref() {
+                Some(path) => format!(
+                    "{}\\n{}",
+                    DependencyGraph::escape(&node.name),
+                    DependencyGraph::escape(path)
+                ),
+                None => format!("{}\\n(not found)", DependencyGraph::escape(&node.name)),
+            };
+            writeln!(
+                out,
+                "    n{} [label=\"{}\", color={}, fontcolor={}];",
+                index,
+                label,
+                DependencyGraph::color(node.origin),
+                DependencyGraph::color(node.origin)
+            )?;
+        }
+        for (from, to) in self.edges.iter() {
+            writeln!(out, "    n{} -> n{};", from, to)?;
+        }
+        writeln!(out, "}}")
+    }
+}
@@ -0,0 +1,297 @@
+//! `dlopen`, `dlsym`, `dlerror`, `dlclose` and `dladdr` for the loaded program, defined ahead of
+//! the C library so that plugins are loaded by drow, see the objects it loaded and are placed by
+//! its address allocator.
+//!
+//! Every object drow maps joins the global scope and is relocated at once, so `RTLD_LOCAL` and
+//! `RTLD_LAZY` behave like `RTLD_GLOBAL` and `RTLD_NOW`. `RTLD_NOLOAD` is supported, other
+//! flags and `RTLD_NEXT` fail with a `dlerror` message.
+
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::loader::Elf64Loader;
+use crate::tls::ThreadPointerGuard;
+use crate::{
+    Elf64ResolvedSymbolTableEntry, SHN_ABSOLUTE, SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_FUNCTION,
+};
+
+const SUPPORTED_FLAGS: libc::c_int =
+    libc::RTLD_LAZY | libc::RTLD_NOW | libc::RTLD_GLOBAL | libc::RTLD_LOCAL | libc::RTLD_NOLOAD;
+
+/// The loader running the program, set before its entry point is called.
+static LOADER: AtomicPtr<Elf64Loader> = AtomicPtr::new(ptr::null_mut());
+
+/// The handle `dlopen(NULL)` returns, for the global scope.
+static GLOBAL_HANDLE: u8 = 0;
+
+static STATE: Mutex<DlState> = Mutex::new(DlState {
+    handles: None,
+    error: None,
+    reported: None,
+    names: None,
+});
+
+struct DlState {
+    /// Handles by path. Boxed, as their addresses are what `dlopen` returns.
+    handles: Option<HashMap<String, Box<Handle>>>,
+    /// The error of the last failed call, until `dlerror` reports it.
+    error: Option<CString>,
+    /// The message `dlerror` returned last, which has to stay valid until the next call.
+    reported: Option<CString>,
+    /// Paths and symbol names `dladdr` returned, which have to stay valid.
+    names: Option<HashMap<String, CString>>,
+}
+
+struct Handle {
+    path: String,
+    /// References taken through this handle, each released by a `dlclose`.
+    opens: usize,
+}
+
+fn state() -> MutexGuard<'static, DlState> {
+    STATE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Makes the functions operate on `loader`, which must outlive the program.
+pub fn attach(loader: &Elf64Loader) {
+    LOADER.store(
+        loader as *const Elf64Loader as *mut Elf64Loader,
+        Ordering::SeqCst,
+    );
+}
+
+/// Stops the functions from operating on `loader`, which is going away, forgetting its handles.
+pub fn detach(loader: &Elf64Loader) {
+    let loader = loader as *const Elf64Loader as *mut Elf64Loader;
+    if LOADER
+        .compare_exchange(loader, ptr::null_mut(), Ordering::SeqCst, Ordering::SeqCst)
+        .is_ok()
+    {
+        state().handles = None;
+    }
+}
+
+fn loader() -> Option<&'static Elf64Loader> {
+    unsafe { LOADER.load(Ordering::SeqCst).as_ref() }
+}
+
+fn fail(message: String) {
+    debug!("dl: {}", message);
+    let message = CString::new(message).unwrap_or_default();
+    state().error = Some(message);
+}
+
+fn intern(state: &mut DlState, name: &str) -> *const libc::c_char {
+    state
+        .names
+        .get_or_insert_with(HashMap::new)
+        .entry(name.to_string())
+        .or_insert_with(|| CString::new(name).unwrap_or_default())
+        .as_ptr()
+}
+
+fn symbol(name: &str, value: u64) -> Elf64ResolvedSymbolTableEntry {
+    Elf64ResolvedSymbolTableEntry {
+        symbol_name: name.to_string(),
+        binding: SYMBOL_BINDING_GLOBAL,
+        symbol_type: SYMBOL_TYPE_FUNCTION,
+        section_index: SHN_ABSOLUTE,
+        value,
+        size: 0,
+        version: None,
+    }
+}
+
+/// The symbols for the global symbol table.
+pub fn symbols() -> Vec<Elf64ResolvedSymbolTableEntry> {
+    let open: unsafe extern "C" fn(*const libc::c_char, libc::c_int) -> *mut libc::c_void = dlopen;
+    let sym: unsafe extern "C" fn(*mut libc::c_void, *const libc::c_char) -> *mut libc::c_void =
+        dlsym;
+    let error: extern "C" fn() -> *mut libc::c_char = dlerror;
+    let close: unsafe extern "C" fn(*mut libc::c_void) -> libc::c_int = dlclose;
+    let addr: unsafe extern "C" fn(*const libc::c_void, *mut libc::Dl_info) -> libc::c_int = dladdr;
+    vec![
+        symbol("dlopen", open as usize as u64),
+        symbol("dlsym", sym as usize as u64),
+        symbol("dlerror", error as usize as u64),
+        symbol("dlclose", close as usize as u64),
+        symbol("dladdr", addr as usize as u64),
+    ]
+}
+
+unsafe extern "C" fn dlopen(
+    filename: *const libc::c_char,
+    flags: libc::c_int,
+) -> *mut libc::c_void {
+    let _thread_pointer = ThreadPointerGuard::drow();
+    let loader = match loader() {
+        Some(loader) => loader,
+        None => {
+            fail(String::from("dlopen: drow is not running a program"));
+            return ptr::null_mut();
+        }
+    };
+    if flags & (libc::RTLD_LAZY | libc::RTLD_NOW) == 0 {
+        fail(String::from(
+            "invalid mode for dlopen(): RTLD_LAZY or RTLD_NOW required",
+        ));
+        return ptr::null_mut();
+    }
+    if flags & !SUPPORTED_FLAGS != 0 {
+        fail(format!(
+            "dlopen: unsupported flags {:#x}",
+            flags & !SUPPORTED_FLAGS
+        ));
+        return ptr::null_mut();
+    }
+    if filename.is_null() {
+        return &GLOBAL_HANDLE as *const u8 as *mut libc::c_void;
+    }
+    let library = CStr::from_ptr(filename).to_string_lossy().into_owned();
+    debug!("dlopen({}, {:#x})", library, flags);
+    if flags & libc::RTLD_NOLOAD != 0 && loader.loaded_library(&library).is_none() {
+        return ptr::null_mut();
+    }
+    // The registry is not held while init functions run, as they may call dlopen themselves.
+    match loader.open_library(&library) {
+        Ok(path) => {
+            let mut state = state();
+            let handle = state
+                .handles
+                .get_or_insert_with(HashMap::new)
+                .entry(path.clone())
+                .or_insert_with(|| Box::new(Handle { path, opens: 0 }));
+            handle.opens += 1;
+            handle.as_mut() as *mut Handle as *mut libc::c_void
+        }
+        Err(err) => {
+            fail(err.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// The path of the object `handle` was returned for, none for the global scope.
+fn handle_path(handle: *mut libc::c_void) -> Result<Option<String>, String> {
+    if handle == libc::RTLD_DEFAULT || ptr::eq(handle as *const u8, &GLOBAL_HANDLE) {
+        return Ok(None);
+    }
+    if handle == libc::RTLD_NEXT {
+        return Err(String::from("RTLD_NEXT is not supported"));
+    }
+    state()
+        .handles
+        .iter()
+        .flat_map(|handles| handles.values())
+        .find(|candidate| candidate.as_ref() as *const Handle as *mut libc::c_void == handle)
+        .map(|handle| Some(handle.path.clone()))
+        .ok_or_else(|| format!("invalid handle {:p}", handle))
+}
+
+unsafe extern "C" fn dlsym(
+    handle: *mut libc::c_void,
+    symbol: *const libc::c_char,
+) -> *mut libc::c_void {
+    let _thread_pointer = ThreadPointerGuard::drow();
+    let loader = match loader() {
+        Some(loader) => loader,
+        None => {
+            fail(String::from("dlsym: drow is not running a program"));
+            return ptr::null_mut();
+        }
+    };
+    let name = CStr::from_ptr(symbol).to_string_lossy().into_owned();
+    let address = match handle_path(handle) {
+        Ok(Some(path)) => loader.lookup_symbol_in(&path, &name),
+        Ok(None) => loader.lookup_symbol(&name),
+        Err(message) => {
+            fail(format!("dlsym: {}", message));
+            return ptr::null_mut();
+        }
+    };
+    debug!("dlsym({}) = {:#x?}", name, address);
+    match address {
+        Some(address) => address as *mut libc::c_void,
+        None => {
+            fail(format!("undefined symbol: {}", name));
+            ptr::null_mut()
+        }
+    }
+}
+
+extern "C" fn dlerror() -> *mut libc::c_char {
+    let _thread_pointer = ThreadPointerGuard::drow();
+    let mut state = state();
+    state.reported = state.error.take();
+    state
+        .reported
+        .as_ref()
+        .map(|message| message.as_ptr() as *mut libc::c_char)
+        .unwrap_or(ptr::null_mut())
+}
+
+unsafe extern "C" fn dlclose(handle: *mut libc::c_void) -> libc::c_int {
+    let _thread_pointer = ThreadPointerGuard::drow();
+    let loader = match loader() {
+        Some(loader) => loader,
+        None => {
+            fail(String::from("dlclose: drow is not running a program"));
+            return -1;
+        }
+    };
+    let path = match handle_path(handle) {
+        Ok(Some(path)) => path,
+        Ok(None) => return 0,
+        Err(message) => {
+            fail(format!("dlclose: {}", message));
+            return -1;
+        }
+    };
+    debug!("dlclose({})", path);
+    {
+        let mut state = state();
+        let handles = state.handles.get_or_insert_with(HashMap::new);
+        if let Some(handle) = handles.get_mut(&path) {
+            handle.opens -= 1;
+            if handle.opens == 0 {
+                handles.remove(&path);
+            }
+        }
+    }
+    // Fini functions run without the registry held, as they may call dlclose themselves.
+    match loader.close_library(&path) {
+        Ok(_) => 0,
+        Err(err) => {
+            fail(err.to_string());
+            -1
+        }
+    }
+}
+
+unsafe extern "C" fn dladdr(address: *const libc::c_void, info: *mut libc::Dl_info) -> libc::c_int {
+    let _thread_pointer = ThreadPointerGuard::drow();
+    let resolved = match loader().and_then(|loader| loader.resolve_address(address as u64)) {
+        Some(resolved) => resolved,
+        None => return 0,
+    };
+    let mut state = state();
+    let info = &mut *info;
+    info.dli_fname = intern(&mut state, &resolved.object);
+    info.dli_fbase = resolved.base as *mut libc::c_void;
+    match resolved.symbol.as_ref() {
+        Some((name, offset)) => {
+            info.dli_sname = intern(&mut state, name);
+            info.dli_saddr = (address as u64 - offset) as *mut libc::c_void;
+        }
+        None => {
+            info.dli_sname = ptr::null();
+            info.dli_saddr = ptr::null_mut();
+        }
+    }
+    1
+}
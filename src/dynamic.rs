@@ -1,6 +1,13 @@
-use crate::string_tables::{get_string_tables_content, string_length};
-use crate::{Elf64Metadata, Elf64SectionHeader, ELF64_SECTION_HEADER_DYNAMIC};
-use std::io::{Read, Seek, SeekFrom};
+use crate::error::DrowError;
+use crate::string_tables::{
+    by_offset, by_virtual_address, file_offset, StrError, StringTable, StringTableCache,
+};
+use crate::{
+    read_entries, Elf64ProgramHeader, Elf64SectionHeader, ELF64_SECTION_HEADER_DYNAMIC,
+    ELF64_SECTION_HEADER_STRING_TABLE, PROGRAM_HEADER_TYPE_DYNAMIC,
+};
+use std::convert::TryFrom;
+use std::io::{Read, Seek};
 use std::mem;
 
 #[repr(C)]
@@ -12,133 +19,565 @@ struct Elf64DynamicSection {
 
 struct Elf64DynamicData {
     required_libraries_string_table_offset: Vec<u64>,
-    dynamic_string_table_address: u64,
+    soname_string_table_offset: Option<u64>,
+    rpath_string_table_offset: Option<u64>,
+    runpath_string_table_offset: Option<u64>,
+    dynamic_string_table_address: Option<u64>,
     init_function: u64,
     init_array: u64,
     init_array_size: u64,
+    fini_function: u64,
+    fini_array: u64,
+    fini_array_size: u64,
+    plt_got: u64,
+    jump_relocations: u64,
+    jump_relocations_size: u64,
+    flags: u64,
+    flags_1: u64,
+    text_relocations: bool,
 }
 
 impl Elf64DynamicData {
     fn new() -> Elf64DynamicData {
         Elf64DynamicData {
             required_libraries_string_table_offset: Vec::new(),
-            dynamic_string_table_address: 0,
+            soname_string_table_offset: None,
+            rpath_string_table_offset: None,
+            runpath_string_table_offset: None,
+            dynamic_string_table_address: None,
             init_function: 0,
             init_array: 0,
             init_array_size: 0,
+            fini_function: 0,
+            fini_array: 0,
+            fini_array_size: 0,
+            plt_got: 0,
+            jump_relocations: 0,
+            jump_relocations_size: 0,
+            flags: 0,
+            flags_1: 0,
+            text_relocations: false,
         }
     }
+
+    fn uses_string_table(&self) -> bool {
+        !self.required_libraries_string_table_offset.is_empty()
+            || self.soname_string_table_offset.is_some()
+            || self.rpath_string_table_offset.is_some()
+            || self.runpath_string_table_offset.is_some()
+    }
 }
 
+const DYNAMIC_TABLE_NULL: i64 = 0;
 const DYNAMIC_TABLE_NEEDED: i64 = 1;
+const DYNAMIC_TABLE_PLT_RELOCATIONS_SIZE: i64 = 2;
+const DYNAMIC_TABLE_PLT_GOT: i64 = 3;
+const DYNAMIC_TABLE_HASH: i64 = 4;
 const DYNAMIC_TABLE_STRING_TABLE: i64 = 5;
+const DYNAMIC_TABLE_SYMBOL_TABLE: i64 = 6;
+const DYNAMIC_TABLE_RELOCATIONS: i64 = 7;
 const DYNAMIC_TABLE_INIT_FUNCTION: i64 = 12;
+const DYNAMIC_TABLE_FINI_FUNCTION: i64 = 13;
+const DYNAMIC_TABLE_SONAME: i64 = 14;
+const DYNAMIC_TABLE_RPATH: i64 = 15;
+const DYNAMIC_TABLE_TEXT_RELOCATIONS: i64 = 22;
 const DYNAMIC_TABLE_INIT_ARRAY: i64 = 25;
+const DYNAMIC_TABLE_FINI_ARRAY: i64 = 26;
+const DYNAMIC_TABLE_JUMP_RELOCATIONS: i64 = 23;
 const DYNAMIC_TABLE_INIT_ARRAY_SIZE: i64 = 27;
+const DYNAMIC_TABLE_FINI_ARRAY_SIZE: i64 = 28;
+const DYNAMIC_TABLE_RUNPATH: i64 = 29;
+const DYNAMIC_TABLE_FLAGS: i64 = 30;
+const DYNAMIC_TABLE_GNU_HASH: i64 = 0x6ffffef5;
+const DYNAMIC_TABLE_VERSION_SYMBOLS: i64 = 0x6ffffff0;
+const DYNAMIC_TABLE_VERSION_DEFINITIONS: i64 = 0x6ffffffc;
+const DYNAMIC_TABLE_VERSION_NEEDED: i64 = 0x6ffffffe;
+const DYNAMIC_TABLE_FLAGS_1: i64 = 0x6ffffffb;
+
+/// Tags of the generic ABI and the GNU extensions, whether drow uses them or not. Other tags are
+/// kept in `Elf64Dynamic::unknown_tags`.
+const KNOWN_TAGS: &[(i64, &str)] = &[
+    (DYNAMIC_TABLE_NULL, "DT_NULL"),
+    (DYNAMIC_TABLE_NEEDED, "DT_NEEDED"),
+    (DYNAMIC_TABLE_PLT_RELOCATIONS_SIZE, "DT_PLTRELSZ"),
+    (DYNAMIC_TABLE_PLT_GOT, "DT_PLTGOT"),
+    (DYNAMIC_TABLE_HASH, "DT_HASH"),
+    (DYNAMIC_TABLE_STRING_TABLE, "DT_STRTAB"),
+    (DYNAMIC_TABLE_SYMBOL_TABLE, "DT_SYMTAB"),
+    (DYNAMIC_TABLE_RELOCATIONS, "DT_RELA"),
+    (8, "DT_RELASZ"),
+    (9, "DT_RELAENT"),
+    (10, "DT_STRSZ"),
+    (11, "DT_SYMENT"),
+    (DYNAMIC_TABLE_INIT_FUNCTION, "DT_INIT"),
+    (DYNAMIC_TABLE_FINI_FUNCTION, "DT_FINI"),
+    (DYNAMIC_TABLE_SONAME, "DT_SONAME"),
+    (DYNAMIC_TABLE_RPATH, "DT_RPATH"),
+    (16, "DT_SYMBOLIC"),
+    (17, "DT_REL"),
+    (18, "DT_RELSZ"),
+    (19, "DT_RELENT"),
+    (20, "DT_PLTREL"),
+    (21, "DT_DEBUG"),
+    (DYNAMIC_TABLE_TEXT_RELOCATIONS, "DT_TEXTREL"),
+    (DYNAMIC_TABLE_JUMP_RELOCATIONS, "DT_JMPREL"),
+    (24, "DT_BIND_NOW"),
+    (DYNAMIC_TABLE_INIT_ARRAY, "DT_INIT_ARRAY"),
+    (DYNAMIC_TABLE_FINI_ARRAY, "DT_FINI_ARRAY"),
+    (DYNAMIC_TABLE_INIT_ARRAY_SIZE, "DT_INIT_ARRAYSZ"),
+    (DYNAMIC_TABLE_FINI_ARRAY_SIZE, "DT_FINI_ARRAYSZ"),
+    (DYNAMIC_TABLE_RUNPATH, "DT_RUNPATH"),
+    (DYNAMIC_TABLE_FLAGS, "DT_FLAGS"),
+    (32, "DT_PREINIT_ARRAY"),
+    (33, "DT_PREINIT_ARRAYSZ"),
+    (34, "DT_SYMTAB_SHNDX"),
+    (0x6ffffdf5, "DT_GNU_PRELINKED"),
+    (0x6ffffdf6, "DT_GNU_CONFLICTSZ"),
+    (0x6ffffdf7, "DT_GNU_LIBLISTSZ"),
+    (0x6ffffdf8, "DT_CHECKSUM"),
+    (DYNAMIC_TABLE_GNU_HASH, "DT_GNU_HASH"),
+    (0x6ffffef8, "DT_GNU_CONFLICT"),
+    (0x6ffffef9, "DT_GNU_LIBLIST"),
+    (DYNAMIC_TABLE_VERSION_SYMBOLS, "DT_VERSYM"),
+    (0x6ffffff9, "DT_RELACOUNT"),
+    (0x6ffffffa, "DT_RELCOUNT"),
+    (DYNAMIC_TABLE_FLAGS_1, "DT_FLAGS_1"),
+    (DYNAMIC_TABLE_VERSION_DEFINITIONS, "DT_VERDEF"),
+    (0x6ffffffd, "DT_VERDEFNUM"),
+    (DYNAMIC_TABLE_VERSION_NEEDED, "DT_VERNEED"),
+    (0x6fffffff, "DT_VERNEEDNUM"),
+];
+
+/// The DT_ name of `tag`, if it is one of `KNOWN_TAGS`.
+pub fn tag_name(tag: i64) -> Option<&'static str> {
+    KNOWN_TAGS
+        .iter()
+        .find(|(known, _)| *known == tag)
+        .map(|(_, name)| *name)
+}
+
+/// Entries holding the address of a table or function, which must be inside a loadable segment.
+const ADDRESS_TAGS: [(i64, &str); 14] = [
+    (DYNAMIC_TABLE_PLT_GOT, "DT_PLTGOT"),
+    (DYNAMIC_TABLE_HASH, "DT_HASH"),
+    (DYNAMIC_TABLE_STRING_TABLE, "DT_STRTAB"),
+    (DYNAMIC_TABLE_SYMBOL_TABLE, "DT_SYMTAB"),
+    (DYNAMIC_TABLE_RELOCATIONS, "DT_RELA"),
+    (DYNAMIC_TABLE_INIT_FUNCTION, "DT_INIT"),
+    (DYNAMIC_TABLE_FINI_FUNCTION, "DT_FINI"),
+    (DYNAMIC_TABLE_JUMP_RELOCATIONS, "DT_JMPREL"),
+    (DYNAMIC_TABLE_INIT_ARRAY, "DT_INIT_ARRAY"),
+    (DYNAMIC_TABLE_FINI_ARRAY, "DT_FINI_ARRAY"),
+    (DYNAMIC_TABLE_GNU_HASH, "DT_GNU_HASH"),
+    (DYNAMIC_TABLE_VERSION_SYMBOLS, "DT_VERSYM"),
+    (DYNAMIC_TABLE_VERSION_DEFINITIONS, "DT_VERDEF"),
+    (DYNAMIC_TABLE_VERSION_NEEDED, "DT_VERNEED"),
+];
+
+pub const DYNAMIC_FLAGS_TEXTREL: u64 = 0x4;
+pub const DYNAMIC_FLAGS_STATIC_TLS: u64 = 0x10;
+pub const DYNAMIC_FLAGS_1_NODELETE: u64 = 0x8;
+pub const DYNAMIC_FLAGS_1_NOOPEN: u64 = 0x40;
+pub const DYNAMIC_FLAGS_1_PIE: u64 = 0x0800_0000;
 
 #[derive(Clone)]
 pub struct Elf64Dynamic {
     pub required_libraries: Vec<String>,
+    pub soname: Option<String>,
+    pub rpath: Option<String>,
+    pub runpath: Option<String>,
     pub init_function: u64,
     pub init_array: u64,
     pub init_array_size: u64,
+    pub fini_function: u64,
+    pub fini_array: u64,
+    pub fini_array_size: u64,
+    pub plt_got: u64,
+    pub jump_relocations: u64,
+    pub jump_relocations_size: u64,
+    /// DT_FLAGS.
+    pub flags: u64,
+    pub flags_1: u64,
+    /// Whether there is a DT_TEXTREL entry.
+    pub text_relocations: bool,
+    /// The addresses of the `ADDRESS_TAGS` entries, by tag name, in the order of the array.
+    pub addresses: Vec<(&'static str, u64)>,
+    /// (tag, value) of the entries with tags outside `KNOWN_TAGS`, in the order of the array.
+    pub unknown_tags: Vec<(i64, u64)>,
+    /// Whether every dynamic array ends with DT_NULL. Entries are read up to the first one.
+    pub terminated: bool,
+}
+
+/// Index of the section holding the dynamic string table. DT_STRTAB is looked up by address,
+/// then by the file offset it maps to, and the link of the dynamic section is used when the file
+/// has no DT_STRTAB entry or none of its sections starts there.
+fn string_table_index(
+    array: &DynamicArray,
+    section_headers: &[Elf64SectionHeader],
+    program_headers: &[Elf64ProgramHeader],
+    address: Option<u64>,
+) -> Result<usize, DrowError> {
+    address
+        .and_then(|address| {
+            by_virtual_address(section_headers, address).or_else(|| {
+                file_offset(program_headers, address)
+                    .and_then(|offset| by_offset(section_headers, offset))
+            })
+        })
+        .or_else(|| {
+            let link = array.link? as usize;
+            section_headers
+                .get(link)
+                .filter(|header| header.sh_type == ELF64_SECTION_HEADER_STRING_TABLE)
+                .map(|_| link)
+        })
+        .ok_or_else(|| DrowError::Malformed {
+            what: match address {
+                Some(address) => format!(
+                    "DT_STRTAB {:#X} is not the start of a string table",
+                    address
+                ),
+                None => String::from("dynamic section has no string table"),
+            },
+            offset: Some(array.offset),
+        })
+}
+
+/// Where the dynamic array is in the file, and the string table the section header links.
+struct DynamicArray {
+    offset: u64,
+    size: u64,
+    link: Option<u32>,
+}
+
+impl DynamicArray {
+    /// PT_DYNAMIC, which is what the dynamic linker reads, and otherwise the dynamic sections.
+    fn locate(
+        section_headers: &[Elf64SectionHeader],
+        program_headers: &[Elf64ProgramHeader],
+    ) -> Vec<DynamicArray> {
+        let sections = section_headers
+            .iter()
+            .filter(|sec| sec.sh_type == ELF64_SECTION_HEADER_DYNAMIC);
+        match program_headers
+            .iter()
+            .find(|header| header.p_type == PROGRAM_HEADER_TYPE_DYNAMIC)
+        {
+            Some(segment) => vec![DynamicArray {
+                offset: segment.p_offset,
+                size: segment.p_file_size,
+                link: sections.clone().next().map(|section| section.sh_link),
+            }],
+            None => sections
+                .map(|section| DynamicArray {
+                    offset: section.sh_offset,
+                    size: section.sh_size,
+                    link: Some(section.sh_link),
+                })
+                .collect(),
+        }
+    }
+}
+
+fn dynamic_string(table: &StringTable, offset: u64, what: &str) -> Result<String, DrowError> {
+    let offset = u32::try_from(offset).map_err(|_| StrError::OutOfBounds {
+        offset: offset as usize,
+        length: table.len(),
+    });
+    offset
+        .and_then(|offset| table.get_lossy(offset))
+        .map(|name| name.into_owned())
+        .map_err(|source| DrowError::InvalidString {
+            what: String::from(what),
+            source,
+        })
+}
+
+impl Default for Elf64Dynamic {
+    /// No entries, as for a file without a dynamic section.
+    fn default() -> Elf64Dynamic {
+        Elf64Dynamic {
+            required_libraries: Vec::new(),
+            soname: None,
+            rpath: None,
+            runpath: None,
+            init_array: 0,
+            init_function: 0,
+            init_array_size: 0,
+            fini_function: 0,
+            fini_array: 0,
+            fini_array_size: 0,
+            plt_got: 0,
+            jump_relocations: 0,
+            jump_relocations_size: 0,
+            flags: 0,
+            flags_1: 0,
+            text_relocations: false,
+            addresses: Vec::new(),
+            unknown_tags: Vec::new(),
+            terminated: true,
+        }
+    }
 }
 
 impl Elf64Dynamic {
-    fn load_dynamic_section<T: Read + Seek>(
-        entry: &Elf64SectionHeader,
-        section_headers: &Vec<Elf64SectionHeader>,
+    /// DT_NEEDED names with repeated entries dropped, in the order of their first occurrence.
+    pub fn unique_required_libraries(&self) -> Vec<&String> {
+        let mut result: Vec<&String> = Vec::new();
+        for library in self.required_libraries.iter() {
+            if result.contains(&library) {
+                debug!("Ignoring repeated DT_NEEDED entry {}", library);
+            } else {
+                result.push(library);
+            }
+        }
+        result
+    }
+
+    /// DT_TEXTREL or DF_TEXTREL: relocations write to segments that are not writable.
+    pub fn writes_text(&self) -> bool {
+        self.text_relocations || self.flags & DYNAMIC_FLAGS_TEXTREL != 0
+    }
+
+    /// DF_STATIC_TLS: the object reaches its TLS at a fixed offset from the thread pointer.
+    pub fn static_tls(&self) -> bool {
+        self.flags & DYNAMIC_FLAGS_STATIC_TLS != 0
+    }
+
+    /// DF_1_NODELETE: the object stays mapped until the process exits.
+    pub fn no_delete(&self) -> bool {
+        self.flags_1 & DYNAMIC_FLAGS_1_NODELETE != 0
+    }
+
+    /// DF_1_NOOPEN: the object can only be loaded as a dependency, never on its own.
+    pub fn no_open(&self) -> bool {
+        self.flags_1 & DYNAMIC_FLAGS_1_NOOPEN != 0
+    }
+
+    /// DF_1_PIE: the linker built the object as a position-independent executable.
+    pub fn pie(&self) -> bool {
+        self.flags_1 & DYNAMIC_FLAGS_1_PIE != 0
+    }
+
+    fn load_dynamic_array<T: Read + Seek>(
+        array: &DynamicArray,
+        section_headers: &[Elf64SectionHeader],
+        program_headers: &[Elf64ProgramHeader],
         elf64_dynamic: &mut Elf64Dynamic,
+        string_tables: &mut StringTableCache,
         reader: &mut T,
-    ) {
+    ) -> Result<(), DrowError> {
         let mut elf_dynamic_data = Elf64DynamicData::new();
-        let mut buffer: Vec<u8> = Vec::new();
-        buffer.resize(entry.sh_size as usize, 0);
-        reader.seek(SeekFrom::Start(entry.sh_offset));
-        reader.read_exact(&mut buffer).expect("Error");
-        let size = mem::size_of::<Elf64DynamicSection>();
-        let len = buffer.len() / size;
-        let mut dynamic_array: Vec<Elf64DynamicSection> = Vec::new();
-        for x in 0..len {
-            let from = x * size;
-            let to = (x + 1) * size;
-            let elem: Elf64DynamicSection =
-                unsafe { std::ptr::read((&buffer[from..to]).as_ptr() as *const _) };
-            dynamic_array.push(elem.clone());
+        let mut dynamic_array: Vec<Elf64DynamicSection> = read_entries(
+            reader,
+            array.offset,
+            array.size / mem::size_of::<Elf64DynamicSection>() as u64,
+        )?;
+        match dynamic_array
+            .iter()
+            .position(|entry| entry.tag == DYNAMIC_TABLE_NULL)
+        {
+            Some(terminator) => {
+                let trailing = dynamic_array
+                    .split_off(terminator)
+                    .iter()
+                    .filter(|entry| entry.tag != DYNAMIC_TABLE_NULL)
+                    .count();
+                if trailing > 0 {
+                    warn!(
+                        "Ignoring {} dynamic entries after DT_NULL at {:#X}",
+                        trailing,
+                        array.offset + (terminator * mem::size_of::<Elf64DynamicSection>()) as u64
+                    );
+                }
+            }
+            None => {
+                warn!(
+                    "Dynamic array at {:#X} has no DT_NULL terminator",
+                    array.offset
+                );
+                elf64_dynamic.terminated = false;
+            }
         }
         for entry in dynamic_array.iter() {
+            if tag_name(entry.tag).is_none() {
+                debug!(
+                    "Unknown dynamic tag {:#X} = {:#X}",
+                    entry.tag, entry.value_or_pointer
+                );
+                elf64_dynamic
+                    .unknown_tags
+                    .push((entry.tag, entry.value_or_pointer));
+            }
+            if let Some((_, name)) = ADDRESS_TAGS.iter().find(|(tag, _)| *tag == entry.tag) {
+                elf64_dynamic.addresses.push((name, entry.value_or_pointer));
+            }
             if entry.tag == DYNAMIC_TABLE_NEEDED {
                 elf_dynamic_data
                     .required_libraries_string_table_offset
                     .push(entry.value_or_pointer);
-                println!(
+                debug!(
                     "Required libraries string table offset: {}",
                     entry.value_or_pointer
                 );
             }
+            if entry.tag == DYNAMIC_TABLE_SONAME {
+                elf_dynamic_data.soname_string_table_offset = Some(entry.value_or_pointer);
+            }
+            if entry.tag == DYNAMIC_TABLE_RPATH {
+                elf_dynamic_data.rpath_string_table_offset = Some(entry.value_or_pointer);
+            }
+            if entry.tag == DYNAMIC_TABLE_RUNPATH {
+                elf_dynamic_data.runpath_string_table_offset = Some(entry.value_or_pointer);
+            }
             if entry.tag == DYNAMIC_TABLE_STRING_TABLE {
-                elf_dynamic_data.dynamic_string_table_address = entry.value_or_pointer;
-                println!(
+                elf_dynamic_data.dynamic_string_table_address = Some(entry.value_or_pointer);
+                debug!(
                     "Dynamic string table address: {:#X}",
-                    elf_dynamic_data.dynamic_string_table_address
+                    entry.value_or_pointer
                 );
             }
             if entry.tag == DYNAMIC_TABLE_INIT_FUNCTION {
                 elf_dynamic_data.init_function = entry.value_or_pointer;
-                println!(
+                debug!(
                     "Init function address: {:#X}",
                     elf_dynamic_data.init_function
                 );
             }
             if entry.tag == DYNAMIC_TABLE_INIT_ARRAY {
                 elf_dynamic_data.init_array = entry.value_or_pointer;
-                println!(
+                debug!(
                     "Init functions array address: {:#X}",
                     elf_dynamic_data.init_array
                 );
             }
+            if entry.tag == DYNAMIC_TABLE_PLT_GOT {
+                elf_dynamic_data.plt_got = entry.value_or_pointer;
+                debug!("PLT GOT address: {:#X}", elf_dynamic_data.plt_got);
+            }
+            if entry.tag == DYNAMIC_TABLE_JUMP_RELOCATIONS {
+                elf_dynamic_data.jump_relocations = entry.value_or_pointer;
+                debug!(
+                    "PLT relocations address: {:#X}",
+                    elf_dynamic_data.jump_relocations
+                );
+            }
+            if entry.tag == DYNAMIC_TABLE_PLT_RELOCATIONS_SIZE {
+                elf_dynamic_data.jump_relocations_size = entry.value_or_pointer;
+                debug!(
+                    "PLT relocations size: {}",
+                    elf_dynamic_data.jump_relocations_size
+                );
+            }
             if entry.tag == DYNAMIC_TABLE_INIT_ARRAY_SIZE {
                 elf_dynamic_data.init_array_size = entry.value_or_pointer;
-                println!(
+                debug!(
                     "Init functions array size: {}",
                     elf_dynamic_data.init_array_size
                 );
             }
+            if entry.tag == DYNAMIC_TABLE_FINI_FUNCTION {
+                elf_dynamic_data.fini_function = entry.value_or_pointer;
+                debug!(
+                    "Fini function address: {:#X}",
+                    elf_dynamic_data.fini_function
+                );
+            }
+            if entry.tag == DYNAMIC_TABLE_FINI_ARRAY {
+                elf_dynamic_data.fini_array = entry.value_or_pointer;
+                debug!(
+                    "Fini functions array address: {:#X}",
+                    elf_dynamic_data.fini_array
+                );
+            }
+            if entry.tag == DYNAMIC_TABLE_FINI_ARRAY_SIZE {
+                elf_dynamic_data.fini_array_size = entry.value_or_pointer;
+                debug!(
+                    "Fini functions array size: {}",
+                    elf_dynamic_data.fini_array_size
+                );
+            }
+            if entry.tag == DYNAMIC_TABLE_FLAGS {
+                elf_dynamic_data.flags = entry.value_or_pointer;
+                debug!("DT_FLAGS: {:#X}", elf_dynamic_data.flags);
+            }
+            if entry.tag == DYNAMIC_TABLE_TEXT_RELOCATIONS {
+                elf_dynamic_data.text_relocations = true;
+            }
+            if entry.tag == DYNAMIC_TABLE_FLAGS_1 {
+                elf_dynamic_data.flags_1 = entry.value_or_pointer;
+                debug!("DT_FLAGS_1: {:#X}", elf_dynamic_data.flags_1);
+            }
         }
-        let string_tables = get_string_tables_content(section_headers, reader);
-        let string_table = string_tables
-            .get(&elf_dynamic_data.dynamic_string_table_address)
-            .unwrap();
-        for entry in elf_dynamic_data.required_libraries_string_table_offset {
-            let from = entry as usize;
-            let len = string_length(&string_table[from..]);
-            let to = from + len - 1;
-            elf64_dynamic.required_libraries.push(
-                std::str::from_utf8(&string_table[from..to])
-                    .unwrap()
-                    .to_string(),
-            );
+        if elf_dynamic_data.uses_string_table() {
+            let index = string_table_index(
+                array,
+                section_headers,
+                program_headers,
+                elf_dynamic_data.dynamic_string_table_address,
+            )?;
+            let string_table = string_tables.get(section_headers, index, reader)?;
+            for offset in elf_dynamic_data
+                .required_libraries_string_table_offset
+                .iter()
+            {
+                let library = dynamic_string(string_table, *offset, "required library name")?;
+                elf64_dynamic.required_libraries.push(library);
+            }
+            let names = [
+                (
+                    elf_dynamic_data.soname_string_table_offset,
+                    &mut elf64_dynamic.soname,
+                    "soname",
+                ),
+                (
+                    elf_dynamic_data.rpath_string_table_offset,
+                    &mut elf64_dynamic.rpath,
+                    "rpath",
+                ),
+                (
+                    elf_dynamic_data.runpath_string_table_offset,
+                    &mut elf64_dynamic.runpath,
+                    "runpath",
+                ),
+            ];
+            for (offset, name, what) in names {
+                if let Some(offset) = offset {
+                    *name = Some(dynamic_string(string_table, offset, what)?);
+                }
+            }
         }
         elf64_dynamic.init_function = elf_dynamic_data.init_function;
         elf64_dynamic.init_array = elf_dynamic_data.init_array;
         elf64_dynamic.init_array_size = elf_dynamic_data.init_array_size;
+        elf64_dynamic.fini_function = elf_dynamic_data.fini_function;
+        elf64_dynamic.fini_array = elf_dynamic_data.fini_array;
+        elf64_dynamic.fini_array_size = elf_dynamic_data.fini_array_size;
+        elf64_dynamic.plt_got = elf_dynamic_data.plt_got;
+        elf64_dynamic.jump_relocations = elf_dynamic_data.jump_relocations;
+        elf64_dynamic.jump_relocations_size = elf_dynamic_data.jump_relocations_size;
+        elf64_dynamic.flags = elf_dynamic_data.flags;
+        elf64_dynamic.flags_1 = elf_dynamic_data.flags_1;
+        elf64_dynamic.text_relocations = elf_dynamic_data.text_relocations;
+        Ok(())
     }
 
     pub fn load<T: Read + Seek>(
-        section_headers: &Vec<Elf64SectionHeader>,
+        section_headers: &[Elf64SectionHeader],
+        program_headers: &[Elf64ProgramHeader],
+        string_tables: &mut StringTableCache,
         reader: &mut T,
-    ) -> Result<Elf64Dynamic, String> {
-        let mut result = Elf64Dynamic {
-            required_libraries: Vec::new(),
-            init_array: 0,
-            init_function: 0,
-            init_array_size: 0,
-        };
-        let dynamic_sections = section_headers
-            .iter()
-            .filter(|sec| sec.sh_type == ELF64_SECTION_HEADER_DYNAMIC);
-        for entry in dynamic_sections {
-            Elf64Dynamic::load_dynamic_section(entry, section_headers, &mut result, reader);
+    ) -> Result<Elf64Dynamic, DrowError> {
+        let mut result = Elf64Dynamic::default();
+        for array in DynamicArray::locate(section_headers, program_headers).iter() {
+            Elf64Dynamic::load_dynamic_array(
+                array,
+                section_headers,
+                program_headers,
+                &mut result,
+                string_tables,
+                reader,
+            )?;
         }
         Result::Ok(result)
     }
@@ -1,11 +1,17 @@
-use crate::string_tables::{get_string_table_content, string_length};
+use crate::error::DrowError;
+use crate::gnu_hash::Elf64GnuHash;
+use crate::group::Elf64SectionGroup;
+use crate::offset_reader::{read_segment, read_segment_into};
+use crate::string_tables::{self, StringTableCache};
+use crate::symbol_name;
+use crate::sysv_hash::Elf64SysvHash;
+use crate::versions::{self, VersionRequirement};
 use crate::Elf64Dynamic;
-use libc::wchar_t;
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::io::{Read, Seek, SeekFrom};
+use std::mem;
 use std::mem::size_of;
-use std::{iter, mem};
 
 const IDENT_SIZE: usize = 16;
 
@@ -33,6 +39,53 @@ pub const PROGRAM_FLAG_WRITE: u32 = 2;
 pub const PROGRAM_FLAG_READ: u32 = 4;
 
 pub const PROGRAM_HEADER_TYPE_LOADABLE: u32 = 1;
+pub const PROGRAM_HEADER_TYPE_DYNAMIC: u32 = 2;
+pub const PROGRAM_HEADER_TYPE_INTERPRETER: u32 = 3;
+pub const PROGRAM_HEADER_TYPE_NOTE: u32 = 4;
+pub const PROGRAM_HEADER_TYPE_PHDR: u32 = 6;
+pub const PROGRAM_HEADER_TYPE_TLS: u32 = 7;
+
+pub const ELF_TYPE_EXECUTABLE: u16 = 2;
+pub const ELF_TYPE_SHARED_OBJECT: u16 = 3;
+pub const ELF_TYPE_CORE: u16 = 4;
+
+pub const MACHINE_X86_64: u16 = 0x3E;
+pub const MACHINE_AARCH64: u16 = 0xB7;
+
+/// What an object is for, which ET_DYN alone does not tell: PIEs and shared libraries share it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ObjectKind {
+    /// ET_EXEC, linked at a fixed address.
+    Executable,
+    PositionIndependentExecutable,
+    /// ET_DYN without an entry point.
+    SharedLibrary,
+    /// A shared library with an entry point and an interpreter, like libc.so.6, which prints
+    /// its version when run.
+    ExecutableLibrary,
+    /// ET_REL, ET_CORE and anything else, which cannot be loaded.
+    Other,
+}
+
+impl ObjectKind {
+    /// Whether the object has an entry point to start it at.
+    pub fn executable(&self) -> bool {
+        !matches!(self, ObjectKind::SharedLibrary | ObjectKind::Other)
+    }
+}
+
+impl Display for ObjectKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ObjectKind::Executable => "executable",
+            ObjectKind::PositionIndependentExecutable => "pie",
+            ObjectKind::SharedLibrary => "shared-library",
+            ObjectKind::ExecutableLibrary => "executable-library",
+            ObjectKind::Other => "other",
+        };
+        f.write_str(name)
+    }
+}
 
 #[repr(C)]
 #[derive(Clone)]
@@ -61,12 +114,50 @@ impl Elf64ProgramHeader {
     }
 }
 
+/// A PT_TLS segment: the initialization image of the thread-local variables of an object, which
+/// every thread gets a copy of.
+#[derive(Clone, Debug)]
+pub struct Elf64TlsSegment {
+    /// The file the segment is in.
+    pub object: String,
+    /// Of the image, relative to the base of the object.
+    pub virtual_address: u64,
+    /// Bytes of the image, `.tdata`. The block is zero filled past them, `.tbss`.
+    pub file_size: u64,
+    /// Bytes of the block of each thread.
+    pub memory_size: u64,
+    pub alignment: u64,
+    /// The DTV index the loader gives the object, none until it is loaded.
+    pub module_id: Option<usize>,
+}
+
+impl Elf64TlsSegment {
+    pub fn from_program_headers(
+        object: &str,
+        program_headers: &[Elf64ProgramHeader],
+    ) -> Vec<Elf64TlsSegment> {
+        program_headers
+            .iter()
+            .filter(|header| header.p_type == PROGRAM_HEADER_TYPE_TLS)
+            .map(|header| Elf64TlsSegment {
+                object: object.to_string(),
+                virtual_address: header.p_virtual_address,
+                file_size: header.p_file_size,
+                memory_size: header.p_memory_size,
+                alignment: header.p_align.max(1),
+                module_id: None,
+            })
+            .collect()
+    }
+}
+
 pub const ELF64_SECTION_HEADER_UNUSED: u32 = 0;
 pub const ELF64_SECTION_HEADER_SYMBOL_TABLE: u32 = 2;
 pub const ELF64_SECTION_HEADER_STRING_TABLE: u32 = 3;
 pub const ELF64_SECTION_HEADER_RELOCATION_ADDEND: u32 = 4;
 pub const ELF64_SECTION_HEADER_DYNAMIC: u32 = 6;
 pub const ELF64_SECTION_HEADER_NO_BITS: u32 = 8;
+pub const ELF64_SECTION_HEADER_RELOCATION: u32 = 9;
 pub const ELF64_SECTION_HEADER_DYNAMIC_SYMBOL_TABLE: u32 = 11;
 
 #[repr(C)]
@@ -87,6 +178,7 @@ pub struct Elf64SectionHeader {
 pub const SECTION_FLAG_WRITE: u64 = 1;
 pub const SECTION_FLAG_ALLOCATED: u64 = 2;
 pub const SECTION_FLAG_EXECUTABLE_INSTRUCTIONS: u64 = 4;
+pub const SECTION_FLAG_THREAD_LOCAL: u64 = 0x400;
 
 impl Elf64SectionHeader {
     pub fn allocated_in_memory(&self) -> bool {
@@ -100,6 +192,10 @@ impl Elf64SectionHeader {
     pub fn writable(&self) -> bool {
         self.sh_flags & SECTION_FLAG_WRITE > 0
     }
+
+    pub fn thread_local(&self) -> bool {
+        self.sh_flags & SECTION_FLAG_THREAD_LOCAL > 0
+    }
 }
 
 #[repr(C)]
@@ -117,16 +213,18 @@ pub const SYMBOL_BINDING_LOCAL: u8 = 0;
 pub const SYMBOL_BINDING_GLOBAL: u8 = 1;
 pub const SYMBOL_BINDING_WEAK: u8 = 2;
 pub const SYMBOL_BINDING_LOOS: u8 = 10;
+pub const SYMBOL_BINDING_GNU_UNIQUE: u8 = SYMBOL_BINDING_LOOS;
 pub const SYMBOL_BINDING_HIOS: u8 = 12;
 pub const SYMBOL_BINDING_LOPROC: u8 = 13;
 pub const SYMBOL_BINDING_HIPROC: u8 = 15;
 
 const SHN_UNDEF: u16 = 0;
-const SHN_ABSOLUTE: u16 = 0xfff1;
+pub const SHN_ABSOLUTE: u16 = 0xfff1;
 const SHN_COMMON: u16 = 0xfff2;
 
 pub const SYMBOL_TYPE_FUNCTION: u8 = 2;
 pub const SYMBOL_TYPE_OBJECT: u8 = 1;
+pub const SYMBOL_TYPE_TLS: u8 = 6;
 pub const SYMBOL_TYPE_INDIRECT_FUNCTION: u8 = 10;
 
 impl Elf64SymbolTableEntry {
@@ -147,9 +245,19 @@ pub struct Elf64ResolvedSymbolTableEntry {
     pub section_index: u16,
     pub value: u64,
     pub size: u64,
+    /// The version `.gnu.version` gives a dynamic symbol, none for unversioned and local ones.
+    pub version: Option<String>,
 }
 
 impl Elf64ResolvedSymbolTableEntry {
+    /// The name with its version, as `printf@GLIBC_2.2.5`.
+    pub fn versioned_name(&self) -> String {
+        match &self.version {
+            Some(version) => format!("{}@{}", self.symbol_name, version),
+            None => self.symbol_name.clone(),
+        }
+    }
+
     pub fn global(&self) -> bool {
         self.binding == SYMBOL_BINDING_GLOBAL
     }
@@ -158,14 +266,33 @@ impl Elf64ResolvedSymbolTableEntry {
         self.binding == SYMBOL_BINDING_WEAK
     }
 
+    pub fn unique(&self) -> bool {
+        self.binding == SYMBOL_BINDING_GNU_UNIQUE
+    }
+
     pub fn function(&self) -> bool {
         self.symbol_type == SYMBOL_TYPE_FUNCTION
     }
 
+    pub fn thread_local(&self) -> bool {
+        self.symbol_type == SYMBOL_TYPE_TLS
+    }
+
     pub fn undefined(&self) -> bool {
         self.section_index == SHN_UNDEF
     }
 
+    /// A global or weak definition, which other objects can bind to.
+    pub fn exported(&self) -> bool {
+        (self.global() || self.weak()) && !self.undefined()
+    }
+
+    /// A global or weak reference the object expects another one to define. Local symbols are
+    /// neither imported nor exported.
+    pub fn imported(&self) -> bool {
+        (self.global() || self.weak()) && self.undefined()
+    }
+
     pub fn indirect_function(&self) -> bool {
         self.symbol_type == SYMBOL_TYPE_INDIRECT_FUNCTION
     }
@@ -176,7 +303,7 @@ impl Elf64ResolvedSymbolTableEntry {
 pub struct Elf64RelocationAddend {
     pub offset: u64,
     pub info: u64,
-    pub addend: i32,
+    pub addend: i64,
 }
 
 impl Elf64RelocationAddend {
@@ -189,6 +316,24 @@ impl Elf64RelocationAddend {
     }
 }
 
+/// An entry of a SHT_REL section, whose addend is the value at the place it relocates.
+#[repr(C)]
+#[derive(Clone)]
+pub struct Elf64Relocation {
+    pub offset: u64,
+    pub info: u64,
+}
+
+impl Elf64Relocation {
+    fn symbol_table_index(&self) -> u64 {
+        self.info >> 32
+    }
+
+    fn relocation_type(&self) -> u64 {
+        self.info & 0xFFFFFFFF
+    }
+}
+
 pub const RELOCATION_X86_64_NONE: u64 = 0;
 pub const RELOCATION_X86_64_64: u64 = 1;
 pub const RELOCATION_X86_64_PC32: u64 = 2;
@@ -207,6 +352,7 @@ pub const RELOCATION_X86_64_8: u64 = 14;
 pub const RELOCATION_X86_64_PC8: u64 = 15;
 pub const RELOCATION_X86_64_DPTMOD64: u64 = 16;
 pub const RELOCATION_X86_64_DTPOFF64: u64 = 17;
+pub const RELOCATION_X86_64_TPOFF64: u64 = 18;
 pub const RELOCATION_X86_64_TLSGD: u64 = 19;
 pub const RELOCATION_X86_64_TLSLD: u64 = 20;
 pub const RELOCATION_X86_64_DTPOFF32: u64 = 21;
@@ -217,63 +363,208 @@ pub const RELOCATION_X86_64_GOTOFF64: u64 = 25;
 pub const RELOCATION_X86_64_GOTOPC32: u64 = 26;
 pub const RELOCATION_X86_64_IRELATIV: u64 = 37;
 
+pub const RELOCATION_AARCH64_NONE: u64 = 0;
+pub const RELOCATION_AARCH64_ABS64: u64 = 257;
+pub const RELOCATION_AARCH64_COPY: u64 = 1024;
+pub const RELOCATION_AARCH64_GLOB_DAT: u64 = 1025;
+pub const RELOCATION_AARCH64_JUMP_SLOT: u64 = 1026;
+pub const RELOCATION_AARCH64_RELATIVE: u64 = 1027;
+pub const RELOCATION_AARCH64_IRELATIVE: u64 = 1032;
+
 #[derive(Clone)]
 pub struct Elf64ResolvedRelocationAddend {
     pub symbol_name: String,
     pub symbol_index: u64,
     pub relocation_type: u64,
+    /// e_machine of the file, which the meaning of `relocation_type` depends on.
+    pub machine: u16,
     pub offset: u64,
-    pub addend: i32,
+    pub addend: i64,
     pub symbol_section_index: u32,
+    pub section_index: usize,
+}
+
+/// A relocation referring to a symbol past the end of the dynamic symbol table. It is left out
+/// of `Elf64Metadata::relocations`, as it names no symbol.
+#[derive(Clone)]
+pub struct Elf64MalformedRelocation {
+    pub section_index: usize,
+    /// Position of the entry in its section.
+    pub position: u64,
+    pub symbol_index: u64,
+    pub relocation_type: u64,
+    pub machine: u16,
+    /// File offset of the entry.
+    pub file_offset: u64,
+    pub symbol_count: usize,
+}
+
+impl Elf64MalformedRelocation {
+    pub fn to_error(&self) -> DrowError {
+        DrowError::Malformed {
+            what: format!(
+                "relocation {} ({}) of section [{}] refers to symbol {}, past the {} dynamic symbols",
+                self.position,
+                machine_relocation_type_name(self.machine, self.relocation_type),
+                self.section_index,
+                self.symbol_index,
+                self.symbol_count
+            ),
+            offset: Some(self.file_offset),
+        }
+    }
+}
+
+pub fn relocation_type_name(relocation_type: u64) -> &'static str {
+    match relocation_type {
+        RELOCATION_X86_64_NONE => "R_X86_64_NONE",
+        RELOCATION_X86_64_64 => "R_X86_64_64",
+        RELOCATION_X86_64_PC32 => "R_X86_64_PC32",
+        RELOCATION_X86_64_GOT32 => "R_X86_64_GOT32",
+        RELOCATION_X86_64_PLT32 => "R_X86_64_PLT32",
+        RELOCATION_X86_64_COPY => "R_X86_64_COPY",
+        RELOCATION_X86_64_GLOB_DAT => "R_X86_64_GLOB_DAT",
+        RELOCATION_X86_64_JUMP_SLOT => "R_X86_64_JUMP_SLOT",
+        RELOCATION_X86_64_RELATIVE => "R_X86_64_RELATIVE",
+        RELOCATION_X86_64_GOTPCREL => "R_X86_64_GOTPCREL",
+        RELOCATION_X86_64_32 => "R_X86_64_32",
+        RELOCATION_X86_64_32S => "R_X86_64_32S",
+        RELOCATION_X86_64_16 => "R_X86_64_16",
+        RELOCATION_X86_64_PC16 => "R_X86_64_PC16",
+        RELOCATION_X86_64_8 => "R_X86_64_8",
+        RELOCATION_X86_64_PC8 => "R_X86_64_PC8",
+        RELOCATION_X86_64_DPTMOD64 => "R_X86_64_DTPMOD64",
+        RELOCATION_X86_64_DTPOFF64 => "R_X86_64_DTPOFF64",
+        RELOCATION_X86_64_TPOFF64 => "R_X86_64_TPOFF64",
+        RELOCATION_X86_64_TLSGD => "R_X86_64_TLSGD",
+        RELOCATION_X86_64_TLSLD => "R_X86_64_TLSLD",
+        RELOCATION_X86_64_DTPOFF32 => "R_X86_64_DTPOFF32",
+        RELOCATION_X86_64_GOTTPOFF => "R_X86_64_GOTTPOFF",
+        RELOCATION_X86_64_TPOFF32 => "R_X86_64_TPOFF32",
+        RELOCATION_X86_64_PC64 => "R_X86_64_PC64",
+        RELOCATION_X86_64_GOTOFF64 => "R_X86_64_GOTOFF64",
+        RELOCATION_X86_64_GOTOPC32 => "R_X86_64_GOTPC32",
+        RELOCATION_X86_64_IRELATIV => "R_X86_64_IRELATIVE",
+        _ => "Other",
+    }
+}
+
+pub fn aarch64_relocation_type_name(relocation_type: u64) -> &'static str {
+    match relocation_type {
+        RELOCATION_AARCH64_NONE => "R_AARCH64_NONE",
+        RELOCATION_AARCH64_ABS64 => "R_AARCH64_ABS64",
+        RELOCATION_AARCH64_COPY => "R_AARCH64_COPY",
+        RELOCATION_AARCH64_GLOB_DAT => "R_AARCH64_GLOB_DAT",
+        RELOCATION_AARCH64_JUMP_SLOT => "R_AARCH64_JUMP_SLOT",
+        RELOCATION_AARCH64_RELATIVE => "R_AARCH64_RELATIVE",
+        RELOCATION_AARCH64_IRELATIVE => "R_AARCH64_IRELATIVE",
+        _ => "Other",
+    }
+}
+
+/// The name of `relocation_type` for files of `machine`.
+pub fn machine_relocation_type_name(machine: u16, relocation_type: u64) -> &'static str {
+    match machine {
+        MACHINE_AARCH64 => aarch64_relocation_type_name(relocation_type),
+        _ => relocation_type_name(relocation_type),
+    }
+}
+
+impl Elf64ResolvedRelocationAddend {
+    pub fn type_name(&self) -> &'static str {
+        machine_relocation_type_name(self.machine, self.relocation_type)
+    }
+
+    /// Bytes the relocation writes at its offset. A COPY relocation writes as many as its symbol
+    /// has, which it does not know itself, so it counts as writing none.
+    pub fn width(&self) -> u64 {
+        if self.copy() {
+            return 0;
+        }
+        if self.machine != MACHINE_X86_64 {
+            return if self.relocation_type == RELOCATION_AARCH64_NONE {
+                0
+            } else {
+                8
+            };
+        }
+        match self.relocation_type {
+            RELOCATION_X86_64_NONE => 0,
+            RELOCATION_X86_64_8 | RELOCATION_X86_64_PC8 => 1,
+            RELOCATION_X86_64_16 | RELOCATION_X86_64_PC16 => 2,
+            RELOCATION_X86_64_32
+            | RELOCATION_X86_64_32S
+            | RELOCATION_X86_64_PC32
+            | RELOCATION_X86_64_GOT32
+            | RELOCATION_X86_64_PLT32
+            | RELOCATION_X86_64_GOTPCREL
+            | RELOCATION_X86_64_GOTOPC32
+            | RELOCATION_X86_64_TLSGD
+            | RELOCATION_X86_64_TLSLD
+            | RELOCATION_X86_64_DTPOFF32
+            | RELOCATION_X86_64_GOTTPOFF
+            | RELOCATION_X86_64_TPOFF32 => 4,
+            _ => 8,
+        }
+    }
+
+    /// R_X86_64_COPY or R_AARCH64_COPY.
+    pub fn copy(&self) -> bool {
+        matches!(
+            (self.machine, self.relocation_type),
+            (MACHINE_X86_64, RELOCATION_X86_64_COPY) | (MACHINE_AARCH64, RELOCATION_AARCH64_COPY)
+        )
+    }
+
+    /// Stores S + A in a word: R_X86_64_64 or R_AARCH64_ABS64.
+    pub fn absolute(&self) -> bool {
+        matches!(
+            (self.machine, self.relocation_type),
+            (MACHINE_X86_64, RELOCATION_X86_64_64) | (MACHINE_AARCH64, RELOCATION_AARCH64_ABS64)
+        )
+    }
+
+    /// What a symbol word adds to the address of its symbol. Every one of AArch64 is S + A,
+    /// of x86-64 only R_X86_64_64 is.
+    pub fn symbol_addend(&self) -> i64 {
+        if self.absolute() || self.machine == MACHINE_AARCH64 {
+            self.addend
+        } else {
+            0
+        }
+    }
+
+    /// Stores the address of its symbol in a word, or copies the symbol: the relocations the
+    /// prelink cache records.
+    pub fn symbol_word(&self) -> bool {
+        self.absolute()
+            || self.copy()
+            || matches!(
+                (self.machine, self.relocation_type),
+                (MACHINE_X86_64, RELOCATION_X86_64_GLOB_DAT)
+                    | (MACHINE_X86_64, RELOCATION_X86_64_JUMP_SLOT)
+                    | (MACHINE_AARCH64, RELOCATION_AARCH64_GLOB_DAT)
+                    | (MACHINE_AARCH64, RELOCATION_AARCH64_JUMP_SLOT)
+            )
+    }
+}
+
+pub fn format_addend(addend: i64) -> String {
+    if addend < 0 {
+        format!("-{:#X}", addend.unsigned_abs())
+    } else {
+        format!("{:#X}", addend)
+    }
 }
 
 impl Display for Elf64ResolvedRelocationAddend {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let relocation_types = [
-            "R_X86_64_NONE",
-            "R_X86_64_64",
-            "R_X86_64_PC3",
-            "R_X86_64_GOT32",
-            "R_X86_64_PLT32",
-            "R_X86_64_COPY",
-            "R_X86_64_GLOB_DAT",
-            "R_X86_64_JUMP_SLOT",
-            "R_X86_64_RELATIVE",
-            "R_X86_64_GOTPCREL",
-            "R_X86_64_32",
-            "R_X86_64_32S",
-            "R_X86_64_16",
-            "R_X86_64_PC16",
-            "R_X86_64_8",
-            "R_X86_64_PC8",
-            "R_X86_64_DPTMOD64",
-            "R_X86_64_DTPOFF64",
-            "R_X86_64_TLSGD",
-            "R_X86_64_TLSLD",
-            "R_X86_64_DTPOFF32",
-            "R_X86_64_GOTTPOFF",
-            "R_X86_64_TPOFF32",
-            "R_X86_64_PC64",
-            "R_X86_64_GOTOFF64",
-            "R_X86_64_GOTOPC32",
-        ];
-        let values: Vec<u64> = (0..26).collect();
-        let relocation_map: HashMap<u64, &str> =
-            Iterator::zip(values.iter().cloned(), relocation_types).collect();
         f.write_str(format!("| Symbol name: {}", self.symbol_name).as_str())?;
-        f.write_str(
-            format!(
-                "| Relocation type: {}",
-                relocation_map
-                    .get(&self.relocation_type)
-                    .unwrap_or(&"Other")
-            )
-            .as_str(),
-        )?;
+        f.write_str(format!("| Relocation type: {}", self.type_name()).as_str())?;
         f.write_str(format!("| Symbol table index: {}", self.symbol_index).as_str())?;
         f.write_str(format!("| Offset: {:X}", self.offset).as_str())?;
-        f.write_str(format!("| Addend: {:X}", self.offset).as_str())?;
-        f.write_str(format!("|").as_str())
+        f.write_str(format!("| Addend: {}", format_addend(self.addend)).as_str())?;
+        f.write_str("|".to_string().as_str())
     }
 }
 
@@ -307,7 +598,7 @@ impl Display for Elf64ResolvedSymbolTableEntry {
                 symbol_bindings.get(&self.binding).unwrap_or(&"Other")
             )
             .as_str(),
-        );
+        )?;
         f.write_str(format!("| Value: {:X}", self.value).as_str())?;
         if self.section_index == SHN_UNDEF {
             f.write_str("| Section Index: UNDEFINED")?;
@@ -322,7 +613,7 @@ impl Display for Elf64ResolvedSymbolTableEntry {
     }
 }
 
-fn make_flags_string(flags: &Vec<&str>) -> String {
+fn make_flags_string(flags: &[&str]) -> String {
     let mut flags_string = String::new();
     for x in 0..flags.len() {
         if x != (flags.len() - 1) {
@@ -440,9 +731,9 @@ impl Display for Elf64Header {
         let mut magic = String::new();
         for x in 0..IDENT_SIZE {
             if x == 0 {
-                magic.push_str(format!("{:#02X}", self.e_ident[x]).as_str());
+                magic.push_str(format!("{:#04X}", self.e_ident[x]).as_str());
             } else {
-                magic.push_str(format!(" {:#02X}", self.e_ident[x]).as_str());
+                magic.push_str(format!(" {:#04X}", self.e_ident[x]).as_str());
             }
         }
         f.write_str(format!("Magic: {}\n", magic).as_str())?;
@@ -453,8 +744,8 @@ impl Display for Elf64Header {
             )
             .as_str(),
         )?;
-        f.write_str(format!("Machine: {:#02X}\n", self.e_machine).as_str())?;
-        f.write_str(format!("Version: {:#02X}\n", self.e_version).as_str())?;
+        f.write_str(format!("Machine: {:#04X}\n", self.e_machine).as_str())?;
+        f.write_str(format!("Version: {:#04X}\n", self.e_version).as_str())?;
         f.write_str(format!("Entry point address: {:#X}\n", self.e_entry).as_str())?;
         f.write_str(
             format!(
@@ -503,216 +794,729 @@ pub struct Elf64Metadata {
     pub symbol_table: Vec<Elf64ResolvedSymbolTableEntry>,
     pub dynamic_symbol_table: Vec<Elf64ResolvedSymbolTableEntry>,
     pub relocations: Vec<Elf64ResolvedRelocationAddend>,
+    pub malformed_relocations: Vec<Elf64MalformedRelocation>,
     pub dynamic: Elf64Dynamic,
+    /// The dynamic loader the program expects, from PT_INTERP.
+    pub interpreter: Option<String>,
+    /// From PT_TLS, at most one in a well-formed file.
+    pub tls_segments: Vec<Elf64TlsSegment>,
+    pub gnu_hash: Option<Elf64GnuHash>,
+    pub sysv_hash: Option<Elf64SysvHash>,
+    /// The versions required from each dependency, from `.gnu.version_r`.
+    pub version_requirements: Vec<VersionRequirement>,
+    pub groups: Vec<Elf64SectionGroup>,
+    pub string_tables: Option<StringTableCache>,
+    pub string_table_reads: Vec<(usize, usize)>,
+}
+
+/// Bytes of a table read at once by default, which bounds the buffer for huge tables.
+pub const DEFAULT_READ_CHUNK_SIZE: u64 = 1024 * 1024;
+
+/// How `Elf64Metadata::load_with_options` reads a file.
+#[derive(Clone, Copy)]
+pub struct ParseOptions {
+    /// Keeps all string tables in `string_tables`, not only the ones symbols refer to.
+    pub keep_string_tables: bool,
+    /// Largest number of bytes of a symbol or relocation table read at once, at least one entry.
+    pub read_chunk_size: u64,
+}
+
+impl Default for ParseOptions {
+    fn default() -> ParseOptions {
+        ParseOptions {
+            keep_string_tables: false,
+            read_chunk_size: DEFAULT_READ_CHUNK_SIZE,
+        }
+    }
+}
+
+pub(crate) fn read_entries<T: Read + Seek, E>(
+    reader: &mut T,
+    offset: u64,
+    count: u64,
+) -> Result<Vec<E>, DrowError> {
+    let mut result = Vec::new();
+    let mut buffer = Vec::new();
+    for_each_entry(
+        reader,
+        offset,
+        count,
+        DEFAULT_READ_CHUNK_SIZE,
+        &mut buffer,
+        |entry| {
+            result.push(entry);
+            Ok(())
+        },
+    )?;
+    Ok(result)
+}
+
+/// Decodes the `count` entries at `offset` in order, reading up to `chunk_size` bytes of them at
+/// once into `buffer`.
+pub(crate) fn for_each_entry<T: Read + Seek, E>(
+    reader: &mut T,
+    offset: u64,
+    count: u64,
+    chunk_size: u64,
+    buffer: &mut Vec<u8>,
+    mut visit: impl FnMut(E) -> Result<(), DrowError>,
+) -> Result<(), DrowError> {
+    let size = size_of::<E>() as u64;
+    let chunk_entries = (chunk_size / size).max(1);
+    let mut first = 0;
+    while first < count {
+        let entries = chunk_entries.min(count - first);
+        read_segment_into(reader, offset + first * size, entries * size, buffer)?;
+        for chunk in buffer.chunks_exact(size as usize) {
+            visit(unsafe { std::ptr::read_unaligned(chunk.as_ptr() as *const E) })?;
+        }
+        first += entries;
+    }
+    Ok(())
+}
+
+/// The in-memory bytes of a `#[repr(C)]` entry, which match its file layout on little-endian
+/// targets.
+pub fn entry_bytes<E>(entry: &E) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(entry as *const E as *const u8, size_of::<E>()) }
 }
 
 impl Elf64Metadata {
-    fn check_file_ident(header: &Elf64Header) -> Result<(), String> {
+    fn check_file_ident(path: &str, header: &Elf64Header) -> Result<(), DrowError> {
         let mag = &header.e_ident[0..4];
-        if mag[0] == 0x7F && mag[1] == 'E' as u8 && mag[2] == 'L' as u8 && mag[3] == 'F' as u8 {
-            println!("ELF file detected");
+        if mag[0] == 0x7F && mag[1] == b'E' && mag[2] == b'L' && mag[3] == b'F' {
+            debug!("ELF file detected");
             Ok(())
         } else {
-            Result::Err(format!(
-                "Not an ELF file. {:#02X} {:#02X} {:#02X} {:#02X}",
-                mag[0], mag[1], mag[2], mag[3]
-            ))
+            Err(DrowError::NotElf {
+                path: path.to_string(),
+                magic: [mag[0], mag[1], mag[2], mag[3]],
+            })
         }
     }
 
-    fn check_class(header: &Elf64Header) -> Result<(), String> {
+    fn check_class(path: &str, header: &Elf64Header) -> Result<(), DrowError> {
         let mag = &header.e_ident[4..5];
         if mag[0] == 2 {
-            println!("ELF64 detected");
+            debug!("ELF64 detected");
             Ok(())
         } else {
-            Result::Err(format!("ELF64 required, found: {:#02X}", mag[0]))
+            Err(DrowError::WrongClass {
+                path: path.to_string(),
+                class: mag[0],
+            })
         }
     }
 
-    fn check_endian(header: &Elf64Header) -> Result<(), String> {
-        let mag = &header.e_ident[4..5];
-        if mag[0] == 2 {
-            println!("Little endian encoding detected");
+    fn check_endian(path: &str, header: &Elf64Header) -> Result<(), DrowError> {
+        let mag = &header.e_ident[5..6];
+        if mag[0] == 1 {
+            debug!("Little endian encoding detected");
             Ok(())
         } else {
-            Result::Err(format!("Little Endian required, found: {:#02X}", mag[0]))
+            Err(DrowError::WrongEncoding {
+                path: path.to_string(),
+                encoding: mag[0],
+            })
         }
     }
 
-    fn check_machine(header: &Elf64Header) -> Result<(), String> {
-        if header.e_machine == 0x3E {
-            println!("AMD64 detected");
+    fn check_machine(path: &str, header: &Elf64Header) -> Result<(), DrowError> {
+        match header.e_machine {
+            MACHINE_X86_64 => {
+                debug!("AMD64 detected");
+                Ok(())
+            }
+            MACHINE_AARCH64 => {
+                debug!("AArch64 detected");
+                Ok(())
+            }
+            machine => Err(DrowError::WrongMachine {
+                path: path.to_string(),
+                machine,
+            }),
+        }
+    }
+
+    fn check_version(path: &str, header: &Elf64Header) -> Result<(), DrowError> {
+        if header.e_ident[6] != 1 {
+            return Err(DrowError::WrongVersion {
+                path: path.to_string(),
+                field: "e_ident[EI_VERSION]",
+                version: header.e_ident[6] as u32,
+            });
+        }
+        if header.e_version != 1 {
+            return Err(DrowError::WrongVersion {
+                path: path.to_string(),
+                field: "e_version",
+                version: header.e_version,
+            });
+        }
+        Ok(())
+    }
+
+    fn check_header_size(path: &str, header: &Elf64Header) -> Result<(), DrowError> {
+        if header.e_elf_header_size as usize == mem::size_of::<Elf64Header>() {
             Ok(())
         } else {
-            Result::Err(format!("AMD64 expected, found: {:#02X}", header.e_machine))
+            Err(DrowError::WrongHeaderSize {
+                path: path.to_string(),
+                size: header.e_elf_header_size,
+            })
         }
     }
 
-    fn check_header(header: &Elf64Header) -> Result<(), String> {
-        Elf64Metadata::check_file_ident(header)?;
-        Elf64Metadata::check_class(header)?;
-        Elf64Metadata::check_endian(header)?;
-        Elf64Metadata::check_machine(header)
+    /// Only warns, as some packers store data in the padding of e_ident.
+    fn check_ident_padding(path: &str, header: &Elf64Header) {
+        let padding = &header.e_ident[9..];
+        if padding.iter().any(|byte| *byte != 0) {
+            warn!("{}: non-zero padding in e_ident: {:02X?}", path, padding);
+        }
+    }
+
+    fn check_header(path: &str, header: &Elf64Header) -> Result<(), DrowError> {
+        Elf64Metadata::check_file_ident(path, header)?;
+        Elf64Metadata::check_class(path, header)?;
+        Elf64Metadata::check_endian(path, header)?;
+        Elf64Metadata::check_version(path, header)?;
+        Elf64Metadata::check_machine(path, header)?;
+        Elf64Metadata::check_header_size(path, header)?;
+        Elf64Metadata::check_ident_padding(path, header);
+        Ok(())
+    }
+
+    /// Reads and checks the ELF header only, to tell whether a file can be loaded at all before
+    /// parsing the rest of it.
+    pub fn load_header<T: Read + Seek>(
+        file_path: &str,
+        reader: &mut T,
+    ) -> Result<Elf64Header, DrowError> {
+        let header = Elf64Metadata::load_elf_header(reader)?;
+        Elf64Metadata::check_header(file_path, &header)?;
+        Ok(header)
     }
 
-    fn load_elf_header<T: Read>(reader: &mut T) -> Result<Elf64Header, String> {
-        let mut header_buffer: Vec<u8> = Vec::new();
-        header_buffer.resize(mem::size_of::<Elf64Header>(), 0);
-        reader
-            .read_exact(&mut header_buffer)
-            .map_err(|err| format!("Unable to read file: {:?}", err))?;
+    fn load_elf_header<T: Read + Seek>(reader: &mut T) -> Result<Elf64Header, DrowError> {
+        let header_buffer = read_segment(reader, 0, mem::size_of::<Elf64Header>() as u64)?;
         let header: Elf64Header =
             unsafe { std::ptr::read_unaligned(header_buffer.as_ptr() as *const _) };
         Result::Ok(header)
     }
 
+    /// Reads a header table of `count` entries `entry_size` bytes apart with a single read, once
+    /// the table is known to fit in the file. Entries larger than `E` are decoded from their
+    /// start.
+    fn load_header_table<T: Read + Seek, E>(
+        reader: &mut T,
+        what: &str,
+        offset: u64,
+        count: u64,
+        entry_size: u64,
+    ) -> Result<Vec<E>, DrowError> {
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        if entry_size < size_of::<E>() as u64 {
+            return Err(DrowError::Malformed {
+                what: format!(
+                    "{} entries of {} bytes, shorter than the {} bytes of an entry",
+                    what,
+                    entry_size,
+                    size_of::<E>()
+                ),
+                offset: Some(offset),
+            });
+        }
+        let file_size = reader
+            .seek(SeekFrom::End(0))
+            .map_err(|source| DrowError::Read {
+                offset,
+                length: 0,
+                source,
+            })?;
+        let size = count * entry_size;
+        if offset.checked_add(size).is_none_or(|end| end > file_size) {
+            return Err(DrowError::Malformed {
+                what: format!(
+                    "{} {} entries of {} bytes extend past the end of the file",
+                    count, what, entry_size
+                ),
+                offset: Some(offset),
+            });
+        }
+        let buffer = read_segment(reader, offset, size)?;
+        Ok(buffer
+            .chunks_exact(entry_size as usize)
+            .map(|chunk| unsafe { std::ptr::read_unaligned(chunk.as_ptr() as *const E) })
+            .collect())
+    }
+
     fn load_program_headers<T: Read + Seek>(
         header: &Elf64Header,
         reader: &mut T,
-    ) -> Result<Vec<Elf64ProgramHeader>, String> {
-        reader
-            .seek(SeekFrom::Start(header.e_program_header_offset))
-            .map_err(|err| format!("Unable to read file: {:?}", err))?;
-        let mut program_headers: Vec<Elf64ProgramHeader> = Vec::new();
-        for _ in 0..header.e_program_header_entries {
-            let mut program_header_buffer: Vec<u8> = Vec::new();
-            program_header_buffer.resize(mem::size_of::<Elf64ProgramHeader>(), 0);
-            reader
-                .read_exact(&mut program_header_buffer)
-                .map_err(|err| format!("Unable to read file: {:?}", err))?;
-            let program_header: Elf64ProgramHeader =
-                unsafe { std::ptr::read_unaligned(program_header_buffer.as_ptr() as *const _) };
-            program_headers.push(program_header);
-        }
-        Result::Ok(program_headers)
+    ) -> Result<Vec<Elf64ProgramHeader>, DrowError> {
+        Elf64Metadata::load_header_table(
+            reader,
+            "program header",
+            header.e_program_header_offset,
+            header.e_program_header_entries as u64,
+            header.e_program_header_entry_size as u64,
+        )
+    }
+
+    /// The path in PT_INTERP. Like the kernel, refuses one that is empty, longer than a path
+    /// can be or not ended by its only NUL byte.
+    fn load_interpreter<T: Read + Seek>(
+        program_headers: &[Elf64ProgramHeader],
+        reader: &mut T,
+    ) -> Result<Option<String>, DrowError> {
+        let header = match program_headers
+            .iter()
+            .find(|header| header.p_type == PROGRAM_HEADER_TYPE_INTERPRETER)
+        {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        let malformed = |what: String| DrowError::Malformed {
+            what,
+            offset: Some(header.p_offset),
+        };
+        if header.p_file_size > libc::PATH_MAX as u64 {
+            return Err(malformed(format!(
+                "PT_INTERP of {} bytes is longer than a path can be",
+                header.p_file_size
+            )));
+        }
+        let content = read_segment(reader, header.p_offset, header.p_file_size)?;
+        match content.split_last() {
+            Some((0, [])) | None => Err(malformed(String::from("PT_INTERP is empty"))),
+            Some((0, path)) if !path.contains(&0) => {
+                Ok(Some(String::from_utf8_lossy(path).into_owned()))
+            }
+            _ => Err(malformed(String::from(
+                "PT_INTERP does not end with its only NUL byte",
+            ))),
+        }
     }
 
     fn load_section_headers<T: Read + Seek>(
         header: &Elf64Header,
         reader: &mut T,
-    ) -> Result<Vec<Elf64SectionHeader>, String> {
-        reader
-            .seek(SeekFrom::Start(header.e_section_header_offset))
-            .map_err(|err| format!("Unable to read file: {:?}", err))?;
-        let mut section_headers: Vec<Elf64SectionHeader> = Vec::new();
-        for _ in 0..header.e_section_header_entries {
-            let mut buffer: Vec<u8> = Vec::new();
-            buffer.resize(mem::size_of::<Elf64SectionHeader>(), 0);
-            reader
-                .read_exact(&mut buffer)
-                .map_err(|err| format!("Unable to read file: {:?}", err))?;
-            let section_header: Elf64SectionHeader =
-                unsafe { std::ptr::read_unaligned(buffer.as_ptr() as *const _) };
-            section_headers.push(section_header);
-        }
-        Result::Ok(section_headers)
+    ) -> Result<Vec<Elf64SectionHeader>, DrowError> {
+        Elf64Metadata::load_header_table(
+            reader,
+            "section header",
+            header.e_section_header_offset,
+            header.e_section_header_entries as u64,
+            header.e_section_header_entry_size as u64,
+        )
     }
 
     fn load_symbol_table<T: Read + Seek>(
-        section_headers: &Vec<Elf64SectionHeader>,
+        section_headers: &[Elf64SectionHeader],
+        string_tables: &mut StringTableCache,
         reader: &mut T,
         table_type: u32,
-    ) -> Result<Vec<Elf64ResolvedSymbolTableEntry>, String> {
+        options: &ParseOptions,
+        buffer: &mut Vec<u8>,
+    ) -> Result<Vec<Elf64ResolvedSymbolTableEntry>, DrowError> {
         let mut result: Vec<Elf64ResolvedSymbolTableEntry> = Vec::new();
         for table in section_headers
             .iter()
             .filter(|header| header.sh_type == table_type)
         {
-            let section_string_table = get_string_table_content(
-                &section_headers.get(table.sh_link as usize).unwrap(),
+            let section_string_table =
+                string_tables.get(section_headers, table.sh_link as usize, reader)?;
+            for_each_entry(
                 reader,
-            );
-            reader.seek(SeekFrom::Start(table.sh_offset));
-            let entries = table.sh_size / size_of::<Elf64SymbolTableEntry>() as u64;
-            for _ in 0..entries {
-                let mut buffer: Vec<u8> = Vec::new();
-                buffer.resize(size_of::<Elf64SymbolTableEntry>(), 0);
-                reader
-                    .read_exact(&mut buffer)
-                    .map_err(|err| format!("Unable to read file: {:?}", err))?;
-                let section_entry: Elf64SymbolTableEntry =
-                    unsafe { std::ptr::read_unaligned(buffer.as_ptr() as *const _) };
-                let len = string_length(&section_string_table[section_entry.st_name as usize..]);
-                let from = section_entry.st_name as usize;
-                let to = from + len;
-                let symbol_name = std::str::from_utf8(&section_string_table[from..to])
-                    .unwrap()
-                    .to_string();
-                let resolved_entry = Elf64ResolvedSymbolTableEntry {
-                    symbol_name,
-                    binding: section_entry.binding(),
-                    symbol_type: section_entry.symbol_type(),
-                    section_index: section_entry.st_section_index,
-                    value: section_entry.st_value,
-                    size: section_entry.st_size,
-                };
-                result.push(resolved_entry);
-            }
+                table.sh_offset,
+                table.sh_size / size_of::<Elf64SymbolTableEntry>() as u64,
+                options.read_chunk_size,
+                buffer,
+                |section_entry: Elf64SymbolTableEntry| {
+                    let symbol_name = section_string_table
+                        .get_lossy(section_entry.st_name)
+                        .map_err(|err| DrowError::InvalidString {
+                            what: String::from("symbol name"),
+                            source: err,
+                        })?
+                        .into_owned();
+                    let resolved_entry = Elf64ResolvedSymbolTableEntry {
+                        symbol_name,
+                        binding: section_entry.binding(),
+                        symbol_type: section_entry.symbol_type(),
+                        section_index: section_entry.st_section_index,
+                        value: section_entry.st_value,
+                        size: section_entry.st_size,
+                        version: None,
+                    };
+                    result.push(resolved_entry);
+                    Ok(())
+                },
+            )?;
         }
         Result::Ok(result)
     }
 
+    /// The addend of a SHT_REL entry: the value at the place `relocation` writes, as the file
+    /// has it, sign extended from the width of the relocation. Places the file does not back,
+    /// as in objects without program headers, hold none.
+    fn implicit_addend<T: Read + Seek>(
+        program_headers: &[Elf64ProgramHeader],
+        reader: &mut T,
+        relocation: &Elf64ResolvedRelocationAddend,
+    ) -> Result<i64, DrowError> {
+        let width = relocation.width() as usize;
+        let offset = match string_tables::file_offset(program_headers, relocation.offset) {
+            Some(offset) if width > 0 => offset,
+            _ => return Ok(0),
+        };
+        let bytes = read_segment(reader, offset, width as u64)?;
+        let mut value = [0; 8];
+        value[..width].copy_from_slice(&bytes);
+        let shift = 64 - 8 * width as u32;
+        Ok((i64::from_le_bytes(value) << shift) >> shift)
+    }
+
     fn load_relocation_entries<T: Read + Seek>(
-        section_headers: &Vec<Elf64SectionHeader>,
-        dynamic_symbol_table: &Vec<Elf64ResolvedSymbolTableEntry>,
+        machine: u16,
+        program_headers: &[Elf64ProgramHeader],
+        section_headers: &[Elf64SectionHeader],
+        dynamic_symbol_table: &[Elf64ResolvedSymbolTableEntry],
         reader: &mut T,
-    ) -> Vec<Elf64ResolvedRelocationAddend> {
+        options: &ParseOptions,
+        buffer: &mut Vec<u8>,
+    ) -> Result<
+        (
+            Vec<Elf64ResolvedRelocationAddend>,
+            Vec<Elf64MalformedRelocation>,
+        ),
+        DrowError,
+    > {
         let mut result = Vec::new();
-        for header in section_headers.iter() {
-            if header.sh_type == ELF64_SECTION_HEADER_RELOCATION_ADDEND {
-                reader.seek(SeekFrom::Start(header.sh_offset));
-                let entries = header.sh_size / size_of::<Elf64SymbolTableEntry>() as u64;
-                for _ in 0..entries {
-                    let mut buffer: Vec<u8> = Vec::new();
-                    buffer.resize(size_of::<Elf64SymbolTableEntry>(), 0);
-                    reader
-                        .read_exact(&mut buffer)
-                        .map_err(|err| format!("Unable to read file: {:?}", err));
-                    let relocation_entry: Elf64RelocationAddend =
-                        unsafe { std::ptr::read_unaligned(buffer.as_ptr() as *const _) };
-                    let symbol_name: String = dynamic_symbol_table
-                        .get(relocation_entry.symbol_table_index() as usize)
-                        .map(|s| s.symbol_name.clone())
-                        .unwrap_or("".to_string());
-                    let resolved_entry = Elf64ResolvedRelocationAddend {
-                        symbol_name,
-                        relocation_type: relocation_entry.relocation_type(),
-                        offset: relocation_entry.offset,
-                        addend: relocation_entry.addend,
-                        symbol_index: relocation_entry.symbol_table_index(),
-                        symbol_section_index: header.sh_link,
-                    };
-                    result.push(resolved_entry);
+        let mut malformed = Vec::new();
+        for (section_index, header) in section_headers.iter().enumerate() {
+            // (offset, symbol index, type, addend) of each entry. Those of SHT_REL sections get
+            // their addends once the relocations are resolved.
+            let mut entries: Vec<(u64, u64, u64, i64)> = Vec::new();
+            let entry_size = match header.sh_type {
+                ELF64_SECTION_HEADER_RELOCATION_ADDEND => {
+                    for_each_entry(
+                        reader,
+                        header.sh_offset,
+                        header.sh_size / size_of::<Elf64RelocationAddend>() as u64,
+                        options.read_chunk_size,
+                        buffer,
+                        |entry: Elf64RelocationAddend| {
+                            entries.push((
+                                entry.offset,
+                                entry.symbol_table_index(),
+                                entry.relocation_type(),
+                                entry.addend,
+                            ));
+                            Ok(())
+                        },
+                    )?;
+                    size_of::<Elf64RelocationAddend>()
+                }
+                ELF64_SECTION_HEADER_RELOCATION => {
+                    for_each_entry(
+                        reader,
+                        header.sh_offset,
+                        header.sh_size / size_of::<Elf64Relocation>() as u64,
+                        options.read_chunk_size,
+                        buffer,
+                        |entry: Elf64Relocation| {
+                            entries.push((
+                                entry.offset,
+                                entry.symbol_table_index(),
+                                entry.relocation_type(),
+                                0,
+                            ));
+                            Ok(())
+                        },
+                    )?;
+                    size_of::<Elf64Relocation>()
+                }
+                _ => continue,
+            };
+            for (position, (offset, symbol_index, relocation_type, addend)) in
+                entries.into_iter().enumerate()
+            {
+                // Index 0 is STN_UNDEF, used by relocations without a symbol like
+                // R_X86_64_RELATIVE.
+                let symbol_name = if symbol_index == 0 {
+                    String::new()
+                } else {
+                    match dynamic_symbol_table.get(symbol_index as usize) {
+                        Some(symbol) => symbol.symbol_name.clone(),
+                        None => {
+                            malformed.push(Elf64MalformedRelocation {
+                                section_index,
+                                position: position as u64,
+                                symbol_index,
+                                relocation_type,
+                                machine,
+                                file_offset: header.sh_offset + (position * entry_size) as u64,
+                                symbol_count: dynamic_symbol_table.len(),
+                            });
+                            continue;
+                        }
+                    }
+                };
+                let mut resolved_entry = Elf64ResolvedRelocationAddend {
+                    symbol_name,
+                    relocation_type,
+                    machine,
+                    offset,
+                    addend,
+                    symbol_index,
+                    symbol_section_index: header.sh_link,
+                    section_index,
+                };
+                if header.sh_type == ELF64_SECTION_HEADER_RELOCATION {
+                    resolved_entry.addend =
+                        Elf64Metadata::implicit_addend(program_headers, reader, &resolved_entry)?;
+                }
+                result.push(resolved_entry);
+            }
+        }
+        Result::Ok((result, malformed))
+    }
+
+    fn has_interpreter(&self) -> bool {
+        self.program_headers
+            .iter()
+            .any(|header| header.p_type == PROGRAM_HEADER_TYPE_INTERPRETER)
+    }
+
+    /// Classifies ET_DYN objects by DF_1_PIE, then by an entry point together with PT_INTERP,
+    /// which older linkers leave as the only sign of a PIE. Such an object with a soname is a
+    /// library that can also be run.
+    pub fn object_kind(&self) -> ObjectKind {
+        let header = &self.elf_header;
+        match header.e_type {
+            ELF_TYPE_EXECUTABLE => ObjectKind::Executable,
+            ELF_TYPE_SHARED_OBJECT if self.dynamic.pie() => {
+                ObjectKind::PositionIndependentExecutable
+            }
+            ELF_TYPE_SHARED_OBJECT if header.e_entry != 0 && self.has_interpreter() => {
+                if self.dynamic.soname.is_some() {
+                    ObjectKind::ExecutableLibrary
+                } else {
+                    ObjectKind::PositionIndependentExecutable
+                }
+            }
+            ELF_TYPE_SHARED_OBJECT => ObjectKind::SharedLibrary,
+            _ => ObjectKind::Other,
+        }
+    }
+
+    /// Why DF_1_PIE does not fit the rest of the object, if it does not.
+    pub fn pie_flag_problem(&self) -> Option<String> {
+        if !self.dynamic.pie() {
+            None
+        } else if self.elf_header.e_type != ELF_TYPE_SHARED_OBJECT {
+            Some(format!(
+                "DF_1_PIE is set on an object of type {}, only ET_DYN can be a PIE",
+                self.elf_header.e_type
+            ))
+        } else if self.elf_header.e_entry == 0 {
+            Some(String::from("DF_1_PIE is set but there is no entry point"))
+        } else {
+            None
+        }
+    }
+
+    /// The first defined dynamic symbol named `name`, without version, that `accept` takes, with
+    /// its index. Looked up in `.gnu.hash` when the file has one, then in `.hash`, found by
+    /// walking the table otherwise.
+    pub fn find_dynamic_symbol(
+        &self,
+        name: &str,
+        mut accept: impl FnMut(usize, &Elf64ResolvedSymbolTableEntry) -> bool,
+    ) -> Option<(usize, &Elf64ResolvedSymbolTableEntry)> {
+        match (self.gnu_hash.as_ref(), self.sysv_hash.as_ref()) {
+            (Some(gnu_hash), _) => {
+                gnu_hash.lookup_with(name, &self.dynamic_symbol_table, |index, symbol| {
+                    !symbol.undefined() && accept(index, symbol)
+                })
+            }
+            (None, Some(sysv_hash)) => {
+                sysv_hash.lookup_with(name, &self.dynamic_symbol_table, |index, symbol| {
+                    !symbol.undefined() && accept(index, symbol)
+                })
+            }
+            (None, None) => self
+                .dynamic_symbol_table
+                .iter()
+                .enumerate()
+                .find(|(index, symbol)| {
+                    !symbol.undefined()
+                        && symbol_name::base_name(&symbol.symbol_name) == name
+                        && accept(*index, symbol)
+                }),
+        }
+    }
+
+    /// The dynamic symbols the object refers to without defining them.
+    pub fn imports(&self) -> impl Iterator<Item = &Elf64ResolvedSymbolTableEntry> {
+        self.dynamic_symbol_table
+            .iter()
+            .filter(|symbol| symbol.imported())
+    }
+
+    pub fn plt_relocation(&self, relocation: &Elf64ResolvedRelocationAddend) -> bool {
+        self.dynamic.jump_relocations != 0
+            && self
+                .section_headers
+                .get(relocation.section_index)
+                .map(|header| header.sh_virtual_address == self.dynamic.jump_relocations)
+                .unwrap_or(false)
+    }
+
+    /// Whether a dynamic relocation stores a 32-bit absolute address, as the small code model
+    /// does. Such an object only works mapped below 2 GiB.
+    pub fn needs_low_placement(&self) -> bool {
+        self.relocations.iter().any(|relocation| {
+            relocation.machine == MACHINE_X86_64
+                && matches!(
+                    relocation.relocation_type,
+                    RELOCATION_X86_64_32 | RELOCATION_X86_64_32S
+                )
+        })
+    }
+
+    pub fn containing_function(&self, address: u64) -> Option<&str> {
+        self.containing_function_symbol(address)
+            .map(|symbol| symbol.symbol_name.as_str())
+    }
+
+    /// The function symbol spanning `address`, unless several names share its start.
+    pub fn containing_function_symbol(
+        &self,
+        address: u64,
+    ) -> Option<&Elf64ResolvedSymbolTableEntry> {
+        let mut best: Option<&Elf64ResolvedSymbolTableEntry> = None;
+        let mut tie = false;
+        let candidates = self
+            .symbol_table
+            .iter()
+            .chain(self.dynamic_symbol_table.iter())
+            .filter(|symbol| symbol.function() || symbol.indirect_function())
+            .filter(|symbol| !symbol.undefined() && symbol.size > 0)
+            .filter(|symbol| symbol.value <= address && address < symbol.value + symbol.size);
+        for symbol in candidates {
+            match best {
+                Some(current) if symbol.value < current.value => {}
+                Some(current) if symbol.value == current.value => {
+                    if symbol.symbol_name != current.symbol_name {
+                        tie = true;
+                    }
+                }
+                _ => {
+                    best = Some(symbol);
+                    tie = false;
                 }
             }
         }
-        result
+        if tie {
+            return None;
+        }
+        best
+    }
+
+    pub fn describe_address(&self, address: u64) -> String {
+        format!(
+            "<{}>",
+            self.containing_function(address).unwrap_or("unknown")
+        )
     }
 
     pub fn load<T: Read + Seek>(
         file_path: &String,
         reader: &mut T,
-    ) -> Result<Elf64Metadata, String> {
-        println!("Loading file: {}", file_path);
+    ) -> Result<Elf64Metadata, DrowError> {
+        Elf64Metadata::load_with_options(file_path, reader, ParseOptions::default())
+    }
+
+    pub fn load_with_string_tables<T: Read + Seek>(
+        file_path: &String,
+        reader: &mut T,
+        keep_string_tables: bool,
+    ) -> Result<Elf64Metadata, DrowError> {
+        let options = ParseOptions {
+            keep_string_tables,
+            ..ParseOptions::default()
+        };
+        Elf64Metadata::load_with_options(file_path, reader, options)
+    }
+
+    pub fn load_with_options<T: Read + Seek>(
+        file_path: &String,
+        reader: &mut T,
+        options: ParseOptions,
+    ) -> Result<Elf64Metadata, DrowError> {
+        info!("Loading file: {}", file_path);
         let elf_header = Elf64Metadata::load_elf_header(reader)?;
-        Elf64Metadata::check_header(&elf_header)?;
+        Elf64Metadata::check_header(file_path, &elf_header)?;
         let program_headers = Elf64Metadata::load_program_headers(&elf_header, reader)?;
+        let interpreter = Elf64Metadata::load_interpreter(&program_headers, reader)?;
+        let tls_segments = Elf64TlsSegment::from_program_headers(file_path, &program_headers);
         let section_headers = Elf64Metadata::load_section_headers(&elf_header, reader)?;
+        let mut string_tables = StringTableCache::new();
+        let mut buffer = Vec::new();
         let symbol_table = Elf64Metadata::load_symbol_table(
             &section_headers,
+            &mut string_tables,
             reader,
             ELF64_SECTION_HEADER_SYMBOL_TABLE,
+            &options,
+            &mut buffer,
         )?;
         let dynamic_symbol_table = Elf64Metadata::load_symbol_table(
             &section_headers,
+            &mut string_tables,
             reader,
             ELF64_SECTION_HEADER_DYNAMIC_SYMBOL_TABLE,
+            &options,
+            &mut buffer,
+        )?;
+        let (relocations, malformed_relocations) = Elf64Metadata::load_relocation_entries(
+            elf_header.e_machine,
+            &program_headers,
+            &section_headers,
+            &dynamic_symbol_table,
+            reader,
+            &options,
+            &mut buffer,
+        )?;
+        let dynamic = Elf64Dynamic::load(
+            &section_headers,
+            &program_headers,
+            &mut string_tables,
+            reader,
+        )?;
+        let gnu_hash = Elf64GnuHash::load(
+            &section_headers,
+            &program_headers,
+            &dynamic,
+            dynamic_symbol_table.len(),
+            reader,
         )?;
-        let relocations =
-            Elf64Metadata::load_relocation_entries(&section_headers, &dynamic_symbol_table, reader);
-        let dynamic = Elf64Dynamic::load(&section_headers, reader)?;
-        let result = Elf64Metadata {
+        let sysv_hash = Elf64SysvHash::load(&section_headers, &program_headers, &dynamic, reader)?;
+        let groups = Elf64SectionGroup::load_all(
+            &section_headers,
+            elf_header.e_section_name_string_table_index as usize,
+            &mut string_tables,
+            reader,
+        )?;
+        if options.keep_string_tables {
+            string_tables.load_all(&section_headers, reader)?;
+        }
+        let string_table_reads = string_tables.reads();
+        let mut result = Elf64Metadata {
             file_path: file_path.clone(),
             elf_header,
             program_headers,
@@ -720,8 +1524,344 @@ impl Elf64Metadata {
             symbol_table,
             dynamic_symbol_table,
             relocations,
+            malformed_relocations,
             dynamic,
+            interpreter,
+            tls_segments,
+            gnu_hash,
+            sysv_hash,
+            version_requirements: Vec::new(),
+            groups,
+            string_tables: if options.keep_string_tables {
+                Some(string_tables)
+            } else {
+                None
+            },
+            string_table_reads,
         };
+        let versions = versions::dynamic_symbol_versions(&result, reader)?;
+        for (symbol, version) in result.dynamic_symbol_table.iter_mut().zip(versions) {
+            symbol.version = version.map(|version| version.name);
+        }
+        result.version_requirements = versions::version_requirements(&result, reader)?;
         Result::Ok(result)
     }
 }
+
+pub const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+pub const ELF_CLASS_32: u8 = 1;
+pub const ELF_CLASS_64: u8 = 2;
+
+/// EI_CLASS of the file `reader` reads, after checking its magic.
+pub fn read_class<T: Read + Seek>(path: &str, reader: &mut T) -> Result<u8, DrowError> {
+    let ident = read_segment(reader, 0, IDENT_SIZE as u64)?;
+    if ident.len() < IDENT_SIZE || ident[0..4] != ELF_MAGIC {
+        let mut magic = [0; 4];
+        for (byte, value) in magic.iter_mut().zip(ident.iter()) {
+            *byte = *value;
+        }
+        return Err(DrowError::NotElf {
+            path: path.to_string(),
+            magic,
+        });
+    }
+    Ok(ident[4])
+}
+
+#[repr(C)]
+#[derive(Clone)]
+pub struct Elf32Header {
+    pub e_ident: [u8; IDENT_SIZE],
+    pub e_type: u16,
+    pub e_machine: u16,
+    pub e_version: u32,
+    pub e_entry: u32,
+    pub e_program_header_offset: u32,
+    pub e_section_header_offset: u32,
+    pub e_flags: u32,
+    pub e_elf_header_size: u16,
+    pub e_program_header_entry_size: u16,
+    pub e_program_header_entries: u16,
+    pub e_section_header_entry_size: u16,
+    pub e_section_header_entries: u16,
+    pub e_section_name_string_table_index: u16,
+}
+
+/// Unlike the 64-bit layout, p_flags follows p_memsz.
+#[repr(C)]
+#[derive(Clone)]
+pub struct Elf32ProgramHeader {
+    pub p_type: u32,
+    pub p_offset: u32,
+    pub p_virtual_address: u32,
+    pub p_physical_address: u32,
+    pub p_file_size: u32,
+    pub p_memory_size: u32,
+    pub p_flags: u32,
+    pub p_align: u32,
+}
+
+#[repr(C)]
+#[derive(Clone)]
+pub struct Elf32SectionHeader {
+    pub sh_name: u32,
+    pub sh_type: u32,
+    pub sh_flags: u32,
+    pub sh_virtual_address: u32,
+    pub sh_offset: u32,
+    pub sh_size: u32,
+    pub sh_link: u32,
+    pub sh_info: u32,
+    pub sh_address_align: u32,
+    pub sh_entry_size: u32,
+}
+
+/// Unlike the 64-bit layout, st_value and st_size come before st_info.
+#[repr(C)]
+#[derive(Clone)]
+pub struct Elf32SymbolTableEntry {
+    pub st_name: u32,
+    pub st_value: u32,
+    pub st_size: u32,
+    pub st_info: u8,
+    pub st_other: u8,
+    pub st_section_index: u16,
+}
+
+impl From<&Elf32Header> for Elf64Header {
+    fn from(header: &Elf32Header) -> Elf64Header {
+        Elf64Header {
+            e_ident: header.e_ident,
+            e_type: header.e_type,
+            e_machine: header.e_machine,
+            e_version: header.e_version,
+            e_entry: header.e_entry as u64,
+            e_program_header_offset: header.e_program_header_offset as u64,
+            e_section_header_offset: header.e_section_header_offset as u64,
+            e_flags: header.e_flags,
+            e_elf_header_size: header.e_elf_header_size,
+            e_program_header_entry_size: header.e_program_header_entry_size,
+            e_program_header_entries: header.e_program_header_entries,
+            e_section_header_entry_size: header.e_section_header_entry_size,
+            e_section_header_entries: header.e_section_header_entries,
+            e_section_name_string_table_index: header.e_section_name_string_table_index,
+        }
+    }
+}
+
+impl From<&Elf32ProgramHeader> for Elf64ProgramHeader {
+    fn from(header: &Elf32ProgramHeader) -> Elf64ProgramHeader {
+        Elf64ProgramHeader {
+            p_type: header.p_type,
+            p_flags: header.p_flags,
+            p_offset: header.p_offset as u64,
+            p_virtual_address: header.p_virtual_address as u64,
+            p_physical_address: header.p_physical_address as u64,
+            p_file_size: header.p_file_size as u64,
+            p_memory_size: header.p_memory_size as u64,
+            p_align: header.p_align as u64,
+        }
+    }
+}
+
+impl From<&Elf32SectionHeader> for Elf64SectionHeader {
+    fn from(header: &Elf32SectionHeader) -> Elf64SectionHeader {
+        Elf64SectionHeader {
+            sh_name: header.sh_name,
+            sh_type: header.sh_type,
+            sh_flags: header.sh_flags as u64,
+            sh_virtual_address: header.sh_virtual_address as u64,
+            sh_offset: header.sh_offset as u64,
+            sh_size: header.sh_size as u64,
+            sh_link: header.sh_link,
+            sh_info: header.sh_info,
+            sh_address_align: header.sh_address_align as u64,
+            sh_entry_size: header.sh_entry_size as u64,
+        }
+    }
+}
+
+/// The headers and symbol tables of an ELFCLASS32 file, for inspection only: drow loads 64-bit
+/// files alone, and does not read the relocations or the dynamic section of 32-bit ones.
+#[derive(Clone)]
+pub struct Elf32Metadata {
+    pub file_path: String,
+    pub elf_header: Elf32Header,
+    pub program_headers: Vec<Elf32ProgramHeader>,
+    pub section_headers: Vec<Elf32SectionHeader>,
+    pub interpreter: Option<String>,
+    pub symbol_table: Vec<Elf64ResolvedSymbolTableEntry>,
+    pub dynamic_symbol_table: Vec<Elf64ResolvedSymbolTableEntry>,
+    pub groups: Vec<Elf64SectionGroup>,
+    pub string_tables: StringTableCache,
+}
+
+impl Elf32Metadata {
+    fn check_header(path: &str, header: &Elf32Header) -> Result<(), DrowError> {
+        let header64 = Elf64Header::from(header);
+        Elf64Metadata::check_file_ident(path, &header64)?;
+        if header.e_ident[4] != ELF_CLASS_32 {
+            return Err(DrowError::WrongClass {
+                path: path.to_string(),
+                class: header.e_ident[4],
+            });
+        }
+        Elf64Metadata::check_endian(path, &header64)?;
+        Elf64Metadata::check_version(path, &header64)?;
+        if header.e_elf_header_size as usize != size_of::<Elf32Header>() {
+            return Err(DrowError::Malformed {
+                what: format!(
+                    "ELF32 header size {}, expected {}",
+                    header.e_elf_header_size,
+                    size_of::<Elf32Header>()
+                ),
+                offset: None,
+            });
+        }
+        Elf64Metadata::check_ident_padding(path, &header64);
+        Ok(())
+    }
+
+    fn load_symbol_table<T: Read + Seek>(
+        section_headers: &[Elf64SectionHeader],
+        string_tables: &mut StringTableCache,
+        reader: &mut T,
+        table_type: u32,
+    ) -> Result<Vec<Elf64ResolvedSymbolTableEntry>, DrowError> {
+        let mut result = Vec::new();
+        let mut buffer = Vec::new();
+        for table in section_headers
+            .iter()
+            .filter(|header| header.sh_type == table_type)
+        {
+            let section_string_table =
+                string_tables.get(section_headers, table.sh_link as usize, reader)?;
+            for_each_entry(
+                reader,
+                table.sh_offset,
+                table.sh_size / size_of::<Elf32SymbolTableEntry>() as u64,
+                DEFAULT_READ_CHUNK_SIZE,
+                &mut buffer,
+                |entry: Elf32SymbolTableEntry| {
+                    let symbol_name = section_string_table
+                        .get_lossy(entry.st_name)
+                        .map_err(|err| DrowError::InvalidString {
+                            what: String::from("symbol name"),
+                            source: err,
+                        })?
+                        .into_owned();
+                    result.push(Elf64ResolvedSymbolTableEntry {
+                        symbol_name,
+                        binding: entry.st_info >> 4,
+                        symbol_type: entry.st_info & 0xf,
+                        section_index: entry.st_section_index,
+                        value: entry.st_value as u64,
+                        size: entry.st_size as u64,
+                        version: None,
+                    });
+                    Ok(())
+                },
+            )?;
+        }
+        Ok(result)
+    }
+
+    pub fn load<T: Read + Seek>(
+        file_path: &String,
+        reader: &mut T,
+    ) -> Result<Elf32Metadata, DrowError> {
+        info!("Loading 32-bit file: {}", file_path);
+        let header_buffer = read_segment(reader, 0, size_of::<Elf32Header>() as u64)?;
+        let elf_header: Elf32Header =
+            unsafe { std::ptr::read_unaligned(header_buffer.as_ptr() as *const _) };
+        Elf32Metadata::check_header(file_path, &elf_header)?;
+        let program_headers: Vec<Elf32ProgramHeader> = Elf64Metadata::load_header_table(
+            reader,
+            "program header",
+            elf_header.e_program_header_offset as u64,
+            elf_header.e_program_header_entries as u64,
+            elf_header.e_program_header_entry_size as u64,
+        )?;
+        let section_headers: Vec<Elf32SectionHeader> = Elf64Metadata::load_header_table(
+            reader,
+            "section header",
+            elf_header.e_section_header_offset as u64,
+            elf_header.e_section_header_entries as u64,
+            elf_header.e_section_header_entry_size as u64,
+        )?;
+        let wide_program_headers: Vec<Elf64ProgramHeader> = program_headers
+            .iter()
+            .map(Elf64ProgramHeader::from)
+            .collect();
+        let interpreter = Elf64Metadata::load_interpreter(&wide_program_headers, reader)?;
+        let wide_section_headers: Vec<Elf64SectionHeader> = section_headers
+            .iter()
+            .map(Elf64SectionHeader::from)
+            .collect();
+        let mut string_tables = StringTableCache::new();
+        let symbol_table = Elf32Metadata::load_symbol_table(
+            &wide_section_headers,
+            &mut string_tables,
+            reader,
+            ELF64_SECTION_HEADER_SYMBOL_TABLE,
+        )?;
+        let dynamic_symbol_table = Elf32Metadata::load_symbol_table(
+            &wide_section_headers,
+            &mut string_tables,
+            reader,
+            ELF64_SECTION_HEADER_DYNAMIC_SYMBOL_TABLE,
+        )?;
+        let groups = Elf64SectionGroup::load_all(
+            &wide_section_headers,
+            elf_header.e_section_name_string_table_index as usize,
+            &mut string_tables,
+            reader,
+        )?;
+        string_tables.load_all(&wide_section_headers, reader)?;
+        Ok(Elf32Metadata {
+            file_path: file_path.clone(),
+            elf_header,
+            program_headers,
+            section_headers,
+            interpreter,
+            symbol_table,
+            dynamic_symbol_table,
+            groups,
+            string_tables,
+        })
+    }
+
+    /// The file with its headers widened to the 64-bit layouts, which the printer and the
+    /// summary take. It has no relocations and an empty dynamic section.
+    pub fn to_elf64(&self) -> Elf64Metadata {
+        let program_headers: Vec<Elf64ProgramHeader> = self
+            .program_headers
+            .iter()
+            .map(Elf64ProgramHeader::from)
+            .collect();
+        let tls_segments = Elf64TlsSegment::from_program_headers(&self.file_path, &program_headers);
+        Elf64Metadata {
+            file_path: self.file_path.clone(),
+            elf_header: Elf64Header::from(&self.elf_header),
+            program_headers,
+            section_headers: self
+                .section_headers
+                .iter()
+                .map(Elf64SectionHeader::from)
+                .collect(),
+            symbol_table: self.symbol_table.clone(),
+            dynamic_symbol_table: self.dynamic_symbol_table.clone(),
+            relocations: Vec::new(),
+            malformed_relocations: Vec::new(),
+            dynamic: Elf64Dynamic::default(),
+            interpreter: self.interpreter.clone(),
+            tls_segments,
+            gnu_hash: None,
+            sysv_hash: None,
+            version_requirements: Vec::new(),
+            groups: self.groups.clone(),
+            string_tables: Some(self.string_tables.clone()),
+            string_table_reads: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io;
+
+use crate::machine_relocation_type_name;
+use crate::phase::LoaderPhase;
+use crate::string_tables::StrError;
+use crate::versions::MissingVersion;
+
+#[derive(Debug)]
+pub enum DrowError {
+    Io {
+        path: String,
+        source: io::Error,
+    },
+    Read {
+        offset: u64,
+        length: u64,
+        source: io::Error,
+    },
+    NotElf {
+        path: String,
+        magic: [u8; 4],
+    },
+    WrongClass {
+        path: String,
+        class: u8,
+    },
+    WrongEncoding {
+        path: String,
+        encoding: u8,
+    },
+    WrongMachine {
+        path: String,
+        machine: u16,
+    },
+    /// `field` is e_version or the version byte of e_ident, both of which must be 1.
+    WrongVersion {
+        path: String,
+        field: &'static str,
+        version: u32,
+    },
+    WrongHeaderSize {
+        path: String,
+        size: u16,
+    },
+    Malformed {
+        what: String,
+        offset: Option<u64>,
+    },
+    InvalidString {
+        what: String,
+        source: StrError,
+    },
+    UnresolvedLibrary {
+        name: String,
+        trail: Vec<String>,
+    },
+    /// Libraries missing in offline mode, each with the chain of objects requiring it.
+    UnresolvedLibraries {
+        unresolved: Vec<(String, Vec<String>)>,
+    },
+    UnsupportedRelocation {
+        type_: u64,
+        machine: u16,
+        object: String,
+    },
+    MapFailed {
+        address: u64,
+        length: u64,
+        source: io::Error,
+    },
+    Syscall {
+        call: String,
+        source: io::Error,
+    },
+    Edit {
+        path: String,
+        reason: String,
+    },
+    NotLoadable {
+        path: String,
+        reason: String,
+    },
+    NotLoaded {
+        path: String,
+    },
+    InsufficientMemory {
+        what: String,
+        required: u64,
+        available: u64,
+    },
+    Manifest {
+        path: String,
+        reason: String,
+    },
+    /// A file the manifest pins `soname` to, whose SHA-256 digest is not the one listed. The
+    /// digests are in hex.
+    DigestMismatch {
+        manifest: String,
+        soname: String,
+        path: String,
+        actual: String,
+        expected: String,
+    },
+    Bundle {
+        path: String,
+        reason: String,
+    },
+    UnmappedLibraries {
+        manifest: String,
+        names: Vec<String>,
+        trail: Vec<String>,
+    },
+    /// A loader function called from code the loader runs while it holds its registry, like an
+    /// indirect function resolver.
+    Reentered {
+        call: String,
+        phase: LoaderPhase,
+    },
+    /// Symbol versions the objects require that the libraries found for them lack.
+    MissingVersions {
+        missing: Vec<MissingVersion>,
+    },
+}
+
+impl Display for DrowError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DrowError::Io { path, source } => write!(f, "Unable to access {}: {}", path, source),
+            DrowError::Read {
+                offset,
+                length,
+                source,
+            } => write!(
+                f,
+                "Unable to read {} bytes at {:#X}: {}",
+                length, offset, source
+            ),
+            DrowError::NotElf { path, magic } => write!(
+                f,
+                "{} is not an ELF file. {:#04X} {:#04X} {:#04X} {:#04X}",
+                path, magic[0], magic[1], magic[2], magic[3]
+            ),
+            DrowError::WrongClass { path, class } => {
+                write!(f, "{}: ELF64 required, found: {:#04X}", path, class)
+            }
+            DrowError::WrongEncoding { path, encoding } => write!(
+                f,
+                "{}: Little Endian required, found: {:#04X}",
+                path, encoding
+            ),
+            DrowError::WrongMachine { path, machine } => {
+                write!(
+                    f,
+                    "{}: AMD64 or AArch64 expected, found: {:#04X}",
+                    path, machine
+                )
+            }
+            DrowError::WrongVersion {
+                path,
+                field,
+                version,
+            } => write!(f, "{}: {} 1 required, found: {}", path, field, version),
+            DrowError::WrongHeaderSize { path, size } => {
+                write!(f, "{}: ELF header size 64 required, found: {}", path, size)
+            }
+            DrowError::Malformed {
+                what,
+                offset: Some(offset),
+            } => write!(f, "Malformed ELF: {} at {:#X}", what, offset),
+            DrowError::Malformed { what, offset: None } => write!(f, "Malformed ELF: {}", what),
+            DrowError::InvalidString { what, source } => write!(f, "Invalid {}: {}", what, source),
+            DrowError::UnresolvedLibrary { name, trail } => write!(
+                f,
+                "Unable to resolve {} required by {}",
+                name,
+                trail.join(" -> ")
+            ),
+            DrowError::UnresolvedLibraries { unresolved } => {
+                let unresolved: Vec<String> = unresolved
+                    .iter()
+                    .map(|(name, trail)| format!("{} required by {}", name, trail.join(" -> ")))
+                    .collect();
+                write!(f, "Unable to resolve {}", unresolved.join(", "))
+            }
+            DrowError::UnsupportedRelocation {
+                type_,
+                machine,
+                object,
+            } => write!(
+                f,
+                "Unsupported relocation {} ({}) in {}",
+                machine_relocation_type_name(*machine, *type_),
+                type_,
+                object
+            ),
+            DrowError::MapFailed {
+                address,
+                length,
+                source,
+            } => write!(
+                f,
+                "Unable to map {} bytes at {:#X}: {}",
+                length, address, source
+            ),
+            DrowError::Syscall { call, source } => write!(f, "{} failed: {}", call, source),
+            DrowError::Edit { path, reason } => write!(f, "Unable to edit {}: {}", path, reason),
+            DrowError::NotLoadable { path, reason } => {
+                write!(f, "Unable to load {}: {}", path, reason)
+            }
+            DrowError::NotLoaded { path } => write!(f, "{} is not loaded", path),
+            DrowError::InsufficientMemory {
+                what,
+                required,
+                available,
+            } => write!(
+                f,
+                "Loading needs {} bytes of {}, only {} available",
+                required, what, available
+            ),
+            DrowError::Manifest { path, reason } => write!(f, "Manifest {}: {}", path, reason),
+            DrowError::DigestMismatch {
+                manifest,
+                soname,
+                path,
+                actual,
+                expected,
+            } => write!(
+                f,
+                "Manifest {}: {} for {} has SHA-256 {}, expected {}",
+                manifest, path, soname, actual, expected
+            ),
+            DrowError::Bundle { path, reason } => write!(f, "Replay bundle {}: {}", path, reason),
+            DrowError::UnmappedLibraries {
+                manifest,
+                names,
+                trail,
+            } => write!(
+                f,
+                "Manifest {} does not map {} required by {}",
+                manifest,
+                names.join(", "),
+                trail.join(" -> ")
+            ),
+            DrowError::Reentered { call, phase } => write!(
+                f,
+                "{} called while {} on the same thread, as from an indirect function resolver; \
+                 only init functions and the program may call back into the loader",
+                call, phase
+            ),
+            DrowError::MissingVersions { missing } => {
+                let missing: Vec<String> =
+                    missing.iter().map(|missing| missing.describe()).collect();
+                write!(
+                    f,
+                    "Missing symbol versions, the libraries found are older than required \
+                     (--best-effort binds to older versions):\n  {}",
+                    missing.join("\n  ")
+                )
+            }
+        }
+    }
+}
+
+impl Error for DrowError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DrowError::Io { source, .. }
+            | DrowError::Read { source, .. }
+            | DrowError::MapFailed { source, .. }
+            | DrowError::Syscall { source, .. } => Some(source),
+            DrowError::InvalidString { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
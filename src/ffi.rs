@@ -0,0 +1,229 @@
+//! A C ABI for programs embedding drow, declared in `include/drow.h`. A loader from
+//! `drow_loader_new` loads files with `drow_load`, which hands back a handle to look symbols up
+//! in and list the objects of. Strings passed in and out are UTF-8 and NUL terminated; those
+//! passed in stay owned by the caller. Handles belong to their loader and are freed with it.
+//! A function failing returns a status other than `DROW_OK` and leaves a message for
+//! `drow_last_error_message`. A panic does not unwind into the caller, it fails the call with
+//! `DROW_ERROR_PANIC`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::loader::Elf64Loader;
+
+pub const DROW_OK: c_int = 0;
+/// A null pointer or a string that is not UTF-8 was passed.
+pub const DROW_ERROR_INVALID_ARGUMENT: c_int = 1;
+/// drow failed, as the message tells.
+pub const DROW_ERROR_LOAD: c_int = 2;
+/// The symbol looked up is defined nowhere in the scope of the handle.
+pub const DROW_ERROR_NOT_FOUND: c_int = 3;
+pub const DROW_ERROR_PANIC: c_int = 4;
+
+thread_local! {
+    /// The message of the last failed call on this thread, valid until the next one fails.
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+pub struct DrowLoader {
+    loader: Elf64Loader,
+    /// Handles by path. Boxed, as their addresses are what `drow_load` hands back.
+    handles: HashMap<String, Box<DrowHandle>>,
+}
+
+/// An object loaded by `drow_load`.
+pub struct DrowHandle {
+    loader: *const DrowLoader,
+    path: String,
+}
+
+/// Called by `drow_list_objects` with the path and load bias of each object and the pointer it
+/// was given. The path is valid for the duration of the call only.
+pub type DrowObjectCallback =
+    Option<unsafe extern "C" fn(path: *const c_char, base: u64, user_data: *mut c_void)>;
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+/// Runs `call`, turning an error into its status and message and a panic into
+/// `DROW_ERROR_PANIC`.
+fn guarded(call: impl FnOnce() -> Result<(), (c_int, String)>) -> c_int {
+    match panic::catch_unwind(AssertUnwindSafe(call)) {
+        Ok(Ok(())) => DROW_OK,
+        Ok(Err((status, message))) => {
+            set_last_error(message);
+            status
+        }
+        Err(payload) => {
+            let reason = payload
+                .downcast_ref::<&str>()
+                .map(|reason| reason.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| String::from("unknown reason"));
+            set_last_error(format!("drow panicked: {}", reason));
+            DROW_ERROR_PANIC
+        }
+    }
+}
+
+fn invalid(message: &str) -> (c_int, String) {
+    (DROW_ERROR_INVALID_ARGUMENT, message.to_string())
+}
+
+/// The UTF-8 string `pointer` points to, which `name` describes in errors.
+unsafe fn string_argument<'a>(
+    pointer: *const c_char,
+    name: &str,
+) -> Result<&'a str, (c_int, String)> {
+    if pointer.is_null() {
+        return Err(invalid(&format!("{} is null", name)));
+    }
+    CStr::from_ptr(pointer)
+        .to_str()
+        .map_err(|_| invalid(&format!("{} is not UTF-8", name)))
+}
+
+/// A loader searching the system library cache and LD_LIBRARY_PATH, to be freed with
+/// `drow_loader_free`. Null when it cannot be created.
+#[no_mangle]
+pub extern "C" fn drow_loader_new() -> *mut DrowLoader {
+    let mut created = ptr::null_mut();
+    guarded(|| {
+        let loader =
+            Elf64Loader::with_defaults().map_err(|err| (DROW_ERROR_LOAD, err.to_string()))?;
+        created = Box::into_raw(Box::new(DrowLoader {
+            loader,
+            handles: HashMap::new(),
+        }));
+        Ok(())
+    });
+    created
+}
+
+/// Unmaps everything `loader` loaded and frees it with its handles. Null is ignored.
+///
+/// # Safety
+///
+/// `loader` must come from `drow_loader_new` and not be used afterwards, nor any of its handles.
+#[no_mangle]
+pub unsafe extern "C" fn drow_loader_free(loader: *mut DrowLoader) {
+    if !loader.is_null() {
+        guarded(|| {
+            drop(Box::from_raw(loader));
+            Ok(())
+        });
+    }
+}
+
+/// Loads the file at `path`, or the library of that name when it has no '/', with its
+/// dependencies, and stores a handle to it in `handle`. The objects are mapped and relocated,
+/// their init functions are not run. Loading a loaded file takes another reference to it and
+/// hands back the same handle.
+///
+/// # Safety
+///
+/// `loader` must come from `drow_loader_new`, `path` must be a NUL terminated string and
+/// `handle` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn drow_load(
+    loader: *mut DrowLoader,
+    path: *const c_char,
+    handle: *mut *mut DrowHandle,
+) -> c_int {
+    guarded(|| {
+        let loader = loader.as_mut().ok_or_else(|| invalid("loader is null"))?;
+        let path = string_argument(path, "path")?;
+        if handle.is_null() {
+            return Err(invalid("handle is null"));
+        }
+        let path = loader
+            .loader
+            .load_library(path)
+            .map_err(|err| (DROW_ERROR_LOAD, err.to_string()))?;
+        let owner = loader as *const DrowLoader;
+        let loaded = loader.handles.entry(path.clone()).or_insert_with(|| {
+            Box::new(DrowHandle {
+                loader: owner,
+                path,
+            })
+        });
+        *handle = loaded.as_mut() as *mut DrowHandle;
+        Ok(())
+    })
+}
+
+/// Looks `name`, optionally versioned as `name@VERSION`, up in the object of `handle` and then
+/// in its dependencies, breadth first, and stores its address in `address`.
+///
+/// # Safety
+///
+/// `handle` must come from `drow_load` of a loader not freed yet, `name` must be a NUL
+/// terminated string and `address` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn drow_lookup_symbol(
+    handle: *const DrowHandle,
+    name: *const c_char,
+    address: *mut u64,
+) -> c_int {
+    guarded(|| {
+        let handle = handle.as_ref().ok_or_else(|| invalid("handle is null"))?;
+        let name = string_argument(name, "name")?;
+        if address.is_null() {
+            return Err(invalid("address is null"));
+        }
+        let loader = &(*handle.loader).loader;
+        match loader.lookup_symbol_in(&handle.path, name) {
+            Some(found) => {
+                *address = found;
+                Ok(())
+            }
+            None => Err((
+                DROW_ERROR_NOT_FOUND,
+                format!("{} is not defined in the scope of {}", name, handle.path),
+            )),
+        }
+    })
+}
+
+/// Calls `callback` with the object of `handle` and then each of its dependencies, breadth
+/// first, passing `user_data` along.
+///
+/// # Safety
+///
+/// `handle` must come from `drow_load` of a loader not freed yet, and `callback` must be safe
+/// to call with `user_data`.
+#[no_mangle]
+pub unsafe extern "C" fn drow_list_objects(
+    handle: *const DrowHandle,
+    callback: DrowObjectCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    guarded(|| {
+        let handle = handle.as_ref().ok_or_else(|| invalid("handle is null"))?;
+        let callback = callback.ok_or_else(|| invalid("callback is null"))?;
+        for (path, base) in (*handle.loader).loader.object_scope(&handle.path) {
+            let path = CString::new(path).unwrap_or_default();
+            callback(path.as_ptr(), base, user_data);
+        }
+        Ok(())
+    })
+}
+
+/// The message of the last call that failed on the calling thread, null when none did. It stays
+/// valid until another call fails on the thread, and is owned by drow.
+#[no_mangle]
+pub extern "C" fn drow_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map(|message| message.as_ptr())
+            .unwrap_or(ptr::null())
+    })
+}
@@ -0,0 +1,175 @@
+//! `.gnu.hash`: a bloom filter and hash buckets over the exported dynamic symbols, which the
+//! linker places last in the dynamic symbol table, grouped by bucket.
+
+use std::io::{Read, Seek, SeekFrom};
+use std::mem::size_of;
+
+use crate::error::DrowError;
+use crate::string_tables::file_offset;
+use crate::{
+    read_entries, Elf64Dynamic, Elf64ProgramHeader, Elf64ResolvedSymbolTableEntry,
+    Elf64SectionHeader,
+};
+
+pub const ELF64_SECTION_HEADER_GNU_HASH: u32 = 0x6ffffff6;
+
+/// Words of the header: bucket count, first hashed symbol, bloom words and bloom shift.
+const HEADER_WORDS: u64 = 4;
+
+/// The hash of `name` the table is built with, the one of glibc's `dl_new_hash`.
+pub fn gnu_hash(name: &str) -> u32 {
+    name.bytes().fold(5381u32, |hash, byte| {
+        hash.wrapping_mul(33).wrapping_add(byte as u32)
+    })
+}
+
+#[derive(Clone)]
+pub struct Elf64GnuHash {
+    /// Index of the first symbol in the table. The symbols before it are not exported.
+    pub symbol_offset: u32,
+    pub bloom_shift: u32,
+    pub bloom: Vec<u64>,
+    /// Index of the first symbol of each bucket, zero for an empty one.
+    pub buckets: Vec<u32>,
+    /// Hash of each symbol from `symbol_offset` on, the lowest bit set on the last one of its
+    /// bucket.
+    pub chains: Vec<u32>,
+}
+
+impl Elf64GnuHash {
+    /// The table of the file, found by its section or else by DT_GNU_HASH, none when it has
+    /// neither. `symbol_count` is the size of the dynamic symbol table, which the chains reach
+    /// the end of.
+    pub fn load<T: Read + Seek>(
+        section_headers: &[Elf64SectionHeader],
+        program_headers: &[Elf64ProgramHeader],
+        dynamic: &Elf64Dynamic,
+        symbol_count: usize,
+        reader: &mut T,
+    ) -> Result<Option<Elf64GnuHash>, DrowError> {
+        let offset = section_headers
+            .iter()
+            .find(|header| header.sh_type == ELF64_SECTION_HEADER_GNU_HASH)
+            .map(|header| header.sh_offset)
+            .or_else(|| {
+                dynamic
+                    .addresses
+                    .iter()
+                    .find(|(tag, _)| *tag == "DT_GNU_HASH")
+                    .and_then(|(_, address)| file_offset(program_headers, *address))
+            });
+        let offset = match offset {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+        let header: Vec<u32> = read_entries(reader, offset, HEADER_WORDS)?;
+        let (bucket_count, symbol_offset, bloom_size, bloom_shift) =
+            (header[0], header[1], header[2], header[3]);
+        let malformed = |what: String| DrowError::Malformed {
+            what,
+            offset: Some(offset),
+        };
+        if bucket_count == 0 || bloom_size == 0 {
+            return Err(malformed(format!(
+                "GNU hash table has {} buckets and {} bloom words",
+                bucket_count, bloom_size
+            )));
+        }
+        if symbol_offset as usize > symbol_count {
+            return Err(malformed(format!(
+                "GNU hash table starts at symbol {}, past the {} dynamic symbols",
+                symbol_offset, symbol_count
+            )));
+        }
+        let chain_count = (symbol_count - symbol_offset as usize) as u64;
+        let past_end = || {
+            malformed(String::from(
+                "GNU hash table extends past the end of the file",
+            ))
+        };
+        let table_end = |start: u64, count: u64, size: usize| {
+            count
+                .checked_mul(size as u64)
+                .and_then(|length| start.checked_add(length))
+                .ok_or_else(past_end)
+        };
+        let bloom_offset = table_end(offset, HEADER_WORDS, size_of::<u32>())?;
+        let buckets_offset = table_end(bloom_offset, bloom_size as u64, size_of::<u64>())?;
+        let chains_offset = table_end(buckets_offset, bucket_count as u64, size_of::<u32>())?;
+        let end = table_end(chains_offset, chain_count, size_of::<u32>())?;
+        let file_size = reader
+            .seek(SeekFrom::End(0))
+            .map_err(|source| DrowError::Read {
+                offset: 0,
+                length: 0,
+                source,
+            })?;
+        if end > file_size {
+            return Err(past_end());
+        }
+        Ok(Some(Elf64GnuHash {
+            symbol_offset,
+            bloom_shift,
+            bloom: read_entries(reader, bloom_offset, bloom_size as u64)?,
+            buckets: read_entries(reader, buckets_offset, bucket_count as u64)?,
+            chains: read_entries(reader, chains_offset, chain_count)?,
+        }))
+    }
+
+    /// Whether the bloom filter lets a symbol with `hash` through. False means the table has
+    /// none.
+    fn may_contain(&self, hash: u32) -> bool {
+        let word = self.bloom[(hash as usize / 64) % self.bloom.len()];
+        let mask = (1 << (hash % 64)) | (1 << ((hash >> self.bloom_shift) % 64));
+        word & mask == mask
+    }
+
+    /// The first symbol of `symbols`, the dynamic symbol table, named `name` that `accept`
+    /// takes, with its index. The symbols of one name differ only in their versions.
+    pub fn lookup_with<'a>(
+        &self,
+        name: &str,
+        symbols: &'a [Elf64ResolvedSymbolTableEntry],
+        mut accept: impl FnMut(usize, &Elf64ResolvedSymbolTableEntry) -> bool,
+    ) -> Option<(usize, &'a Elf64ResolvedSymbolTableEntry)> {
+        let hash = gnu_hash(name);
+        if !self.may_contain(hash) {
+            return None;
+        }
+        let mut index = self.buckets[hash as usize % self.buckets.len()] as usize;
+        if index < self.symbol_offset as usize {
+            return None;
+        }
+        loop {
+            let chain = *self.chains.get(index - self.symbol_offset as usize)?;
+            if chain | 1 == hash | 1 {
+                let symbol = symbols.get(index)?;
+                if symbol.symbol_name == name && accept(index, symbol) {
+                    return Some((index, symbol));
+                }
+            }
+            if chain & 1 != 0 {
+                return None;
+            }
+            index += 1;
+        }
+    }
+
+    /// The first symbol of `symbols`, the dynamic symbol table, named `name`.
+    pub fn lookup<'a>(
+        &self,
+        name: &str,
+        symbols: &'a [Elf64ResolvedSymbolTableEntry],
+    ) -> Option<&'a Elf64ResolvedSymbolTableEntry> {
+        self.lookup_with(name, symbols, |_, _| true)
+            .map(|(_, symbol)| symbol)
+    }
+
+    /// The exported symbols of `symbols`, the dynamic symbol table.
+    pub fn exported<'a>(
+        &self,
+        symbols: &'a [Elf64ResolvedSymbolTableEntry],
+    ) -> &'a [Elf64ResolvedSymbolTableEntry] {
+        symbols.get(self.symbol_offset as usize..).unwrap_or(&[])
+    }
+}
@@ -0,0 +1,126 @@
+use std::io::{Read, Seek};
+use std::mem::size_of;
+
+use crate::error::DrowError;
+use crate::string_tables::StringTableCache;
+use crate::{read_entries, Elf64SectionHeader, Elf64SymbolTableEntry};
+
+pub const ELF64_SECTION_HEADER_GROUP: u32 = 17;
+pub const GROUP_COMDAT: u32 = 1;
+
+const SYMBOL_TYPE_SECTION: u8 = 3;
+
+/// A `SHT_GROUP` section: sections that are kept or discarded together.
+#[derive(Clone)]
+pub struct Elf64SectionGroup {
+    pub section_index: usize,
+    pub flags: u32,
+    pub signature: String,
+    pub members: Vec<u32>,
+}
+
+impl Elf64SectionGroup {
+    pub fn comdat(&self) -> bool {
+        self.flags & GROUP_COMDAT != 0
+    }
+
+    /// The name of the symbol at `sh_info` in the symbol table at `sh_link`. Section symbols have
+    /// no name of their own and stand for the section they refer to.
+    fn signature<T: Read + Seek>(
+        group: &Elf64SectionHeader,
+        section_headers: &[Elf64SectionHeader],
+        section_names: usize,
+        string_tables: &mut StringTableCache,
+        reader: &mut T,
+    ) -> Result<String, DrowError> {
+        let symbol_table =
+            section_headers
+                .get(group.sh_link as usize)
+                .ok_or_else(|| DrowError::Malformed {
+                    what: format!(
+                        "group symbol table section {} does not exist",
+                        group.sh_link
+                    ),
+                    offset: Some(group.sh_offset),
+                })?;
+        let entry_size = size_of::<Elf64SymbolTableEntry>() as u64;
+        if (group.sh_info as u64 + 1) * entry_size > symbol_table.sh_size {
+            return Err(DrowError::Malformed {
+                what: format!("group signature symbol {} does not exist", group.sh_info),
+                offset: Some(group.sh_offset),
+            });
+        }
+        let symbol: Vec<Elf64SymbolTableEntry> = read_entries(
+            reader,
+            symbol_table.sh_offset + group.sh_info as u64 * entry_size,
+            1,
+        )?;
+        let symbol = &symbol[0];
+        let (table, offset) = match section_headers.get(symbol.st_section_index as usize) {
+            Some(section) if symbol.symbol_type() == SYMBOL_TYPE_SECTION => {
+                (section_names, section.sh_name)
+            }
+            _ => (symbol_table.sh_link as usize, symbol.st_name),
+        };
+        string_tables
+            .get(section_headers, table, reader)?
+            .get_lossy(offset)
+            .map(|name| name.into_owned())
+            .map_err(|source| DrowError::InvalidString {
+                what: String::from("group signature"),
+                source,
+            })
+    }
+
+    pub fn load_all<T: Read + Seek>(
+        section_headers: &[Elf64SectionHeader],
+        section_names: usize,
+        string_tables: &mut StringTableCache,
+        reader: &mut T,
+    ) -> Result<Vec<Elf64SectionGroup>, DrowError> {
+        let mut result = Vec::new();
+        for (section_index, header) in section_headers
+            .iter()
+            .enumerate()
+            .filter(|(_, header)| header.sh_type == ELF64_SECTION_HEADER_GROUP)
+        {
+            let words: Vec<u32> = read_entries(
+                reader,
+                header.sh_offset,
+                header.sh_size / size_of::<u32>() as u64,
+            )?;
+            let (flags, members) = match words.split_first() {
+                Some((flags, members)) => (*flags, members.to_vec()),
+                None => {
+                    return Err(DrowError::Malformed {
+                        what: String::from("group section has no flag word"),
+                        offset: Some(header.sh_offset),
+                    })
+                }
+            };
+            if let Some(member) = members
+                .iter()
+                .find(|member| **member as usize >= section_headers.len())
+            {
+                return Err(DrowError::Malformed {
+                    what: format!("group member section {} does not exist", member),
+                    offset: Some(header.sh_offset),
+                });
+            }
+            let signature = Elf64SectionGroup::signature(
+                header,
+                section_headers,
+                section_names,
+                string_tables,
+                reader,
+            )?;
+            result.push(Elf64SectionGroup {
+                section_index,
+                flags,
+                signature,
+                members,
+            });
+        }
+        Ok(result)
+    }
+}
@@ -0,0 +1,171 @@
+//! Differences between the mapped segments of a loaded object and its file, each explained by
+//! the relocations writing to it. A difference no relocation explains is a loader bug.
+
+use crate::{Elf64Metadata, Elf64ResolvedRelocationAddend, PROGRAM_HEADER_TYPE_LOADABLE};
+
+/// Differing bytes closer than this are one range.
+const MERGE_DISTANCE: usize = 8;
+
+/// A range of bytes of a segment that differ from the file.
+#[derive(Clone, Debug)]
+pub struct ImageDifference {
+    /// Address of the first byte in memory.
+    pub address: u64,
+    pub file_offset: u64,
+    pub before: Vec<u8>,
+    pub after: Vec<u8>,
+    /// The relocations writing to the range, as `type symbol`.
+    pub relocations: Vec<String>,
+    /// Some differing byte of the range is written by no relocation.
+    pub unexplained: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct ObjectImageDiff {
+    pub path: String,
+    pub base: u64,
+    /// The first differences, up to the limit `Elf64Loader::diff_image` was given.
+    pub differences: Vec<ImageDifference>,
+    /// Differences past the limit.
+    pub omitted: usize,
+    /// Differences no relocation explains, including the omitted ones.
+    pub unexplained: usize,
+}
+
+/// Bytes a relocation writes at its offset.
+fn relocation_width(elf_metadata: &Elf64Metadata, rela: &Elf64ResolvedRelocationAddend) -> u64 {
+    if rela.copy() {
+        return elf_metadata
+            .dynamic_symbol_table
+            .get(rela.symbol_index as usize)
+            .map(|symbol| symbol.size)
+            .unwrap_or(0);
+    }
+    rela.width()
+}
+
+/// (start, end, description) of the ranges the relocations of `elf_metadata` write, at link
+/// time addresses, sorted by start.
+fn relocation_targets(elf_metadata: &Elf64Metadata) -> Vec<(u64, u64, String)> {
+    let mut targets: Vec<(u64, u64, String)> = elf_metadata
+        .relocations
+        .iter()
+        .map(|rela| {
+            let name = rela.type_name();
+            let description = if rela.symbol_name.is_empty() {
+                name.to_string()
+            } else {
+                format!("{} {}", name, rela.symbol_name)
+            };
+            (
+                rela.offset,
+                rela.offset + relocation_width(elf_metadata, rela),
+                description,
+            )
+        })
+        .filter(|(start, end, _)| start < end)
+        .collect();
+    targets.sort_by_key(|(start, _, _)| *start);
+    targets
+}
+
+/// Compares the bytes of one segment, `file` as read at `file_offset` and `memory` as mapped at
+/// `address`, `base` being the load bias, and explains them with the relocation `targets`.
+fn diff_segment(
+    targets: &[(u64, u64, String)],
+    base: u64,
+    address: u64,
+    file_offset: u64,
+    file: &[u8],
+    memory: &[u8],
+) -> Vec<ImageDifference> {
+    // Runs of differing bytes less than a word apart are one range, as a relocation leaves
+    // the bytes of its value that did not change.
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for index in (0..file.len()).filter(|index| file[*index] != memory[*index]) {
+        match runs.last_mut() {
+            Some((_, end)) if index < *end + MERGE_DISTANCE => *end = index + 1,
+            _ => runs.push((index, index + 1)),
+        }
+    }
+    let mut differences = Vec::new();
+    for (start, end) in runs.into_iter() {
+        let link_address = |index: usize| address + index as u64 - base;
+        let overlapping: Vec<&(u64, u64, String)> = targets
+            .iter()
+            .take_while(|(target_start, _, _)| *target_start < link_address(end))
+            .filter(|(_, target_end, _)| *target_end > link_address(start))
+            .collect();
+        let explained = (start..end)
+            .filter(|index| file[*index] != memory[*index])
+            .all(|index| {
+                overlapping.iter().any(|(target_start, target_end, _)| {
+                    (*target_start..*target_end).contains(&link_address(index))
+                })
+            });
+        differences.push(ImageDifference {
+            address: address + start as u64,
+            file_offset: file_offset + start as u64,
+            before: file[start..end].to_vec(),
+            after: memory[start..end].to_vec(),
+            relocations: overlapping
+                .iter()
+                .map(|(_, _, description)| description.clone())
+                .collect(),
+            unexplained: !explained,
+        });
+    }
+    differences
+}
+
+/// Compares every loadable segment of `elf_metadata` mapped at `base` with `file`, the whole
+/// file. Only the bytes backed by the file are compared, and only in the readable segments the
+/// loader maps, which leaves out a segment at address zero.
+///
+/// # Safety
+///
+/// The readable segments of `elf_metadata` must be mapped at `base`.
+pub unsafe fn diff_object(
+    elf_metadata: &Elf64Metadata,
+    base: u64,
+    file: &[u8],
+    limit: usize,
+) -> ObjectImageDiff {
+    let targets = relocation_targets(elf_metadata);
+    let mut differences = Vec::new();
+    for header in elf_metadata.program_headers.iter().filter(|header| {
+        header.p_type == PROGRAM_HEADER_TYPE_LOADABLE && header.p_file_size > 0 && header.read()
+    }) {
+        let start = header.p_offset as usize;
+        let end = match start.checked_add(header.p_file_size as usize) {
+            Some(end) if end <= file.len() => end,
+            _ => continue,
+        };
+        let address = base + header.p_virtual_address;
+        let memory = std::slice::from_raw_parts(address as *const u8, end - start);
+        differences.extend(diff_segment(
+            &targets,
+            base,
+            address,
+            header.p_offset,
+            &file[start..end],
+            memory,
+        ));
+    }
+    let unexplained = differences
+        .iter()
+        .filter(|difference| difference.unexplained)
+        .count();
+    // Unexplained differences come first, so the limit never hides them.
+    differences.sort_by_key(|difference| (!difference.unexplained, difference.address));
+    let omitted = differences.len().saturating_sub(limit);
+    differences.truncate(limit);
+    differences.sort_by_key(|difference| difference.address);
+    ObjectImageDiff {
+        path: elf_metadata.file_path.clone(),
+        base,
+        differences,
+        omitted,
+        unexplained,
+    }
+}
@@ -1,45 +1,100 @@
 use std::collections::HashMap;
 use std::fs;
 
+use crate::sysroot::Sysroot;
+
 pub struct LdPathLoader {
     paths: Vec<String>,
     libraries: HashMap<String, String>,
+    sysroot: Option<Sysroot>,
+    /// Searched in every path before the path itself, like `glibc-hwcaps/x86-64-v3`.
+    hwcaps_subdirectories: Vec<String>,
 }
 
 impl LdPathLoader {
     pub fn new(ld_library_path: &str) -> LdPathLoader {
-        let separated_paths: Vec<&str> =
-            ld_library_path.split(":").filter(|p| p.len() > 0).collect();
+        let separated_paths: Vec<&str> = ld_library_path
+            .split(":")
+            .filter(|p| !p.is_empty())
+            .collect();
         LdPathLoader {
             paths: separated_paths.iter().map(|a| a.to_string()).collect(),
             libraries: HashMap::new(),
+            sysroot: None,
+            hwcaps_subdirectories: Vec::new(),
+        }
+    }
+
+    /// Looks up absolute search paths inside `sysroot`.
+    pub fn with_sysroot(mut self, sysroot: Sysroot) -> LdPathLoader {
+        self.sysroot = Some(sysroot);
+        self
+    }
+
+    /// Prefers the libraries of `subdirectories` of each search path, the first found winning.
+    pub fn with_hwcaps(mut self, subdirectories: Vec<String>) -> LdPathLoader {
+        self.hwcaps_subdirectories = subdirectories;
+        self
+    }
+
+    fn host_path(&self, path: &str) -> String {
+        match self.sysroot.as_ref() {
+            Some(sysroot) if path.starts_with('/') => sysroot.resolve(path),
+            _ => path.to_string(),
         }
     }
 
+    /// The search paths as they are found on the host.
+    pub fn directories(&self) -> Vec<String> {
+        self.paths.iter().map(|path| self.host_path(path)).collect()
+    }
+
+    pub fn add_path(&mut self, path: &str) {
+        self.paths.push(path.to_string());
+    }
+
     pub fn get(&mut self, key: &String) -> Option<String> {
-        let mut result = Option::None;
         if let Some(value) = self.libraries.get(key) {
-            result = Option::Some(value.clone());
-        } else {
-            for path in self.paths.iter() {
-                let dir_paths = fs::read_dir(path)
-                    .expect(format!("Unable to read directory {}", path).as_str());
-                for dir_path in dir_paths {
-                    let dir_file = dir_path.unwrap();
-                    let absolute_path = fs::canonicalize(dir_file.path()).unwrap();
-                    if let Some(abs_path) = absolute_path.to_str() {
-                        self.libraries.insert(key.clone(), abs_path.to_string());
-                        if let Some(file_name) = absolute_path.file_name() {
-                            if let Some(file_name_string) = file_name.to_str() {
-                                if key == &file_name_string.to_string() {
-                                    result = Option::Some(abs_path.to_string());
-                                }
-                            }
-                        }
+            return Option::Some(value.clone());
+        }
+        // Missing hwcaps subdirectories are the norm, only missing search paths are reported.
+        let paths: Vec<(String, bool)> = self
+            .paths
+            .iter()
+            .flat_map(|path| {
+                self.hwcaps_subdirectories
+                    .iter()
+                    .map(move |subdirectory| (format!("{}/{}", path, subdirectory), true))
+                    .chain(std::iter::once((path.clone(), false)))
+            })
+            .collect();
+        for (path, hwcaps) in paths.iter() {
+            let dir_paths = match fs::read_dir(self.host_path(path)) {
+                Ok(dir_paths) => dir_paths,
+                Err(_) if *hwcaps => continue,
+                Err(err) => {
+                    warn!("Unable to read directory {}: {}", path, err);
+                    continue;
+                }
+            };
+            for dir_file in dir_paths.flatten() {
+                if dir_file.file_name().to_str() != Some(key.as_str()) {
+                    continue;
+                }
+                let absolute_path = match self.sysroot.as_ref() {
+                    Some(sysroot) if path.starts_with('/') => {
+                        Some(sysroot.resolve(&format!("{}/{}", path, key)))
                     }
+                    _ => fs::canonicalize(dir_file.path())
+                        .ok()
+                        .and_then(|path| path.to_str().map(|path| path.to_string())),
+                };
+                if let Some(abs_path) = absolute_path {
+                    self.libraries.insert(key.clone(), abs_path.clone());
+                    return Option::Some(abs_path);
                 }
             }
         }
-        result
+        Option::None
     }
 }
@@ -0,0 +1,76 @@
+//! ELF parsing and loading as a library.
+//!
+//! The supported API is the `elf` types re-exported at the crate root (`Elf64Metadata` and the
+//! header, symbol and relocation types), `DrowError`, `Elf64Loader` and `DependenciesResolver`
+//! from `loader`, `LibraryCache` from `cache`, `LdPathLoader` from `ld_path_loader`,
+//! `OffsetReader` from `offset_reader` and `Progress` from `progress`.
+//! `Elf64Loader::with_defaults` assembles a loader from the system library cache and
+//! LD_LIBRARY_PATH, and `Elf64Loader::builder` customizes each piece.
+//! With the `ffi` feature, `ffi` exports a C ABI for the same, declared in `include/drow.h`.
+//! Diagnostics go through the leveled macros in `log`. The remaining public modules back the
+//! `drow` binary and may change with it.
+
+#[macro_use]
+pub mod log;
+
+pub mod address_space;
+pub mod auxv;
+pub mod bundle;
+pub mod cache;
+pub mod capabilities;
+pub mod core_file;
+pub mod cpu_features;
+pub mod debuginfo;
+pub mod dependency_graph;
+pub mod dynamic;
+pub mod elf;
+pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod glob;
+pub mod gnu_hash;
+pub mod group;
+pub mod image_diff;
+pub mod ld_path_loader;
+pub mod libc_flavor;
+pub mod loader;
+pub mod manifest;
+pub mod memory_elf;
+pub mod object_rules;
+pub mod offset_reader;
+pub mod pause;
+pub mod phase;
+pub mod printer;
+pub mod progress;
+pub mod search_directories;
+pub mod string_tables;
+pub mod summary;
+pub mod symbol_name;
+pub mod sysroot;
+pub mod sysv_hash;
+pub mod table;
+#[cfg(feature = "testutil")]
+pub mod testutil;
+pub mod tls;
+pub mod versions;
+pub mod writer;
+
+mod consistency;
+mod crash;
+mod crc32;
+mod dl;
+mod memory_limits;
+mod notes;
+mod prelink;
+mod program_identity;
+#[cfg(not(feature = "libc-syscalls"))]
+mod raw_syscall;
+mod sha256;
+mod smaps;
+mod soname;
+mod stubs;
+mod syscall;
+
+pub use crate::dynamic::Elf64Dynamic;
+pub use crate::elf::*;
+pub use crate::error::DrowError;
@@ -0,0 +1,141 @@
+use std::ffi::OsStr;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::path::Path;
+
+use crate::offset_reader::OffsetReader;
+use crate::versions::version_definitions;
+use crate::Elf64Metadata;
+
+const GLIBC_INTERPRETER: &str = "ld-linux-x86-64.so.2";
+const GLIBC_SONAME: &str = "libc.so.6";
+const GLIBC_VERSION_PREFIX: &str = "GLIBC_";
+const MUSL_INTERPRETER_PREFIX: &str = "ld-musl-";
+const MUSL_SONAME: &str = "libc.so";
+
+/// The C library a program is linked against, which decides the loader's libc specific shims.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LibcFlavor {
+    Glibc,
+    Musl,
+    Unknown,
+}
+
+impl LibcFlavor {
+    pub fn parse(value: &str) -> Result<LibcFlavor, String> {
+        match value {
+            "glibc" => Ok(LibcFlavor::Glibc),
+            "musl" => Ok(LibcFlavor::Musl),
+            "none" => Ok(LibcFlavor::Unknown),
+            other => Err(format!(
+                "Unknown libc: {}, expected glibc, musl or none",
+                other
+            )),
+        }
+    }
+
+    fn from_name(name: &str) -> Option<LibcFlavor> {
+        let name = name.rsplit('/').next().unwrap_or(name);
+        if name == GLIBC_SONAME || name == GLIBC_INTERPRETER {
+            Some(LibcFlavor::Glibc)
+        } else if name == MUSL_SONAME || name.starts_with(MUSL_INTERPRETER_PREFIX) {
+            Some(LibcFlavor::Musl)
+        } else {
+            None
+        }
+    }
+
+    /// Glibc defines its symbols under GLIBC_* versions whatever the library is called.
+    fn from_version_definitions(elf_metadata: &Elf64Metadata) -> Option<LibcFlavor> {
+        let mut reader = OffsetReader::open(&elf_metadata.file_path).ok()?;
+        let names = version_definitions(elf_metadata, &mut reader).ok()?;
+        names
+            .iter()
+            .any(|name| name.starts_with(GLIBC_VERSION_PREFIX))
+            .then_some(LibcFlavor::Glibc)
+    }
+
+    fn from_object(elf_metadata: &Elf64Metadata) -> Option<LibcFlavor> {
+        let dynamic = &elf_metadata.dynamic;
+        dynamic
+            .soname
+            .iter()
+            .chain(dynamic.required_libraries.iter())
+            .chain(std::iter::once(&elf_metadata.file_path))
+            .find_map(|name| LibcFlavor::from_name(name))
+            .or_else(|| match dynamic.soname.as_ref() {
+                Some(soname) if soname.starts_with("libc.") => {
+                    LibcFlavor::from_version_definitions(elf_metadata)
+                }
+                _ => None,
+            })
+    }
+
+    /// Classifies a program from its PT_INTERP path, then from the sonames, dependencies and
+    /// version definitions of the objects loaded with it.
+    pub fn detect<'a>(
+        interpreter: Option<&str>,
+        objects: impl IntoIterator<Item = &'a Elf64Metadata>,
+    ) -> LibcFlavor {
+        interpreter
+            .and_then(LibcFlavor::from_name)
+            .or_else(|| objects.into_iter().find_map(LibcFlavor::from_object))
+            .unwrap_or(LibcFlavor::Unknown)
+    }
+
+    /// The C library whose dynamic loader is at `path`, none for a loader drow does not know.
+    pub fn from_interpreter(path: &str) -> Option<LibcFlavor> {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        if name == GLIBC_INTERPRETER {
+            Some(LibcFlavor::Glibc)
+        } else if name.starts_with(MUSL_INTERPRETER_PREFIX) {
+            Some(LibcFlavor::Musl)
+        } else {
+            None
+        }
+    }
+
+    /// The dynamic loader that is left out of the load of a program expecting `interpreter`, the
+    /// glibc one by name for a program without PT_INTERP. The musl one is the C library itself,
+    /// so it is loaded like any other dependency.
+    pub fn left_out_interpreter(self, interpreter: Option<&str>) -> Option<&str> {
+        match self {
+            LibcFlavor::Glibc => Some(interpreter.unwrap_or(GLIBC_INTERPRETER)),
+            _ => None,
+        }
+    }
+
+    /// Whether default symbol versions are recorded as `name@@VERSION`.
+    pub fn versioned_symbols(self) -> bool {
+        self == LibcFlavor::Glibc
+    }
+
+    /// Whether glibc expects `_rtld_global_ro` and `__tunable_get_val` from the dynamic loader.
+    pub fn linker_symbols(self) -> bool {
+        self == LibcFlavor::Glibc
+    }
+}
+
+/// Whether the object at `path` is the dynamic loader `interpreter`: the same file, or a file of
+/// the same name the resolver found in another directory.
+pub fn is_interpreter(path: &str, interpreter: &str) -> bool {
+    let name = |path: &str| Path::new(path).file_name().map(OsStr::to_os_string);
+    if name(path) == name(interpreter) {
+        return true;
+    }
+    match (fs::canonicalize(path), fs::canonicalize(interpreter)) {
+        (Ok(path), Ok(interpreter)) => path == interpreter,
+        _ => false,
+    }
+}
+
+impl Display for LibcFlavor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            LibcFlavor::Glibc => "glibc",
+            LibcFlavor::Musl => "musl",
+            LibcFlavor::Unknown => "unknown",
+        };
+        write!(f, "{}", name)
+    }
+}
@@ -1,20 +1,59 @@
-use libc::{perror, printf, wchar_t};
-use std::collections::{HashMap, HashSet, VecDeque};
-use std::ffi::{CStr, CString};
-use std::fs::File;
-use std::io::BufReader;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io;
+use std::marker::PhantomData;
 use std::mem::size_of;
-use std::{arch, mem, ptr};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+use std::sync::{
+    Arc, Mutex, MutexGuard, OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError,
+};
+use std::time::{Duration, Instant};
+use std::{arch, fs, mem, process, ptr};
 
+use crate::address_space::{AddressSpace, USER_SPACE_END};
+use crate::auxv;
+use crate::cache::{LibraryCache, DEFAULT_CACHE_PATH};
+use crate::capabilities;
+use crate::consistency;
+use crate::cpu_features::{self, CpuFeatures};
+use crate::crash::{self, Crash};
+use crate::dl;
+use crate::error::DrowError;
+use crate::image_diff::{self, ObjectImageDiff};
+use crate::ld_path_loader::LdPathLoader;
+use crate::libc_flavor::{is_interpreter, LibcFlavor};
+use crate::manifest::Manifest;
+use crate::memory_elf::MemoryBackedElf;
+use crate::memory_limits;
+use crate::object_rules::{ObjectRules, ObjectSettings};
+use crate::offset_reader::OffsetReader;
+use crate::pause::PauseMode;
+use crate::phase::{LoaderPhase, PhaseTracker};
+use crate::prelink::{self, Prelink, PrelinkObject, PrelinkWrite};
+use crate::program_identity::ProgramIdentity;
+use crate::progress::Progress;
+use crate::search_directories::SearchDirectories;
+use crate::smaps;
+use crate::soname;
+use crate::string_tables::StringTable;
+use crate::stubs::{StubObject, StubbedImport};
+use crate::symbol_name::{self, SymbolName};
+use crate::sysroot::Sysroot;
+use crate::sysv_hash::sysv_hash;
+use crate::table::Table;
+use crate::tls::{self, StaticTlsArea, ThreadPointerGuard, TlsModule, TlsRegistry};
+use crate::versions::{self, MissingVersion, SymbolVersion};
 use crate::{
-    syscall, Elf64Dynamic, Elf64Metadata, Elf64ProgramHeader, Elf64ResolvedRelocationAddend,
-    Elf64ResolvedSymbolTableEntry, Elf64SectionHeader, LdPathLoader, LibraryCache,
-    ELF64_SECTION_HEADER_NO_BITS, PROGRAM_HEADER_TYPE_LOADABLE, RELOCATION_X86_64_64,
-    RELOCATION_X86_64_COPY, RELOCATION_X86_64_GLOB_DAT, RELOCATION_X86_64_IRELATIV,
-    RELOCATION_X86_64_JUMP_SLOT, RELOCATION_X86_64_RELATIVE, SYMBOL_BINDING_GLOBAL,
-    SYMBOL_TYPE_FUNCTION, SYMBOL_TYPE_OBJECT,
+    syscall, Elf64Metadata, Elf64ProgramHeader, Elf64ResolvedRelocationAddend,
+    Elf64ResolvedSymbolTableEntry, ObjectKind, MACHINE_AARCH64, MACHINE_X86_64,
+    PROGRAM_HEADER_TYPE_INTERPRETER, PROGRAM_HEADER_TYPE_LOADABLE, PROGRAM_HEADER_TYPE_PHDR,
+    RELOCATION_AARCH64_IRELATIVE, RELOCATION_AARCH64_RELATIVE, RELOCATION_X86_64_32,
+    RELOCATION_X86_64_32S, RELOCATION_X86_64_DPTMOD64, RELOCATION_X86_64_DTPOFF64,
+    RELOCATION_X86_64_IRELATIV, RELOCATION_X86_64_RELATIVE, RELOCATION_X86_64_TPOFF64,
+    SHN_ABSOLUTE, SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_FUNCTION, SYMBOL_TYPE_OBJECT,
 };
-
 fn align_address(address: u64, alignment: u64) -> u64 {
     let modulo = address % alignment;
     if modulo > 0 {
@@ -24,7 +63,11 @@ fn align_address(address: u64, alignment: u64) -> u64 {
     }
 }
 
-const DEFAULT_STACK_SIZE: libc::size_t = 1024 * 1000 * 10;
+pub const DEFAULT_STACK_SIZE: libc::size_t = 1024 * 1000 * 10;
+pub const DEFAULT_BASE_ADDRESS: u64 = 0x20000;
+/// Address space one object may span, far beyond any real object but small enough that sizes
+/// near the top of the address space are refused before anything is mapped.
+pub const DEFAULT_MAX_OBJECT_SIZE: u64 = 16 << 30;
 
 struct ProgramStack {
     address: *const libc::c_void,
@@ -37,54 +80,121 @@ extern "C" {
     static __tunable_get_val: u8;
 }
 
-impl ProgramStack {
-    fn allocate_default_size() -> Option<ProgramStack> {
-        ProgramStack::allocate(DEFAULT_STACK_SIZE)
+/// Variables the dynamic loader defines and sets before the program starts.
+struct StartupVariables {
+    /// `__libc_stack_end`, the stack pointer the program starts with.
+    stack_end: AtomicU64,
+    /// `__stack_chk_guard`, the stack protector canary, from the AT_RANDOM bytes.
+    stack_guard: u64,
+}
+
+impl StartupVariables {
+    fn new() -> StartupVariables {
+        // The low byte stays zero, so string functions overflowing a buffer stop at the canary.
+        let stack_guard = auxv::current()
+            .random()
+            .map(|address| unsafe { ptr::read_unaligned(address as *const u64) } & !0xFF)
+            .unwrap_or(0);
+        StartupVariables {
+            stack_end: AtomicU64::new(0),
+            stack_guard,
+        }
     }
 
-    fn allocate(size: libc::size_t) -> Option<ProgramStack> {
-        let mut result = Option::None;
-        unsafe {
-            let ptr: *const libc::c_void = syscall::mmap(
-                0 as *const libc::c_void,
-                size,
-                libc::PROT_READ | libc::PROT_WRITE | libc::PROT_EXEC,
-                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
-                -1,
-                0,
-            );
-            if ptr != libc::MAP_FAILED {
-                println!("Allocated pointer: {:#X}", ptr as usize);
-                result = Option::Some(ProgramStack {
-                    address: ptr,
-                    size,
-                    last_address: (ptr as usize + (size - 1)) as *const libc::c_void,
-                });
-            } else {
-                println!("Mmap failed");
-                unsafe {
-                    let error_location = libc::__errno_location();
-                    perror(error_location as *const libc::c_char);
-                };
-            }
+    fn symbol(name: &str, address: u64) -> Elf64ResolvedSymbolTableEntry {
+        Elf64ResolvedSymbolTableEntry {
+            symbol_name: name.to_string(),
+            binding: SYMBOL_BINDING_GLOBAL,
+            symbol_type: SYMBOL_TYPE_OBJECT,
+            section_index: SHN_ABSOLUTE,
+            value: address,
+            size: size_of::<u64>() as u64,
+            version: None,
         }
-        result
+    }
+}
+
+impl ProgramStack {
+    fn allocate(size: libc::size_t) -> Result<ProgramStack, DrowError> {
+        let ptr = syscall::mmap_checked(
+            std::ptr::null::<libc::c_void>(),
+            size,
+            libc::PROT_READ | libc::PROT_WRITE | libc::PROT_EXEC,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+        .map_err(|errno| DrowError::MapFailed {
+            address: 0,
+            length: size as u64,
+            source: errno.into(),
+        })?;
+        debug!("Allocated pointer: {:#X}", ptr as usize);
+        Ok(ProgramStack {
+            address: ptr,
+            size,
+            last_address: (ptr as usize + (size - 1)) as *const libc::c_void,
+        })
     }
 }
 
 impl Drop for ProgramStack {
     fn drop(&mut self) {
         if !self.address.is_null() {
-            unsafe {
-                syscall::munmap(self.address, self.size);
+            if let Err(errno) = syscall::munmap_checked(self.address, self.size) {
+                warn!(
+                    "munmap({:#X}, {}) of the program stack failed: {}",
+                    self.address as u64, self.size, errno
+                );
             }
         }
     }
 }
 
+// The stack is only reached through the loader that owns it, whichever thread holds it.
+unsafe impl Send for ProgramStack {}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LibraryOrigin {
+    Input,
+    Cache,
+    LdPath,
+    Default,
+    /// Another version of the requested library, found with the versioned soname fallback.
+    Substituted,
+    /// Pinned by the manifest.
+    Manifest,
+    /// Found below the search directories of the offline mode.
+    SearchDirectory,
+    Missing,
+}
+
+/// A file to map, with the paths of its direct dependencies.
+type ResolvedObject = (Arc<Elf64Metadata>, Vec<String>);
+
+/// Searched after the cache and LD_LIBRARY_PATH, like the system directories of ld.so.
+const DEFAULT_LIBRARY_DIRECTORIES: &[&str] = &["/lib64", "/usr/lib64", "/lib", "/usr/lib"];
+
+#[derive(Default)]
 pub struct DependenciesResolver {
-    library_cache: LibraryCache,
+    library_cache: Option<LibraryCache>,
+    cache_path: Option<String>,
     ld_path_loader: Option<LdPathLoader>,
+    sysroot: Option<Sysroot>,
+    fuzzy_soname: bool,
+    substitutions: HashMap<String, Option<String>>,
+    manifest: Option<Manifest>,
+    manifest_fallback: bool,
+    /// Sonames whose manifest entry matched its digest.
+    verified: HashSet<String>,
+    /// Set in offline mode, the only place libraries are searched for.
+    search_directories: Option<SearchDirectories>,
+    /// See `with_hwcaps`.
+    hwcaps_subdirectories: Vec<String>,
+    /// See `with_object_rules`.
+    object_rules: ObjectRules,
+    /// See `with_stubs`.
+    stubs: Vec<String>,
 }
 
 impl DependenciesResolver {
@@ -93,74 +203,544 @@ impl DependenciesResolver {
         ld_path_loader: Option<LdPathLoader>,
     ) -> DependenciesResolver {
         DependenciesResolver {
-            library_cache,
+            library_cache: Some(library_cache),
+            cache_path: None,
+            ld_path_loader,
+            sysroot: None,
+            fuzzy_soname: false,
+            substitutions: HashMap::new(),
+            manifest: None,
+            manifest_fallback: false,
+            verified: HashSet::new(),
+            search_directories: None,
+            hwcaps_subdirectories: Vec::new(),
+            object_rules: ObjectRules::default(),
+            stubs: Vec::new(),
+        }
+    }
+
+    /// Loads the cache at `cache_path` on the first lookup. An unreadable cache is reported and
+    /// treated as empty, leaving resolution to the search paths.
+    pub fn with_cache_path(
+        cache_path: &str,
+        ld_path_loader: Option<LdPathLoader>,
+    ) -> DependenciesResolver {
+        DependenciesResolver {
+            library_cache: None,
+            cache_path: Some(cache_path.to_string()),
             ld_path_loader,
+            sysroot: None,
+            fuzzy_soname: false,
+            substitutions: HashMap::new(),
+            manifest: None,
+            manifest_fallback: false,
+            verified: HashSet::new(),
+            search_directories: None,
+            hwcaps_subdirectories: Vec::new(),
+            object_rules: ObjectRules::default(),
+            stubs: Vec::new(),
         }
     }
 
-    fn resolve_path(&mut self, library: &String) -> Vec<String> {
-        let mut result = Vec::new();
-        if let Some(absolute_paths) = self.library_cache.find(library) {
-            result = absolute_paths.clone();
+    /// Searches for libraries only below `directories`, ignoring the library cache, the search
+    /// paths and the default directories of the host.
+    pub fn offline(directories: &[String]) -> DependenciesResolver {
+        let mut resolver = DependenciesResolver::new(LibraryCache::empty(), None);
+        resolver.search_directories = Some(SearchDirectories::new(directories));
+        resolver
+    }
+
+    pub fn search_directories(&self) -> Option<&SearchDirectories> {
+        self.search_directories.as_ref()
+    }
+
+    /// Resolves every library from `manifest` instead of searching for it. Libraries the manifest
+    /// does not map are searched for only with `fallback`.
+    pub fn with_manifest(mut self, manifest: Manifest, fallback: bool) -> DependenciesResolver {
+        self.manifest = Some(manifest);
+        self.manifest_fallback = fallback;
+        self
+    }
+
+    /// Resolves the cache file, the paths it returns and the default directories inside
+    /// `sysroot`. Search paths are rooted by their `LdPathLoader`.
+    pub fn with_sysroot(mut self, sysroot: Sysroot) -> DependenciesResolver {
+        self.sysroot = Some(sysroot);
+        self
+    }
+
+    pub fn sysroot(&self) -> Option<&Sysroot> {
+        self.sysroot.as_ref()
+    }
+
+    /// When a library is not found under its exact name, falls back to another version of it,
+    /// such as `libfoo.so.3` for `libfoo.so`. Ignored when running in secure mode.
+    pub fn with_fuzzy_soname(mut self, enabled: bool) -> DependenciesResolver {
+        if enabled && auxv::current().secure().unwrap_or(false) {
+            warn!("Running in secure mode, versioned soname fallback is disabled");
         } else {
-            let path = self
-                .ld_path_loader
-                .as_mut()
-                .map(|loader| loader.get(library))
-                .flatten();
-            if let Some(p) = path {
-                result.push(p);
+            self.fuzzy_soname = enabled;
+        }
+        self
+    }
+
+    /// Searches the glibc-hwcaps subdirectories `features` allow in the default directories
+    /// and, like ld.so, in the search paths, before the directories themselves.
+    pub fn with_hwcaps(mut self, features: CpuFeatures) -> DependenciesResolver {
+        self.hwcaps_subdirectories = features.hwcaps_subdirectories();
+        self.ld_path_loader = self
+            .ld_path_loader
+            .map(|loader| loader.with_hwcaps(features.hwcaps_subdirectories()));
+        self
+    }
+
+    /// Loads the file of a matching `replace` rule instead of each required library.
+    pub fn with_object_rules(mut self, rules: ObjectRules) -> DependenciesResolver {
+        self.object_rules = rules;
+        self
+    }
+
+    /// Never searches for the libraries named `sonames`, the loader binds the symbols imported
+    /// from them to stubs instead.
+    pub fn with_stubs(mut self, sonames: Vec<String>) -> DependenciesResolver {
+        self.stubs = sonames;
+        self
+    }
+
+    /// The file an object rule loads instead of `library`, found at `paths` if anywhere.
+    fn replacement(&self, library: &str, paths: &[String]) -> Option<String> {
+        let replacement = self.object_rules.replacement(library, paths)?;
+        info!("Object rule replaces {} with {}", library, replacement);
+        Some(replacement)
+    }
+
+    fn host_path(&self, path: &str) -> String {
+        match self.sysroot.as_ref() {
+            Some(sysroot) => sysroot.resolve(path),
+            None => path.to_string(),
+        }
+    }
+
+    fn library_cache(&mut self) -> &LibraryCache {
+        let cache_path = self.cache_path.as_ref().map(|path| self.host_path(path));
+        self.library_cache.get_or_insert_with(|| match cache_path {
+            Some(path) => LibraryCache::load(&path).unwrap_or_else(|err| {
+                warn!("{}, continuing without the library cache", err);
+                LibraryCache::empty()
+            }),
+            None => LibraryCache::empty(),
+        })
+    }
+
+    fn find_in_default_directories(&self, library: &str) -> Option<String> {
+        DEFAULT_LIBRARY_DIRECTORIES
+            .iter()
+            .flat_map(|directory| {
+                self.hwcaps_subdirectories
+                    .iter()
+                    .map(move |subdirectory| format!("{}/{}", directory, subdirectory))
+                    .chain(std::iter::once(directory.to_string()))
+            })
+            .map(|directory| self.host_path(&format!("{}/{}", directory, library)))
+            .find(|path| Path::new(path).is_file())
+    }
+
+    fn find_substitute(&mut self, library: &str) -> Option<String> {
+        if let Some(substitute) = self.substitutions.get(library) {
+            return substitute.clone();
+        }
+        let stem = soname::split_version(library).map(|(stem, _)| stem.to_string());
+        let mut candidates: Vec<(String, String)> = match stem.as_ref() {
+            Some(stem) => {
+                let names: Vec<String> = self
+                    .library_cache()
+                    .names()
+                    .filter(|name| name.starts_with(stem.as_str()))
+                    .cloned()
+                    .collect();
+                names
+                    .into_iter()
+                    .flat_map(|name| {
+                        let paths = self
+                            .library_cache()
+                            .find(&name)
+                            .cloned()
+                            .unwrap_or_default();
+                        paths
+                            .into_iter()
+                            .map(|path| (name.clone(), self.host_path(&path)))
+                            .collect::<Vec<(String, String)>>()
+                    })
+                    .collect()
             }
+            None => Vec::new(),
+        };
+        let mut directories = self
+            .ld_path_loader
+            .as_ref()
+            .map(|loader| loader.directories())
+            .unwrap_or_default();
+        directories.extend(
+            DEFAULT_LIBRARY_DIRECTORIES
+                .iter()
+                .map(|directory| self.host_path(directory)),
+        );
+        candidates.extend(soname::directory_candidates(&directories, library));
+        let substitute = soname::best_candidate(library, candidates).map(|candidate| {
+            warn!(
+                "{} not found, substituting another version of it: {} from {}",
+                library, candidate.name, candidate.path
+            );
+            candidate.path
+        });
+        self.substitutions
+            .insert(library.to_string(), substitute.clone());
+        substitute
+    }
+
+    /// The file the manifest maps `library` to, once checked against its digest.
+    fn manifest_path(&mut self, library: &str) -> Result<Option<String>, DrowError> {
+        let manifest = match self.manifest.as_ref() {
+            Some(manifest) => manifest,
+            None => return Ok(None),
+        };
+        let entry = match manifest.get(library) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        if !self.verified.contains(library) {
+            manifest.verify(library, entry)?;
+            self.verified.insert(library.to_string());
+        }
+        Ok(Some(entry.path.clone()))
+    }
+
+    /// Whether libraries missing from the manifest are not searched for.
+    fn manifest_only(&self) -> bool {
+        self.manifest.is_some() && !self.manifest_fallback
+    }
+
+    /// The paths `library` is found at for an object of `machine`, and where they came from.
+    /// Fails when the file the manifest maps it to does not have the digest listed.
+    pub fn resolve_path_with_origin(
+        &mut self,
+        library: &String,
+        machine: u16,
+    ) -> Result<(Vec<String>, LibraryOrigin), DrowError> {
+        match self.manifest_path(library)? {
+            Some(path) => Ok((vec![path], LibraryOrigin::Manifest)),
+            None if self.manifest_only() => Ok((Vec::new(), LibraryOrigin::Missing)),
+            None => Ok(self.search_path_with_origin(library, machine)),
+        }
+    }
+
+    fn search_path_with_origin(
+        &mut self,
+        library: &String,
+        machine: u16,
+    ) -> (Vec<String>, LibraryOrigin) {
+        if let Some(directories) = self.search_directories.as_mut() {
+            return match directories.find(library) {
+                Some(path) => (vec![path], LibraryOrigin::SearchDirectory),
+                None => (Vec::new(), LibraryOrigin::Missing),
+            };
+        }
+        let cached = self.library_cache().find_for_machine(library, machine);
+        if !cached.is_empty() {
+            let paths = cached.iter().map(|path| self.host_path(path)).collect();
+            return (paths, LibraryOrigin::Cache);
+        }
+        let path = self
+            .ld_path_loader
+            .as_mut()
+            .and_then(|loader| loader.get(library));
+        if let Some(p) = path {
+            (vec![p], LibraryOrigin::LdPath)
+        } else if let Some(p) = self.find_in_default_directories(library) {
+            (vec![p], LibraryOrigin::Default)
+        } else if let Some(p) = self
+            .fuzzy_soname
+            .then(|| self.find_substitute(library))
+            .flatten()
+        {
+            (vec![p], LibraryOrigin::Substituted)
+        } else {
+            (Vec::new(), LibraryOrigin::Missing)
         }
-        result
+    }
+
+    /// Paths of a library required by no object, such as a preload, for a program of `machine`.
+    fn resolve_path(&mut self, library: &String, machine: u16) -> Result<Vec<String>, DrowError> {
+        Ok(self.resolve_path_with_origin(library, machine)?.0)
     }
 
     pub fn resolve_direct_dependencies(
         &mut self,
         elf_metadata: &Elf64Metadata,
-    ) -> Vec<Elf64Metadata> {
-        let mut result = Vec::new();
-        for library in elf_metadata.dynamic.required_libraries.iter() {
-            println!("Required library: {}", library);
-            let absolute_paths = self.resolve_path(library);
-            for path in absolute_paths.iter() {
-                let elf_file = File::open(path.clone()).expect("Unable to open elf file");
-                let mut reader = BufReader::new(elf_file);
-                let metadata = Elf64Metadata::load(path, &mut reader);
-                if let Ok(loaded) = metadata {
-                    result.push(loaded);
+    ) -> Result<Vec<Arc<Elf64Metadata>>, DrowError> {
+        self.resolve_dependencies_with_trail(
+            elf_metadata,
+            std::slice::from_ref(&elf_metadata.file_path),
+        )
+    }
+
+    /// Paths of the direct dependencies of `elf_metadata`, each listed once. In offline mode the
+    /// libraries that are not found are added to `unresolved` so all of them get reported.
+    fn resolve_dependency_paths(
+        &mut self,
+        elf_metadata: &Elf64Metadata,
+        trail: &[String],
+        unresolved: &mut Vec<(String, Vec<String>)>,
+    ) -> Result<Vec<String>, DrowError> {
+        let mut result: Vec<String> = Vec::new();
+        let mut unmapped = Vec::new();
+        for library in elf_metadata.dynamic.unique_required_libraries() {
+            info!("Required library: {}", library);
+            if self.stubs.contains(library) {
+                info!("Stubbing {}", library);
+                continue;
+            }
+            if let Some(path) = self.replacement(library, &[]) {
+                if !result.contains(&path) {
+                    result.push(path);
+                }
+                continue;
+            }
+            let found = match self.manifest_path(library)? {
+                Some(path) => vec![path],
+                None if self.manifest_only() => {
+                    unmapped.push(library.clone());
+                    continue;
+                }
+                None => {
+                    let machine = elf_metadata.elf_header.e_machine;
+                    let candidates = self.search_path_with_origin(library, machine).0;
+                    DependenciesResolver::choose_candidate(library, candidates, elf_metadata)?
+                        .into_iter()
+                        .collect()
+                }
+            };
+            let absolute_paths = match self.replacement(library, &found) {
+                Some(path) => vec![path],
+                None => found,
+            };
+            if absolute_paths.is_empty() && self.search_directories.is_some() {
+                unresolved.push((library.clone(), trail.to_vec()));
+                continue;
+            }
+            if absolute_paths.is_empty() {
+                return Err(DrowError::UnresolvedLibrary {
+                    name: library.clone(),
+                    trail: trail.to_vec(),
+                });
+            }
+            for path in absolute_paths {
+                if !result.contains(&path) {
+                    result.push(path);
                 }
             }
         }
-        result
+        if let Some(manifest) = self.manifest.as_ref().filter(|_| !unmapped.is_empty()) {
+            return Err(DrowError::UnmappedLibraries {
+                manifest: manifest.path.clone(),
+                names: unmapped,
+                trail: trail.to_vec(),
+            });
+        }
+        Ok(result)
     }
 
-    fn add_front<T: Clone>(queue: &mut VecDeque<T>, vector: &Vec<T>) {
-        for entry in vector.iter() {
-            queue.push_front(entry.clone());
+    /// The first of the paths `library` was found at that `needed_by` can load, skipping files
+    /// of another class or machine. Fails with the error of the first candidate when none fits.
+    fn choose_candidate(
+        library: &str,
+        candidates: Vec<String>,
+        needed_by: &Elf64Metadata,
+    ) -> Result<Option<String>, DrowError> {
+        let mut first_error = None;
+        for path in candidates {
+            match DependenciesResolver::check_candidate(&path, needed_by) {
+                Ok(()) => return Ok(Some(path)),
+                Err(err) => {
+                    debug!("Skipping {} for {}: {}", path, library, err);
+                    first_error.get_or_insert(err);
+                }
+            }
+        }
+        first_error.map_or(Ok(None), Err)
+    }
+
+    fn check_candidate(path: &str, needed_by: &Elf64Metadata) -> Result<(), DrowError> {
+        let mut reader = OffsetReader::open(path).map_err(|source| DrowError::Io {
+            path: path.to_string(),
+            source,
+        })?;
+        let header = Elf64Metadata::load_header(path, &mut reader)?;
+        if header.e_machine != needed_by.elf_header.e_machine {
+            return Err(DrowError::WrongMachine {
+                path: path.to_string(),
+                machine: header.e_machine,
+            });
         }
+        Ok(())
+    }
+
+    fn load_dependency(path: &String) -> Result<Arc<Elf64Metadata>, DrowError> {
+        let mut reader = OffsetReader::open(path).map_err(|err| DrowError::Io {
+            path: path.clone(),
+            source: err,
+        })?;
+        Elf64Metadata::load(path, &mut reader).map(Arc::new)
+    }
+
+    fn resolve_dependencies_with_trail(
+        &mut self,
+        elf_metadata: &Elf64Metadata,
+        trail: &[String],
+    ) -> Result<Vec<Arc<Elf64Metadata>>, DrowError> {
+        let mut unresolved = Vec::new();
+        let paths = self.resolve_dependency_paths(elf_metadata, trail, &mut unresolved)?;
+        DependenciesResolver::check_unresolved(unresolved)?;
+        paths
+            .iter()
+            .map(DependenciesResolver::load_dependency)
+            .collect()
     }
 
-    pub fn resolve_in_loading_order(&mut self, elf_metadata: &Elf64Metadata) -> Vec<Elf64Metadata> {
-        let mut libraries: VecDeque<Elf64Metadata> = VecDeque::new();
-        libraries.push_back(elf_metadata.clone());
-        let mut queue = VecDeque::new();
-        let dependencies = self.resolve_direct_dependencies(elf_metadata);
-        DependenciesResolver::add_front(&mut queue, &dependencies);
-        while let Some(entry) = queue.pop_front() {
-            libraries.push_front(entry.clone());
-            let entry_dependencies = self.resolve_direct_dependencies(&entry);
-            DependenciesResolver::add_front(&mut queue, &entry_dependencies);
+    fn check_unresolved(mut unresolved: Vec<(String, Vec<String>)>) -> Result<(), DrowError> {
+        match unresolved.len() {
+            0 => Ok(()),
+            1 => {
+                let (name, trail) = unresolved.remove(0);
+                Err(DrowError::UnresolvedLibrary { name, trail })
+            }
+            _ => Err(DrowError::UnresolvedLibraries { unresolved }),
         }
-        let mut result = Vec::new();
-        let mut loaded: HashSet<String> = HashSet::new();
-        for elem in libraries.iter() {
-            if !loaded.contains(&elem.file_path) {
-                loaded.insert(elem.file_path.clone());
-                result.push(elem.clone());
+    }
+
+    /// Appends the nodes reachable from `node` in depth first postorder.
+    fn postorder(node: usize, edges: &[Vec<usize>], visited: &mut [bool], order: &mut Vec<usize>) {
+        visited[node] = true;
+        for child in edges[node].iter() {
+            if !visited[*child] {
+                DependenciesResolver::postorder(*child, edges, visited, order);
             }
         }
-        result
+        order.push(node);
+    }
+
+    /// Returns the file and its dependencies in the order they are mapped and initialized:
+    ///
+    /// - Objects are found breadth first, each DT_NEEDED list in file order, and an object
+    ///   needed several times is kept once.
+    /// - Each object is placed at the length of the longest chain of DT_NEEDED entries leading to
+    ///   it from the file, ignoring entries that close a cycle. Every dependency is then deeper
+    ///   than the objects needing it.
+    /// - The deepest objects come first. Objects at the same depth keep their breadth first
+    ///   order, so siblings stay in DT_NEEDED order, and the file itself comes last.
+    pub fn resolve_in_loading_order(
+        &mut self,
+        elf_metadata: &Arc<Elf64Metadata>,
+    ) -> Result<Vec<Arc<Elf64Metadata>>, DrowError> {
+        Ok(self
+            .resolve_with_dependencies(elf_metadata, &mut None)?
+            .into_iter()
+            .map(|(file, _)| file)
+            .collect())
+    }
+
+    /// Like `resolve_in_loading_order`, with the paths of the direct dependencies of each file.
+    fn resolve_with_dependencies(
+        &mut self,
+        elf_metadata: &Arc<Elf64Metadata>,
+        progress: &mut Option<Box<dyn Progress>>,
+    ) -> Result<Vec<ResolvedObject>, DrowError> {
+        let mut objects: Vec<Arc<Elf64Metadata>> = vec![Arc::clone(elf_metadata)];
+        let mut trails: Vec<Vec<String>> = vec![vec![elf_metadata.file_path.clone()]];
+        let mut edges: Vec<Vec<usize>> = Vec::new();
+        let mut indexes: HashMap<ObjectKey, usize> = HashMap::new();
+        indexes.insert(ObjectKey::new(&elf_metadata.file_path), 0);
+        let mut unresolved = Vec::new();
+        let mut next = 0;
+        while next < objects.len() {
+            let paths =
+                self.resolve_dependency_paths(&objects[next], &trails[next], &mut unresolved)?;
+            let mut children = Vec::new();
+            for path in paths {
+                let key = ObjectKey::new(&path);
+                let index = match indexes.get(&key) {
+                    Some(index) => *index,
+                    None => {
+                        let mut trail = trails[next].clone();
+                        trail.push(path.clone());
+                        objects.push(DependenciesResolver::load_dependency(&path)?);
+                        trails.push(trail);
+                        indexes.insert(key, objects.len() - 1);
+                        objects.len() - 1
+                    }
+                };
+                children.push(index);
+            }
+            edges.push(children);
+            next += 1;
+            if let Some(progress) = progress.as_mut() {
+                progress.resolving(next, objects.len());
+            }
+        }
+        DependenciesResolver::check_unresolved(unresolved)?;
+        let mut visited = vec![false; objects.len()];
+        let mut order = Vec::new();
+        DependenciesResolver::postorder(0, &edges, &mut visited, &mut order);
+        order.reverse();
+        let mut ranks = vec![0; objects.len()];
+        for (rank, node) in order.iter().enumerate() {
+            ranks[*node] = rank;
+        }
+        // In reverse postorder only the edges closing a cycle point backwards.
+        let mut depths = vec![0; objects.len()];
+        for node in order.iter() {
+            for child in edges[*node].iter() {
+                if ranks[*child] > ranks[*node] {
+                    depths[*child] = depths[*child].max(depths[*node] + 1);
+                }
+            }
+        }
+        let mut sequence: Vec<usize> = (0..objects.len()).collect();
+        sequence.sort_by_key(|index| (std::cmp::Reverse(depths[*index]), *index));
+        let dependencies: Vec<Vec<String>> = edges
+            .iter()
+            .map(|children| {
+                children
+                    .iter()
+                    .map(|child| objects[*child].file_path.clone())
+                    .collect()
+            })
+            .collect();
+        let mut objects: Vec<Option<ResolvedObject>> =
+            objects.into_iter().zip(dependencies).map(Some).collect();
+        Ok(sequence
+            .into_iter()
+            .filter_map(|index| objects[index].take())
+            .collect())
+    }
+}
+
+pub trait DescriptorProvider {
+    fn open(&self, elf_metadata: &Elf64Metadata) -> Result<i32, DrowError>;
+    fn release(&self, file_descriptor: i32);
+}
+
+pub struct FileDescriptorProvider;
+
+impl DescriptorProvider for FileDescriptorProvider {
+    fn open(&self, elf_metadata: &Elf64Metadata) -> Result<i32, DrowError> {
+        syscall::open_file(&elf_metadata.file_path)
+    }
+
+    fn release(&self, file_descriptor: i32) {
+        if let Err(errno) = syscall::close_checked(file_descriptor) {
+            warn!("close({}) failed: {}", file_descriptor, errno);
+        }
     }
 }
 
@@ -176,69 +756,120 @@ impl MappedMemory {
         base_address: *const libc::c_void,
         file_offset: libc::off_t,
         protection: libc::c_int,
-    ) -> Result<MappedMemory, String> {
-        let ptr: *const libc::c_void = unsafe {
-            syscall::mmap(
-                base_address,
-                size,
-                protection,
-                libc::MAP_FIXED | libc::MAP_PRIVATE,
-                file_descriptor,
-                file_offset,
-            )
-        };
-        if ptr == libc::MAP_FAILED {
-            println!(
-                "fd: {}, size: {}, addr: {:#X}, offset: {:#X}, prot: {}",
-                file_descriptor, size, base_address as u64, file_offset, protection
-            );
-            Result::Err(format!("Unable to map address {:#X}", base_address as u64))
-        } else {
-            Result::Ok(MappedMemory {
-                pointer: ptr,
-                length: size,
-            })
-        }
+    ) -> Result<MappedMemory, DrowError> {
+        let ptr = syscall::mmap_checked(
+            base_address,
+            size,
+            protection,
+            libc::MAP_FIXED | libc::MAP_PRIVATE,
+            file_descriptor,
+            file_offset,
+        )
+        .map_err(|errno| DrowError::MapFailed {
+            address: base_address as u64,
+            length: size as u64,
+            source: errno.into(),
+        })?;
+        Result::Ok(MappedMemory {
+            pointer: ptr,
+            length: size,
+        })
     }
 }
 
 impl Drop for MappedMemory {
     fn drop(&mut self) {
         if !self.pointer.is_null() {
-            unsafe {
-                syscall::munmap(self.pointer, self.length);
+            if let Err(errno) = syscall::munmap_checked(self.pointer, self.length) {
+                warn!(
+                    "munmap({:#X}, {}) failed: {}",
+                    self.pointer as u64, self.length, errno
+                );
             }
         }
     }
 }
 
-const DYNAMIC_LOADER_SO: &str = "ld-linux-x86-64.so.2";
+// The pointer is never dereferenced, only handed back to munmap.
+unsafe impl Send for MappedMemory {}
 
 #[repr(C)]
 struct HandlerArguments {
     entry: u64,
     init_functions: Vec<u64>,
+    /// In load order, run backwards.
+    fini_functions: Vec<u64>,
     last_stack_address: u64,
+    /// The phase of the loader, running init functions while they run.
+    phase: *const PhaseTracker,
+    /// Held before the init functions run, in the process that runs them.
+    pause: Option<PauseMode>,
+    /// Set before the init functions run, ending the static TLS area of the loaded objects.
+    thread_pointer: Option<u64>,
+}
+
+/// The arguments of the running program, whose fini functions are still to run.
+static FINI_ARGUMENTS: AtomicPtr<HandlerArguments> = AtomicPtr::new(ptr::null_mut());
+
+/// Passed to the entry point in rdx, for the C library to register with atexit, so the fini
+/// functions run after the atexit handlers of the program: the fini arrays of the program first,
+/// then those of its dependencies, backwards from the order they were loaded in. Runs once.
+extern "C" fn run_fini_functions() {
+    let args = FINI_ARGUMENTS.swap(ptr::null_mut(), Ordering::SeqCst);
+    if args.is_null() {
+        return;
+    }
+    unsafe {
+        for fini in (*args).fini_functions.iter().rev() {
+            let pointer = *fini as *const ();
+            let function = mem::transmute::<*const (), unsafe extern "C" fn()>(pointer);
+            function();
+        }
+    }
+    let _thread_pointer = ThreadPointerGuard::drow();
+    debug!("FINALIZED SUCCESSFULLY");
 }
 
 unsafe fn run_init_functions(args: *const HandlerArguments) {
+    if let Some(pause) = (*args).pause {
+        if let Err(err) = pause.wait() {
+            warn!("Not pausing any longer: {}", err);
+        }
+    }
+    let phase = &*(*args).phase;
+    phase.set(LoaderPhase::RunningInit);
+    if let Some(thread_pointer) = (*args).thread_pointer {
+        if let Err(err) = tls::enter_loaded_code(thread_pointer) {
+            warn!(
+                "Static TLS is not reachable, the thread pointer is drow's: {}",
+                err
+            );
+        }
+    }
     for init in (*args).init_functions.iter() {
-        let pointer = init.clone() as *const ();
+        let pointer = *init as *const ();
         let function = mem::transmute::<*const (), unsafe extern "C" fn()>(pointer);
         function();
     }
-    println!("INITIALIZED SUCCESSFULLY");
+    // The entry point runs with the thread pointer of the loaded code once the guard is dropped.
+    let _thread_pointer = ThreadPointerGuard::drow();
+    phase.set(LoaderPhase::Ready);
+    debug!("INITIALIZED SUCCESSFULLY");
 }
 
 unsafe fn handle_same_process(args: *const HandlerArguments) {
     run_init_functions(args);
+    FINI_ARGUMENTS.store(args as *mut HandlerArguments, Ordering::SeqCst);
+    // The entry point cannot return here, the program stack has no return address, so the fini
+    // functions only run through rdx.
     arch::asm!(
         "mov rax, {entry}",
         "mov rbx, {stack}",
         "mov rsp, rbx",
         "jmp rax",
         entry = in(reg) (*args).entry,
-        stack = in(reg) (*args).last_stack_address
+        stack = in(reg) (*args).last_stack_address,
+        in("rdx") run_fini_functions as *const () as usize,
     );
 }
 
@@ -249,391 +880,4296 @@ unsafe fn handle(args: *const HandlerArguments) {
         check_stdfiles_vtables (0x02d210)
      */
     run_init_functions(args);
-    let entry_pointer = (*args).entry as *const ();
-    let function = mem::transmute::<*const (), fn()>(entry_pointer);
-    function();
+    FINI_ARGUMENTS.store(args as *mut HandlerArguments, Ordering::SeqCst);
+    arch::asm!(
+        "call {entry}",
+        entry = in(reg) (*args).entry,
+        in("rdx") run_fini_functions as *const () as usize,
+        clobber_abi("C"),
+    );
+    // Returning from the entry point is a normal exit as well.
+    run_fini_functions();
 }
 
-pub struct Elf64Loader {
-    mapped_memory: Vec<MappedMemory>,
-    entry: u64,
-    base_address: u64,
-    global_symbols: HashMap<String, Elf64ResolvedSymbolTableEntry>,
-    default_global_symbols: HashMap<String, Elf64ResolvedSymbolTableEntry>,
-    dependency_resolver: DependenciesResolver,
-    init_functions: Vec<u64>,
+extern "C" fn child_entry(args: *mut libc::c_void) -> libc::c_int {
+    crash::install();
+    unsafe {
+        handle(args as *const HandlerArguments);
+        syscall::exit_group(0)
+    }
 }
 
-impl Elf64Loader {
-    fn map_protection(header: &Elf64ProgramHeader) -> libc::c_int {
-        let mut flags: libc::c_int = 0;
-        if header.execute() {
-            flags = flags | libc::PROT_EXEC;
-        }
-        if header.write() {
-            flags = flags | libc::PROT_WRITE;
-        }
-        if header.read() {
-            flags = flags | libc::PROT_READ;
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChildStatus {
+    Exited(i32),
+    Killed(i32),
+}
+
+impl ChildStatus {
+    /// Status a shell would report for the child, 128 + signal when it was killed.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ChildStatus::Exited(status) => *status,
+            ChildStatus::Killed(signal) => 128 + signal,
         }
-        flags
     }
+}
 
-    fn init_linker_symbols() -> HashMap<String, Elf64ResolvedSymbolTableEntry> {
-        let mut result = HashMap::new();
-        let value = unsafe {
-            let pointer: *const u8 = ptr::addr_of!(_rtld_global_ro) as *const u8;
-            println!("Value at 0xb8: {:#X}", *(pointer.offset(0xb8)));
-            pointer as u64
-        };
-        println!("_rtld_global_ro located at: {:#X}", value);
-        let entry = Elf64ResolvedSymbolTableEntry {
-            symbol_name: String::from("_rtld_global_ro"),
-            binding: SYMBOL_BINDING_GLOBAL,
-            symbol_type: SYMBOL_TYPE_OBJECT,
-            section_index: 0,
-            value,
-            size: size_of::<u8>() as u64,
-        };
-        result.insert(String::from("_rtld_global_ro"), entry);
-        let value = unsafe {
-            let pointer: *const u8 = ptr::addr_of!(__tunable_get_val) as *const u8;
-            pointer as u64
-        };
-        println!("__tunable_get_val located at: {:#X}", value);
-        let entry = Elf64ResolvedSymbolTableEntry {
-            symbol_name: String::from("__tunable_get_val"),
-            binding: SYMBOL_BINDING_GLOBAL,
-            symbol_type: SYMBOL_TYPE_FUNCTION,
-            section_index: 0,
-            value,
-            size: size_of::<u8>() as u64,
-        };
-        result.insert(String::from("__tunable_get_val"), entry);
-        result
-    }
+pub struct ChildHandle<'a> {
+    pid: i32,
+    pidfd: i32,
+    stack: PhantomData<&'a ProgramStack>,
+}
 
-    pub fn new(dependency_resolver: DependenciesResolver) -> Elf64Loader {
-        let linker_symbols = Elf64Loader::init_linker_symbols();
-        Elf64Loader {
-            mapped_memory: Vec::new(),
-            base_address: 0x20000,
-            entry: 0,
-            global_symbols: linker_symbols.clone(),
-            default_global_symbols: linker_symbols,
-            dependency_resolver,
-            init_functions: Vec::new(),
-        }
+impl ChildHandle<'_> {
+    pub fn pid(&self) -> i32 {
+        self.pid
     }
 
-    fn round_page_size(value: u64) -> u64 {
-        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u64;
-        if value % page_size == 0 {
-            value
-        } else {
-            let x = value / page_size;
-            page_size * (x + 1)
+    pub fn wait(&self) -> Result<ChildStatus, DrowError> {
+        let call = || format!("waitid(P_PIDFD, {})", self.pidfd);
+        let info = syscall::waitid_checked(libc::P_PIDFD, self.pidfd as libc::id_t, libc::WEXITED)
+            .map_err(|errno| DrowError::Syscall {
+                call: call(),
+                source: errno.into(),
+            })?;
+        let status = unsafe { info.si_status() };
+        match info.si_code {
+            libc::CLD_EXITED => Ok(ChildStatus::Exited(status)),
+            libc::CLD_KILLED | libc::CLD_DUMPED => Ok(ChildStatus::Killed(status)),
+            code => Err(DrowError::Syscall {
+                call: call(),
+                source: io::Error::other(format!("unexpected si_code {}", code)),
+            }),
         }
     }
+}
 
-    fn update_global_symbols(&mut self, elf_metadata: &Elf64Metadata, offset: u64) {
-        for symbol in elf_metadata.dynamic_symbol_table.iter() {
-            if symbol.global() || symbol.weak() {
-                if !symbol.undefined() {
-                    let mut entry = symbol.clone();
-                    entry.value = entry.value + offset;
-                    if !self.global_symbols.contains_key(&entry.symbol_name) {
-                        self.global_symbols
-                            .insert(entry.symbol_name.clone(), entry.clone());
-                    }
-                    if symbol.symbol_name.contains("@@") {
-                        let v: Vec<&str> = symbol.symbol_name.split("@@").collect();
-                        let name = v[0].to_string();
-                        if !self.default_global_symbols.contains_key(&name) {
-                            self.default_global_symbols.insert(name, entry.clone());
-                        }
-                    }
-                }
-            } else {
-                println!(
-                    "Symbol {} in {} is UNDEFINED",
-                    symbol.symbol_name, elf_metadata.file_path
-                );
-            }
+impl Drop for ChildHandle<'_> {
+    fn drop(&mut self) {
+        if let Err(errno) = syscall::close_checked(self.pidfd) {
+            warn!("close({}) of pidfd failed: {}", self.pidfd, errno);
         }
     }
+}
 
-    fn relocation_symbol_value(rela: &Elf64ResolvedRelocationAddend, offset: u64, value: u64) {
-        unsafe {
-            let destination_pointer = (rela.offset + offset) as *mut u64;
-            println!(
-                "Symbol found: {}. Address value at {:#X} will be changed to {:#X}",
-                rela.symbol_name.clone(),
-                destination_pointer as u64,
-                value
-            );
-            *destination_pointer = value;
+fn spawn_child(
+    stack: &ProgramStack,
+    entry: syscall::ChildEntry,
+    arg: *mut libc::c_void,
+) -> Result<ChildHandle<'_>, DrowError> {
+    let mut pidfd: i32 = -1;
+    let args = syscall::CloneArgs::new()
+        .flags(libc::CLONE_VM as u64)
+        .pidfd(&mut pidfd)
+        .exit_signal(libc::SIGCHLD)
+        .stack(stack.address, stack.size);
+    let pid = unsafe { syscall::clone3_checked(&args, entry, arg) }.map_err(|errno| {
+        DrowError::Syscall {
+            call: format!(
+                "clone3(stack: {:#X}, CLONE_VM|CLONE_PIDFD)",
+                stack.address as u64
+            ),
+            source: errno.into(),
         }
-    }
+    })?;
+    Ok(ChildHandle {
+        pid,
+        pidfd,
+        stack: PhantomData,
+    })
+}
 
-    fn get_symbol(
-        &self,
-        rela: &Elf64ResolvedRelocationAddend,
-    ) -> Option<Elf64ResolvedSymbolTableEntry> {
-        if let Some(symbol) = self.global_symbols.get(&rela.symbol_name) {
-            Option::Some(symbol.clone())
-        } else {
-            let v: Vec<&str> = rela.symbol_name.split("@").collect();
-            let name = String::from(v[0].to_string().trim_matches('\0'));
-            if let Some(symbol) = self.default_global_symbols.get(&name) {
-                Option::Some(symbol.clone())
-            } else {
-                println!("WARN: symbol {} not found", rela.symbol_name);
-                Option::None
-            }
+#[derive(Clone)]
+pub struct MapEntry {
+    pub start: u64,
+    pub end: u64,
+    pub protection: libc::c_int,
+    pub file_offset: Option<u64>,
+    pub object: String,
+    pub role: &'static str,
+    /// The range the object was restricted to, if it could not be placed anywhere.
+    pub constraint: Option<&'static str>,
+}
+
+/// Identifies a file whichever path reached it, like the device and inode pair ld.so compares.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ObjectKey {
+    pub path: PathBuf,
+    pub device: u64,
+    pub inode: u64,
+}
+
+impl ObjectKey {
+    /// Files that cannot be inspected are identified by the path alone.
+    pub fn new(path: &str) -> ObjectKey {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+        let (device, inode) = fs::metadata(&path)
+            .map(|metadata| (metadata.dev(), metadata.ino()))
+            .unwrap_or((0, 0));
+        ObjectKey {
+            path,
+            device,
+            inode,
         }
     }
+}
 
-    fn relocate(&self, elf_metadata: &Elf64Metadata, offset: u64) {
-        for rela in elf_metadata.relocations.iter() {
-            if rela.relocation_type == RELOCATION_X86_64_JUMP_SLOT
-                || rela.relocation_type == RELOCATION_X86_64_GLOB_DAT
-            {
-                if let Some(symbol) = self.get_symbol(rela) {
-                    if symbol.undefined() {
-                        println!("SYMBOL {} UNDEFINED!!", symbol.symbol_name);
-                    }
-                    let mut value = symbol.value;
-                    if symbol.indirect_function() {
-                        let pointer = symbol.value as *const ();
-                        let resolve_function = unsafe {
-                            mem::transmute::<*const (), unsafe extern "C" fn() -> u64>(pointer)
-                        };
-                        let function_pointer = unsafe { resolve_function() };
-                        value = function_pointer;
-                        println!(
-                            "INDIRECT FUNCTION {} RESOLVED: {:#X}",
-                            symbol.symbol_name,
-                            value.clone()
-                        );
+pub struct LoadedObject {
+    pub metadata: Arc<Elf64Metadata>,
+    pub key: ObjectKey,
+    pub base: u64,
+    pub program_headers: ProgramHeaderTable,
+    pub kind: ObjectKind,
+    /// One for each explicit load of the object and each loaded object depending on it.
+    pub references: usize,
+    pub dependencies: Vec<String>,
+    /// How the symbols its relocations refer to were bound, recorded only with the bindings
+    /// debug category.
+    pub bindings: Vec<BindingDecision>,
+    /// The object rules that matched the object, in the order they were given.
+    pub rules: Vec<String>,
+    /// Names, with versions, of the symbols the object refers to without defining them, which
+    /// other objects have to.
+    pub imports: Vec<String>,
+    /// (st_value, st_size, name) of the defined functions, sorted by value. Built on the first
+    /// address lookup.
+    functions: OnceLock<Vec<(u64, u64, String)>>,
+    /// The copy `program_headers` points to when the table is not in a loadable segment, freed
+    /// with the object once it is unmapped.
+    program_header_copy: Option<Box<[Elf64ProgramHeader]>>,
+}
+
+/// How one symbol a relocation refers to was bound, with what led to the definition.
+#[derive(Clone, Debug)]
+pub struct BindingDecision {
+    /// The object whose relocation refers to the symbol.
+    pub requester: String,
+    /// The name looked up, without version.
+    pub symbol: String,
+    pub version: Option<String>,
+    /// Loaded objects looked at in scope order, up to the one defining the symbol, or all of
+    /// them when none does.
+    pub searched: Vec<String>,
+    /// Object of the definition, `drow` for the symbols drow defines itself, none when the
+    /// symbol is unresolved.
+    pub definition: Option<String>,
+    /// Other objects defining the symbol too, which the definition interposes. They come later
+    /// in the scope, unless the definition was preferred for its version.
+    pub interposed: Vec<String>,
+    /// Bound to the default version of the symbol, the reference naming another or none.
+    pub default_version: bool,
+    pub weak: bool,
+    /// The definition is an indirect function, whose resolver ran to get the address.
+    pub indirect_function: bool,
+}
+
+impl BindingDecision {
+    /// The decision in the style of LD_DEBUG=bindings, followed by how it was made.
+    pub fn describe(&self) -> String {
+        let definition = match self.definition.as_ref() {
+            Some(definition) => definition,
+            None => {
+                return format!(
+                    "{}: symbol `{}' not found, searched {}",
+                    self.requester,
+                    self.symbol,
+                    self.searched.join(", ")
+                )
+            }
+        };
+        let version = self
+            .version
+            .as_ref()
+            .map(|version| format!(" [{}]", version))
+            .unwrap_or_default();
+        let mut reasons = Vec::new();
+        if self.interposed.is_empty() {
+            reasons.push(String::from("only definition"));
+        } else {
+            reasons.push(format!("interposes {}", self.interposed.join(", ")));
+        }
+        if self.default_version {
+            reasons.push(String::from("default version"));
+        }
+        if self.weak {
+            reasons.push(String::from("weak"));
+        }
+        if self.indirect_function {
+            reasons.push(String::from("ifunc resolver ran"));
+        }
+        format!(
+            "binding file {} [0] to {} [0]: normal symbol `{}'{}\n  searched {}; {}",
+            self.requester,
+            definition,
+            self.symbol,
+            version,
+            self.searched.join(", "),
+            reasons.join(", ")
+        )
+    }
+}
+
+/// A runtime address mapped back to the object containing it.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SymbolizedAddress {
+    pub object: String,
+    /// Address the object is mapped at.
+    pub base: u64,
+    /// The function containing the address and the offset into it, none in gaps between
+    /// functions.
+    pub symbol: Option<(String, u64)>,
+    /// Role of the mapping, text, data, rodata or bss.
+    pub segment: &'static str,
+}
+
+/// Where the program header table of a loaded object is mapped.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ProgramHeaderTable {
+    pub address: u64,
+    pub count: u16,
+    pub entry_size: u16,
+}
+
+impl ProgramHeaderTable {
+    /// The AT_PHDR, AT_PHENT and AT_PHNUM entries of the auxiliary vector of a program.
+    pub fn auxiliary_vector(&self) -> [(u64, u64); 3] {
+        [
+            (auxv::AT_PHDR, self.address),
+            (auxv::AT_PHENT, self.entry_size as u64),
+            (auxv::AT_PHNUM, self.count as u64),
+        ]
+    }
+}
+
+impl LoadedObject {
+    fn new(
+        metadata: &Arc<Elf64Metadata>,
+        base: u64,
+        program_headers: ProgramHeaderTable,
+    ) -> LoadedObject {
+        LoadedObject {
+            metadata: Arc::clone(metadata),
+            key: ObjectKey::new(&metadata.file_path),
+            base,
+            program_headers,
+            kind: metadata.object_kind(),
+            references: 0,
+            dependencies: Vec::new(),
+            bindings: Vec::new(),
+            rules: Vec::new(),
+            imports: metadata
+                .imports()
+                .map(|symbol| symbol.versioned_name())
+                .collect(),
+            functions: OnceLock::new(),
+            program_header_copy: None,
+        }
+    }
+
+    /// Functions of the symbol table, which holds debug symbols when they were merged, and of
+    /// the dynamic symbol table. Empty ones contain nothing and are left out, then the first
+    /// name of an address wins.
+    fn functions(&self) -> &[(u64, u64, String)] {
+        self.functions.get_or_init(|| {
+            let mut functions: Vec<(u64, u64, String)> = self
+                .metadata
+                .symbol_table
+                .iter()
+                .chain(self.metadata.dynamic_symbol_table.iter())
+                .filter(|symbol| symbol.function() || symbol.indirect_function())
+                .filter(|symbol| !symbol.undefined() && symbol.size != 0)
+                .map(|symbol| (symbol.value, symbol.size, symbol.symbol_name.clone()))
+                .collect();
+            functions.sort_by_key(|function| function.0);
+            functions.dedup_by_key(|function| function.0);
+            functions
+        })
+    }
+
+    /// The function spanning `offset` from the base, and how far into it `offset` is. Of
+    /// functions nested in one another, the one starting last wins.
+    fn function_at(&self, offset: u64) -> Option<(String, u64)> {
+        let functions = self.functions();
+        let end = functions.partition_point(|function| function.0 <= offset);
+        functions[..end]
+            .iter()
+            .rev()
+            .find(|(value, size, _)| offset - value < *size)
+            .map(|(value, _, name)| (name.clone(), offset - value))
+    }
+
+    /// Names of the STB_GNU_UNIQUE and thread-local symbols the object defines.
+    fn pinning_definitions(&self) -> Vec<&str> {
+        self.metadata
+            .dynamic_symbol_table
+            .iter()
+            .filter(|symbol| !symbol.undefined() && (symbol.unique() || symbol.thread_local()))
+            .map(|symbol| symbol_name::base_name(&symbol.symbol_name))
+            .collect()
+    }
+
+    fn references_symbol(&self, name: &str) -> bool {
+        self.metadata
+            .dynamic_symbol_table
+            .iter()
+            .any(|symbol| symbol.undefined() && symbol_name::base_name(&symbol.symbol_name) == name)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct LoadOptions {
+    pub prefault: bool,
+    pub advise_sequential: bool,
+    /// Maps a file from an in-memory copy when its mount forbids executable mappings.
+    pub noexec_fallback: bool,
+    /// Fails the load on relocations that would corrupt memory instead of working around them,
+    /// on non-weak symbols nothing defines, and on section headers that disagree with the
+    /// program headers.
+    pub strict: bool,
+    /// Reports the fault and backtrace of a program killed by a fatal signal.
+    pub crash_handler: bool,
+    /// Loads objects with malformed relocations, leaving those relocations out.
+    pub force: bool,
+    /// Loads objects requiring symbol versions their dependencies lack, binding those symbols
+    /// to the closest older version.
+    pub best_effort: bool,
+    /// Records and prints how every symbol is bound, see `BindingDecision`.
+    pub debug_bindings: bool,
+    /// Prints the implementation every ifunc resolver picked.
+    pub debug_relocations: bool,
+    /// Prints the symbols each object imports as it is loaded.
+    pub debug_symbols: bool,
+    /// What ifunc resolvers reading their arguments and the glibc-hwcaps lookup are told the CPU
+    /// supports. The resolvers of glibc read the host's features instead.
+    pub cpu_features: CpuFeatures,
+    pub stack_size: libc::size_t,
+    pub base_address: u64,
+    /// Largest span of address space the loadable segments of one object may cover.
+    pub max_object_size: u64,
+    /// Stops a loaded program until told to go on, before its init functions run.
+    pub pause_after_load: Option<PauseMode>,
+}
+
+impl Default for LoadOptions {
+    fn default() -> LoadOptions {
+        LoadOptions {
+            prefault: false,
+            advise_sequential: false,
+            noexec_fallback: true,
+            strict: false,
+            crash_handler: true,
+            force: false,
+            best_effort: false,
+            debug_bindings: false,
+            debug_relocations: false,
+            debug_symbols: false,
+            cpu_features: CpuFeatures::native(),
+            stack_size: DEFAULT_STACK_SIZE,
+            base_address: DEFAULT_BASE_ADDRESS,
+            max_object_size: DEFAULT_MAX_OBJECT_SIZE,
+            pause_after_load: None,
+        }
+    }
+}
+
+/// Time spent in each phase of the loads done by one loader, summed over all objects.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct PhaseTimes {
+    pub resolve: Duration,
+    pub map: Duration,
+    pub relocate: Duration,
+}
+
+/// Symbols looked up while relocating, and the lookups saved by looking each symbol of an object
+/// up once for all its relocations.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct SymbolLookups {
+    pub lookups: usize,
+    pub saved: usize,
+}
+
+/// Memory taken by the mappings of one object.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ObjectMemory {
+    pub mapped: u64,
+    pub writable: u64,
+    /// Bytes in RAM, when /proc/self/smaps was read.
+    pub resident: Option<u64>,
+    /// Bytes written to since mapping, relocations included, when /proc/self/smaps was read.
+    pub dirty: Option<u64>,
+}
+
+/// One loaded object in a `LoadReport`.
+#[derive(Clone, Debug)]
+pub struct ObjectReport {
+    pub path: String,
+    pub soname: Option<String>,
+    pub kind: ObjectKind,
+    pub base: u64,
+    /// Size of the object's TLS block, zero without one.
+    pub tls_size: u64,
+    pub program_headers: ProgramHeaderTable,
+    /// Number of relocations of each type, by name.
+    pub relocations: BTreeMap<&'static str, usize>,
+    pub memory: ObjectMemory,
+    /// The object rules that matched the object.
+    pub rules: Vec<String>,
+}
+
+/// What a loader loaded, for tools supervising it.
+#[derive(Clone, Debug)]
+pub struct LoadReport {
+    /// In loading order.
+    pub objects: Vec<ObjectReport>,
+    pub entry: u64,
+    pub static_tls_size: u64,
+    /// Symbols relocations refer to that no object defines, with the object referring to them.
+    pub unresolved_symbols: Vec<(String, String)>,
+    /// Versions required from dependencies that do not define them, see `LoadOptions::best_effort`.
+    pub missing_versions: Vec<MissingVersion>,
+    pub phase_times: PhaseTimes,
+}
+
+/// What `Elf64Loader::shutdown` released.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ShutdownReport {
+    pub objects_unmapped: usize,
+    /// Of the objects, the program stack and the stubs.
+    pub bytes_released: u64,
+    /// Fini functions run.
+    pub handlers_run: usize,
+}
+
+/// Called with every object right after it is mapped and relocated.
+pub type AuditHook = Box<dyn FnMut(&LoadedObject) + Send>;
+
+pub struct Elf64LoaderBuilder {
+    cache_path: String,
+    ld_library_path: Option<String>,
+    search_paths: Vec<String>,
+    sysroot: Option<String>,
+    fuzzy_soname: bool,
+    libc: Option<LibcFlavor>,
+    argv0: Option<String>,
+    options: LoadOptions,
+    audit_hooks: Vec<AuditHook>,
+    progress: Option<Box<dyn Progress>>,
+    prelink_cache: Option<String>,
+    manifest: Option<Manifest>,
+    manifest_fallback: bool,
+    offline_directories: Option<Vec<String>>,
+    object_rules: ObjectRules,
+    stubs: Vec<String>,
+}
+
+impl Elf64LoaderBuilder {
+    pub fn cache_path(mut self, cache_path: &str) -> Elf64LoaderBuilder {
+        self.cache_path = cache_path.to_string();
+        self
+    }
+
+    /// Replaces the LD_LIBRARY_PATH value read from the environment.
+    pub fn ld_library_path(mut self, ld_library_path: Option<&str>) -> Elf64LoaderBuilder {
+        self.ld_library_path = ld_library_path.map(|path| path.to_string());
+        self
+    }
+
+    /// Adds a directory searched after LD_LIBRARY_PATH.
+    pub fn search_path(mut self, path: &str) -> Elf64LoaderBuilder {
+        self.search_paths.push(path.to_string());
+        self
+    }
+
+    /// Resolves libraries against the root filesystem at `sysroot`. The loaded file itself is
+    /// opened as given.
+    pub fn sysroot(mut self, sysroot: &str) -> Elf64LoaderBuilder {
+        self.sysroot = Some(sysroot.to_string());
+        self
+    }
+
+    /// See `DependenciesResolver::with_fuzzy_soname`.
+    pub fn fuzzy_soname(mut self, enabled: bool) -> Elf64LoaderBuilder {
+        self.fuzzy_soname = enabled;
+        self
+    }
+
+    /// Uses the shims of `libc` instead of detecting the C library the program links against.
+    pub fn libc(mut self, libc: LibcFlavor) -> Elf64LoaderBuilder {
+        self.libc = Some(libc);
+        self
+    }
+
+    /// The program name `__progname` and `program_invocation_name` report, the path of the
+    /// loaded program by default.
+    pub fn argv0(mut self, argv0: &str) -> Elf64LoaderBuilder {
+        self.argv0 = Some(argv0.to_string());
+        self
+    }
+
+    pub fn options(mut self, options: LoadOptions) -> Elf64LoaderBuilder {
+        self.options = options;
+        self
+    }
+
+    pub fn base_address(mut self, base_address: u64) -> Elf64LoaderBuilder {
+        self.options.base_address = base_address;
+        self
+    }
+
+    pub fn audit(mut self, hook: impl FnMut(&LoadedObject) + Send + 'static) -> Elf64LoaderBuilder {
+        self.audit_hooks.push(Box::new(hook));
+        self
+    }
+
+    /// See `DependenciesResolver::offline`. Replaces the library cache, LD_LIBRARY_PATH, the
+    /// search paths and the sysroot.
+    pub fn offline(mut self, directories: &[String]) -> Elf64LoaderBuilder {
+        self.offline_directories = Some(directories.to_vec());
+        self
+    }
+
+    /// See `DependenciesResolver::with_manifest`.
+    pub fn manifest(mut self, manifest: Manifest) -> Elf64LoaderBuilder {
+        self.manifest = Some(manifest);
+        self
+    }
+
+    /// Searches for the libraries the manifest does not map instead of failing.
+    pub fn manifest_fallback(mut self, enabled: bool) -> Elf64LoaderBuilder {
+        self.manifest_fallback = enabled;
+        self
+    }
+
+    /// Saves the symbol relocations of each program loaded into an empty loader under
+    /// `directory`, and replays them on the next load of the same files instead of looking the
+    /// symbols up again.
+    pub fn prelink_cache(mut self, directory: &str) -> Elf64LoaderBuilder {
+        self.prelink_cache = Some(directory.to_string());
+        self
+    }
+
+    /// Overrides how the objects matching `rules` are loaded, see `ObjectRules`.
+    pub fn object_rules(mut self, rules: ObjectRules) -> Elf64LoaderBuilder {
+        self.object_rules = rules;
+        self
+    }
+
+    /// Stubs the library `soname` instead of loading it: the functions the objects requiring it
+    /// import trap when called, the variables are zero.
+    pub fn stub(mut self, soname: &str) -> Elf64LoaderBuilder {
+        self.stubs.push(soname.to_string());
+        self
+    }
+
+    /// Reports the progress of every load to `progress`.
+    pub fn progress(mut self, progress: impl Progress + 'static) -> Elf64LoaderBuilder {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    pub fn dependencies_resolver(&self) -> DependenciesResolver {
+        let resolver = match self.offline_directories.as_ref() {
+            Some(directories) => {
+                for directory in directories.iter() {
+                    info!("Search directory: {}", directory);
+                }
+                DependenciesResolver::offline(directories)
+            }
+            None => self.host_resolver(),
+        };
+        let resolver = resolver
+            .with_object_rules(self.object_rules.clone())
+            .with_stubs(self.stubs.clone());
+        match self.manifest.as_ref() {
+            Some(manifest) => {
+                info!("Manifest: {}", manifest.path);
+                resolver.with_manifest(manifest.clone(), self.manifest_fallback)
+            }
+            None => resolver,
+        }
+    }
+
+    /// Searches the library cache, LD_LIBRARY_PATH, the search paths and the default
+    /// directories, inside the sysroot when one is set.
+    fn host_resolver(&self) -> DependenciesResolver {
+        match self.ld_library_path.as_ref() {
+            Some(path) => info!("LD_LIBRARY_PATH: {}", path),
+            None if self.search_paths.is_empty()
+                && (self.manifest.is_none() || self.manifest_fallback) =>
+            {
+                warn!("LD_LIBRARY_PATH not set")
+            }
+            None => {}
+        }
+        let mut ld_path_loader = self
+            .ld_library_path
+            .as_ref()
+            .map(|path| LdPathLoader::new(path));
+        if !self.search_paths.is_empty() {
+            let loader = ld_path_loader.get_or_insert_with(|| LdPathLoader::new(""));
+            for path in self.search_paths.iter() {
+                info!("Search path: {}", path);
+                loader.add_path(path);
+            }
+        }
+        let sysroot = self.sysroot.as_ref().map(|root| Sysroot::new(root));
+        if let Some(sysroot) = sysroot.as_ref() {
+            info!("Sysroot: {}", sysroot.root().display());
+            ld_path_loader = ld_path_loader.map(|loader| loader.with_sysroot(sysroot.clone()));
+        }
+        let resolver = DependenciesResolver::with_cache_path(&self.cache_path, ld_path_loader)
+            .with_fuzzy_soname(self.fuzzy_soname)
+            .with_hwcaps(self.options.cpu_features);
+        match sysroot {
+            Some(sysroot) => resolver.with_sysroot(sysroot),
+            None => resolver,
+        }
+    }
+
+    pub fn build(self) -> Result<Elf64Loader, DrowError> {
+        let dependencies_resolver = self.dependencies_resolver();
+        self.build_with(dependencies_resolver)
+    }
+
+    /// Builds around an existing resolver, keeping the cache and search results it gathered.
+    pub fn build_with(
+        self,
+        dependencies_resolver: DependenciesResolver,
+    ) -> Result<Elf64Loader, DrowError> {
+        let mut loader = Elf64Loader::new(dependencies_resolver);
+        loader.set_options(self.options);
+        loader.state().audit_hooks = self.audit_hooks;
+        loader.state().progress = self.progress;
+        loader.libc_override = self.libc;
+        loader.argv0 = self.argv0;
+        loader.prelink_cache = self.prelink_cache.map(PathBuf::from);
+        loader.object_rules = self.object_rules;
+        loader.stubs = self.stubs;
+        Ok(loader)
+    }
+}
+
+/// Whether a reference to `requested` binds to `definition`, as glibc decides it. An unversioned
+/// reference binds to an unversioned definition or a default version, a versioned one to that
+/// version or an unversioned definition, never to another version.
+fn binds_to(definition: &SymbolName, requested: &SymbolName) -> bool {
+    definition.name == requested.name
+        && match requested.version.as_ref() {
+            None => definition.default,
+            Some(version) => definition
+                .version
+                .as_ref()
+                .map(|other| other == version)
+                .unwrap_or(true),
+        }
+}
+
+/// The object `elf_metadata` requires the version of `requested` from, as its requirement names
+/// it.
+fn version_provider<'a>(
+    elf_metadata: &'a Elf64Metadata,
+    requested: &SymbolName,
+) -> Option<&'a str> {
+    let version = requested.version.as_ref()?;
+    elf_metadata
+        .version_requirements
+        .iter()
+        .find(|requirement| {
+            &requirement.version == version
+                && requirement
+                    .symbols
+                    .iter()
+                    .any(|symbol| symbol_name::base_name(symbol) == requested.name)
+        })
+        .map(|requirement| requirement.file.as_str())
+}
+
+/// One definition of a `VersionedSymbolTable`.
+struct Definition {
+    name: SymbolName,
+    symbol: Elf64ResolvedSymbolTableEntry,
+    /// Soname of the defining object, or its file name, as version requirements name it. Empty
+    /// for the symbols drow defines.
+    object: String,
+    /// Position in scope order, negative for the symbols drow defines.
+    rank: i64,
+}
+
+/// Definitions keyed by name and version hash, the `vna_hash` of the version name, zero for an
+/// unversioned definition. A versioned reference finds its version without walking the other
+/// definitions of the name.
+#[derive(Default)]
+struct VersionedSymbolTable {
+    definitions: HashMap<(String, u32), Vec<Definition>>,
+    /// Version hashes each name is defined with.
+    versions: HashMap<String, Vec<u32>>,
+    next_rank: i64,
+    /// Rank of the last symbol defined ahead of the others.
+    first_rank: i64,
+}
+
+fn version_hash(name: &SymbolName) -> u32 {
+    name.version.as_deref().map(sysv_hash).unwrap_or(0)
+}
+
+impl VersionedSymbolTable {
+    /// Adds `symbol` behind the definitions added so far, or ahead of all of them, unless the
+    /// same definition is already there.
+    fn insert(
+        &mut self,
+        name: SymbolName,
+        symbol: Elf64ResolvedSymbolTableEntry,
+        object: String,
+        ahead: bool,
+    ) {
+        let key = (name.name.clone(), version_hash(&name));
+        if self.definitions.get(&key).is_some_and(|definitions| {
+            definitions
+                .iter()
+                .any(|other| other.name == name && other.symbol.value == symbol.value)
+        }) {
+            return;
+        }
+        let rank = if ahead {
+            self.first_rank -= 1;
+            self.first_rank
+        } else {
+            self.next_rank += 1;
+            self.next_rank
+        };
+        let hashes = self.versions.entry(key.0.clone()).or_default();
+        if !hashes.contains(&key.1) {
+            hashes.push(key.1);
+        }
+        let definitions = self.definitions.entry(key).or_default();
+        let position = definitions.partition_point(|other| other.rank < rank);
+        definitions.insert(
+            position,
+            Definition {
+                name,
+                symbol,
+                object,
+                rank,
+            },
+        );
+    }
+
+    /// The definitions of `name`, whatever their versions, in scope order.
+    fn of_name(&self, name: &str) -> Vec<&Definition> {
+        let mut result: Vec<&Definition> = self
+            .versions
+            .get(name)
+            .into_iter()
+            .flatten()
+            .filter_map(|hash| self.definitions.get(&(name.to_string(), *hash)))
+            .flatten()
+            .collect();
+        result.sort_by_key(|definition| definition.rank);
+        result
+    }
+
+    /// The definitions of the very name and version of `requested`, in scope order.
+    fn exact<'a>(&'a self, requested: &'a SymbolName) -> impl Iterator<Item = &'a Definition> {
+        self.definitions
+            .get(&(requested.name.clone(), version_hash(requested)))
+            .into_iter()
+            .flatten()
+            .filter(move |definition| definition.name.version == requested.version)
+    }
+
+    /// All definitions, in scope order.
+    fn drain(&mut self) -> Vec<Definition> {
+        self.versions.clear();
+        let mut result: Vec<Definition> = self
+            .definitions
+            .drain()
+            .flat_map(|(_, definitions)| definitions)
+            .collect();
+        result.sort_by_key(|definition| definition.rank);
+        result
+    }
+
+    fn retain(&mut self, keep: impl Fn(&Elf64ResolvedSymbolTableEntry) -> bool) {
+        for definitions in self.definitions.values_mut() {
+            definitions.retain(|definition| keep(&definition.symbol));
+        }
+        self.definitions
+            .retain(|_, definitions| !definitions.is_empty());
+        self.versions.clear();
+        for (name, hash) in self.definitions.keys() {
+            self.versions.entry(name.clone()).or_default().push(*hash);
+        }
+    }
+}
+
+/// Symbols relocations and lookups resolve against. The symbols drow defines come first. Then a
+/// versioned reference binds to its version, preferably in the object its requirement names,
+/// then to the default version of the name, then to an unversioned definition. An unversioned
+/// reference binds to the first default or unversioned definition.
+#[derive(Default)]
+struct SymbolScope {
+    global: VersionedSymbolTable,
+    /// Names of the symbols defined by drow rather than by a loaded object.
+    defined: HashSet<String>,
+    versioned: bool,
+}
+
+impl SymbolScope {
+    /// Defines `symbol` ahead of the definitions of the loaded objects.
+    fn define(&mut self, symbol: Elf64ResolvedSymbolTableEntry) {
+        self.defined.insert(symbol.symbol_name.clone());
+        let name = SymbolName::unversioned(&symbol.symbol_name);
+        self.global.insert(name, symbol, String::new(), true);
+    }
+
+    /// Adds the definitions of `elf_metadata`, mapped at `offset`, with the versions its
+    /// `.gnu.version` gives them, none meaning the file has no version table.
+    fn add_object(
+        &mut self,
+        elf_metadata: &Elf64Metadata,
+        versions: &[Option<SymbolVersion>],
+        offset: u64,
+        versioned: bool,
+    ) {
+        let object = elf_metadata.dynamic.soname.clone().unwrap_or_else(|| {
+            Path::new(&elf_metadata.file_path)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        });
+        let exports = elf_metadata
+            .dynamic_symbol_table
+            .iter()
+            .enumerate()
+            .filter(|(_, symbol)| symbol.exported());
+        for (index, symbol) in exports {
+            let mut entry = symbol.clone();
+            entry.value += offset;
+            let name = if versioned {
+                SymbolName::from_table(&symbol.symbol_name, versions, index)
+            } else {
+                SymbolName::unversioned(symbol_name::base_name(&symbol.symbol_name))
+            };
+            self.global.insert(name, entry, object.clone(), false);
+        }
+    }
+
+    /// Adds the definitions of `other` this scope does not have yet, behind its own.
+    fn merge(&mut self, mut other: SymbolScope) {
+        for definition in other.global.drain() {
+            self.global
+                .insert(definition.name, definition.symbol, definition.object, false);
+        }
+    }
+
+    /// The definition `requested` binds to in this scope followed by `pending`. `provider` is
+    /// the object the version of `requested` is required from. Versions are ignored for C
+    /// libraries without symbol versioning.
+    fn lookup<'a>(
+        &'a self,
+        pending: Option<&'a SymbolScope>,
+        requested: &'a SymbolName,
+        provider: Option<&str>,
+    ) -> Option<&'a Definition> {
+        let scopes = || std::iter::once(self).chain(pending);
+        let of_name = || scopes().flat_map(|scope| scope.global.of_name(&requested.name));
+        let first = of_name().next();
+        if !self.versioned || first.is_some_and(|definition| definition.rank < 0) {
+            return first;
+        }
+        if requested.version.is_none() {
+            return of_name().find(|definition| definition.name.default);
+        }
+        let exact: Vec<&Definition> = scopes()
+            .flat_map(|scope| scope.global.exact(requested))
+            .collect();
+        exact
+            .iter()
+            .find(|definition| Some(definition.object.as_str()) == provider)
+            .or_else(|| exact.first())
+            .copied()
+            .or_else(|| {
+                of_name()
+                    .find(|definition| definition.name.version.is_some() && definition.name.default)
+            })
+            .or_else(|| of_name().find(|definition| definition.name.version.is_none()))
+    }
+
+    fn find(
+        &self,
+        pending: Option<&SymbolScope>,
+        requested: &SymbolName,
+    ) -> Option<Elf64ResolvedSymbolTableEntry> {
+        self.lookup(pending, requested, None)
+            .map(|definition| definition.symbol.clone())
+    }
+
+    /// Drops the definitions for which `keep` is false.
+    fn retain(&mut self, keep: impl Fn(&Elf64ResolvedSymbolTableEntry) -> bool) {
+        self.global.retain(keep);
+    }
+}
+
+/// The symbols the relocations of one object refer to, by symbol index.
+#[derive(Default)]
+struct ResolutionTable {
+    symbols: HashMap<u64, Option<Elf64ResolvedSymbolTableEntry>>,
+    /// Addresses of the symbols, indirect functions being resolved on first use.
+    addresses: HashMap<u64, u64>,
+    /// Name, resolver and chosen implementation of each indirect function resolved.
+    resolved_indirect: Vec<(String, u64, u64)>,
+}
+
+impl ResolutionTable {
+    fn symbol(
+        &self,
+        rela: &Elf64ResolvedRelocationAddend,
+    ) -> Option<&Elf64ResolvedSymbolTableEntry> {
+        self.symbols
+            .get(&rela.symbol_index)
+            .and_then(Option::as_ref)
+    }
+
+    fn address(&mut self, rela: &Elf64ResolvedRelocationAddend) -> Option<u64> {
+        let symbol = self.symbols.get(&rela.symbol_index)?.as_ref()?;
+        let resolved_indirect = &mut self.resolved_indirect;
+        Some(*self.addresses.entry(rela.symbol_index).or_insert_with(|| {
+            let address = Elf64Loader::symbol_address(symbol);
+            if symbol.indirect_function() {
+                resolved_indirect.push((symbol.symbol_name.clone(), symbol.value, address));
+            }
+            address
+        }))
+    }
+}
+
+/// The prelink records of the load in progress: the ones to replay, checked against the files
+/// being mapped, and the ones to save for the next load.
+struct PrelinkSession {
+    cache_file: PathBuf,
+    fingerprints: HashMap<String, [u8; 32]>,
+    replay: HashMap<String, PrelinkObject>,
+    recorded: Prelink,
+    replayed: usize,
+}
+
+impl PrelinkSession {
+    /// The records of the object at `path` mapped at `base`. A different base means the other
+    /// records are off as well, so none is replayed from then on.
+    fn replay(&mut self, path: &str, base: u64) -> Option<PrelinkObject> {
+        let object = self.replay.remove(path)?;
+        if object.base != base {
+            warn!(
+                "Prelink cache expected {} at {:#X}, not at {:#X}",
+                path, object.base, base
+            );
+            self.replay.clear();
+            return None;
+        }
+        self.replayed += 1;
+        Some(object)
+    }
+
+    fn record(&mut self, path: &str, base: u64, writes: Vec<PrelinkWrite>) {
+        self.recorded.objects.push(PrelinkObject {
+            path: path.to_string(),
+            fingerprint: self.fingerprints.get(path).copied().unwrap_or_default(),
+            base,
+            writes,
+        });
+    }
+
+    /// Saves the records unless they were all replayed.
+    fn finish(self) {
+        if self.replayed == self.recorded.objects.len() {
+            info!("Replayed all symbol relocations from the prelink cache");
+            return;
+        }
+        match self.recorded.save(&self.cache_file) {
+            Ok(()) => info!("Prelink cache written to {}", self.cache_file.display()),
+            Err(err) => warn!(
+                "Unable to write prelink cache {}: {}",
+                self.cache_file.display(),
+                err
+            ),
+        }
+    }
+}
+
+/// Objects mapped and relocations applied by the current load, for its `Progress`.
+#[derive(Default)]
+struct LoadCounters {
+    objects: usize,
+    relocations: usize,
+}
+
+/// The objects a loader mapped, with everything recorded while mapping them.
+struct LoaderState {
+    prefaulted_pages: Vec<(String, usize)>,
+    mapped_memory: Vec<(String, MappedMemory)>,
+    loaded_objects: Vec<LoadedObject>,
+    memory_layout: Vec<MapEntry>,
+    stack: Option<ProgramStack>,
+    entry: u64,
+    /// Traps and variables standing in for the stubbed libraries, one per load that needed them.
+    stub_objects: Vec<StubObject>,
+    /// What the last program loaded is, which decides whether it can be started.
+    program_kind: Option<(String, ObjectKind)>,
+    /// Libraries to preload as given, resolved once the program they are for is known.
+    pending_preloads: Vec<String>,
+    preloads: Vec<Arc<Elf64Metadata>>,
+    audit_hooks: Vec<AuditHook>,
+    init_functions: Vec<u64>,
+    fini_functions: Vec<u64>,
+    /// Fini functions of the objects whose init functions `open_library` ran in this process,
+    /// in load order, left for `close_library` or `shutdown` to run.
+    pending_fini_functions: Vec<u64>,
+    phase_times: PhaseTimes,
+    symbol_lookups: SymbolLookups,
+    progress: Option<Box<dyn Progress>>,
+    load_counters: LoadCounters,
+    prelink: Option<PrelinkSession>,
+    libc_flavor: LibcFlavor,
+    /// The dynamic loader the first loaded object expects, from PT_INTERP.
+    interpreter: Option<String>,
+    program_identity: Option<ProgramIdentity>,
+    tls_registry: TlsRegistry,
+    /// Definitions of the objects mapped by the running load, published once all of them are
+    /// relocated.
+    pending_symbols: SymbolScope,
+    /// See `LoadOptions::strict`.
+    strict: bool,
+    /// See `LoadOptions::debug_bindings`.
+    debug_bindings: bool,
+    /// See `LoadOptions::debug_relocations`.
+    debug_relocations: bool,
+    /// Decisions made for the object being relocated.
+    bindings: Vec<BindingDecision>,
+    /// Object and name of each symbol a relocation refers to that was not found.
+    unresolved_symbols: Vec<(String, String)>,
+    /// Versions missing from the loaded objects, whose symbols are bound to older versions.
+    missing_versions: Vec<MissingVersion>,
+    /// Versions of the dynamic symbols of each mapped object, by path, empty for objects
+    /// without a version table.
+    symbol_versions: HashMap<String, Vec<Option<SymbolVersion>>>,
+}
+
+impl LoaderState {
+    fn new() -> LoaderState {
+        LoaderState {
+            prefaulted_pages: Vec::new(),
+            mapped_memory: Vec::new(),
+            loaded_objects: Vec::new(),
+            memory_layout: Vec::new(),
+            stack: None,
+            entry: 0,
+            stub_objects: Vec::new(),
+            program_kind: None,
+            pending_preloads: Vec::new(),
+            preloads: Vec::new(),
+            audit_hooks: Vec::new(),
+            init_functions: Vec::new(),
+            fini_functions: Vec::new(),
+            pending_fini_functions: Vec::new(),
+            phase_times: PhaseTimes::default(),
+            symbol_lookups: SymbolLookups::default(),
+            progress: None,
+            load_counters: LoadCounters::default(),
+            prelink: None,
+            libc_flavor: LibcFlavor::Unknown,
+            interpreter: None,
+            program_identity: None,
+            tls_registry: TlsRegistry::new(),
+            pending_symbols: SymbolScope::default(),
+            strict: false,
+            debug_bindings: false,
+            debug_relocations: false,
+            bindings: Vec::new(),
+            unresolved_symbols: Vec::new(),
+            missing_versions: Vec::new(),
+            symbol_versions: HashMap::new(),
+        }
+    }
+
+    /// The machine of the first object loaded, which the libraries loaded by name later are
+    /// found for. The host machine before anything is loaded.
+    fn machine(&self) -> u16 {
+        self.loaded_objects
+            .first()
+            .map_or(MACHINE_X86_64, |object| {
+                object.metadata.elf_header.e_machine
+            })
+    }
+
+    fn symbol_versions(&self, elf_metadata: &Elf64Metadata) -> &[Option<SymbolVersion>] {
+        self.symbol_versions
+            .get(&elf_metadata.file_path)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// The name and version the relocation `rela` of `elf_metadata` refers to.
+    fn requested_symbol(
+        &self,
+        elf_metadata: &Elf64Metadata,
+        rela: &Elf64ResolvedRelocationAddend,
+    ) -> SymbolName {
+        SymbolName::from_table(
+            &rela.symbol_name,
+            self.symbol_versions(elf_metadata),
+            rela.symbol_index as usize,
+        )
+    }
+
+    fn resolve_address(&self, address: u64) -> Option<SymbolizedAddress> {
+        let entry = self
+            .memory_layout
+            .iter()
+            .find(|entry| entry.start <= address && address < entry.end)?;
+        let object = self
+            .loaded_objects
+            .iter()
+            .filter(|object| object.metadata.file_path == entry.object && object.base <= address)
+            .max_by_key(|object| object.base)?;
+        Some(SymbolizedAddress {
+            object: object.metadata.file_path.clone(),
+            base: object.base,
+            symbol: object.function_at(address - object.base),
+            segment: entry.role,
+        })
+    }
+
+    /// The address as `object`function+offset, like a debugger prints it.
+    fn symbolize(&self, address: u64) -> String {
+        let resolved = match self.resolve_address(address) {
+            Some(resolved) => resolved,
+            None => return String::from("unknown"),
+        };
+        let name = Path::new(&resolved.object)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| resolved.object.clone());
+        match resolved.symbol {
+            Some((symbol, offset)) => format!("{}`{}+{:#x}", name, symbol, offset),
+            None => format!("{} {} segment", name, resolved.segment),
+        }
+    }
+
+    fn describe_address(&self, address: u64) -> String {
+        self.loaded_objects
+            .iter()
+            .filter(|object| object.base <= address)
+            .max_by_key(|object| object.base)
+            .map(|object| object.metadata.describe_address(address - object.base))
+            .unwrap_or_else(|| String::from("<unknown>"))
+    }
+
+    /// Makes a write of a symbol relocation of the object mapped at `base`.
+    fn apply_write(&self, symbols: &SymbolScope, write: &PrelinkWrite, base: u64) {
+        match write {
+            PrelinkWrite::Word { offset, value } => unsafe {
+                let destination_pointer = (offset + base) as *mut u64;
+                trace!(
+                    "Address value at {:#X} will be changed to {:#X}",
+                    destination_pointer as u64,
+                    value
+                );
+                *destination_pointer = *value;
+            },
+            PrelinkWrite::Copy {
+                offset,
+                source,
+                size,
+            } => {
+                trace!(
+                    "{} bytes will be copied to {:#X} from {:#X}",
+                    size,
+                    offset + base,
+                    source
+                );
+                unsafe {
+                    libc::memcpy(
+                        (offset + base) as *mut libc::c_void,
+                        *source as *const libc::c_void,
+                        *size as libc::size_t,
+                    );
+                }
+            }
+            PrelinkWrite::Symbol {
+                offset,
+                name,
+                addend,
+            } => match symbols.find(Some(&self.pending_symbols), &SymbolName::parse(name)) {
+                Some(symbol) => {
+                    let value = (Elf64Loader::symbol_address(&symbol) as i64 + addend) as u64;
+                    let word = PrelinkWrite::Word {
+                        offset: *offset,
+                        value,
+                    };
+                    self.apply_write(symbols, &word, base);
+                }
+                None => warn!("Symbol {} not found", name),
+            },
+        }
+    }
+
+    /// The write a symbol relocation makes, when its symbol is found. Symbols defined by drow
+    /// itself are kept by name, their addresses changing with every run of drow.
+    fn symbol_write(
+        &self,
+        symbols: &SymbolScope,
+        table: &mut ResolutionTable,
+        elf_metadata: &Elf64Metadata,
+        rela: &Elf64ResolvedRelocationAddend,
+    ) -> Result<Option<PrelinkWrite>, DrowError> {
+        let symbol = match table.symbol(rela) {
+            Some(symbol) => symbol,
+            None => return Ok(None),
+        };
+        if rela.machine != MACHINE_X86_64 && symbol.indirect_function() {
+            warn!(
+                "{}: not running the resolver of indirect function {}",
+                elf_metadata.file_path, symbol.symbol_name
+            );
+            return Ok(None);
+        }
+        if symbol.undefined() && !rela.copy() {
+            warn!("SYMBOL {} UNDEFINED!!", symbol.symbol_name);
+        }
+        let offset = rela.offset;
+        if rela.copy() {
+            return Ok(Some(PrelinkWrite::Copy {
+                offset,
+                source: symbol.value,
+                size: self.copy_size(elf_metadata, rela, symbol)?,
+            }));
+        }
+        let addend = rela.symbol_addend();
+        if symbols.defined.contains(&symbol.symbol_name) {
+            return Ok(Some(PrelinkWrite::Symbol {
+                offset,
+                name: symbol.symbol_name.clone(),
+                addend,
+            }));
+        }
+        let value = if rela.absolute() {
+            symbol.value
+        } else {
+            match table.address(rela) {
+                Some(address) => address,
+                None => return Ok(None),
+            }
+        };
+        let value = (value as i64 + addend) as u64;
+        Ok(Some(PrelinkWrite::Word { offset, value }))
+    }
+
+    /// Stores the 32-bit S + A of an R_X86_64_32 or R_X86_64_32S relocation of the object mapped
+    /// at `base`, failing when the value does not fit: zero-extended for the former, sign-extended
+    /// for the latter. These are never replayed from the prelink cache, whose words are 64-bit.
+    fn relocate_32(
+        &mut self,
+        symbols: &SymbolScope,
+        table: &mut ResolutionTable,
+        elf_metadata: &Elf64Metadata,
+        rela: &Elf64ResolvedRelocationAddend,
+        base: u64,
+    ) -> Result<(), DrowError> {
+        let symbol_value = if rela.symbol_index == 0 {
+            0
+        } else {
+            if let Entry::Vacant(entry) = table.symbols.entry(rela.symbol_index) {
+                entry.insert(self.get_symbol(symbols, elf_metadata, rela)?);
+            }
+            let defined_by_drow = table
+                .symbol(rela)
+                .map(|symbol| symbols.defined.contains(&symbol.symbol_name))
+                .unwrap_or(false);
+            let address = if defined_by_drow {
+                symbols
+                    .find(
+                        Some(&self.pending_symbols),
+                        &self.requested_symbol(elf_metadata, rela),
+                    )
+                    .map(|symbol| Elf64Loader::symbol_address(&symbol))
+            } else {
+                table.address(rela)
+            };
+            match address {
+                Some(address) => address,
+                None => return Ok(()),
+            }
+        };
+        let value = symbol_value as i128 + rela.addend as i128;
+        let fits = if rela.relocation_type == RELOCATION_X86_64_32 {
+            (0..=u32::MAX as i128).contains(&value)
+        } else {
+            (i32::MIN as i128..=i32::MAX as i128).contains(&value)
+        };
+        if !fits {
+            return Err(DrowError::NotLoadable {
+                path: elf_metadata.file_path.clone(),
+                reason: format!(
+                    "{} relocation of {} at {:#X} overflows, {:#X} does not fit in 32 bits",
+                    rela.type_name(),
+                    if rela.symbol_name.is_empty() {
+                        "<no symbol>"
+                    } else {
+                        rela.symbol_name.as_str()
+                    },
+                    rela.offset,
+                    value
+                ),
+            });
+        }
+        let destination_pointer = (rela.offset + base) as *mut u32;
+        trace!(
+            "32-bit value at {:#X} will be changed to {:#X}",
+            destination_pointer as u64,
+            value as u32
+        );
+        unsafe { ptr::write_unaligned(destination_pointer, value as u32) };
+        Ok(())
+    }
+
+    /// Bytes a COPY relocation of `elf_metadata` copies from the definition `symbol`. An object
+    /// built against an older library may reserve less room than the definition now takes, so
+    /// the copy is clamped to its own symbol's size, or refused when strict.
+    fn copy_size(
+        &self,
+        elf_metadata: &Elf64Metadata,
+        rela: &Elf64ResolvedRelocationAddend,
+        symbol: &Elf64ResolvedSymbolTableEntry,
+    ) -> Result<u64, DrowError> {
+        let reserved = match elf_metadata
+            .dynamic_symbol_table
+            .get(rela.symbol_index as usize)
+        {
+            Some(own) if own.size != symbol.size => own.size,
+            _ => return Ok(symbol.size),
+        };
+        let provider = self
+            .memory_layout
+            .iter()
+            .find(|entry| entry.start <= symbol.value && symbol.value < entry.end)
+            .map(|entry| entry.object.as_str())
+            .unwrap_or("an unknown object");
+        let reason = format!(
+            "COPY relocation of {} reserves {} bytes, but {} defines it with {} bytes",
+            rela.symbol_name, reserved, provider, symbol.size
+        );
+        if self.strict {
+            return Err(DrowError::NotLoadable {
+                path: elf_metadata.file_path.clone(),
+                reason,
+            });
+        }
+        let size = reserved.min(symbol.size);
+        warn!(
+            "{}: {}, copying only {} bytes",
+            elf_metadata.file_path, reason, size
+        );
+        Ok(size)
+    }
+
+    /// The definition the symbol of `rela` binds to. Binding a versioned reference to another
+    /// version of the symbol fails the load when strict.
+    fn get_symbol(
+        &mut self,
+        symbols: &SymbolScope,
+        elf_metadata: &Elf64Metadata,
+        rela: &Elf64ResolvedRelocationAddend,
+    ) -> Result<Option<Elf64ResolvedSymbolTableEntry>, DrowError> {
+        let result = match self.version_fallback(elf_metadata, rela) {
+            Some(symbol) => Some(symbol),
+            None => {
+                let requested = self.requested_symbol(elf_metadata, rela);
+                let provider = version_provider(elf_metadata, &requested);
+                match symbols.lookup(Some(&self.pending_symbols), &requested, provider) {
+                    Some(definition) => {
+                        if definition.name.version.is_some()
+                            && definition.name.version != requested.version
+                        {
+                            self.check_other_version(elf_metadata, &requested, &definition.name)?;
+                        }
+                        Some(definition.symbol.clone())
                     }
-                    Elf64Loader::relocation_symbol_value(rela, offset, value);
+                    None => None,
                 }
             }
-            if rela.relocation_type == RELOCATION_X86_64_64 {
-                if let Some(symbol) = self.get_symbol(rela) {
-                    if symbol.undefined() {
-                        println!("SYMBOL {} UNDEFINED!!", symbol.symbol_name);
+        };
+        if result.is_none() {
+            warn!("Symbol {} not found", rela.symbol_name);
+        }
+        if self.debug_bindings {
+            let decision = self.binding_decision(symbols, elf_metadata, rela, result.as_ref());
+            crate::log::write_category("bindings", format_args!("{}", decision.describe()));
+            self.bindings.push(decision);
+        }
+        Ok(result)
+    }
+
+    /// Fails when strict, as the version of `requested` is not defined and `bound`, the default
+    /// version of the symbol, may not behave as `elf_metadata` expects.
+    fn check_other_version(
+        &self,
+        elf_metadata: &Elf64Metadata,
+        requested: &SymbolName,
+        bound: &SymbolName,
+    ) -> Result<(), DrowError> {
+        let reason = format!(
+            "no loaded object defines {}@{}",
+            requested.name,
+            requested.version.as_deref().unwrap_or_default()
+        );
+        if self.strict {
+            return Err(DrowError::NotLoadable {
+                path: elf_metadata.file_path.clone(),
+                reason,
+            });
+        }
+        warn!("{}: {}, binding {}", elf_metadata.file_path, reason, bound);
+        Ok(())
+    }
+
+    /// The older definition the symbol of `rela` falls back to when the version `elf_metadata`
+    /// requires for it is missing.
+    fn version_fallback(
+        &self,
+        elf_metadata: &Elf64Metadata,
+        rela: &Elf64ResolvedRelocationAddend,
+    ) -> Option<Elf64ResolvedSymbolTableEntry> {
+        let name = symbol_name::base_name(&rela.symbol_name);
+        let (missing, fallback) = self
+            .missing_versions
+            .iter()
+            .filter(|missing| missing.requester == elf_metadata.file_path)
+            .find_map(|missing| {
+                missing
+                    .fallbacks
+                    .iter()
+                    .find(|fallback| fallback.symbol == name)
+                    .map(|fallback| (missing, fallback))
+            })?;
+        let provider = self
+            .loaded_objects
+            .iter()
+            .rev()
+            .find(|object| object.metadata.file_path == missing.provider)?;
+        let mut symbol = provider
+            .metadata
+            .dynamic_symbol_table
+            .iter()
+            .find(|symbol| symbol.symbol_name == name && symbol.value == fallback.value)?
+            .clone();
+        symbol.value += provider.base;
+        Some(symbol)
+    }
+
+    /// Works out which loaded objects define the symbol of `rela`, in scope order, to explain
+    /// why `symbol` was the one found.
+    fn binding_decision(
+        &self,
+        symbols: &SymbolScope,
+        elf_metadata: &Elf64Metadata,
+        rela: &Elf64ResolvedRelocationAddend,
+        symbol: Option<&Elf64ResolvedSymbolTableEntry>,
+    ) -> BindingDecision {
+        let requested = self.requested_symbol(elf_metadata, rela);
+        let name = requested.name.as_str();
+        let version = requested.version.clone();
+        let provider = version_provider(elf_metadata, &requested);
+        let bound_version = symbols
+            .lookup(Some(&self.pending_symbols), &requested, provider)
+            .and_then(|definition| definition.name.version.clone());
+        let definers: Vec<&LoadedObject> = self
+            .loaded_objects
+            .iter()
+            .filter(|object| {
+                object
+                    .metadata
+                    .find_dynamic_symbol(name, |_, candidate| {
+                        candidate.global() || candidate.weak()
+                    })
+                    .is_some()
+            })
+            .collect();
+        let drow = symbols.defined.contains(&rela.symbol_name);
+        let winner = symbol.and_then(|symbol| {
+            definers.iter().position(|object| {
+                object
+                    .metadata
+                    .dynamic_symbol_table
+                    .iter()
+                    .any(|candidate| candidate.value + object.base == symbol.value)
+            })
+        });
+        let definition = match (symbol, drow, winner) {
+            (None, _, _) => None,
+            (Some(_), true, _) => Some(String::from("drow")),
+            (Some(_), false, Some(index)) => Some(definers[index].metadata.file_path.clone()),
+            (Some(_), false, None) => Some(String::from("?")),
+        };
+        let paths = self
+            .loaded_objects
+            .iter()
+            .map(|object| object.metadata.file_path.clone());
+        // The definitions of drow come ahead of every object.
+        let searched = match (drow, winner) {
+            (true, _) => Vec::new(),
+            (false, Some(index)) => {
+                let path = &definers[index].metadata.file_path;
+                let position = paths.clone().position(|other| &other == path);
+                paths
+                    .take(position.map(|position| position + 1).unwrap_or(0))
+                    .collect()
+            }
+            (false, None) => paths.collect(),
+        };
+        let interposed = match winner {
+            Some(index) if !drow => definers
+                .iter()
+                .enumerate()
+                .filter(|(other, _)| *other != index)
+                .map(|(_, object)| object.metadata.file_path.clone())
+                .collect(),
+            _ => Vec::new(),
+        };
+        BindingDecision {
+            requester: elf_metadata.file_path.clone(),
+            symbol: name.to_string(),
+            default_version: symbol.is_some()
+                && bound_version.is_some()
+                && bound_version != version,
+            version,
+            searched,
+            definition,
+            interposed,
+            weak: symbol.map(|symbol| symbol.weak()).unwrap_or(false),
+            indirect_function: symbol
+                .map(|symbol| symbol.indirect_function())
+                .unwrap_or(false),
+        }
+    }
+
+    /// The TLS module a relocation refers to and the offset of its symbol in the module block.
+    /// Relocations without a symbol refer to the block of the relocated object.
+    fn tls_target(
+        &self,
+        elf_metadata: &Elf64Metadata,
+        rela: &Elf64ResolvedRelocationAddend,
+    ) -> Option<(&TlsModule, u64)> {
+        let target = if rela.symbol_index == 0 {
+            self.tls_registry
+                .module_of(&elf_metadata.file_path)
+                .map(|module| (module, 0))
+        } else {
+            self.tls_registry.symbol(&rela.symbol_name)
+        };
+        if target.is_none() {
+            warn!(
+                "Thread-local symbol {} of {} not found",
+                rela.symbol_name, elf_metadata.file_path
+            );
+        }
+        target
+    }
+
+    fn relocate_tls(
+        &self,
+        elf_metadata: &Elf64Metadata,
+        rela: &Elf64ResolvedRelocationAddend,
+        offset: u64,
+    ) -> Result<(), DrowError> {
+        let (module, symbol_offset) = match self.tls_target(elf_metadata, rela) {
+            Some(target) => target,
+            None => return Ok(()),
+        };
+        let value = match rela.relocation_type {
+            RELOCATION_X86_64_DPTMOD64 => module.id as i64,
+            RELOCATION_X86_64_DTPOFF64 => symbol_offset as i64 + rela.addend,
+            _ => match module.thread_pointer_offset() {
+                Some(block) => block + symbol_offset as i64 + rela.addend,
+                None => {
+                    return Err(DrowError::NotLoadable {
+                        path: elf_metadata.file_path.clone(),
+                        reason: format!(
+                            "{} uses static TLS of {}, which was loaded after the program",
+                            rela.symbol_name, module.segment.object
+                        ),
+                    })
+                }
+            },
+        };
+        trace!(
+            "{} of {} in TLS module {} set to {:#X}",
+            rela.type_name(),
+            rela.symbol_name,
+            module.id,
+            value
+        );
+        unsafe {
+            *((rela.offset + offset) as *mut i64) = value;
+        }
+        Ok(())
+    }
+
+    /// Prints which implementation the resolver of the indirect function `name` picked for
+    /// `elf_metadata`.
+    fn report_resolution(
+        &self,
+        elf_metadata: &Elf64Metadata,
+        name: &str,
+        resolver: u64,
+        value: u64,
+    ) {
+        crate::log::write_category(
+            "reloc",
+            format_args!(
+                "{}: ifunc {} resolved by {:#x} to {:#x} ({})",
+                elf_metadata.file_path,
+                name,
+                resolver,
+                value,
+                self.symbolize(value)
+            ),
+        );
+    }
+
+    /// Reports the end of a load to the progress sink, whatever its `result`.
+    fn finish_load<T>(&mut self, result: Result<T, DrowError>) -> Result<T, DrowError> {
+        let counters = mem::take(&mut self.load_counters);
+        if let Some(progress) = self.progress.as_mut() {
+            progress.finished(counters.objects, counters.relocations);
+        }
+        result
+    }
+
+    /// Looks up every symbol the relocations of `elf_metadata` refer to, once per symbol index.
+    fn resolution_table(
+        &mut self,
+        symbols: &SymbolScope,
+        elf_metadata: &Elf64Metadata,
+    ) -> Result<ResolutionTable, DrowError> {
+        let mut table = ResolutionTable::default();
+        let mut relocations = 0;
+        for rela in elf_metadata.relocations.iter().filter(|rela| {
+            match (rela.machine, rela.relocation_type) {
+                (MACHINE_X86_64, RELOCATION_X86_64_32 | RELOCATION_X86_64_32S) => {
+                    rela.symbol_index != 0
+                }
+                _ => rela.symbol_word(),
+            }
+        }) {
+            relocations += 1;
+            if let Entry::Vacant(entry) = table.symbols.entry(rela.symbol_index) {
+                entry.insert(self.get_symbol(symbols, elf_metadata, rela)?);
+            }
+        }
+        self.symbol_lookups.lookups += table.symbols.len();
+        self.symbol_lookups.saved += relocations - table.symbols.len();
+        let missing: Vec<&Elf64ResolvedSymbolTableEntry> = table
+            .symbols
+            .iter()
+            .filter(|(_, symbol)| symbol.is_none())
+            .filter_map(|(index, _)| elf_metadata.dynamic_symbol_table.get(*index as usize))
+            .collect();
+        if self.strict {
+            let undefined = missing
+                .iter()
+                .filter(|symbol| !symbol.weak())
+                .map(|symbol| symbol.symbol_name.as_str())
+                .min();
+            if let Some(name) = undefined {
+                return Err(DrowError::NotLoadable {
+                    path: elf_metadata.file_path.clone(),
+                    reason: format!("no loaded object defines {}", name),
+                });
+            }
+        }
+        let mut unresolved: Vec<(String, String)> = missing
+            .iter()
+            .map(|symbol| (elf_metadata.file_path.clone(), symbol.symbol_name.clone()))
+            .collect();
+        unresolved.sort();
+        self.unresolved_symbols.extend(unresolved);
+        Ok(table)
+    }
+
+    /// Relocates `elf_metadata` against the published `symbols`, then the definitions of the
+    /// objects mapped by the same load. Symbol relocations are replayed from the prelink cache
+    /// when it has them.
+    fn relocate(
+        &mut self,
+        symbols: &SymbolScope,
+        elf_metadata: &Elf64Metadata,
+        offset: u64,
+    ) -> Result<(), DrowError> {
+        Elf64Loader::check_relocations(elf_metadata)?;
+        let replayed = self
+            .prelink
+            .as_mut()
+            .and_then(|session| session.replay(&elf_metadata.file_path, offset));
+        let mut table = match replayed {
+            Some(_) => ResolutionTable::default(),
+            None => self.resolution_table(symbols, elf_metadata)?,
+        };
+        let recording = replayed.is_none() && self.prelink.is_some();
+        let mut writes = Vec::new();
+        for rela in elf_metadata.relocations.iter() {
+            match (rela.machine, rela.relocation_type) {
+                (
+                    MACHINE_X86_64,
+                    RELOCATION_X86_64_DPTMOD64
+                    | RELOCATION_X86_64_DTPOFF64
+                    | RELOCATION_X86_64_TPOFF64,
+                ) => {
+                    self.relocate_tls(elf_metadata, rela, offset)?;
+                }
+                (MACHINE_X86_64, RELOCATION_X86_64_32 | RELOCATION_X86_64_32S) => {
+                    self.relocate_32(symbols, &mut table, elf_metadata, rela, offset)?;
+                }
+                (MACHINE_X86_64, RELOCATION_X86_64_RELATIVE)
+                | (MACHINE_AARCH64, RELOCATION_AARCH64_RELATIVE) => unsafe {
+                    let destination_pointer = (rela.offset + offset) as *mut i64;
+                    *destination_pointer = (offset as i64) + rela.addend;
+                },
+                // The resolver is AArch64 code, which cannot run here.
+                (MACHINE_AARCH64, RELOCATION_AARCH64_IRELATIVE) => {
+                    warn!(
+                        "{}: not running the resolver of R_AARCH64_IRELATIVE at {:#X}",
+                        elf_metadata.file_path, rela.offset
+                    );
+                }
+                (MACHINE_X86_64, RELOCATION_X86_64_IRELATIV) => unsafe {
+                    let resolver = rela.addend as u64 + offset;
+                    let destination_pointer = (rela.offset + offset) as *mut u64;
+                    let value = cpu_features::resolve(resolver);
+                    if self.debug_relocations {
+                        let name = elf_metadata
+                            .containing_function(rela.addend as u64)
+                            .unwrap_or("<unknown>");
+                        self.report_resolution(elf_metadata, name, resolver, value);
+                    }
+                    *destination_pointer = value;
+                },
+                _ if rela.symbol_word() && replayed.is_none() => {
+                    if let Some(write) =
+                        self.symbol_write(symbols, &mut table, elf_metadata, rela)?
+                    {
+                        self.apply_write(symbols, &write, offset);
+                        if recording {
+                            writes.push(write);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if self.debug_relocations {
+            for (name, resolver, value) in table.resolved_indirect.iter() {
+                self.report_resolution(elf_metadata, name, *resolver, *value);
+            }
+        }
+        if let Some(object) = replayed.as_ref() {
+            debug!(
+                "Replaying {} symbol relocation(s) of {} from the prelink cache",
+                object.writes.len(),
+                elf_metadata.file_path
+            );
+            for write in object.writes.iter() {
+                self.apply_write(symbols, write, offset);
+            }
+        }
+        if let Some(session) = self.prelink.as_mut() {
+            let writes = replayed.map(|object| object.writes).unwrap_or(writes);
+            session.record(&elf_metadata.file_path, offset, writes);
+        }
+        Ok(())
+    }
+
+    fn record_mapping(
+        &mut self,
+        elf_metadata: &Elf64Metadata,
+        info: &Elf64ProgramHeader,
+        aligned_address: u64,
+        diff: u64,
+        memory_size: u64,
+        constraint: Option<&'static str>,
+    ) {
+        let protection = Elf64Loader::map_protection(info);
+        let role = if info.execute() {
+            "text"
+        } else if info.write() {
+            "data"
+        } else {
+            "rodata"
+        };
+        let end = aligned_address + memory_size;
+        let file_end = if info.p_memory_size > info.p_file_size {
+            Elf64Loader::round_page_size(aligned_address + diff + info.p_file_size).min(end)
+        } else {
+            end
+        };
+        self.memory_layout.push(MapEntry {
+            start: aligned_address,
+            end: file_end,
+            protection,
+            file_offset: Some(info.p_offset - diff),
+            object: elf_metadata.file_path.clone(),
+            role,
+            constraint,
+        });
+        if file_end < end {
+            self.memory_layout.push(MapEntry {
+                start: file_end,
+                end,
+                protection,
+                file_offset: None,
+                object: elf_metadata.file_path.clone(),
+                role: "bss",
+                constraint,
+            });
+        }
+    }
+
+    fn memory_map_entries(&self) -> Vec<MapEntry> {
+        let mut entries = self.memory_layout.clone();
+        if let Some(stack) = self.stack.as_ref() {
+            entries.push(MapEntry {
+                start: stack.address as u64,
+                end: stack.address as u64 + stack.size as u64,
+                protection: libc::PROT_READ | libc::PROT_WRITE | libc::PROT_EXEC,
+                file_offset: None,
+                object: String::from("[drow stack]"),
+                role: "stack",
+                constraint: None,
+            });
+        }
+        entries.sort_by_key(|entry| entry.start);
+        entries
+    }
+
+    /// Index of the object loaded from `path`, or from another path to the same file.
+    fn position(&self, path: &str) -> Option<usize> {
+        self.loaded_objects
+            .iter()
+            .position(|object| object.metadata.file_path == path)
+            .or_else(|| {
+                let key = ObjectKey::new(path);
+                self.loaded_objects
+                    .iter()
+                    .position(|object| object.key == key)
+            })
+    }
+
+    fn loaded_object_mut(&mut self, path: &str) -> Option<&mut LoadedObject> {
+        let index = self.position(path)?;
+        self.loaded_objects.get_mut(index)
+    }
+
+    /// Returns false when `path` is not loaded, like the skipped dynamic loader.
+    fn add_reference(&mut self, path: &str) -> bool {
+        match self.loaded_object_mut(path) {
+            Some(object) => {
+                object.references += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Why the object at `index` must stay mapped even without references.
+    fn unload_constraint(&self, index: usize) -> Option<String> {
+        let object = &self.loaded_objects[index];
+        if object.metadata.dynamic.no_delete() {
+            return Some(String::from("it is marked DF_1_NODELETE"));
+        }
+        let definitions = object.pinning_definitions();
+        self.loaded_objects
+            .iter()
+            .enumerate()
+            .filter(|(other, _)| *other != index)
+            .find_map(|(_, other)| {
+                definitions
+                    .iter()
+                    .find(|name| other.references_symbol(name))
+                    .map(|name| {
+                        format!(
+                            "its unique or thread-local symbol {} is used by {}",
+                            name, other.metadata.file_path
+                        )
+                    })
+            })
+    }
+}
+
+/// Loads objects into the current process. Loads and unloads may run from several threads:
+/// they hold the object registry for their whole duration, like the load lock of glibc, and
+/// publish the symbols of the objects they map only once all of them are relocated, so lookups
+/// never see a half-registered object.
+///
+/// Locks are always taken in the order of the fields below, `state` first and `symbols` last.
+pub struct Elf64Loader {
+    options: LoadOptions,
+    libc_override: Option<LibcFlavor>,
+    argv0: Option<String>,
+    prelink_cache: Option<PathBuf>,
+    object_rules: ObjectRules,
+    /// Sonames of the stubbed libraries.
+    stubs: Vec<String>,
+    startup_variables: Box<StartupVariables>,
+    state: Mutex<LoaderState>,
+    dependency_resolver: Mutex<DependenciesResolver>,
+    /// Where the next object is mapped.
+    address_space: Mutex<AddressSpace>,
+    symbols: RwLock<SymbolScope>,
+    phase: PhaseTracker,
+}
+
+impl Elf64Loader {
+    /// Maps one segment of `elf_metadata`. A noexec mount makes an executable mapping fail with
+    /// EPERM or EACCES; then, unless disabled, the file is copied into a memfd and this and the
+    /// remaining segments are mapped from `memory_copy` instead. `map` maps the segment from a
+    /// descriptor.
+    fn map_segment(
+        &self,
+        elf_metadata: &Elf64Metadata,
+        file_descriptor: i32,
+        memory_copy: &mut Option<MemoryBackedElf>,
+        protection: libc::c_int,
+        map: impl Fn(i32) -> Result<MappedMemory, DrowError>,
+    ) -> Result<MappedMemory, DrowError> {
+        if let Some(copy) = memory_copy.as_ref() {
+            return map(copy.open(elf_metadata)?);
+        }
+        match map(file_descriptor) {
+            Err(DrowError::MapFailed { source, .. })
+                if self.options.noexec_fallback
+                    && protection & libc::PROT_EXEC != 0
+                    && matches!(
+                        source.raw_os_error(),
+                        Some(libc::EPERM) | Some(libc::EACCES)
+                    ) =>
+            {
+                warn!(
+                    "Unable to map {} for execution ({}), mapping it from an in-memory copy; its pages are no longer shared with other processes",
+                    elf_metadata.file_path, source
+                );
+                let copy = Elf64Loader::copy_to_memory(elf_metadata, file_descriptor)?;
+                let copy_descriptor = copy.open(elf_metadata)?;
+                *memory_copy = Some(copy);
+                map(copy_descriptor)
+            }
+            result => result,
+        }
+    }
+
+    fn copy_to_memory(
+        elf_metadata: &Elf64Metadata,
+        file_descriptor: i32,
+    ) -> Result<MemoryBackedElf, DrowError> {
+        let io_error = |errno: syscall::Errno| DrowError::Io {
+            path: elf_metadata.file_path.clone(),
+            source: errno.into(),
+        };
+        let size = syscall::get_file_size(file_descriptor).map_err(io_error)?;
+        let mut bytes = vec![0u8; size as usize];
+        let mut copied = 0;
+        while copied < bytes.len() {
+            match syscall::pread(file_descriptor, &mut bytes[copied..], copied as u64) {
+                Ok(0) => break,
+                Ok(read) => copied += read,
+                Err(errno) => return Err(io_error(errno)),
+            }
+        }
+        bytes.truncate(copied);
+        let name = Path::new(&elf_metadata.file_path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("object");
+        MemoryBackedElf::from_bytes(name, &bytes)
+    }
+
+    fn map_protection(header: &Elf64ProgramHeader) -> libc::c_int {
+        let mut flags: libc::c_int = 0;
+        if header.execute() {
+            flags |= libc::PROT_EXEC;
+        }
+        if header.write() {
+            flags |= libc::PROT_WRITE;
+        }
+        if header.read() {
+            flags |= libc::PROT_READ;
+        }
+        flags
+    }
+
+    fn state(&self) -> MutexGuard<'_, LoaderState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Fails when the calling thread is in the middle of a load, as code run by the load, like
+    /// an indirect function resolver calling `dlopen`, would wait on the registry forever.
+    fn check_reentry(&self, call: &str) -> Result<(), DrowError> {
+        match self.phase.reentered() {
+            Some(phase) => Err(DrowError::Reentered {
+                call: call.to_string(),
+                phase,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// What the loader is doing. Code it runs may only call back into it while it runs init
+    /// functions or is ready.
+    pub fn phase(&self) -> LoaderPhase {
+        self.phase.phase()
+    }
+
+    fn resolver(&self) -> MutexGuard<'_, DependenciesResolver> {
+        self.dependency_resolver
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn address_space(&self) -> MutexGuard<'_, AddressSpace> {
+        self.address_space
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn symbols(&self) -> RwLockReadGuard<'_, SymbolScope> {
+        self.symbols
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn symbols_mut(&self) -> RwLockWriteGuard<'_, SymbolScope> {
+        self.symbols
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Symbols of the dynamic loader that glibc expects. `__stack_chk_guard` is only defined
+    /// when `files` use it and none of them defines it, as the x86-64 glibc keeps the canary in
+    /// the thread control block.
+    fn init_linker_symbols(
+        &self,
+        files: &[ResolvedObject],
+    ) -> HashMap<String, Elf64ResolvedSymbolTableEntry> {
+        let mut result = HashMap::new();
+        let value = unsafe {
+            let pointer: *const u8 = ptr::addr_of!(_rtld_global_ro);
+            trace!("Value at 0xb8: {:#X}", *(pointer.offset(0xb8)));
+            pointer as u64
+        };
+        debug!("_rtld_global_ro located at: {:#X}", value);
+        let entry = Elf64ResolvedSymbolTableEntry {
+            symbol_name: String::from("_rtld_global_ro"),
+            binding: SYMBOL_BINDING_GLOBAL,
+            symbol_type: SYMBOL_TYPE_OBJECT,
+            section_index: 0,
+            value,
+            size: size_of::<u8>() as u64,
+            version: None,
+        };
+        result.insert(String::from("_rtld_global_ro"), entry);
+        let value = {
+            let pointer: *const u8 = ptr::addr_of!(__tunable_get_val);
+            pointer as u64
+        };
+        debug!("__tunable_get_val located at: {:#X}", value);
+        let entry = Elf64ResolvedSymbolTableEntry {
+            symbol_name: String::from("__tunable_get_val"),
+            binding: SYMBOL_BINDING_GLOBAL,
+            symbol_type: SYMBOL_TYPE_FUNCTION,
+            section_index: 0,
+            value,
+            size: size_of::<u8>() as u64,
+            version: None,
+        };
+        result.insert(String::from("__tunable_get_val"), entry);
+        let variables = &self.startup_variables;
+        let entry =
+            StartupVariables::symbol("__libc_stack_end", variables.stack_end.as_ptr() as u64);
+        result.insert(String::from("__libc_stack_end"), entry);
+        let symbols = files
+            .iter()
+            .flat_map(|(file, _)| file.dynamic_symbol_table.iter())
+            .filter(|symbol| symbol_name::base_name(&symbol.symbol_name) == "__stack_chk_guard");
+        let (undefined, defined): (Vec<_>, Vec<_>) = symbols.partition(|symbol| symbol.undefined());
+        if !undefined.is_empty() && defined.is_empty() {
+            let address = ptr::addr_of!(variables.stack_guard) as u64;
+            debug!("__stack_chk_guard located at: {:#X}", address);
+            let entry = StartupVariables::symbol("__stack_chk_guard", address);
+            result.insert(String::from("__stack_chk_guard"), entry);
+        }
+        result
+    }
+
+    /// Records `address` as the initial stack pointer of the program in `__libc_stack_end`.
+    fn set_stack_end(&self, address: u64) {
+        debug!("__libc_stack_end set to {:#X}", address);
+        self.startup_variables
+            .stack_end
+            .store(address, Ordering::Relaxed);
+    }
+
+    /// The stack pointer the program starts with in the same process, once the stack is
+    /// allocated.
+    pub fn stack_top(&self) -> Option<u64> {
+        self.state()
+            .stack
+            .as_ref()
+            .map(|stack| stack.last_address as u64)
+    }
+
+    pub fn new(dependency_resolver: DependenciesResolver) -> Elf64Loader {
+        let options = LoadOptions::default();
+        cpu_features::install(options.cpu_features);
+        Elf64Loader {
+            options,
+            libc_override: None,
+            argv0: None,
+            prelink_cache: None,
+            object_rules: ObjectRules::default(),
+            stubs: Vec::new(),
+            startup_variables: Box::new(StartupVariables::new()),
+            state: Mutex::new(LoaderState::new()),
+            dependency_resolver: Mutex::new(dependency_resolver),
+            address_space: Mutex::new(AddressSpace::new(
+                DEFAULT_BASE_ADDRESS,
+                Elf64Loader::page_size(),
+            )),
+            symbols: RwLock::new(SymbolScope::default()),
+            phase: PhaseTracker::default(),
+        }
+    }
+
+    /// Starts from the system library cache and LD_LIBRARY_PATH of the current process.
+    pub fn builder() -> Elf64LoaderBuilder {
+        Elf64LoaderBuilder {
+            cache_path: DEFAULT_CACHE_PATH.to_string(),
+            ld_library_path: std::env::var("LD_LIBRARY_PATH").ok(),
+            search_paths: Vec::new(),
+            sysroot: None,
+            fuzzy_soname: false,
+            libc: None,
+            argv0: None,
+            options: LoadOptions::default(),
+            audit_hooks: Vec::new(),
+            progress: None,
+            prelink_cache: None,
+            manifest: None,
+            manifest_fallback: false,
+            offline_directories: None,
+            object_rules: ObjectRules::default(),
+            stubs: Vec::new(),
+        }
+    }
+
+    pub fn with_defaults() -> Result<Elf64Loader, DrowError> {
+        Elf64Loader::builder().build()
+    }
+
+    /// Unmaps everything this loader mapped and hands back its resolver for the next load.
+    pub fn into_dependencies_resolver(mut self) -> DependenciesResolver {
+        mem::take(
+            self.dependency_resolver
+                .get_mut()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        )
+    }
+
+    pub fn set_options(&mut self, options: LoadOptions) {
+        self.options = options;
+        *self.address_space() = AddressSpace::new(options.base_address, Elf64Loader::page_size());
+        self.state().strict = options.strict;
+        self.state().debug_bindings = options.debug_bindings;
+        self.state().debug_relocations = options.debug_relocations;
+        cpu_features::install(options.cpu_features);
+    }
+
+    /// Loads `library` and its dependencies ahead of the next program loaded, so its symbols
+    /// take precedence like with LD_PRELOAD. Names without a slash are resolved like
+    /// dependencies of that program, when it is loaded.
+    pub fn preload(&self, library: &str) -> Result<(), DrowError> {
+        self.state().pending_preloads.push(library.to_string());
+        Ok(())
+    }
+
+    /// Resolves and parses the libraries `preload` was given for the program `elf_metadata`.
+    fn open_preloads(
+        &self,
+        state: &mut LoaderState,
+        elf_metadata: &Elf64Metadata,
+    ) -> Result<(), DrowError> {
+        let machine = elf_metadata.elf_header.e_machine;
+        for library in mem::take(&mut state.pending_preloads) {
+            let path = self.library_path(&library, "preload", machine)?;
+            info!("Preloading {}", path);
+            let preload = Elf64Loader::open_metadata(&path)?;
+            state.preloads.push(Arc::new(preload));
+        }
+        Ok(())
+    }
+
+    /// Where `library` is, found like the dependencies of a program of `machine` when it is a
+    /// name without a slash.
+    fn library_path(
+        &self,
+        library: &str,
+        requester: &str,
+        machine: u16,
+    ) -> Result<String, DrowError> {
+        if library.contains('/') {
+            Ok(library.to_string())
+        } else {
+            self.resolver()
+                .resolve_path(&library.to_string(), machine)?
+                .into_iter()
+                .next()
+                .ok_or_else(|| DrowError::UnresolvedLibrary {
+                    name: library.to_string(),
+                    trail: vec![requester.to_string()],
+                })
+        }
+    }
+
+    fn open_metadata(path: &str) -> Result<Elf64Metadata, DrowError> {
+        let mut reader = OffsetReader::open(path).map_err(|source| DrowError::Io {
+            path: path.to_string(),
+            source,
+        })?;
+        Elf64Metadata::load(&path.to_string(), &mut reader)
+    }
+
+    pub fn prefaulted_pages(&self) -> Vec<(String, usize)> {
+        self.state().prefaulted_pages.clone()
+    }
+
+    pub fn phase_times(&self) -> PhaseTimes {
+        self.state().phase_times
+    }
+
+    pub fn symbol_lookups(&self) -> SymbolLookups {
+        self.state().symbol_lookups
+    }
+
+    /// The C library of the last loaded program, detected or set on the builder.
+    pub fn libc_flavor(&self) -> LibcFlavor {
+        self.state().libc_flavor
+    }
+
+    fn detect_libc(&self, elf_metadata: &Elf64Metadata, files: &[ResolvedObject]) -> LibcFlavor {
+        if let Some(libc) = self.libc_override {
+            return libc;
+        }
+        LibcFlavor::detect(
+            elf_metadata.interpreter.as_deref(),
+            files.iter().map(|(file, _)| file.as_ref()),
+        )
+    }
+
+    fn set_libc_flavor(
+        &self,
+        state: &mut LoaderState,
+        elf_metadata: &Elf64Metadata,
+        files: &[ResolvedObject],
+    ) {
+        state.libc_flavor = self.detect_libc(elf_metadata, files);
+        info!("Target C library: {}", state.libc_flavor);
+        state.interpreter = elf_metadata.interpreter.clone();
+        if let Some(interpreter) = state.interpreter.as_ref() {
+            match LibcFlavor::from_interpreter(interpreter) {
+                None => warn!(
+                    "{} expects the dynamic loader {}, which drow does not know, loading it \
+                     for the {} C library",
+                    elf_metadata.file_path, interpreter, state.libc_flavor
+                ),
+                Some(expected) if expected != state.libc_flavor => warn!(
+                    "{} expects the {} dynamic loader {}, loading it for the {} C library",
+                    elf_metadata.file_path, expected, interpreter, state.libc_flavor
+                ),
+                Some(_) => {}
+            }
+        }
+        let mut symbols = self.symbols_mut();
+        symbols.versioned = state.libc_flavor.versioned_symbols();
+        symbols.define(tls::tls_get_addr_symbol());
+        for symbol in dl::symbols() {
+            symbols.define(symbol);
+        }
+        if state.libc_flavor.linker_symbols() {
+            if self.options.cpu_features != CpuFeatures::native() {
+                warn!(
+                    "glibc resolvers read the CPU features of the host dynamic loader, only the \
+                     other resolvers and the glibc-hwcaps lookup see {}",
+                    self.options.cpu_features
+                );
+            }
+            for (_, symbol) in self.init_linker_symbols(files) {
+                symbols.define(symbol);
+            }
+        }
+    }
+
+    /// Binds the program name and environment variables of libc to storage set up for
+    /// `elf_metadata`, ahead of the definitions of the loaded C library.
+    fn set_program_identity(&self, state: &mut LoaderState, elf_metadata: &Elf64Metadata) {
+        let argv0 = self
+            .argv0
+            .clone()
+            .unwrap_or_else(|| elf_metadata.file_path.clone());
+        let identity = ProgramIdentity::new(&argv0);
+        info!("Program name: {:?}", identity.full_name());
+        let mut symbols = self.symbols_mut();
+        for symbol in identity.symbols() {
+            symbols.define(symbol);
+        }
+        state.program_identity = Some(identity);
+    }
+
+    /// Binds the symbols the objects among `files` requiring a stubbed library import, and
+    /// nothing loaded defines, to stubs. Weak references stay null.
+    fn define_stubs(
+        &self,
+        state: &mut LoaderState,
+        files: &[ResolvedObject],
+    ) -> Result<(), DrowError> {
+        if self.stubs.is_empty() {
+            return Ok(());
+        }
+        let mut imports: Vec<StubbedImport> = Vec::new();
+        {
+            let defined: HashSet<&str> = files
+                .iter()
+                .map(|(file, _)| file.as_ref())
+                .chain(
+                    state
+                        .loaded_objects
+                        .iter()
+                        .map(|object| object.metadata.as_ref()),
+                )
+                .flat_map(|file| file.dynamic_symbol_table.iter())
+                .filter(|symbol| symbol.exported())
+                .map(|symbol| symbol_name::base_name(&symbol.symbol_name))
+                .collect();
+            let symbols = self.symbols();
+            for (file, _) in files.iter() {
+                let libraries: Vec<String> = file
+                    .dynamic
+                    .unique_required_libraries()
+                    .into_iter()
+                    .filter(|library| self.stubs.contains(library))
+                    .cloned()
+                    .collect();
+                if libraries.is_empty() {
+                    continue;
+                }
+                for symbol in file.imports().filter(|symbol| symbol.global()) {
+                    if defined.contains(symbol_name::base_name(&symbol.symbol_name))
+                        || symbols
+                            .find(None, &SymbolName::parse(&symbol.symbol_name))
+                            .is_some()
+                        || imports
+                            .iter()
+                            .any(|import| import.name == symbol.symbol_name)
+                    {
+                        continue;
                     }
-                    unsafe {
-                        let destination_pointer = (rela.offset + offset) as *mut i64;
-                        let value = (symbol.value as i64) + (rela.addend as i64);
-                        println!(
-                            "Symbol found: {}. Address value at {:#X} will be changed to {:#X} (SYMBOL + ADDEND)",
-                            rela.symbol_name.clone(),
-                            destination_pointer as u64,
-                            value
+                    if symbol.thread_local() {
+                        warn!(
+                            "Thread-local {} of {} cannot be stubbed",
+                            symbol.symbol_name, file.file_path
                         );
-                        *destination_pointer = value;
+                        continue;
                     }
+                    imports.push(StubbedImport {
+                        name: symbol.symbol_name.clone(),
+                        variable: symbol.symbol_type == SYMBOL_TYPE_OBJECT,
+                        libraries: libraries.clone(),
+                    });
                 }
             }
-            if rela.relocation_type == RELOCATION_X86_64_RELATIVE {
-                unsafe {
-                    let destination_pointer = (rela.offset + offset) as *mut i64;
-                    *destination_pointer = (offset as i64) + (rela.addend as i64);
-                }
+        }
+        if imports.is_empty() {
+            return Ok(());
+        }
+        let stubs = StubObject::new(&imports)?;
+        info!(
+            "Stubbed {} symbols imported from {}",
+            imports.len(),
+            self.stubs.join(", ")
+        );
+        let mut symbols = self.symbols_mut();
+        for symbol in stubs.symbols() {
+            symbols.define(symbol.clone());
+        }
+        state.stub_objects.push(stubs);
+        Ok(())
+    }
+
+    /// What the object rules set for `elf_metadata`, matched against its soname and path.
+    fn object_settings(&self, elf_metadata: &Elf64Metadata) -> ObjectSettings {
+        let name = elf_metadata
+            .dynamic
+            .soname
+            .as_deref()
+            .unwrap_or(&elf_metadata.file_path);
+        self.object_rules
+            .settings(name, Some(&elf_metadata.file_path))
+    }
+
+    fn page_size() -> u64 {
+        auxv::current()
+            .page_size()
+            .unwrap_or_else(|| unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u64)
+    }
+
+    fn advise(address: u64, length: u64, advice: i32, advice_name: &str) {
+        if let Err(errno) =
+            syscall::madvise_checked(address as *const libc::c_void, length as usize, advice)
+        {
+            warn!(
+                "madvise({:#X}, {}, {}) failed: {}",
+                address, length, advice_name, errno
+            );
+        }
+    }
+
+    fn prefault(address: u64, length: u64) -> usize {
+        Elf64Loader::advise(address, length, libc::MADV_WILLNEED, "MADV_WILLNEED");
+        let page_size = Elf64Loader::page_size();
+        let mut touched = 0;
+        for page in (address..address + length).step_by(page_size as usize) {
+            unsafe {
+                ptr::read_volatile(page as *const u8);
             }
-            if rela.relocation_type == RELOCATION_X86_64_IRELATIV {
-                unsafe {
-                    let func_pointer = (rela.addend as u64 + offset) as *const ();
-                    let destination_pointer = (rela.offset + offset) as *mut i64;
-                    let function = mem::transmute::<*const (), fn() -> i64>(func_pointer);
-                    *destination_pointer = function();
+            touched += 1;
+        }
+        touched
+    }
+
+    /// Bytes the loadable segments of `elf_metadata` span once mapped.
+    fn mapped_size(elf_metadata: &Elf64Metadata) -> u64 {
+        elf_metadata
+            .program_headers
+            .iter()
+            .filter(|h| h.p_type == PROGRAM_HEADER_TYPE_LOADABLE)
+            .map(|h| h.p_memory_size)
+            .sum()
+    }
+
+    fn round_page_size(value: u64) -> u64 {
+        let page_size = Elf64Loader::page_size();
+        if value.is_multiple_of(page_size) {
+            value
+        } else {
+            let x = value / page_size;
+            page_size * (x + 1)
+        }
+    }
+
+    pub fn describe_address(&self, address: u64) -> String {
+        self.state().describe_address(address)
+    }
+
+    /// The loaded object, function and segment containing the runtime `address`, none outside
+    /// the mapped objects.
+    pub fn resolve_address(&self, address: u64) -> Option<SymbolizedAddress> {
+        if let Err(err) = self.check_reentry("resolve_address") {
+            warn!("{}", err);
+            return None;
+        }
+        self.state().resolve_address(address)
+    }
+
+    /// For example "SIGSEGV at 0x7f0000001234 (libfoo.so.1`frob_widget+0x42), accessing 0x10".
+    /// Addresses are not symbolized when the fault hit code a load ran with the registry held,
+    /// like an indirect function resolver, as waiting for the registry would never end.
+    pub(crate) fn describe_crash(&self, crash: &Crash) -> String {
+        let state = match self.state.try_lock() {
+            Ok(state) => state,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            Err(TryLockError::WouldBlock) => {
+                let mut description = format!(
+                    "{} at {:#x} while {}",
+                    crash.signal_name(),
+                    crash.instruction(),
+                    self.phase()
+                );
+                if crash.memory_access() {
+                    description.push_str(&format!(", accessing {:#x}", crash.address));
                 }
+                return description;
             }
-            if rela.relocation_type == RELOCATION_X86_64_COPY {
-                if let Some(symbol) = self.get_symbol(rela) {
-                    let destination_addr = rela.offset + offset;
-                    let destination_pointer = destination_addr.clone() as *mut libc::c_void;
-                    println!(
-                        "Symbol {} of size {} will be copied to {:#X} from {:#X}",
-                        symbol.symbol_name, symbol.size, destination_addr, symbol.value
-                    );
-                    unsafe {
-                        libc::memcpy(
-                            destination_pointer,
-                            symbol.value as *const libc::c_void,
-                            symbol.size as libc::size_t,
-                        );
-                    }
-                }
+        };
+        let mut description = format!(
+            "{} at {:#x} ({})",
+            crash.signal_name(),
+            crash.instruction(),
+            state.symbolize(crash.instruction())
+        );
+        if crash.memory_access() {
+            description.push_str(&format!(", accessing {:#x}", crash.address));
+        }
+        // Return addresses follow the call, which may be the last instruction of a function.
+        for (index, frame) in crash.frames.iter().enumerate().skip(1) {
+            // The walk ends in drow, which called the entry point.
+            if state.resolve_address(frame - 1).is_none() {
+                break;
+            }
+            description.push_str(&format!(
+                "\n  #{} {:#x} ({})",
+                index,
+                frame,
+                state.symbolize(frame - 1)
+            ));
+        }
+        description
+    }
+
+    fn symbol_address(symbol: &Elf64ResolvedSymbolTableEntry) -> u64 {
+        if symbol.indirect_function() {
+            let value = unsafe { cpu_features::resolve(symbol.value) };
+            debug!(
+                "INDIRECT FUNCTION {} RESOLVED: {:#X}",
+                symbol.symbol_name, value
+            );
+            value
+        } else {
+            symbol.value
+        }
+    }
+
+    pub fn lookup_symbol(&self, symbol_name: &str) -> Option<u64> {
+        self.symbols()
+            .find(None, &SymbolName::parse(symbol_name))
+            .filter(|symbol| !symbol.undefined())
+            .map(|symbol| Elf64Loader::symbol_address(&symbol))
+    }
+
+    /// Looks `symbol_name` up in the object loaded from `path`, then in its dependencies,
+    /// breadth first, like `dlsym` on a handle.
+    pub fn lookup_symbol_in(&self, path: &str, symbol_name: &str) -> Option<u64> {
+        if let Err(err) = self.check_reentry("lookup_symbol_in") {
+            warn!("{}", err);
+            return None;
+        }
+        let state = self.state();
+        let requested = SymbolName::parse(symbol_name);
+        let mut queue = VecDeque::from([path.to_string()]);
+        let mut searched = HashSet::new();
+        while let Some(path) = queue.pop_front() {
+            if !searched.insert(path.clone()) {
+                continue;
+            }
+            let object = match state.position(&path) {
+                Some(index) => &state.loaded_objects[index],
+                None => continue,
+            };
+            let versions = state.symbol_versions(&object.metadata);
+            let definition =
+                object
+                    .metadata
+                    .find_dynamic_symbol(&requested.name, |index, symbol| {
+                        (symbol.global() || symbol.weak())
+                            && binds_to(
+                                &SymbolName::from_table(&symbol.symbol_name, versions, index),
+                                &requested,
+                            )
+                    });
+            if let Some((_, symbol)) = definition {
+                let mut symbol = symbol.clone();
+                symbol.value += object.base;
+                return Some(Elf64Loader::symbol_address(&symbol));
+            }
+            queue.extend(object.dependencies.iter().cloned());
+        }
+        None
+    }
+
+    /// Paths and bases of the object loaded from `path` and its dependencies, breadth first, in
+    /// the order `lookup_symbol_in` searches them.
+    pub fn object_scope(&self, path: &str) -> Vec<(String, u64)> {
+        let state = self.state();
+        let mut scope = Vec::new();
+        let mut queue = VecDeque::from([path.to_string()]);
+        let mut searched = HashSet::new();
+        while let Some(path) = queue.pop_front() {
+            if !searched.insert(path.clone()) {
+                continue;
+            }
+            if let Some(index) = state.position(&path) {
+                let object = &state.loaded_objects[index];
+                scope.push((path, object.base));
+                queue.extend(object.dependencies.iter().cloned());
+            }
+        }
+        scope
+    }
+
+    /// The path `library` is loaded from, if it is loaded.
+    pub fn loaded_library(&self, library: &str) -> Option<String> {
+        if let Err(err) = self.check_reentry("loaded_library") {
+            warn!("{}", err);
+            return None;
+        }
+        let state = self.state();
+        let path = self
+            .library_path(library, "loaded_library", state.machine())
+            .ok()?;
+        state
+            .position(&path)
+            .map(|index| state.loaded_objects[index].metadata.file_path.clone())
+    }
+
+    fn check_relocations(elf_metadata: &Elf64Metadata) -> Result<(), DrowError> {
+        let unsupported = elf_metadata
+            .relocations
+            .iter()
+            .find(|rela| !capabilities::supports_relocation(rela.machine, rela.relocation_type));
+        match unsupported {
+            Some(rela) => Err(DrowError::UnsupportedRelocation {
+                type_: rela.relocation_type,
+                machine: rela.machine,
+                object: elf_metadata.file_path.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn load_program_header(
+        &self,
+        elf_metadata: &Elf64Metadata,
+        descriptors: &dyn DescriptorProvider,
+    ) -> Result<(), DrowError> {
+        let elf_metadata = Arc::new(elf_metadata.clone());
+        self.check_reentry("load_program_header")?;
+        Elf64Loader::check_layout(&elf_metadata, self.options.max_object_size)?;
+        let mut state = self.state();
+        let _phase = self.phase.enter(LoaderPhase::Mapping);
+        let result = self.map_file(&mut state, &elf_metadata, descriptors);
+        self.publish_symbols(&mut state, result.is_ok());
+        result.map(|_| ())
+    }
+
+    /// Maps and relocates one object, returning the base it was mapped at.
+    fn map_file(
+        &self,
+        state: &mut LoaderState,
+        elf_metadata: &Arc<Elf64Metadata>,
+        descriptors: &dyn DescriptorProvider,
+    ) -> Result<u64, DrowError> {
+        info!("Loading executable {}", elf_metadata.file_path);
+        let file_descriptor = descriptors.open(elf_metadata)?;
+        let result = self.map_program_headers(state, elf_metadata, file_descriptor);
+        descriptors.release(file_descriptor);
+        result
+    }
+
+    fn check_malformed_relocations(&self, elf_metadata: &Elf64Metadata) -> Result<(), DrowError> {
+        let malformed = match elf_metadata.malformed_relocations.first() {
+            Some(malformed) => malformed,
+            None => return Ok(()),
+        };
+        if !self.options.force {
+            return Err(DrowError::NotLoadable {
+                path: elf_metadata.file_path.clone(),
+                reason: format!(
+                    "{}, {} malformed relocation(s) in total (--force loads it anyway)",
+                    malformed.to_error(),
+                    elf_metadata.malformed_relocations.len()
+                ),
+            });
+        }
+        for malformed in elf_metadata.malformed_relocations.iter() {
+            warn!(
+                "{}: ignoring relocation: {}",
+                elf_metadata.file_path,
+                malformed.to_error()
+            );
+        }
+        Ok(())
+    }
+
+    /// Where the program header table of `elf_metadata` is mapped at `base`: the PT_PHDR
+    /// segment when a loadable segment covers it, otherwise the part of a loadable segment
+    /// holding e_phoff, as for most shared libraries, which have no PT_PHDR. Tables outside the
+    /// loadable segments are copied to memory the object owns, like ld.so does, and returned
+    /// with the table.
+    fn locate_program_headers(
+        elf_metadata: &Elf64Metadata,
+        base: u64,
+    ) -> (ProgramHeaderTable, Option<Box<[Elf64ProgramHeader]>>) {
+        let header = &elf_metadata.elf_header;
+        let loadable = elf_metadata
+            .program_headers
+            .iter()
+            .filter(|h| h.p_type == PROGRAM_HEADER_TYPE_LOADABLE);
+        let table = |address: u64| ProgramHeaderTable {
+            address: base + address,
+            count: header.e_program_header_entries,
+            entry_size: header.e_program_header_entry_size,
+        };
+        let phdr = elf_metadata
+            .program_headers
+            .iter()
+            .find(|h| h.p_type == PROGRAM_HEADER_TYPE_PHDR);
+        let executable = elf_metadata
+            .program_headers
+            .iter()
+            .any(|h| h.p_type == PROGRAM_HEADER_TYPE_INTERPRETER);
+        match phdr {
+            Some(phdr)
+                if loadable.clone().any(|segment| {
+                    segment.p_virtual_address <= phdr.p_virtual_address
+                        && phdr
+                            .p_virtual_address
+                            .checked_add(phdr.p_memory_size)
+                            .is_some_and(|end| {
+                                end <= segment.p_virtual_address + segment.p_file_size
+                            })
+                }) =>
+            {
+                return (table(phdr.p_virtual_address), None);
             }
+            Some(phdr) => warn!(
+                "{}: PT_PHDR at {:#x} is outside the loadable segments, using e_phoff",
+                elf_metadata.file_path, phdr.p_virtual_address
+            ),
+            None if executable => warn!(
+                "{} has no PT_PHDR, locating the program headers through e_phoff",
+                elf_metadata.file_path
+            ),
+            None => {}
+        }
+        let offset = header.e_program_header_offset;
+        let size =
+            header.e_program_header_entries as u64 * header.e_program_header_entry_size as u64;
+        loadable
+            .clone()
+            .find(|segment| {
+                segment.p_offset <= offset
+                    && offset
+                        .checked_add(size)
+                        .is_some_and(|end| end <= segment.p_offset + segment.p_file_size)
+            })
+            .map(|segment| {
+                let address = segment.p_virtual_address + offset - segment.p_offset;
+                (table(address), None)
+            })
+            .unwrap_or_else(|| {
+                debug!(
+                    "{}: the program header table at file offset {:#x} is not in a loadable \
+                     segment, copying it",
+                    elf_metadata.file_path, offset
+                );
+                let copy = elf_metadata.program_headers.clone().into_boxed_slice();
+                let table = ProgramHeaderTable {
+                    address: copy.as_ptr() as u64,
+                    count: copy.len() as u16,
+                    entry_size: size_of::<Elf64ProgramHeader>() as u16,
+                };
+                (table, Some(copy))
+            })
+    }
+
+    /// Fails on the section headers that disagree with the program headers when strict, see
+    /// `consistency`. Otherwise warns, the segments being what is mapped.
+    fn check_section_layout(elf_metadata: &Elf64Metadata, strict: bool) -> Result<(), DrowError> {
+        let names_index = elf_metadata.elf_header.e_section_name_string_table_index as usize;
+        let kept = elf_metadata
+            .string_tables
+            .as_ref()
+            .and_then(|tables| tables.tables().get(&names_index));
+        let loaded = match (kept, elf_metadata.section_headers.get(names_index)) {
+            (None, Some(header)) => fs::File::open(&elf_metadata.file_path)
+                .ok()
+                .and_then(|mut file| StringTable::load(header, &mut file).ok()),
+            _ => None,
+        };
+        let violations = consistency::violations(elf_metadata, kept.or(loaded.as_ref()));
+        if violations.is_empty() {
+            return Ok(());
+        }
+        if strict {
+            return Err(DrowError::NotLoadable {
+                path: elf_metadata.file_path.clone(),
+                reason: format!(
+                    "its section headers or dynamic entries disagree with its segments:\n  {}",
+                    violations.join("\n  ")
+                ),
+            });
         }
+        warn!(
+            "{}: mapping the segments, the section headers or dynamic entries disagree: {}",
+            elf_metadata.file_path,
+            violations.join("; ")
+        );
+        Ok(())
     }
 
-    pub fn load_program_header(&mut self, elf_metadata: &Elf64Metadata) {
-        println!("Loading executable {}", elf_metadata.file_path);
-        let file_descriptor = syscall::open_file(&elf_metadata.file_path).unwrap();
+    fn map_program_headers(
+        &self,
+        state: &mut LoaderState,
+        elf_metadata: &Arc<Elf64Metadata>,
+        file_descriptor: i32,
+    ) -> Result<u64, DrowError> {
+        let started = Instant::now();
+        self.check_malformed_relocations(elf_metadata)?;
+        Elf64Loader::check_section_layout(elf_metadata, state.strict)?;
+        if let Some(problem) = elf_metadata.pie_flag_problem() {
+            if state.strict {
+                return Err(DrowError::NotLoadable {
+                    path: elf_metadata.file_path.clone(),
+                    reason: problem,
+                });
+            }
+            warn!("{}: {}", elf_metadata.file_path, problem);
+        }
         let program_info = elf_metadata
             .program_headers
             .iter()
-            .filter(|h| h.p_virtual_address != 0)
             .filter(|h| h.p_file_size > 0)
             .filter(|h| h.p_type == PROGRAM_HEADER_TYPE_LOADABLE);
-        let offset = self.base_address;
-        let mut last_address: u64 = 0;
-        self.update_global_symbols(elf_metadata, offset);
+        let low = program_info
+            .clone()
+            .map(|info| align_address(info.p_virtual_address, Elf64Loader::page_size()))
+            .min()
+            .unwrap_or(0);
+        let high = program_info
+            .clone()
+            .map(|info| Elf64Loader::round_page_size(info.p_virtual_address + info.p_memory_size))
+            .max()
+            .unwrap_or(0);
+        // A multiple of the largest alignment keeps every segment congruent to its file layout.
+        let alignment = program_info
+            .clone()
+            .map(|info| info.p_align)
+            .max()
+            .unwrap_or(0);
+        let settings = self.object_settings(elf_metadata);
+        if !settings.rules.is_empty() {
+            info!(
+                "Object rules for {}: {}",
+                elf_metadata.file_path,
+                settings.rules.join("; ")
+            );
+        }
+        // 32-bit absolute relocations only reach the low 2 GiB, where the whole object must fit.
+        let constraint = elf_metadata.needs_low_placement().then_some("below 2 GiB");
+        let offset = if let Some(base) = settings.base {
+            self.address_space()
+                .reserve_at(&elf_metadata.file_path, base, low, high)?
+        } else if constraint.is_some() {
+            debug!(
+                "{} has 32-bit absolute relocations, placing it below 2 GiB",
+                elf_metadata.file_path
+            );
+            self.address_space()
+                .reserve_low(&elf_metadata.file_path, low, high, alignment)?
+        } else {
+            self.address_space()
+                .reserve(&elf_metadata.file_path, low, high, alignment)?
+        };
+        let (program_headers, program_header_copy) =
+            Elf64Loader::locate_program_headers(elf_metadata, offset);
+        let mut touched_pages = 0;
+        let mut memory_copy: Option<MemoryBackedElf> = None;
+        let versions = versions::read_dynamic_symbol_versions(elf_metadata);
+        state.pending_symbols.add_object(
+            elf_metadata,
+            &versions,
+            offset,
+            state.libc_flavor.versioned_symbols(),
+        );
+        state
+            .symbol_versions
+            .insert(elf_metadata.file_path.clone(), versions);
         for info in program_info {
-            let aligned_address = align_address(info.p_virtual_address + offset, info.p_align);
+            let aligned_address =
+                align_address(info.p_virtual_address + offset, Elf64Loader::page_size());
             let diff = info.p_virtual_address + offset - aligned_address;
-            if aligned_address + info.p_memory_size > last_address {
-                last_address = aligned_address + info.p_memory_size;
-            }
             let virtual_ptr = aligned_address as *const libc::c_void;
             let memory_size =
                 Elf64Loader::round_page_size(info.p_memory_size + diff) as libc::size_t;
             let file_offset = info.p_offset - diff;
-            println!(
+            debug!(
                 "Virtual Address {:#X} will be loaded at {:#X}, size: {}, file offset: {:#X}, last addr: {:#X}",
                 info.p_virtual_address, aligned_address, memory_size, file_offset, aligned_address + (memory_size as u64)
             );
             let protection = Elf64Loader::map_protection(info);
-            let memory_mapped = MappedMemory::memory_map(
+            let memory_mapped = self.map_segment(
+                elf_metadata,
                 file_descriptor,
-                memory_size,
-                virtual_ptr,
-                file_offset as libc::off_t,
+                &mut memory_copy,
                 protection,
-            )
-            .unwrap();
-            self.mapped_memory.push(memory_mapped);
+                |descriptor| {
+                    MappedMemory::memory_map(
+                        descriptor,
+                        memory_size,
+                        virtual_ptr,
+                        file_offset as libc::off_t,
+                        protection,
+                    )
+                },
+            )?;
+            state
+                .mapped_memory
+                .push((elf_metadata.file_path.clone(), memory_mapped));
+            let file_backed_size =
+                Elf64Loader::round_page_size(diff + info.p_file_size).min(memory_size as u64);
+            if file_backed_size < memory_size as u64 {
+                Elf64Loader::map_anonymous(
+                    aligned_address + file_backed_size,
+                    memory_size as u64 - file_backed_size,
+                    protection,
+                )?;
+            }
+            if self.options.advise_sequential {
+                Elf64Loader::advise(
+                    aligned_address,
+                    memory_size as u64,
+                    libc::MADV_SEQUENTIAL,
+                    "MADV_SEQUENTIAL",
+                );
+            }
+            if self.options.prefault && info.read() {
+                touched_pages += Elf64Loader::prefault(aligned_address, file_backed_size);
+            }
+            state.record_mapping(
+                elf_metadata,
+                info,
+                aligned_address,
+                diff,
+                memory_size as u64,
+                constraint,
+            );
         }
-        Elf64Loader::zero_bss_section(elf_metadata, offset);
-        self.relocate(elf_metadata, offset);
-        self.entry = elf_metadata.elf_header.e_entry + offset;
-        self.base_address = Elf64Loader::round_page_size(last_address + 1);
-        unsafe {
-            syscall::close(file_descriptor);
+        if self.options.prefault {
+            state
+                .prefaulted_pages
+                .push((elf_metadata.file_path.clone(), touched_pages));
+        }
+        Elf64Loader::zero_segment_tails(elf_metadata, offset);
+        let mut object = LoadedObject::new(elf_metadata, offset, program_headers);
+        object.program_header_copy = program_header_copy;
+        object.rules = settings.rules;
+        if self.options.debug_symbols {
+            crate::log::write_category(
+                "symbols",
+                format_args!(
+                    "{} imports {}",
+                    elf_metadata.file_path,
+                    if object.imports.is_empty() {
+                        String::from("nothing")
+                    } else {
+                        object.imports.join(", ")
+                    }
+                ),
+            );
+        }
+        state.loaded_objects.push(object);
+        state.tls_registry.register(elf_metadata, offset);
+        let relocation_started = Instant::now();
+        state.phase_times.map += relocation_started - started;
+        let phase = self.phase();
+        self.phase.set(LoaderPhase::Relocating);
+        let relocated = state.relocate(&self.symbols(), elf_metadata, offset);
+        self.phase.set(phase);
+        relocated?;
+        state.phase_times.relocate += relocation_started.elapsed();
+        let bindings = mem::take(&mut state.bindings);
+        if let Some(object) = state.loaded_objects.last_mut() {
+            object.bindings = bindings;
         }
+        if let Some(object) = state.loaded_objects.last() {
+            for hook in state.audit_hooks.iter_mut() {
+                hook(object);
+            }
+        }
+        Ok(offset)
+    }
+
+    /// Makes the definitions of the objects mapped by a load visible, or drops them when the
+    /// load failed.
+    fn publish_symbols(&self, state: &mut LoaderState, publish: bool) {
+        let pending = mem::take(&mut state.pending_symbols);
+        if publish {
+            self.symbols_mut().merge(pending);
+        }
+    }
+
+    pub fn allocate_stack(&self) -> Result<(), DrowError> {
+        let mut state = self.state();
+        if state.stack.is_none() {
+            let stack = ProgramStack::allocate(self.options.stack_size)?;
+            self.set_stack_end(stack.last_address as u64);
+            state.stack = Some(stack);
+        }
+        Ok(())
     }
 
-    fn append_init_functions(init_array: &mut Vec<u64>, dynamic: &Elf64Dynamic, base: u64) {
-        println!(
+    pub fn memory_map_entries(&self) -> Vec<MapEntry> {
+        self.state().memory_map_entries()
+    }
+
+    pub fn print_maps(&self) {
+        let mut maps = Table::new(&["Start-End", "Perms", "Offset", "Object", "Role"]);
+        for entry in self.memory_map_entries().iter() {
+            let perms = format!(
+                "{}{}{}p",
+                if entry.protection & libc::PROT_READ != 0 {
+                    'r'
+                } else {
+                    '-'
+                },
+                if entry.protection & libc::PROT_WRITE != 0 {
+                    'w'
+                } else {
+                    '-'
+                },
+                if entry.protection & libc::PROT_EXEC != 0 {
+                    'x'
+                } else {
+                    '-'
+                }
+            );
+            maps.add_row(vec![
+                format!("{:012x}-{:012x}", entry.start, entry.end),
+                perms,
+                entry
+                    .file_offset
+                    .map(|offset| format!("{:08x}", offset))
+                    .unwrap_or_else(|| String::from("-")),
+                entry.object.clone(),
+                match entry.constraint {
+                    Some(constraint) => format!("{} ({})", entry.role, constraint),
+                    None => entry.role.to_string(),
+                },
+            ]);
+        }
+        print!("{}", maps.render(false));
+    }
+
+    fn append_init_functions(init_array: &mut Vec<u64>, elf_metadata: &Elf64Metadata, base: u64) {
+        let dynamic = &elf_metadata.dynamic;
+        debug!(
             "Init function: {:#X}, init_array: {:#X}, init_array_size: {}",
             dynamic.init_function, dynamic.init_array, dynamic.init_array_size
         );
         if dynamic.init_function > 0 {
             let value = dynamic.init_function + base;
             init_array.push(value);
-            println!("Init function at: {:#X}, base: {:#X}", value, base);
+            debug!(
+                "Init function at: {:#X} {}, base: {:#X}",
+                value,
+                elf_metadata.describe_address(dynamic.init_function),
+                base
+            );
         }
         if dynamic.init_array > 0 && dynamic.init_array_size > 0 {
             unsafe {
                 let value = dynamic.init_array + base;
-                println!("Init array at: {:#X}, base: {:#X}", value, base);
+                debug!("Init array at: {:#X}, base: {:#X}", value, base);
                 let pointer = value as *const u64;
                 for x in 0..(dynamic.init_array_size / (size_of::<u64>() as u64)) {
                     let elem_pointer = *(pointer.offset(x as isize));
                     init_array.push(elem_pointer);
-                    println!(
-                        "Init array element points to: {:#X}, already reallocated",
-                        elem_pointer
+                    debug!(
+                        "Init array element points to: {:#X} {}, already reallocated",
+                        elem_pointer,
+                        elf_metadata.describe_address(elem_pointer.wrapping_sub(base))
                     );
                 }
             }
         }
     }
 
-    fn zero_bss_section(elf_metadata: &Elf64Metadata, base: u64) {
-        let bss_sections = elf_metadata
-            .section_headers
+    /// Adds the fini functions of `elf_metadata` in the order they would run if the list was
+    /// run backwards: DT_FINI, then DT_FINI_ARRAY.
+    fn append_fini_functions(fini_array: &mut Vec<u64>, elf_metadata: &Elf64Metadata, base: u64) {
+        let dynamic = &elf_metadata.dynamic;
+        if dynamic.fini_function > 0 {
+            fini_array.push(dynamic.fini_function + base);
+        }
+        if dynamic.fini_array > 0 && dynamic.fini_array_size > 0 {
+            let pointer = (dynamic.fini_array + base) as *const u64;
+            for x in 0..(dynamic.fini_array_size / (size_of::<u64>() as u64)) {
+                let elem_pointer = unsafe { *(pointer.offset(x as isize)) };
+                // Entries of 0 and -1 mark the ends of the array in older toolchains.
+                if elem_pointer != 0 && elem_pointer != u64::MAX {
+                    fini_array.push(elem_pointer);
+                }
+            }
+        }
+    }
+
+    /// Replaces the pages of a segment past its file content with anonymous ones, as its file
+    /// pages past the end of the file would fault with SIGBUS. The segment's own mapping still
+    /// covers them and unmaps them.
+    fn map_anonymous(address: u64, length: u64, protection: libc::c_int) -> Result<(), DrowError> {
+        debug!(
+            "Segment pages past the file at {:#X} with size {} are anonymous",
+            address, length
+        );
+        syscall::mmap_checked(
+            address as *const libc::c_void,
+            length as libc::size_t,
+            protection,
+            libc::MAP_FIXED | libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+        .map_err(|errno| DrowError::MapFailed {
+            address,
+            length,
+            source: errno.into(),
+        })?;
+        Ok(())
+    }
+
+    /// Zeroes the memory of the writable segments past their file content, which holds .bss.
+    /// The segments decide, NOBITS sections that disagree with them are left alone.
+    fn zero_segment_tails(elf_metadata: &Elf64Metadata, base: u64) {
+        let tails = elf_metadata
+            .program_headers
             .iter()
-            .filter(|h| h.writable() && h.sh_type == ELF64_SECTION_HEADER_NO_BITS && h.sh_size > 0);
-        for section in bss_sections {
-            let address = section.sh_virtual_address + base;
-            println!(
-                "BSS section loaded at {:#X} with size {} will be cleared",
-                address, section.sh_size
+            .filter(|h| h.p_file_size > 0)
+            .filter(|h| h.p_type == PROGRAM_HEADER_TYPE_LOADABLE && h.write())
+            .filter(|h| h.p_memory_size > h.p_file_size);
+        for segment in tails {
+            let address = segment.p_virtual_address + segment.p_file_size + base;
+            let size = segment.p_memory_size - segment.p_file_size;
+            debug!(
+                "Segment memory past the file at {:#X} with size {} will be cleared",
+                address, size
             );
-            let size = section.sh_size;
             unsafe {
                 libc::memset(address as *mut libc::c_void, 0, size as libc::size_t);
             }
         }
     }
 
-    pub fn load(&mut self, elf_metadata: &Elf64Metadata) {
-        let files = self
-            .dependency_resolver
-            .resolve_in_loading_order(elf_metadata);
-        for file in files.iter() {
-            if !file.file_path.contains(DYNAMIC_LOADER_SO) {
-                if !file.program_headers.is_empty() {
-                    let base = self.base_address;
-                    self.load_program_header(file);
-                    Elf64Loader::append_init_functions(
-                        &mut self.init_functions,
-                        &file.dynamic,
-                        base,
+    /// Copies `elf_metadata` once, the loaded objects share it with the resolver afterwards.
+    pub fn load(&self, elf_metadata: &Elf64Metadata) -> Result<(), DrowError> {
+        let elf_metadata = Arc::new(elf_metadata.clone());
+        self.load_with_descriptors(&elf_metadata, &FileDescriptorProvider)
+    }
+
+    pub fn load_file(&self, path: &str) -> Result<(), DrowError> {
+        let elf_metadata = Arc::new(Elf64Loader::open_metadata(path)?);
+        self.load_with_descriptors(&elf_metadata, &FileDescriptorProvider)
+    }
+
+    pub fn load_from_bytes(&self, image: &MemoryBackedElf) -> Result<(), DrowError> {
+        let elf_metadata = Arc::new(image.metadata()?);
+        self.load_with_descriptors(&elf_metadata, image)
+    }
+
+    fn load_with_descriptors(
+        &self,
+        elf_metadata: &Arc<Elf64Metadata>,
+        descriptors: &dyn DescriptorProvider,
+    ) -> Result<(), DrowError> {
+        self.check_reentry("load")?;
+        let mut state = self.state();
+        let result = self.load_locked(&mut state, elf_metadata, descriptors);
+        state.finish_load(result)
+    }
+
+    fn load_locked(
+        &self,
+        state: &mut LoaderState,
+        elf_metadata: &Arc<Elf64Metadata>,
+        descriptors: &dyn DescriptorProvider,
+    ) -> Result<(), DrowError> {
+        let _phase = self.phase.enter(LoaderPhase::Resolving);
+        let started = Instant::now();
+        self.open_preloads(state, elf_metadata)?;
+        let mut files = Vec::new();
+        {
+            let mut resolver = self.resolver();
+            for preload in state.preloads.iter() {
+                files.extend(resolver.resolve_with_dependencies(preload, &mut state.progress)?);
+            }
+            files.extend(resolver.resolve_with_dependencies(elf_metadata, &mut state.progress)?);
+        }
+        state.phase_times.resolve += started.elapsed();
+        self.set_libc_flavor(state, elf_metadata, &files);
+        self.set_program_identity(state, elf_metadata);
+        state.tls_registry.reserve_program(elf_metadata);
+        self.map_objects(state, files, elf_metadata, descriptors)?;
+        self.set_entry(state, elf_metadata);
+        state.tls_registry.close_static();
+        let roots: Vec<String> = state
+            .preloads
+            .iter()
+            .chain(std::iter::once(elf_metadata))
+            .map(|file| file.file_path.clone())
+            .collect();
+        for root in roots.iter() {
+            state.add_reference(root);
+        }
+        Ok(())
+    }
+
+    /// Takes the entry point from the program only, libraries with one, like libc.so.6, are
+    /// never started when loaded as dependencies or with `load_library`.
+    fn set_entry(&self, state: &mut LoaderState, elf_metadata: &Elf64Metadata) {
+        let kind = elf_metadata.object_kind();
+        let base = state
+            .position(&elf_metadata.file_path)
+            .map(|index| state.loaded_objects[index].base)
+            .unwrap_or(0);
+        let entry = elf_metadata.elf_header.e_entry;
+        state.entry = if kind.executable() { base + entry } else { 0 };
+        if entry != 0 {
+            debug!(
+                "entry: {:#x} {}, {}",
+                state.entry,
+                elf_metadata.describe_address(entry),
+                kind
+            );
+        }
+        state.program_kind = Some((elf_metadata.file_path.clone(), kind));
+    }
+
+    /// Refuses to start a program without an entry point, which jumps to its ELF header.
+    fn check_startable(&self) -> Result<(), DrowError> {
+        let state = self.state();
+        // Objects of other machines are only mapped and relocated.
+        if let Some(object) = state
+            .loaded_objects
+            .iter()
+            .find(|object| object.metadata.elf_header.e_machine != MACHINE_X86_64)
+        {
+            return Err(DrowError::NotLoadable {
+                path: object.metadata.file_path.clone(),
+                reason: format!(
+                    "it is built for machine {:#04X}, only x86-64 code runs",
+                    object.metadata.elf_header.e_machine
+                ),
+            });
+        }
+        match state.program_kind.as_ref() {
+            Some((path, kind)) if !kind.executable() => Err(DrowError::NotLoadable {
+                path: path.clone(),
+                reason: format!(
+                    "it is a {} without an entry point, run a program linked against it instead \
+                     or list its symbols with `drow inspect`",
+                    kind
+                ),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Starts replaying or recording the prelink records of `elf_metadata` when loading it into
+    /// an empty loader, which leaves the files to map as the only input of symbol resolution.
+    fn prelink_session(
+        &self,
+        state: &LoaderState,
+        files: &[ResolvedObject],
+        elf_metadata: &Elf64Metadata,
+    ) -> Option<PrelinkSession> {
+        let directory = self.prelink_cache.as_ref()?;
+        if !state.loaded_objects.is_empty() {
+            return None;
+        }
+        // The records hold the implementations ifunc resolvers picked for the whole CPU.
+        if self.options.cpu_features != CpuFeatures::native() {
+            info!("Not using the prelink cache: CPU features are masked");
+            return None;
+        }
+        let mut fingerprints = HashMap::new();
+        let mut objects = Vec::new();
+        for (file, _) in files.iter() {
+            let fingerprint = match prelink::fingerprint(&file.file_path) {
+                Some(fingerprint) => fingerprint,
+                None => {
+                    info!(
+                        "Not using the prelink cache: {} has no fingerprint",
+                        file.file_path
+                    );
+                    return None;
+                }
+            };
+            fingerprints.insert(file.file_path.clone(), fingerprint);
+            objects.push((file.file_path.clone(), fingerprint));
+        }
+        let start = self.address_space().next();
+        let cache_file = prelink::cache_file(directory, &elf_metadata.file_path);
+        let replay = match Prelink::load(&cache_file) {
+            Ok(cached) => match cached.mismatch(start, &objects) {
+                None => cached.into_objects(),
+                Some(reason) => {
+                    info!(
+                        "Prelink cache {} is stale: {}",
+                        cache_file.display(),
+                        reason
                     );
+                    HashMap::new()
+                }
+            },
+            Err(err) => {
+                debug!("No prelink cache at {}: {}", cache_file.display(), err);
+                HashMap::new()
+            }
+        };
+        Some(PrelinkSession {
+            cache_file,
+            fingerprints,
+            replay,
+            recorded: Prelink {
+                start,
+                objects: Vec::new(),
+            },
+            replayed: 0,
+        })
+    }
+
+    /// Fails when `files` require symbol versions that neither they nor the loaded objects
+    /// define, or binds the symbols of those versions to older ones with `best_effort`.
+    fn check_versions(
+        &self,
+        state: &mut LoaderState,
+        files: &[ResolvedObject],
+    ) -> Result<(), DrowError> {
+        let objects: Vec<&Elf64Metadata> = files
+            .iter()
+            .map(|(file, _)| file.as_ref())
+            .chain(
+                state
+                    .loaded_objects
+                    .iter()
+                    .map(|object| object.metadata.as_ref()),
+            )
+            .collect();
+        let missing: Vec<MissingVersion> = versions::missing_versions(&objects)
+            .into_iter()
+            .filter(|missing| {
+                files
+                    .iter()
+                    .any(|(file, _)| file.file_path == missing.requester)
+            })
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        if !self.options.best_effort {
+            return Err(DrowError::MissingVersions { missing });
+        }
+        for missing in missing.iter() {
+            warn!("{}", missing.describe());
+            for symbol in missing.symbols.iter() {
+                match missing
+                    .fallbacks
+                    .iter()
+                    .find(|fallback| &fallback.symbol == symbol)
+                {
+                    Some(fallback) => warn!(
+                        "Binding {}@{} to {}@{}, it may not behave as {} expects",
+                        symbol, missing.version, symbol, fallback.version, missing.requester
+                    ),
+                    None => warn!(
+                        "{} has no version of {} older than {}, binding the default one",
+                        missing.provider, symbol, missing.version
+                    ),
                 }
             }
         }
+        state.missing_versions.extend(missing);
+        Ok(())
     }
 
-    pub fn execute_same_process(&self) {
-        let stack = ProgramStack::allocate_default_size().unwrap();
-        println!("Starting in the same process");
-        let args = HandlerArguments {
-            entry: self.entry,
-            init_functions: self.init_functions.clone(),
-            last_stack_address: stack.last_address as u64,
+    /// Fails on loadable segments whose addresses or file offsets wrap, reach past the user
+    /// address space or past the end of the file, on an object spanning more than
+    /// `max_object_size`, and on relocations writing outside its segments. Placing the object
+    /// then cannot wrap any address computed from these fields, which come straight from the
+    /// file.
+    fn check_layout(elf_metadata: &Elf64Metadata, max_object_size: u64) -> Result<(), DrowError> {
+        let page_size = Elf64Loader::page_size();
+        let header = &elf_metadata.elf_header;
+        let not_loadable = |error: DrowError| DrowError::NotLoadable {
+            path: elf_metadata.file_path.clone(),
+            reason: error.to_string(),
         };
-        unsafe {
-            handle_same_process(&args as *const HandlerArguments);
+        // Objects read from a pipe have no size to check against.
+        let file_length = fs::metadata(&elf_metadata.file_path)
+            .ok()
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len());
+        let mut low = u64::MAX;
+        let mut high = 0;
+        for (index, segment) in elf_metadata.program_headers.iter().enumerate() {
+            if segment.p_type != PROGRAM_HEADER_TYPE_LOADABLE {
+                continue;
+            }
+            let malformed = |what: String| {
+                not_loadable(DrowError::Malformed {
+                    what,
+                    offset: (index as u64)
+                        .checked_mul(header.e_program_header_entry_size as u64)
+                        .and_then(|entry| entry.checked_add(header.e_program_header_offset)),
+                })
+            };
+            let end = segment
+                .p_virtual_address
+                .checked_add(segment.p_memory_size)
+                .filter(|end| *end <= USER_SPACE_END)
+                .ok_or_else(|| {
+                    malformed(format!(
+                        "segment of {:#X} bytes at {:#X} ends past the user address space",
+                        segment.p_memory_size, segment.p_virtual_address
+                    ))
+                })?;
+            let file_end = segment
+                .p_offset
+                .checked_add(segment.p_file_size)
+                .ok_or_else(|| {
+                    malformed(format!(
+                        "segment of {:#X} bytes at file offset {:#X} ends past the largest offset",
+                        segment.p_file_size, segment.p_offset
+                    ))
+                })?;
+            if let Some(length) = file_length.filter(|length| file_end > *length) {
+                return Err(malformed(format!(
+                    "segment of {:#X} bytes at file offset {:#X} ends past the end of the file, \
+                     {:#X} bytes",
+                    segment.p_file_size, segment.p_offset, length
+                )));
+            }
+            if segment.p_offset < segment.p_virtual_address % page_size {
+                return Err(malformed(format!(
+                    "segment at {:#X} starts {:#X} bytes into its page, before file offset {:#X}",
+                    segment.p_virtual_address,
+                    segment.p_virtual_address % page_size,
+                    segment.p_offset
+                )));
+            }
+            low = low.min(align_address(segment.p_virtual_address, page_size));
+            high = high.max(Elf64Loader::round_page_size(end));
+        }
+        if high.saturating_sub(low) > max_object_size {
+            return Err(DrowError::NotLoadable {
+                path: elf_metadata.file_path.clone(),
+                reason: format!(
+                    "its segments span {:#X} bytes, more than {:#X} (see --max-object-size)",
+                    high - low,
+                    max_object_size
+                ),
+            });
+        }
+        for rela in elf_metadata.relocations.iter() {
+            let width = if rela.copy() {
+                elf_metadata
+                    .dynamic_symbol_table
+                    .get(rela.symbol_index as usize)
+                    .map(|symbol| symbol.size)
+                    .unwrap_or(0)
+            } else {
+                rela.width()
+            };
+            if width == 0 {
+                continue;
+            }
+            let inside = rela.offset >= low
+                && rela
+                    .offset
+                    .checked_add(width)
+                    .is_some_and(|end| end <= high);
+            if !inside {
+                return Err(not_loadable(DrowError::Malformed {
+                    what: format!(
+                        "{} relocation of {} bytes at {:#X} writes outside the segments, {:#X}-{:#X}",
+                        rela.type_name(),
+                        width,
+                        rela.offset,
+                        low,
+                        high
+                    ),
+                    offset: None,
+                }));
+            }
         }
+        Ok(())
     }
 
-    pub fn execute(&self) {
-        let stack = ProgramStack::allocate_default_size().unwrap();
-        let args = HandlerArguments {
-            entry: self.entry,
-            init_functions: self.init_functions.clone(),
-            last_stack_address: stack.address as u64,
+    /// Fails when mapping `files` needs more address space than RLIMIT_AS leaves, or more
+    /// private writable memory than the kernel commits to.
+    fn check_memory(files: &[ResolvedObject]) -> Result<(), DrowError> {
+        let loadable = |file: &Elf64Metadata| {
+            file.program_headers
+                .iter()
+                .filter(|h| h.p_type == PROGRAM_HEADER_TYPE_LOADABLE)
+                .map(|h| {
+                    let start = align_address(h.p_virtual_address, Elf64Loader::page_size());
+                    let end = Elf64Loader::round_page_size(h.p_virtual_address + h.p_memory_size);
+                    (h.write(), end - start)
+                })
+                .collect::<Vec<(bool, u64)>>()
         };
-        let pid = unsafe {
-            syscall::clone(
-                handle as *const libc::c_void,
-                stack.last_address,
-                libc::CLONE_VM | libc::SIGCHLD,
-                ptr::addr_of!(args) as *const libc::c_void,
-                0 as *const libc::pid_t,
-                0 as *const libc::c_void,
-                0 as *const libc::c_void,
-            )
+        let segments: Vec<(bool, u64)> =
+            files.iter().flat_map(|(file, _)| loadable(file)).collect();
+        let required = segments.iter().map(|(_, size)| size).sum();
+        let writable = segments
+            .iter()
+            .filter(|(write, _)| *write)
+            .map(|(_, size)| size)
+            .sum();
+        let limits = [
+            (
+                "address space (RLIMIT_AS)",
+                required,
+                memory_limits::address_space(),
+            ),
+            ("committable memory", writable, memory_limits::commit()),
+        ];
+        for (what, required, available) in limits {
+            if let Some(available) = available.filter(|available| *available < required) {
+                return Err(DrowError::InsufficientMemory {
+                    what: what.to_string(),
+                    required,
+                    available,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Unmaps the objects a failed load mapped, from `first_mapped` on in the registry, and what
+    /// was mapped of `failed` before it failed.
+    fn unwind(&self, state: &mut LoaderState, first_mapped: usize, failed: &str) {
+        let objects: Vec<LoadedObject> = state.loaded_objects.drain(first_mapped..).collect();
+        for object in objects.iter().rev() {
+            self.unmap_object(state, object);
+        }
+        state.memory_layout.retain(|entry| entry.object != failed);
+        state.mapped_memory.retain(|(owner, _)| owner != failed);
+        state.tls_registry.release(failed);
+    }
+
+    /// Maps the files that are not loaded yet, then takes a reference to each dependency of the
+    /// newly loaded ones.
+    fn map_objects(
+        &self,
+        state: &mut LoaderState,
+        mut files: Vec<ResolvedObject>,
+        elf_metadata: &Elf64Metadata,
+        descriptors: &dyn DescriptorProvider,
+    ) -> Result<(), DrowError> {
+        let mut loaded: HashSet<ObjectKey> = state
+            .loaded_objects
+            .iter()
+            .map(|object| object.key.clone())
+            .collect();
+        files.retain(|(file, _)| loaded.insert(ObjectKey::new(&file.file_path)));
+        let interpreter = state
+            .libc_flavor
+            .left_out_interpreter(state.interpreter.as_deref());
+        files.retain(|(file, _)| {
+            !interpreter.is_some_and(|interpreter| is_interpreter(&file.file_path, interpreter))
+                && !file.program_headers.is_empty()
+        });
+        let total_relocations = files.iter().map(|(file, _)| file.relocations.len()).sum();
+        self.phase.set(LoaderPhase::Mapping);
+        for (file, _) in files.iter() {
+            Elf64Loader::check_layout(file, self.options.max_object_size)?;
+        }
+        Elf64Loader::check_memory(&files)?;
+        self.check_versions(state, &files)?;
+        self.define_stubs(state, &files)?;
+        state.prelink = self.prelink_session(state, &files, elf_metadata);
+        let first_mapped = state.loaded_objects.len();
+        let mut mapped = Vec::new();
+        for (file, dependencies) in files.into_iter() {
+            if let Some(progress) = state.progress.as_mut() {
+                progress.mapping(&file.file_path, Elf64Loader::mapped_size(&file));
+            }
+            let result = if file.file_path == elf_metadata.file_path {
+                self.map_file(state, &file, descriptors)
+            } else {
+                self.map_file(state, &file, &FileDescriptorProvider)
+            };
+            let base = match result {
+                Ok(base) => base,
+                Err(err) => {
+                    state.prelink = None;
+                    self.publish_symbols(state, false);
+                    self.unwind(state, first_mapped, &file.file_path);
+                    return Err(err);
+                }
+            };
+            state.load_counters.objects += 1;
+            state.load_counters.relocations += file.relocations.len();
+            if let Some(progress) = state.progress.as_mut() {
+                progress.relocating(state.load_counters.relocations, total_relocations);
+            }
+            if self.object_settings(&file).skip_init {
+                info!("Not running the init functions of {}", file.file_path);
+            } else {
+                Elf64Loader::append_init_functions(&mut state.init_functions, &file, base);
+            }
+            Elf64Loader::append_fini_functions(&mut state.fini_functions, &file, base);
+            mapped.push((file.file_path.clone(), dependencies));
+        }
+        if let Some(session) = state.prelink.take() {
+            session.finish();
+        }
+        self.publish_symbols(state, true);
+        for (path, dependencies) in mapped.into_iter() {
+            let dependencies: Vec<String> = dependencies
+                .into_iter()
+                .filter_map(|dependency| {
+                    let object = state.loaded_object_mut(&dependency)?;
+                    object.references += 1;
+                    Some(object.metadata.file_path.clone())
+                })
+                .collect();
+            if let Some(object) = state.loaded_object_mut(&path) {
+                object.dependencies = dependencies;
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads `library` and its dependencies after the program was loaded, like `dlopen`. Loading
+    /// an object that is already loaded only takes another reference to it. Returns the path
+    /// `unload` takes.
+    pub fn load_library(&self, library: &str) -> Result<String, DrowError> {
+        self.check_reentry("load_library")?;
+        let mut state = self.state();
+        let result = self.load_library_locked(&mut state, library);
+        state.finish_load(result)
+    }
+
+    fn load_library_locked(
+        &self,
+        state: &mut LoaderState,
+        library: &str,
+    ) -> Result<String, DrowError> {
+        let _phase = self.phase.enter(LoaderPhase::Resolving);
+        let path = self.library_path(library, "load_library", state.machine())?;
+        if state.add_reference(&path) {
+            debug!("{} is already loaded", path);
+            return Ok(path);
+        }
+        let elf_metadata = Arc::new(Elf64Loader::open_metadata(&path)?);
+        if elf_metadata.dynamic.no_open() {
+            return Err(DrowError::NotLoadable {
+                path,
+                reason: String::from("it is marked DF_1_NOOPEN"),
+            });
+        }
+        info!("Loading library {}", path);
+        let started = Instant::now();
+        let files = self
+            .resolver()
+            .resolve_with_dependencies(&elf_metadata, &mut state.progress)?;
+        state.phase_times.resolve += started.elapsed();
+        if state.loaded_objects.is_empty() {
+            self.set_libc_flavor(state, &elf_metadata, &files);
+        }
+        let loaded = state.loaded_objects.len();
+        if let Err(err) = self.map_objects(state, files, &elf_metadata, &FileDescriptorProvider) {
+            for object in state.loaded_objects.split_off(loaded).iter().rev() {
+                self.unmap_object(state, object);
+            }
+            return Err(err);
+        }
+        state.add_reference(&path);
+        Ok(path)
+    }
+
+    /// Drops a reference taken by `load` or `load_library`, like `dlclose`. An object left
+    /// without references is unmapped, then releases its own dependencies, unless it has to
+    /// stay mapped. Returns whether the object was unmapped.
+    pub fn unload(&self, path: &str) -> Result<bool, DrowError> {
+        self.check_reentry("unload")?;
+        let mut state = self.state();
+        match state.loaded_object_mut(path) {
+            Some(object) if object.references == 0 => {
+                info!("Not unloading {}: it has no references left", path);
+                Ok(false)
+            }
+            Some(_) => {
+                let mut released = Vec::new();
+                let unmapped = Elf64Loader::release(&mut state, path, &mut released);
+                for object in released.iter() {
+                    self.unmap_object(&mut state, object);
+                }
+                Ok(unmapped)
+            }
+            None => Err(DrowError::NotLoaded {
+                path: path.to_string(),
+            }),
+        }
+    }
+
+    /// Loads `library` like `load_library`, then runs the init functions of the objects it
+    /// mapped, as `dlopen` does in a running program. They run without the registry held, so
+    /// they may load libraries themselves.
+    pub fn open_library(&self, library: &str) -> Result<String, DrowError> {
+        self.check_reentry("open_library")?;
+        let (path, init_functions) = {
+            let mut state = self.state();
+            let initialized = state.init_functions.len();
+            let finalized = state.fini_functions.len();
+            let result = self.load_library_locked(&mut state, library);
+            let path = state.finish_load(result)?;
+            let fini_functions = state.fini_functions[finalized..].to_vec();
+            state.pending_fini_functions.extend(fini_functions);
+            (path, state.init_functions[initialized..].to_vec())
+        };
+        let _phase = self.phase.enter(LoaderPhase::RunningInit);
+        for init in init_functions.iter() {
+            unsafe {
+                let function =
+                    mem::transmute::<*const (), unsafe extern "C" fn()>(*init as *const ());
+                function();
+            }
+        }
+        Ok(path)
+    }
+
+    /// Drops a reference like `unload`, running the fini functions of the objects left without
+    /// references before they are unmapped, as `dlclose` does in a running program.
+    pub fn close_library(&self, path: &str) -> Result<bool, DrowError> {
+        self.check_reentry("close_library")?;
+        let (unmapped, released, fini_functions) = {
+            let mut state = self.state();
+            if state.position(path).is_none() {
+                return Err(DrowError::NotLoaded {
+                    path: path.to_string(),
+                });
+            }
+            let mut released = Vec::new();
+            let unmapped = Elf64Loader::release(&mut state, path, &mut released);
+            let ranges: Vec<(u64, u64)> = state
+                .memory_layout
+                .iter()
+                .filter(|entry| {
+                    released
+                        .iter()
+                        .any(|object| object.metadata.file_path == entry.object)
+                })
+                .map(|entry| (entry.start, entry.end))
+                .collect();
+            let fini_functions: Vec<u64> = state
+                .fini_functions
+                .iter()
+                .rev()
+                .copied()
+                .filter(|function| {
+                    ranges
+                        .iter()
+                        .any(|(start, end)| *start <= *function && *function < *end)
+                })
+                .collect();
+            (unmapped, released, fini_functions)
         };
-        println!("Process with PID {} started", pid);
-        let mut status: libc::c_int = 0;
-        let finished_pid = unsafe { libc::waitpid(pid, &mut status, 0) };
-        if finished_pid == -1 {
-            println!("waitpid failed");
+        for fini in fini_functions.iter() {
             unsafe {
-                let error_location = libc::__errno_location();
-                perror(error_location as *const libc::c_char);
+                let function =
+                    mem::transmute::<*const (), unsafe extern "C" fn()>(*fini as *const ());
+                function();
+            }
+        }
+        let mut state = self.state();
+        for object in released.iter() {
+            self.unmap_object(&mut state, object);
+        }
+        Ok(unmapped)
+    }
+
+    /// Drops a reference to `path`, then to the dependencies of an object left without any.
+    /// The objects taken out of the registry are added to `released`, for the caller to unmap.
+    fn release(state: &mut LoaderState, path: &str, released: &mut Vec<LoadedObject>) -> bool {
+        let index = match state.position(path) {
+            Some(index) => index,
+            None => return false,
+        };
+        let object = &mut state.loaded_objects[index];
+        object.references = object.references.saturating_sub(1);
+        if object.references > 0 {
+            debug!(
+                "{} still has {} reference(s), keeping it mapped",
+                path, object.references
+            );
+            return false;
+        }
+        if let Some(reason) = state.unload_constraint(index) {
+            info!("Not unloading {}: {}", path, reason);
+            return false;
+        }
+        let object = state.loaded_objects.remove(index);
+        let dependencies = object.dependencies.clone();
+        released.push(object);
+        for dependency in dependencies.iter() {
+            Elf64Loader::release(state, dependency, released);
+        }
+        true
+    }
+
+    /// Forgets the mappings, init functions and symbols of an object taken out of the registry.
+    fn unmap_object(&self, state: &mut LoaderState, object: &LoadedObject) {
+        let path = &object.metadata.file_path;
+        info!("Unloading {}", path);
+        let ranges: Vec<(u64, u64)> = state
+            .memory_layout
+            .iter()
+            .filter(|entry| &entry.object == path)
+            .map(|entry| (entry.start, entry.end))
+            .collect();
+        let inside = |address: u64| {
+            ranges
+                .iter()
+                .any(|(start, end)| *start <= address && address < *end)
+        };
+        state.memory_layout.retain(|entry| &entry.object != path);
+        state.tls_registry.release(path);
+        state.mapped_memory.retain(|(owner, _)| owner != path);
+        state.init_functions.retain(|function| !inside(*function));
+        state.fini_functions.retain(|function| !inside(*function));
+        state
+            .pending_fini_functions
+            .retain(|function| !inside(*function));
+        let mut remaining = SymbolScope::default();
+        for object in state.loaded_objects.iter() {
+            remaining.add_object(
+                &object.metadata,
+                state.symbol_versions(&object.metadata),
+                object.base,
+                state.libc_flavor.versioned_symbols(),
+            );
+        }
+        let mut symbols = self.symbols_mut();
+        symbols.retain(|symbol| !inside(symbol.value));
+        symbols.merge(remaining);
+    }
+
+    /// Runs the fini functions left by `open_library`, then unmaps every object, newest first,
+    /// with the program stack and the stubs, and forgets all symbols. Dropping the loader does
+    /// the same, without running fini functions when the thread is panicking.
+    pub fn shutdown(mut self) -> ShutdownReport {
+        self.shut_down(true)
+    }
+
+    fn shut_down(&mut self, orderly: bool) -> ShutdownReport {
+        let mut report = ShutdownReport::default();
+        // Not locked: a child killed in the middle of a load leaves the registry locked for good.
+        let fini_functions = mem::take(
+            &mut self
+                .state
+                .get_mut()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .pending_fini_functions,
+        );
+        if orderly {
+            for fini in fini_functions.iter().rev() {
+                unsafe {
+                    let function =
+                        mem::transmute::<*const (), unsafe extern "C" fn()>(*fini as *const ());
+                    function();
+                }
+                report.handlers_run += 1;
+            }
+        }
+        dl::detach(self);
+        crash::detach(self);
+        let state = self
+            .state
+            .get_mut()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let objects = mem::take(&mut state.loaded_objects);
+        for object in objects.iter().rev() {
+            let path = &object.metadata.file_path;
+            info!("Unloading {}", path);
+            state.tls_registry.release(path);
+            let (released, kept) = mem::take(&mut state.mapped_memory)
+                .into_iter()
+                .partition::<Vec<_>, _>(|(owner, _)| owner == path);
+            state.mapped_memory = kept;
+            report.bytes_released += released
+                .iter()
+                .map(|(_, memory)| memory.length as u64)
+                .sum::<u64>();
+            report.objects_unmapped += 1;
+        }
+        if orderly {
+            debug_assert!(
+                state.mapped_memory.is_empty(),
+                "mappings of objects no longer loaded: {:?}",
+                state
+                    .mapped_memory
+                    .iter()
+                    .map(|(owner, _)| owner)
+                    .collect::<Vec<_>>()
+            );
+        }
+        report.bytes_released += mem::take(&mut state.mapped_memory)
+            .iter()
+            .map(|(_, memory)| memory.length as u64)
+            .sum::<u64>();
+        if let Some(stack) = state.stack.take() {
+            report.bytes_released += stack.size as u64;
+        }
+        report.bytes_released += state.tls_registry.release_static();
+        report.bytes_released += mem::take(&mut state.stub_objects)
+            .iter()
+            .map(|stubs| stubs.length() as u64)
+            .sum::<u64>();
+        state.memory_layout.clear();
+        state.init_functions.clear();
+        state.fini_functions.clear();
+        state.symbol_versions.clear();
+        state.pending_symbols = SymbolScope::default();
+        *self
+            .symbols
+            .get_mut()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = SymbolScope::default();
+        if report.objects_unmapped > 0 {
+            debug!(
+                "Shut down: {} object(s) unmapped, {} byte(s) released, {} fini function(s) run",
+                report.objects_unmapped, report.bytes_released, report.handlers_run
+            );
+        }
+        report
+    }
+
+    /// Needs exclusive access, as loads on other threads change the registry.
+    pub fn tls_registry(&mut self) -> &TlsRegistry {
+        &self
+            .state
+            .get_mut()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .tls_registry
+    }
+
+    /// The program header table of the program, the object holding the entry point, which its
+    /// auxiliary vector reports as AT_PHDR, AT_PHENT and AT_PHNUM.
+    pub fn program_headers(&self) -> Option<ProgramHeaderTable> {
+        let state = self.state();
+        state
+            .loaded_objects
+            .iter()
+            .rev()
+            .find(|object| {
+                let entry = object.metadata.elf_header.e_entry;
+                entry != 0 && object.base + entry == state.entry
+            })
+            .map(|object| object.program_headers)
+    }
+
+    /// The loaded objects, the entry point, TLS, unresolved symbols and phase times of the loads
+    /// so far, with the resident and dirty sizes of the objects when `rss`.
+    pub fn load_report(&self, rss: bool) -> LoadReport {
+        let memory: HashMap<String, ObjectMemory> = self.object_memory(rss).into_iter().collect();
+        let state = self.state();
+        let objects = state
+            .loaded_objects
+            .iter()
+            .map(|object| {
+                let metadata = &object.metadata;
+                let mut relocations = BTreeMap::new();
+                for rela in metadata.relocations.iter() {
+                    *relocations.entry(rela.type_name()).or_insert(0) += 1;
+                }
+                ObjectReport {
+                    path: metadata.file_path.clone(),
+                    soname: metadata.dynamic.soname.clone(),
+                    kind: object.kind,
+                    base: object.base,
+                    tls_size: state
+                        .tls_registry
+                        .module_of(&metadata.file_path)
+                        .map(|module| module.segment.memory_size)
+                        .unwrap_or(0),
+                    program_headers: object.program_headers,
+                    relocations,
+                    memory: memory.get(&metadata.file_path).copied().unwrap_or_default(),
+                    rules: object.rules.clone(),
+                }
+            })
+            .collect();
+        LoadReport {
+            objects,
+            entry: state.entry,
+            static_tls_size: state.tls_registry.static_size(),
+            unresolved_symbols: state.unresolved_symbols.clone(),
+            missing_versions: state.missing_versions.clone(),
+            phase_times: state.phase_times,
+        }
+    }
+
+    /// Mapped and writable bytes of each loaded object, in loading order. With `rss`, also the
+    /// resident and dirty bytes, walking /proc/self/smaps; a mapping listed as part of a larger
+    /// one there gets its share by size.
+    pub fn object_memory(&self, rss: bool) -> Vec<(String, ObjectMemory)> {
+        let state = self.state();
+        let smaps = if rss {
+            match smaps::read() {
+                Ok(entries) => Some(entries),
+                Err(err) => {
+                    warn!("{}", err);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        state
+            .loaded_objects
+            .iter()
+            .map(|object| {
+                let path = &object.metadata.file_path;
+                let mut memory = ObjectMemory::default();
+                let entries = state
+                    .memory_layout
+                    .iter()
+                    .filter(|entry| &entry.object == path);
+                for entry in entries {
+                    let size = entry.end - entry.start;
+                    memory.mapped += size;
+                    if entry.protection & libc::PROT_WRITE != 0 {
+                        memory.writable += size;
+                    }
+                    if let Some(smaps) = smaps.as_ref() {
+                        let (resident, dirty) = Elf64Loader::smaps_share(smaps, entry);
+                        *memory.resident.get_or_insert(0) += resident;
+                        *memory.dirty.get_or_insert(0) += dirty;
+                    }
+                }
+                (path.clone(), memory)
+            })
+            .collect()
+    }
+
+    /// Resident and dirty bytes of the smaps entries overlapping `entry`, in proportion to the
+    /// overlap.
+    fn smaps_share(smaps: &[smaps::SmapsEntry], entry: &MapEntry) -> (u64, u64) {
+        let mut share = (0, 0);
+        for smaps_entry in smaps.iter() {
+            let start = smaps_entry.start.max(entry.start);
+            let end = smaps_entry.end.min(entry.end);
+            if start >= end {
+                continue;
+            }
+            let part = |value: u64| {
+                (value as u128 * (end - start) as u128
+                    / (smaps_entry.end - smaps_entry.start) as u128) as u64
+            };
+            share.0 += part(smaps_entry.resident);
+            share.1 += part(smaps_entry.dirty);
+        }
+        share
+    }
+
+    /// Paths of the loaded objects with their reference counts, in loading order.
+    pub fn object_references(&self) -> Vec<(String, usize)> {
+        self.state()
+            .loaded_objects
+            .iter()
+            .map(|object| (object.metadata.file_path.clone(), object.references))
+            .collect()
+    }
+
+    /// Paths of the loaded objects object rules matched, with those rules, in loading order.
+    pub fn object_rules_applied(&self) -> Vec<(String, Vec<String>)> {
+        self.state()
+            .loaded_objects
+            .iter()
+            .filter(|object| !object.rules.is_empty())
+            .map(|object| (object.metadata.file_path.clone(), object.rules.clone()))
+            .collect()
+    }
+
+    /// Compares the file backed bytes of each loaded object with its file, keeping up to `limit`
+    /// differences per object. Objects whose file cannot be read are skipped.
+    pub fn diff_image(&self, limit: usize) -> Vec<ObjectImageDiff> {
+        let state = self.state();
+        state
+            .loaded_objects
+            .iter()
+            .filter_map(|object| {
+                let path = &object.metadata.file_path;
+                match fs::read(path) {
+                    Ok(file) => Some(unsafe {
+                        image_diff::diff_object(&object.metadata, object.base, &file, limit)
+                    }),
+                    Err(err) => {
+                        warn!("Not comparing the image of {}: {}", path, err);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    pub fn dump_got(&self) {
+        let state = self.state();
+        let symbols = self.symbols();
+        for object in state.loaded_objects.iter() {
+            let metadata = &object.metadata;
+            print!("GOT of {} (base: {:#X}", metadata.file_path, object.base);
+            if metadata.dynamic.plt_got > 0 {
+                print!(", DT_PLTGOT: {:#X}", metadata.dynamic.plt_got + object.base);
             }
+            println!(")");
+            let slots = metadata
+                .relocations
+                .iter()
+                .filter(|rela| rela.symbol_word() && !rela.copy());
+            for rela in slots {
+                let slot_address = rela.offset + object.base;
+                let actual = unsafe { ptr::read_unaligned(slot_address as *const u64) };
+                let requested = state.requested_symbol(metadata, rela);
+                let expected = symbols.find(None, &requested).map(|symbol| {
+                    (Elf64Loader::symbol_address(&symbol) as i64 + rela.symbol_addend()) as u64
+                });
+                let table = if metadata.plt_relocation(rela) {
+                    "PLT"
+                } else {
+                    "GOT"
+                };
+                let expected_string = expected
+                    .map(|value| format!("{:#X}", value))
+                    .unwrap_or_else(|| String::from("UNRESOLVED"));
+                let status = match expected {
+                    Some(value) if value == actual => "OK",
+                    Some(_) => "MISMATCH",
+                    None => "UNKNOWN",
+                };
+                println!(
+                    "{} slot {:#X} | {} | {} | expected: {} | actual: {:#X} | {}",
+                    table,
+                    slot_address,
+                    rela.type_name(),
+                    rela.symbol_name,
+                    expected_string,
+                    actual,
+                    status
+                );
+            }
+        }
+    }
+
+    /// The entry point and init functions, copied so the loaded code runs without holding the
+    /// registry.
+    fn handler_arguments(&self, last_stack_address: u64) -> HandlerArguments {
+        let state = self.state();
+        HandlerArguments {
+            entry: state.entry,
+            init_functions: state.init_functions.clone(),
+            fini_functions: state.fini_functions.clone(),
+            last_stack_address,
+            phase: &self.phase,
+            pause: self.options.pause_after_load,
+            thread_pointer: state
+                .tls_registry
+                .static_area()
+                .map(StaticTlsArea::thread_pointer),
+        }
+    }
+
+    /// Sets up the static TLS area the program starts with, its modules freshly initialized.
+    fn allocate_static_tls(&self) -> Result<(), DrowError> {
+        let stack_guard = self.startup_variables.stack_guard;
+        self.state().tls_registry.allocate_static(stack_guard)?;
+        Ok(())
+    }
+
+    pub fn execute_same_process(&self) -> Result<(), DrowError> {
+        self.check_startable()?;
+        self.allocate_stack()?;
+        let last_stack_address = match self.stack_top() {
+            Some(address) => address,
+            None => return Ok(()),
+        };
+        info!("Starting in the same process");
+        self.set_stack_end(last_stack_address);
+        self.allocate_static_tls()?;
+        let args = self.handler_arguments(last_stack_address);
+        dl::attach(self);
+        if self.options.crash_handler {
+            let stack_start = last_stack_address + 1 - self.options.stack_size as u64;
+            crash::arm(Some(self), stack_start, last_stack_address + 1);
+            crash::install();
+        }
+        if let Some(pause) = self.options.pause_after_load {
+            pause.hold()?;
+            eprintln!(
+                "Paused process {} after load, {}",
+                process::id(),
+                pause.resume_hint(process::id() as i32)
+            );
+        }
+        unsafe {
+            handle_same_process(&args as *const HandlerArguments);
         }
-        println!("Process with PID {} finished", finished_pid);
-        if libc::WIFEXITED(status) {
-            println!(
-                "Process exited normally with status: {}",
-                libc::WEXITSTATUS(status)
+        Ok(())
+    }
+
+    pub fn execute(&self) -> Result<ChildStatus, DrowError> {
+        self.check_startable()?;
+        let stack = ProgramStack::allocate(self.options.stack_size)?;
+        self.set_stack_end(stack.last_address as u64);
+        self.allocate_static_tls()?;
+        let args = self.handler_arguments(stack.address as u64);
+        dl::attach(self);
+        if self.options.crash_handler {
+            crash::arm(
+                None,
+                stack.address as u64,
+                stack.address as u64 + stack.size as u64,
             );
         } else {
-            println!("Process did not exit normally");
-            if libc::WIFSIGNALED(status) {
-                println!("Process terminated by a signal");
+            crash::disarm();
+        }
+        if let Some(pause) = self.options.pause_after_load {
+            pause.hold()?;
+        }
+        let child = spawn_child(
+            &stack,
+            child_entry,
+            ptr::addr_of!(args) as *mut libc::c_void,
+        );
+        if let Some(pause) = self.options.pause_after_load {
+            pause.release()?;
+        }
+        let child = child?;
+        info!("Process with PID {} started", child.pid());
+        if let Some(pause) = self.options.pause_after_load {
+            eprintln!(
+                "Paused process {} after load, {}",
+                child.pid(),
+                pause.resume_hint(child.pid())
+            );
+        }
+        let status = child.wait()?;
+        info!("Process with PID {} finished", child.pid());
+        match status {
+            ChildStatus::Exited(status) => {
+                info!("Process exited normally with status: {}", status);
             }
+            ChildStatus::Killed(signal) => match crash::take(signal) {
+                Some(crash) => error!("Process crashed: {}", self.describe_crash(&crash)),
+                None => warn!("Process terminated by signal {}", signal),
+            },
         }
+        Ok(status)
+    }
+}
+
+impl Drop for Elf64Loader {
+    fn drop(&mut self) {
+        self.shut_down(!std::thread::panicking());
     }
 }
@@ -0,0 +1,129 @@
+use std::fmt::{Arguments, Display, Formatter};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    const ALL: [Level; 5] = [
+        Level::Error,
+        Level::Warn,
+        Level::Info,
+        Level::Debug,
+        Level::Trace,
+    ];
+
+    /// Level selected by `-q` (negative verbosity) or a number of `-v` flags.
+    pub fn from_verbosity(verbosity: i32) -> Level {
+        let index = (Level::Warn as i32 - 1 + verbosity).clamp(0, Level::ALL.len() as i32 - 1);
+        Level::ALL[index as usize]
+    }
+
+    pub fn parse(value: &str) -> Result<Level, String> {
+        Level::ALL
+            .iter()
+            .find(|level| level.as_str().eq_ignore_ascii_case(value))
+            .copied()
+            .ok_or_else(|| {
+                format!(
+                    "Unknown log level: {}, expected error, warn, info, debug or trace",
+                    value
+                )
+            })
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+static MAX_LEVEL: AtomicUsize = AtomicUsize::new(Level::Warn as usize);
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+
+pub fn set_level(level: Level) {
+    MAX_LEVEL.store(level as usize, Ordering::Relaxed);
+}
+
+pub fn enabled(level: Level) -> bool {
+    level as usize <= MAX_LEVEL.load(Ordering::Relaxed)
+}
+
+/// Sends diagnostics to `path` instead of stderr. The file is appended to.
+pub fn set_log_file(path: &str) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    *LOG_FILE.lock().unwrap_or_else(|err| err.into_inner()) = Some(file);
+    Ok(())
+}
+
+pub fn write(level: Level, target: &str, args: Arguments<'_>) {
+    if !enabled(level) {
+        return;
+    }
+    emit(&format!("[{} {}] {}\n", level, target, args));
+}
+
+/// Writes the output of a debug category asked for on the command line, whatever the level.
+pub fn write_category(category: &str, args: Arguments<'_>) {
+    emit(&format!("[{}] {}\n", category, args));
+}
+
+fn emit(line: &str) {
+    let mut log_file = LOG_FILE.lock().unwrap_or_else(|err| err.into_inner());
+    let _ = match log_file.as_mut() {
+        Some(file) => file.write_all(line.as_bytes()),
+        None => io::stderr().write_all(line.as_bytes()),
+    };
+}
+
+#[macro_export]
+macro_rules! log {
+    ($level:expr, $($arg:tt)+) => {
+        $crate::log::write($level, module_path!(), format_args!($($arg)+))
+    };
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Error, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Warn, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Info, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Debug, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => { $crate::log!($crate::log::Level::Trace, $($arg)+) };
+}
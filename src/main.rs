@@ -1,57 +1,811 @@
-use crate::cache::LibraryCache;
-use crate::dynamic::Elf64Dynamic;
-use crate::elf::*;
-use crate::ld_path_loader::LdPathLoader;
-use crate::loader::{DependenciesResolver, Elf64Loader};
+use crate::cli::{Command, Config};
+use drow::bundle::Bundle;
+use drow::cache::DEFAULT_CACHE_PATH;
+use drow::capabilities::{self, CapabilityReport, Support};
+use drow::core_file::CoreFile;
+use drow::debuginfo;
+use drow::dependency_graph::DependencyGraph;
+use drow::loader::{DependenciesResolver, Elf64Loader, Elf64LoaderBuilder};
+use drow::log::{self, Level};
+use drow::memory_elf::MemoryBackedElf;
+use drow::offset_reader::OffsetReader;
+use drow::progress::TerminalProgress;
+use drow::summary::{Summary, SummaryFormat};
+use drow::sysroot::Sysroot;
+use drow::table::Table;
+use drow::versions;
+use drow::writer::{self, Elf64Writer, SectionData};
+use drow::{
+    elf, error, info, printer, warn, DrowError, Elf32Metadata, Elf64Metadata, ELF_CLASS_32,
+    ELF_MAGIC, ELF_TYPE_CORE,
+};
 use std::env;
-use std::fs::File;
-use std::io::BufReader;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read};
 
-mod cache;
-mod dynamic;
-mod elf;
-mod ld_path_loader;
-mod loader;
-mod printer;
-mod string_tables;
-mod syscall;
+mod bench;
+mod cli;
+mod config_file;
+mod report;
+mod settings;
+mod shell;
 
-const CACHE_PATH: &str = "/etc/ld.so.cache";
+// Exit statuses reserved for drow itself, following env, nice and timeout. Anything else is the
+// status of the loaded program.
+const EXIT_USAGE: i32 = 125;
+const EXIT_LOAD_FAILED: i32 = 126;
+const EXIT_NOT_FOUND: i32 = 127;
+
+/// Every error is either a file that is not there or one that could not be loaded. Listed in
+/// full so a new variant gets a status on purpose.
+fn exit_code(err: &DrowError) -> i32 {
+    match err {
+        DrowError::Io { source, .. } if source.kind() == ErrorKind::NotFound => EXIT_NOT_FOUND,
+        DrowError::UnresolvedLibrary { .. }
+        | DrowError::UnresolvedLibraries { .. }
+        | DrowError::UnmappedLibraries { .. } => EXIT_NOT_FOUND,
+        DrowError::Io { .. }
+        | DrowError::Read { .. }
+        | DrowError::NotElf { .. }
+        | DrowError::WrongClass { .. }
+        | DrowError::WrongEncoding { .. }
+        | DrowError::WrongMachine { .. }
+        | DrowError::WrongVersion { .. }
+        | DrowError::WrongHeaderSize { .. }
+        | DrowError::Malformed { .. }
+        | DrowError::InvalidString { .. }
+        | DrowError::UnsupportedRelocation { .. }
+        | DrowError::MapFailed { .. }
+        | DrowError::Syscall { .. }
+        | DrowError::Edit { .. }
+        | DrowError::NotLoadable { .. }
+        | DrowError::NotLoaded { .. }
+        | DrowError::InsufficientMemory { .. }
+        | DrowError::Manifest { .. }
+        | DrowError::DigestMismatch { .. }
+        | DrowError::Bundle { .. }
+        | DrowError::Reentered { .. }
+        | DrowError::MissingVersions { .. } => EXIT_LOAD_FAILED,
+    }
+}
+
+fn open(path: &str) -> Result<OffsetReader, DrowError> {
+    OffsetReader::open(path).map_err(|source| DrowError::Io {
+        path: path.to_string(),
+        source,
+    })
+}
+
+/// Reads `image` when the file came from stdin or --fd, or else opens `path`.
+fn open_input(path: &str, image: Option<&MemoryBackedElf>) -> Result<OffsetReader, DrowError> {
+    match image {
+        Some(image) => image.reader(),
+        None => open(path),
+    }
+}
+
+fn summarize(path: &str, debuginfo_dirs: &[String]) -> Result<Summary, String> {
+    let mut reader = open(path).map_err(|err| err.to_string())?;
+    let class = elf::read_class(path, &mut reader).map_err(|err| err.to_string())?;
+    let elf_metadata = if class == ELF_CLASS_32 {
+        Elf32Metadata::load(&path.to_string(), &mut reader).map(|metadata| metadata.to_elf64())
+    } else {
+        Elf64Metadata::load(&path.to_string(), &mut reader)
+    }
+    .map_err(|err| err.to_string())?;
+    Summary::load(&elf_metadata, &mut reader, debuginfo_dirs)
+}
+
+fn print_summaries(paths: &[String], format: SummaryFormat, debuginfo_dirs: &[String]) -> usize {
+    let mut failures = 0;
+    if let Some(header) = Summary::header(format) {
+        println!("{}", header);
+    }
+    for path in paths.iter() {
+        match summarize(path, debuginfo_dirs) {
+            Ok(summary) => println!("{}", summary.format(format)),
+            Err(err) => {
+                failures += 1;
+                println!("{}", Summary::format_error(path, &err, format));
+            }
+        }
+    }
+    failures
+}
+
+fn load_metadata(
+    file_path: &String,
+    reader: &mut OffsetReader,
+    stats: bool,
+) -> Result<Elf64Metadata, DrowError> {
+    let elf_metadata = Elf64Metadata::load_with_string_tables(file_path, reader, true)?;
+    if stats {
+        for (index, count) in elf_metadata.string_table_reads.iter() {
+            println!("String table in section {} read {} time(s)", index, count);
+        }
+    }
+    Ok(elf_metadata)
+}
+
+fn loader_builder(config: &Config) -> Elf64LoaderBuilder {
+    let mut builder = Elf64Loader::builder()
+        .options(config.load_options)
+        .fuzzy_soname(config.fuzzy_soname);
+    if let Some(sysroot) = config.sysroot.as_ref() {
+        builder = builder.sysroot(sysroot);
+    }
+    if config.offline {
+        builder = builder.offline(&config.search_dirs);
+    }
+    if let Some(manifest) = config.manifest.as_ref() {
+        builder = builder
+            .manifest(manifest.clone())
+            .manifest_fallback(config.manifest_fallback);
+    }
+    if let Some(libc) = config.libc {
+        builder = builder.libc(libc);
+    }
+    if let Some(argv0) = config.argv0.as_ref() {
+        builder = builder.argv0(argv0);
+    }
+    if !config.object_rules.is_empty() {
+        builder = builder.object_rules(config.object_rules.clone());
+    }
+    for soname in config.stubs.iter() {
+        builder = builder.stub(soname);
+    }
+    if let Some(directory) = config.prelink_cache.as_ref() {
+        builder = builder.prelink_cache(directory);
+    }
+    for path in config.search_paths.iter() {
+        builder = builder.search_path(path);
+    }
+    builder
+}
+
+fn inspect(
+    config: &Config,
+    file_path: &String,
+    image: Option<&MemoryBackedElf>,
+    color: bool,
+) -> Result<i32, DrowError> {
+    let mut reader = open_input(file_path, image)?;
+    if elf::read_class(file_path, &mut reader)? == ELF_CLASS_32 {
+        let elf_metadata = Elf32Metadata::load(file_path, &mut reader)?.to_elf64();
+        println!("Class: ELF32");
+        printer::print(
+            &elf_metadata,
+            &mut reader,
+            config.log_level >= Level::Info,
+            color,
+        );
+        return Ok(0);
+    }
+    let mut elf_metadata = load_metadata(file_path, &mut reader, config.stats)?;
+    if elf_metadata.elf_header.e_type == ELF_TYPE_CORE {
+        println!("{}", elf_metadata.elf_header);
+        match CoreFile::load(&elf_metadata, &mut reader) {
+            Ok(core_file) => core_file.print(color),
+            Err(err) => {
+                error!("{}", err);
+                return Ok(EXIT_LOAD_FAILED);
+            }
+        }
+        return Ok(0);
+    }
+    if let Some(path) =
+        debuginfo::load_symbols(&mut elf_metadata, &mut reader, &config.debuginfo_dirs)
+    {
+        println!("Debuginfo: {}", path);
+    }
+    printer::print(
+        &elf_metadata,
+        &mut reader,
+        config.log_level >= Level::Info,
+        color,
+    );
+    Ok(0)
+}
+
+fn resolve(
+    config: &Config,
+    file_path: &String,
+    image: Option<&MemoryBackedElf>,
+    dependencies_resolver: &mut DependenciesResolver,
+    color: bool,
+) -> Result<i32, DrowError> {
+    let mut reader = open_input(file_path, image)?;
+    let elf_metadata = Elf64Metadata::load(file_path, &mut reader)?;
+    let graph = DependencyGraph::build(dependencies_resolver, &elf_metadata)?;
+    if let Some(dot_path) = config.dep_graph.as_ref() {
+        File::create(dot_path)
+            .and_then(|mut dot_file| graph.write_dot(&mut dot_file))
+            .map_err(|source| DrowError::Io {
+                path: dot_path.clone(),
+                source,
+            })?;
+        println!("Dependency graph written to {}", dot_path);
+        return Ok(0);
+    }
+    if let Some(sysroot) = dependencies_resolver.sysroot() {
+        println!("Sysroot: {}", sysroot.root().display());
+    }
+    if let Some(directories) = dependencies_resolver.search_directories() {
+        for directory in directories.directories() {
+            println!("Search directory: {}", directory.display());
+        }
+    }
+    let status = print_libraries(&graph, color);
+    if print_missing_versions(&graph, &elf_metadata, color) && status == 0 {
+        return Ok(EXIT_LOAD_FAILED);
+    }
+    Ok(status)
+}
+
+/// Prints the symbol versions the libraries found lack, with the symbols requiring each, and
+/// returns whether there were any.
+fn print_missing_versions(
+    graph: &DependencyGraph,
+    elf_metadata: &Elf64Metadata,
+    color: bool,
+) -> bool {
+    let libraries: Vec<Elf64Metadata> = graph
+        .nodes
+        .iter()
+        .skip(1)
+        .filter_map(|node| node.path.as_ref())
+        .filter_map(|path| {
+            let mut reader = open(path).ok()?;
+            Elf64Metadata::load(path, &mut reader).ok()
+        })
+        .collect();
+    let objects: Vec<&Elf64Metadata> = std::iter::once(elf_metadata)
+        .chain(libraries.iter())
+        .collect();
+    let missing = versions::missing_versions(&objects);
+    if missing.is_empty() {
+        return false;
+    }
+    let mut table = Table::new(&["Requester", "Version", "Library", "Symbols"]);
+    for missing in missing.iter() {
+        table.add_row(vec![
+            missing.requester.clone(),
+            missing.version.clone(),
+            missing.provider.clone(),
+            missing.symbols.join(", "),
+        ]);
+    }
+    println!("Missing symbol versions:");
+    print!("{}", table.render(color));
+    true
+}
+
+/// Prints one row per library and returns `EXIT_NOT_FOUND` if any was not found.
+fn print_libraries(graph: &DependencyGraph, color: bool) -> i32 {
+    let mut libraries = Table::new(&["Library", "Path", "Origin"]);
+    for node in graph.nodes.iter() {
+        libraries.add_row(vec![
+            node.name.clone(),
+            node.path.clone().unwrap_or_else(|| String::from("-")),
+            format!("{:?}", node.origin),
+        ]);
+    }
+    print!("{}", libraries.render(color));
+    if graph.nodes.iter().any(|node| node.path.is_none()) {
+        EXIT_NOT_FOUND
+    } else {
+        0
+    }
+}
+
+/// Writes a copy of the file with the requested changes applied. The copy keeps the permissions
+/// of the original so an edited executable can be run directly.
+fn edit(config: &Config, file_path: &String) -> Result<i32, DrowError> {
+    let mut reader = open(file_path)?;
+    let mut elf_metadata = Elf64Metadata::load(file_path, &mut reader)?;
+    let mut sections_data = SectionData::load(&elf_metadata, &mut reader)?;
+    for name in config.remove_sections.iter() {
+        writer::remove_section(&mut elf_metadata, &mut sections_data, name)?;
+    }
+    if let Some(rpath) = config.set_rpath.as_ref() {
+        writer::set_rpath(&mut elf_metadata, &mut sections_data, rpath)?;
+    }
+    let output = config.output.clone().unwrap_or_default();
+    let io_error = |source| DrowError::Io {
+        path: output.clone(),
+        source,
+    };
+    let permissions = fs::metadata(file_path)
+        .map_err(|source| DrowError::Io {
+            path: file_path.clone(),
+            source,
+        })?
+        .permissions();
+    let mut file = File::create(&output).map_err(io_error)?;
+    Elf64Writer::write(&elf_metadata, &sections_data, &mut file).map_err(io_error)?;
+    fs::set_permissions(&output, permissions).map_err(io_error)?;
+    Ok(0)
+}
+
+/// Checks `elf_metadata` and the libraries found for it against what the loader implements.
+fn analyze_capabilities(
+    config: &Config,
+    elf_metadata: &Elf64Metadata,
+    resolver: &mut DependenciesResolver,
+) -> Result<CapabilityReport, DrowError> {
+    let graph = DependencyGraph::build(resolver, elf_metadata)?;
+    let libraries: Vec<Elf64Metadata> = graph
+        .nodes
+        .iter()
+        .skip(1)
+        .filter_map(|node| node.path.as_ref())
+        .filter_map(|path| {
+            let mut reader = open(path).ok()?;
+            Elf64Metadata::load(path, &mut reader).ok()
+        })
+        .collect();
+    let unresolved: Vec<String> = graph
+        .nodes
+        .iter()
+        .filter(|node| node.path.is_none())
+        .map(|node| node.name.clone())
+        .collect();
+    Ok(capabilities::analyze(
+        elf_metadata,
+        &libraries,
+        &unresolved,
+        config.load_options.best_effort,
+    ))
+}
+
+/// Prints the features `file_path` needs, why those that are not supported are not, and the
+/// verdict.
+fn print_capabilities(file_path: &str, report: &CapabilityReport, color: bool) {
+    let mut table = Table::new(&["Feature", "Support", "Object", "Detail"]);
+    for requirement in report.requirements.iter() {
+        table.add_row(vec![
+            requirement.feature.to_string(),
+            requirement.feature.support().to_string(),
+            requirement.object.clone(),
+            requirement.detail.clone(),
+        ]);
+    }
+    if !table.is_empty() {
+        print!("{}", table.render(color));
+    }
+    let mut explained = Vec::new();
+    for requirement in report.requirements.iter() {
+        let feature = requirement.feature;
+        if feature.support() != Support::Supported && !explained.contains(&feature) {
+            println!("{} is {}: {}", feature, feature.support(), feature.reason());
+            explained.push(feature);
+        }
+    }
+    println!("{}: {}", file_path, report.verdict());
+}
+
+fn run(
+    config: &Config,
+    file_path: &String,
+    image: Option<&MemoryBackedElf>,
+    dependencies_resolver: &mut Option<DependenciesResolver>,
+    color: bool,
+) -> Result<i32, DrowError> {
+    if let Some(bundle) = config.replay.as_ref() {
+        info!(
+            "Replaying {} as recorded by drow {}: {}",
+            bundle.program.0,
+            bundle.version,
+            bundle.arguments.join(" ")
+        );
+        if bundle.version != env!("CARGO_PKG_VERSION") {
+            warn!(
+                "The bundle was recorded by drow {}, this is drow {}",
+                bundle.version,
+                env!("CARGO_PKG_VERSION")
+            );
+        }
+    }
+    let mut reader = open_input(file_path, image)?;
+    let elf_metadata = load_metadata(file_path, &mut reader, config.stats)?;
+    if elf_metadata.elf_header.e_type == ELF_TYPE_CORE {
+        error!("Core files can only be inspected, not loaded");
+        return Ok(EXIT_LOAD_FAILED);
+    }
+    let mut builder = loader_builder(config);
+    if config.progress && unsafe { libc::isatty(libc::STDERR_FILENO) == 1 } {
+        builder = builder.progress(TerminalProgress);
+    }
+    let mut resolver = dependencies_resolver
+        .take()
+        .unwrap_or_else(|| builder.dependencies_resolver());
+    let report = analyze_capabilities(config, &elf_metadata, &mut resolver)?;
+    if config.check {
+        print_capabilities(file_path, &report, color);
+        *dependencies_resolver = Some(resolver);
+        return Ok(if report.likely_to_work() {
+            0
+        } else {
+            EXIT_LOAD_FAILED
+        });
+    }
+    if !report.likely_to_work() {
+        warn!(
+            "{} {}, loading it anyway (--check tells more)",
+            file_path,
+            report.verdict()
+        );
+    }
+    let elf_loader = builder.build_with(resolver)?;
+    let status = load_and_execute(config, file_path, image, &elf_metadata, &elf_loader);
+    *dependencies_resolver = Some(elf_loader.into_dependencies_resolver());
+    status
+}
+
+/// Copies what the load of `file_path` read into the bundle directory of --record: the program,
+/// every library by the names it was required by, and the library cache when it was consulted.
+fn record(
+    config: &Config,
+    directory: &str,
+    file_path: &str,
+    elf_metadata: &Elf64Metadata,
+    elf_loader: &Elf64Loader,
+) -> Result<(), DrowError> {
+    let graph = DependencyGraph::build(
+        &mut loader_builder(config).dependencies_resolver(),
+        elf_metadata,
+    )?;
+    let mut libraries: Vec<(String, String)> = graph
+        .nodes
+        .iter()
+        .skip(1)
+        .filter_map(|node| Some((node.name.clone(), node.path.clone()?)))
+        .collect();
+    // Preloads and replacements are loaded without being required by those names.
+    for (path, _) in elf_loader.object_references().into_iter() {
+        if path == file_path {
+            continue;
+        }
+        let file_name = path.rsplit('/').next().unwrap_or(&path).to_string();
+        let soname = open(&path)
+            .ok()
+            .and_then(|mut reader| Elf64Metadata::load(&path, &mut reader).ok())
+            .and_then(|metadata| metadata.dynamic.soname);
+        for name in soname.into_iter().chain(std::iter::once(file_name)) {
+            libraries.push((name, path.clone()));
+        }
+    }
+    let cache = match config.sysroot.as_ref() {
+        _ if config.offline || config.manifest.is_some() => None,
+        Some(root) => Some(Sysroot::new(root).resolve(DEFAULT_CACHE_PATH)),
+        None => Some(DEFAULT_CACHE_PATH.to_string()),
+    }
+    .filter(|path| fs::metadata(path).is_ok());
+    let environment: Vec<(String, String)> = env::vars()
+        .filter(|(name, _)| name == "LD_LIBRARY_PATH" || name.starts_with("DROW_"))
+        .collect();
+    let base = elf_loader
+        .memory_map_entries()
+        .iter()
+        .map(|entry| entry.start)
+        .min()
+        .unwrap_or(config.load_options.base_address)
+        .min(config.load_options.base_address);
+    let bundle = Bundle::record(
+        directory,
+        file_path,
+        cache.as_deref(),
+        &libraries,
+        &env::args().collect::<Vec<String>>(),
+        &environment,
+        base,
+    )?;
+    println!(
+        "Recorded {} file(s) to {}",
+        bundle.libraries.len() + 1 + bundle.cache.iter().count(),
+        directory
+    );
+    Ok(())
+}
+
+fn print_object_memory(elf_loader: &Elf64Loader, rss: bool) {
+    let optional = |value: Option<u64>| {
+        value
+            .map(|value| value.to_string())
+            .unwrap_or_else(|| String::from("-"))
+    };
+    let mut table = Table::new(&["Object", "Mapped", "Writable", "Resident", "Dirty"]);
+    for (object, memory) in elf_loader.object_memory(rss).into_iter() {
+        table.add_row(vec![
+            object,
+            memory.mapped.to_string(),
+            memory.writable.to_string(),
+            optional(memory.resident),
+            optional(memory.dirty),
+        ]);
+    }
+    print!("{}", table.render(false));
+}
+
+/// Differences between a loaded object and its file printed at most, per object.
+const DIFF_IMAGE_LIMIT: usize = 64;
+/// Bytes of each difference printed at most.
+const DIFF_IMAGE_BYTES: usize = 16;
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    let shown: Vec<String> = bytes
+        .iter()
+        .take(DIFF_IMAGE_BYTES)
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    if bytes.len() > DIFF_IMAGE_BYTES {
+        format!("{} ..", shown.join(" "))
+    } else {
+        shown.join(" ")
+    }
+}
+
+/// Prints the bytes drow changed in each loaded object and the relocations writing them.
+/// Returns the number of changes no relocation explains.
+fn print_image_diff(elf_loader: &Elf64Loader) -> usize {
+    let mut unexplained = 0;
+    for object in elf_loader.diff_image(DIFF_IMAGE_LIMIT).iter() {
+        println!(
+            "Image of {} (base: {:#X}): {} changed range(s)",
+            object.path,
+            object.base,
+            object.differences.len() + object.omitted
+        );
+        for difference in object.differences.iter() {
+            let explanation = if difference.unexplained {
+                format!("UNEXPLAINED {}", difference.relocations.join(", "))
+            } else {
+                difference.relocations.join(", ")
+            };
+            println!(
+                "  {:#X} (file {:#X}, {} bytes): {} -> {} | {}",
+                difference.address,
+                difference.file_offset,
+                difference.before.len(),
+                hex_bytes(&difference.before),
+                hex_bytes(&difference.after),
+                explanation.trim_end()
+            );
+        }
+        if object.omitted > 0 {
+            println!("  ... {} more range(s) not shown", object.omitted);
+        }
+        unexplained += object.unexplained;
+    }
+    if unexplained > 0 {
+        warn!(
+            "{} changed range(s) are written by no relocation, which is a loader bug",
+            unexplained
+        );
+    }
+    unexplained
+}
+
+fn load_and_execute(
+    config: &Config,
+    file_path: &String,
+    image: Option<&MemoryBackedElf>,
+    elf_metadata: &Elf64Metadata,
+    elf_loader: &Elf64Loader,
+) -> Result<i32, DrowError> {
+    for library in config.preload.iter() {
+        elf_loader.preload(library)?;
+    }
+    if let Some(image) = image {
+        elf_loader.load_from_bytes(image)?;
+    } else if config.from_memory {
+        let bytes = std::fs::read(file_path).map_err(|source| DrowError::Io {
+            path: file_path.clone(),
+            source,
+        })?;
+        let image = MemoryBackedElf::from_bytes(file_path, &bytes)?;
+        elf_loader.load_from_bytes(&image)?;
+    } else {
+        elf_loader.load(elf_metadata)?;
+    }
+    if config.stats {
+        for (object, pages) in elf_loader.prefaulted_pages().iter() {
+            println!("Prefaulted {} page(s) in {}", pages, object);
+        }
+        for (object, references) in elf_loader.object_references().iter() {
+            println!("{} reference(s) to {}", references, object);
+        }
+        let lookups = elf_loader.symbol_lookups();
+        println!(
+            "{} symbol lookup(s) for relocations, {} saved",
+            lookups.lookups, lookups.saved
+        );
+    }
+    for (object, rules) in elf_loader.object_rules_applied().iter() {
+        println!("Object rules applied to {}: {}", object, rules.join("; "));
+    }
+    if config.stats || config.rss_report {
+        print_object_memory(elf_loader, config.rss_report);
+    }
+    if let Some(directory) = config.record.as_ref() {
+        record(config, directory, file_path, elf_metadata, elf_loader)?;
+    }
+    if let Some(destination) = config.report.as_ref() {
+        report::write(destination, file_path, elf_loader, config.rss_report)?;
+    }
+    if config.dump_got {
+        elf_loader.dump_got();
+    }
+    if config.diff_image {
+        print_image_diff(elf_loader);
+    }
+    if config.maps {
+        elf_loader.allocate_stack()?;
+        elf_loader.print_maps();
+    }
+    if config.no_exec {
+        info!("Loaded {}, not running it", file_path);
+        return Ok(0);
+    }
+    if config.fork {
+        Ok(elf_loader.execute()?.exit_code())
+    } else {
+        elf_loader.execute_same_process()?;
+        Ok(0)
+    }
+}
+
+/// The file given by --fd or piped to stdin, read through a descriptor instead of its path.
+struct InputImage {
+    /// Of the file among the paths, where its name stands.
+    index: usize,
+    image: MemoryBackedElf,
+}
+
+/// Expands `-` to the paths listed on stdin, one per line, or to the file itself when stdin is
+/// an ELF file, copied into memory. Adds the file of --fd in front.
+fn input_paths(
+    paths: &[String],
+    fd: Option<i32>,
+) -> Result<(Vec<String>, Option<InputImage>), DrowError> {
+    let mut result = Vec::new();
+    let mut input = match fd {
+        Some(fd) => {
+            let image = MemoryBackedElf::from_descriptor(fd)?;
+            result.push(image.name().to_string());
+            Some(InputImage { index: 0, image })
+        }
+        None => None,
+    };
+    for path in paths.iter() {
+        if path != "-" {
+            result.push(path.clone());
+            continue;
+        }
+        let mut bytes = Vec::new();
+        io::stdin()
+            .lock()
+            .read_to_end(&mut bytes)
+            .map_err(|source| DrowError::Io {
+                path: String::from("<stdin>"),
+                source,
+            })?;
+        if bytes.starts_with(&ELF_MAGIC) && input.is_none() {
+            let image = MemoryBackedElf::from_bytes("stdin", &bytes)?;
+            result.push(image.name().to_string());
+            input = Some(InputImage {
+                index: result.len() - 1,
+                image,
+            });
+            continue;
+        }
+        for line in String::from_utf8_lossy(&bytes).lines() {
+            let line = line.trim();
+            if !line.is_empty() {
+                result.push(line.to_string());
+            }
+        }
+    }
+    Ok((result, input))
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Path argument should be provided");
-        std::process::exit(-1);
+    let config = match Config::parse(&args) {
+        Ok(Some(config)) => config,
+        Ok(None) => {
+            print!("{}", cli::usage());
+            return;
+        }
+        Err(err) => {
+            error!("{} (run drow --help for usage)", err);
+            std::process::exit(EXIT_USAGE);
+        }
+    };
+    log::set_level(config.log_level);
+    if let Some(path) = config.log_file.as_ref() {
+        if let Err(err) = log::set_log_file(path) {
+            error!("Unable to open log file {}: {}", path, err);
+            std::process::exit(EXIT_USAGE);
+        }
     }
-    let ld_library_path = env::var("LD_LIBRARY_PATH").ok();
-    if let Some(path) = ld_library_path.as_ref() {
-        println!("LD_LIBRARY_PATH: {}", path);
+    if config.show_config {
+        print!("{}", config.show());
+        return;
+    }
+    let (paths, input) = input_paths(&config.paths, config.fd).unwrap_or_else(|err| {
+        error!("{}", err);
+        std::process::exit(exit_code(&err));
+    });
+    if input.is_some()
+        && (config.summary.is_some()
+            || !matches!(
+                config.command,
+                Command::Inspect | Command::Resolve | Command::Run
+            ))
+    {
+        error!("Only inspect, resolve and run read a file from stdin or --fd");
+        std::process::exit(EXIT_USAGE);
+    }
+    if let Err(err) = config.check_path_count(paths.len()) {
+        error!("{} (run drow --help for usage)", err);
+        std::process::exit(EXIT_USAGE);
+    }
+    if config.command == Command::Run {
+        if !config.program_arguments.is_empty() {
+            warn!("Program arguments are not passed to the loaded program yet");
+        }
+        if !config.bind_now {
+            warn!("Lazy binding is not supported, all symbols are bound at load time");
+        }
+    }
+    let mut failures = 0;
+    let mut exit_status = 0;
+    if let (Command::Inspect, Some(format)) = (config.command, config.summary) {
+        failures = print_summaries(&paths, format, &config.debuginfo_dirs);
+        if failures > 0 {
+            exit_status = EXIT_LOAD_FAILED;
+        }
     } else {
-        println!("WARNING: LD_LIBRARY_PATH not set.");
-    }
-    let file_path = &args[1];
-    let elf_file = File::open(file_path).expect("Unable to open elf file");
-    let mut reader = BufReader::new(elf_file);
-    let elf_metadata: Elf64Metadata = Elf64Metadata::load(file_path, &mut reader).unwrap();
-    let cache = LibraryCache::load(CACHE_PATH).expect("Unable to load cache");
-    let mut ld_path_loader = ld_library_path.as_ref().map(|a| LdPathLoader::new(a));
-    printer::print(&elf_metadata, &mut reader);
-    /*
-    for symbol in elf_metadata.symbol_table.iter() {
-        println!("{}", symbol);
-    }
-    println!("Dynamic symbol table:");
-    for symbol in elf_metadata.dynamic_symbol_table.iter() {
-        println!("{}", symbol);
-    }
-    println!("Relocations:");
-    for relocation in elf_metadata.relocations.iter() {
-        println!("{}", relocation);
-    }
-     */
-    let mut dependencies_resolver = DependenciesResolver::new(cache, ld_path_loader);
-    let mut elf_loader = Elf64Loader::new(dependencies_resolver);
-    elf_loader.load(&elf_metadata);
-    elf_loader.execute_same_process();
+        let color = config.color.enabled();
+        let mut resolver = None;
+        for (index, path) in paths.iter().enumerate() {
+            if paths.len() > 1 {
+                if index > 0 {
+                    println!();
+                }
+                println!("==> {} <==", path);
+            }
+            let image = input
+                .as_ref()
+                .filter(|input| input.index == index)
+                .map(|input| &input.image);
+            let result = match config.command {
+                Command::Inspect => inspect(&config, path, image, color),
+                Command::Resolve => {
+                    let resolver = resolver
+                        .get_or_insert_with(|| loader_builder(&config).dependencies_resolver());
+                    resolve(&config, path, image, resolver, color)
+                }
+                Command::Run => run(&config, path, image, &mut resolver, color),
+                Command::Shell => shell::run(&config, path, color),
+                Command::Bench => bench::run(&config, path, color),
+                Command::Edit => edit(&config, path),
+            };
+            let status = result.unwrap_or_else(|err| {
+                error!("{}", err);
+                exit_code(&err)
+            });
+            if status != 0 {
+                failures += 1;
+                if exit_status == 0 {
+                    exit_status = status;
+                }
+            }
+        }
+    }
+    if paths.len() > 1 {
+        eprintln!("{} ok, {} failed", paths.len() - failures, failures);
+    }
+    std::process::exit(exit_status);
 }
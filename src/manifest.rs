@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::Path;
+
+use crate::error::DrowError;
+use crate::sha256;
+
+/// The file a manifest pins a soname to, and the SHA-256 digest it must have when one is given.
+#[derive(Clone, Debug)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub sha256: Option<[u8; 32]>,
+}
+
+/// Maps sonames to files, one `soname = /absolute/path` per line, optionally followed by
+/// `sha256=<digest>`. Blank lines and lines starting with `#` are ignored.
+#[derive(Clone, Debug)]
+pub struct Manifest {
+    pub path: String,
+    entries: HashMap<String, ManifestEntry>,
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub(crate) fn parse_digest(value: &str) -> Option<[u8; 32]> {
+    if value.len() != 64 || !value.is_ascii() {
+        return None;
+    }
+    let mut digest = [0; 32];
+    for (byte, pair) in digest.iter_mut().zip(value.as_bytes().chunks_exact(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+impl Manifest {
+    pub fn load(path: &str) -> Result<Manifest, DrowError> {
+        let text = fs::read_to_string(path).map_err(|source| DrowError::Io {
+            path: path.to_string(),
+            source,
+        })?;
+        Manifest::parse(path, &text)
+    }
+
+    pub fn parse(path: &str, text: &str) -> Result<Manifest, DrowError> {
+        let error = |line: usize, reason: String| DrowError::Manifest {
+            path: path.to_string(),
+            reason: format!("line {}: {}", line, reason),
+        };
+        let mut entries = HashMap::new();
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (soname, value) = line
+                .split_once('=')
+                .ok_or_else(|| error(line_number, String::from("expected soname = path")))?;
+            let soname = soname.trim();
+            let mut fields = value.split_whitespace();
+            let file = fields.next().unwrap_or_default();
+            if soname.is_empty() || file.is_empty() {
+                return Err(error(line_number, String::from("expected soname = path")));
+            }
+            if !Path::new(file).is_absolute() {
+                return Err(error(
+                    line_number,
+                    format!("{} is not an absolute path", file),
+                ));
+            }
+            let sha256 = match fields.next() {
+                None => None,
+                Some(field) => {
+                    let digest = field
+                        .strip_prefix("sha256=")
+                        .and_then(parse_digest)
+                        .ok_or_else(|| {
+                            error(
+                                line_number,
+                                format!("expected sha256=<64 hex digits>, found {}", field),
+                            )
+                        })?;
+                    Some(digest)
+                }
+            };
+            if let Some(field) = fields.next() {
+                return Err(error(line_number, format!("unexpected {}", field)));
+            }
+            let entry = ManifestEntry {
+                path: file.to_string(),
+                sha256,
+            };
+            if entries.insert(soname.to_string(), entry).is_some() {
+                return Err(error(line_number, format!("{} is mapped twice", soname)));
+            }
+        }
+        Ok(Manifest {
+            path: path.to_string(),
+            entries,
+        })
+    }
+
+    pub fn get(&self, soname: &str) -> Option<&ManifestEntry> {
+        self.entries.get(soname)
+    }
+
+    /// Checks the file `soname` is mapped to against its digest, when the manifest has one.
+    pub fn verify(&self, soname: &str, entry: &ManifestEntry) -> Result<(), DrowError> {
+        let expected = match entry.sha256 {
+            Some(expected) => expected,
+            None => return Ok(()),
+        };
+        let io_error = |source| DrowError::Io {
+            path: entry.path.clone(),
+            source,
+        };
+        let actual =
+            sha256::digest(File::open(&entry.path).map_err(io_error)?).map_err(io_error)?;
+        if actual != expected {
+            return Err(DrowError::DigestMismatch {
+                manifest: self.path.clone(),
+                soname: soname.to_string(),
+                path: entry.path.clone(),
+                actual: hex(&actual),
+                expected: hex(&expected),
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,95 @@
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+
+use crate::error::DrowError;
+use crate::loader::DescriptorProvider;
+use crate::offset_reader::OffsetReader;
+use crate::{syscall, Elf64Metadata};
+
+/// A file read and mapped through a descriptor drow holds rather than through its path: a sealed
+/// in-memory copy, or a descriptor handed over by a supervisor.
+pub struct MemoryBackedElf {
+    name: String,
+    file: File,
+}
+
+impl MemoryBackedElf {
+    pub fn from_bytes(name: &str, bytes: &[u8]) -> Result<MemoryBackedElf, DrowError> {
+        let file_descriptor =
+            syscall::memfd_create_checked(name, libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING)
+                .map_err(|errno| DrowError::Syscall {
+                    call: format!("memfd_create({})", name),
+                    source: errno.into(),
+                })?;
+        let mut file = unsafe { File::from_raw_fd(file_descriptor) };
+        file.write_all(bytes).map_err(|err| DrowError::Io {
+            path: format!("memfd:{}", name),
+            source: err,
+        })?;
+        syscall::add_seals_checked(
+            file_descriptor,
+            libc::F_SEAL_SHRINK | libc::F_SEAL_GROW | libc::F_SEAL_WRITE | libc::F_SEAL_SEAL,
+        )
+        .map_err(|errno| DrowError::Syscall {
+            call: format!("fcntl(memfd:{}, F_ADD_SEALS)", name),
+            source: errno.into(),
+        })?;
+        Ok(MemoryBackedElf {
+            name: format!("memfd:{}", name),
+            file,
+        })
+    }
+
+    /// Takes over `file_descriptor`, already open. A regular file is read and mapped as it is,
+    /// anything else, like a pipe, is read to its end into a sealed copy, as mapping needs a file.
+    pub fn from_descriptor(file_descriptor: i32) -> Result<MemoryBackedElf, DrowError> {
+        let name = descriptor_name(file_descriptor);
+        let mut file = unsafe { File::from_raw_fd(file_descriptor) };
+        let io_error = |source| DrowError::Io {
+            path: name.clone(),
+            source,
+        };
+        if file.metadata().map_err(io_error)?.is_file() {
+            return Ok(MemoryBackedElf { name, file });
+        }
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).map_err(io_error)?;
+        MemoryBackedElf::from_bytes(&name, &bytes)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Reads the file from its start, whatever was read through the descriptor before.
+    pub fn reader(&self) -> Result<OffsetReader, DrowError> {
+        let file = self.file.try_clone().map_err(|err| DrowError::Io {
+            path: self.name.clone(),
+            source: err,
+        })?;
+        Ok(OffsetReader::new(file))
+    }
+
+    pub fn metadata(&self) -> Result<Elf64Metadata, DrowError> {
+        Elf64Metadata::load(&self.name, &mut self.reader()?)
+    }
+}
+
+impl DescriptorProvider for MemoryBackedElf {
+    fn open(&self, _elf_metadata: &Elf64Metadata) -> Result<i32, DrowError> {
+        Ok(self.file.as_raw_fd())
+    }
+
+    fn release(&self, _file_descriptor: i32) {}
+}
+
+/// The path `file_descriptor` was opened from, as /proc/self/fd tells it, or `fd:N` for a
+/// descriptor without one, like a pipe or a deleted file.
+pub fn descriptor_name(file_descriptor: i32) -> String {
+    fs::read_link(format!("/proc/self/fd/{}", file_descriptor))
+        .ok()
+        .map(|path| path.to_string_lossy().into_owned())
+        .filter(|path| path.starts_with('/') && !path.ends_with(" (deleted)"))
+        .unwrap_or_else(|| format!("fd:{}", file_descriptor))
+}
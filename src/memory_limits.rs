@@ -0,0 +1,43 @@
+//! How much more the process may map, so a load too large fails before mapping anything.
+
+use crate::syscall;
+
+const PROC_SELF_STATUS: &str = "/proc/self/status";
+const PROC_MEMINFO: &str = "/proc/meminfo";
+const OVERCOMMIT_MEMORY: &str = "/proc/sys/vm/overcommit_memory";
+/// Overcommit mode where the kernel refuses commitments past CommitLimit.
+const OVERCOMMIT_NEVER: &str = "2";
+
+/// The value in bytes of the `key:` line of a /proc file listing sizes in kB.
+fn kilobytes(content: &str, key: &str) -> Option<u64> {
+    let line = content.lines().find(|line| line.starts_with(key))?;
+    let value = line[key.len()..]
+        .trim_start_matches(':')
+        .split_whitespace()
+        .next()?;
+    value.parse::<u64>().ok().map(|value| value * 1024)
+}
+
+/// Bytes the address space may still grow by under RLIMIT_AS, none when unlimited or unknown.
+pub fn address_space() -> Option<u64> {
+    let limit = syscall::getrlimit_checked(libc::RLIMIT_AS as i32).ok()?;
+    if limit.rlim_cur == libc::RLIM_INFINITY {
+        return None;
+    }
+    let status = std::fs::read_to_string(PROC_SELF_STATUS).ok()?;
+    let used = kilobytes(&status, "VmSize")?;
+    Some(limit.rlim_cur.saturating_sub(used))
+}
+
+/// Bytes of private writable memory the kernel still commits to. Only known when overcommit is
+/// disabled, otherwise mappings succeed and the shortage shows up as the OOM killer later.
+pub fn commit() -> Option<u64> {
+    let mode = std::fs::read_to_string(OVERCOMMIT_MEMORY).ok()?;
+    if mode.trim() != OVERCOMMIT_NEVER {
+        return None;
+    }
+    let meminfo = std::fs::read_to_string(PROC_MEMINFO).ok()?;
+    let limit = kilobytes(&meminfo, "CommitLimit")?;
+    let committed = kilobytes(&meminfo, "Committed_AS")?;
+    Some(limit.saturating_sub(committed))
+}
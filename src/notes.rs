@@ -0,0 +1,56 @@
+use std::io::{Read, Seek};
+
+use crate::offset_reader::read_segment;
+use crate::Elf64ProgramHeader;
+
+pub struct ElfNote {
+    pub name: String,
+    pub note_type: u32,
+    pub description: Vec<u8>,
+}
+
+fn align_note(value: usize) -> usize {
+    (value + 3) & !3
+}
+
+pub fn read_u32(content: &[u8], at: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&content[at..at + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+pub fn read_u64(content: &[u8], at: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&content[at..at + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+pub fn read_notes<T: Read + Seek>(
+    reader: &mut T,
+    header: &Elf64ProgramHeader,
+) -> Result<Vec<ElfNote>, String> {
+    let content =
+        read_segment(reader, header.p_offset, header.p_file_size).map_err(|err| err.to_string())?;
+    let mut notes = Vec::new();
+    let mut position = 0;
+    while position + 12 <= content.len() {
+        let name_size = read_u32(&content, position) as usize;
+        let description_size = read_u32(&content, position + 4) as usize;
+        let note_type = read_u32(&content, position + 8);
+        let name_start = position + 12;
+        let description_start = name_start + align_note(name_size);
+        let description_end = description_start + description_size;
+        if description_end > content.len() {
+            break;
+        }
+        let name = &content[name_start..name_start + name_size];
+        let name_end = name.iter().position(|b| *b == 0).unwrap_or(name.len());
+        notes.push(ElfNote {
+            name: String::from_utf8_lossy(&name[..name_end]).to_string(),
+            note_type,
+            description: content[description_start..description_end].to_vec(),
+        });
+        position = description_start + align_note(description_size);
+    }
+    Ok(notes)
+}
@@ -0,0 +1,190 @@
+//! Per-object overrides of load behavior, given as `<glob>:key=value[,key=value]` with
+//! `--object-rule`. The glob is matched against the name an object was required by, its file
+//! name and its path. When several rules set the same key for an object, the last one wins.
+
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use crate::glob::glob_match;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuleAction {
+    /// Does not run the init functions of the object. Its fini functions still run.
+    SkipInit(bool),
+    /// Binds every symbol of the object at load time, which drow does for all objects.
+    BindNow(bool),
+    /// Leaves the RELRO segment of the object writable, which drow does for all objects.
+    NoRelro(bool),
+    /// Maps the object at this base address instead of the next free one.
+    Base(u64),
+    /// Loads this file instead of the object.
+    Replace(String),
+}
+
+impl Display for RuleAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleAction::SkipInit(value) => write!(f, "skip-init={}", value),
+            RuleAction::BindNow(value) => write!(f, "bind-now={}", value),
+            RuleAction::NoRelro(value) => write!(f, "no-relro={}", value),
+            RuleAction::Base(base) => write!(f, "base={:#x}", base),
+            RuleAction::Replace(path) => write!(f, "replace={}", path),
+        }
+    }
+}
+
+fn flag(key: &str, value: Option<&str>) -> Result<bool, String> {
+    match value {
+        None | Some("true") | Some("yes") | Some("1") => Ok(true),
+        Some("false") | Some("no") | Some("0") => Ok(false),
+        Some(value) => Err(format!("Invalid value for {}: {}", key, value)),
+    }
+}
+
+fn required<'a>(key: &str, value: Option<&'a str>) -> Result<&'a str, String> {
+    value
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| format!("{} needs a value", key))
+}
+
+#[derive(Clone, Debug)]
+pub struct ObjectRule {
+    pub pattern: String,
+    pub actions: Vec<RuleAction>,
+}
+
+impl ObjectRule {
+    pub fn parse(value: &str) -> Result<ObjectRule, String> {
+        let (pattern, settings) = value
+            .split_once(':')
+            .filter(|(pattern, _)| !pattern.is_empty())
+            .ok_or_else(|| format!("Object rule {} is not <glob>:key=value", value))?;
+        let mut actions = Vec::new();
+        for setting in settings.split(',').filter(|setting| !setting.is_empty()) {
+            let (key, value) = match setting.split_once('=') {
+                Some((key, value)) => (key, Some(value)),
+                None => (setting, None),
+            };
+            actions.push(match key {
+                "skip-init" => RuleAction::SkipInit(flag(key, value)?),
+                "bind-now" => RuleAction::BindNow(flag(key, value)?),
+                "no-relro" => RuleAction::NoRelro(flag(key, value)?),
+                "base" => {
+                    let base = required(key, value)?;
+                    let digits = base.trim_start_matches("0x").trim_start_matches("0X");
+                    RuleAction::Base(
+                        u64::from_str_radix(digits, 16)
+                            .map_err(|_| format!("Invalid base address: {}", base))?,
+                    )
+                }
+                "replace" => RuleAction::Replace(required(key, value)?.to_string()),
+                _ => {
+                    return Err(format!(
+                        "Unknown object rule key: {}, expected skip-init, bind-now, no-relro, \
+                         base or replace",
+                        key
+                    ))
+                }
+            });
+        }
+        if actions.is_empty() {
+            return Err(format!("Object rule {} sets nothing", value));
+        }
+        Ok(ObjectRule {
+            pattern: pattern.to_string(),
+            actions,
+        })
+    }
+
+    /// Whether the rule replaces an object with the file at `path`.
+    fn loads(&self, path: Option<&str>) -> bool {
+        self.actions.iter().any(
+            |action| matches!(action, RuleAction::Replace(file) if Some(file.as_str()) == path),
+        )
+    }
+
+    /// Whether the rule applies to an object required as `name` and found at `path`.
+    fn matches(&self, name: &str, path: Option<&str>) -> bool {
+        let file_name = path
+            .and_then(|path| Path::new(path).file_name())
+            .and_then(|name| name.to_str());
+        std::iter::once(name)
+            .chain(file_name)
+            .chain(path)
+            .any(|candidate| glob_match(&self.pattern, candidate))
+    }
+}
+
+impl Display for ObjectRule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let actions: Vec<String> = self
+            .actions
+            .iter()
+            .map(|action| action.to_string())
+            .collect();
+        write!(f, "{}:{}", self.pattern, actions.join(","))
+    }
+}
+
+/// What the rules matching one object set, each key from the last rule setting it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ObjectSettings {
+    pub skip_init: bool,
+    pub bind_now: bool,
+    pub no_relro: bool,
+    pub base: Option<u64>,
+    pub replace: Option<String>,
+    /// The rules that matched, in the order they were given.
+    pub rules: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ObjectRules {
+    rules: Vec<ObjectRule>,
+}
+
+impl ObjectRules {
+    pub fn push(&mut self, rule: ObjectRule) {
+        self.rules.push(rule);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The settings for an object required as `name` and found at `path`, if it was found. The
+    /// file a rule replaces an object with gets the other settings of that rule.
+    pub fn settings(&self, name: &str, path: Option<&str>) -> ObjectSettings {
+        let mut settings = ObjectSettings::default();
+        for rule in self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches(name, path) || rule.loads(path))
+        {
+            for action in rule.actions.iter() {
+                match action {
+                    RuleAction::SkipInit(value) => settings.skip_init = *value,
+                    RuleAction::BindNow(value) => settings.bind_now = *value,
+                    RuleAction::NoRelro(value) => settings.no_relro = *value,
+                    RuleAction::Base(base) => settings.base = Some(*base),
+                    RuleAction::Replace(file) if Some(file.as_str()) != path => {
+                        settings.replace = Some(file.clone())
+                    }
+                    RuleAction::Replace(_) => {}
+                }
+            }
+            settings.rules.push(rule.to_string());
+        }
+        settings
+    }
+
+    /// The file a `replace` rule loads instead of the object required as `name`, which is
+    /// matched on its own first, then with each of the `paths` found for it.
+    pub fn replacement(&self, name: &str, paths: &[String]) -> Option<String> {
+        self.settings(name, None).replace.or_else(|| {
+            paths
+                .iter()
+                .find_map(|path| self.settings(name, Some(path)).replace)
+        })
+    }
+}
@@ -0,0 +1,110 @@
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom};
+use std::os::unix::io::AsRawFd;
+
+use crate::error::DrowError;
+use crate::syscall;
+
+pub struct OffsetReader {
+    file: File,
+    position: u64,
+}
+
+impl OffsetReader {
+    pub fn new(file: File) -> OffsetReader {
+        OffsetReader { file, position: 0 }
+    }
+
+    pub fn open(path: &str) -> std::io::Result<OffsetReader> {
+        File::open(path).map(OffsetReader::new)
+    }
+
+    pub fn read_at(&self, buffer: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        syscall::pread(self.file.as_raw_fd(), buffer, offset)
+            .map_err(|errno| Error::from_raw_os_error(errno.0))
+    }
+
+    pub fn read_exact_at(&self, mut buffer: &mut [u8], mut offset: u64) -> std::io::Result<()> {
+        while !buffer.is_empty() {
+            match self.read_at(buffer, offset) {
+                Ok(0) => {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        format!("Unexpected end of file at offset {}", offset),
+                    ))
+                }
+                Ok(read) => {
+                    buffer = &mut buffer[read..];
+                    offset += read as u64;
+                }
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Read for OffsetReader {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.read_at(buffer, self.position)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for OffsetReader {
+    fn seek(&mut self, position: SeekFrom) -> std::io::Result<u64> {
+        let (base, delta) = match position {
+            SeekFrom::Start(offset) => {
+                self.position = offset;
+                return Ok(offset);
+            }
+            SeekFrom::Current(delta) => (self.position, delta),
+            SeekFrom::End(delta) => (self.file.metadata()?.len(), delta),
+        };
+        let position = if delta >= 0 {
+            base.checked_add(delta as u64)
+        } else {
+            base.checked_sub(delta.unsigned_abs())
+        }
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Invalid seek position"))?;
+        self.position = position;
+        Ok(position)
+    }
+}
+
+pub fn read_segment<T: Read + Seek>(
+    reader: &mut T,
+    offset: u64,
+    size: u64,
+) -> Result<Vec<u8>, DrowError> {
+    let mut buffer = Vec::new();
+    read_segment_into(reader, offset, size, &mut buffer)?;
+    Ok(buffer)
+}
+
+/// Like `read_segment`, reusing the allocation of `buffer`.
+pub fn read_segment_into<T: Read + Seek>(
+    reader: &mut T,
+    offset: u64,
+    size: u64,
+    buffer: &mut Vec<u8>,
+) -> Result<(), DrowError> {
+    buffer.clear();
+    buffer.resize(size as usize, 0);
+    reader
+        .seek(SeekFrom::Start(offset))
+        .and_then(|_| reader.read_exact(buffer))
+        .map_err(|err| match err.kind() {
+            ErrorKind::UnexpectedEof => DrowError::Malformed {
+                what: format!("{} bytes extend past the end of the file", size),
+                offset: Some(offset),
+            },
+            _ => DrowError::Read {
+                offset,
+                length: size,
+                source: err,
+            },
+        })
+}
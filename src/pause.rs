@@ -0,0 +1,89 @@
+//! Holding a loaded program before its init functions run, so a debugger can attach to it or its
+//! mappings be read from /proc first. The program goes on at SIGUSR1 or at a line on stdin.
+
+use crate::error::DrowError;
+use crate::syscall;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PauseMode {
+    Signal,
+    Stdin,
+}
+
+fn signal_error(call: &str, errno: syscall::Errno) -> DrowError {
+    DrowError::Syscall {
+        call: format!("{}(SIGUSR1)", call),
+        source: errno.into(),
+    }
+}
+
+impl PauseMode {
+    pub fn parse(value: &str) -> Result<PauseMode, String> {
+        match value {
+            "signal" => Ok(PauseMode::Signal),
+            "stdin" => Ok(PauseMode::Stdin),
+            other => Err(format!(
+                "Unknown pause mode: {}, expected signal or stdin",
+                other
+            )),
+        }
+    }
+
+    /// What lets the process `pid` go on.
+    pub fn resume_hint(self, pid: i32) -> String {
+        match self {
+            PauseMode::Signal => format!("send SIGUSR1 to {} to continue", pid),
+            PauseMode::Stdin => String::from("press Enter to continue"),
+        }
+    }
+
+    /// Keeps a SIGUSR1 sent before `wait` pending instead of killing the process. Called before
+    /// the pid is told, a child started afterwards inherits it.
+    pub fn hold(self) -> Result<(), DrowError> {
+        match self {
+            PauseMode::Signal => syscall::mask_signal_checked(libc::SIG_BLOCK, libc::SIGUSR1)
+                .map_err(|errno| signal_error("sigprocmask", errno)),
+            PauseMode::Stdin => Ok(()),
+        }
+    }
+
+    /// Undoes `hold`, so the program sees SIGUSR1 as if drow had not been there.
+    pub fn release(self) -> Result<(), DrowError> {
+        match self {
+            PauseMode::Signal => syscall::mask_signal_checked(libc::SIG_UNBLOCK, libc::SIGUSR1)
+                .map_err(|errno| signal_error("sigprocmask", errno)),
+            PauseMode::Stdin => Ok(()),
+        }
+    }
+
+    /// Blocks until SIGUSR1 arrives, or until stdin has a line or ends, then releases the hold.
+    /// Uses the system calls alone, as it runs in children sharing drow's memory.
+    pub fn wait(self) -> Result<(), DrowError> {
+        match self {
+            PauseMode::Signal => loop {
+                match syscall::wait_signal_checked(libc::SIGUSR1) {
+                    Ok(()) => break,
+                    Err(syscall::Errno(libc::EINTR)) => continue,
+                    Err(errno) => return Err(signal_error("sigwaitinfo", errno)),
+                }
+            },
+            PauseMode::Stdin => {
+                let mut byte = [0u8];
+                loop {
+                    match syscall::read_checked(libc::STDIN_FILENO, &mut byte) {
+                        Ok(0) => break,
+                        Ok(_) if byte[0] == b'\n' => break,
+                        Ok(_) | Err(syscall::Errno(libc::EINTR)) => continue,
+                        Err(errno) => {
+                            return Err(DrowError::Syscall {
+                                call: String::from("read(stdin)"),
+                                source: errno.into(),
+                            })
+                        }
+                    }
+                }
+            }
+        }
+        self.release()
+    }
+}
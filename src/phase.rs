@@ -0,0 +1,110 @@
+//! What a loader is doing, so that code it runs can be told whether it may call back into it.
+//! Indirect function resolvers run while the loader holds its registry, so a resolver calling
+//! `dlopen` would deadlock or see half relocated objects. Init functions run with the registry
+//! released, and a library they load is a nested load with its own address reservation.
+
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicI32, AtomicU8, Ordering};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LoaderPhase {
+    Ready,
+    Resolving,
+    Mapping,
+    Relocating,
+    RunningInit,
+}
+
+const PHASES: [LoaderPhase; 5] = [
+    LoaderPhase::Ready,
+    LoaderPhase::Resolving,
+    LoaderPhase::Mapping,
+    LoaderPhase::Relocating,
+    LoaderPhase::RunningInit,
+];
+
+impl LoaderPhase {
+    /// Whether the registry is held, so the thread in the phase cannot enter the loader again.
+    pub fn exclusive(self) -> bool {
+        matches!(
+            self,
+            LoaderPhase::Resolving | LoaderPhase::Mapping | LoaderPhase::Relocating
+        )
+    }
+}
+
+impl Display for LoaderPhase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            LoaderPhase::Ready => "ready",
+            LoaderPhase::Resolving => "resolving dependencies",
+            LoaderPhase::Mapping => "mapping objects",
+            LoaderPhase::Relocating => "relocating objects",
+            LoaderPhase::RunningInit => "running init functions",
+        })
+    }
+}
+
+fn current_thread() -> i32 {
+    unsafe { libc::gettid() }
+}
+
+/// The phase of one loader and the thread in it.
+pub(crate) struct PhaseTracker {
+    phase: AtomicU8,
+    thread: AtomicI32,
+}
+
+impl Default for PhaseTracker {
+    fn default() -> PhaseTracker {
+        PhaseTracker {
+            phase: AtomicU8::new(LoaderPhase::Ready as u8),
+            thread: AtomicI32::new(0),
+        }
+    }
+}
+
+impl PhaseTracker {
+    pub fn phase(&self) -> LoaderPhase {
+        PHASES[self.phase.load(Ordering::SeqCst) as usize]
+    }
+
+    /// The exclusive phase the calling thread is in, which it must not enter the loader from.
+    pub fn reentered(&self) -> Option<LoaderPhase> {
+        let phase = self.phase();
+        (phase.exclusive() && self.thread.load(Ordering::SeqCst) == current_thread())
+            .then_some(phase)
+    }
+
+    /// Enters `phase` on the calling thread, until the guard restores the phase before it. Loads
+    /// enter their phases with the registry held, after checking `reentered`, so no two threads
+    /// are in one at once.
+    pub fn enter(&self, phase: LoaderPhase) -> PhaseGuard<'_> {
+        let guard = PhaseGuard {
+            tracker: self,
+            phase: self.phase(),
+            thread: self.thread.load(Ordering::SeqCst),
+        };
+        self.set(phase);
+        guard
+    }
+
+    /// Moves the load in progress on to `phase`.
+    pub fn set(&self, phase: LoaderPhase) {
+        self.thread.store(current_thread(), Ordering::SeqCst);
+        self.phase.store(phase as u8, Ordering::SeqCst);
+    }
+}
+
+pub(crate) struct PhaseGuard<'a> {
+    tracker: &'a PhaseTracker,
+    phase: LoaderPhase,
+    thread: i32,
+}
+
+impl Drop for PhaseGuard<'_> {
+    fn drop(&mut self) {
+        self.tracker.thread.store(self.thread, Ordering::SeqCst);
+        self.tracker.phase.store(self.phase as u8, Ordering::SeqCst);
+    }
+}
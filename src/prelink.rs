@@ -0,0 +1,231 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::sha256;
+
+const MAGIC: &[u8; 8] = b"DROWPRE2";
+
+/// A value a symbol relocation wrote, replayed without looking the symbol up again.
+#[derive(Clone, Debug)]
+pub enum PrelinkWrite {
+    /// `value` written at `offset`.
+    Word { offset: u64, value: u64 },
+    /// `size` bytes copied to `offset` from `source`, for `R_X86_64_COPY`.
+    Copy { offset: u64, source: u64, size: u64 },
+    /// A symbol defined by drow itself, whose address changes from one run to the next, so it is
+    /// looked up again by `name`.
+    Symbol {
+        offset: u64,
+        name: String,
+        addend: i64,
+    },
+}
+
+/// The writes of the symbol relocations of one object, valid while the file and its base stay
+/// the same. Offsets are relative to the base.
+#[derive(Clone, Debug)]
+pub struct PrelinkObject {
+    pub path: String,
+    pub fingerprint: [u8; 32],
+    pub base: u64,
+    pub writes: Vec<PrelinkWrite>,
+}
+
+/// The objects mapped by one load of a program, in mapping order, from the first base address.
+#[derive(Clone, Debug, Default)]
+pub struct Prelink {
+    pub start: u64,
+    pub objects: Vec<PrelinkObject>,
+}
+
+/// Identifies the content of `path` by its SHA-256 digest, like bundles do. File metadata would
+/// miss a library rewritten in place with its size and modification time kept.
+pub fn fingerprint(path: &str) -> Option<[u8; 32]> {
+    sha256::digest(File::open(path).ok()?).ok()
+}
+
+/// The file in `directory` holding the prelink records of the program at `path`.
+pub fn cache_file(directory: &Path, path: &str) -> PathBuf {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    directory.join(format!("{:016x}.prelink", hasher.finish()))
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what.to_string())
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_string(reader: &mut impl Read) -> io::Result<String> {
+    let length = read_u64(reader)?;
+    let mut bytes = Vec::new();
+    reader.take(length).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != length {
+        return Err(invalid("truncated string"));
+    }
+    String::from_utf8(bytes).map_err(|_| invalid("string is not UTF-8"))
+}
+
+fn write_string(writer: &mut impl Write, value: &str) -> io::Result<()> {
+    writer.write_all(&(value.len() as u64).to_le_bytes())?;
+    writer.write_all(value.as_bytes())
+}
+
+fn version() -> String {
+    format!("drow {}", env!("CARGO_PKG_VERSION"))
+}
+
+impl PrelinkWrite {
+    fn read(reader: &mut impl Read) -> io::Result<PrelinkWrite> {
+        let mut kind = [0];
+        reader.read_exact(&mut kind)?;
+        let offset = read_u64(reader)?;
+        match kind[0] {
+            0 => Ok(PrelinkWrite::Word {
+                offset,
+                value: read_u64(reader)?,
+            }),
+            1 => Ok(PrelinkWrite::Copy {
+                offset,
+                source: read_u64(reader)?,
+                size: read_u64(reader)?,
+            }),
+            2 => Ok(PrelinkWrite::Symbol {
+                offset,
+                name: read_string(reader)?,
+                addend: read_u64(reader)? as i64,
+            }),
+            _ => Err(invalid("unknown write kind")),
+        }
+    }
+
+    fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            PrelinkWrite::Word { offset, value } => {
+                writer.write_all(&[0])?;
+                writer.write_all(&offset.to_le_bytes())?;
+                writer.write_all(&value.to_le_bytes())
+            }
+            PrelinkWrite::Copy {
+                offset,
+                source,
+                size,
+            } => {
+                writer.write_all(&[1])?;
+                writer.write_all(&offset.to_le_bytes())?;
+                writer.write_all(&source.to_le_bytes())?;
+                writer.write_all(&size.to_le_bytes())
+            }
+            PrelinkWrite::Symbol {
+                offset,
+                name,
+                addend,
+            } => {
+                writer.write_all(&[2])?;
+                writer.write_all(&offset.to_le_bytes())?;
+                write_string(writer, name)?;
+                writer.write_all(&addend.to_le_bytes())
+            }
+        }
+    }
+}
+
+impl Prelink {
+    /// Fails on files written by another version of drow, whose relocations may differ.
+    pub fn load(path: &Path) -> io::Result<Prelink> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC || read_string(&mut reader)? != version() {
+            return Err(invalid("written by another version of drow"));
+        }
+        let start = read_u64(&mut reader)?;
+        let count = read_u64(&mut reader)?;
+        let mut objects = Vec::new();
+        for _ in 0..count {
+            let path = read_string(&mut reader)?;
+            let mut fingerprint = [0; 32];
+            reader.read_exact(&mut fingerprint)?;
+            let base = read_u64(&mut reader)?;
+            let write_count = read_u64(&mut reader)?;
+            let mut writes = Vec::new();
+            for _ in 0..write_count {
+                writes.push(PrelinkWrite::read(&mut reader)?);
+            }
+            objects.push(PrelinkObject {
+                path,
+                fingerprint,
+                base,
+                writes,
+            });
+        }
+        Ok(Prelink { start, objects })
+    }
+
+    /// Writes a temporary file next to `path` first, so a concurrent load never reads a partial
+    /// file.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let temporary = path.with_extension(format!("tmp{}", std::process::id()));
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        writer.write_all(MAGIC)?;
+        write_string(&mut writer, &version())?;
+        writer.write_all(&self.start.to_le_bytes())?;
+        writer.write_all(&(self.objects.len() as u64).to_le_bytes())?;
+        for object in self.objects.iter() {
+            write_string(&mut writer, &object.path)?;
+            writer.write_all(&object.fingerprint)?;
+            writer.write_all(&object.base.to_le_bytes())?;
+            writer.write_all(&(object.writes.len() as u64).to_le_bytes())?;
+            for write in object.writes.iter() {
+                write.write(&mut writer)?;
+            }
+        }
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&temporary, path)
+    }
+
+    /// Why the records do not apply to mapping `objects`, given as paths and fingerprints, from
+    /// `start`, if they do not.
+    pub fn mismatch(&self, start: u64, objects: &[(String, [u8; 32])]) -> Option<String> {
+        if self.start != start {
+            return Some(format!(
+                "it starts at {:#X} instead of {:#X}",
+                self.start, start
+            ));
+        }
+        if self.objects.len() != objects.len() {
+            return Some(String::from("the set of objects changed"));
+        }
+        for (object, (path, fingerprint)) in self.objects.iter().zip(objects.iter()) {
+            if &object.path != path {
+                return Some(String::from("the set of objects changed"));
+            }
+            if object.fingerprint != *fingerprint {
+                return Some(format!("{} changed", path));
+            }
+        }
+        None
+    }
+
+    /// The records by path, for replaying them as the objects get mapped.
+    pub fn into_objects(self) -> HashMap<String, PrelinkObject> {
+        self.objects
+            .into_iter()
+            .map(|object| (object.path.clone(), object))
+            .collect()
+    }
+}
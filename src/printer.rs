@@ -1,38 +1,447 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::io::{Read, Seek, SeekFrom};
+use std::io::{Read, Seek};
 
-use crate::string_tables::{
-    convert_string_tables_content, get_string_tables_content, string_length,
+use crate::error::DrowError;
+use crate::string_tables::{get_string_tables_content, StringTable};
+use crate::table::{flag_letters, header, Table};
+use crate::{
+    format_addend, Elf64Metadata, Elf64ResolvedRelocationAddend, Elf64ResolvedSymbolTableEntry,
 };
-use crate::{Elf64Metadata, Elf64SectionHeader, ELF64_SECTION_HEADER_STRING_TABLE};
 
-pub fn print<T: Read + Seek>(elf_metadata: &Elf64Metadata, reader: &mut T) {
-    print!("{}\n", elf_metadata.elf_header);
-    println!("Program headers");
-    for header in elf_metadata.program_headers.iter() {
-        println!("{}", header);
+fn segment_type_name(segment_type: u32) -> String {
+    match segment_type {
+        0 => String::from("NULL"),
+        1 => String::from("LOAD"),
+        2 => String::from("DYNAMIC"),
+        3 => String::from("INTERP"),
+        4 => String::from("NOTE"),
+        5 => String::from("SHLIB"),
+        6 => String::from("PHDR"),
+        7 => String::from("TLS"),
+        0x6474e550 => String::from("GNU_EH_FRAME"),
+        0x6474e551 => String::from("GNU_STACK"),
+        0x6474e552 => String::from("GNU_RELRO"),
+        0x6474e553 => String::from("GNU_PROPERTY"),
+        other => format!("{:#X}", other),
     }
-    let string_tables_content = get_string_tables_content(&elf_metadata.section_headers, reader);
-    let string_tables_content_converted = convert_string_tables_content(&string_tables_content);
-    for (key, value) in string_tables_content_converted.iter() {
-        println!("String table at {} content:", key);
-        for entry in value.iter() {
-            println!("{}", entry);
+}
+
+fn section_type_name(section_type: u32) -> String {
+    match section_type {
+        0 => String::from("NULL"),
+        1 => String::from("PROGBITS"),
+        2 => String::from("SYMTAB"),
+        3 => String::from("STRTAB"),
+        4 => String::from("RELA"),
+        5 => String::from("HASH"),
+        6 => String::from("DYNAMIC"),
+        7 => String::from("NOTE"),
+        8 => String::from("NOBITS"),
+        9 => String::from("REL"),
+        10 => String::from("SHLIB"),
+        11 => String::from("DYNSYM"),
+        14 => String::from("INIT_ARRAY"),
+        15 => String::from("FINI_ARRAY"),
+        16 => String::from("PREINIT_ARRAY"),
+        17 => String::from("GROUP"),
+        18 => String::from("SYMTAB_SHNDX"),
+        0x6ffffff6 => String::from("GNU_HASH"),
+        0x6ffffffd => String::from("VERDEF"),
+        0x6ffffffe => String::from("VERNEED"),
+        0x6fffffff => String::from("VERSYM"),
+        other => format!("{:#X}", other),
+    }
+}
+
+pub fn symbol_type_name(symbol_type: u8) -> String {
+    match symbol_type {
+        0 => String::from("NOTYPE"),
+        1 => String::from("OBJECT"),
+        2 => String::from("FUNC"),
+        3 => String::from("SECTION"),
+        4 => String::from("FILE"),
+        5 => String::from("COMMON"),
+        6 => String::from("TLS"),
+        10 => String::from("IFUNC"),
+        other => other.to_string(),
+    }
+}
+
+pub fn symbol_binding_name(binding: u8) -> String {
+    match binding {
+        0 => String::from("LOCAL"),
+        1 => String::from("GLOBAL"),
+        2 => String::from("WEAK"),
+        10 => String::from("UNIQUE"),
+        other => other.to_string(),
+    }
+}
+
+pub fn symbol_section_name(section_index: u16) -> String {
+    match section_index {
+        0 => String::from("UND"),
+        0xfff1 => String::from("ABS"),
+        0xfff2 => String::from("COM"),
+        other => other.to_string(),
+    }
+}
+
+/// Prints the symbols accepted by `filter`, numbered by their index in `symbols`.
+pub fn print_symbols(
+    title: &str,
+    symbols: &[Elf64ResolvedSymbolTableEntry],
+    filter: impl Fn(&Elf64ResolvedSymbolTableEntry) -> bool,
+    color: bool,
+) {
+    let mut table = Table::new(&["Num", "Value", "Size", "Type", "Bind", "Ndx", "Name"]);
+    for (index, symbol) in symbols.iter().enumerate() {
+        if !filter(symbol) {
+            continue;
         }
+        table.add_row(vec![
+            index.to_string(),
+            format!("{:016X}", symbol.value),
+            symbol.size.to_string(),
+            symbol_type_name(symbol.symbol_type),
+            symbol_binding_name(symbol.binding),
+            symbol_section_name(symbol.section_index),
+            symbol.versioned_name(),
+        ]);
     }
-    println!("Section headers");
-    let section_names = elf_metadata
-        .section_headers
-        .get(elf_metadata.elf_header.e_section_name_string_table_index as usize)
-        .unwrap();
+    if table.is_empty() {
+        return;
+    }
+    println!("{}", header(title, color));
+    print!("{}", table.render(color));
+}
+
+pub fn print_header(elf_metadata: &Elf64Metadata, color: bool) {
+    println!("{}", elf_metadata.elf_header);
+    println!(
+        "entry: {:#x} {}",
+        elf_metadata.elf_header.e_entry,
+        elf_metadata.describe_address(elf_metadata.elf_header.e_entry)
+    );
+    println!("kind: {}", elf_metadata.object_kind());
+    if let Some(interpreter) = elf_metadata.interpreter.as_ref() {
+        println!("interpreter: {}", interpreter);
+    }
+    if let Some(problem) = elf_metadata.pie_flag_problem() {
+        println!("warning: {}", problem);
+    }
+    println!("{}", header("Program headers", color));
+    let mut segments = Table::new(&[
+        "Type", "Offset", "VirtAddr", "FileSize", "MemSize", "Flags", "Align",
+    ]);
+    for program_header in elf_metadata.program_headers.iter() {
+        segments.add_row(vec![
+            segment_type_name(program_header.p_type),
+            format!("{:#08X}", program_header.p_offset),
+            format!("{:#016X}", program_header.p_virtual_address),
+            format!("{:#X}", program_header.p_file_size),
+            format!("{:#X}", program_header.p_memory_size),
+            flag_letters(
+                &[
+                    ('R', program_header.read()),
+                    ('W', program_header.write()),
+                    ('X', program_header.execute()),
+                ],
+                color,
+            ),
+            format!("{:#X}", program_header.p_align),
+        ]);
+    }
+    print!("{}", segments.render(color));
+    if elf_metadata.tls_segments.is_empty() {
+        return;
+    }
+    println!("{}", header("TLS segments", color));
+    let mut tls = Table::new(&["VirtAddr", "FileSize", "MemSize", "Align"]);
+    for segment in elf_metadata.tls_segments.iter() {
+        tls.add_row(vec![
+            format!("{:#016X}", segment.virtual_address),
+            format!("{:#X}", segment.file_size),
+            format!("{:#X}", segment.memory_size),
+            format!("{:#X}", segment.alignment),
+        ]);
+    }
+    print!("{}", tls.render(color));
+}
+
+/// The string tables kept by `Elf64Metadata::load_with_string_tables`, or read from `reader`.
+pub fn string_tables_content<'a, T: Read + Seek>(
+    elf_metadata: &'a Elf64Metadata,
+    reader: &mut T,
+) -> Result<Cow<'a, HashMap<usize, StringTable>>, DrowError> {
+    match elf_metadata.string_tables.as_ref() {
+        Some(cache) => Ok(Cow::Borrowed(cache.tables())),
+        None => get_string_tables_content(&elf_metadata.section_headers, reader).map(Cow::Owned),
+    }
+}
+
+pub fn section_names(
+    elf_metadata: &Elf64Metadata,
+    string_tables_content: &HashMap<usize, StringTable>,
+) -> Vec<String> {
+    let empty_table = StringTable::from_bytes(&[]);
     let section_names_table = string_tables_content
-        .get(&section_names.sh_virtual_address)
-        .unwrap();
-    for header in elf_metadata.section_headers.iter() {
-        let idx = header.sh_name as usize;
-        let length = string_length(&section_names_table[idx..]);
-        let end_idx = idx + length;
-        let name = std::str::from_utf8(&section_names_table[idx..end_idx]).unwrap();
-        println!("Section name: {}, header: {}", name, header);
+        .get(&(elf_metadata.elf_header.e_section_name_string_table_index as usize))
+        .unwrap_or(&empty_table);
+    elf_metadata
+        .section_headers
+        .iter()
+        .map(|section_header| {
+            section_names_table
+                .get_lossy(section_header.sh_name)
+                .map(|name| name.into_owned())
+                .unwrap_or_else(|err| format!("<{}>", err))
+        })
+        .collect()
+}
+
+/// Prints the sections whose name is accepted by `filter`.
+pub fn print_sections(
+    elf_metadata: &Elf64Metadata,
+    section_names: &[String],
+    filter: impl Fn(&str) -> bool,
+    color: bool,
+) {
+    println!("{}", header("Section headers", color));
+    let mut sections = Table::new(&[
+        "Nr", "Name", "Type", "Address", "Offset", "Size", "Flags", "Link",
+    ]);
+    for (index, section_header) in elf_metadata.section_headers.iter().enumerate() {
+        if !filter(&section_names[index]) {
+            continue;
+        }
+        sections.add_row(vec![
+            index.to_string(),
+            section_names[index].clone(),
+            section_type_name(section_header.sh_type),
+            format!("{:016X}", section_header.sh_virtual_address),
+            format!("{:08X}", section_header.sh_offset),
+            format!("{:#X}", section_header.sh_size),
+            flag_letters(
+                &[
+                    ('A', section_header.allocated_in_memory()),
+                    ('W', section_header.writable()),
+                    ('X', section_header.executable()),
+                ],
+                color,
+            ),
+            section_header.sh_link.to_string(),
+        ]);
+    }
+    print!("{}", sections.render(color));
+}
+
+pub fn print_dynamic(elf_metadata: &Elf64Metadata, color: bool) {
+    let dynamic = &elf_metadata.dynamic;
+    println!("{}", header("Dynamic section", color));
+    let mut entries = Table::new(&["Tag", "Value"]);
+    for library in dynamic.required_libraries.iter() {
+        entries.add_row(vec![String::from("NEEDED"), library.clone()]);
+    }
+    let names = [
+        ("SONAME", &dynamic.soname),
+        ("RPATH", &dynamic.rpath),
+        ("RUNPATH", &dynamic.runpath),
+    ];
+    for (tag, name) in names.iter() {
+        if let Some(name) = name {
+            entries.add_row(vec![tag.to_string(), name.clone()]);
+        }
+    }
+    let addresses = [
+        ("INIT", dynamic.init_function),
+        ("INIT_ARRAY", dynamic.init_array),
+        ("FINI", dynamic.fini_function),
+        ("FINI_ARRAY", dynamic.fini_array),
+        ("PLTGOT", dynamic.plt_got),
+        ("JMPREL", dynamic.jump_relocations),
+        ("FLAGS_1", dynamic.flags_1),
+    ];
+    let sizes = [
+        ("INIT_ARRAYSZ", dynamic.init_array_size),
+        ("FINI_ARRAYSZ", dynamic.fini_array_size),
+        ("PLTRELSZ", dynamic.jump_relocations_size),
+    ];
+    for (tag, address) in addresses.iter().filter(|(_, value)| *value != 0) {
+        entries.add_row(vec![tag.to_string(), format!("{:#X}", address)]);
+    }
+    for (tag, size) in sizes.iter().filter(|(_, value)| *value != 0) {
+        entries.add_row(vec![tag.to_string(), format!("{} (bytes)", size)]);
+    }
+    for (tag, value) in dynamic.unknown_tags.iter() {
+        entries.add_row(vec![
+            format!("{:#X} (unknown)", tag),
+            format!("{:#X}", value),
+        ]);
+    }
+    if dynamic.terminated {
+        entries.add_row(vec![String::from("NULL"), String::from("0x0")]);
+    } else {
+        entries.add_row(vec![String::from("(no NULL terminator)"), String::new()]);
+    }
+    print!("{}", entries.render(color));
+}
+
+/// Prints each section group with its members, like `readelf -g`.
+pub fn print_groups(elf_metadata: &Elf64Metadata, names: &[String], color: bool) {
+    let section_name = |index: usize| names.get(index).cloned().unwrap_or_default();
+    for group in elf_metadata.groups.iter() {
+        println!(
+            "{}",
+            header(
+                &format!(
+                    "{} group section [{}] {} [{}] contains {} sections",
+                    if group.comdat() { "COMDAT" } else { "Group" },
+                    group.section_index,
+                    section_name(group.section_index),
+                    group.signature,
+                    group.members.len()
+                ),
+                color
+            )
+        );
+        let mut members = Table::new(&["Index", "Name"]);
+        for member in group.members.iter() {
+            members.add_row(vec![member.to_string(), section_name(*member as usize)]);
+        }
+        print!("{}", members.render(color));
+    }
+}
+
+/// Prints the versions the file requires from each dependency, with the symbols bound to them.
+pub fn print_version_requirements(elf_metadata: &Elf64Metadata, color: bool) {
+    let mut table = Table::new(&["File", "Version", "Symbols"]);
+    for requirement in elf_metadata.version_requirements.iter() {
+        table.add_row(vec![
+            requirement.file.clone(),
+            requirement.version.clone(),
+            requirement.symbols.len().to_string(),
+        ]);
+    }
+    if table.is_empty() {
+        return;
+    }
+    println!("{}", header("Version requirements", color));
+    print!("{}", table.render(color));
+}
+
+/// Strings printed per string table, the rest only counted.
+const MAX_STRINGS_PER_TABLE: usize = 2000;
+
+/// Prints each string table under the name of its section, every string at its offset, which
+/// is what sh_name and st_name refer to. Empty strings are left out unless `verbose`.
+pub fn print_string_tables(
+    string_tables_content: &HashMap<usize, StringTable>,
+    section_names: &[String],
+    verbose: bool,
+    color: bool,
+) {
+    let mut string_table_indexes: Vec<&usize> = string_tables_content.keys().collect();
+    string_table_indexes.sort();
+    for index in string_table_indexes {
+        let name = section_names.get(*index).map(String::as_str).unwrap_or("?");
+        println!(
+            "{}",
+            header(&format!("String table {} [{}]", name, index), color)
+        );
+        let mut strings = Table::new(&["Offset", "Hex", "String"]);
+        let mut shown = 0;
+        let mut omitted = 0;
+        for (offset, entry) in string_tables_content[index].iter() {
+            if entry.is_empty() && !verbose {
+                continue;
+            }
+            if shown == MAX_STRINGS_PER_TABLE {
+                omitted += 1;
+                continue;
+            }
+            shown += 1;
+            let value = match entry {
+                Cow::Borrowed(entry) => entry.to_string(),
+                Cow::Owned(entry) => format!("{} (lossy)", entry),
+            };
+            strings.add_row(vec![offset.to_string(), format!("{:#X}", offset), value]);
+        }
+        print!("{}", strings.render(color));
+        if omitted > 0 {
+            println!(
+                "... {} more string(s) not shown, the first {} only are printed",
+                omitted, MAX_STRINGS_PER_TABLE
+            );
+        }
+    }
+}
+
+/// Prints everything about `elf_metadata`, with empty strings of string tables when `verbose`.
+pub fn print<T: Read + Seek>(
+    elf_metadata: &Elf64Metadata,
+    reader: &mut T,
+    verbose: bool,
+    color: bool,
+) {
+    print_header(elf_metadata, color);
+    let string_tables_content =
+        string_tables_content(elf_metadata, reader).expect("Unable to read string tables");
+    let section_names = section_names(elf_metadata, &string_tables_content);
+    print_string_tables(&string_tables_content, &section_names, verbose, color);
+    print_sections(elf_metadata, &section_names, |_| true, color);
+    print_groups(elf_metadata, &section_names, color);
+    print_symbols("Symbol table", &elf_metadata.symbol_table, |_| true, color);
+    print_symbols(
+        "Dynamic symbol table",
+        &elf_metadata.dynamic_symbol_table,
+        |_| true,
+        color,
+    );
+    print_version_requirements(elf_metadata, color);
+    print_relocations(elf_metadata, &section_names, |_| true, color);
+}
+
+/// Prints the relocations accepted by `filter`, grouped by the section holding them.
+pub fn print_relocations(
+    elf_metadata: &Elf64Metadata,
+    section_names: &[String],
+    filter: impl Fn(&Elf64ResolvedRelocationAddend) -> bool,
+    color: bool,
+) {
+    for (section_index, section_name) in section_names.iter().enumerate() {
+        let section_relocations: Vec<&Elf64ResolvedRelocationAddend> = elf_metadata
+            .relocations
+            .iter()
+            .filter(|relocation| relocation.section_index == section_index)
+            .filter(|relocation| filter(relocation))
+            .collect();
+        if section_relocations.is_empty() {
+            continue;
+        }
+        println!(
+            "{}",
+            header(
+                format!(
+                    "Relocation section '{}' contains {} entries",
+                    section_name,
+                    section_relocations.len()
+                )
+                .as_str(),
+                color
+            )
+        );
+        let mut relocations = Table::new(&["Offset", "Info", "Type", "Symbol", "Addend"]);
+        for relocation in section_relocations {
+            let info = (relocation.symbol_index << 32) | relocation.relocation_type;
+            relocations.add_row(vec![
+                format!("{:012X}", relocation.offset),
+                format!("{:012X}", info),
+                relocation.type_name().to_string(),
+                relocation.symbol_name.clone(),
+                format_addend(relocation.addend),
+            ]);
+        }
+        print!("{}", relocations.render(color));
     }
 }
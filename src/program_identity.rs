@@ -0,0 +1,98 @@
+use std::ffi::{CStr, CString};
+use std::mem::size_of;
+use std::os::raw::c_char;
+use std::os::unix::ffi::OsStrExt;
+
+use crate::{
+    Elf64ResolvedSymbolTableEntry, SHN_ABSOLUTE, SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_OBJECT,
+};
+
+const FULL_NAME: usize = 0;
+const SHORT_NAME: usize = 1;
+const ENVIRONMENT: usize = 2;
+
+/// Symbols naming the cell each one binds to. The `program_invocation_*` names are the glibc
+/// aliases of the `__progname*` variables.
+const SYMBOLS: &[(&str, usize)] = &[
+    ("__progname_full", FULL_NAME),
+    ("program_invocation_name", FULL_NAME),
+    ("__progname", SHORT_NAME),
+    ("program_invocation_short_name", SHORT_NAME),
+    ("environ", ENVIRONMENT),
+    ("__environ", ENVIRONMENT),
+    ("_environ", ENVIRONMENT),
+];
+
+/// Storage for the `char *` and `char **` variables libc start up code fills from argv and
+/// envp, initialized for the loaded program instead of drow.
+pub struct ProgramIdentity {
+    full_name: CString,
+    environment: Vec<CString>,
+    environment_pointers: Vec<*const c_char>,
+    cells: Box<[u64; 3]>,
+}
+
+// The pointers point into the strings owned by the same value.
+unsafe impl Send for ProgramIdentity {}
+
+impl ProgramIdentity {
+    /// `argv0` is the program name as it was invoked, the short name is its last component.
+    pub fn new(argv0: &str) -> ProgramIdentity {
+        let full_name = CString::new(argv0.replace('\0', "")).unwrap_or_default();
+        let environment: Vec<CString> = std::env::vars_os()
+            .filter_map(|(name, value)| {
+                let mut entry = name.as_bytes().to_vec();
+                entry.push(b'=');
+                entry.extend_from_slice(value.as_bytes());
+                CString::new(entry).ok()
+            })
+            .collect();
+        let mut environment_pointers: Vec<*const c_char> =
+            environment.iter().map(|entry| entry.as_ptr()).collect();
+        environment_pointers.push(std::ptr::null());
+        let short_offset = full_name
+            .as_bytes()
+            .iter()
+            .rposition(|byte| *byte == b'/')
+            .map(|slash| slash + 1)
+            .unwrap_or(0);
+        let full_pointer = full_name.as_ptr() as u64;
+        let cells = Box::new([
+            full_pointer,
+            full_pointer + short_offset as u64,
+            environment_pointers.as_ptr() as u64,
+        ]);
+        ProgramIdentity {
+            full_name,
+            environment,
+            environment_pointers,
+            cells,
+        }
+    }
+
+    pub fn full_name(&self) -> &CStr {
+        &self.full_name
+    }
+
+    /// Entries for the global symbol table, pointing at the cells owned by this value.
+    pub fn symbols(&self) -> Vec<Elf64ResolvedSymbolTableEntry> {
+        debug!(
+            "Program identity {:?}, {} environment entries at {:#X}",
+            self.full_name,
+            self.environment.len(),
+            self.environment_pointers.as_ptr() as u64
+        );
+        SYMBOLS
+            .iter()
+            .map(|(name, cell)| Elf64ResolvedSymbolTableEntry {
+                symbol_name: name.to_string(),
+                binding: SYMBOL_BINDING_GLOBAL,
+                symbol_type: SYMBOL_TYPE_OBJECT,
+                section_index: SHN_ABSOLUTE,
+                value: &self.cells[*cell] as *const u64 as u64,
+                size: size_of::<u64>() as u64,
+                version: None,
+            })
+            .collect()
+    }
+}
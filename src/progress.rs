@@ -0,0 +1,72 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Receives the progress of the loads done by one loader. Every event has an empty default, so a
+/// sink only implements the ones it shows.
+pub trait Progress: Send {
+    /// `resolved` of the `found` objects found so far had their dependencies resolved.
+    fn resolving(&mut self, _resolved: usize, _found: usize) {}
+
+    /// `path` is about to be mapped, its loadable segments spanning `size` bytes.
+    fn mapping(&mut self, _path: &str, _size: u64) {}
+
+    /// `done` of the `total` relocations of the objects mapped by the load were applied.
+    fn relocating(&mut self, _done: usize, _total: usize) {}
+
+    /// The load ended, successfully or not, after mapping `objects` objects and applying
+    /// `relocations` relocations.
+    fn finished(&mut self, _objects: usize, _relocations: usize) {}
+}
+
+/// Shows the progress on a single line of stderr, rewritten on every event and cleared when the
+/// load ends. Only meant for a terminal.
+#[derive(Default)]
+pub struct TerminalProgress;
+
+impl TerminalProgress {
+    fn show(&self, line: &str) {
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "\r\x1b[K{}", line);
+        let _ = stderr.flush();
+    }
+}
+
+fn count(value: usize) -> String {
+    if value >= 1000 {
+        format!("{}k", value / 1000)
+    } else {
+        value.to_string()
+    }
+}
+
+fn size(bytes: u64) -> String {
+    const MB: u64 = 1 << 20;
+    const KB: u64 = 1 << 10;
+    if bytes >= MB {
+        format!("{} MB", bytes / MB)
+    } else {
+        format!("{} KB", bytes.div_ceil(KB))
+    }
+}
+
+impl Progress for TerminalProgress {
+    fn resolving(&mut self, resolved: usize, found: usize) {
+        self.show(&format!("resolving {}/{} objects", resolved, found));
+    }
+
+    fn mapping(&mut self, path: &str, bytes: u64) {
+        let name = Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_else(|| path.into());
+        self.show(&format!("mapping {} ({})", name, size(bytes)));
+    }
+
+    fn relocating(&mut self, done: usize, total: usize) {
+        self.show(&format!("relocating {}/{}", count(done), count(total)));
+    }
+
+    fn finished(&mut self, _objects: usize, _relocations: usize) {
+        self.show("");
+    }
+}
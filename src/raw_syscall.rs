@@ -0,0 +1,360 @@
+use crate::syscall::{ChildEntry, CloneArgs};
+use std::arch::{asm, global_asm};
+use std::mem::size_of;
+
+const SYS_MMAP: i64 = 9;
+const SYS_MUNMAP: i64 = 11;
+const SYS_MADVISE: i64 = 28;
+const SYS_CLOSE: i64 = 3;
+const SYS_FSTAT: i64 = 5;
+const SYS_PREAD64: i64 = 17;
+const SYS_CLONE3: i64 = 435;
+const SYS_EXIT_GROUP: i64 = 231;
+const SYS_WAITID: i64 = 247;
+const SYS_OPENAT: i64 = 257;
+const SYS_FCNTL: i64 = 72;
+const SYS_MEMFD_CREATE: i64 = 319;
+const SYS_EXIT: i64 = 60;
+const SYS_RT_SIGACTION: i64 = 13;
+const SYS_RT_SIGRETURN: i64 = 15;
+const SYS_SIGALTSTACK: i64 = 131;
+const SYS_GETPID: i64 = 39;
+const SYS_KILL: i64 = 62;
+const SYS_GETRLIMIT: i64 = 97;
+const SYS_READ: i64 = 0;
+const SYS_RT_SIGPROCMASK: i64 = 14;
+const SYS_RT_SIGTIMEDWAIT: i64 = 128;
+const SYS_ARCH_PRCTL: i64 = 158;
+
+/// The kernel returns from a signal handler through the restorer, which libc normally provides.
+const SA_RESTORER: u64 = 0x0400_0000;
+
+const MAX_ERRNO: i64 = 4095;
+
+pub unsafe fn syscall1(number: i64, a1: i64) -> i64 {
+    let result;
+    asm!(
+        "syscall",
+        inlateout("rax") number => result,
+        in("rdi") a1,
+        out("rcx") _,
+        out("r11") _,
+        options(nostack)
+    );
+    result
+}
+
+pub unsafe fn syscall2(number: i64, a1: i64, a2: i64) -> i64 {
+    let result;
+    asm!(
+        "syscall",
+        inlateout("rax") number => result,
+        in("rdi") a1,
+        in("rsi") a2,
+        out("rcx") _,
+        out("r11") _,
+        options(nostack)
+    );
+    result
+}
+
+pub unsafe fn syscall3(number: i64, a1: i64, a2: i64, a3: i64) -> i64 {
+    let result;
+    asm!(
+        "syscall",
+        inlateout("rax") number => result,
+        in("rdi") a1,
+        in("rsi") a2,
+        in("rdx") a3,
+        out("rcx") _,
+        out("r11") _,
+        options(nostack)
+    );
+    result
+}
+
+pub unsafe fn syscall4(number: i64, a1: i64, a2: i64, a3: i64, a4: i64) -> i64 {
+    let result;
+    asm!(
+        "syscall",
+        inlateout("rax") number => result,
+        in("rdi") a1,
+        in("rsi") a2,
+        in("rdx") a3,
+        in("r10") a4,
+        out("rcx") _,
+        out("r11") _,
+        options(nostack)
+    );
+    result
+}
+
+pub unsafe fn syscall5(number: i64, a1: i64, a2: i64, a3: i64, a4: i64, a5: i64) -> i64 {
+    let result;
+    asm!(
+        "syscall",
+        inlateout("rax") number => result,
+        in("rdi") a1,
+        in("rsi") a2,
+        in("rdx") a3,
+        in("r10") a4,
+        in("r8") a5,
+        out("rcx") _,
+        out("r11") _,
+        options(nostack)
+    );
+    result
+}
+
+pub unsafe fn syscall6(number: i64, a1: i64, a2: i64, a3: i64, a4: i64, a5: i64, a6: i64) -> i64 {
+    let result;
+    asm!(
+        "syscall",
+        inlateout("rax") number => result,
+        in("rdi") a1,
+        in("rsi") a2,
+        in("rdx") a3,
+        in("r10") a4,
+        in("r8") a5,
+        in("r9") a6,
+        out("rcx") _,
+        out("r11") _,
+        options(nostack)
+    );
+    result
+}
+
+fn set_errno(result: i64) -> i64 {
+    if (-MAX_ERRNO..0).contains(&result) {
+        unsafe {
+            *libc::__errno_location() = -result as i32;
+        }
+        -1
+    } else {
+        result
+    }
+}
+
+pub unsafe fn mmap(
+    address: *const libc::c_void,
+    length: libc::size_t,
+    protection: i32,
+    flags: i32,
+    file_descriptor: i32,
+    offset: libc::off_t,
+) -> *const libc::c_void {
+    let result = syscall6(
+        SYS_MMAP,
+        address as i64,
+        length as i64,
+        protection as i64,
+        flags as i64,
+        file_descriptor as i64,
+        offset,
+    );
+    if set_errno(result) == -1 {
+        libc::MAP_FAILED
+    } else {
+        result as *const libc::c_void
+    }
+}
+
+pub unsafe fn munmap(address: *const libc::c_void, length: libc::size_t) -> i32 {
+    set_errno(syscall2(SYS_MUNMAP, address as i64, length as i64)) as i32
+}
+
+pub unsafe fn madvise(address: *const libc::c_void, length: libc::size_t, advice: i32) -> i32 {
+    set_errno(syscall3(
+        SYS_MADVISE,
+        address as i64,
+        length as i64,
+        advice as i64,
+    )) as i32
+}
+
+pub unsafe fn open(pathname: *const libc::c_char, flags: i32) -> i32 {
+    set_errno(syscall4(
+        SYS_OPENAT,
+        libc::AT_FDCWD as i64,
+        pathname as i64,
+        flags as i64,
+        0,
+    )) as i32
+}
+
+pub unsafe fn close(file_descriptor: i32) -> i32 {
+    set_errno(syscall1(SYS_CLOSE, file_descriptor as i64)) as i32
+}
+
+pub unsafe fn fstat(file_descriptor: i32, result: *mut libc::stat) -> i32 {
+    set_errno(syscall2(SYS_FSTAT, file_descriptor as i64, result as i64)) as i32
+}
+
+pub unsafe fn pread64(
+    file_descriptor: i32,
+    buffer: *mut libc::c_void,
+    count: libc::size_t,
+    offset: libc::off_t,
+) -> libc::ssize_t {
+    set_errno(syscall4(
+        SYS_PREAD64,
+        file_descriptor as i64,
+        buffer as i64,
+        count as i64,
+        offset,
+    )) as libc::ssize_t
+}
+
+pub unsafe fn read(
+    file_descriptor: i32,
+    buffer: *mut libc::c_void,
+    count: libc::size_t,
+) -> libc::ssize_t {
+    set_errno(syscall3(
+        SYS_READ,
+        file_descriptor as i64,
+        buffer as i64,
+        count as i64,
+    )) as libc::ssize_t
+}
+
+pub unsafe fn waitid(
+    id_type: libc::idtype_t,
+    id: libc::id_t,
+    info: *mut libc::siginfo_t,
+    options: i32,
+) -> i32 {
+    set_errno(syscall5(
+        SYS_WAITID,
+        id_type as i64,
+        id as i64,
+        info as i64,
+        options as i64,
+        0,
+    )) as i32
+}
+
+pub unsafe fn memfd_create(name: *const libc::c_char, flags: u32) -> i32 {
+    set_errno(syscall2(SYS_MEMFD_CREATE, name as i64, flags as i64)) as i32
+}
+
+pub unsafe fn fcntl(file_descriptor: i32, command: i32, argument: i64) -> i32 {
+    set_errno(syscall3(
+        SYS_FCNTL,
+        file_descriptor as i64,
+        command as i64,
+        argument,
+    )) as i32
+}
+
+global_asm!(
+    ".globl drow_signal_restorer",
+    "drow_signal_restorer:",
+    "mov rax, {rt_sigreturn}",
+    "syscall",
+    rt_sigreturn = const SYS_RT_SIGRETURN,
+);
+
+extern "C" {
+    fn drow_signal_restorer();
+}
+
+/// The kernel's `struct sigaction`, which differs from the libc one.
+#[repr(C)]
+struct KernelSigaction {
+    handler: libc::sighandler_t,
+    flags: u64,
+    restorer: usize,
+    mask: u64,
+}
+
+pub unsafe fn sigaction(signal: i32, handler: libc::sighandler_t, flags: i32) -> i32 {
+    let action = KernelSigaction {
+        handler,
+        flags: flags as u64 | SA_RESTORER,
+        restorer: drow_signal_restorer as *const () as usize,
+        mask: 0,
+    };
+    set_errno(syscall4(
+        SYS_RT_SIGACTION,
+        signal as i64,
+        &action as *const KernelSigaction as i64,
+        0,
+        size_of::<u64>() as i64,
+    )) as i32
+}
+
+pub unsafe fn sigaltstack(stack: *const libc::stack_t) -> i32 {
+    set_errno(syscall2(SYS_SIGALTSTACK, stack as i64, 0)) as i32
+}
+
+/// The kernel's signal set, one bit per signal from 1.
+fn signal_set(signal: i32) -> u64 {
+    1 << (signal - 1)
+}
+
+pub unsafe fn mask_signal(how: i32, signal: i32) -> i32 {
+    let set = signal_set(signal);
+    set_errno(syscall4(
+        SYS_RT_SIGPROCMASK,
+        how as i64,
+        &set as *const u64 as i64,
+        0,
+        size_of::<u64>() as i64,
+    )) as i32
+}
+
+pub unsafe fn wait_signal(signal: i32) -> i32 {
+    let set = signal_set(signal);
+    set_errno(syscall4(
+        SYS_RT_SIGTIMEDWAIT,
+        &set as *const u64 as i64,
+        0,
+        0,
+        size_of::<u64>() as i64,
+    )) as i32
+}
+
+pub unsafe fn kill_self(signal: i32) -> i32 {
+    let pid = syscall1(SYS_GETPID, 0);
+    set_errno(syscall2(SYS_KILL, pid, signal as i64)) as i32
+}
+
+pub unsafe fn arch_prctl(code: i32, address: u64) -> i32 {
+    set_errno(syscall2(SYS_ARCH_PRCTL, code as i64, address as i64)) as i32
+}
+
+pub unsafe fn getrlimit(resource: i32, limit: *mut libc::rlimit) -> i32 {
+    set_errno(syscall2(SYS_GETRLIMIT, resource as i64, limit as i64)) as i32
+}
+
+pub unsafe fn exit_group(status: i32) -> ! {
+    syscall1(SYS_EXIT_GROUP, status as i64);
+    unreachable!()
+}
+
+pub unsafe fn clone3(args: &CloneArgs, entry: ChildEntry, arg: *mut libc::c_void) -> i32 {
+    let result: i64;
+    asm!(
+        "syscall",
+        "test rax, rax",
+        "jnz 2f",
+        "xor ebp, ebp",
+        "and rsp, -16",
+        "mov rdi, r13",
+        "call r12",
+        "mov edi, eax",
+        "mov eax, {exit}",
+        "syscall",
+        "2:",
+        exit = const SYS_EXIT,
+        inlateout("rax") SYS_CLONE3 => result,
+        in("rdi") args as *const CloneArgs as i64,
+        in("rsi") size_of::<CloneArgs>() as i64,
+        in("r12") entry as usize as i64,
+        in("r13") arg as i64,
+        out("rcx") _,
+        out("r11") _,
+    );
+    set_errno(result) as i32
+}
@@ -0,0 +1,210 @@
+use drow::loader::{Elf64Loader, LoadReport};
+use drow::offset_reader::OffsetReader;
+use drow::summary::read_build_id;
+use drow::{DrowError, Elf64Metadata};
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::io::FromRawFd;
+use std::time::Duration;
+
+/// A JSON string literal of `value`. Shared with the --json output of bench, so fields they have
+/// in common are spelled the same.
+pub fn json_string(value: &str) -> String {
+    let mut result = String::from("\"");
+    for ch in value.chars() {
+        match ch {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\t' => result.push_str("\\t"),
+            ch if (ch as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => result.push(ch),
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn json_optional(value: Option<&str>) -> String {
+    value
+        .map(json_string)
+        .unwrap_or_else(|| String::from("null"))
+}
+
+/// Read again from the file, as the loader keeps no notes. Objects loaded from memory have none.
+fn build_id(path: &str) -> Option<String> {
+    let mut reader = OffsetReader::open(path).ok()?;
+    let metadata = Elf64Metadata::load(&path.to_string(), &mut reader).ok()?;
+    read_build_id(&metadata, &mut reader).ok()?
+}
+
+fn json_optional_number(value: Option<u64>) -> String {
+    value
+        .map(|value| value.to_string())
+        .unwrap_or_else(|| String::from("null"))
+}
+
+fn json_array(items: &[String]) -> String {
+    if items.is_empty() {
+        String::from("[]")
+    } else {
+        format!("[\n{}\n  ]", items.join(",\n"))
+    }
+}
+
+fn phase(name: &str, duration: Duration) -> String {
+    format!(
+        "    {{\"phase\": {}, \"duration_us\": {:.3}}}",
+        json_string(name),
+        duration.as_secs_f64() * 1e6
+    )
+}
+
+fn to_json(file_path: &str, report: &LoadReport) -> String {
+    let objects: Vec<String> = report
+        .objects
+        .iter()
+        .map(|object| {
+            let relocations: Vec<String> = object
+                .relocations
+                .iter()
+                .map(|(name, count)| format!("{}: {}", json_string(name), count))
+                .collect();
+            let rules: Vec<String> = object.rules.iter().map(|rule| json_string(rule)).collect();
+            format!(
+                "    {{\"path\": {}, \"soname\": {}, \"kind\": {}, \"base\": {}, \"build_id\": {}, \"tls_size\": {}, \"phdr\": {}, \"phnum\": {}, \"relocations\": {{{}}}, \"mapped_bytes\": {}, \"writable_bytes\": {}, \"resident_bytes\": {}, \"dirty_bytes\": {}, \"rules\": [{}]}}",
+                json_string(&object.path),
+                json_optional(object.soname.as_deref()),
+                json_string(&object.kind.to_string()),
+                object.base,
+                json_optional(build_id(&object.path).as_deref()),
+                object.tls_size,
+                object.program_headers.address,
+                object.program_headers.count,
+                relocations.join(", "),
+                object.memory.mapped,
+                object.memory.writable,
+                json_optional_number(object.memory.resident),
+                json_optional_number(object.memory.dirty),
+                rules.join(", ")
+            )
+        })
+        .collect();
+    let unresolved: Vec<String> = report
+        .unresolved_symbols
+        .iter()
+        .map(|(object, symbol)| {
+            format!(
+                "    {{\"object\": {}, \"symbol\": {}}}",
+                json_string(object),
+                json_string(symbol)
+            )
+        })
+        .collect();
+    let missing_versions: Vec<String> = report
+        .missing_versions
+        .iter()
+        .map(|missing| {
+            let symbols: Vec<String> = missing
+                .symbols
+                .iter()
+                .map(|symbol| json_string(symbol))
+                .collect();
+            let fallbacks: Vec<String> = missing
+                .fallbacks
+                .iter()
+                .map(|fallback| {
+                    format!(
+                        "{}: {}",
+                        json_string(&fallback.symbol),
+                        json_string(&fallback.version)
+                    )
+                })
+                .collect();
+            format!(
+                "    {{\"requester\": {}, \"library\": {}, \"provider\": {}, \"version\": {}, \"symbols\": [{}], \"fallbacks\": {{{}}}}}",
+                json_string(&missing.requester),
+                json_string(&missing.library),
+                json_string(&missing.provider),
+                json_string(&missing.version),
+                symbols.join(", "),
+                fallbacks.join(", ")
+            )
+        })
+        .collect();
+    let times = &report.phase_times;
+    let phases = [
+        phase("resolve", times.resolve),
+        phase("map", times.map),
+        phase("relocate", times.relocate),
+    ];
+    let mut json = String::from("{\n");
+    json.push_str(&format!("  \"file\": {},\n", json_string(file_path)));
+    json.push_str(&format!("  \"entry\": {},\n", report.entry));
+    json.push_str(&format!(
+        "  \"static_tls_size\": {},\n",
+        report.static_tls_size
+    ));
+    json.push_str(&format!("  \"objects\": {},\n", json_array(&objects)));
+    json.push_str(&format!(
+        "  \"unresolved_symbols\": {},\n",
+        json_array(&unresolved)
+    ));
+    json.push_str(&format!(
+        "  \"missing_versions\": {},\n",
+        json_array(&missing_versions)
+    ));
+    json.push_str(&format!("  \"phases\": {}\n", json_array(&phases)));
+    json.push_str("}\n");
+    json
+}
+
+/// Opens `destination`, a path or `fd:N` for a descriptor inherited from the supervisor.
+fn open_destination(destination: &str) -> Result<File, DrowError> {
+    match destination.strip_prefix("fd:") {
+        Some(descriptor) => {
+            let descriptor: i32 = descriptor.parse().map_err(|_| DrowError::Io {
+                path: destination.to_string(),
+                source: std::io::Error::from_raw_os_error(libc::EBADF),
+            })?;
+            // The standard streams stay open for the program, so the report gets a copy of them.
+            // Other descriptors are the supervisor's and closing them ends its read.
+            let owned = if descriptor <= libc::STDERR_FILENO {
+                unsafe { libc::fcntl(descriptor, libc::F_DUPFD_CLOEXEC, 0) }
+            } else if unsafe { libc::fcntl(descriptor, libc::F_GETFD) } < 0 {
+                -1
+            } else {
+                descriptor
+            };
+            if owned < 0 {
+                return Err(DrowError::Io {
+                    path: destination.to_string(),
+                    source: std::io::Error::last_os_error(),
+                });
+            }
+            Ok(unsafe { File::from_raw_fd(owned) })
+        }
+        None => File::create(destination).map_err(|source| DrowError::Io {
+            path: destination.to_string(),
+            source,
+        }),
+    }
+}
+
+/// Writes the report of what `elf_loader` loaded to `destination` as JSON, then closes it so a
+/// supervisor reading it sees the end before the program starts. Resident and dirty sizes are
+/// included when `rss`.
+pub fn write(
+    destination: &str,
+    file_path: &str,
+    elf_loader: &Elf64Loader,
+    rss: bool,
+) -> Result<(), DrowError> {
+    let json = to_json(file_path, &elf_loader.load_report(rss));
+    let mut file = open_destination(destination)?;
+    file.write_all(json.as_bytes())
+        .map_err(|source| DrowError::Io {
+            path: destination.to_string(),
+            source,
+        })
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Finds libraries by file name anywhere below a set of directories, like an ld.so.cache built
+/// from those directories alone. Symbolic links, such as the soname links next to versioned
+/// libraries, are followed only when they stay inside the directories, so a tree extracted from
+/// a container image never resolves to a library of the host.
+pub struct SearchDirectories {
+    directories: Vec<PathBuf>,
+    /// Built on the first lookup.
+    index: Option<HashMap<String, String>>,
+}
+
+impl SearchDirectories {
+    pub fn new(directories: &[String]) -> SearchDirectories {
+        SearchDirectories {
+            directories: directories.iter().map(PathBuf::from).collect(),
+            index: None,
+        }
+    }
+
+    pub fn directories(&self) -> &[PathBuf] {
+        &self.directories
+    }
+
+    pub fn find(&mut self, library: &str) -> Option<String> {
+        if self.index.is_none() {
+            self.index = Some(self.build_index());
+        }
+        self.index.as_ref()?.get(library).cloned()
+    }
+
+    /// Earlier directories win, then files closer to the top of a directory, then names in
+    /// byte order.
+    fn build_index(&self) -> HashMap<String, String> {
+        let roots: Vec<PathBuf> = self
+            .directories
+            .iter()
+            .filter_map(|directory| match fs::canonicalize(directory) {
+                Ok(root) => Some(root),
+                Err(err) => {
+                    warn!("Unable to read directory {}: {}", directory.display(), err);
+                    None
+                }
+            })
+            .collect();
+        let mut index = HashMap::new();
+        for root in roots.iter() {
+            let mut level = vec![root.clone()];
+            while !level.is_empty() {
+                let mut next = Vec::new();
+                for directory in level.iter() {
+                    self.index_directory(directory, &roots, &mut index, &mut next);
+                }
+                level = next;
+            }
+        }
+        debug!("Indexed {} file(s) in the search directories", index.len());
+        index
+    }
+
+    fn index_directory(
+        &self,
+        directory: &Path,
+        roots: &[PathBuf],
+        index: &mut HashMap<String, String>,
+        subdirectories: &mut Vec<PathBuf>,
+    ) {
+        let mut entries: Vec<fs::DirEntry> = match fs::read_dir(directory) {
+            Ok(entries) => entries.flatten().collect(),
+            Err(err) => {
+                warn!("Unable to read directory {}: {}", directory.display(), err);
+                return;
+            }
+        };
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(_) => continue,
+            };
+            if file_type.is_dir() {
+                subdirectories.push(entry.path());
+                continue;
+            }
+            let name = match entry.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+            if index.contains_key(&name) {
+                continue;
+            }
+            let target = match fs::canonicalize(entry.path()) {
+                Ok(target) if target.is_file() => target,
+                _ => continue,
+            };
+            if !roots.iter().any(|root| target.starts_with(root)) {
+                debug!(
+                    "Ignoring {}: it points outside the search directories, to {}",
+                    entry.path().display(),
+                    target.display()
+                );
+                continue;
+            }
+            if let Some(target) = target.to_str() {
+                index.insert(name, target.to_string());
+            }
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::fmt::{Display, Formatter};
+
+use crate::config_file::Value;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    String,
+    Strings,
+    Integer,
+    Boolean,
+}
+
+/// A persistent option, settable from the config file as `key` and from the environment as
+/// `variable`.
+pub struct Setting {
+    pub key: &'static str,
+    pub variable: &'static str,
+    pub kind: Kind,
+    pub help: &'static str,
+}
+
+pub const CONFIG_VARIABLE: &str = "DROW_CONFIG";
+
+pub const SETTINGS: &[Setting] = &[
+    Setting {
+        key: "search_paths",
+        variable: "DROW_LIBRARY_PATH",
+        kind: Kind::Strings,
+        help: "Directories searched after LD_LIBRARY_PATH",
+    },
+    Setting {
+        key: "preload",
+        variable: "DROW_PRELOAD",
+        kind: Kind::Strings,
+        help: "Libraries loaded ahead of the program",
+    },
+    Setting {
+        key: "stack_size",
+        variable: "DROW_STACK_SIZE",
+        kind: Kind::Integer,
+        help: "Size of the program stack",
+    },
+    Setting {
+        key: "base",
+        variable: "DROW_BASE",
+        kind: Kind::Integer,
+        help: "Address at which the first object is mapped",
+    },
+    Setting {
+        key: "max_object_size",
+        variable: "DROW_MAX_OBJECT_SIZE",
+        kind: Kind::Integer,
+        help: "Largest address range the segments of one object may span",
+    },
+    Setting {
+        key: "bind_now",
+        variable: "DROW_BIND_NOW",
+        kind: Kind::Boolean,
+        help: "Bind all symbols at load time",
+    },
+    Setting {
+        key: "log_level",
+        variable: "DROW_LOG",
+        kind: Kind::String,
+        help: "error, warn, info, debug or trace",
+    },
+    Setting {
+        key: "log_file",
+        variable: "DROW_LOG_FILE",
+        kind: Kind::String,
+        help: "File diagnostics are appended to",
+    },
+    Setting {
+        key: "sysroot",
+        variable: "DROW_SYSROOT",
+        kind: Kind::String,
+        help: "Root filesystem libraries are resolved against",
+    },
+];
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Source {
+    Default,
+    File(String),
+    Environment(&'static str),
+    CommandLine,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File(path) => write!(f, "config file {}", path),
+            Source::Environment(variable) => write!(f, "environment {}", variable),
+            Source::CommandLine => write!(f, "command line"),
+        }
+    }
+}
+
+pub fn find(key: &str) -> Option<&'static Setting> {
+    SETTINGS.iter().find(|setting| setting.key == key)
+}
+
+/// Decimal or `0x` hexadecimal, with an optional K, M or G suffix for decimal values.
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let invalid = || format!("Invalid number {}", text);
+    if let Some(hex) = text.strip_prefix("0x") {
+        return u64::from_str_radix(hex, 16).map_err(|_| invalid());
+    }
+    let (digits, multiplier) = match text.chars().last().map(|ch| ch.to_ascii_uppercase()) {
+        Some('K') => (&text[..text.len() - 1], 1 << 10),
+        Some('M') => (&text[..text.len() - 1], 1 << 20),
+        Some('G') => (&text[..text.len() - 1], 1 << 30),
+        _ => (text, 1),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|value| value.checked_mul(multiplier))
+        .ok_or_else(invalid)
+}
+
+/// Converts the text of an environment variable to the value the config file would hold.
+pub fn parse_variable(setting: &Setting, text: &str) -> Result<Value, String> {
+    match setting.kind {
+        Kind::String => Ok(Value::String(text.to_string())),
+        Kind::Strings => Ok(Value::Array(
+            text.split(':')
+                .filter(|part| !part.is_empty())
+                .map(|part| Value::String(part.to_string()))
+                .collect(),
+        )),
+        Kind::Integer => parse_size(text).map(Value::Integer),
+        Kind::Boolean => match text.to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Ok(Value::Boolean(true)),
+            "0" | "false" | "no" | "off" | "" => Ok(Value::Boolean(false)),
+            _ => Err(format!("Invalid boolean {}, expected 1 or 0", text)),
+        },
+    }
+}
@@ -0,0 +1,378 @@
+use crate::cli::{suggestion, with_suggestion, Config};
+use crate::{load_metadata, loader_builder, open, print_libraries};
+use drow::debuginfo;
+use drow::dependency_graph::DependencyGraph;
+use drow::glob::glob_match;
+use drow::loader::DependenciesResolver;
+use drow::offset_reader::OffsetReader;
+use drow::printer;
+use drow::symbol_name::SymbolName;
+use drow::table::Table;
+use drow::versions::{self, SymbolVersion};
+use drow::{
+    error, DrowError, Elf64Metadata, Elf64ResolvedRelocationAddend, Elf64ResolvedSymbolTableEntry,
+};
+use std::io::{self, BufRead, Write};
+
+struct ShellCommand {
+    name: &'static str,
+    usage: &'static str,
+    help: &'static str,
+}
+
+const COMMANDS: &[ShellCommand] = &[
+    ShellCommand {
+        name: "header",
+        usage: "header",
+        help: "Print the ELF header and program headers",
+    },
+    ShellCommand {
+        name: "sections",
+        usage: "sections [glob]",
+        help: "Print the section headers, optionally only those matching glob",
+    },
+    ShellCommand {
+        name: "symbols",
+        usage: "symbols [glob]",
+        help: "Print the symbol tables, optionally only the names matching glob",
+    },
+    ShellCommand {
+        name: "relocs",
+        usage: "relocs [type]",
+        help: "Print the relocations, optionally only those of type, like JUMP_SLOT",
+    },
+    ShellCommand {
+        name: "dynamic",
+        usage: "dynamic",
+        help: "Print the dynamic section",
+    },
+    ShellCommand {
+        name: "deps",
+        usage: "deps",
+        help: "Resolve the dependency tree",
+    },
+    ShellCommand {
+        name: "sym",
+        usage: "sym <name>[@version]",
+        help: "Look up a symbol by name",
+    },
+    ShellCommand {
+        name: "addr",
+        usage: "addr <hex>",
+        help: "Find the symbol and section containing an address",
+    },
+    ShellCommand {
+        name: "help",
+        usage: "help",
+        help: "Print this help",
+    },
+    ShellCommand {
+        name: "quit",
+        usage: "quit",
+        help: "Leave the shell, as does the end of input",
+    },
+];
+
+const SYMBOL_TYPE_SECTION: u8 = 3;
+const SYMBOL_TYPE_FILE: u8 = 4;
+
+fn relocation_type_matches(relocation: &Elf64ResolvedRelocationAddend, name: &str) -> bool {
+    let type_name = relocation.type_name();
+    type_name.eq_ignore_ascii_case(name)
+        || type_name
+            .strip_prefix("R_X86_64_")
+            .or_else(|| type_name.strip_prefix("R_AARCH64_"))
+            .map(|short| short.eq_ignore_ascii_case(name))
+            .unwrap_or(false)
+        || name.parse::<u64>() == Ok(relocation.relocation_type)
+}
+
+fn parse_address(text: &str) -> Result<u64, String> {
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    u64::from_str_radix(digits, 16).map_err(|_| format!("Invalid address {}", text))
+}
+
+struct Shell<'a> {
+    config: &'a Config,
+    elf_metadata: Elf64Metadata,
+    reader: OffsetReader,
+    section_names: Vec<String>,
+    versions: Option<Vec<Option<SymbolVersion>>>,
+    resolver: Option<DependenciesResolver>,
+    color: bool,
+}
+
+impl<'a> Shell<'a> {
+    fn symbols(&self, pattern: Option<&str>) {
+        let filter = |symbol: &Elf64ResolvedSymbolTableEntry| {
+            pattern
+                .map(|pattern| glob_match(pattern, &symbol.symbol_name))
+                .unwrap_or(true)
+        };
+        let tables = [
+            ("Symbol table", &self.elf_metadata.symbol_table),
+            (
+                "Dynamic symbol table",
+                &self.elf_metadata.dynamic_symbol_table,
+            ),
+        ];
+        let mut matched = false;
+        for (title, symbols) in tables.iter() {
+            matched |= symbols.iter().any(filter);
+            printer::print_symbols(title, symbols, filter, self.color);
+        }
+        if !matched {
+            println!("No symbols match {}", pattern.unwrap_or("*"));
+        }
+    }
+
+    fn relocations(&self, relocation_type: Option<&str>) {
+        let filter = |relocation: &Elf64ResolvedRelocationAddend| {
+            relocation_type
+                .map(|name| relocation_type_matches(relocation, name))
+                .unwrap_or(true)
+        };
+        let relocations = &self.elf_metadata.relocations;
+        if !relocations.iter().any(&filter) {
+            match relocation_type {
+                Some(name) => println!("No relocations of type {}", name),
+                None => println!("No relocations"),
+            }
+            return;
+        }
+        printer::print_relocations(
+            &self.elf_metadata,
+            &self.section_names,
+            |relocation| filter(relocation),
+            self.color,
+        );
+    }
+
+    fn dependencies(&mut self) -> Result<(), DrowError> {
+        let config = self.config;
+        let resolver = self
+            .resolver
+            .get_or_insert_with(|| loader_builder(config).dependencies_resolver());
+        let graph = DependencyGraph::build(resolver, &self.elf_metadata)?;
+        print_libraries(&graph, self.color);
+        Ok(())
+    }
+
+    fn version(&mut self, index: usize) -> Result<Option<&SymbolVersion>, DrowError> {
+        if self.versions.is_none() {
+            self.versions = Some(versions::dynamic_symbol_versions(
+                &self.elf_metadata,
+                &mut self.reader,
+            )?);
+        }
+        Ok(self
+            .versions
+            .as_ref()
+            .and_then(|versions| versions.get(index))
+            .and_then(|version| version.as_ref()))
+    }
+
+    fn lookup(&mut self, query: &str) -> Result<(), DrowError> {
+        let query = SymbolName::parse(query);
+        let (name, version) = (query.name.as_str(), query.version.as_deref());
+        let mut table = Table::new(&[
+            "Table", "Num", "Value", "Size", "Type", "Bind", "Ndx", "Version",
+        ]);
+        let tables = [
+            ("symtab", &self.elf_metadata.symbol_table),
+            ("dynsym", &self.elf_metadata.dynamic_symbol_table),
+        ];
+        let symbols: Vec<(&str, usize)> = tables
+            .iter()
+            .flat_map(|(table_name, symbols)| {
+                symbols
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, symbol)| symbol.symbol_name == name)
+                    .map(move |(index, _)| (*table_name, index))
+            })
+            .collect();
+        for (table_name, index) in symbols {
+            let symbol_version = if table_name == "dynsym" {
+                self.version(index)?.cloned()
+            } else {
+                None
+            };
+            let symbol = if table_name == "dynsym" {
+                &self.elf_metadata.dynamic_symbol_table[index]
+            } else {
+                &self.elf_metadata.symbol_table[index]
+            };
+            let version_name = symbol_version.as_ref().map(|version| version.name.as_str());
+            if version.is_some() && version != version_name {
+                continue;
+            }
+            let version_text = match symbol_version.as_ref() {
+                Some(version) if version.hidden || symbol.undefined() => {
+                    format!("@{}", version.name)
+                }
+                Some(version) => format!("@@{}", version.name),
+                None => String::from("-"),
+            };
+            table.add_row(vec![
+                table_name.to_string(),
+                index.to_string(),
+                format!("{:016X}", symbol.value),
+                symbol.size.to_string(),
+                printer::symbol_type_name(symbol.symbol_type),
+                printer::symbol_binding_name(symbol.binding),
+                printer::symbol_section_name(symbol.section_index),
+                version_text,
+            ]);
+        }
+        if table.is_empty() {
+            println!("No symbol named {}", query);
+        } else {
+            print!("{}", table.render(self.color));
+        }
+        Ok(())
+    }
+
+    fn address(&self, address: u64) {
+        let section = self
+            .elf_metadata
+            .section_headers
+            .iter()
+            .enumerate()
+            .filter(|(_, section)| section.allocated_in_memory())
+            .find(|(_, section)| {
+                section.sh_virtual_address <= address
+                    && address < section.sh_virtual_address + section.sh_size
+            })
+            .map(|(index, _)| self.section_names[index].clone());
+        let symbol = self
+            .elf_metadata
+            .symbol_table
+            .iter()
+            .chain(self.elf_metadata.dynamic_symbol_table.iter())
+            .filter(|symbol| !symbol.undefined())
+            .filter(|symbol| {
+                symbol.symbol_type != SYMBOL_TYPE_SECTION && symbol.symbol_type != SYMBOL_TYPE_FILE
+            })
+            .filter(|symbol| {
+                symbol.value <= address
+                    && (address < symbol.value + symbol.size
+                        || (symbol.size == 0 && symbol.value == address))
+            })
+            .max_by_key(|symbol| symbol.value);
+        if section.is_none() && symbol.is_none() {
+            println!("{:#x} is not in any section or symbol", address);
+            return;
+        }
+        let mut table = Table::new(&["Address", "Symbol", "Section"]);
+        table.add_row(vec![
+            format!("{:#x}", address),
+            symbol
+                .map(|symbol| match address - symbol.value {
+                    0 => symbol.symbol_name.clone(),
+                    offset => format!("{}+{:#x}", symbol.symbol_name, offset),
+                })
+                .unwrap_or_else(|| String::from("-")),
+            section.unwrap_or_else(|| String::from("-")),
+        ]);
+        print!("{}", table.render(self.color));
+    }
+
+    fn help(&self) {
+        let mut table = Table::new(&["Command", "Description"]);
+        for command in COMMANDS.iter() {
+            table.add_row(vec![command.usage.to_string(), command.help.to_string()]);
+        }
+        print!("{}", table.render(self.color));
+    }
+
+    /// Runs one line of input and returns false once the shell should stop.
+    fn execute(&mut self, line: &str) -> Result<bool, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, arguments) = match words.split_first() {
+            Some((command, arguments)) => (*command, arguments),
+            None => return Ok(true),
+        };
+        if arguments.len() > 1 {
+            return Err(format!("{} takes at most one argument", command));
+        }
+        let argument = arguments.first().copied();
+        let required = || argument.ok_or_else(|| format!("{} expects an argument", command));
+        match command {
+            "header" => printer::print_header(&self.elf_metadata, self.color),
+            "sections" => printer::print_sections(
+                &self.elf_metadata,
+                &self.section_names,
+                |name| {
+                    argument
+                        .map(|pattern| glob_match(pattern, name))
+                        .unwrap_or(true)
+                },
+                self.color,
+            ),
+            "symbols" => self.symbols(argument),
+            "relocs" => self.relocations(argument),
+            "dynamic" => printer::print_dynamic(&self.elf_metadata, self.color),
+            "deps" => self.dependencies().map_err(|err| err.to_string())?,
+            "sym" => self.lookup(required()?).map_err(|err| err.to_string())?,
+            "addr" => self.address(parse_address(required()?)?),
+            "help" => self.help(),
+            "quit" | "exit" => return Ok(false),
+            _ => {
+                return Err(with_suggestion(
+                    format!("Unknown command {}", command),
+                    suggestion(command, COMMANDS.iter().map(|command| command.name)),
+                ))
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Parses `file_path` once and runs the commands read from stdin until `quit` or the end of
+/// input. A failing command is reported and the shell carries on.
+pub fn run(config: &Config, file_path: &String, color: bool) -> Result<i32, DrowError> {
+    let mut reader = open(file_path)?;
+    let mut elf_metadata = load_metadata(file_path, &mut reader, false)?;
+    debuginfo::load_symbols(&mut elf_metadata, &mut reader, &config.debuginfo_dirs);
+    let string_tables_content = printer::string_tables_content(&elf_metadata, &mut reader)?;
+    let section_names = printer::section_names(&elf_metadata, &string_tables_content);
+    let mut shell = Shell {
+        config,
+        elf_metadata,
+        reader,
+        section_names,
+        versions: None,
+        resolver: None,
+        color,
+    };
+    let interactive = unsafe { libc::isatty(libc::STDIN_FILENO) == 1 };
+    let stdin = io::stdin();
+    let mut line = String::new();
+    loop {
+        if interactive {
+            print!("drow> ");
+            let _ = io::stdout().flush();
+        }
+        line.clear();
+        let read = stdin
+            .lock()
+            .read_line(&mut line)
+            .map_err(|source| DrowError::Io {
+                path: String::from("<stdin>"),
+                source,
+            })?;
+        if read == 0 {
+            break;
+        }
+        match shell.execute(&line) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(err) => error!("{}", err),
+        }
+    }
+    Ok(0)
+}
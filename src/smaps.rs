@@ -0,0 +1,59 @@
+//! Resident and dirty sizes of the mappings of the process, from /proc/self/smaps.
+
+const PROC_SELF_SMAPS: &str = "/proc/self/smaps";
+
+pub struct SmapsEntry {
+    pub start: u64,
+    pub end: u64,
+    pub resident: u64,
+    /// Shared and private dirty bytes.
+    pub dirty: u64,
+}
+
+fn range(line: &str) -> Option<(u64, u64)> {
+    let (start, end) = line.split_whitespace().next()?.split_once('-')?;
+    Some((
+        u64::from_str_radix(start, 16).ok()?,
+        u64::from_str_radix(end, 16).ok()?,
+    ))
+}
+
+/// The size in bytes of a `Key: N kB` line.
+fn size(line: &str) -> u64 {
+    line.split_whitespace()
+        .nth(1)
+        .and_then(|value| value.parse::<u64>().ok())
+        .map(|value| value * 1024)
+        .unwrap_or(0)
+}
+
+pub fn parse(content: &str) -> Vec<SmapsEntry> {
+    let mut entries: Vec<SmapsEntry> = Vec::new();
+    for line in content.lines() {
+        if let Some((start, end)) = range(line) {
+            entries.push(SmapsEntry {
+                start,
+                end,
+                resident: 0,
+                dirty: 0,
+            });
+            continue;
+        }
+        let entry = match entries.last_mut() {
+            Some(entry) => entry,
+            None => continue,
+        };
+        if line.starts_with("Rss:") {
+            entry.resident = size(line);
+        } else if line.starts_with("Private_Dirty:") || line.starts_with("Shared_Dirty:") {
+            entry.dirty += size(line);
+        }
+    }
+    entries
+}
+
+pub fn read() -> Result<Vec<SmapsEntry>, String> {
+    std::fs::read_to_string(PROC_SELF_SMAPS)
+        .map(|content| parse(&content))
+        .map_err(|err| format!("Unable to read {}: {}", PROC_SELF_SMAPS, err))
+}
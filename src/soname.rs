@@ -0,0 +1,123 @@
+use std::fs;
+
+use crate::offset_reader::OffsetReader;
+use crate::Elf64Metadata;
+
+/// Splits the trailing numeric components off a library name, so `libfoo.so.3.1` becomes
+/// `libfoo.so` and `[3, 1]`. Returns `None` for names that are not of the `*.so[.N...]` form.
+pub fn split_version(name: &str) -> Option<(&str, Vec<u32>)> {
+    let mut stem = name;
+    let mut versions = Vec::new();
+    while let Some((rest, component)) = stem.rsplit_once('.') {
+        match component.parse::<u32>() {
+            Ok(version) if !component.starts_with('+') => {
+                versions.push(version);
+                stem = rest;
+            }
+            _ => break,
+        }
+    }
+    versions.reverse();
+    if stem.ends_with(".so") && stem.len() > ".so".len() {
+        Some((stem, versions))
+    } else {
+        None
+    }
+}
+
+/// A library found under another version of the requested name.
+pub struct Candidate {
+    pub name: String,
+    pub path: String,
+    versions: Vec<u32>,
+}
+
+impl Candidate {
+    /// Returns `None` unless `name` has the stem of the requested library and another version.
+    pub fn new(name: &str, path: String, stem: &str, requested: &[u32]) -> Option<Candidate> {
+        let (candidate_stem, versions) = split_version(name)?;
+        if candidate_stem != stem || versions == requested {
+            return None;
+        }
+        Some(Candidate {
+            name: name.to_string(),
+            path,
+            versions,
+        })
+    }
+
+    /// Candidates sharing more leading version components with the request rank first, then
+    /// higher versions.
+    fn rank(&self, requested: &[u32]) -> (usize, Vec<u32>) {
+        let common = self
+            .versions
+            .iter()
+            .zip(requested.iter())
+            .take_while(|(candidate, requested)| candidate == requested)
+            .count();
+        (common, self.versions.clone())
+    }
+
+    /// The candidate must not declare a DT_SONAME of another library.
+    fn soname_matches(&self, stem: &str) -> bool {
+        let metadata = OffsetReader::open(&self.path)
+            .ok()
+            .and_then(|mut reader| Elf64Metadata::load(&self.path, &mut reader).ok());
+        match metadata {
+            Some(metadata) => match metadata.dynamic.soname.as_ref() {
+                Some(soname) => {
+                    split_version(soname).map(|(soname_stem, _)| soname_stem) == Some(stem)
+                }
+                None => true,
+            },
+            None => false,
+        }
+    }
+}
+
+/// Files in `directories` with the stem of `library`, as (name, path) pairs.
+pub fn directory_candidates(directories: &[String], library: &str) -> Vec<(String, String)> {
+    let stem = match split_version(library) {
+        Some((stem, _)) => stem,
+        None => return Vec::new(),
+    };
+    let mut result = Vec::new();
+    for directory in directories.iter() {
+        let entries = match fs::read_dir(directory) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let name = match entry.file_name().into_string() {
+                Ok(name) if name.starts_with(stem) => name,
+                _ => continue,
+            };
+            if entry.path().is_file() {
+                let path = format!("{}/{}", directory.trim_end_matches('/'), name);
+                result.push((name, path));
+            }
+        }
+    }
+    result
+}
+
+/// Picks the best of `candidates` for `library`, skipping those whose DT_SONAME names another
+/// library. Candidates with the same rank keep their order, so earlier search paths win.
+pub fn best_candidate(library: &str, candidates: Vec<(String, String)>) -> Option<Candidate> {
+    let (stem, requested) = split_version(library)?;
+    let mut candidates: Vec<Candidate> = candidates
+        .into_iter()
+        .filter_map(|(name, path)| Candidate::new(&name, path, stem, &requested))
+        .collect();
+    candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.rank(&requested)));
+    candidates.into_iter().find(|candidate| {
+        let matches = candidate.soname_matches(stem);
+        if !matches {
+            debug!(
+                "{} does not match {}, its DT_SONAME names another library",
+                candidate.path, library
+            );
+        }
+        matches
+    })
+}
@@ -1,55 +1,224 @@
-use crate::{Elf64SectionHeader, ELF64_SECTION_HEADER_STRING_TABLE};
+use crate::error::DrowError;
+use crate::offset_reader::read_segment;
+use crate::{
+    Elf64ProgramHeader, Elf64SectionHeader, ELF64_SECTION_HEADER_STRING_TABLE,
+    PROGRAM_HEADER_TYPE_LOADABLE,
+};
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::io::{Read, Seek, SeekFrom};
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Seek};
+
+#[derive(Clone, Default)]
+pub struct StringTableCache {
+    tables: HashMap<usize, StringTable>,
+    reads: HashMap<usize, usize>,
+}
+
+impl StringTableCache {
+    pub fn new() -> StringTableCache {
+        StringTableCache {
+            tables: HashMap::new(),
+            reads: HashMap::new(),
+        }
+    }
+
+    pub fn get<T: Read + Seek>(
+        &mut self,
+        section_headers: &[Elf64SectionHeader],
+        index: usize,
+        reader: &mut T,
+    ) -> Result<&StringTable, DrowError> {
+        if !self.tables.contains_key(&index) {
+            let header = section_headers
+                .get(index)
+                .ok_or_else(|| DrowError::Malformed {
+                    what: format!("string table section {} does not exist", index),
+                    offset: None,
+                })?;
+            let table = StringTable::load(header, reader)?;
+            *self.reads.entry(index).or_insert(0) += 1;
+            self.tables.insert(index, table);
+        }
+        Ok(&self.tables[&index])
+    }
+
+    pub fn load_all<T: Read + Seek>(
+        &mut self,
+        section_headers: &[Elf64SectionHeader],
+        reader: &mut T,
+    ) -> Result<(), DrowError> {
+        for (index, header) in section_headers.iter().enumerate() {
+            if header.sh_type == ELF64_SECTION_HEADER_STRING_TABLE {
+                self.get(section_headers, index, reader)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn tables(&self) -> &HashMap<usize, StringTable> {
+        &self.tables
+    }
+
+    pub fn reads(&self) -> Vec<(usize, usize)> {
+        let mut reads: Vec<(usize, usize)> = self
+            .reads
+            .iter()
+            .map(|(index, count)| (*index, *count))
+            .collect();
+        reads.sort_unstable();
+        reads
+    }
+}
 
 pub fn get_string_tables_content<T: Read + Seek>(
-    section_headers: &Vec<Elf64SectionHeader>,
+    section_headers: &[Elf64SectionHeader],
     reader: &mut T,
-) -> HashMap<u64, Vec<u8>> {
-    let mut result = HashMap::new();
-    let string_table_headers = section_headers
+) -> Result<HashMap<usize, StringTable>, DrowError> {
+    let mut cache = StringTableCache::new();
+    cache.load_all(section_headers, reader)?;
+    Ok(cache.tables)
+}
+
+fn string_table_index(
+    section_headers: &[Elf64SectionHeader],
+    predicate: impl Fn(&Elf64SectionHeader) -> bool,
+) -> Option<usize> {
+    section_headers
+        .iter()
+        .position(|header| header.sh_type == ELF64_SECTION_HEADER_STRING_TABLE && predicate(header))
+}
+
+/// Only tables mapped in memory have an address, unmapped ones have 0 instead.
+pub fn by_virtual_address(section_headers: &[Elf64SectionHeader], address: u64) -> Option<usize> {
+    string_table_index(section_headers, |header| {
+        header.allocated_in_memory() && header.sh_virtual_address == address
+    })
+}
+
+pub fn by_offset(section_headers: &[Elf64SectionHeader], offset: u64) -> Option<usize> {
+    string_table_index(section_headers, |header| header.sh_offset == offset)
+}
+
+pub fn file_offset(program_headers: &[Elf64ProgramHeader], address: u64) -> Option<u64> {
+    program_headers
         .iter()
-        .filter(|t| t.sh_type == ELF64_SECTION_HEADER_STRING_TABLE);
-    for entry in string_table_headers {
-        let content = get_string_table_content(entry, reader);
-        result.insert(entry.sh_virtual_address, content);
+        .filter(|header| header.p_type == PROGRAM_HEADER_TYPE_LOADABLE)
+        .find(|header| {
+            header.p_virtual_address <= address
+                && address < header.p_virtual_address + header.p_file_size
+        })
+        .map(|header| address - header.p_virtual_address + header.p_offset)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StrError {
+    OutOfBounds { offset: usize, length: usize },
+    Unterminated { offset: usize },
+    InvalidUtf8 { offset: usize },
+}
+
+impl Display for StrError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StrError::OutOfBounds { offset, length } => write!(
+                f,
+                "String offset {} out of bounds of table with size {}",
+                offset, length
+            ),
+            StrError::Unterminated { offset } => {
+                write!(f, "String at offset {} is not NUL terminated", offset)
+            }
+            StrError::InvalidUtf8 { offset } => {
+                write!(f, "String at offset {} is not valid UTF-8", offset)
+            }
+        }
     }
-    result
 }
 
-pub fn get_string_table_content<T: Read + Seek>(
-    section_header: &Elf64SectionHeader,
-    reader: &mut T,
-) -> Vec<u8> {
-    let mut buffer: Vec<u8> = Vec::new();
-    buffer.resize(section_header.sh_size as usize, 0);
-    reader
-        .seek(SeekFrom::Start(section_header.sh_offset))
-        .expect("Unable to change position");
-    reader
-        .read_exact(&mut buffer)
-        .expect("Unable to read string table content");
-    buffer
-}
-
-pub fn convert_string_tables_content(
-    string_tables: &HashMap<u64, Vec<u8>>,
-) -> HashMap<u64, Vec<&str>> {
-    let mut result = HashMap::new();
-    for (key, value) in string_tables.iter() {
-        let mut strings = Vec::new();
-        for part in value.split(|x| *x == 0) {
-            strings.push(std::str::from_utf8(part).unwrap());
-        }
-        result.insert(*key, strings);
-    }
-    result
-}
-
-pub fn string_length(string: &[u8]) -> usize {
-    let mut index = 0;
-    while string[index] != 0 {
-        index += 1;
-    }
-    return index + 1;
+impl std::error::Error for StrError {}
+
+fn c_str_at(buf: &[u8], offset: usize) -> Result<&str, StrError> {
+    let tail = buf
+        .get(offset..)
+        .filter(|tail| !tail.is_empty())
+        .ok_or(StrError::OutOfBounds {
+            offset,
+            length: buf.len(),
+        })?;
+    let end = tail
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or(StrError::Unterminated { offset })?;
+    std::str::from_utf8(&tail[..end]).map_err(|_| StrError::InvalidUtf8 { offset })
+}
+
+fn c_str_lossy_at(buf: &[u8], offset: usize) -> Result<Cow<'_, str>, StrError> {
+    match c_str_at(buf, offset) {
+        Ok(value) => Ok(Cow::Borrowed(value)),
+        Err(StrError::InvalidUtf8 { .. }) => {
+            let tail = &buf[offset..];
+            let end = tail.iter().position(|b| *b == 0).unwrap_or(tail.len());
+            let value = String::from_utf8_lossy(&tail[..end]);
+            warn!(
+                "string at offset {} is not valid UTF-8, using {:?}",
+                offset, value
+            );
+            Ok(value)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+#[derive(Clone)]
+pub struct StringTable {
+    bytes: Vec<u8>,
+}
+
+impl StringTable {
+    pub fn from_bytes(bytes: &[u8]) -> StringTable {
+        StringTable {
+            bytes: bytes.to_vec(),
+        }
+    }
+
+    pub fn load<T: Read + Seek>(
+        section_header: &Elf64SectionHeader,
+        reader: &mut T,
+    ) -> Result<StringTable, DrowError> {
+        let bytes = read_segment(reader, section_header.sh_offset, section_header.sh_size)?;
+        Ok(StringTable { bytes })
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn get(&self, offset: u32) -> Result<&str, StrError> {
+        if offset == 0 && self.bytes.is_empty() {
+            return Ok("");
+        }
+        c_str_at(&self.bytes, offset as usize)
+    }
+
+    pub fn get_lossy(&self, offset: u32) -> Result<Cow<'_, str>, StrError> {
+        if offset == 0 && self.bytes.is_empty() {
+            return Ok(Cow::Borrowed(""));
+        }
+        c_str_lossy_at(&self.bytes, offset as usize)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u32, Cow<'_, str>)> {
+        let mut offset = 0;
+        self.bytes.split_inclusive(|b| *b == 0).map(move |part| {
+            let start = offset;
+            offset += part.len() as u32;
+            let value = part.strip_suffix(&[0]).unwrap_or(part);
+            (start, String::from_utf8_lossy(value))
+        })
+    }
 }
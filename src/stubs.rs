@@ -0,0 +1,145 @@
+//! Stand-ins for the libraries given to `--stub`, which are never searched for. The symbols the
+//! objects requiring them import and nothing loaded defines are bound to stubs: each function
+//! to a trap reporting its name when called, each variable to a zero filled page.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+
+use crate::error::DrowError;
+use crate::syscall;
+use crate::{
+    Elf64ResolvedSymbolTableEntry, SHN_ABSOLUTE, SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_FUNCTION,
+    SYMBOL_TYPE_OBJECT,
+};
+
+/// Bytes of each trap: `mov rdi, message; mov rax, stub_trap; jmp rax`, padded with int3.
+const TRAP_SIZE: usize = 32;
+const PAGE_SIZE: usize = 4096;
+
+/// Exit status of a program calling a stubbed function.
+pub const STUB_EXIT_STATUS: i32 = 127;
+
+/// Reports the stubbed function `message` names and ends the process, as nothing sensible can
+/// be returned to the caller.
+extern "C" fn stub_trap(message: *const c_char) -> ! {
+    let message = unsafe { CStr::from_ptr(message) }.to_bytes();
+    unsafe {
+        libc::write(2, message.as_ptr() as *const libc::c_void, message.len());
+        syscall::exit_group(STUB_EXIT_STATUS)
+    }
+}
+
+fn trap(message: &CStr) -> [u8; TRAP_SIZE] {
+    let mut code = [0xCC; TRAP_SIZE];
+    code[0..2].copy_from_slice(&[0x48, 0xBF]);
+    code[2..10].copy_from_slice(&(message.as_ptr() as u64).to_le_bytes());
+    code[10..12].copy_from_slice(&[0x48, 0xB8]);
+    code[12..20].copy_from_slice(&(stub_trap as *const () as u64).to_le_bytes());
+    code[20..22].copy_from_slice(&[0xFF, 0xE0]);
+    code
+}
+
+/// A symbol to stub: its name as the importing object refers to it, whether it is a variable,
+/// and the stubbed libraries it may come from.
+pub struct StubbedImport {
+    pub name: String,
+    pub variable: bool,
+    pub libraries: Vec<String>,
+}
+
+/// The traps and the zero page of one load, mapped until the loader is dropped.
+pub struct StubObject {
+    address: u64,
+    length: usize,
+    /// What the traps print, which they point to.
+    messages: Vec<CString>,
+    symbols: Vec<Elf64ResolvedSymbolTableEntry>,
+}
+
+impl StubObject {
+    pub fn new(imports: &[StubbedImport]) -> Result<StubObject, DrowError> {
+        let functions = imports.iter().filter(|import| !import.variable).count();
+        let code_length = (functions * TRAP_SIZE).div_ceil(PAGE_SIZE).max(1) * PAGE_SIZE;
+        let length = code_length + PAGE_SIZE;
+        let address = syscall::mmap_checked(
+            std::ptr::null::<libc::c_void>(),
+            length,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+        .map_err(|errno| DrowError::MapFailed {
+            address: 0,
+            length: length as u64,
+            source: errno.into(),
+        })? as u64;
+        let mut stubs = StubObject {
+            address,
+            length,
+            messages: Vec::new(),
+            symbols: Vec::new(),
+        };
+        let zero_page = address + code_length as u64;
+        for import in imports.iter() {
+            let (value, symbol_type, size) = if import.variable {
+                (zero_page, SYMBOL_TYPE_OBJECT, PAGE_SIZE as u64)
+            } else {
+                let message = CString::new(format!(
+                    "drow: {} was called, but {} is stubbed\n",
+                    import.name,
+                    import.libraries.join(" or ")
+                ))
+                .unwrap_or_default();
+                let value = address + (stubs.messages.len() * TRAP_SIZE) as u64;
+                let code = trap(&message);
+                unsafe {
+                    std::ptr::copy_nonoverlapping(code.as_ptr(), value as *mut u8, TRAP_SIZE);
+                }
+                stubs.messages.push(message);
+                (value, SYMBOL_TYPE_FUNCTION, TRAP_SIZE as u64)
+            };
+            debug!("Stubbed {} at {:#X}", import.name, value);
+            stubs.symbols.push(Elf64ResolvedSymbolTableEntry {
+                symbol_name: import.name.clone(),
+                binding: SYMBOL_BINDING_GLOBAL,
+                symbol_type,
+                section_index: SHN_ABSOLUTE,
+                value,
+                size,
+                version: None,
+            });
+        }
+        let protection = libc::PROT_READ | libc::PROT_EXEC;
+        if unsafe { libc::mprotect(address as *mut libc::c_void, code_length, protection) } < 0 {
+            return Err(DrowError::Syscall {
+                call: String::from("mprotect"),
+                source: std::io::Error::last_os_error(),
+            });
+        }
+        Ok(stubs)
+    }
+
+    /// Entries for the global symbol table, pointing at the traps and the zero page.
+    /// Bytes mapped for the traps and the variables.
+    pub fn length(&self) -> usize {
+        self.length
+    }
+
+    pub fn symbols(&self) -> &[Elf64ResolvedSymbolTableEntry] {
+        &self.symbols
+    }
+}
+
+impl Drop for StubObject {
+    fn drop(&mut self) {
+        if let Err(errno) =
+            syscall::munmap_checked(self.address as *const libc::c_void, self.length)
+        {
+            warn!(
+                "munmap({:#X}, {}) of the stubs failed: {}",
+                self.address, self.length, errno
+            );
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::io::{Read, Seek};
+
+use crate::debuginfo;
+use crate::libc_flavor::LibcFlavor;
+use crate::notes::read_notes;
+use crate::{Elf64Metadata, ObjectKind, ELF_TYPE_EXECUTABLE, PROGRAM_HEADER_TYPE_NOTE};
+
+const NOTE_GNU_BUILD_ID: u32 = 3;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SummaryFormat {
+    Plain,
+    Csv,
+    Tsv,
+}
+
+impl SummaryFormat {
+    pub fn parse(value: &str) -> Result<SummaryFormat, String> {
+        match value {
+            "plain" => Ok(SummaryFormat::Plain),
+            "csv" => Ok(SummaryFormat::Csv),
+            "tsv" => Ok(SummaryFormat::Tsv),
+            other => Err(format!(
+                "Unknown summary format: {}, expected plain, csv or tsv",
+                other
+            )),
+        }
+    }
+
+    fn separator(&self) -> &'static str {
+        match self {
+            SummaryFormat::Plain => " ",
+            SummaryFormat::Csv => ",",
+            SummaryFormat::Tsv => "\t",
+        }
+    }
+}
+
+pub struct Summary {
+    pub path: String,
+    pub file_type: String,
+    pub machine: String,
+    pub pie: bool,
+    pub kind: ObjectKind,
+    pub interpreter: Option<String>,
+    pub libc: LibcFlavor,
+    pub entry: u64,
+    pub needed: usize,
+    pub build_id: Option<String>,
+    /// Path of the detached debug file.
+    pub debuginfo: Option<String>,
+}
+
+fn file_type_name(e_type: u16) -> String {
+    match e_type {
+        0 => String::from("NONE"),
+        1 => String::from("REL"),
+        2 => String::from("EXEC"),
+        3 => String::from("DYN"),
+        4 => String::from("CORE"),
+        other => format!("{:#X}", other),
+    }
+}
+
+fn machine_name(e_machine: u16) -> String {
+    match e_machine {
+        0x03 => String::from("i386"),
+        0x3E => String::from("x86_64"),
+        0xB7 => String::from("aarch64"),
+        other => format!("{:#X}", other),
+    }
+}
+
+pub fn read_build_id<T: Read + Seek>(
+    elf_metadata: &Elf64Metadata,
+    reader: &mut T,
+) -> Result<Option<String>, String> {
+    let notes = elf_metadata
+        .program_headers
+        .iter()
+        .filter(|h| h.p_type == PROGRAM_HEADER_TYPE_NOTE);
+    for header in notes {
+        for note in read_notes(reader, header)? {
+            if note.note_type == NOTE_GNU_BUILD_ID && note.name == "GNU" {
+                let build_id: Vec<String> = note
+                    .description
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect();
+                return Ok(Some(build_id.join("")));
+            }
+        }
+    }
+    Ok(None)
+}
+
+impl Summary {
+    /// `debuginfo_directories` are searched for the debug file instead of /usr/lib/debug when not
+    /// empty.
+    pub fn load<T: Read + Seek>(
+        elf_metadata: &Elf64Metadata,
+        reader: &mut T,
+        debuginfo_directories: &[String],
+    ) -> Result<Summary, String> {
+        let header = &elf_metadata.elf_header;
+        let interpreter = elf_metadata.interpreter.clone();
+        let kind = elf_metadata.object_kind();
+        let pie = kind == ObjectKind::PositionIndependentExecutable;
+        Ok(Summary {
+            path: elf_metadata.file_path.clone(),
+            file_type: if pie {
+                String::from("PIE")
+            } else if header.e_type == ELF_TYPE_EXECUTABLE {
+                String::from("EXEC")
+            } else {
+                file_type_name(header.e_type)
+            },
+            machine: machine_name(header.e_machine),
+            pie,
+            kind,
+            libc: LibcFlavor::detect(interpreter.as_deref(), std::iter::once(elf_metadata)),
+            interpreter,
+            entry: header.e_entry,
+            needed: elf_metadata.dynamic.required_libraries.len(),
+            build_id: read_build_id(elf_metadata, reader)?,
+            debuginfo: debuginfo::find(elf_metadata, reader, debuginfo_directories)?,
+        })
+    }
+
+    pub fn header(format: SummaryFormat) -> Option<String> {
+        let columns = [
+            "path",
+            "type",
+            "machine",
+            "pie",
+            "kind",
+            "interpreter",
+            "libc",
+            "entry",
+            "needed",
+            "build_id",
+            "debuginfo",
+        ];
+        match format {
+            SummaryFormat::Plain => None,
+            _ => Some(columns.join(format.separator())),
+        }
+    }
+
+    pub fn format(&self, format: SummaryFormat) -> String {
+        let fields = [
+            self.path.clone(),
+            self.file_type.clone(),
+            self.machine.clone(),
+            String::from(if self.pie { "pie" } else { "no-pie" }),
+            self.kind.to_string(),
+            self.interpreter
+                .clone()
+                .unwrap_or_else(|| String::from("-")),
+            self.libc.to_string(),
+            format!("{:#x}", self.entry),
+            self.needed.to_string(),
+            self.build_id.clone().unwrap_or_else(|| String::from("-")),
+            self.debuginfo.clone().unwrap_or_else(|| String::from("-")),
+        ];
+        fields.join(format.separator())
+    }
+
+    pub fn format_error(path: &str, message: &str, format: SummaryFormat) -> String {
+        let separator = format.separator();
+        let message = message.replace(separator, "_");
+        format!("{}{}ERROR{}{}", path, separator, separator, message)
+    }
+}
@@ -0,0 +1,86 @@
+//! Names of dynamic symbols with their versions. The version of a symbol comes from
+//! `.gnu.version` when the file has one; otherwise it is taken from the name itself, as
+//! `name@VERSION` for a hidden version and `name@@VERSION` for the default one.
+
+use std::fmt::{Display, Formatter};
+
+use crate::versions::SymbolVersion;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SymbolName {
+    pub name: String,
+    pub version: Option<String>,
+    /// The version is the one unversioned references bind to. Always set for unversioned names.
+    pub default: bool,
+}
+
+/// Splits `raw` into name, version and default-ness. Versions never contain '@', so the last
+/// one separates the version, and a name that would be left empty or without a version keeps
+/// its '@' characters.
+fn split(raw: &str) -> Option<(&str, &str, bool)> {
+    let at = raw.rfind('@')?;
+    let version = &raw[at + 1..];
+    let (name, default) = match raw[..at].strip_suffix('@') {
+        Some(name) => (name, true),
+        None => (&raw[..at], false),
+    };
+    if name.is_empty() || version.is_empty() {
+        None
+    } else {
+        Some((name, version, default))
+    }
+}
+
+/// The name of `raw` without its version.
+pub fn base_name(raw: &str) -> &str {
+    split(raw).map(|(name, _, _)| name).unwrap_or(raw)
+}
+
+impl SymbolName {
+    pub fn unversioned(name: &str) -> SymbolName {
+        SymbolName {
+            name: name.to_string(),
+            version: None,
+            default: true,
+        }
+    }
+
+    /// Parses the version out of `raw`, for symbols of files without version tables.
+    pub fn parse(raw: &str) -> SymbolName {
+        match split(raw) {
+            Some((name, version, default)) => SymbolName {
+                name: name.to_string(),
+                version: Some(version.to_string()),
+                default,
+            },
+            None => SymbolName::unversioned(raw),
+        }
+    }
+
+    /// The name of entry `index` of a dynamic symbol table with the version `versions`, as read
+    /// from `.gnu.version`, gives it, or parsed from `raw` when the file has no version table.
+    /// A hidden version is not the default one.
+    pub fn from_table(raw: &str, versions: &[Option<SymbolVersion>], index: usize) -> SymbolName {
+        if versions.is_empty() {
+            return SymbolName::parse(raw);
+        }
+        match versions.get(index).and_then(Option::as_ref) {
+            Some(version) => SymbolName {
+                name: raw.to_string(),
+                version: Some(version.name.clone()),
+                default: !version.hidden,
+            },
+            None => SymbolName::unversioned(raw),
+        }
+    }
+}
+
+impl Display for SymbolName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (&self.version, self.default) {
+            (Some(version), true) => write!(f, "{}@@{}", self.name, version),
+            (Some(version), false) => write!(f, "{}@{}", self.name, version),
+            (None, _) => f.write_str(&self.name),
+        }
+    }
+}
@@ -1,6 +1,10 @@
-use std::ffi::CString;
-use std::{mem, ptr};
+use std::ffi::{CStr, CString};
+use std::fmt::{Display, Formatter};
+use std::{io, mem};
 
+use crate::error::DrowError;
+
+#[cfg(feature = "libc-syscalls")]
 extern "C" {
     pub fn mmap(
         address: *const libc::c_void,
@@ -13,41 +17,454 @@ extern "C" {
 
     pub fn munmap(address: *const libc::c_void, length: libc::size_t) -> i32;
 
+    pub fn madvise(address: *const libc::c_void, length: libc::size_t, advice: i32) -> i32;
+
     pub fn open(pathname: *const libc::c_char, flags: i32) -> i32;
 
     pub fn close(file_descriptor: i32) -> i32;
 
     pub fn clone(
-        entry: *const libc::c_void,
+        entry: ChildEntry,
         stack: *const libc::c_void,
         flags: i32,
-        arg: *const libc::c_void,
+        arg: *mut libc::c_void,
         parent_thread_identifier: *const libc::pid_t,
         thread_local_storage: *const libc::c_void,
         child_thread_identifier: *const libc::c_void,
     ) -> i32;
 
-    pub fn wait(status: *const i32);
+    pub fn waitid(
+        id_type: libc::idtype_t,
+        id: libc::id_t,
+        info: *mut libc::siginfo_t,
+        options: i32,
+    ) -> i32;
+
+    pub fn fstat(file_descriptor: i32, result: *mut libc::stat) -> i32;
+
+    pub fn pread64(
+        file_descriptor: i32,
+        buffer: *mut libc::c_void,
+        count: libc::size_t,
+        offset: libc::off_t,
+    ) -> libc::ssize_t;
+
+    pub fn read(
+        file_descriptor: i32,
+        buffer: *mut libc::c_void,
+        count: libc::size_t,
+    ) -> libc::ssize_t;
+}
+
+#[cfg(feature = "libc-syscalls")]
+pub unsafe fn exit_group(status: i32) -> ! {
+    libc::_exit(status)
+}
+
+#[cfg(feature = "libc-syscalls")]
+pub unsafe fn memfd_create(name: *const libc::c_char, flags: u32) -> i32 {
+    libc::memfd_create(name, flags)
+}
+
+#[cfg(feature = "libc-syscalls")]
+pub unsafe fn fcntl(file_descriptor: i32, command: i32, argument: i64) -> i32 {
+    libc::fcntl(file_descriptor, command, argument)
+}
+
+#[cfg(feature = "libc-syscalls")]
+pub unsafe fn sigaction(signal: i32, handler: libc::sighandler_t, flags: i32) -> i32 {
+    let mut action: libc::sigaction = mem::zeroed();
+    action.sa_sigaction = handler;
+    action.sa_flags = flags;
+    libc::sigemptyset(&mut action.sa_mask);
+    libc::sigaction(signal, &action, std::ptr::null_mut())
+}
 
-    pub fn fstat(file_descriptor: i32, result: *const libc::stat) -> i32;
+#[cfg(feature = "libc-syscalls")]
+pub unsafe fn sigaltstack(stack: *const libc::stack_t) -> i32 {
+    libc::sigaltstack(stack, std::ptr::null_mut())
 }
 
-pub fn get_file_size(descriptor: i32) -> i64 {
-    let mut buffer: Vec<u8> = Vec::new();
-    buffer.resize(mem::size_of::<libc::stat>(), 0);
-    unsafe {
-        fstat(descriptor, buffer.as_ptr() as *const libc::stat);
+#[cfg(feature = "libc-syscalls")]
+pub unsafe fn arch_prctl(code: i32, address: u64) -> i32 {
+    libc::syscall(libc::SYS_arch_prctl, code, address) as i32
+}
+
+#[cfg(feature = "libc-syscalls")]
+pub unsafe fn getrlimit(resource: i32, limit: *mut libc::rlimit) -> i32 {
+    libc::getrlimit(resource as _, limit)
+}
+
+/// Blocks or unblocks `signal` alone, as `how` says.
+#[cfg(feature = "libc-syscalls")]
+pub unsafe fn mask_signal(how: i32, signal: i32) -> i32 {
+    let mut set: libc::sigset_t = mem::zeroed();
+    libc::sigemptyset(&mut set);
+    libc::sigaddset(&mut set, signal);
+    libc::sigprocmask(how, &set, std::ptr::null_mut())
+}
+
+/// Waits for `signal`, which must be blocked, and takes it.
+#[cfg(feature = "libc-syscalls")]
+pub unsafe fn wait_signal(signal: i32) -> i32 {
+    let mut set: libc::sigset_t = mem::zeroed();
+    libc::sigemptyset(&mut set);
+    libc::sigaddset(&mut set, signal);
+    libc::sigwaitinfo(&set, std::ptr::null_mut())
+}
+
+/// Signals the calling process rather than the thread libc believes is running, which differs in
+/// a child sharing drow's memory.
+#[cfg(feature = "libc-syscalls")]
+pub unsafe fn kill_self(signal: i32) -> i32 {
+    libc::kill(libc::getpid(), signal)
+}
+
+/// glibc has no clone3 wrapper, so the feature build maps the arguments onto clone(). Flags above
+/// the low 32 bits can't be expressed that way and are rejected with EINVAL.
+#[cfg(feature = "libc-syscalls")]
+pub unsafe fn clone3(args: &CloneArgs, entry: ChildEntry, arg: *mut libc::c_void) -> i32 {
+    if args.flags >> 32 != 0 {
+        *libc::__errno_location() = libc::EINVAL;
+        return -1;
     }
-    let file_info: libc::stat = unsafe { ptr::read(buffer.as_ptr() as *const _) };
-    file_info.st_size
+    clone(
+        entry,
+        (args.stack + args.stack_size) as *const libc::c_void,
+        (args.flags | args.exit_signal) as i32,
+        arg,
+        args.pidfd as *const libc::pid_t,
+        args.tls as *const libc::c_void,
+        args.child_tid as *const libc::c_void,
+    )
+}
+
+#[cfg(not(feature = "libc-syscalls"))]
+pub use crate::raw_syscall::*;
+
+/// The function a cloned child starts in, called with the C ABI by both glibc's clone() and the
+/// raw clone3 path. Its result is the exit status of the child.
+pub type ChildEntry = extern "C" fn(arg: *mut libc::c_void) -> libc::c_int;
+
+/// Mirrors the kernel's `struct clone_args`.
+#[repr(C)]
+#[derive(Clone, Copy, Default, Debug)]
+pub struct CloneArgs {
+    flags: u64,
+    pidfd: u64,
+    child_tid: u64,
+    parent_tid: u64,
+    exit_signal: u64,
+    stack: u64,
+    stack_size: u64,
+    tls: u64,
+    set_tid: u64,
+    set_tid_size: u64,
+    cgroup: u64,
 }
 
-pub fn open_file(file_path: &String) -> Result<i32, String> {
-    let file_path_c_string = CString::new(file_path.clone()).unwrap();
-    let file_descriptor = unsafe { open(file_path_c_string.as_ptr(), libc::O_RDONLY) };
+impl CloneArgs {
+    pub fn new() -> CloneArgs {
+        CloneArgs::default()
+    }
+
+    pub fn flags(mut self, flags: u64) -> CloneArgs {
+        self.flags |= flags;
+        self
+    }
+
+    pub fn pidfd(mut self, pidfd: *mut i32) -> CloneArgs {
+        self.flags |= libc::CLONE_PIDFD as u64;
+        self.pidfd = pidfd as u64;
+        self
+    }
+
+    pub fn exit_signal(mut self, signal: i32) -> CloneArgs {
+        self.exit_signal = signal as u64;
+        self
+    }
+
+    pub fn stack(mut self, address: *const libc::c_void, size: libc::size_t) -> CloneArgs {
+        self.stack = address as u64;
+        self.stack_size = size as u64;
+        self
+    }
+}
+
+pub fn get_file_size(descriptor: i32) -> Result<i64, Errno> {
+    fstat_checked(descriptor).map(|file_info| file_info.st_size)
+}
+
+pub fn open_file(file_path: &str) -> Result<i32, DrowError> {
+    open_checked(file_path, libc::O_RDONLY).map_err(|errno| DrowError::Io {
+        path: file_path.to_string(),
+        source: errno.into(),
+    })
+}
+
+/// The arch_prctl code setting the FS base, which libc does not export.
+const ARCH_SET_FS: i32 = 0x1002;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Errno(pub i32);
+
+impl Errno {
+    pub fn last() -> Errno {
+        Errno(unsafe { *libc::__errno_location() })
+    }
+}
+
+impl From<Errno> for io::Error {
+    fn from(errno: Errno) -> io::Error {
+        io::Error::from_raw_os_error(errno.0)
+    }
+}
+
+impl Display for Errno {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let message = unsafe { CStr::from_ptr(libc::strerror(self.0)) };
+        write!(f, "{} (errno {})", message.to_string_lossy(), self.0)
+    }
+}
+
+#[cfg(feature = "testutil")]
+thread_local! {
+    /// The errno the executable mappings of files fail with on this thread, as on a noexec
+    /// mount. See `testutil::fail_executable_file_mappings`.
+    static EXECUTABLE_FILE_MAPPING_ERROR: std::cell::Cell<Option<i32>> =
+        const { std::cell::Cell::new(None) };
+}
+
+#[cfg(feature = "testutil")]
+pub fn set_executable_file_mapping_error(errno: Option<i32>) {
+    EXECUTABLE_FILE_MAPPING_ERROR.with(|error| error.set(errno));
+}
+
+/// The injected failure of an executable mapping of `file_descriptor`. Memfds, which accept
+/// seals, are not on a mount and still map.
+#[cfg(feature = "testutil")]
+fn injected_mapping_error(protection: i32, file_descriptor: i32) -> Option<Errno> {
+    let errno = EXECUTABLE_FILE_MAPPING_ERROR.with(|error| error.get())?;
+    if protection & libc::PROT_EXEC == 0 || file_descriptor < 0 {
+        return None;
+    }
+    let memfd = unsafe { fcntl(file_descriptor, libc::F_GET_SEALS, 0) } >= 0;
+    if memfd {
+        None
+    } else {
+        Some(Errno(errno))
+    }
+}
+
+pub fn mmap_checked(
+    address: *const libc::c_void,
+    length: libc::size_t,
+    protection: i32,
+    flags: i32,
+    file_descriptor: i32,
+    offset: libc::off_t,
+) -> Result<*const libc::c_void, Errno> {
+    #[cfg(feature = "testutil")]
+    if let Some(errno) = injected_mapping_error(protection, file_descriptor) {
+        return Err(errno);
+    }
+    let pointer = unsafe { mmap(address, length, protection, flags, file_descriptor, offset) };
+    if pointer == libc::MAP_FAILED {
+        Err(Errno::last())
+    } else {
+        Ok(pointer)
+    }
+}
+
+pub fn munmap_checked(address: *const libc::c_void, length: libc::size_t) -> Result<(), Errno> {
+    if unsafe { munmap(address, length) } < 0 {
+        Err(Errno::last())
+    } else {
+        Ok(())
+    }
+}
+
+pub fn madvise_checked(
+    address: *const libc::c_void,
+    length: libc::size_t,
+    advice: i32,
+) -> Result<(), Errno> {
+    if unsafe { madvise(address, length, advice) } < 0 {
+        Err(Errno::last())
+    } else {
+        Ok(())
+    }
+}
+
+pub fn open_checked(file_path: &str, flags: i32) -> Result<i32, Errno> {
+    let c_path = CString::new(file_path).map_err(|_| Errno(libc::EINVAL))?;
+    let file_descriptor = unsafe { open(c_path.as_ptr(), flags) };
+    if file_descriptor < 0 {
+        Err(Errno::last())
+    } else {
+        Ok(file_descriptor)
+    }
+}
+
+pub fn memfd_create_checked(name: &str, flags: u32) -> Result<i32, Errno> {
+    let c_name = CString::new(name).map_err(|_| Errno(libc::EINVAL))?;
+    let file_descriptor = unsafe { memfd_create(c_name.as_ptr(), flags) };
     if file_descriptor < 0 {
-        Result::Err(format!("Unable to open file {}", file_path))
+        Err(Errno::last())
+    } else {
+        Ok(file_descriptor)
+    }
+}
+
+pub fn add_seals_checked(file_descriptor: i32, seals: i32) -> Result<(), Errno> {
+    if unsafe { fcntl(file_descriptor, libc::F_ADD_SEALS, seals as i64) } < 0 {
+        Err(Errno::last())
+    } else {
+        Ok(())
+    }
+}
+
+pub fn close_checked(file_descriptor: i32) -> Result<(), Errno> {
+    if unsafe { close(file_descriptor) } < 0 {
+        Err(Errno::last())
+    } else {
+        Ok(())
+    }
+}
+
+pub fn pread(file_descriptor: i32, buffer: &mut [u8], offset: u64) -> Result<usize, Errno> {
+    let read = unsafe {
+        pread64(
+            file_descriptor,
+            buffer.as_mut_ptr() as *mut libc::c_void,
+            buffer.len(),
+            offset as libc::off_t,
+        )
+    };
+    if read < 0 {
+        Err(Errno::last())
+    } else {
+        Ok(read as usize)
+    }
+}
+
+pub fn read_checked(file_descriptor: i32, buffer: &mut [u8]) -> Result<usize, Errno> {
+    let read = unsafe {
+        read(
+            file_descriptor,
+            buffer.as_mut_ptr() as *mut libc::c_void,
+            buffer.len(),
+        )
+    };
+    if read < 0 {
+        Err(Errno::last())
+    } else {
+        Ok(read as usize)
+    }
+}
+
+pub fn fstat_checked(file_descriptor: i32) -> Result<libc::stat, Errno> {
+    let mut file_info: libc::stat = unsafe { mem::zeroed() };
+    if unsafe { fstat(file_descriptor, &mut file_info) } < 0 {
+        Err(Errno::last())
+    } else {
+        Ok(file_info)
+    }
+}
+
+pub fn getrlimit_checked(resource: i32) -> Result<libc::rlimit, Errno> {
+    let mut limit: libc::rlimit = unsafe { mem::zeroed() };
+    if unsafe { getrlimit(resource, &mut limit) } < 0 {
+        Err(Errno::last())
+    } else {
+        Ok(limit)
+    }
+}
+
+/// # Safety
+/// `handler` must be safe to run in a signal handler, with the signature `flags` imply.
+pub unsafe fn sigaction_checked(
+    signal: i32,
+    handler: libc::sighandler_t,
+    flags: i32,
+) -> Result<(), Errno> {
+    if sigaction(signal, handler, flags) < 0 {
+        Err(Errno::last())
+    } else {
+        Ok(())
+    }
+}
+
+/// Points the FS base, the x86-64 thread pointer, at `address`.
+///
+/// # Safety
+/// Thread-local variables of drow and libc are reached through the thread pointer, so none may be
+/// used until it points at their TLS area again. That includes errno, so a failure is only
+/// reported correctly while the thread pointer is drow's.
+pub unsafe fn set_thread_pointer_checked(address: u64) -> Result<(), Errno> {
+    if arch_prctl(ARCH_SET_FS, address) < 0 {
+        Err(Errno::last())
+    } else {
+        Ok(())
+    }
+}
+
+/// `how` is SIG_BLOCK or SIG_UNBLOCK.
+pub fn mask_signal_checked(how: i32, signal: i32) -> Result<(), Errno> {
+    if unsafe { mask_signal(how, signal) } < 0 {
+        Err(Errno::last())
+    } else {
+        Ok(())
+    }
+}
+
+pub fn wait_signal_checked(signal: i32) -> Result<(), Errno> {
+    if unsafe { wait_signal(signal) } < 0 {
+        Err(Errno::last())
+    } else {
+        Ok(())
+    }
+}
+
+/// Runs signal handlers flagged SA_ONSTACK on `size` bytes at `address`.
+pub fn sigaltstack_checked(address: *mut libc::c_void, size: usize) -> Result<(), Errno> {
+    let stack = libc::stack_t {
+        ss_sp: address,
+        ss_flags: 0,
+        ss_size: size,
+    };
+    if unsafe { sigaltstack(&stack) } < 0 {
+        Err(Errno::last())
+    } else {
+        Ok(())
+    }
+}
+
+/// # Safety
+/// `entry` must be a function taking `arg`, and the stack in `args` must stay mapped for as long
+/// as the child runs.
+pub unsafe fn clone3_checked(
+    args: &CloneArgs,
+    entry: ChildEntry,
+    arg: *mut libc::c_void,
+) -> Result<i32, Errno> {
+    let pid = clone3(args, entry, arg);
+    if pid < 0 {
+        Err(Errno::last())
+    } else {
+        Ok(pid)
+    }
+}
+
+pub fn waitid_checked(
+    id_type: libc::idtype_t,
+    id: libc::id_t,
+    options: i32,
+) -> Result<libc::siginfo_t, Errno> {
+    let mut info: libc::siginfo_t = unsafe { mem::zeroed() };
+    if unsafe { waitid(id_type, id, &mut info, options) } < 0 {
+        Err(Errno::last())
     } else {
-        Result::Ok(file_descriptor)
+        Ok(info)
     }
 }
@@ -0,0 +1,61 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+const MAX_SYMLINKS: usize = 40;
+
+/// A directory standing in for `/` while resolving libraries, like the root filesystem of a
+/// target image mounted on the host.
+#[derive(Clone, Debug)]
+pub struct Sysroot {
+    root: PathBuf,
+}
+
+impl Sysroot {
+    pub fn new(root: &str) -> Sysroot {
+        Sysroot {
+            root: PathBuf::from(root),
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn push_components(pending: &mut Vec<OsString>, path: &Path) {
+        for component in path.components().rev() {
+            match component {
+                Component::Normal(name) => pending.push(name.to_os_string()),
+                Component::ParentDir => pending.push(OsString::from("..")),
+                _ => {}
+            }
+        }
+    }
+
+    /// Maps a path of the target filesystem to the host. Symbolic links are followed inside the
+    /// sysroot, so absolute link targets do not escape it.
+    pub fn resolve(&self, path: &str) -> String {
+        let mut pending = Vec::new();
+        Sysroot::push_components(&mut pending, Path::new(path));
+        let mut resolved = PathBuf::new();
+        let mut links = 0;
+        while let Some(component) = pending.pop() {
+            if component == ".." {
+                resolved.pop();
+                continue;
+            }
+            let candidate = resolved.join(&component);
+            match fs::read_link(self.root.join(&candidate)) {
+                Ok(target) if links < MAX_SYMLINKS => {
+                    links += 1;
+                    if target.is_absolute() {
+                        resolved = PathBuf::new();
+                    }
+                    Sysroot::push_components(&mut pending, &target);
+                }
+                _ => resolved = candidate,
+            }
+        }
+        self.root.join(resolved).to_string_lossy().into_owned()
+    }
+}
@@ -0,0 +1,135 @@
+//! `.hash`: the SysV hash table, buckets and chains over the whole dynamic symbol table. Older
+//! and non-GNU toolchains emit it instead of `.gnu.hash`. As it has a chain per symbol, its size
+//! is also the number of dynamic symbols, which files without section headers do not tell
+//! otherwise.
+
+use std::io::{Read, Seek, SeekFrom};
+use std::mem::size_of;
+
+use crate::error::DrowError;
+use crate::string_tables::file_offset;
+use crate::{
+    read_entries, Elf64Dynamic, Elf64ProgramHeader, Elf64ResolvedSymbolTableEntry,
+    Elf64SectionHeader,
+};
+
+pub const ELF64_SECTION_HEADER_HASH: u32 = 5;
+
+/// Words of the header: bucket count and chain count.
+const HEADER_WORDS: u64 = 2;
+
+/// The hash of `name` the table is built with, the `elf_hash` of the System V ABI.
+pub fn sysv_hash(name: &str) -> u32 {
+    name.bytes().fold(0u32, |hash, byte| {
+        let hash = (hash << 4).wrapping_add(byte as u32);
+        let high = hash & 0xf000_0000;
+        (hash ^ (high >> 24)) & !high
+    })
+}
+
+#[derive(Clone)]
+pub struct Elf64SysvHash {
+    /// Index of the first symbol of each bucket, zero for an empty one.
+    pub buckets: Vec<u32>,
+    /// Index of the symbol after each one in its bucket, zero for the last one.
+    pub chains: Vec<u32>,
+}
+
+impl Elf64SysvHash {
+    /// The table of the file, found by its section or else by DT_HASH, none when it has neither.
+    pub fn load<T: Read + Seek>(
+        section_headers: &[Elf64SectionHeader],
+        program_headers: &[Elf64ProgramHeader],
+        dynamic: &Elf64Dynamic,
+        reader: &mut T,
+    ) -> Result<Option<Elf64SysvHash>, DrowError> {
+        let offset = section_headers
+            .iter()
+            .find(|header| header.sh_type == ELF64_SECTION_HEADER_HASH)
+            .map(|header| header.sh_offset)
+            .or_else(|| {
+                dynamic
+                    .addresses
+                    .iter()
+                    .find(|(tag, _)| *tag == "DT_HASH")
+                    .and_then(|(_, address)| file_offset(program_headers, *address))
+            });
+        let offset = match offset {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+        let header: Vec<u32> = read_entries(reader, offset, HEADER_WORDS)?;
+        let (bucket_count, chain_count) = (header[0], header[1]);
+        let malformed = |what: String| DrowError::Malformed {
+            what,
+            offset: Some(offset),
+        };
+        if bucket_count == 0 {
+            return Err(malformed(String::from("SysV hash table has no buckets")));
+        }
+        let past_end = || {
+            malformed(format!(
+                "SysV hash table of {} buckets and {} chains extends past the end of the file",
+                bucket_count, chain_count
+            ))
+        };
+        let words = HEADER_WORDS + bucket_count as u64 + chain_count as u64;
+        let end = offset
+            .checked_add(words * size_of::<u32>() as u64)
+            .ok_or_else(past_end)?;
+        let file_size = reader
+            .seek(SeekFrom::End(0))
+            .map_err(|source| DrowError::Read {
+                offset: 0,
+                length: 0,
+                source,
+            })?;
+        if end > file_size {
+            return Err(past_end());
+        }
+        let buckets_offset = offset + HEADER_WORDS * size_of::<u32>() as u64;
+        let chains_offset = buckets_offset + bucket_count as u64 * size_of::<u32>() as u64;
+        Ok(Some(Elf64SysvHash {
+            buckets: read_entries(reader, buckets_offset, bucket_count as u64)?,
+            chains: read_entries(reader, chains_offset, chain_count as u64)?,
+        }))
+    }
+
+    /// Number of dynamic symbols, one per chain.
+    pub fn symbol_count(&self) -> usize {
+        self.chains.len()
+    }
+
+    /// The first symbol of `symbols`, the dynamic symbol table, named `name` that `accept`
+    /// takes, with its index. A chain looping back on itself ends after visiting every symbol.
+    pub fn lookup_with<'a>(
+        &self,
+        name: &str,
+        symbols: &'a [Elf64ResolvedSymbolTableEntry],
+        mut accept: impl FnMut(usize, &Elf64ResolvedSymbolTableEntry) -> bool,
+    ) -> Option<(usize, &'a Elf64ResolvedSymbolTableEntry)> {
+        let hash = sysv_hash(name);
+        let mut index = self.buckets[hash as usize % self.buckets.len()] as usize;
+        for _ in 0..self.chains.len() {
+            if index == 0 {
+                return None;
+            }
+            let symbol = symbols.get(index)?;
+            if symbol.symbol_name == name && accept(index, symbol) {
+                return Some((index, symbol));
+            }
+            index = *self.chains.get(index)? as usize;
+        }
+        None
+    }
+
+    /// The first symbol of `symbols`, the dynamic symbol table, named `name`.
+    pub fn lookup<'a>(
+        &self,
+        name: &str,
+        symbols: &'a [Elf64ResolvedSymbolTableEntry],
+    ) -> Option<&'a Elf64ResolvedSymbolTableEntry> {
+        self.lookup_with(name, symbols, |_, _| true)
+            .map(|(_, symbol)| symbol)
+    }
+}
@@ -0,0 +1,156 @@
+const ANSI_RESET: &str = "\x1b[0m";
+const ANSI_BOLD_CYAN: &str = "\x1b[1;36m";
+const ANSI_GREEN: &str = "\x1b[32m";
+const ANSI_YELLOW: &str = "\x1b[33m";
+const ANSI_RED: &str = "\x1b[31m";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorMode {
+    Always,
+    Never,
+    Auto,
+}
+
+impl ColorMode {
+    pub fn parse(value: &str) -> Result<ColorMode, String> {
+        match value {
+            "always" => Ok(ColorMode::Always),
+            "never" => Ok(ColorMode::Never),
+            "auto" => Ok(ColorMode::Auto),
+            other => Err(format!(
+                "Unknown color mode: {}, expected always, never or auto",
+                other
+            )),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => unsafe { libc::isatty(libc::STDOUT_FILENO) == 1 },
+        }
+    }
+}
+
+fn paint(text: &str, color: &str, enabled: bool) -> String {
+    if enabled {
+        format!("{}{}{}", color, text, ANSI_RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+pub fn header(text: &str, enabled: bool) -> String {
+    paint(text, ANSI_BOLD_CYAN, enabled)
+}
+
+pub fn flag_letters(flags: &[(char, bool)], enabled: bool) -> String {
+    let mut result = String::new();
+    for (letter, set) in flags.iter() {
+        if *set {
+            let color = match letter {
+                'W' => ANSI_YELLOW,
+                'X' => ANSI_RED,
+                _ => ANSI_GREEN,
+            };
+            result.push_str(&paint(&letter.to_string(), color, enabled));
+        } else {
+            result.push(' ');
+        }
+    }
+    result
+}
+
+fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut escape = false;
+    for ch in text.chars() {
+        if escape {
+            if ch == 'm' {
+                escape = false;
+            }
+        } else if ch == '\x1b' {
+            escape = true;
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
+
+pub struct Table {
+    headers: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(headers: &[&str]) -> Table {
+        Table {
+            headers: headers.iter().map(|h| h.to_string()).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn add_row(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    fn column_widths(&self) -> Vec<usize> {
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| visible_width(h)).collect();
+        for row in self.rows.iter() {
+            for (index, cell) in row.iter().enumerate() {
+                let width = visible_width(cell);
+                if index >= widths.len() {
+                    widths.push(width);
+                } else if width > widths[index] {
+                    widths[index] = width;
+                }
+            }
+        }
+        widths
+    }
+
+    fn render_row(cells: &[String], widths: &[usize]) -> String {
+        let mut line = String::new();
+        for (index, cell) in cells.iter().enumerate() {
+            if index > 0 {
+                line.push_str("  ");
+            }
+            line.push_str(cell);
+            if index + 1 < cells.len() {
+                let padding = widths[index] - visible_width(cell);
+                line.push_str(&" ".repeat(padding));
+            }
+        }
+        line
+    }
+
+    pub fn render(&self, color: bool) -> String {
+        let widths = self.column_widths();
+        let mut result = String::new();
+        let padded_headers: Vec<String> = self
+            .headers
+            .iter()
+            .enumerate()
+            .map(|(index, h)| {
+                if index + 1 < self.headers.len() {
+                    format!("{:width$}", h, width = widths[index])
+                } else {
+                    h.clone()
+                }
+            })
+            .collect();
+        result.push_str(&header(&padded_headers.join("  "), color));
+        result.push('\n');
+        for row in self.rows.iter() {
+            result.push_str(&Table::render_row(row, &widths));
+            result.push('\n');
+        }
+        result
+    }
+}
@@ -0,0 +1,845 @@
+//! Builds small ELF64 images in memory, so the parser and loader can be exercised without
+//! checked-in binaries, and injects the failures a test cannot set up, like a noexec mount.
+//! Enabled with the `testutil` feature.
+
+use std::mem::{self, size_of};
+
+use crate::syscall;
+use crate::sysv_hash::sysv_hash;
+use crate::versions::{
+    ELF64_SECTION_HEADER_VERSION_DEFINITIONS, ELF64_SECTION_HEADER_VERSION_NEEDS,
+    ELF64_SECTION_HEADER_VERSION_SYMBOLS,
+};
+use crate::{
+    entry_bytes, Elf64Header, Elf64ProgramHeader, Elf64Relocation, Elf64RelocationAddend,
+    Elf64SectionHeader, Elf64SymbolTableEntry, ELF64_SECTION_HEADER_DYNAMIC,
+    ELF64_SECTION_HEADER_DYNAMIC_SYMBOL_TABLE, ELF64_SECTION_HEADER_NO_BITS,
+    ELF64_SECTION_HEADER_RELOCATION, ELF64_SECTION_HEADER_RELOCATION_ADDEND,
+    ELF64_SECTION_HEADER_STRING_TABLE, MACHINE_X86_64, PROGRAM_FLAG_READ,
+    PROGRAM_HEADER_TYPE_DYNAMIC, PROGRAM_HEADER_TYPE_LOADABLE, PROGRAM_HEADER_TYPE_PHDR,
+    SECTION_FLAG_ALLOCATED,
+};
+
+const ELF_TYPE_SHARED_OBJECT: u16 = 3;
+const PAGE_SIZE: u64 = 0x1000;
+const DYNAMIC_TABLE_NEEDED: i64 = 1;
+const VERSION_FLAG_BASE: u16 = 1;
+const VERSION_HIDDEN: u16 = 0x8000;
+const VERSION_INDEX_GLOBAL: u16 = 1;
+
+struct Segment {
+    segment_type: u32,
+    flags: u32,
+    address: u64,
+    content: Vec<u8>,
+    memory_size: u64,
+}
+
+struct Section {
+    name: String,
+    section_type: u32,
+    flags: u64,
+    address: u64,
+    content: Vec<u8>,
+    size: u64,
+    link: u32,
+    info: u32,
+    entry_size: u64,
+    /// Describes bytes of the loadable segment holding `address` instead of having content.
+    in_segment: bool,
+}
+
+struct Symbol {
+    name: String,
+    binding: u8,
+    symbol_type: u8,
+    section_index: u16,
+    value: u64,
+    size: u64,
+    /// The version name, and whether it is hidden, as in `name@VERSION`.
+    version: Option<(String, bool)>,
+}
+
+struct Relocation {
+    offset: u64,
+    relocation_type: u64,
+    symbol: Option<String>,
+    addend: i64,
+    /// A REL entry, whose addend is the word at `offset`.
+    implicit: bool,
+}
+
+fn align(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment) * alignment
+}
+
+fn push_entry<E>(buffer: &mut Vec<u8>, entry: &E) {
+    buffer.extend_from_slice(entry_bytes(entry));
+}
+
+fn add_string(table: &mut Vec<u8>, value: &str) -> u32 {
+    let offset = table.len() as u32;
+    table.extend_from_slice(value.as_bytes());
+    table.push(0);
+    offset
+}
+
+/// Collects segments, sections and dynamic linking information, then lays them out as a
+/// little-endian ELF64 file with `finalize`.
+///
+/// Sections added with `add_section` and `add_nobits` get the indexes 1, 2 and so on in the order
+/// they are added, which is what `add_symbol` expects. Symbols, symbol versions, relocations and
+/// dynamic entries go to generated `.dynsym`, `.dynstr`, `.gnu.version`, `.gnu.version_d`,
+/// `.gnu.version_r`, `.rela.dyn`, `.rel.dyn` and `.dynamic` sections placed after them, followed
+/// by `.shstrtab`. They are not mapped unless `map_dynamic` is used.
+pub struct ElfBuilder {
+    elf_type: u16,
+    machine: u16,
+    entry: u64,
+    segments: Vec<Segment>,
+    sections: Vec<Section>,
+    needed: Vec<String>,
+    dynamic: Vec<(i64, u64)>,
+    dynamic_terminated: bool,
+    dynamic_padding: usize,
+    symbols: Vec<Symbol>,
+    relocations: Vec<Relocation>,
+    version_definitions: Vec<String>,
+    /// Versions required, as (library, version).
+    version_requirements: Vec<(String, String)>,
+    segment_alignment: u64,
+    headers_address: Option<u64>,
+    dynamic_address: Option<u64>,
+}
+
+impl Default for ElfBuilder {
+    fn default() -> ElfBuilder {
+        ElfBuilder::new()
+    }
+}
+
+impl ElfBuilder {
+    /// A position independent shared object with no content.
+    pub fn new() -> ElfBuilder {
+        ElfBuilder {
+            elf_type: ELF_TYPE_SHARED_OBJECT,
+            machine: MACHINE_X86_64,
+            entry: 0,
+            segments: Vec::new(),
+            sections: Vec::new(),
+            needed: Vec::new(),
+            dynamic: Vec::new(),
+            dynamic_terminated: true,
+            dynamic_padding: 0,
+            symbols: Vec::new(),
+            relocations: Vec::new(),
+            version_definitions: Vec::new(),
+            version_requirements: Vec::new(),
+            segment_alignment: PAGE_SIZE,
+            headers_address: None,
+            dynamic_address: None,
+        }
+    }
+
+    pub fn elf_type(mut self, elf_type: u16) -> ElfBuilder {
+        self.elf_type = elf_type;
+        self
+    }
+
+    /// The e_machine of the file, x86-64 by default. Relocation types are the machine's own.
+    pub fn machine(mut self, machine: u16) -> ElfBuilder {
+        self.machine = machine;
+        self
+    }
+
+    pub fn entry(mut self, entry: u64) -> ElfBuilder {
+        self.entry = entry;
+        self
+    }
+
+    /// The p_align of the loadable segments, the page size by default. File offsets stay
+    /// congruent to the addresses modulo the page size only.
+    pub fn segment_alignment(mut self, alignment: u64) -> ElfBuilder {
+        self.segment_alignment = alignment;
+        self
+    }
+
+    /// Maps the ELF header and the program header table at `address`, through a loadable segment
+    /// at file offset 0, and describes the table with a PT_PHDR, as linkers do for programs.
+    pub fn map_headers(mut self, address: u64) -> ElfBuilder {
+        self.headers_address = Some(address);
+        self
+    }
+
+    /// Maps the generated sections at `address`, through a read-only loadable segment, and
+    /// describes `.dynamic` with a PT_DYNAMIC, so the section headers agree with the segments as
+    /// `--strict` requires.
+    pub fn map_dynamic(mut self, address: u64) -> ElfBuilder {
+        self.dynamic_address = Some(address);
+        self
+    }
+
+    /// Adds a program header whose file content is `content`. Loadable segments are placed at a
+    /// file offset congruent to `address` modulo the page size, so they can be mapped.
+    pub fn add_segment(
+        mut self,
+        segment_type: u32,
+        flags: u32,
+        address: u64,
+        content: &[u8],
+        memory_size: u64,
+    ) -> ElfBuilder {
+        self.segments.push(Segment {
+            segment_type,
+            flags,
+            address,
+            content: content.to_vec(),
+            memory_size: memory_size.max(content.len() as u64),
+        });
+        self
+    }
+
+    pub fn add_section(
+        mut self,
+        name: &str,
+        section_type: u32,
+        flags: u64,
+        content: &[u8],
+    ) -> ElfBuilder {
+        self.sections.push(Section {
+            name: name.to_string(),
+            section_type,
+            flags,
+            address: 0,
+            content: content.to_vec(),
+            size: content.len() as u64,
+            link: 0,
+            info: 0,
+            entry_size: 0,
+            in_segment: false,
+        });
+        self
+    }
+
+    /// Adds a section for `size` bytes at `address`, whose file offset is where the loadable
+    /// segment holding `address` has them, or 0 when no segment does. Giving it flags, an address
+    /// or a size the segments disagree with builds the mismatches `--strict` reports.
+    pub fn add_segment_section(
+        mut self,
+        name: &str,
+        section_type: u32,
+        flags: u64,
+        address: u64,
+        size: u64,
+    ) -> ElfBuilder {
+        self.sections.push(Section {
+            name: name.to_string(),
+            section_type,
+            flags,
+            address,
+            content: Vec::new(),
+            size,
+            link: 0,
+            info: 0,
+            entry_size: 0,
+            in_segment: true,
+        });
+        self
+    }
+
+    /// Adds an allocated section without file content, like `.bss`.
+    pub fn add_nobits(mut self, name: &str, flags: u64, address: u64, size: u64) -> ElfBuilder {
+        self.sections.push(Section {
+            name: name.to_string(),
+            section_type: ELF64_SECTION_HEADER_NO_BITS,
+            flags: flags | SECTION_FLAG_ALLOCATED,
+            address,
+            content: Vec::new(),
+            size,
+            link: 0,
+            info: 0,
+            entry_size: 0,
+            in_segment: false,
+        });
+        self
+    }
+
+    /// Adds a `DT_NEEDED` entry naming `library`.
+    pub fn add_needed(mut self, library: &str) -> ElfBuilder {
+        self.needed.push(library.to_string());
+        self
+    }
+
+    pub fn add_dynamic(mut self, tag: i64, value: u64) -> ElfBuilder {
+        self.dynamic.push((tag, value));
+        self
+    }
+
+    /// Leaves out the DT_NULL entry ending `.dynamic`, or pads the section with `padding` more
+    /// entries after it. A DT_NULL added with `add_dynamic` ends the array early.
+    pub fn dynamic_terminator(mut self, terminated: bool, padding: usize) -> ElfBuilder {
+        self.dynamic_terminated = terminated;
+        self.dynamic_padding = padding;
+        self
+    }
+
+    pub fn add_symbol(
+        mut self,
+        name: &str,
+        binding: u8,
+        symbol_type: u8,
+        section_index: u16,
+        value: u64,
+        size: u64,
+    ) -> ElfBuilder {
+        self.symbols.push(Symbol {
+            name: name.to_string(),
+            binding,
+            symbol_type,
+            section_index,
+            value,
+            size,
+            version: None,
+        });
+        self
+    }
+
+    /// Gives the symbol added last the version `version`, one added with `add_version_definition`
+    /// or `add_version_requirement`. A hidden version is a non-default one, as in `name@VERSION`
+    /// rather than `name@@VERSION`.
+    pub fn symbol_version(mut self, version: &str, hidden: bool) -> ElfBuilder {
+        let symbol = self
+            .symbols
+            .last_mut()
+            .expect("symbol_version needs a symbol");
+        symbol.version = Some((version.to_string(), hidden));
+        self
+    }
+
+    /// Adds a version definition. The first one is the base definition, naming the file itself,
+    /// and gets the index 1, the others 2, 3 and so on.
+    pub fn add_version_definition(mut self, name: &str) -> ElfBuilder {
+        self.version_definitions.push(name.to_string());
+        self
+    }
+
+    /// Adds a requirement of `version` from `library`, indexed after the version definitions.
+    /// Requirements from the same library are grouped in one `Elf64_Verneed` entry.
+    pub fn add_version_requirement(mut self, library: &str, version: &str) -> ElfBuilder {
+        self.version_requirements
+            .push((library.to_string(), version.to_string()));
+        self
+    }
+
+    /// The libraries versions are required from, each once.
+    fn required_libraries(&self) -> Vec<&str> {
+        let mut libraries: Vec<&str> = Vec::new();
+        for (library, _) in self.version_requirements.iter() {
+            if !libraries.contains(&library.as_str()) {
+                libraries.push(library);
+            }
+        }
+        libraries
+    }
+
+    /// The `.gnu.version` index of the version named `name`. Requirements start at 2 when there
+    /// are no definitions, since 1 stands for an unversioned global symbol.
+    fn version_index(&self, name: &str) -> u16 {
+        if let Some(index) = self
+            .version_definitions
+            .iter()
+            .position(|version| version == name)
+        {
+            return index as u16 + 1;
+        }
+        self.version_requirements
+            .iter()
+            .position(|(_, version)| version == name)
+            .map(|index| (self.version_definitions.len().max(1) + index) as u16 + 1)
+            .unwrap_or_else(|| panic!("Symbol of unknown version {}", name))
+    }
+
+    /// The `.gnu.version`, `.gnu.version_d` and `.gnu.version_r` contents, empty when there is no
+    /// version information.
+    fn version_sections(&self, strings: &mut Vec<u8>) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        if self.version_definitions.is_empty() && self.version_requirements.is_empty() {
+            return (Vec::new(), Vec::new(), Vec::new());
+        }
+        let mut symbols = Vec::new();
+        push_entry(&mut symbols, &0u16);
+        for symbol in self.symbols.iter() {
+            let index = match &symbol.version {
+                Some((version, true)) => self.version_index(version) | VERSION_HIDDEN,
+                Some((version, false)) => self.version_index(version),
+                None => VERSION_INDEX_GLOBAL,
+            };
+            push_entry(&mut symbols, &index);
+        }
+        // Elf64_Verdef entries, each followed by its one Elf64_Verdaux.
+        let mut definitions = Vec::new();
+        for (position, name) in self.version_definitions.iter().enumerate() {
+            let last = position + 1 == self.version_definitions.len();
+            let flags = if position == 0 { VERSION_FLAG_BASE } else { 0 };
+            push_entry(&mut definitions, &[1u16, flags, position as u16 + 1, 1]);
+            push_entry(
+                &mut definitions,
+                &[sysv_hash(name), 20, if last { 0 } else { 28 }],
+            );
+            push_entry(&mut definitions, &[add_string(strings, name), 0]);
+        }
+        // Elf64_Verneed entries, each followed by the Elf64_Vernaux of its versions.
+        let libraries = self.required_libraries();
+        let mut needs = Vec::new();
+        for (position, library) in libraries.iter().enumerate() {
+            let versions: Vec<&String> = self
+                .version_requirements
+                .iter()
+                .filter(|(required_from, _)| required_from == library)
+                .map(|(_, version)| version)
+                .collect();
+            let size = 16 + 16 * versions.len() as u32;
+            let next = if position + 1 == libraries.len() {
+                0
+            } else {
+                size
+            };
+            push_entry(&mut needs, &[1u16, versions.len() as u16]);
+            push_entry(&mut needs, &[add_string(strings, library), 16, next]);
+            for (index, version) in versions.iter().enumerate() {
+                let next = if index + 1 == versions.len() { 0 } else { 16 };
+                push_entry(&mut needs, &sysv_hash(version));
+                push_entry(&mut needs, &[0u16, self.version_index(version)]);
+                push_entry(&mut needs, &[add_string(strings, version), next]);
+            }
+        }
+        (symbols, definitions, needs)
+    }
+
+    /// Adds a relocation against the symbol named `symbol`, which must have been added with
+    /// `add_symbol`, or against no symbol.
+    pub fn add_rela(
+        mut self,
+        offset: u64,
+        relocation_type: u64,
+        symbol: Option<&str>,
+        addend: i64,
+    ) -> ElfBuilder {
+        self.relocations.push(Relocation {
+            offset,
+            relocation_type,
+            symbol: symbol.map(|name| name.to_string()),
+            addend,
+            implicit: false,
+        });
+        self
+    }
+
+    /// Adds a REL relocation, going to `.rel.dyn`. Its addend is whatever the segment content
+    /// holds at `offset`.
+    pub fn add_rel(
+        mut self,
+        offset: u64,
+        relocation_type: u64,
+        symbol: Option<&str>,
+    ) -> ElfBuilder {
+        self.relocations.push(Relocation {
+            offset,
+            relocation_type,
+            symbol: symbol.map(|name| name.to_string()),
+            addend: 0,
+            implicit: true,
+        });
+        self
+    }
+
+    fn symbol_index(&self, name: &str) -> u64 {
+        self.symbols
+            .iter()
+            .position(|symbol| symbol.name == name)
+            .map(|index| index as u64 + 1)
+            .unwrap_or_else(|| panic!("Relocation against unknown symbol {}", name))
+    }
+
+    /// Appends the `.dynsym`, `.dynstr`, `.rela.dyn` and `.dynamic` sections that are needed.
+    fn generated_sections(&self, sections: &mut Vec<Section>) {
+        if self.symbols.is_empty()
+            && self.relocations.is_empty()
+            && self.needed.is_empty()
+            && self.dynamic.is_empty()
+            && self.version_definitions.is_empty()
+            && self.version_requirements.is_empty()
+        {
+            return;
+        }
+        let symbol_table_index = sections.len() as u32 + 1;
+        let string_table_index = symbol_table_index + 1;
+        let mut strings = vec![0];
+        let mut symbols = Vec::new();
+        push_entry(
+            &mut symbols,
+            &Elf64SymbolTableEntry {
+                st_name: 0,
+                st_info: 0,
+                st_other: 0,
+                st_section_index: 0,
+                st_value: 0,
+                st_size: 0,
+            },
+        );
+        for symbol in self.symbols.iter() {
+            let entry = Elf64SymbolTableEntry {
+                st_name: add_string(&mut strings, &symbol.name),
+                st_info: (symbol.binding << 4) | (symbol.symbol_type & 0xf),
+                st_other: 0,
+                st_section_index: symbol.section_index,
+                st_value: symbol.value,
+                st_size: symbol.size,
+            };
+            push_entry(&mut symbols, &entry);
+        }
+        let (version_symbols, version_definitions, version_needs) =
+            self.version_sections(&mut strings);
+        let mut dynamic = Vec::new();
+        for library in self.needed.iter() {
+            let offset = add_string(&mut strings, library) as u64;
+            push_entry(&mut dynamic, &[DYNAMIC_TABLE_NEEDED as u64, offset]);
+        }
+        for (tag, value) in self.dynamic.iter() {
+            push_entry(&mut dynamic, &[*tag as u64, *value]);
+        }
+        if self.dynamic_terminated {
+            push_entry(&mut dynamic, &[0u64, 0u64]);
+        }
+        for _ in 0..self.dynamic_padding {
+            push_entry(&mut dynamic, &[0u64, 0u64]);
+        }
+        let mut relocations = Vec::new();
+        let mut implicit_relocations = Vec::new();
+        for relocation in self.relocations.iter() {
+            let symbol_index = relocation
+                .symbol
+                .as_ref()
+                .map(|name| self.symbol_index(name))
+                .unwrap_or(0);
+            let info = (symbol_index << 32) | relocation.relocation_type;
+            if relocation.implicit {
+                push_entry(
+                    &mut implicit_relocations,
+                    &Elf64Relocation {
+                        offset: relocation.offset,
+                        info,
+                    },
+                );
+            } else {
+                push_entry(
+                    &mut relocations,
+                    &Elf64RelocationAddend {
+                        offset: relocation.offset,
+                        info,
+                        addend: relocation.addend,
+                    },
+                );
+            }
+        }
+        let section = |name: &str, section_type, content: Vec<u8>, link, entry_size| Section {
+            name: name.to_string(),
+            section_type,
+            flags: SECTION_FLAG_ALLOCATED,
+            address: 0,
+            size: content.len() as u64,
+            content,
+            link,
+            info: match section_type {
+                ELF64_SECTION_HEADER_DYNAMIC_SYMBOL_TABLE => 1,
+                ELF64_SECTION_HEADER_VERSION_DEFINITIONS => self.version_definitions.len() as u32,
+                ELF64_SECTION_HEADER_VERSION_NEEDS => self.required_libraries().len() as u32,
+                _ => 0,
+            },
+            entry_size,
+            in_segment: false,
+        };
+        sections.push(section(
+            ".dynsym",
+            ELF64_SECTION_HEADER_DYNAMIC_SYMBOL_TABLE,
+            symbols,
+            string_table_index,
+            size_of::<Elf64SymbolTableEntry>() as u64,
+        ));
+        sections.push(section(
+            ".dynstr",
+            ELF64_SECTION_HEADER_STRING_TABLE,
+            strings,
+            0,
+            0,
+        ));
+        if !version_symbols.is_empty() {
+            sections.push(section(
+                ".gnu.version",
+                ELF64_SECTION_HEADER_VERSION_SYMBOLS,
+                version_symbols,
+                symbol_table_index,
+                2,
+            ));
+        }
+        if !version_definitions.is_empty() {
+            sections.push(section(
+                ".gnu.version_d",
+                ELF64_SECTION_HEADER_VERSION_DEFINITIONS,
+                version_definitions,
+                string_table_index,
+                0,
+            ));
+        }
+        if !version_needs.is_empty() {
+            sections.push(section(
+                ".gnu.version_r",
+                ELF64_SECTION_HEADER_VERSION_NEEDS,
+                version_needs,
+                string_table_index,
+                0,
+            ));
+        }
+        if !relocations.is_empty() {
+            sections.push(section(
+                ".rela.dyn",
+                ELF64_SECTION_HEADER_RELOCATION_ADDEND,
+                relocations,
+                symbol_table_index,
+                size_of::<Elf64RelocationAddend>() as u64,
+            ));
+        }
+        if !implicit_relocations.is_empty() {
+            sections.push(section(
+                ".rel.dyn",
+                ELF64_SECTION_HEADER_RELOCATION,
+                implicit_relocations,
+                symbol_table_index,
+                size_of::<Elf64Relocation>() as u64,
+            ));
+        }
+        sections.push(section(
+            ".dynamic",
+            ELF64_SECTION_HEADER_DYNAMIC,
+            dynamic,
+            string_table_index,
+            16,
+        ));
+    }
+
+    /// Moves the content of `sections` to a loadable segment at `address`, one after the other.
+    fn map_sections(&mut self, sections: &mut [Section], address: u64) {
+        let mut content = Vec::new();
+        for section in sections.iter_mut() {
+            let offset = align(content.len() as u64, 8);
+            content.resize(offset as usize, 0);
+            content.append(&mut section.content);
+            section.address = address + offset;
+            section.in_segment = true;
+        }
+        self.segments.push(Segment {
+            segment_type: PROGRAM_HEADER_TYPE_LOADABLE,
+            flags: PROGRAM_FLAG_READ,
+            address,
+            memory_size: content.len() as u64,
+            content,
+        });
+    }
+
+    /// Lays out the header, program headers, segment contents, section contents and section
+    /// headers, in that order, and returns the file.
+    pub fn finalize(mut self) -> Vec<u8> {
+        let mut sections = mem::take(&mut self.sections);
+        let generated = sections.len();
+        self.generated_sections(&mut sections);
+        let mut dynamic = None;
+        if let Some(address) = self.dynamic_address.filter(|_| sections.len() > generated) {
+            self.map_sections(&mut sections[generated..], address);
+            dynamic = sections
+                .last()
+                .map(|section| (section.address, section.size));
+        }
+        let mut section_names = vec![0];
+        let mut name_offsets: Vec<u32> = sections
+            .iter()
+            .map(|section| add_string(&mut section_names, &section.name))
+            .collect();
+        name_offsets.push(add_string(&mut section_names, ".shstrtab"));
+        sections.push(Section {
+            name: String::from(".shstrtab"),
+            section_type: ELF64_SECTION_HEADER_STRING_TABLE,
+            flags: 0,
+            address: 0,
+            size: section_names.len() as u64,
+            content: section_names,
+            link: 0,
+            info: 0,
+            entry_size: 0,
+            in_segment: false,
+        });
+
+        let header_size = size_of::<Elf64Header>() as u64;
+        let program_header_count = self.segments.len()
+            + if self.headers_address.is_some() { 2 } else { 0 }
+            + if dynamic.is_some() { 1 } else { 0 };
+        let program_headers_size = (program_header_count * size_of::<Elf64ProgramHeader>()) as u64;
+        let mut file = vec![0u8; (header_size + program_headers_size) as usize];
+        let mut program_headers = Vec::new();
+        if let Some(address) = self.headers_address {
+            let header = |p_type, p_offset, size| Elf64ProgramHeader {
+                p_type,
+                p_flags: PROGRAM_FLAG_READ,
+                p_offset,
+                p_virtual_address: address + p_offset,
+                p_physical_address: address + p_offset,
+                p_file_size: size,
+                p_memory_size: size,
+                p_align: 8,
+            };
+            program_headers.push(header(
+                PROGRAM_HEADER_TYPE_PHDR,
+                header_size,
+                program_headers_size,
+            ));
+            program_headers.push(Elf64ProgramHeader {
+                p_align: self.segment_alignment,
+                ..header(
+                    PROGRAM_HEADER_TYPE_LOADABLE,
+                    0,
+                    header_size + program_headers_size,
+                )
+            });
+        }
+        for segment in self.segments.iter() {
+            let offset = if segment.segment_type == PROGRAM_HEADER_TYPE_LOADABLE {
+                align(file.len() as u64, PAGE_SIZE) + segment.address % PAGE_SIZE
+            } else {
+                align(file.len() as u64, 8)
+            };
+            file.resize(offset as usize, 0);
+            file.extend_from_slice(&segment.content);
+            program_headers.push(Elf64ProgramHeader {
+                p_type: segment.segment_type,
+                p_flags: segment.flags,
+                p_offset: offset,
+                p_virtual_address: segment.address,
+                p_physical_address: segment.address,
+                p_file_size: segment.content.len() as u64,
+                p_memory_size: segment.memory_size,
+                p_align: if segment.segment_type == PROGRAM_HEADER_TYPE_LOADABLE {
+                    self.segment_alignment
+                } else {
+                    8
+                },
+            });
+        }
+        if let Some((address, size)) = dynamic {
+            let offset = program_headers
+                .iter()
+                .find(|header| {
+                    header.p_type == PROGRAM_HEADER_TYPE_LOADABLE
+                        && header.p_virtual_address <= address
+                        && address < header.p_virtual_address + header.p_file_size
+                })
+                .map(|header| header.p_offset + address - header.p_virtual_address)
+                .unwrap_or(0);
+            program_headers.push(Elf64ProgramHeader {
+                p_type: PROGRAM_HEADER_TYPE_DYNAMIC,
+                p_flags: PROGRAM_FLAG_READ,
+                p_offset: offset,
+                p_virtual_address: address,
+                p_physical_address: address,
+                p_file_size: size,
+                p_memory_size: size,
+                p_align: 8,
+            });
+        }
+        let mut section_headers = Vec::new();
+        push_entry(
+            &mut section_headers,
+            &Elf64SectionHeader {
+                sh_name: 0,
+                sh_type: 0,
+                sh_flags: 0,
+                sh_virtual_address: 0,
+                sh_offset: 0,
+                sh_size: 0,
+                sh_link: 0,
+                sh_info: 0,
+                sh_address_align: 0,
+                sh_entry_size: 0,
+            },
+        );
+        for (section, name) in sections.iter().zip(name_offsets.iter()) {
+            let offset = if section.in_segment {
+                program_headers
+                    .iter()
+                    .filter(|header| header.p_type == PROGRAM_HEADER_TYPE_LOADABLE)
+                    .find(|header| {
+                        header.p_virtual_address <= section.address
+                            && section.address < header.p_virtual_address + header.p_memory_size
+                    })
+                    .map(|header| header.p_offset + section.address - header.p_virtual_address)
+                    .unwrap_or(0)
+            } else {
+                let offset = align(file.len() as u64, 8);
+                file.resize(offset as usize, 0);
+                file.extend_from_slice(&section.content);
+                offset
+            };
+            push_entry(
+                &mut section_headers,
+                &Elf64SectionHeader {
+                    sh_name: *name,
+                    sh_type: section.section_type,
+                    sh_flags: section.flags,
+                    sh_virtual_address: section.address,
+                    sh_offset: offset,
+                    sh_size: section.size,
+                    sh_link: section.link,
+                    sh_info: section.info,
+                    sh_address_align: 8,
+                    sh_entry_size: section.entry_size,
+                },
+            );
+        }
+        let section_headers_offset = align(file.len() as u64, 8);
+        file.resize(section_headers_offset as usize, 0);
+        file.extend_from_slice(&section_headers);
+
+        let mut e_ident = [0u8; 16];
+        e_ident[..7].copy_from_slice(&[0x7F, b'E', b'L', b'F', 2, 1, 1]);
+        let header = Elf64Header {
+            e_ident,
+            e_type: self.elf_type,
+            e_machine: self.machine,
+            e_version: 1,
+            e_entry: self.entry,
+            e_program_header_offset: if program_headers.is_empty() {
+                0
+            } else {
+                header_size
+            },
+            e_section_header_offset: section_headers_offset,
+            e_flags: 0,
+            e_elf_header_size: header_size as u16,
+            e_program_header_entry_size: size_of::<Elf64ProgramHeader>() as u16,
+            e_program_header_entries: program_headers.len() as u16,
+            e_section_header_entry_size: size_of::<Elf64SectionHeader>() as u16,
+            e_section_header_entries: sections.len() as u16 + 1,
+            e_section_name_string_table_index: sections.len() as u16,
+        };
+        let mut headers = Vec::new();
+        push_entry(&mut headers, &header);
+        for program_header in program_headers.iter() {
+            push_entry(&mut headers, program_header);
+        }
+        file[..headers.len()].copy_from_slice(&headers);
+        file
+    }
+}
+
+/// Makes the executable mappings of files done by the calling thread fail with `errno`, as they
+/// do on a noexec mount, until called again with `None`. Mappings of memfds still succeed.
+pub fn fail_executable_file_mappings(errno: Option<i32>) {
+    syscall::set_executable_file_mapping_error(errno);
+}
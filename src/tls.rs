@@ -0,0 +1,540 @@
+use std::alloc::{self, Layout};
+use std::arch::asm;
+use std::collections::{BTreeMap, HashMap};
+use std::ptr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::error::DrowError;
+use crate::symbol_name;
+use crate::syscall::{self, Errno};
+use crate::{
+    Elf64Metadata, Elf64ResolvedSymbolTableEntry, Elf64TlsSegment, SHN_ABSOLUTE,
+    SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_FUNCTION,
+};
+
+/// Module IDs are shared by all loaders of the process, as they index the same DTV.
+static NEXT_MODULE_ID: AtomicUsize = AtomicUsize::new(1);
+
+/// The DTV of the only thread running loaded code, by module ID. Blocks are allocated on the
+/// first `__tls_get_addr` call for their module.
+static DYNAMIC_THREAD_VECTOR: Mutex<BTreeMap<usize, DtvSlot>> = Mutex::new(BTreeMap::new());
+
+struct DtvSlot {
+    image_address: u64,
+    image_size: u64,
+    layout: Layout,
+    block: Option<u64>,
+    /// Whether the block is in the static TLS area rather than allocated by the slot.
+    static_block: bool,
+}
+
+impl DtvSlot {
+    fn new(module: &TlsModule) -> Option<DtvSlot> {
+        let segment = &module.segment;
+        let layout = Layout::from_size_align(
+            segment.memory_size.max(1) as usize,
+            segment.alignment as usize,
+        );
+        match layout {
+            Ok(layout) => Some(DtvSlot {
+                image_address: module.image_address,
+                image_size: segment.file_size,
+                layout,
+                block: None,
+                static_block: false,
+            }),
+            Err(err) => {
+                warn!(
+                    "TLS module {} of {} is not usable: {}",
+                    module.id, segment.object, err
+                );
+                None
+            }
+        }
+    }
+
+    /// The block of the module, copied from the init image and zero filled past it.
+    fn block(&mut self) -> Option<u64> {
+        if self.block.is_none() {
+            let block = unsafe { alloc::alloc_zeroed(self.layout) };
+            if block.is_null() {
+                return None;
+            }
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    self.image_address as *const u8,
+                    block,
+                    (self.image_size as usize).min(self.layout.size()),
+                );
+            }
+            self.block = Some(block as u64);
+        }
+        self.block
+    }
+
+    /// Points the slot at the block of the module in the static TLS area, so that
+    /// `__tls_get_addr` and TPOFF accesses reach the same variables.
+    fn place(&mut self, block: u64) {
+        self.free();
+        self.block = Some(block);
+        self.static_block = true;
+    }
+
+    fn free(&mut self) {
+        if let Some(block) = self.block.take() {
+            if !self.static_block {
+                unsafe { alloc::dealloc(block as *mut u8, self.layout) };
+            }
+        }
+        self.static_block = false;
+    }
+}
+
+impl Drop for DtvSlot {
+    fn drop(&mut self) {
+        self.free();
+    }
+}
+
+fn dynamic_thread_vector() -> MutexGuard<'static, BTreeMap<usize, DtvSlot>> {
+    DYNAMIC_THREAD_VECTOR
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// The argument of `__tls_get_addr`, filled by DTPMOD64 and DTPOFF64 relocations.
+#[repr(C)]
+pub struct TlsIndex {
+    pub module: u64,
+    pub offset: u64,
+}
+
+/// The `__tls_get_addr` loaded objects call for general and local dynamic TLS accesses. Returns
+/// null for modules that are not loaded.
+///
+/// # Safety
+///
+/// `index` must point to a valid `TlsIndex`.
+pub unsafe extern "C" fn tls_get_addr(index: *const TlsIndex) -> *mut u8 {
+    let _thread_pointer = ThreadPointerGuard::drow();
+    let index = &*index;
+    match dynamic_thread_vector()
+        .get_mut(&(index.module as usize))
+        .and_then(DtvSlot::block)
+    {
+        Some(block) => (block + index.offset) as *mut u8,
+        None => ptr::null_mut(),
+    }
+}
+
+/// The `__tls_get_addr` symbol for the global symbol table.
+pub(crate) fn tls_get_addr_symbol() -> Elf64ResolvedSymbolTableEntry {
+    let function: unsafe extern "C" fn(*const TlsIndex) -> *mut u8 = tls_get_addr;
+    Elf64ResolvedSymbolTableEntry {
+        symbol_name: String::from("__tls_get_addr"),
+        binding: SYMBOL_BINDING_GLOBAL,
+        symbol_type: SYMBOL_TYPE_FUNCTION,
+        section_index: SHN_ABSOLUTE,
+        value: function as usize as u64,
+        size: 0,
+        version: None,
+    }
+}
+
+/// The PT_TLS segment of a loaded object.
+#[derive(Clone, Debug)]
+pub struct TlsModule {
+    /// Index into the dynamic thread vector, starting at 1 like DTPMOD64 expects.
+    pub id: usize,
+    /// The segment of the object, with `id` as its module ID.
+    pub segment: Elf64TlsSegment,
+    /// Address of the initialization image in the mapped object.
+    pub image_address: u64,
+    /// Distance from the thread pointer down to the block, for modules in the static TLS area.
+    /// Modules loaded after the program are allocated on first use and have none.
+    pub static_offset: Option<u64>,
+}
+
+impl TlsModule {
+    pub fn dynamic(&self) -> bool {
+        self.static_offset.is_none()
+    }
+
+    /// The TPOFF of the block, negative as the x86-64 static TLS area ends at the thread pointer.
+    pub fn thread_pointer_offset(&self) -> Option<i64> {
+        self.static_offset.map(|offset| -(offset as i64))
+    }
+}
+
+/// Bytes reserved past the thread pointer for the thread control block. glibc's `struct pthread`
+/// starts there, drow only fills the fields compilers reach by offset.
+const TCB_SIZE: u64 = 0x1000;
+/// `tcbhead_t::tcb`, the thread pointer itself, read by `mov %fs:0`.
+const TCB_SELF: u64 = 0x0;
+/// `tcbhead_t::self`.
+const TCB_HEADER_SELF: u64 = 0x10;
+/// `tcbhead_t::stack_guard`, the canary of `-fstack-protector` code.
+const TCB_STACK_GUARD: u64 = 0x28;
+
+/// The thread pointer of drow's own thread, saved when loaded code first gets its own.
+static DROW_THREAD_POINTER: AtomicU64 = AtomicU64::new(0);
+
+/// The thread pointer of the calling thread, from the self-pointer the x86-64 TCB starts with.
+pub(crate) fn thread_pointer() -> u64 {
+    let thread_pointer: u64;
+    unsafe {
+        asm!(
+            "mov {}, qword ptr fs:[0]",
+            out(reg) thread_pointer,
+            options(nostack, readonly, preserves_flags)
+        );
+    }
+    thread_pointer
+}
+
+/// Switches from drow's thread pointer to `thread_pointer` for the loaded code about to run.
+///
+/// # Safety
+/// Nothing using drow's TLS may run until a `ThreadPointerGuard` switches back, including logging
+/// and allocation.
+pub(crate) unsafe fn enter_loaded_code(thread_pointer: u64) -> Result<(), Errno> {
+    DROW_THREAD_POINTER.store(self::thread_pointer(), Ordering::SeqCst);
+    syscall::set_thread_pointer_checked(thread_pointer)
+}
+
+/// Runs drow code called from loaded code with drow's thread pointer, and restores the thread
+/// pointer of the loaded code when dropped. Does nothing when drow's is already set.
+pub(crate) struct ThreadPointerGuard {
+    loaded_code: Option<u64>,
+}
+
+impl ThreadPointerGuard {
+    pub fn drow() -> ThreadPointerGuard {
+        let drow = DROW_THREAD_POINTER.load(Ordering::SeqCst);
+        let current = thread_pointer();
+        let loaded_code = if drow == 0 || drow == current {
+            None
+        } else {
+            unsafe { syscall::set_thread_pointer_checked(drow) }
+                .ok()
+                .map(|_| current)
+        };
+        ThreadPointerGuard { loaded_code }
+    }
+}
+
+impl Drop for ThreadPointerGuard {
+    fn drop(&mut self) {
+        if let Some(thread_pointer) = self.loaded_code {
+            let _ = unsafe { syscall::set_thread_pointer_checked(thread_pointer) };
+        }
+    }
+}
+
+/// The static TLS blocks of the modules loaded with the program, ending at the thread pointer,
+/// followed by a minimal TCB.
+pub struct StaticTlsArea {
+    address: *const libc::c_void,
+    length: usize,
+    thread_pointer: u64,
+}
+
+impl StaticTlsArea {
+    pub fn thread_pointer(&self) -> u64 {
+        self.thread_pointer
+    }
+
+    pub fn length(&self) -> usize {
+        self.length
+    }
+}
+
+impl Drop for StaticTlsArea {
+    fn drop(&mut self) {
+        if let Err(errno) = syscall::munmap_checked(self.address, self.length) {
+            warn!(
+                "Failed to unmap the static TLS area at {:#X}: {}",
+                self.address as u64, errno
+            );
+        }
+    }
+}
+
+// The area is only written through raw pointers, by the thread setting it up.
+unsafe impl Send for StaticTlsArea {}
+
+fn align_up(value: u64, alignment: u64) -> u64 {
+    if alignment > 1 {
+        value.div_ceil(alignment) * alignment
+    } else {
+        value
+    }
+}
+
+/// TLS modules of the loaded objects. IDs are assigned in load order and not reused after an
+/// unload, and each change bumps the generation a DTV is compared against. Registered modules
+/// are published to the DTV `tls_get_addr` reads until they are released.
+pub struct TlsRegistry {
+    modules: Vec<TlsModule>,
+    symbols: HashMap<String, (usize, u64)>,
+    max_id: usize,
+    static_size: u64,
+    static_alignment: u64,
+    static_closed: bool,
+    /// The program and the static offset kept for it by `reserve_program`.
+    reserved: Option<(String, u64)>,
+    static_area: Option<StaticTlsArea>,
+    generation: u64,
+}
+
+impl TlsRegistry {
+    pub(crate) fn new() -> TlsRegistry {
+        TlsRegistry {
+            modules: Vec::new(),
+            symbols: HashMap::new(),
+            max_id: 0,
+            static_size: 0,
+            static_alignment: 1,
+            static_closed: false,
+            reserved: None,
+            static_area: None,
+            generation: 0,
+        }
+    }
+
+    /// Places the PT_TLS of the program first in the static TLS area, at the offset its
+    /// local-exec accesses were linked with, although its dependencies are registered before it.
+    pub(crate) fn reserve_program(&mut self, elf_metadata: &Elf64Metadata) {
+        if self.static_closed || self.static_size != 0 {
+            return;
+        }
+        if let Some(segment) = elf_metadata.tls_segments.first() {
+            let offset = self.next_static_offset(segment);
+            self.reserved = Some((elf_metadata.file_path.clone(), offset));
+        }
+    }
+
+    fn next_static_offset(&mut self, segment: &Elf64TlsSegment) -> u64 {
+        let alignment = segment.alignment;
+        // The block start keeps the misalignment of p_vaddr, like the image in the file.
+        let first_byte = segment.virtual_address.wrapping_neg() & (alignment - 1);
+        let end = (self.static_size + segment.memory_size).saturating_sub(first_byte);
+        let offset = align_up(end, alignment) + first_byte;
+        self.static_size = offset;
+        self.static_alignment = self.static_alignment.max(alignment);
+        offset
+    }
+
+    /// Registers the PT_TLS segment of `elf_metadata` mapped at `base`, with its thread-local
+    /// symbol definitions. Returns the module ID, or `None` for objects without TLS.
+    pub(crate) fn register(&mut self, elf_metadata: &Elf64Metadata, base: u64) -> Option<usize> {
+        let segment = elf_metadata.tls_segments.first()?;
+        let id = NEXT_MODULE_ID.fetch_add(1, Ordering::Relaxed);
+        self.max_id = id;
+        let static_offset = match self.reserved.take() {
+            Some((object, offset)) if object == elf_metadata.file_path => Some(offset),
+            reserved => {
+                self.reserved = reserved;
+                if self.static_closed {
+                    None
+                } else {
+                    Some(self.next_static_offset(segment))
+                }
+            }
+        };
+        let module = TlsModule {
+            id,
+            segment: Elf64TlsSegment {
+                module_id: Some(id),
+                ..segment.clone()
+            },
+            image_address: segment.virtual_address + base,
+            static_offset,
+        };
+        debug!(
+            "TLS module {} of {}: {} bytes aligned to {}, {}",
+            id,
+            module.segment.object,
+            module.segment.memory_size,
+            module.segment.alignment,
+            match module.static_offset {
+                Some(offset) => format!("static at TP-{:#X}", offset),
+                None => String::from("dynamic"),
+            }
+        );
+        for symbol in elf_metadata.dynamic_symbol_table.iter() {
+            if symbol.thread_local() && !symbol.undefined() {
+                let name = symbol_name::base_name(&symbol.symbol_name);
+                self.symbols
+                    .entry(name.to_string())
+                    .or_insert((id, symbol.value));
+            }
+        }
+        if let Some(slot) = DtvSlot::new(&module) {
+            dynamic_thread_vector().insert(id, slot);
+        }
+        self.modules.push(module);
+        self.generation += 1;
+        Some(id)
+    }
+
+    /// Modules registered from now on are dynamic, the static TLS area keeps its size.
+    pub(crate) fn close_static(&mut self) {
+        self.reserved = None;
+        if !self.static_closed {
+            debug!(
+                "Static TLS: {} bytes aligned to {}",
+                self.static_size, self.static_alignment
+            );
+            self.static_closed = true;
+        }
+    }
+
+    /// Maps a fresh static TLS area: each static module's block initialized from its image, and
+    /// a TCB holding `stack_guard` at the thread pointer. Replaces the area of a previous run.
+    /// Returns the thread pointer.
+    pub(crate) fn allocate_static(&mut self, stack_guard: u64) -> Result<u64, DrowError> {
+        self.release_static();
+        let alignment = self.static_alignment;
+        let blocks_size = align_up(self.static_size, alignment);
+        // mmap aligns to pages, the padding covers larger alignments.
+        let length = (blocks_size + alignment + TCB_SIZE) as usize;
+        let address = syscall::mmap_checked(
+            ptr::null(),
+            length,
+            libc::PROT_READ | libc::PROT_WRITE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+        .map_err(|errno| DrowError::MapFailed {
+            address: 0,
+            length: length as u64,
+            source: errno.into(),
+        })?;
+        let thread_pointer = align_up(address as u64 + blocks_size, alignment);
+        unsafe {
+            ptr::write((thread_pointer + TCB_SELF) as *mut u64, thread_pointer);
+            ptr::write(
+                (thread_pointer + TCB_HEADER_SELF) as *mut u64,
+                thread_pointer,
+            );
+            ptr::write((thread_pointer + TCB_STACK_GUARD) as *mut u64, stack_guard);
+        }
+        let mut dtv = dynamic_thread_vector();
+        for module in self.modules.iter() {
+            if let Some(offset) = module.static_offset {
+                let block = thread_pointer - offset;
+                unsafe {
+                    ptr::copy_nonoverlapping(
+                        module.image_address as *const u8,
+                        block as *mut u8,
+                        module.segment.file_size as usize,
+                    );
+                }
+                if let Some(slot) = dtv.get_mut(&module.id) {
+                    slot.place(block);
+                }
+            }
+        }
+        debug!(
+            "Static TLS area at {:#X}, thread pointer {:#X}",
+            address as u64, thread_pointer
+        );
+        self.static_area = Some(StaticTlsArea {
+            address,
+            length,
+            thread_pointer,
+        });
+        Ok(thread_pointer)
+    }
+
+    /// Unmaps the static TLS area, returning its size. The blocks of the static modules are no
+    /// longer reachable through the DTV.
+    pub(crate) fn release_static(&mut self) -> u64 {
+        let area = match self.static_area.take() {
+            Some(area) => area,
+            None => return 0,
+        };
+        let mut dtv = dynamic_thread_vector();
+        for module in self.modules.iter().filter(|module| !module.dynamic()) {
+            if let Some(slot) = dtv.get_mut(&module.id) {
+                slot.free();
+            }
+        }
+        area.length() as u64
+    }
+
+    pub fn static_area(&self) -> Option<&StaticTlsArea> {
+        self.static_area.as_ref()
+    }
+
+    /// Drops the module of an unloaded object, with the symbols it defined.
+    pub(crate) fn release(&mut self, object: &str) {
+        if let Some(index) = self
+            .modules
+            .iter()
+            .position(|module| module.segment.object == object)
+        {
+            let module = self.modules.remove(index);
+            self.symbols.retain(|_, (id, _)| *id != module.id);
+            dynamic_thread_vector().remove(&module.id);
+            self.generation += 1;
+        }
+    }
+
+    pub fn modules(&self) -> &[TlsModule] {
+        &self.modules
+    }
+
+    pub fn module(&self, id: usize) -> Option<&TlsModule> {
+        self.modules.iter().find(|module| module.id == id)
+    }
+
+    pub fn module_of(&self, object: &str) -> Option<&TlsModule> {
+        self.modules
+            .iter()
+            .find(|module| module.segment.object == object)
+    }
+
+    /// The module defining the thread-local symbol `name` and the symbol's offset in its block.
+    pub fn symbol(&self, name: &str) -> Option<(&TlsModule, u64)> {
+        let name = symbol_name::base_name(name);
+        self.symbols
+            .get(name)
+            .and_then(|(id, offset)| self.module(*id).map(|module| (module, *offset)))
+    }
+
+    pub fn static_size(&self) -> u64 {
+        self.static_size
+    }
+
+    pub fn static_alignment(&self) -> u64 {
+        self.static_alignment
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// The highest module ID handed out, the number of DTV slots a thread needs.
+    pub fn max_id(&self) -> usize {
+        self.max_id
+    }
+}
+
+impl Default for TlsRegistry {
+    fn default() -> TlsRegistry {
+        TlsRegistry::new()
+    }
+}
+
+impl Drop for TlsRegistry {
+    fn drop(&mut self) {
+        let mut dtv = dynamic_thread_vector();
+        for module in self.modules.iter() {
+            dtv.remove(&module.id);
+        }
+    }
+}
@@ -0,0 +1,453 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use crate::error::DrowError;
+use crate::offset_reader::{read_segment, OffsetReader};
+use crate::string_tables::StringTable;
+use crate::{Elf64Metadata, Elf64SectionHeader};
+
+pub const ELF64_SECTION_HEADER_VERSION_DEFINITIONS: u32 = 0x6ffffffd;
+pub const ELF64_SECTION_HEADER_VERSION_NEEDS: u32 = 0x6ffffffe;
+pub const ELF64_SECTION_HEADER_VERSION_SYMBOLS: u32 = 0x6fffffff;
+
+const VERSION_INDEX_MASK: u16 = 0x7fff;
+const VERSION_HIDDEN: u16 = 0x8000;
+const VERSION_INDEX_GLOBAL: u16 = 1;
+
+#[derive(Clone, Debug)]
+pub struct SymbolVersion {
+    pub name: String,
+    pub hidden: bool,
+}
+
+fn field(
+    content: &[u8],
+    at: usize,
+    size: usize,
+    section: &Elf64SectionHeader,
+) -> Result<u64, DrowError> {
+    let bytes = content
+        .get(at..at + size)
+        .ok_or_else(|| DrowError::Malformed {
+            what: String::from("version entry extends past its section"),
+            offset: Some(section.sh_offset + at as u64),
+        })?;
+    Ok(bytes
+        .iter()
+        .rev()
+        .fold(0, |value, byte| (value << 8) | *byte as u64))
+}
+
+fn version_name(table: &StringTable, offset: u64) -> Result<String, DrowError> {
+    table
+        .get_lossy(offset as u32)
+        .map(|name| name.into_owned())
+        .map_err(|source| DrowError::InvalidString {
+            what: String::from("version name"),
+            source,
+        })
+}
+
+/// Walks a chain of `Elf64_Verdef` or `Elf64_Verneed` entries. `entry` reads one entry at the
+/// given offset and returns the offset of the next one, or zero at the end of the chain.
+fn walk(
+    content: &[u8],
+    mut entry: impl FnMut(usize) -> Result<u64, DrowError>,
+) -> Result<(), DrowError> {
+    let mut position = 0;
+    let mut remaining = content.len();
+    while remaining > 0 {
+        let next = entry(position)?;
+        if next == 0 {
+            break;
+        }
+        position += next as usize;
+        remaining -= 1;
+    }
+    Ok(())
+}
+
+fn load_names<T: Read + Seek>(
+    elf_metadata: &Elf64Metadata,
+    section: &Elf64SectionHeader,
+    reader: &mut T,
+    names: &mut HashMap<u16, String>,
+) -> Result<(), DrowError> {
+    let content = read_segment(reader, section.sh_offset, section.sh_size)?;
+    let string_section = elf_metadata
+        .section_headers
+        .get(section.sh_link as usize)
+        .ok_or_else(|| DrowError::Malformed {
+            what: format!(
+                "version string table section {} does not exist",
+                section.sh_link
+            ),
+            offset: None,
+        })?;
+    let table = StringTable::load(string_section, reader)?;
+    if section.sh_type == ELF64_SECTION_HEADER_VERSION_DEFINITIONS {
+        walk(&content, |at| {
+            let index = field(&content, at + 4, 2, section)? as u16;
+            let auxiliary = field(&content, at + 12, 4, section)? as usize;
+            let name = field(&content, at + auxiliary, 4, section)?;
+            names.insert(index & VERSION_INDEX_MASK, version_name(&table, name)?);
+            field(&content, at + 16, 4, section)
+        })
+    } else {
+        for (index, _, name) in needs(&content, section, &table)? {
+            names.insert(index, name);
+        }
+        Ok(())
+    }
+}
+
+/// The index, file and name of each version an `Elf64_Verneed` chain requires.
+fn needs(
+    content: &[u8],
+    section: &Elf64SectionHeader,
+    table: &StringTable,
+) -> Result<Vec<(u16, String, String)>, DrowError> {
+    let mut result = Vec::new();
+    walk(content, |at| {
+        let count = field(content, at + 2, 2, section)?;
+        let file = version_name(table, field(content, at + 4, 4, section)?)?;
+        let mut auxiliary = at + field(content, at + 8, 4, section)? as usize;
+        for _ in 0..count {
+            let index = field(content, auxiliary + 6, 2, section)? as u16;
+            let name = field(content, auxiliary + 8, 4, section)?;
+            result.push((
+                index & VERSION_INDEX_MASK,
+                file.clone(),
+                version_name(table, name)?,
+            ));
+            match field(content, auxiliary + 12, 4, section)? {
+                0 => break,
+                next => auxiliary += next as usize,
+            }
+        }
+        field(content, at + 12, 4, section)
+    })?;
+    Ok(result)
+}
+
+/// Names of the versions the file defines, ordered by version index. The first one is the name
+/// of the file itself.
+pub fn version_definitions<T: Read + Seek>(
+    elf_metadata: &Elf64Metadata,
+    reader: &mut T,
+) -> Result<Vec<String>, DrowError> {
+    let mut names = HashMap::new();
+    for section in elf_metadata
+        .section_headers
+        .iter()
+        .filter(|section| section.sh_type == ELF64_SECTION_HEADER_VERSION_DEFINITIONS)
+    {
+        load_names(elf_metadata, section, reader, &mut names)?;
+    }
+    let mut names: Vec<(u16, String)> = names.into_iter().collect();
+    names.sort_unstable();
+    Ok(names.into_iter().map(|(_, name)| name).collect())
+}
+
+/// The version of each entry of the dynamic symbol table, from `.gnu.version` and the version
+/// definitions and requirements it refers to. Local and unversioned symbols have none.
+pub fn dynamic_symbol_versions<T: Read + Seek>(
+    elf_metadata: &Elf64Metadata,
+    reader: &mut T,
+) -> Result<Vec<Option<SymbolVersion>>, DrowError> {
+    let mut result = vec![None; elf_metadata.dynamic_symbol_table.len()];
+    let symbols = match elf_metadata
+        .section_headers
+        .iter()
+        .find(|section| section.sh_type == ELF64_SECTION_HEADER_VERSION_SYMBOLS)
+    {
+        Some(section) => section,
+        None => return Ok(result),
+    };
+    let mut names = HashMap::new();
+    for section in elf_metadata.section_headers.iter().filter(|section| {
+        section.sh_type == ELF64_SECTION_HEADER_VERSION_DEFINITIONS
+            || section.sh_type == ELF64_SECTION_HEADER_VERSION_NEEDS
+    }) {
+        load_names(elf_metadata, section, reader, &mut names)?;
+    }
+    let content = read_segment(reader, symbols.sh_offset, symbols.sh_size)?;
+    for (index, version) in result.iter_mut().enumerate() {
+        let value = field(&content, index * 2, 2, symbols)? as u16;
+        let version_index = value & VERSION_INDEX_MASK;
+        if version_index <= VERSION_INDEX_GLOBAL {
+            continue;
+        }
+        *version = names.get(&version_index).map(|name| SymbolVersion {
+            name: name.clone(),
+            hidden: value & VERSION_HIDDEN != 0,
+        });
+    }
+    Ok(result)
+}
+
+/// `dynamic_symbol_versions` of the file `elf_metadata` was read from, empty when it has no
+/// `.gnu.version` or cannot be read again.
+pub fn read_dynamic_symbol_versions(elf_metadata: &Elf64Metadata) -> Vec<Option<SymbolVersion>> {
+    if !elf_metadata
+        .section_headers
+        .iter()
+        .any(|section| section.sh_type == ELF64_SECTION_HEADER_VERSION_SYMBOLS)
+    {
+        return Vec::new();
+    }
+    match OffsetReader::open(&elf_metadata.file_path)
+        .map_err(|source| DrowError::Io {
+            path: elf_metadata.file_path.clone(),
+            source,
+        })
+        .and_then(|mut reader| dynamic_symbol_versions(elf_metadata, &mut reader))
+    {
+        Ok(versions) => versions,
+        Err(err) => {
+            debug!(
+                "Not reading the symbol versions of {}: {}",
+                elf_metadata.file_path, err
+            );
+            Vec::new()
+        }
+    }
+}
+
+/// A version a file requires from one of its dependencies, with the undefined dynamic symbols
+/// that reference it.
+#[derive(Clone, Debug)]
+pub struct VersionRequirement {
+    /// The dependency, as named by the requirement.
+    pub file: String,
+    pub version: String,
+    pub symbols: Vec<String>,
+}
+
+/// The versions the file requires, in the order of its `.gnu.version_r` section.
+pub fn version_requirements<T: Read + Seek>(
+    elf_metadata: &Elf64Metadata,
+    reader: &mut T,
+) -> Result<Vec<VersionRequirement>, DrowError> {
+    let mut requirements = Vec::new();
+    let mut indexes = Vec::new();
+    for section in elf_metadata
+        .section_headers
+        .iter()
+        .filter(|section| section.sh_type == ELF64_SECTION_HEADER_VERSION_NEEDS)
+    {
+        let content = read_segment(reader, section.sh_offset, section.sh_size)?;
+        let string_section = elf_metadata
+            .section_headers
+            .get(section.sh_link as usize)
+            .ok_or_else(|| DrowError::Malformed {
+                what: format!(
+                    "version string table section {} does not exist",
+                    section.sh_link
+                ),
+                offset: None,
+            })?;
+        let table = StringTable::load(string_section, reader)?;
+        for (index, file, version) in needs(&content, section, &table)? {
+            indexes.push(index);
+            requirements.push(VersionRequirement {
+                file,
+                version,
+                symbols: Vec::new(),
+            });
+        }
+    }
+    let symbols = match elf_metadata
+        .section_headers
+        .iter()
+        .find(|section| section.sh_type == ELF64_SECTION_HEADER_VERSION_SYMBOLS)
+    {
+        Some(section) => section,
+        None => return Ok(requirements),
+    };
+    let content = read_segment(reader, symbols.sh_offset, symbols.sh_size)?;
+    for (index, symbol) in elf_metadata.dynamic_symbol_table.iter().enumerate() {
+        if !symbol.undefined() || symbol.symbol_name.is_empty() {
+            continue;
+        }
+        let version_index = field(&content, index * 2, 2, symbols)? as u16 & VERSION_INDEX_MASK;
+        if let Some(position) = indexes.iter().position(|index| *index == version_index) {
+            requirements[position]
+                .symbols
+                .push(symbol.symbol_name.clone());
+        }
+    }
+    Ok(requirements)
+}
+
+/// Orders version names like `GLIBC_2.9` and `GLIBC_2.34` by the numbers after the prefix.
+fn version_key(name: &str) -> (&str, Vec<u64>) {
+    let split = name
+        .find(|c: char| c.is_ascii_digit())
+        .unwrap_or(name.len());
+    let (prefix, numbers) = name.split_at(split);
+    (
+        prefix,
+        numbers
+            .split(|c: char| !c.is_ascii_digit())
+            .filter_map(|number| number.parse().ok())
+            .collect(),
+    )
+}
+
+/// The newest of `available` that is older than `version` and shares its prefix.
+pub fn closest_older<'a>(
+    version: &str,
+    available: impl Iterator<Item = &'a str>,
+) -> Option<&'a str> {
+    let required = version_key(version);
+    available
+        .map(|name| (version_key(name), name))
+        .filter(|(key, _)| key.0 == required.0 && key.1 < required.1)
+        .max_by(|(left, _), (right, _)| left.1.cmp(&right.1))
+        .map(|(_, name)| name)
+}
+
+/// A symbol bound to an older version of itself because the version it requires is missing.
+#[derive(Clone, Debug)]
+pub struct VersionFallback {
+    pub symbol: String,
+    pub version: String,
+    /// The value of the older definition in the provider, before relocation.
+    pub value: u64,
+}
+
+/// A version required from a dependency that the object found for the dependency does not
+/// define, as when a file built against a newer glibc meets the glibc of the host.
+#[derive(Clone, Debug)]
+pub struct MissingVersion {
+    pub requester: String,
+    /// The dependency, as named by the requirement.
+    pub library: String,
+    /// The path of the object found for `library`.
+    pub provider: String,
+    pub version: String,
+    pub symbols: Vec<String>,
+    /// The newest older version of each symbol that the provider defines one for.
+    pub fallbacks: Vec<VersionFallback>,
+}
+
+impl MissingVersion {
+    pub fn describe(&self) -> String {
+        format!(
+            "{} requires {} from {}, which {} does not define, for {}",
+            self.requester,
+            self.version,
+            self.library,
+            self.provider,
+            if self.symbols.is_empty() {
+                String::from("no symbol")
+            } else {
+                self.symbols.join(", ")
+            }
+        )
+    }
+}
+
+/// The version definitions and versioned definitions of a provider.
+struct Provider {
+    definitions: Vec<String>,
+    symbols: Vec<(String, String, u64)>,
+}
+
+impl Provider {
+    fn load(elf_metadata: &Elf64Metadata) -> Result<Provider, DrowError> {
+        let mut reader =
+            OffsetReader::open(&elf_metadata.file_path).map_err(|source| DrowError::Io {
+                path: elf_metadata.file_path.clone(),
+                source,
+            })?;
+        let definitions = version_definitions(elf_metadata, &mut reader)?;
+        let symbols = elf_metadata
+            .dynamic_symbol_table
+            .iter()
+            .filter(|symbol| !symbol.undefined())
+            .filter_map(|symbol| {
+                let version = symbol.version.clone()?;
+                Some((symbol.symbol_name.clone(), version, symbol.value))
+            })
+            .collect();
+        Ok(Provider {
+            definitions,
+            symbols,
+        })
+    }
+}
+
+/// The versions `objects` require from each other that are missing. Requirements on objects
+/// that are not in `objects` or define no versions at all are not checked, nor are providers
+/// whose files cannot be read again.
+pub fn missing_versions(objects: &[&Elf64Metadata]) -> Vec<MissingVersion> {
+    let mut providers: HashMap<String, Option<Provider>> = HashMap::new();
+    let mut missing = Vec::new();
+    for requester in objects.iter() {
+        for requirement in requester.version_requirements.iter().cloned() {
+            let found = objects.iter().find(|object| {
+                object.dynamic.soname.as_ref() == Some(&requirement.file)
+                    || Path::new(&object.file_path).file_name()
+                        == Some(OsStr::new(&requirement.file))
+            });
+            let found = match found {
+                Some(found) => found,
+                None => continue,
+            };
+            let provider = providers.entry(found.file_path.clone()).or_insert_with(|| {
+                match Provider::load(found) {
+                    Ok(provider) => Some(provider),
+                    Err(err) => {
+                        debug!(
+                            "Not checking the versions {} defines: {}",
+                            found.file_path, err
+                        );
+                        None
+                    }
+                }
+            });
+            let provider = match provider {
+                Some(provider) if !provider.definitions.is_empty() => provider,
+                _ => continue,
+            };
+            if provider.definitions.contains(&requirement.version) {
+                continue;
+            }
+            let fallbacks = requirement
+                .symbols
+                .iter()
+                .filter_map(|symbol| {
+                    let definitions = provider
+                        .symbols
+                        .iter()
+                        .filter(|(name, _, _)| name == symbol);
+                    let version = closest_older(
+                        &requirement.version,
+                        definitions.clone().map(|(_, version, _)| version.as_str()),
+                    )?;
+                    definitions
+                        .clone()
+                        .find(|(_, candidate, _)| candidate == version)
+                        .map(|(_, version, value)| VersionFallback {
+                            symbol: symbol.clone(),
+                            version: version.clone(),
+                            value: *value,
+                        })
+                })
+                .collect();
+            missing.push(MissingVersion {
+                requester: requester.file_path.clone(),
+                library: requirement.file,
+                provider: found.file_path.clone(),
+                version: requirement.version,
+                symbols: requirement.symbols,
+                fallbacks,
+            });
+        }
+    }
+    missing
+}
@@ -0,0 +1,438 @@
+use std::io::{self, Read, Seek, Write};
+use std::mem::size_of;
+
+use crate::error::DrowError;
+use crate::offset_reader::read_segment;
+use crate::string_tables::StringTable;
+use crate::{
+    entry_bytes, Elf64Header, Elf64Metadata, Elf64ProgramHeader, Elf64SectionHeader,
+    ELF64_SECTION_HEADER_DYNAMIC, ELF64_SECTION_HEADER_DYNAMIC_SYMBOL_TABLE,
+    ELF64_SECTION_HEADER_NO_BITS, ELF64_SECTION_HEADER_RELOCATION,
+    ELF64_SECTION_HEADER_RELOCATION_ADDEND, ELF64_SECTION_HEADER_SYMBOL_TABLE,
+    PROGRAM_HEADER_TYPE_LOADABLE,
+};
+
+const SECTION_FLAG_INFO_LINK: u64 = 0x40;
+
+const DYNAMIC_TABLE_NULL: u64 = 0;
+const DYNAMIC_TABLE_STRING_TABLE: u64 = 5;
+const DYNAMIC_TABLE_STRING_TABLE_SIZE: u64 = 10;
+const DYNAMIC_TABLE_RPATH: u64 = 15;
+const DYNAMIC_TABLE_RUNPATH: u64 = 29;
+
+const SYMBOL_ENTRY_SIZE: usize = 24;
+const SYMBOL_SECTION_INDEX_OFFSET: usize = 6;
+const SHN_LORESERVE: u16 = 0xff00;
+
+/// File content of every program header and section of a parsed file, indexed like
+/// `Elf64Metadata::program_headers` and `Elf64Metadata::section_headers`.
+pub struct SectionData {
+    segments: Vec<Vec<u8>>,
+    sections: Vec<Vec<u8>>,
+}
+
+impl SectionData {
+    pub fn load<T: Read + Seek>(
+        elf_metadata: &Elf64Metadata,
+        reader: &mut T,
+    ) -> Result<SectionData, DrowError> {
+        let segments = elf_metadata
+            .program_headers
+            .iter()
+            .map(|header| read_segment(reader, header.p_offset, header.p_file_size))
+            .collect::<Result<Vec<Vec<u8>>, DrowError>>()?;
+        let sections = elf_metadata
+            .section_headers
+            .iter()
+            .map(|header| match header.sh_type {
+                ELF64_SECTION_HEADER_NO_BITS => Ok(Vec::new()),
+                _ => read_segment(reader, header.sh_offset, header.sh_size),
+            })
+            .collect::<Result<Vec<Vec<u8>>, DrowError>>()?;
+        Ok(SectionData { segments, sections })
+    }
+
+    pub fn segment(&self, index: usize) -> &[u8] {
+        &self.segments[index]
+    }
+
+    pub fn section(&self, index: usize) -> &[u8] {
+        &self.sections[index]
+    }
+
+    pub fn section_mut(&mut self, index: usize) -> &mut Vec<u8> {
+        &mut self.sections[index]
+    }
+}
+
+fn align(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment.max(1)) * alignment.max(1)
+}
+
+fn place(file: &mut [u8], offset: u64, content: &[u8]) {
+    let offset = offset as usize;
+    file[offset..offset + content.len()].copy_from_slice(content);
+}
+
+pub struct Elf64Writer;
+
+impl Elf64Writer {
+    /// Writes `elf_metadata` back as a file. Segments and allocated sections keep their file
+    /// offsets, so the result maps like the original, while the other sections are laid out after
+    /// them with new offsets, followed by the section header table.
+    pub fn write(
+        elf_metadata: &Elf64Metadata,
+        sections_data: &SectionData,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        let elf_header = &elf_metadata.elf_header;
+        let program_headers_end = elf_header.e_program_header_offset
+            + (elf_metadata.program_headers.len() * size_of::<Elf64ProgramHeader>()) as u64;
+        let segments_end = elf_metadata
+            .program_headers
+            .iter()
+            .map(|header| header.p_offset + header.p_file_size);
+        let allocated_end = elf_metadata
+            .section_headers
+            .iter()
+            .filter(|header| header.allocated_in_memory())
+            .filter(|header| header.sh_type != ELF64_SECTION_HEADER_NO_BITS)
+            .map(|header| header.sh_offset + header.sh_size);
+        let mut end = segments_end.chain(allocated_end).fold(
+            program_headers_end.max(size_of::<Elf64Header>() as u64),
+            u64::max,
+        );
+        let mut section_headers = elf_metadata.section_headers.clone();
+        for header in section_headers.iter_mut().skip(1) {
+            if header.allocated_in_memory() {
+                continue;
+            }
+            end = align(end, header.sh_address_align);
+            header.sh_offset = end;
+            if header.sh_type != ELF64_SECTION_HEADER_NO_BITS {
+                end += header.sh_size;
+            }
+        }
+        let section_headers_offset = align(end, 8);
+        let mut file = vec![
+            0u8;
+            section_headers_offset as usize
+                + section_headers.len() * size_of::<Elf64SectionHeader>()
+        ];
+        for (index, header) in elf_metadata.program_headers.iter().enumerate() {
+            place(&mut file, header.p_offset, sections_data.segment(index));
+        }
+        for (index, header) in section_headers.iter().enumerate().skip(1) {
+            if header.sh_type != ELF64_SECTION_HEADER_NO_BITS {
+                place(&mut file, header.sh_offset, sections_data.section(index));
+            }
+        }
+        let mut elf_header = elf_header.clone();
+        elf_header.e_program_header_entry_size = size_of::<Elf64ProgramHeader>() as u16;
+        elf_header.e_program_header_entries = elf_metadata.program_headers.len() as u16;
+        elf_header.e_section_header_entry_size = size_of::<Elf64SectionHeader>() as u16;
+        elf_header.e_section_header_offset = if section_headers.is_empty() {
+            0
+        } else {
+            section_headers_offset
+        };
+        elf_header.e_section_header_entries = section_headers.len() as u16;
+        place(&mut file, 0, entry_bytes(&elf_header));
+        for (index, header) in elf_metadata.program_headers.iter().enumerate() {
+            let offset = elf_header.e_program_header_offset
+                + (index * size_of::<Elf64ProgramHeader>()) as u64;
+            place(&mut file, offset, entry_bytes(header));
+        }
+        for (index, header) in section_headers.iter().enumerate() {
+            let offset = section_headers_offset + (index * size_of::<Elf64SectionHeader>()) as u64;
+            place(&mut file, offset, entry_bytes(header));
+        }
+        out.write_all(&file)
+    }
+}
+
+fn edit_error(elf_metadata: &Elf64Metadata, reason: String) -> DrowError {
+    DrowError::Edit {
+        path: elf_metadata.file_path.clone(),
+        reason,
+    }
+}
+
+fn section_index(
+    elf_metadata: &Elf64Metadata,
+    sections_data: &SectionData,
+    name: &str,
+) -> Option<usize> {
+    let names = StringTable::from_bytes(
+        sections_data.section(elf_metadata.elf_header.e_section_name_string_table_index as usize),
+    );
+    elf_metadata
+        .section_headers
+        .iter()
+        .position(|header| names.get(header.sh_name).ok() == Some(name))
+}
+
+/// Index after removing section `removed`, or 0 for references to it.
+fn shifted(index: u32, removed: u32) -> u32 {
+    match index {
+        index if index == removed => 0,
+        index if index > removed => index - 1,
+        index => index,
+    }
+}
+
+/// Drops the section called `name` from the section header table. Only sections that are not
+/// mapped at run time can be removed, and not while another section links to them.
+pub fn remove_section(
+    elf_metadata: &mut Elf64Metadata,
+    sections_data: &mut SectionData,
+    name: &str,
+) -> Result<(), DrowError> {
+    let index = section_index(elf_metadata, sections_data, name)
+        .filter(|index| *index != 0)
+        .ok_or_else(|| edit_error(elf_metadata, format!("no section named {}", name)))?;
+    let removed = index as u32;
+    if elf_metadata.section_headers[index].allocated_in_memory() {
+        return Err(edit_error(
+            elf_metadata,
+            format!("{} is mapped at run time and cannot be removed", name),
+        ));
+    }
+    if index == elf_metadata.elf_header.e_section_name_string_table_index as usize {
+        return Err(edit_error(
+            elf_metadata,
+            format!("{} holds the section names", name),
+        ));
+    }
+    if let Some(user) = section_index_linking_to(elf_metadata, removed) {
+        let user_name = StringTable::from_bytes(
+            sections_data
+                .section(elf_metadata.elf_header.e_section_name_string_table_index as usize),
+        )
+        .get_lossy(elf_metadata.section_headers[user].sh_name)
+        .map(|name| name.into_owned())
+        .unwrap_or_else(|_| user.to_string());
+        return Err(edit_error(
+            elf_metadata,
+            format!("{} links to {}, remove it first", user_name, name),
+        ));
+    }
+    elf_metadata.section_headers.remove(index);
+    sections_data.sections.remove(index);
+    let header = &mut elf_metadata.elf_header;
+    header.e_section_name_string_table_index =
+        shifted(header.e_section_name_string_table_index as u32, removed) as u16;
+    header.e_section_header_entries = elf_metadata.section_headers.len() as u16;
+    for (position, section) in elf_metadata.section_headers.iter_mut().enumerate() {
+        section.sh_link = shifted(section.sh_link, removed);
+        if section.sh_type == ELF64_SECTION_HEADER_RELOCATION_ADDEND
+            || section.sh_type == ELF64_SECTION_HEADER_RELOCATION
+            || section.sh_flags & SECTION_FLAG_INFO_LINK != 0
+        {
+            section.sh_info = shifted(section.sh_info, removed);
+        }
+        if section.sh_type == ELF64_SECTION_HEADER_SYMBOL_TABLE
+            || section.sh_type == ELF64_SECTION_HEADER_DYNAMIC_SYMBOL_TABLE
+        {
+            shift_symbol_sections(&mut sections_data.sections[position], removed);
+        }
+    }
+    Ok(())
+}
+
+fn section_index_linking_to(elf_metadata: &Elf64Metadata, target: u32) -> Option<usize> {
+    elf_metadata
+        .section_headers
+        .iter()
+        .position(|section| section.sh_type != 0 && section.sh_link == target)
+}
+
+fn shift_symbol_sections(symbols: &mut [u8], removed: u32) {
+    for symbol in symbols.chunks_exact_mut(SYMBOL_ENTRY_SIZE) {
+        let field = &mut symbol[SYMBOL_SECTION_INDEX_OFFSET..SYMBOL_SECTION_INDEX_OFFSET + 2];
+        let section = u16::from_le_bytes([field[0], field[1]]);
+        if section != 0 && section < SHN_LORESERVE {
+            field.copy_from_slice(&(shifted(section as u32, removed) as u16).to_le_bytes());
+        }
+    }
+}
+
+fn dynamic_entries(content: &[u8]) -> Vec<(u64, u64)> {
+    content
+        .chunks_exact(16)
+        .map(|entry| {
+            let mut tag = [0u8; 8];
+            let mut value = [0u8; 8];
+            tag.copy_from_slice(&entry[..8]);
+            value.copy_from_slice(&entry[8..]);
+            (u64::from_le_bytes(tag), u64::from_le_bytes(value))
+        })
+        .collect()
+}
+
+fn dynamic_content(entries: &[(u64, u64)]) -> Vec<u8> {
+    let mut content = Vec::with_capacity(entries.len() * 16);
+    for (tag, value) in entries.iter() {
+        content.extend_from_slice(&tag.to_le_bytes());
+        content.extend_from_slice(&value.to_le_bytes());
+    }
+    content
+}
+
+/// Moves the dynamic string table to the end of the loadable segment holding it, with `extra`
+/// appended, when nothing follows it in the file or in memory. Returns the offset of `extra` in
+/// the moved table.
+fn grow_dynamic_strings(
+    elf_metadata: &mut Elf64Metadata,
+    sections_data: &mut SectionData,
+    string_table: usize,
+    extra: &[u8],
+) -> Result<u64, DrowError> {
+    let strings = elf_metadata.section_headers[string_table].clone();
+    let segment = elf_metadata
+        .program_headers
+        .iter()
+        .position(|header| {
+            header.p_type == PROGRAM_HEADER_TYPE_LOADABLE
+                && header.p_offset <= strings.sh_offset
+                && strings.sh_offset + strings.sh_size <= header.p_offset + header.p_file_size
+        })
+        .ok_or_else(|| {
+            edit_error(
+                elf_metadata,
+                String::from("the dynamic string table is not in a loadable segment"),
+            )
+        })?;
+    let holder = elf_metadata.program_headers[segment].clone();
+    let offset = holder.p_offset + holder.p_file_size;
+    let address = holder.p_virtual_address + holder.p_file_size;
+    let mut content = sections_data.section(string_table).to_vec();
+    let extra_offset = content.len() as u64;
+    content.extend_from_slice(extra);
+    let size = content.len() as u64;
+    let overlaps = |start: u64, end: u64, other_start: u64, other_end: u64| {
+        start < other_end && other_start < end
+    };
+    let blocked = holder.p_memory_size != holder.p_file_size
+        || elf_metadata
+            .program_headers
+            .iter()
+            .enumerate()
+            .filter(|(index, header)| {
+                *index != segment && header.p_type == PROGRAM_HEADER_TYPE_LOADABLE
+            })
+            .any(|(_, header)| {
+                overlaps(
+                    offset,
+                    offset + size,
+                    header.p_offset,
+                    header.p_offset + header.p_file_size,
+                ) || overlaps(
+                    address,
+                    address + size,
+                    header.p_virtual_address,
+                    header.p_virtual_address + header.p_memory_size,
+                )
+            })
+        || elf_metadata
+            .section_headers
+            .iter()
+            .filter(|header| header.allocated_in_memory())
+            .any(|header| {
+                overlaps(
+                    address,
+                    address + size,
+                    header.sh_virtual_address,
+                    header.sh_virtual_address + header.sh_size,
+                )
+            });
+    if blocked {
+        return Err(edit_error(
+            elf_metadata,
+            String::from("no room to grow the dynamic string table"),
+        ));
+    }
+    let holder = &mut elf_metadata.program_headers[segment];
+    holder.p_file_size += size;
+    holder.p_memory_size += size;
+    let header = &mut elf_metadata.section_headers[string_table];
+    header.sh_offset = offset;
+    header.sh_virtual_address = address;
+    header.sh_size = size;
+    *sections_data.section_mut(string_table) = content;
+    Ok(extra_offset)
+}
+
+/// Points DT_RUNPATH, or an existing DT_RPATH, at `rpath`. The string is rewritten in place when
+/// it fits, otherwise the dynamic string table is moved to make room, and a new entry takes one
+/// of the spare DT_NULL slots the link editor leaves at the end of the dynamic section.
+pub fn set_rpath(
+    elf_metadata: &mut Elf64Metadata,
+    sections_data: &mut SectionData,
+    rpath: &str,
+) -> Result<(), DrowError> {
+    let dynamic = elf_metadata
+        .section_headers
+        .iter()
+        .position(|header| header.sh_type == ELF64_SECTION_HEADER_DYNAMIC)
+        .ok_or_else(|| edit_error(elf_metadata, String::from("no dynamic section")))?;
+    let string_table = elf_metadata.section_headers[dynamic].sh_link as usize;
+    if string_table == 0 || string_table >= elf_metadata.section_headers.len() {
+        return Err(edit_error(
+            elf_metadata,
+            String::from("the dynamic section has no string table"),
+        ));
+    }
+    let mut entries = dynamic_entries(sections_data.section(dynamic));
+    let terminator = entries
+        .iter()
+        .position(|(tag, _)| *tag == DYNAMIC_TABLE_NULL)
+        .unwrap_or(entries.len());
+    let existing = entries[..terminator]
+        .iter()
+        .position(|(tag, _)| *tag == DYNAMIC_TABLE_RPATH || *tag == DYNAMIC_TABLE_RUNPATH);
+    let slot = match existing {
+        Some(slot) => slot,
+        None if terminator + 1 < entries.len() => {
+            entries[terminator] = (DYNAMIC_TABLE_RUNPATH, 0);
+            terminator
+        }
+        None => {
+            return Err(edit_error(
+                elf_metadata,
+                String::from("no spare entry in the dynamic section for DT_RUNPATH"),
+            ))
+        }
+    };
+    let mut value = rpath.as_bytes().to_vec();
+    value.push(0);
+    let old_length = existing.and_then(|slot| {
+        let strings = sections_data.section(string_table);
+        let start = entries[slot].1 as usize;
+        strings
+            .get(start..)
+            .and_then(|tail| tail.iter().position(|byte| *byte == 0))
+    });
+    match old_length {
+        Some(length) if length + 1 >= value.len() => {
+            let start = entries[slot].1 as usize;
+            let strings = sections_data.section_mut(string_table);
+            value.resize(length + 1, 0);
+            strings[start..start + value.len()].copy_from_slice(&value);
+        }
+        _ => {
+            let offset = grow_dynamic_strings(elf_metadata, sections_data, string_table, &value)?;
+            let strings = &elf_metadata.section_headers[string_table];
+            for entry in entries.iter_mut() {
+                match entry.0 {
+                    DYNAMIC_TABLE_STRING_TABLE => entry.1 = strings.sh_virtual_address,
+                    DYNAMIC_TABLE_STRING_TABLE_SIZE => entry.1 = strings.sh_size,
+                    _ => {}
+                }
+            }
+            entries[slot].1 = offset;
+        }
+    }
+    *sections_data.section_mut(dynamic) = dynamic_content(&entries);
+    Ok(())
+}
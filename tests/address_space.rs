@@ -0,0 +1,150 @@
+//! Base addresses handed out around the ranges the process has mapped already, given upfront
+//! or, for a loader, read from /proc/self/maps.
+
+mod common;
+
+use std::ptr;
+
+use common::{data_library, fixture_dir, object_base, write_fixture};
+use drow::address_space::{AddressSpace, LOW_WINDOW_END, USER_SPACE_END};
+use drow::loader::{Elf64Loader, LoadOptions};
+
+const PAGE_SIZE: u64 = 0x1000;
+
+/// drow, its heap, and a library just above.
+fn occupied() -> Vec<(u64, u64)> {
+    vec![(0x20000, 0x30000), (0x32000, 0x40000), (0x40000, 0x41000)]
+}
+
+fn reserve(space: &mut AddressSpace, size: u64) -> u64 {
+    space.reserve("libobject.so", 0, size, PAGE_SIZE).unwrap()
+}
+
+#[test]
+fn objects_are_placed_in_the_first_gap_they_fit() {
+    let mut space = AddressSpace::with_occupied(0x20000, PAGE_SIZE, occupied());
+    // The gap at 0x30000 fits two pages.
+    assert_eq!(reserve(&mut space, 0x2000), 0x30000);
+    assert_eq!(space.next(), 0x32000);
+    // The adjacent occupied ranges are one, 0x32000 to 0x41000.
+    assert_eq!(reserve(&mut space, 0x1000), 0x41000);
+    assert_eq!(reserve(&mut space, 0x3000), 0x42000);
+}
+
+#[test]
+fn an_object_too_large_for_a_gap_goes_past_it() {
+    let mut space = AddressSpace::with_occupied(0x20000, PAGE_SIZE, occupied());
+    assert_eq!(reserve(&mut space, 0x3000), 0x41000);
+    // Later objects start after it, the gap left behind stays unused.
+    assert_eq!(reserve(&mut space, 0x1000), 0x44000);
+}
+
+#[test]
+fn the_range_of_the_segments_is_what_must_be_free() {
+    // An object whose first segment is at 0x1000 may have its base inside an occupied range.
+    let mut space = AddressSpace::with_occupied(0x20000, PAGE_SIZE, vec![(0x20000, 0x31000)]);
+    let base = space.reserve("libobject.so", 0x1000, 0x3000, PAGE_SIZE);
+    assert_eq!(base.unwrap(), 0x30000);
+}
+
+#[test]
+fn bases_keep_the_alignment_of_the_segments() {
+    let mut space = AddressSpace::with_occupied(0x20000, PAGE_SIZE, occupied());
+    let base = space.reserve("libhuge.so", 0, 0x1000, 0x20_0000).unwrap();
+    assert_eq!(base, 0x20_0000);
+    // Both the first free address and the alignment, from a start that is neither.
+    let mut space = AddressSpace::with_occupied(0x20800, PAGE_SIZE, vec![(0x20_0000, 0x20_1000)]);
+    let base = space.reserve("libhuge.so", 0, 0x1000, 0x20_0000).unwrap();
+    assert_eq!(base, 0x40_0000);
+}
+
+#[test]
+fn objects_needing_low_addresses_stay_below_2_gib() {
+    let mut space =
+        AddressSpace::with_occupied(0x7f00_0000_0000, PAGE_SIZE, vec![(0x40_0000, 0x50_0000)]);
+    let base = space
+        .reserve_low("liblow.so", 0, 0x2000, PAGE_SIZE)
+        .unwrap();
+    assert_eq!(base, 0x50_0000);
+    assert_eq!(space.next(), 0x7f00_0000_0000);
+
+    let err = space
+        .reserve_low("libhuge.so", 0, LOW_WINDOW_END, PAGE_SIZE)
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Unable to load libhuge.so: no free range of 0x80000000 bytes between 0x502000 and \
+         0x80000000"
+    );
+}
+
+#[test]
+fn no_gap_large_enough_is_an_error() {
+    let mut space =
+        AddressSpace::with_occupied(0x20000, PAGE_SIZE, vec![(0x20000, USER_SPACE_END)]);
+    let err = space
+        .reserve("libobject.so", 0, PAGE_SIZE, PAGE_SIZE)
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Unable to load libobject.so: no free range of 0x1000 bytes above 0x20000"
+    );
+}
+
+#[test]
+fn a_fixed_base_must_not_overlap() {
+    let mut space = AddressSpace::with_occupied(0x20000, PAGE_SIZE, occupied());
+    let err = space
+        .reserve_at("libfixed.so", 0x2f000, 0, 0x2000)
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Unable to load libfixed.so: 0x2F000-0x31000 overlaps the mapping at 0x20000-0x30000"
+    );
+    assert_eq!(
+        space.reserve_at("libfixed.so", 0x30000, 0, 0x2000).unwrap(),
+        0x30000
+    );
+    // Now taken as well.
+    assert_eq!(reserve(&mut space, 0x1000), 0x41000);
+}
+
+#[test]
+fn a_loader_places_objects_around_what_is_mapped() {
+    let dir = fixture_dir("address-space-loader");
+    let path = write_fixture(
+        &dir,
+        "libplaced.so",
+        &data_library("value", &[0; 8], 8)
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    // A mapping of the process where the loader would otherwise start.
+    let length = 0x10000;
+    let occupied = unsafe {
+        libc::mmap(
+            ptr::null_mut(),
+            length,
+            libc::PROT_NONE,
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+    };
+    assert_ne!(occupied, libc::MAP_FAILED);
+    let start = occupied as u64;
+    let loader = Elf64Loader::builder()
+        .offline(&[dir.to_string_lossy().into_owned()])
+        .options(LoadOptions {
+            base_address: start,
+            ..LoadOptions::default()
+        })
+        .build()
+        .unwrap();
+    loader.load_library(&path).unwrap();
+    // The data segment at 0x1000 is the first mapped.
+    let data = object_base(&loader, &path) + 0x1000;
+    assert!(data >= start + length as u64, "{:#x} in {:#x}", data, start);
+    drop(loader);
+    unsafe { libc::munmap(occupied, length) };
+}
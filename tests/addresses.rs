@@ -0,0 +1,166 @@
+//! Which function contains an address: in the file, where functions and segments start and end,
+//! and in the loaded objects.
+
+mod common;
+
+use std::io::Cursor;
+
+use common::{data_library, fixture_dir, object_base, offline_loader, write_fixture};
+use drow::testutil::ElfBuilder;
+use drow::{
+    Elf64Metadata, PROGRAM_FLAG_EXECUTE, PROGRAM_FLAG_READ, PROGRAM_HEADER_TYPE_LOADABLE,
+    SECTION_FLAG_ALLOCATED, SECTION_FLAG_EXECUTABLE_INSTRUCTIONS, SYMBOL_BINDING_GLOBAL,
+    SYMBOL_TYPE_FUNCTION,
+};
+
+/// SHT_PROGBITS, as the builder takes section types.
+const SECTION_TYPE_PROGRAM_BITS: u32 = 1;
+
+/// Start and size of the executable segment, all of it functions.
+const TEXT: u64 = 0x2000;
+const TEXT_SIZE: u64 = 0x40;
+
+/// Section index of .text, after .data.
+const TEXT_SECTION: u16 = 2;
+
+fn function(builder: ElfBuilder, name: &str, value: u64, size: u64) -> ElfBuilder {
+    builder.add_symbol(
+        name,
+        SYMBOL_BINDING_GLOBAL,
+        SYMBOL_TYPE_FUNCTION,
+        TEXT_SECTION,
+        value,
+        size,
+    )
+}
+
+/// A library whose executable segment at `TEXT` holds `first`, 0x10 bytes, then `second` up to
+/// the end of the segment, `inner` inside `second`, and the empty `marker` at its start.
+fn library() -> ElfBuilder {
+    let builder = data_library("value", &[0; 8], 8)
+        .add_segment(
+            PROGRAM_HEADER_TYPE_LOADABLE,
+            PROGRAM_FLAG_READ | PROGRAM_FLAG_EXECUTE,
+            TEXT,
+            &[0xC3; TEXT_SIZE as usize],
+            TEXT_SIZE,
+        )
+        .add_segment_section(
+            ".text",
+            SECTION_TYPE_PROGRAM_BITS,
+            SECTION_FLAG_ALLOCATED | SECTION_FLAG_EXECUTABLE_INSTRUCTIONS,
+            TEXT,
+            TEXT_SIZE,
+        );
+    let builder = function(builder, "first", TEXT, 0x10);
+    let builder = function(builder, "second", TEXT + 0x10, TEXT_SIZE - 0x10);
+    let builder = function(builder, "inner", TEXT + 0x20, 8);
+    function(builder, "marker", TEXT, 0)
+}
+
+fn parse(bytes: &[u8]) -> Elf64Metadata {
+    Elf64Metadata::load(&String::from("fixture.so"), &mut Cursor::new(bytes)).unwrap()
+}
+
+#[test]
+fn a_function_contains_its_first_byte_but_not_the_one_past_its_end() {
+    let metadata = parse(&library().finalize());
+    let cases: &[(u64, Option<&str>)] = &[
+        (TEXT - 1, None),
+        // Not the empty `marker` starting there too.
+        (TEXT, Some("first")),
+        (TEXT + 0xF, Some("first")),
+        (TEXT + 0x10, Some("second")),
+        (TEXT + 0x1F, Some("second")),
+        // The function starting last wins.
+        (TEXT + 0x20, Some("inner")),
+        (TEXT + 0x27, Some("inner")),
+        (TEXT + 0x28, Some("second")),
+        // The last byte of the segment, then the first past it.
+        (TEXT + TEXT_SIZE - 1, Some("second")),
+        (TEXT + TEXT_SIZE, None),
+        // The data segment has no functions.
+        (0x1000, None),
+    ];
+    for (address, expected) in cases {
+        assert_eq!(
+            metadata.containing_function(*address),
+            *expected,
+            "{:#x}",
+            address
+        );
+    }
+    assert_eq!(metadata.describe_address(TEXT + 0x10), "<second>");
+    assert_eq!(metadata.describe_address(TEXT + TEXT_SIZE), "<unknown>");
+}
+
+#[test]
+fn names_sharing_a_start_are_a_tie() {
+    let metadata = parse(&function(library(), "alias", TEXT, 0x10).finalize());
+    assert_eq!(metadata.containing_function(TEXT), None);
+    assert_eq!(metadata.describe_address(TEXT + 0xF), "<unknown>");
+    // Past the shorter of the two, only the longer one remains.
+    let metadata = parse(&function(library(), "alias", TEXT, 0x8).finalize());
+    assert_eq!(metadata.containing_function(TEXT + 8), Some("first"));
+}
+
+#[test]
+fn loaded_addresses_are_described_by_the_function_of_their_object() {
+    let dir = fixture_dir("addresses-loaded");
+    let path = write_fixture(&dir, "libaddresses.so", &library().finalize());
+    let loader = offline_loader(&dir);
+    loader.load_library(&path).unwrap();
+    let base = object_base(&loader, &path);
+    assert_eq!(loader.describe_address(base + TEXT), "<first>");
+    assert_eq!(loader.describe_address(base + TEXT + 0x10), "<second>");
+    assert_eq!(
+        loader.describe_address(base + TEXT + TEXT_SIZE - 1),
+        "<second>"
+    );
+    assert_eq!(
+        loader.describe_address(base + TEXT + TEXT_SIZE),
+        "<unknown>"
+    );
+    assert_eq!(loader.describe_address(base - 1), "<unknown>");
+}
+
+#[test]
+fn loaded_addresses_resolve_to_the_function_and_segment_up_to_the_end_of_the_mapping() {
+    let dir = fixture_dir("addresses-resolved");
+    let path = write_fixture(&dir, "libaddresses.so", &library().finalize());
+    let loader = offline_loader(&dir);
+    loader.load_library(&path).unwrap();
+    let base = object_base(&loader, &path);
+
+    let resolved = loader.resolve_address(base + TEXT).unwrap();
+    assert_eq!(resolved.object, path);
+    assert_eq!(resolved.base, base);
+    assert_eq!(resolved.symbol, Some((String::from("first"), 0)));
+    assert_eq!(resolved.segment, "text");
+    let resolved = loader.resolve_address(base + TEXT + TEXT_SIZE - 1).unwrap();
+    assert_eq!(
+        resolved.symbol,
+        Some((String::from("second"), TEXT_SIZE - 0x11))
+    );
+    assert_eq!(loader.describe_address(base + TEXT + 0x10), "<second>");
+
+    // Past the last function, the rest of the page is still the text mapping.
+    let entry = loader
+        .memory_map_entries()
+        .into_iter()
+        .find(|entry| entry.object == path && entry.start <= base + TEXT && base + TEXT < entry.end)
+        .unwrap();
+    let resolved = loader.resolve_address(base + TEXT + TEXT_SIZE).unwrap();
+    assert_eq!(resolved.symbol, None);
+    assert_eq!(resolved.segment, "text");
+    let resolved = loader.resolve_address(entry.end - 1).unwrap();
+    assert_eq!(resolved.symbol, None);
+    assert_eq!(resolved.segment, "text");
+    // The first byte past the mapping is not the text segment anymore.
+    assert!(loader
+        .resolve_address(entry.end)
+        .is_none_or(|resolved| resolved.segment != "text"));
+    assert!(loader
+        .resolve_address(base + TEXT - 1)
+        .is_none_or(|resolved| { resolved.symbol.is_none() && resolved.segment != "text" }));
+}
@@ -0,0 +1,115 @@
+//! Resolving a dependency tree shares the metadata of each object instead of copying it,
+//! measured with an allocator counting the bytes allocated. The only test of this file, so no
+//! other test allocates while it measures.
+
+mod common;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use common::{data_library, fixture_dir, library_cache, write_fixture};
+use drow::cache::LibraryCache;
+use drow::ld_path_loader::LdPathLoader;
+use drow::loader::DependenciesResolver;
+use drow::offset_reader::OffsetReader;
+use drow::{Elf64Metadata, SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_OBJECT};
+
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        System.dealloc(pointer, layout)
+    }
+
+    unsafe fn realloc(&self, pointer: *mut u8, layout: Layout, size: usize) -> *mut u8 {
+        ALLOCATED.fetch_add(size, Ordering::Relaxed);
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(pointer, layout, size)
+    }
+}
+
+#[global_allocator]
+static COUNTING: Counting = Counting;
+
+/// Bytes and allocations `action` makes.
+fn measure<T>(action: impl FnOnce() -> T) -> (T, usize, usize) {
+    let (bytes, allocations) = (
+        ALLOCATED.load(Ordering::Relaxed),
+        ALLOCATIONS.load(Ordering::Relaxed),
+    );
+    let result = action();
+    (
+        result,
+        ALLOCATED.load(Ordering::Relaxed) - bytes,
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+    )
+}
+
+const SYMBOLS: usize = 4000;
+const USERS: usize = 8;
+
+fn parse(path: &String) -> Elf64Metadata {
+    Elf64Metadata::load(path, &mut OffsetReader::open(path).unwrap()).unwrap()
+}
+
+/// libbig.so with `SYMBOLS` symbols, needed by each of `USERS` libraries the root needs.
+fn tree(dir: &Path) -> (String, String) {
+    let big = (0..SYMBOLS).fold(data_library("big", &[0; 8], 8), |builder, index| {
+        builder.add_symbol(
+            &format!("big_symbol_{}", index),
+            SYMBOL_BINDING_GLOBAL,
+            SYMBOL_TYPE_OBJECT,
+            1,
+            0x1000,
+            8,
+        )
+    });
+    let big = write_fixture(dir, "libbig.so", &big.finalize());
+    let mut root = data_library("root", &[0; 8], 8);
+    for user in 0..USERS {
+        let name = format!("libuser{}.so", user);
+        let library = data_library(&format!("user{}", user), &[0; 8], 8).add_needed("libbig.so");
+        write_fixture(dir, &name, &library.finalize());
+        root = root.add_needed(&name);
+    }
+    (write_fixture(dir, "libroot.so", &root.finalize()), big)
+}
+
+#[test]
+fn resolving_parses_each_object_once_and_copies_none() {
+    let dir = fixture_dir("allocations-resolve");
+    let (root, big) = tree(&dir);
+    let cache = write_fixture(&dir, "ld.so.cache", &library_cache(&[]));
+    let mut resolver = DependenciesResolver::new(
+        LibraryCache::load(&cache).unwrap(),
+        Some(LdPathLoader::new(&dir.to_string_lossy())),
+    );
+    let root = Arc::new(parse(&root));
+    let (_, big_bytes, big_allocations) = measure(|| parse(&big));
+    let (order, bytes, allocations) = measure(|| resolver.resolve_in_loading_order(&root));
+    let order = order.unwrap();
+    assert_eq!(order.len(), USERS + 2);
+    assert!(Arc::ptr_eq(order.last().unwrap(), &root));
+    eprintln!(
+        "libbig.so: {} bytes in {} allocations, resolving: {} bytes in {} allocations",
+        big_bytes, big_allocations, bytes, allocations
+    );
+    // libbig.so dominates, a copy of its metadata anywhere would double the bytes.
+    assert!(bytes < big_bytes * 3 / 2, "{} bytes", bytes);
+    assert!(
+        allocations < big_allocations * 3 / 2,
+        "{} allocations",
+        allocations
+    );
+}
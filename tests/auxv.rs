@@ -0,0 +1,42 @@
+//! The auxiliary vector drow was started with.
+
+use drow::auxv::{
+    self, AuxiliaryVector, AT_HWCAP, AT_NULL, AT_PAGESZ, AT_RANDOM, AT_SECURE, AT_SYSINFO_EHDR,
+};
+
+fn entry(entry_type: u64, value: u64) -> Vec<u8> {
+    let mut bytes = entry_type.to_ne_bytes().to_vec();
+    bytes.extend_from_slice(&value.to_ne_bytes());
+    bytes
+}
+
+#[test]
+fn parsing_stops_at_at_null() {
+    let mut bytes = entry(AT_PAGESZ, 0x1000);
+    bytes.extend(entry(AT_SECURE, 1));
+    bytes.extend(entry(AT_NULL, 0));
+    bytes.extend(entry(AT_HWCAP, 0xFF));
+    let vector = AuxiliaryVector::parse(&bytes);
+    assert_eq!(vector.page_size(), Some(0x1000));
+    assert_eq!(vector.secure(), Some(true));
+    assert_eq!(vector.hwcap(), None);
+}
+
+/// AT_HWCAP is left out, glibc's getauxval returns the bits it computed itself on x86-64.
+#[test]
+fn current_vector_agrees_with_proc() {
+    let proc = AuxiliaryVector::from_proc().unwrap();
+    let current = auxv::current();
+    for entry_type in [AT_PAGESZ, AT_SECURE, AT_RANDOM, AT_SYSINFO_EHDR] {
+        assert_eq!(
+            current.get(entry_type),
+            proc.get(entry_type),
+            "{}",
+            entry_type
+        );
+    }
+    assert_eq!(
+        current.page_size(),
+        Some(unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u64)
+    );
+}
@@ -0,0 +1,145 @@
+//! `drow bench`: the harness runs a small program through both loaders and prints a table per
+//! phase and a comparison, or JSON.
+
+mod common;
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+use common::{compile, fixture_dir};
+
+/// Exits with the number of its arguments.
+const PROGRAM: &str = "int main(int argc, char **argv) { (void)argv; return argc - 1; }\n";
+
+const PHASES: [&str; 5] = ["resolve", "map", "relocate", "load", "exec"];
+
+/// Runs the bench with `options` on the program, which gets `arguments`.
+fn bench(dir: &Path, options: &[&str], arguments: &[&str]) -> Option<Output> {
+    let program = compile(dir, "program", PROGRAM, &[])?;
+    Some(
+        Command::new(env!("CARGO_BIN_EXE_drow"))
+            .args(["bench", "--iterations", "3", "--color=never"])
+            .args(options)
+            .arg(&program)
+            .arg("--")
+            .args(arguments)
+            .output()
+            .unwrap(),
+    )
+}
+
+fn microseconds(text: &str) -> f64 {
+    let value = text.parse::<f64>().unwrap();
+    assert!(value.is_finite() && value >= 0.0, "{}", text);
+    value
+}
+
+#[test]
+fn the_table_has_a_row_per_phase_and_a_comparison() {
+    let dir = fixture_dir("bench-table");
+    let Some(output) = bench(&dir, &[], &[]) else {
+        return;
+    };
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (phases, comparison) = stdout.split_once("\n\n").expect(&stdout);
+
+    let mut rows = phases.lines();
+    let header: Vec<&str> = rows
+        .next()
+        .unwrap()
+        .split("  ")
+        .filter(|cell| !cell.is_empty())
+        .collect();
+    assert_eq!(
+        header,
+        vec!["Phase", "Mean (us)", "Median (us)", "Stddev (us)"]
+    );
+    let descriptions = [
+        "Resolve dependencies",
+        "Map segments",
+        "Relocate",
+        "drow load, total",
+        "System loader, execve to exit",
+    ];
+    let rows: Vec<&str> = rows.collect();
+    assert_eq!(rows.len(), descriptions.len(), "{}", stdout);
+    for (row, description) in rows.iter().zip(descriptions.iter()) {
+        let numbers = row.strip_prefix(description).expect(row);
+        let numbers: Vec<f64> = numbers.split_whitespace().map(microseconds).collect();
+        assert_eq!(numbers.len(), 3, "{}", row);
+    }
+
+    let rows: Vec<Vec<&str>> = comparison
+        .lines()
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+    assert_eq!(rows[0], vec!["Loader", "Mean", "(us)", "Relative"]);
+    assert_eq!(rows[1][0], "drow");
+    microseconds(rows[1][1]);
+    assert!(rows[1][2].ends_with('x'), "{}", comparison);
+    assert_eq!(rows[2][0], "system");
+    microseconds(rows[2][1]);
+    assert_eq!(rows[2][2], "1.00x");
+}
+
+#[test]
+fn json_lists_every_phase() {
+    let dir = fixture_dir("bench-json");
+    let Some(output) = bench(&dir, &["--json"], &[]) else {
+        return;
+    };
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    let program = dir.join("program");
+    assert_eq!(lines[0], "{");
+    assert_eq!(lines[1], format!("  \"file\": \"{}\",", program.display()));
+    assert_eq!(lines[2], "  \"arguments\": [],");
+    assert_eq!(lines[3], "  \"iterations\": 3,");
+    assert_eq!(lines[4], "  \"phases\": [");
+    for (index, name) in PHASES.iter().enumerate() {
+        let line = lines[5 + index];
+        let prefix = format!("    {{\"phase\": \"{}\", ", name);
+        let fields = line
+            .strip_prefix(&prefix)
+            .and_then(|fields| {
+                fields.strip_suffix(if index + 1 < PHASES.len() { "}," } else { "}" })
+            })
+            .expect(line);
+        let keys: Vec<&str> = fields
+            .split(", ")
+            .map(|field| {
+                let (key, value) = field.split_once(": ").unwrap();
+                microseconds(value);
+                key
+            })
+            .collect();
+        assert_eq!(keys, vec!["\"mean_us\"", "\"median_us\"", "\"stddev_us\""]);
+    }
+    assert_eq!(&lines[5 + PHASES.len()..], ["  ]", "}"]);
+}
+
+#[test]
+fn a_program_not_exiting_cleanly_is_reported() {
+    let dir = fixture_dir("bench-status");
+    let Some(output) = bench(&dir, &["--json"], &["one", "two"]) else {
+        return;
+    };
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("  \"arguments\": [\"one\", \"two\"],\n"),
+        "{}",
+        stdout
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stderr
+            .matches("exited with status 2 under the system loader")
+            .count(),
+        1,
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,138 @@
+//! The bindings debug category: for each symbol a relocation refers to, the objects searched,
+//! the definition found and the ones it interposes, printed like LD_DEBUG=bindings and handed to
+//! the audit hooks with the relocated object.
+
+mod common;
+
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+use common::{data_library, fixture_dir, write_fixture};
+use drow::loader::{BindingDecision, Elf64Loader, LoadOptions};
+use drow::{RELOCATION_X86_64_GLOB_DAT, SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_OBJECT};
+
+/// libfirst.so and libsecond.so both defining `shared`, and libapp.so needing them in that
+/// order, with a GOT entry for `shared`. Returns the paths of the three.
+fn interposing_fixtures(dir: &Path) -> (String, String, String) {
+    let first = write_fixture(
+        dir,
+        "libfirst.so",
+        &data_library("shared", &[1; 8], 8)
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    let second = write_fixture(
+        dir,
+        "libsecond.so",
+        &data_library("shared", &[2; 8], 8)
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    let app = write_fixture(
+        dir,
+        "libapp.so",
+        &data_library("pointer", &[0; 8], 8)
+            .add_symbol("shared", SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_OBJECT, 0, 0, 0)
+            .add_rela(0x1000, RELOCATION_X86_64_GLOB_DAT, Some("shared"), 0)
+            .add_needed("libfirst.so")
+            .add_needed("libsecond.so")
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    (first, second, app)
+}
+
+/// Loads `app` with `debug_bindings`, returning the decisions the audit hook saw for it.
+fn audited_bindings(dir: &Path, app: &str, debug_bindings: bool) -> Vec<BindingDecision> {
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let recorded = seen.clone();
+    let app_path = app.to_string();
+    let loader = Elf64Loader::builder()
+        .offline(&[dir.to_string_lossy().into_owned()])
+        .options(LoadOptions {
+            debug_bindings,
+            ..LoadOptions::default()
+        })
+        .audit(move |object| {
+            if object.metadata.file_path == app_path {
+                recorded.lock().unwrap().extend(object.bindings.clone());
+            }
+        })
+        .build()
+        .unwrap();
+    loader.load_library(app).unwrap();
+    let bindings = seen.lock().unwrap().clone();
+    bindings
+}
+
+#[test]
+fn the_first_definition_in_scope_interposes_the_others() {
+    let dir = fixture_dir("bindings-interposed");
+    let (first, second, app) = interposing_fixtures(&dir);
+    let bindings = audited_bindings(&dir, &app, true);
+    assert_eq!(bindings.len(), 1, "{:?}", bindings);
+    let decision = &bindings[0];
+    assert_eq!(decision.requester, app);
+    assert_eq!(decision.symbol, "shared");
+    assert_eq!(decision.version, None);
+    assert_eq!(decision.definition.as_deref(), Some(first.as_str()));
+    assert_eq!(decision.searched, vec![first.clone()]);
+    assert_eq!(decision.interposed, vec![second.clone()]);
+    assert!(!decision.weak);
+    assert!(!decision.indirect_function);
+    assert_eq!(
+        decision.describe(),
+        format!(
+            "binding file {} [0] to {} [0]: normal symbol `shared'\n  searched {}; interposes {}",
+            app, first, first, second
+        )
+    );
+}
+
+#[test]
+fn nothing_is_recorded_without_the_category() {
+    let dir = fixture_dir("bindings-off");
+    let (_, _, app) = interposing_fixtures(&dir);
+    assert!(audited_bindings(&dir, &app, false).is_empty());
+}
+
+#[test]
+fn the_cli_prints_the_decisions_of_the_category() {
+    let dir = fixture_dir("bindings-cli");
+    let (first, second, app) = interposing_fixtures(&dir);
+    let output = Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args([
+            "run",
+            "--no-exec",
+            "--debug=bindings",
+            "--offline",
+            "--search-dir",
+        ])
+        .arg(&dir)
+        .arg(&app)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "[bindings] binding file {} [0] to {} [0]: normal symbol `shared'\n  searched {}; \
+             interposes {}\n",
+            app, first, first, second
+        )),
+        "{}",
+        stderr
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(["run", "--no-exec", "--debug=nonsense", &app])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Unknown debug category: nonsense"),
+        "{:?}",
+        output
+    );
+}
@@ -0,0 +1,155 @@
+//! Replay bundles: a load recorded with `drow run --record` replays from the bundle alone.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use common::{compile, data_library, fixture_dir, write_fixture};
+
+const LIBRARY: &str = "int library_value(void) { return 30; }\n";
+
+/// Exits with a system call and needs no libc, so the host libc does not matter.
+const PROGRAM: &str = "\
+int library_value(void);
+void _start(void) {
+    long status = library_value() + 12;
+    __asm__ volatile(\"syscall\" : : \"a\"(60), \"D\"(status));
+    __builtin_unreachable();
+}
+";
+
+fn drow(arguments: &[&str]) -> Output {
+    drow_with_library_path(arguments, None)
+}
+
+fn drow_with_library_path(arguments: &[&str], library_path: Option<&Path>) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_drow"));
+    command.args(arguments).env_remove("LD_LIBRARY_PATH");
+    if let Some(library_path) = library_path {
+        command.env("LD_LIBRARY_PATH", library_path);
+    }
+    command.output().unwrap()
+}
+
+fn path(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+#[test]
+fn replay_loads_the_copies_after_the_originals_are_gone() {
+    let dir = fixture_dir("bundle-fixtures");
+    let originals = dir.join("originals");
+    fs::create_dir(&originals).unwrap();
+    write_fixture(
+        &originals,
+        "libdependency.so",
+        &data_library("dependency_value", &[1; 8], 8).finalize(),
+    );
+    let root = write_fixture(
+        &originals,
+        "libroot.so",
+        &data_library("root_value", &[2; 8], 8)
+            .add_needed("libdependency.so")
+            .finalize(),
+    );
+    let bundle = path(&dir.join("bundle"));
+    let output = drow(&[
+        "run",
+        "--no-exec",
+        "--offline",
+        "--search-dir",
+        &path(&originals),
+        "--record",
+        &bundle,
+        &root,
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(
+        String::from_utf8_lossy(&output.stdout)
+            .contains(&format!("Recorded 2 file(s) to {}", bundle)),
+        "{:?}",
+        output
+    );
+    fs::remove_dir_all(&originals).unwrap();
+
+    let report = path(&dir.join("report.json"));
+    let output = drow(&["run", "--no-exec", "--replay", &bundle, "--report", &report]);
+    assert!(output.status.success(), "{:?}", output);
+    let report = fs::read_to_string(&report).unwrap();
+    for name in ["libroot.so", "libdependency.so"] {
+        assert!(
+            report.contains(&format!("{}/files/{}", bundle, name)),
+            "{} is not loaded from the bundle: {}",
+            name,
+            report
+        );
+    }
+}
+
+#[test]
+fn replayed_program_exits_as_recorded() {
+    let dir = fixture_dir("bundle-program");
+    let originals = dir.join("originals");
+    fs::create_dir(&originals).unwrap();
+    let Some(_) = compile(&originals, "liblibrary.so", LIBRARY, &["-shared", "-fPIC"]) else {
+        return;
+    };
+    let Some(program) = compile(
+        &originals,
+        "program",
+        PROGRAM,
+        &[
+            "-nostdlib",
+            &format!("-L{}", originals.display()),
+            "-llibrary",
+        ],
+    ) else {
+        return;
+    };
+    let bundle = path(&dir.join("bundle"));
+    let output = drow_with_library_path(
+        &["run", "--fork", "--record", &bundle, &program],
+        Some(&originals),
+    );
+    assert_eq!(output.status.code(), Some(42), "{:?}", output);
+    fs::remove_dir_all(&originals).unwrap();
+
+    let output = drow(&["run", "--fork", "--replay", &bundle]);
+    assert_eq!(output.status.code(), Some(42), "{:?}", output);
+}
+
+#[test]
+fn replay_refuses_a_modified_copy() {
+    let dir = fixture_dir("bundle-modified");
+    let root = write_fixture(
+        &dir,
+        "libroot.so",
+        &data_library("root_value", &[2; 8], 8).finalize(),
+    );
+    let bundle = path(&dir.join("bundle"));
+    let output = drow(&[
+        "run",
+        "--no-exec",
+        "--offline",
+        "--search-dir",
+        &path(&dir),
+        "--record",
+        &bundle,
+        &root,
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let copy = dir.join("bundle/files/libroot.so");
+    let mut bytes = fs::read(&copy).unwrap();
+    *bytes.last_mut().unwrap() ^= 0xFF;
+    fs::write(&copy, bytes).unwrap();
+
+    let output = drow(&["run", "--no-exec", "--replay", &bundle]);
+    assert!(!output.status.success(), "{:?}", output);
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("files/libroot.so has SHA-256"),
+        "{:?}",
+        output
+    );
+}
@@ -0,0 +1,327 @@
+//! `drow run --check`: the verdict for a program needing only what the loader supports, and for
+//! programs needing each feature it supports only partly or not at all.
+
+mod common;
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+use common::{data_library, fixture_dir, write_fixture};
+use drow::testutil::ElfBuilder;
+use drow::{
+    PROGRAM_FLAG_READ, PROGRAM_HEADER_TYPE_INTERPRETER, PROGRAM_HEADER_TYPE_TLS,
+    RELOCATION_X86_64_IRELATIV, RELOCATION_X86_64_PC32, RELOCATION_X86_64_TPOFF64,
+    SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_TLS,
+};
+
+/// DT_TEXTREL.
+const DYNAMIC_TABLE_TEXT_RELOCATIONS: i64 = 22;
+
+/// Offset of e_machine in the ELF header.
+const MACHINE_OFFSET: usize = 18;
+
+/// EM_AARCH64, as stored in e_machine.
+const MACHINE_AARCH64: [u8; 2] = [0xB7, 0x00];
+
+/// Exit status of a check whose verdict is that the program will not work.
+const EXIT_WILL_NOT_WORK: i32 = 126;
+
+fn check(dir: &Path, arguments: &[&str], path: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(["run", "--check", "--offline", "--search-dir"])
+        .arg(dir)
+        .args(arguments)
+        .arg(path)
+        .env_remove("LD_LIBRARY_PATH")
+        .output()
+        .unwrap()
+}
+
+/// Checks `program` in the fixture directory of `test`, next to `libraries`, asserts that the
+/// last line of stdout is `verdict`, where `{}` stands for the program path, with its exit
+/// status, and returns stdout.
+fn verdict(
+    test: &str,
+    program: ElfBuilder,
+    libraries: &[(&str, Vec<u8>)],
+    arguments: &[&str],
+    verdict: &str,
+) -> String {
+    let dir = fixture_dir(test);
+    for (name, bytes) in libraries.iter() {
+        write_fixture(&dir, name, bytes);
+    }
+    let path = write_fixture(&dir, "program", &program.finalize());
+    let output = check(&dir, arguments, &path);
+    let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+    let expected = if verdict == "likely to work" {
+        0
+    } else {
+        EXIT_WILL_NOT_WORK
+    };
+    assert_eq!(output.status.code(), Some(expected), "{:?}", output);
+    let verdict = verdict.replace("{}", &path);
+    assert!(
+        stdout.ends_with(&format!("{}: {}\n", path, verdict)),
+        "{}",
+        stdout
+    );
+    stdout
+}
+
+fn program() -> ElfBuilder {
+    data_library("program_value", &[0; 0x10], 0x10).map_dynamic(0x3000)
+}
+
+/// A library defining `tls_value` in its PT_TLS, reached through `__tls_get_addr`.
+fn tls_library() -> Vec<u8> {
+    data_library("library_value", &[0; 0x10], 0x10)
+        .add_segment(
+            PROGRAM_HEADER_TYPE_TLS,
+            PROGRAM_FLAG_READ,
+            0x1000,
+            &[0; 8],
+            0x10,
+        )
+        .add_symbol("tls_value", SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_TLS, 1, 0, 8)
+        .map_dynamic(0x3000)
+        .finalize()
+}
+
+fn interpreter(path: &str) -> ElfBuilder {
+    let mut content = path.as_bytes().to_vec();
+    content.push(0);
+    program().add_segment(
+        PROGRAM_HEADER_TYPE_INTERPRETER,
+        PROGRAM_FLAG_READ,
+        0x2000,
+        &content,
+        content.len() as u64,
+    )
+}
+
+/// Versions libversioned.so defines, the first naming the file.
+fn versioned_library() -> Vec<u8> {
+    data_library("library_value", &[0; 8], 8)
+        .add_version_definition("libversioned.so")
+        .add_version_definition("DROW_1.0")
+        .map_dynamic(0x3000)
+        .finalize()
+}
+
+#[test]
+fn a_program_needing_only_supported_features_is_likely_to_work() {
+    let stdout = verdict(
+        "capabilities-supported",
+        program().add_needed("libtls.so"),
+        &[("libtls.so", tls_library())],
+        &[],
+        "likely to work",
+    );
+    assert!(stdout.contains("dynamic TLS"), "{}", stdout);
+    assert!(!stdout.contains(" is "), "{}", stdout);
+}
+
+#[test]
+fn indirect_functions_are_supported() {
+    let stdout = verdict(
+        "capabilities-indirect",
+        program().add_rela(0x1000, RELOCATION_X86_64_IRELATIV, None, 0x1008),
+        &[],
+        &[],
+        "likely to work",
+    );
+    assert!(stdout.contains("1 IRELATIVE relocation(s)"), "{}", stdout);
+}
+
+#[test]
+fn a_known_interpreter_is_partly_supported() {
+    let stdout = verdict(
+        "capabilities-interpreter",
+        interpreter("/lib64/ld-linux-x86-64.so.2"),
+        &[],
+        &[],
+        "likely to work",
+    );
+    assert!(
+        stdout.contains("interpreter is partial: drow stands in for the C library's loader"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn an_unknown_interpreter_is_partly_supported() {
+    let stdout = verdict(
+        "capabilities-unknown-interpreter",
+        interpreter("/opt/elsewhere/ld.so"),
+        &[],
+        &[],
+        "likely to work",
+    );
+    assert!(
+        stdout.contains("unknown interpreter is partial: drow does not know"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn unsupported_relocation_types_will_not_work() {
+    let stdout = verdict(
+        "capabilities-relocations",
+        program()
+            .add_rela(0x1000, RELOCATION_X86_64_PC32, None, 0)
+            .add_rela(0x1004, RELOCATION_X86_64_PC32, None, 0),
+        &[],
+        &[],
+        "will not work because of relocation types ({})",
+    );
+    assert!(stdout.contains("R_X86_64_PC32 x2"), "{}", stdout);
+    assert!(
+        stdout.contains("relocation types is unsupported: the loader does not apply"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn a_foreign_machine_will_not_work() {
+    let dir = fixture_dir("capabilities-machine");
+    let mut bytes = program().finalize();
+    bytes[MACHINE_OFFSET..MACHINE_OFFSET + 2].copy_from_slice(&MACHINE_AARCH64);
+    let path = write_fixture(&dir, "program", &bytes);
+    let output = check(&dir, &[], &path);
+    assert_eq!(
+        output.status.code(),
+        Some(EXIT_WILL_NOT_WORK),
+        "{:?}",
+        output
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("e_machine 0xB7"), "{}", stdout);
+    assert!(
+        stdout.ends_with(&format!(
+            "{}: will not work because of foreign machine ({})\n",
+            path, path
+        )),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn static_tls_is_partly_supported() {
+    // The PT_TLS of the program itself.
+    let stdout = verdict(
+        "capabilities-static-tls-program",
+        program().add_segment(
+            PROGRAM_HEADER_TYPE_TLS,
+            PROGRAM_FLAG_READ,
+            0x1000,
+            &[0; 8],
+            0x10,
+        ),
+        &[],
+        &[],
+        "likely to work",
+    );
+    assert!(stdout.contains("PT_TLS of the program"), "{}", stdout);
+    assert!(
+        stdout.contains("static TLS is partial: objects loaded with the program share"),
+        "{}",
+        stdout
+    );
+
+    // TPOFF relocations of a library.
+    let library = data_library("library_value", &[0; 8], 8)
+        .add_symbol("tls_value", SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_TLS, 0, 0, 0)
+        .add_needed("libtls.so")
+        .add_rela(0x1000, RELOCATION_X86_64_TPOFF64, Some("tls_value"), 0)
+        .map_dynamic(0x3000)
+        .finalize();
+    let stdout = verdict(
+        "capabilities-static-tls-library",
+        program().add_needed("libstatic.so"),
+        &[("libtls.so", tls_library()), ("libstatic.so", library)],
+        &[],
+        "likely to work",
+    );
+    assert!(
+        stdout.lines().any(|line| line.starts_with("static TLS ")
+            && line.contains("libstatic.so ")
+            && line.ends_with(" 1 R_X86_64_TPOFF64 relocation(s)")),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn text_relocations_will_not_work() {
+    let stdout = verdict(
+        "capabilities-text-relocations",
+        program().add_dynamic(DYNAMIC_TABLE_TEXT_RELOCATIONS, 0),
+        &[],
+        &[],
+        "will not work because of text relocations ({})",
+    );
+    assert!(stdout.contains("DT_TEXTREL"), "{}", stdout);
+}
+
+#[test]
+fn a_missing_symbol_version_will_not_work_unless_best_effort() {
+    let program = || {
+        program()
+            .add_needed("libversioned.so")
+            .add_version_requirement("libversioned.so", "DROW_2.0")
+    };
+    let libraries = [("libversioned.so", versioned_library())];
+    let stdout = verdict(
+        "capabilities-missing-version",
+        program(),
+        &libraries,
+        &[],
+        "will not work because of missing symbol versions ({})",
+    );
+    assert!(stdout.contains("DROW_2.0 from"), "{}", stdout);
+
+    let stdout = verdict(
+        "capabilities-version-fallback",
+        program(),
+        &libraries,
+        &["--best-effort"],
+        "likely to work",
+    );
+    assert!(
+        stdout.contains("symbol version fallbacks is partial"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn an_unresolved_library_will_not_work() {
+    let stdout = verdict(
+        "capabilities-unresolved",
+        program().add_needed("libabsent.so"),
+        &[],
+        &[],
+        "will not work because of unresolved libraries ({})",
+    );
+    assert!(stdout.contains("libabsent.so"), "{}", stdout);
+}
+
+#[test]
+fn every_blocker_is_named_in_the_verdict() {
+    verdict(
+        "capabilities-blockers",
+        program()
+            .add_needed("libabsent.so")
+            .add_dynamic(DYNAMIC_TABLE_TEXT_RELOCATIONS, 0)
+            .add_rela(0x1000, RELOCATION_X86_64_PC32, None, 0),
+        &[],
+        &[],
+        "will not work because of relocation types ({}), text relocations ({}), \
+         unresolved libraries ({})",
+    );
+}
@@ -0,0 +1,184 @@
+//! Symbol and relocation tables decoded in chunks: whatever the chunk size, the same entries,
+//! and a large table read in a few reads instead of one per entry.
+
+mod common;
+
+use std::fmt::Write as _;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::time::Instant;
+
+use common::{compile, data_library, fixture_dir};
+use drow::{
+    DrowError, Elf64Metadata, Elf64ResolvedSymbolTableEntry, ParseOptions, DEFAULT_READ_CHUNK_SIZE,
+    RELOCATION_X86_64_64, RELOCATION_X86_64_GLOB_DAT, RELOCATION_X86_64_RELATIVE,
+    SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_OBJECT,
+};
+
+const SYMBOLS: usize = 3000;
+const RELOCATIONS: usize = 500;
+
+/// Counts the reads made through it.
+struct CountingReader {
+    inner: Cursor<Vec<u8>>,
+    reads: usize,
+}
+
+impl Read for CountingReader {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        self.reads += 1;
+        self.inner.read(buffer)
+    }
+}
+
+impl Seek for CountingReader {
+    fn seek(&mut self, position: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(position)
+    }
+}
+
+/// `SYMBOLS` symbols, `RELOCATIONS` RELA relocations and as many REL ones.
+fn large_library() -> Vec<u8> {
+    let mut builder = data_library("value", &[0; 0x10], 0x10);
+    for index in 0..SYMBOLS {
+        builder = builder.add_symbol(
+            &format!("symbol_{}", index),
+            SYMBOL_BINDING_GLOBAL,
+            SYMBOL_TYPE_OBJECT,
+            1,
+            0x1000 + index as u64,
+            1,
+        );
+    }
+    for index in 0..RELOCATIONS as u64 {
+        let symbol = format!("symbol_{}", index);
+        builder = builder
+            .add_rela(
+                0x1000 + index * 8,
+                RELOCATION_X86_64_GLOB_DAT,
+                Some(&symbol),
+                0,
+            )
+            .add_rela(
+                0x2000 + index * 8,
+                RELOCATION_X86_64_RELATIVE,
+                None,
+                index as i64,
+            )
+            .add_rel(0x3000 + index * 8, RELOCATION_X86_64_64, Some(&symbol));
+    }
+    builder.finalize()
+}
+
+fn parse(bytes: &[u8], read_chunk_size: u64) -> Result<(Elf64Metadata, usize), DrowError> {
+    let mut reader = CountingReader {
+        inner: Cursor::new(bytes.to_vec()),
+        reads: 0,
+    };
+    let options = ParseOptions {
+        read_chunk_size,
+        ..ParseOptions::default()
+    };
+    let metadata =
+        Elf64Metadata::load_with_options(&String::from("fixture.so"), &mut reader, options)?;
+    Ok((metadata, reader.reads))
+}
+
+type Symbol = (String, u8, u8, u16, u64, u64, Option<String>);
+type Relocation = (String, u64, u64, u64, i64, u32, usize);
+
+/// Everything decoded from the tables, comparable.
+fn entries(metadata: &Elf64Metadata) -> (Vec<Symbol>, Vec<Symbol>, Vec<Relocation>) {
+    let symbols = |table: &[Elf64ResolvedSymbolTableEntry]| {
+        table
+            .iter()
+            .map(|symbol| {
+                (
+                    symbol.symbol_name.clone(),
+                    symbol.binding,
+                    symbol.symbol_type,
+                    symbol.section_index,
+                    symbol.value,
+                    symbol.size,
+                    symbol.version.clone(),
+                )
+            })
+            .collect()
+    };
+    let relocations = metadata
+        .relocations
+        .iter()
+        .map(|relocation| {
+            (
+                relocation.symbol_name.clone(),
+                relocation.symbol_index,
+                relocation.relocation_type,
+                relocation.offset,
+                relocation.addend,
+                relocation.symbol_section_index,
+                relocation.section_index,
+            )
+        })
+        .collect();
+    (
+        symbols(&metadata.symbol_table),
+        symbols(&metadata.dynamic_symbol_table),
+        relocations,
+    )
+}
+
+#[test]
+fn every_chunk_size_decodes_the_same_entries() {
+    let bytes = large_library();
+    let (expected, _) = parse(&bytes, DEFAULT_READ_CHUNK_SIZE).unwrap();
+    let expected = entries(&expected);
+    assert_eq!(expected.1.len(), SYMBOLS + 2);
+    assert_eq!(expected.2.len(), 3 * RELOCATIONS);
+    // Less than an entry, exactly one, a chunk ending inside an entry and a page.
+    for chunk_size in [1, 16, 24, 24 * 7 + 5, 4096] {
+        let (metadata, _) = parse(&bytes, chunk_size).unwrap();
+        assert!(entries(&metadata) == expected, "chunk size {}", chunk_size);
+    }
+}
+
+#[test]
+fn a_large_table_takes_a_few_reads() {
+    let bytes = large_library();
+    let (_, bulk) = parse(&bytes, DEFAULT_READ_CHUNK_SIZE).unwrap();
+    let (_, per_entry) = parse(&bytes, 1).unwrap();
+    // One read per symbol and per relocation.
+    assert!(per_entry > SYMBOLS + 3 * RELOCATIONS, "{} reads", per_entry);
+    assert!(bulk < 100, "{} reads", bulk);
+}
+
+/// Parse times of a compiled library with many functions, one entry per read against the
+/// default chunks. Only printed, as timings vary.
+#[test]
+fn parse_time_of_a_large_library() {
+    let dir = fixture_dir("chunked-reads-benchmark");
+    let mut source = String::new();
+    for index in 0..4000 {
+        writeln!(
+            source,
+            "int function_{0}(int x) {{ return x + {0}; }}",
+            index
+        )
+        .unwrap();
+    }
+    let Some(path) = compile(&dir, "liblarge.so", &source, &["-shared", "-fPIC"]) else {
+        return;
+    };
+    let bytes = std::fs::read(&path).unwrap();
+    let mut results = Vec::new();
+    for chunk_size in [24, DEFAULT_READ_CHUNK_SIZE] {
+        let started = Instant::now();
+        let (metadata, reads) = parse(&bytes, chunk_size).unwrap();
+        eprintln!(
+            "chunk size {}: {} reads in {:?}",
+            chunk_size,
+            reads,
+            started.elapsed()
+        );
+        results.push(entries(&metadata));
+    }
+    assert!(results[0] == results[1]);
+}
@@ -0,0 +1,301 @@
+//! The command line parser of the binary, driven with argv vectors.
+
+mod common;
+
+// The binary's modules, compiled here on their own, with only the parser in use.
+#[allow(dead_code)]
+#[path = "../src/cli.rs"]
+mod cli;
+#[allow(dead_code)]
+#[path = "../src/config_file.rs"]
+mod config_file;
+#[allow(dead_code)]
+#[path = "../src/settings.rs"]
+mod settings;
+
+use std::sync::Once;
+
+use cli::{Command, Config};
+use drow::log::Level;
+use drow::summary::SummaryFormat;
+
+use common::fixture_dir;
+
+/// Keeps the config file and the DROW_ variables of the host out of every parse. The
+/// environment is only changed before the first parse, so the tests may run in parallel.
+fn isolate() {
+    static ISOLATED: Once = Once::new();
+    ISOLATED.call_once(|| {
+        for (name, _) in std::env::vars() {
+            if name.starts_with("DROW_") {
+                std::env::remove_var(name);
+            }
+        }
+        std::env::set_var("XDG_CONFIG_HOME", fixture_dir("cli-config-home"));
+    });
+}
+
+fn parse(args: &[&str]) -> Result<Option<Config>, String> {
+    isolate();
+    let args: Vec<String> = std::iter::once("drow")
+        .chain(args.iter().copied())
+        .map(String::from)
+        .collect();
+    Config::parse(&args)
+}
+
+fn config(args: &[&str]) -> Config {
+    match parse(args) {
+        Ok(Some(config)) => config,
+        Ok(None) => panic!("{:?} asked for help", args),
+        Err(err) => panic!("{:?} failed: {}", args, err),
+    }
+}
+
+fn error(args: &[&str]) -> String {
+    match parse(args) {
+        Err(err) => err,
+        Ok(_) => panic!("{:?} parsed", args),
+    }
+}
+
+#[test]
+fn inspect_takes_several_files_and_a_summary_format() {
+    let parsed = config(&["inspect", "--summary-format=csv", "a.so", "b.so"]);
+    assert_eq!(parsed.command, Command::Inspect);
+    assert_eq!(parsed.paths, vec!["a.so", "b.so"]);
+    assert_eq!(parsed.summary, Some(SummaryFormat::Csv));
+    let parsed = config(&["inspect", "--summary", "--debuginfo-dir", "/debug", "a.so"]);
+    assert_eq!(parsed.summary, Some(SummaryFormat::Plain));
+    assert_eq!(parsed.debuginfo_dirs, vec!["/debug"]);
+}
+
+#[test]
+fn resolve_searches_offline_directories() {
+    let config = config(&[
+        "resolve",
+        "--offline",
+        "--search-dir",
+        "/one",
+        "--search-dir=/two",
+        "--dep-graph",
+        "graph.dot",
+        "a.so",
+    ]);
+    assert_eq!(config.command, Command::Resolve);
+    assert!(config.offline);
+    assert_eq!(config.search_dirs, vec!["/one", "/two"]);
+    assert_eq!(config.dep_graph.as_deref(), Some("graph.dot"));
+    assert_eq!(config.paths, vec!["a.so"]);
+}
+
+#[test]
+fn run_takes_load_options_and_program_arguments() {
+    let config = config(&[
+        "run",
+        "--fork",
+        "--stack-size",
+        "64K",
+        "--base-address=0x10000000",
+        "--max-object-size",
+        "1M",
+        "--no-exec",
+        "--strict",
+        "a.out",
+        "--",
+        "--not-an-option",
+        "argument",
+    ]);
+    assert_eq!(config.command, Command::Run);
+    assert_eq!(config.paths, vec!["a.out"]);
+    assert_eq!(
+        config.program_arguments,
+        vec!["--not-an-option", "argument"]
+    );
+    assert!(config.fork);
+    assert!(config.no_exec);
+    assert!(config.load_options.strict);
+    assert_eq!(config.load_options.stack_size, 64 << 10);
+    assert_eq!(config.load_options.base_address, 0x1000_0000);
+    assert_eq!(config.load_options.max_object_size, 1 << 20);
+}
+
+#[test]
+fn run_each_takes_several_files_and_forks() {
+    let config = config(&["run", "--each", "a.out", "b.out"]);
+    assert!(config.each);
+    assert!(config.fork);
+    assert_eq!(config.paths, vec!["a.out", "b.out"]);
+}
+
+#[test]
+fn shell_takes_one_file() {
+    let config = config(&["shell", "a.so"]);
+    assert_eq!(config.command, Command::Shell);
+    assert_eq!(config.paths, vec!["a.so"]);
+}
+
+#[test]
+fn bench_takes_iterations_and_program_arguments() {
+    let config = config(&["bench", "--iterations", "3", "--json", "a.out", "--", "x"]);
+    assert_eq!(config.command, Command::Bench);
+    assert_eq!(config.iterations, 3);
+    assert!(config.json);
+    assert_eq!(config.program_arguments, vec!["x"]);
+}
+
+#[test]
+fn edit_takes_an_output_and_the_changes() {
+    let config = config(&[
+        "edit",
+        "--set-rpath",
+        "$ORIGIN",
+        "--remove-section=.comment",
+        "--output",
+        "b.so",
+        "a.so",
+    ]);
+    assert_eq!(config.command, Command::Edit);
+    assert_eq!(config.set_rpath.as_deref(), Some("$ORIGIN"));
+    assert_eq!(config.remove_sections, vec![".comment"]);
+    assert_eq!(config.output.as_deref(), Some("b.so"));
+}
+
+#[test]
+fn short_flags_combine_and_verbosity_accumulates() {
+    assert_eq!(config(&["inspect", "a.so"]).log_level, Level::Warn);
+    assert_eq!(config(&["inspect", "-q", "a.so"]).log_level, Level::Error);
+    assert_eq!(config(&["inspect", "-v", "a.so"]).log_level, Level::Info);
+    assert_eq!(config(&["inspect", "-vv", "a.so"]).log_level, Level::Debug);
+    assert_eq!(
+        config(&["-v", "inspect", "--verbose", "-v", "a.so"]).log_level,
+        Level::Trace
+    );
+}
+
+#[test]
+fn help_is_asked_for_anywhere() {
+    for args in [
+        &["--help"][..],
+        &["-h"],
+        &["run", "a.out", "--help"],
+        &["run", "-vh"],
+    ] {
+        assert!(parse(args).unwrap().is_none(), "{:?}", args);
+    }
+}
+
+#[test]
+fn show_config_needs_no_file() {
+    let config = config(&["run", "--show-config"]);
+    assert!(config.show_config);
+    assert!(config.paths.is_empty());
+}
+
+#[test]
+fn unknown_names_are_errors_with_suggestions() {
+    assert_eq!(
+        error(&["rnu", "a.out"]),
+        "Unknown command rnu, did you mean run?"
+    );
+    assert_eq!(
+        error(&["run", "--forkk", "a.out"]),
+        "Unknown option --forkk, did you mean --fork?"
+    );
+    assert_eq!(error(&["run", "-x", "a.out"]), "Unknown option -x");
+    assert_eq!(error(&["a.out"]), "Unknown command a.out");
+}
+
+#[test]
+fn values_are_checked() {
+    assert_eq!(
+        error(&["run", "--fork=yes", "a.out"]),
+        "Option --fork takes no value"
+    );
+    assert_eq!(
+        error(&["run", "a.out", "--stack-size"]),
+        "Option --stack-size expects BYTES"
+    );
+    assert_eq!(error(&["run", "--fd", "-1"]), "Invalid value -1 for --fd");
+    assert_eq!(
+        error(&["bench", "--iterations", "0", "a.out"]),
+        "--iterations must be at least 1"
+    );
+}
+
+#[test]
+fn options_and_arguments_are_checked_against_the_command() {
+    assert_eq!(
+        error(&["inspect", "--fork", "a.so"]),
+        "Option --fork is not supported by inspect"
+    );
+    assert_eq!(
+        error(&["inspect", "a.so", "--", "x"]),
+        "inspect does not take program arguments"
+    );
+    assert_eq!(error(&["edit", "a.so"]), "edit requires --output");
+}
+
+#[test]
+fn path_counts_are_checked() {
+    assert_eq!(
+        error(&[]),
+        "A command is required: inspect, resolve, run, shell, bench or edit"
+    );
+    assert_eq!(error(&["inspect"]), "A file path is required");
+    assert_eq!(
+        error(&["run", "a.out", "b.out"]),
+        "run accepts several files only with --each"
+    );
+    assert_eq!(error(&["shell", "a", "b"]), "shell accepts a single file");
+    assert_eq!(error(&["bench", "a", "b"]), "bench accepts a single file");
+    assert_eq!(
+        error(&["edit", "--output=c", "a", "b"]),
+        "edit accepts a single file"
+    );
+    assert_eq!(
+        error(&["resolve", "--dep-graph", "g.dot", "a", "b"]),
+        "--dep-graph accepts a single file"
+    );
+}
+
+#[test]
+fn dependent_options_are_checked() {
+    assert_eq!(
+        error(&["run", "--offline", "a.out"]),
+        "--offline requires --search-dir"
+    );
+    assert_eq!(
+        error(&["run", "--search-dir", "/lib", "a.out"]),
+        "--search-dir requires --offline"
+    );
+    assert_eq!(
+        error(&["run", "--no-exec", "--pause-after-load", "a.out"]),
+        "--pause-after-load and --no-exec are exclusive"
+    );
+    assert_eq!(
+        error(&["run", "--manifest-fallback", "a.out"]),
+        "--manifest-fallback requires --manifest"
+    );
+}
+
+#[test]
+fn usage_lists_the_commands_and_options() {
+    let usage = cli::usage();
+    for command in ["inspect", "resolve", "run", "shell", "bench", "edit"] {
+        assert!(
+            usage.contains(&format!("\n  {} <file>", command)),
+            "{}",
+            usage
+        );
+    }
+    for option in [
+        "--fork",
+        "--offline",
+        "--search-dir",
+        "--log-file",
+        "--show-config",
+    ] {
+        assert!(usage.contains(option), "{}", option);
+    }
+}
@@ -0,0 +1,231 @@
+//! Fixtures shared by the integration tests, written to a directory of their own per test.
+
+// Each test file compiles this module on its own and uses some of the helpers only.
+#![allow(dead_code)]
+
+use std::convert::TryInto;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use drow::loader::Elf64Loader;
+use drow::testutil::ElfBuilder;
+use drow::{
+    PROGRAM_FLAG_EXECUTE, PROGRAM_FLAG_READ, PROGRAM_FLAG_WRITE, PROGRAM_HEADER_TYPE_LOADABLE,
+    RELOCATION_X86_64_GLOB_DAT, RELOCATION_X86_64_RELATIVE, SECTION_FLAG_ALLOCATED,
+    SECTION_FLAG_WRITE, SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_OBJECT,
+};
+
+/// SHT_PROGBITS, which the parser has no use for.
+pub const SECTION_TYPE_PROGRAM_BITS: u32 = 1;
+
+/// An empty directory for the fixtures of `test`, under the system temporary directory.
+pub fn fixture_dir(test: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir()
+            .join("drow-tests")
+            .join(format!("{}-{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn write_fixture(dir: &Path, name: &str, bytes: &[u8]) -> String {
+    let path = dir.join(name);
+    fs::write(&path, bytes).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+/// A loader that searches `dir` only, ignoring the system cache and LD_LIBRARY_PATH.
+pub fn offline_loader(dir: &Path) -> Elf64Loader {
+    Elf64Loader::builder()
+        .offline(&[dir.to_string_lossy().into_owned()])
+        .build()
+        .unwrap()
+}
+
+/// A shared object with a writable segment at 0x1000 holding `content` and zero filled up to
+/// `memory_size`, and a global object `symbol` at its start.
+pub fn data_library(symbol: &str, content: &[u8], memory_size: u64) -> ElfBuilder {
+    ElfBuilder::new()
+        .add_segment(
+            PROGRAM_HEADER_TYPE_LOADABLE,
+            PROGRAM_FLAG_READ | PROGRAM_FLAG_WRITE,
+            0x1000,
+            content,
+            memory_size,
+        )
+        .add_segment_section(
+            ".data",
+            SECTION_TYPE_PROGRAM_BITS,
+            SECTION_FLAG_ALLOCATED | SECTION_FLAG_WRITE,
+            0x1000,
+            content.len() as u64,
+        )
+        .add_symbol(
+            symbol,
+            SYMBOL_BINDING_GLOBAL,
+            SYMBOL_TYPE_OBJECT,
+            1,
+            0x1000,
+            content.len() as u64,
+        )
+}
+
+const DYNAMIC_TABLE_INIT_ARRAY: i64 = 25;
+const DYNAMIC_TABLE_FINI_ARRAY: i64 = 26;
+const DYNAMIC_TABLE_INIT_ARRAY_SIZE: i64 = 27;
+const DYNAMIC_TABLE_FINI_ARRAY_SIZE: i64 = 28;
+
+/// liblog.so, defining `drow_log`: a count of the markers recorded so far, in a word, then the
+/// markers.
+pub fn log_library() -> ElfBuilder {
+    data_library("drow_log", &[0; 0x48], 0x48).map_dynamic(0x3000)
+}
+
+/// A function appending `marker` to `drow_log`, whose address is in the word at `got`, when
+/// placed at `address`.
+fn record(address: u64, got: u64, marker: u8) -> Vec<u8> {
+    // mov rdx, [rip + got]
+    let mut code = vec![0x48, 0x8B, 0x15];
+    code.extend_from_slice(&((got as i64 - (address as i64 + 7)) as i32).to_le_bytes());
+    code.extend_from_slice(&[
+        0x8B, 0x02, // mov eax, [rdx]
+        0x8D, 0x48, 0x01, // lea ecx, [rax + 1]
+        0x89, 0x0A, // mov [rdx], ecx
+        0xC6, 0x44, 0x02, 0x08, marker, // mov byte [rdx + rax + 8], marker
+        0xC3,   // ret
+    ]);
+    code
+}
+
+/// A library needing liblog.so and `needed`, with an init function recording `marker` in
+/// `drow_log` and a fini function recording it in lowercase. It defines `symbol` at 0x1000.
+pub fn recording_library(symbol: &str, marker: u8, needed: &[&str]) -> ElfBuilder {
+    let init = record(0x2000, 0x1000, marker);
+    let fini = record(
+        0x2000 + init.len() as u64,
+        0x1000,
+        marker.to_ascii_lowercase(),
+    );
+    let code = [init.clone(), fini].concat();
+    let builder = needed.iter().fold(
+        data_library(symbol, &[0; 0x18], 0x18).add_needed("liblog.so"),
+        |builder, library| builder.add_needed(library),
+    );
+    builder
+        .add_segment(
+            PROGRAM_HEADER_TYPE_LOADABLE,
+            PROGRAM_FLAG_READ | PROGRAM_FLAG_EXECUTE,
+            0x2000,
+            &code,
+            code.len() as u64,
+        )
+        .add_symbol(
+            "drow_log",
+            SYMBOL_BINDING_GLOBAL,
+            SYMBOL_TYPE_OBJECT,
+            0,
+            0,
+            0,
+        )
+        .add_rela(0x1000, RELOCATION_X86_64_GLOB_DAT, Some("drow_log"), 0)
+        .add_rela(0x1008, RELOCATION_X86_64_RELATIVE, None, 0x2000)
+        .add_rela(
+            0x1010,
+            RELOCATION_X86_64_RELATIVE,
+            None,
+            0x2000 + init.len() as i64,
+        )
+        .add_dynamic(DYNAMIC_TABLE_INIT_ARRAY, 0x1008)
+        .add_dynamic(DYNAMIC_TABLE_INIT_ARRAY_SIZE, 8)
+        .add_dynamic(DYNAMIC_TABLE_FINI_ARRAY, 0x1010)
+        .add_dynamic(DYNAMIC_TABLE_FINI_ARRAY_SIZE, 8)
+        .map_dynamic(0x3000)
+}
+
+/// The markers recorded in the `drow_log` of `loader` so far.
+pub fn recorded(loader: &Elf64Loader) -> String {
+    let log = loader.lookup_symbol("drow_log").unwrap();
+    let count = u32::from_le_bytes(mapped_bytes(log, 4).try_into().unwrap());
+    String::from_utf8(mapped_bytes(log + 8, count as usize)).unwrap()
+}
+
+/// Reads `length` bytes the loader mapped at `address`.
+pub fn mapped_bytes(address: u64, length: usize) -> Vec<u8> {
+    unsafe { std::slice::from_raw_parts(address as *const u8, length) }.to_vec()
+}
+
+/// Reads the little-endian word the loader mapped at `address`.
+pub fn mapped_word(address: u64) -> u64 {
+    u64::from_le_bytes(mapped_bytes(address, 8).try_into().unwrap())
+}
+
+/// The base `loader` mapped the object at `path` at.
+pub fn object_base(loader: &Elf64Loader, path: &str) -> u64 {
+    loader
+        .load_report(false)
+        .objects
+        .iter()
+        .find(|object| object.path == path)
+        .unwrap_or_else(|| panic!("{} is not loaded", path))
+        .base
+}
+
+/// An ld.so.cache in the glibc 1.1 format listing `(name, path, flags)` entries.
+pub fn library_cache(entries: &[(&str, &str, i32)]) -> Vec<u8> {
+    const HEADER_SIZE: usize = 48;
+    const ENTRY_SIZE: usize = 24;
+    let strings_offset = HEADER_SIZE + entries.len() * ENTRY_SIZE;
+    let mut strings: Vec<u8> = Vec::new();
+    let mut table: Vec<u8> = Vec::new();
+    for (name, path, flags) in entries {
+        let key = strings_offset + strings.len();
+        strings.extend_from_slice(name.as_bytes());
+        strings.push(0);
+        let value = strings_offset + strings.len();
+        strings.extend_from_slice(path.as_bytes());
+        strings.push(0);
+        table.extend_from_slice(&flags.to_le_bytes());
+        table.extend_from_slice(&(key as u32).to_le_bytes());
+        table.extend_from_slice(&(value as u32).to_le_bytes());
+        table.extend_from_slice(&0u32.to_le_bytes());
+        table.extend_from_slice(&0u64.to_le_bytes());
+    }
+    let mut bytes = b"glibc-ld.so.cache1.1".to_vec();
+    bytes.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+    bytes.resize(HEADER_SIZE, 0);
+    bytes.extend_from_slice(&table);
+    bytes.extend_from_slice(&strings);
+    bytes
+}
+
+/// Compiles the C `source` into `dir/name` with `cc` and `arguments`, or returns None when there
+/// is no C compiler, so the tests needing one are skipped.
+pub fn compile(dir: &Path, name: &str, source: &str, arguments: &[&str]) -> Option<String> {
+    let source_path = dir.join(format!("{}.c", name));
+    fs::write(&source_path, source).unwrap();
+    compile_file(&source_path, &dir.join(name), arguments)
+}
+
+/// Like `compile`, for a source file in place, so its relative includes work.
+pub fn compile_file(source: &Path, output: &Path, arguments: &[&str]) -> Option<String> {
+    match std::process::Command::new("cc")
+        .arg(source)
+        .arg("-o")
+        .arg(output)
+        .args(arguments)
+        .output()
+    {
+        Ok(result) if result.status.success() => Some(output.to_string_lossy().into_owned()),
+        Ok(result) => panic!(
+            "cc failed on {}: {}",
+            source.display(),
+            String::from_utf8_lossy(&result.stderr)
+        ),
+        Err(err) => {
+            eprintln!("Skipping, no C compiler: {}", err);
+            None
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Loading libraries from several threads at once.
+
+mod common;
+
+use std::thread;
+
+use common::{data_library, fixture_dir, mapped_bytes, offline_loader, write_fixture};
+use drow::loader::Elf64Loader;
+
+const THREADS: usize = 8;
+const LIBRARIES_PER_THREAD: usize = 6;
+
+/// The content of the symbol of library `index` of `thread`, telling them apart.
+fn content(thread: usize, index: usize) -> Vec<u8> {
+    vec![(thread * LIBRARIES_PER_THREAD + index) as u8 + 1; 32]
+}
+
+/// Writes the libraries of every thread, each needing `libshared.so`, and returns their paths.
+fn write_libraries(dir: &std::path::Path) -> Vec<Vec<String>> {
+    write_fixture(
+        dir,
+        "libshared.so",
+        &data_library("shared_value", &[0xEE; 16], 0x3000).finalize(),
+    );
+    (0..THREADS)
+        .map(|thread| {
+            (0..LIBRARIES_PER_THREAD)
+                .map(|index| {
+                    write_fixture(
+                        dir,
+                        &format!("lib{}_{}.so", thread, index),
+                        &data_library(&symbol(thread, index), &content(thread, index), 0x2000)
+                            .add_needed("libshared.so")
+                            .finalize(),
+                    )
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn symbol(thread: usize, index: usize) -> String {
+    format!("value_{}_{}", thread, index)
+}
+
+/// Fails when two of `ranges` overlap.
+fn assert_disjoint(mut ranges: Vec<(u64, u64, String)>) {
+    ranges.sort();
+    for pair in ranges.windows(2) {
+        assert!(
+            pair[0].1 <= pair[1].0,
+            "{:#X}-{:#X} of {} overlaps {:#X}-{:#X} of {}",
+            pair[0].0,
+            pair[0].1,
+            pair[0].2,
+            pair[1].0,
+            pair[1].1,
+            pair[1].2
+        );
+    }
+}
+
+fn ranges(loader: &Elf64Loader) -> Vec<(u64, u64, String)> {
+    loader
+        .memory_map_entries()
+        .into_iter()
+        .map(|entry| (entry.start, entry.end, entry.object))
+        .collect()
+}
+
+fn assert_symbols_resolve(loader: &Elf64Loader) {
+    for thread in 0..THREADS {
+        for index in 0..LIBRARIES_PER_THREAD {
+            let address = loader
+                .lookup_symbol(&symbol(thread, index))
+                .unwrap_or_else(|| panic!("{} is not defined", symbol(thread, index)));
+            assert_eq!(mapped_bytes(address, 32), content(thread, index));
+        }
+    }
+    let shared = loader.lookup_symbol("shared_value").unwrap();
+    assert_eq!(mapped_bytes(shared, 16), vec![0xEE; 16]);
+}
+
+#[test]
+fn threads_sharing_a_loader_map_disjoint_ranges() {
+    let dir = fixture_dir("threads-shared-loader");
+    let paths = write_libraries(&dir);
+    let loader = offline_loader(&dir);
+    thread::scope(|scope| {
+        for libraries in paths.iter() {
+            let loader = &loader;
+            scope.spawn(move || {
+                for library in libraries.iter() {
+                    loader.load_library(library).unwrap();
+                }
+            });
+        }
+    });
+    assert_disjoint(ranges(&loader));
+    assert_symbols_resolve(&loader);
+    // libshared.so is mapped once however many threads needed it.
+    let report = loader.load_report(false);
+    assert_eq!(report.objects.len(), THREADS * LIBRARIES_PER_THREAD + 1);
+}
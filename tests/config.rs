@@ -0,0 +1,184 @@
+//! Settings from the config file, the environment and the command line, read back with
+//! `--show-config`.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use common::fixture_dir;
+
+const VARIABLES: &[&str] = &[
+    "DROW_CONFIG",
+    "DROW_LIBRARY_PATH",
+    "DROW_PRELOAD",
+    "DROW_STACK_SIZE",
+    "DROW_BASE",
+    "DROW_MAX_OBJECT_SIZE",
+    "DROW_BIND_NOW",
+    "DROW_LOG",
+    "DROW_LOG_FILE",
+    "DROW_SYSROOT",
+];
+
+/// `drow run --show-config` with `arguments`, the DROW_ variables in `environment` only and the
+/// default config file below `dir`.
+fn show_config(dir: &Path, environment: &[(&str, &str)], arguments: &[&str]) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_drow"));
+    command
+        .args(["run", "--show-config"])
+        .args(arguments)
+        .env("XDG_CONFIG_HOME", dir);
+    for variable in VARIABLES {
+        command.env_remove(variable);
+    }
+    command.envs(environment.iter().copied());
+    command.output().unwrap()
+}
+
+/// The value and the source `--show-config` printed for `key`.
+fn setting(output: &Output, key: &str) -> (String, String) {
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let prefix = format!("{} = ", key);
+    let line = stdout
+        .lines()
+        .find(|line| line.starts_with(&prefix))
+        .unwrap_or_else(|| panic!("no {} in {}", key, stdout));
+    let (value, source) = line[prefix.len()..].split_once('#').unwrap();
+    (value.trim().to_string(), source.trim().to_string())
+}
+
+fn write_config(dir: &Path, name: &str, text: &str) -> String {
+    let path = dir.join(name);
+    fs::write(&path, text).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+/// The error line drow printed, without the log prefix and the usage hint.
+fn error(output: &Output) -> String {
+    assert_eq!(output.status.code(), Some(125), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    stderr
+        .trim_end()
+        .trim_start_matches("[ERROR drow] ")
+        .trim_end_matches(" (run drow --help for usage)")
+        .to_string()
+}
+
+#[test]
+fn the_environment_overrides_the_file_and_the_command_line_overrides_both() {
+    let dir = fixture_dir("config-precedence");
+    let path = write_config(
+        &dir,
+        "drow.conf",
+        concat!(
+            "# Every layer sets sysroot, two of them base.\n",
+            "sysroot = \"/from-file\"\n",
+            "base = 0x7000_0000\n",
+            "stack_size = 0x10000\n",
+        ),
+    );
+    let output = show_config(
+        &dir,
+        &[
+            ("DROW_SYSROOT", "/from-environment"),
+            ("DROW_BASE", "0x8000000"),
+        ],
+        &["--config", &path, "--sysroot", "/from-command-line"],
+    );
+    let file = format!("config file {}", path);
+    assert_eq!(
+        setting(&output, "sysroot"),
+        (
+            String::from("\"/from-command-line\""),
+            String::from("command line")
+        )
+    );
+    assert_eq!(
+        setting(&output, "base"),
+        (
+            0x800_0000.to_string(),
+            String::from("environment DROW_BASE")
+        )
+    );
+    assert_eq!(setting(&output, "stack_size"), (0x10000.to_string(), file));
+    assert_eq!(setting(&output, "bind_now").1, "default");
+}
+
+#[test]
+fn the_config_option_overrides_the_variable_and_the_default_path() {
+    let dir = fixture_dir("config-path");
+    fs::create_dir(dir.join("drow")).unwrap();
+    let default = write_config(&dir, "drow/config", "stack_size = 1\n");
+    let variable = write_config(&dir, "variable.conf", "stack_size = 2\n");
+    let option = write_config(&dir, "option.conf", "stack_size = 3\n");
+    let source = |path: &str| format!("config file {}", path);
+    assert_eq!(
+        setting(&show_config(&dir, &[], &[]), "stack_size"),
+        (String::from("1"), source(&default))
+    );
+    let output = show_config(&dir, &[("DROW_CONFIG", &variable)], &[]);
+    assert_eq!(
+        setting(&output, "stack_size"),
+        (String::from("2"), source(&variable))
+    );
+    let output = show_config(&dir, &[("DROW_CONFIG", &variable)], &["--config", &option]);
+    assert_eq!(
+        setting(&output, "stack_size"),
+        (String::from("3"), source(&option))
+    );
+}
+
+#[test]
+fn only_a_missing_file_named_explicitly_is_an_error() {
+    let dir = fixture_dir("config-missing");
+    assert_eq!(
+        setting(&show_config(&dir, &[], &[]), "stack_size").1,
+        "default"
+    );
+    let path = dir.join("absent.conf").to_string_lossy().into_owned();
+    let message = error(&show_config(&dir, &[], &["--config", &path]));
+    assert!(
+        message.starts_with(&format!("Unable to read config file {}: ", path)),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn errors_point_at_the_line_and_column() {
+    let dir = fixture_dir("config-errors");
+    let cases: &[(&str, &str)] = &[
+        (
+            "stack_size = 0x10000\n\nbase = \"high\"\n",
+            "3:8: base expects an integer, found string",
+        ),
+        (
+            "# Typo\nstak_size = 1\n",
+            "2:1: Unknown key stak_size, did you mean stack_size?",
+        ),
+        ("base = 1 2\n", "1:10: unexpected characters after value"),
+        ("sysroot = /usr\n", "1:11: expected a value"),
+        (
+            "log_level = debug\n",
+            "1:13: unexpected debug, strings must be quoted",
+        ),
+        ("sysroot = \"/usr\n", "1:16: unterminated string"),
+        ("base = 1\n  base = 2\n", "2:3: duplicate key base"),
+        ("[drow]\n", "1:1: sections are not supported"),
+    ];
+    for (index, (text, expected)) in cases.iter().enumerate() {
+        let path = write_config(&dir, &format!("{}.conf", index), text);
+        let message = error(&show_config(&dir, &[], &["--config", &path]));
+        assert_eq!(message, format!("{}:{}", path, expected), "{:?}", text);
+    }
+}
+
+#[test]
+fn an_invalid_variable_is_named_in_the_error() {
+    let dir = fixture_dir("config-variable");
+    let message = error(&show_config(&dir, &[("DROW_STACK_SIZE", "abc")], &[]));
+    assert_eq!(message, "DROW_STACK_SIZE: Invalid number abc");
+}
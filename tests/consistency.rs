@@ -0,0 +1,371 @@
+//! Section headers that disagree with the program headers: a warning by default, the segments
+//! being what is mapped, and an error under `--strict`.
+
+mod common;
+
+use std::io::Cursor;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use common::{data_library, fixture_dir, write_fixture, SECTION_TYPE_PROGRAM_BITS};
+use drow::loader::{Elf64Loader, LoadOptions};
+use drow::testutil::ElfBuilder;
+use drow::{
+    DrowError, Elf64Metadata, ELF64_SECTION_HEADER_DYNAMIC, PROGRAM_FLAG_READ,
+    PROGRAM_HEADER_TYPE_DYNAMIC, PROGRAM_HEADER_TYPE_LOADABLE, PROGRAM_HEADER_TYPE_TLS,
+    SECTION_FLAG_ALLOCATED, SECTION_FLAG_EXECUTABLE_INSTRUCTIONS, SECTION_FLAG_THREAD_LOCAL,
+    SECTION_FLAG_WRITE,
+};
+
+/// DT_FINI, which points into the mapped object like the other address tags.
+const DYNAMIC_TABLE_FINI_FUNCTION: i64 = 13;
+
+/// Bytes of a section header and offsets of its fields.
+const SECTION_HEADER_SIZE: u64 = 64;
+const SECTION_TYPE_OFFSET: u64 = 4;
+const SECTION_FILE_OFFSET_OFFSET: u64 = 24;
+const SECTION_SIZE_OFFSET: u64 = 32;
+
+/// Bytes of a program header, whose type is its first field.
+const PROGRAM_HEADER_SIZE: u64 = 56;
+
+/// A library whose `.data` at 0x1000 fills its writable segment, with its dynamic tables mapped
+/// at 0x3000, so its section headers agree with its segments.
+fn consistent() -> ElfBuilder {
+    data_library("value", &[0; 8], 8).map_dynamic(0x3000)
+}
+
+fn parse(bytes: &[u8]) -> Elf64Metadata {
+    Elf64Metadata::load(&String::from("fixture.so"), &mut Cursor::new(bytes)).unwrap()
+}
+
+fn patch(bytes: &mut [u8], offset: u64, value: &[u8]) {
+    let offset = offset as usize;
+    bytes[offset..offset + value.len()].copy_from_slice(value);
+}
+
+/// The index of the dynamic section of `metadata` and the file offset of its header.
+fn dynamic_section(metadata: &Elf64Metadata) -> (usize, u64) {
+    let index = metadata
+        .section_headers
+        .iter()
+        .position(|section| section.sh_type == ELF64_SECTION_HEADER_DYNAMIC)
+        .unwrap();
+    (
+        index,
+        metadata.elf_header.e_section_header_offset + index as u64 * SECTION_HEADER_SIZE,
+    )
+}
+
+/// `.data` at a file offset 8 bytes past where its segment has it.
+fn moved_data() -> (Vec<u8>, String) {
+    let mut bytes = consistent().finalize();
+    let metadata = parse(&bytes);
+    let data = &metadata.section_headers[1];
+    let segment = &metadata.program_headers[0];
+    let header = metadata.elf_header.e_section_header_offset + SECTION_HEADER_SIZE;
+    patch(
+        &mut bytes,
+        header + SECTION_FILE_OFFSET_OFFSET,
+        &(data.sh_offset + 8).to_le_bytes(),
+    );
+    let violation = format!(
+        "section [1] .data at 0x1000 has file offset {:#x}, the PT_LOAD at 0x1000-0x1008 \
+         (RW-, file-backed to 0x1008) maps {:#x} there",
+        data.sh_offset + 8,
+        segment.p_offset
+    );
+    (bytes, violation)
+}
+
+/// A dynamic section 16 bytes shorter than PT_DYNAMIC.
+fn short_dynamic_section() -> (Vec<u8>, String) {
+    let mut bytes = consistent().finalize();
+    let metadata = parse(&bytes);
+    let (index, header) = dynamic_section(&metadata);
+    let section = &metadata.section_headers[index];
+    patch(
+        &mut bytes,
+        header + SECTION_SIZE_OFFSET,
+        &(section.sh_size - 16).to_le_bytes(),
+    );
+    let violation = format!(
+        "PT_DYNAMIC at {:#x} (file offset {:#x}, {} bytes) differs from dynamic section [{}] \
+         .dynamic at {:#x} (file offset {:#x}, {} bytes)",
+        section.sh_virtual_address,
+        section.sh_offset,
+        section.sh_size,
+        index,
+        section.sh_virtual_address,
+        section.sh_offset,
+        section.sh_size - 16
+    );
+    (bytes, violation)
+}
+
+/// A PT_DYNAMIC whose section is no longer typed SHT_DYNAMIC.
+fn untyped_dynamic_section() -> (Vec<u8>, String) {
+    let mut bytes = consistent().finalize();
+    let metadata = parse(&bytes);
+    let (index, header) = dynamic_section(&metadata);
+    patch(
+        &mut bytes,
+        header + SECTION_TYPE_OFFSET,
+        &SECTION_TYPE_PROGRAM_BITS.to_le_bytes(),
+    );
+    let violation = format!(
+        "PT_DYNAMIC at {:#x} has no dynamic section",
+        metadata.section_headers[index].sh_virtual_address
+    );
+    (bytes, violation)
+}
+
+/// A dynamic section whose PT_DYNAMIC is turned into PT_NULL.
+fn dynamic_section_without_segment() -> (Vec<u8>, String) {
+    let mut bytes = consistent().finalize();
+    let metadata = parse(&bytes);
+    let (index, _) = dynamic_section(&metadata);
+    let segment = metadata
+        .program_headers
+        .iter()
+        .position(|header| header.p_type == PROGRAM_HEADER_TYPE_DYNAMIC)
+        .unwrap() as u64;
+    patch(
+        &mut bytes,
+        metadata.elf_header.e_program_header_offset + segment * PROGRAM_HEADER_SIZE,
+        &0u32.to_le_bytes(),
+    );
+    let violation = format!(
+        "dynamic section [{}] .dynamic at {:#x} has no PT_DYNAMIC",
+        index, metadata.section_headers[index].sh_virtual_address
+    );
+    (bytes, violation)
+}
+
+/// One fixture per kind of mismatch, as (name, file, violation reported).
+fn cases() -> Vec<(&'static str, Vec<u8>, String)> {
+    let read_write = "the PT_LOAD at 0x1000-0x1008 (RW-, file-backed to 0x1008)";
+    let mut cases = vec![
+        (
+            "tls-without-pt-tls",
+            consistent()
+                .add_segment_section(
+                    ".tdata",
+                    SECTION_TYPE_PROGRAM_BITS,
+                    SECTION_FLAG_ALLOCATED | SECTION_FLAG_WRITE | SECTION_FLAG_THREAD_LOCAL,
+                    0x1000,
+                    8,
+                )
+                .finalize(),
+            String::from("TLS section [2] .tdata at 0x1000-0x1008 has no PT_TLS"),
+        ),
+        (
+            "tls-outside-pt-tls",
+            consistent()
+                .add_segment(
+                    PROGRAM_HEADER_TYPE_TLS,
+                    PROGRAM_FLAG_READ,
+                    0x1000,
+                    &[0; 4],
+                    4,
+                )
+                .add_segment_section(
+                    ".tdata",
+                    SECTION_TYPE_PROGRAM_BITS,
+                    SECTION_FLAG_ALLOCATED | SECTION_FLAG_WRITE | SECTION_FLAG_THREAD_LOCAL,
+                    0x1000,
+                    8,
+                )
+                .finalize(),
+            String::from(
+                "TLS section [2] .tdata at 0x1000-0x1008 is outside PT_TLS at 0x1000-0x1004",
+            ),
+        ),
+        (
+            "unmapped",
+            consistent()
+                .add_segment_section(
+                    ".orphan",
+                    SECTION_TYPE_PROGRAM_BITS,
+                    SECTION_FLAG_ALLOCATED,
+                    0x5000,
+                    8,
+                )
+                .finalize(),
+            String::from("section [2] .orphan at 0x5000-0x5008 is not in any PT_LOAD"),
+        ),
+        (
+            "past-segment",
+            consistent()
+                .add_segment_section(
+                    ".long",
+                    SECTION_TYPE_PROGRAM_BITS,
+                    SECTION_FLAG_ALLOCATED | SECTION_FLAG_WRITE,
+                    0x1000,
+                    0x10,
+                )
+                .finalize(),
+            format!(
+                "section [2] .long at 0x1000-0x1010 extends past {}",
+                read_write
+            ),
+        ),
+        (
+            "writable",
+            consistent()
+                .add_segment(
+                    PROGRAM_HEADER_TYPE_LOADABLE,
+                    PROGRAM_FLAG_READ,
+                    0x5000,
+                    &[0; 8],
+                    8,
+                )
+                .add_segment_section(
+                    ".ro",
+                    SECTION_TYPE_PROGRAM_BITS,
+                    SECTION_FLAG_ALLOCATED | SECTION_FLAG_WRITE,
+                    0x5000,
+                    8,
+                )
+                .finalize(),
+            String::from(
+                "section [2] .ro at 0x5000 is writable, the PT_LOAD at 0x5000-0x5008 (R--, \
+                 file-backed to 0x5008) is not",
+            ),
+        ),
+        (
+            "executable",
+            consistent()
+                .add_segment_section(
+                    ".text",
+                    SECTION_TYPE_PROGRAM_BITS,
+                    SECTION_FLAG_ALLOCATED | SECTION_FLAG_EXECUTABLE_INSTRUCTIONS,
+                    0x1000,
+                    8,
+                )
+                .finalize(),
+            format!(
+                "section [2] .text at 0x1000 is executable, {} is not",
+                read_write
+            ),
+        ),
+        (
+            "nobits-over-file-content",
+            consistent()
+                .add_nobits(".bss", SECTION_FLAG_WRITE, 0x1000, 8)
+                .finalize(),
+            format!(
+                "NOBITS section [2] .bss at 0x1000-0x1008 overlaps the file content of {}",
+                read_write
+            ),
+        ),
+        (
+            "file-content-past",
+            data_library("value", &[0; 8], 0x10)
+                .add_segment_section(
+                    ".late",
+                    SECTION_TYPE_PROGRAM_BITS,
+                    SECTION_FLAG_ALLOCATED | SECTION_FLAG_WRITE,
+                    0x1008,
+                    8,
+                )
+                .map_dynamic(0x3000)
+                .finalize(),
+            String::from(
+                "section [2] .late at 0x1008-0x1010 has file content past the PT_LOAD at \
+                 0x1000-0x1010 (RW-, file-backed to 0x1008)",
+            ),
+        ),
+        (
+            "unmapped-dynamic-address",
+            consistent()
+                .add_dynamic(DYNAMIC_TABLE_FINI_FUNCTION, 0x9000)
+                .finalize(),
+            String::from("DT_FINI 0x9000 is not in any PT_LOAD"),
+        ),
+    ];
+    for (name, (bytes, violation)) in [
+        ("file-offset", moved_data()),
+        ("dynamic-differs", short_dynamic_section()),
+        ("dynamic-without-section", untyped_dynamic_section()),
+        ("dynamic-without-segment", dynamic_section_without_segment()),
+    ] {
+        cases.push((name, bytes, violation));
+    }
+    cases
+}
+
+fn loader(dir: &Path, strict: bool) -> Elf64Loader {
+    Elf64Loader::builder()
+        .offline(&[dir.to_string_lossy().into_owned()])
+        .options(LoadOptions {
+            strict,
+            ..LoadOptions::default()
+        })
+        .build()
+        .unwrap()
+}
+
+fn drow(dir: &Path, arguments: &[&str], path: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(["run", "--no-exec", "--offline", "--search-dir"])
+        .arg(dir)
+        .args(arguments)
+        .arg(path)
+        .env_remove("LD_LIBRARY_PATH")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn consistent_headers_load_under_strict_mode_without_a_warning() {
+    let dir = fixture_dir("consistency-clean");
+    let path = write_fixture(&dir, "libclean.so", &consistent().finalize());
+    loader(&dir, true).load_library(&path).unwrap();
+    let output = drow(&dir, &[], &path);
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("disagree"), "{}", stderr);
+}
+
+#[test]
+fn each_mismatch_is_a_warning_and_an_error_under_strict_mode() {
+    let dir = fixture_dir("consistency-mismatches");
+    for (name, bytes, violation) in cases() {
+        let path = write_fixture(&dir, &format!("lib{}.so", name), &bytes);
+        loader(&dir, false).load_library(&path).unwrap();
+        let output = drow(&dir, &[], &path);
+        assert!(output.status.success(), "{}: {:?}", name, output);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let warning = format!(
+            "{}: mapping the segments, the section headers or dynamic entries disagree: ",
+            path
+        );
+        assert!(
+            stderr.contains(&warning) && stderr.contains(&violation),
+            "{}: {}",
+            name,
+            stderr
+        );
+
+        match loader(&dir, true).load_library(&path) {
+            Err(DrowError::NotLoadable {
+                path: refused,
+                reason,
+            }) => {
+                assert_eq!(refused, path, "{}", name);
+                assert!(
+                    reason.starts_with(
+                        "its section headers or dynamic entries disagree with its segments:\n"
+                    ) && reason.contains(&violation),
+                    "{}: {}",
+                    name,
+                    reason
+                );
+            }
+            other => panic!("{}: unexpected result {:?}", name, other),
+        }
+        let output = drow(&dir, &["--strict"], &path);
+        assert_eq!(output.status.code(), Some(126), "{}: {:?}", name, output);
+    }
+}
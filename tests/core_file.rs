@@ -0,0 +1,107 @@
+//! Parsing the notes of core files.
+
+use std::io::Cursor;
+
+use drow::core_file::CoreFile;
+use drow::testutil::ElfBuilder;
+use drow::{DrowError, Elf64Metadata, ELF_TYPE_CORE, PROGRAM_FLAG_READ, PROGRAM_HEADER_TYPE_NOTE};
+
+const NOTE_FILE: u32 = 0x4649_4c45;
+
+/// A CORE note of `note_type` holding `description`.
+fn core_note(note_type: u32, description: &[u8]) -> Vec<u8> {
+    let mut note = Vec::new();
+    note.extend_from_slice(&5u32.to_le_bytes());
+    note.extend_from_slice(&(description.len() as u32).to_le_bytes());
+    note.extend_from_slice(&note_type.to_le_bytes());
+    note.extend_from_slice(b"CORE\0\0\0\0");
+    note.extend_from_slice(description);
+    note.resize(note.len().div_ceil(4) * 4, 0);
+    note
+}
+
+/// An NT_FILE description of `count` entries, holding `entries` and `names`.
+fn file_note(count: u64, page_size: u64, entries: &[(u64, u64, u64)], names: &[u8]) -> Vec<u8> {
+    let mut description = Vec::new();
+    description.extend_from_slice(&count.to_le_bytes());
+    description.extend_from_slice(&page_size.to_le_bytes());
+    for (start, end, page_offset) in entries {
+        description.extend_from_slice(&start.to_le_bytes());
+        description.extend_from_slice(&end.to_le_bytes());
+        description.extend_from_slice(&page_offset.to_le_bytes());
+    }
+    description.extend_from_slice(names);
+    core_note(NOTE_FILE, &description)
+}
+
+fn load_core(notes: &[u8]) -> Result<CoreFile, DrowError> {
+    let bytes = ElfBuilder::new()
+        .elf_type(ELF_TYPE_CORE)
+        .add_segment(
+            PROGRAM_HEADER_TYPE_NOTE,
+            PROGRAM_FLAG_READ,
+            0,
+            notes,
+            notes.len() as u64,
+        )
+        .finalize();
+    let mut reader = Cursor::new(bytes);
+    let metadata = Elf64Metadata::load(&String::from("core"), &mut reader).unwrap();
+    CoreFile::load(&metadata, &mut reader)
+}
+
+#[test]
+fn mapped_files_are_read() {
+    let notes = file_note(
+        2,
+        0x1000,
+        &[(0x40_0000, 0x40_1000, 0), (0x7F00_0000, 0x7F00_2000, 3)],
+        b"/bin/true\0/lib/libc.so.6\0",
+    );
+    let core = load_core(&notes).unwrap();
+    let files: Vec<(u64, u64, u64, &str)> = core
+        .files
+        .iter()
+        .map(|file| (file.start, file.end, file.page_offset, file.path.as_str()))
+        .collect();
+    assert_eq!(
+        files,
+        vec![
+            (0x40_0000, 0x40_1000, 0, "/bin/true"),
+            (0x7F00_0000, 0x7F00_2000, 0x3000, "/lib/libc.so.6"),
+        ]
+    );
+}
+
+#[test]
+fn entry_count_wrapping_the_table_size_is_malformed() {
+    // 24 times the count wraps to 8, which a description of 24 bytes would pass for.
+    let notes = file_note(
+        0x0AAA_AAAA_AAAA_AAAB,
+        0x1000,
+        &[],
+        b"/bin/true\0\0\0\0\0\0\0",
+    );
+    assert!(matches!(
+        load_core(&notes),
+        Err(DrowError::Malformed { .. })
+    ));
+}
+
+#[test]
+fn entry_count_past_the_description_is_malformed() {
+    let notes = file_note(u64::MAX, 0x1000, &[(0, 0x1000, 0)], b"/bin/true\0");
+    assert!(matches!(
+        load_core(&notes),
+        Err(DrowError::Malformed { .. })
+    ));
+}
+
+#[test]
+fn file_offset_past_the_address_space_is_malformed() {
+    let notes = file_note(1, 0x1000, &[(0, 0x1000, u64::MAX / 0x100)], b"/bin/true\0");
+    assert!(matches!(
+        load_core(&notes),
+        Err(DrowError::Malformed { .. })
+    ));
+}
@@ -0,0 +1,108 @@
+//! What `--cpu-features` tells ifunc resolvers that read their arguments.
+
+mod common;
+
+use std::convert::TryInto;
+
+use common::{fixture_dir, mapped_bytes, write_fixture, SECTION_TYPE_PROGRAM_BITS};
+use drow::cpu_features::CpuFeatures;
+use drow::loader::{Elf64Loader, LoadOptions};
+use drow::testutil::ElfBuilder;
+use drow::{
+    PROGRAM_FLAG_EXECUTE, PROGRAM_FLAG_READ, PROGRAM_FLAG_WRITE, PROGRAM_HEADER_TYPE_LOADABLE,
+    RELOCATION_X86_64_IRELATIV, SECTION_FLAG_ALLOCATED, SECTION_FLAG_EXECUTABLE_INSTRUCTIONS,
+    SECTION_FLAG_WRITE, SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_OBJECT,
+};
+
+/// Two resolvers, at 0x1000 returning the features of their second argument and at 0x1008
+/// returning their hwcap, each bound by an IRELATIVE relocation to a slot of `slots`.
+fn resolver_library() -> Vec<u8> {
+    let mut code = vec![0x48, 0x8B, 0x46, 0x18, 0xC3, 0x90, 0x90, 0x90];
+    code.extend_from_slice(&[0x48, 0x89, 0xF8, 0xC3]);
+    ElfBuilder::new()
+        .add_segment(
+            PROGRAM_HEADER_TYPE_LOADABLE,
+            PROGRAM_FLAG_READ | PROGRAM_FLAG_EXECUTE,
+            0x1000,
+            &code,
+            code.len() as u64,
+        )
+        .add_segment_section(
+            ".text",
+            SECTION_TYPE_PROGRAM_BITS,
+            SECTION_FLAG_ALLOCATED | SECTION_FLAG_EXECUTABLE_INSTRUCTIONS,
+            0x1000,
+            code.len() as u64,
+        )
+        .add_segment(
+            PROGRAM_HEADER_TYPE_LOADABLE,
+            PROGRAM_FLAG_READ | PROGRAM_FLAG_WRITE,
+            0x2000,
+            &[0; 16],
+            16,
+        )
+        .add_segment_section(
+            ".data",
+            SECTION_TYPE_PROGRAM_BITS,
+            SECTION_FLAG_ALLOCATED | SECTION_FLAG_WRITE,
+            0x2000,
+            16,
+        )
+        .add_symbol(
+            "slots",
+            SYMBOL_BINDING_GLOBAL,
+            SYMBOL_TYPE_OBJECT,
+            2,
+            0x2000,
+            16,
+        )
+        .add_rela(0x2000, RELOCATION_X86_64_IRELATIV, None, 0x1000)
+        .add_rela(0x2008, RELOCATION_X86_64_IRELATIV, None, 0x1008)
+        .finalize()
+}
+
+/// The values the resolvers returned when loaded with `cpu_features`.
+fn resolved(path: &str, cpu_features: CpuFeatures) -> (u64, u64) {
+    let loader = Elf64Loader::builder()
+        .offline(&[])
+        .options(LoadOptions {
+            cpu_features,
+            ..LoadOptions::default()
+        })
+        .build()
+        .unwrap();
+    loader.load_library(path).unwrap();
+    let slots = mapped_bytes(loader.lookup_symbol("slots").unwrap(), 16);
+    (
+        u64::from_le_bytes(slots[..8].try_into().unwrap()),
+        u64::from_le_bytes(slots[8..].try_into().unwrap()),
+    )
+}
+
+/// The loaders of a test process share the resolver arguments, so both loads run in one test.
+#[test]
+fn resolvers_see_the_masked_features() {
+    let dir = fixture_dir("cpu-features");
+    let path = write_fixture(&dir, "libresolvers.so", &resolver_library());
+    let native = CpuFeatures::native();
+    let sse2 = CpuFeatures::parse("sse2").unwrap();
+    assert_ne!(native, sse2);
+
+    let (features, hwcap) = resolved(&path, sse2);
+    assert_eq!(features, 1 << 4);
+    assert_eq!(hwcap, sse2.hwcap() | 1 << 62);
+
+    let (features, hwcap) = resolved(&path, native);
+    assert_ne!(features, 1 << 4);
+    assert_eq!(features & 1 << 4, 1 << 4);
+    assert_eq!(hwcap, native.hwcap() | 1 << 62);
+}
+
+#[test]
+fn native_hwcap_is_the_kernel_one() {
+    let kernel = drow::auxv::AuxiliaryVector::from_proc()
+        .unwrap()
+        .hwcap()
+        .unwrap();
+    assert_eq!(CpuFeatures::native().hwcap(), kernel);
+}
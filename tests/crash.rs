@@ -0,0 +1,219 @@
+//! The report of a fault killing the loaded program: the signal, the faulting instruction and
+//! its callers symbolized through the load map, and the address accessed. In the same process
+//! the handler reports it, even with the stack of the program exhausted, then lets the signal
+//! end drow as it would have ended the program.
+
+mod common;
+
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use common::{compile, fixture_dir};
+
+/// Writes through a null pointer 0x10 bytes in, two calls deep, with no libc.
+const PROGRAM: &str = "\
+__attribute__((noinline)) void frob_widget(volatile int *widget) {
+    widget[4] = 1;
+}
+
+__attribute__((noinline)) void assemble(void) {
+    frob_widget((volatile int *)0);
+}
+
+void _start(void) {
+    assemble();
+    for (;;) {
+    }
+}
+";
+
+/// Recurses until the stack of the program runs out, with `-DOVERFLOW`, or aborts itself
+/// three calls deep.
+const FATAL_PROGRAM: &str = "\
+#ifdef OVERFLOW
+__attribute__((noinline)) int recurse(volatile char *previous) {
+    volatile char frame[4096];
+    frame[0] = *previous;
+    return recurse(frame) + frame[1];
+}
+
+void _start(void) {
+    char start = 0;
+    recurse(&start);
+    for (;;) {
+    }
+}
+#else
+__attribute__((noinline)) void abort_widget(void) {
+    long pid;
+    __asm__ volatile(\"syscall\" : \"=a\"(pid) : \"a\"(39));
+    __asm__ volatile(\"syscall\" : : \"a\"(62), \"D\"(pid), \"S\"(6));
+}
+
+__attribute__((noinline)) void assemble(void) {
+    abort_widget();
+}
+
+void _start(void) {
+    assemble();
+    for (;;) {
+    }
+}
+#endif
+";
+
+fn program(dir: &Path) -> Option<String> {
+    compile(
+        dir,
+        "widgets",
+        PROGRAM,
+        &["-nostdlib", "-O0", "-fno-omit-frame-pointer"],
+    )
+}
+
+fn drow(arguments: &[&str], program: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_drow"))
+        .arg("run")
+        .args(arguments)
+        .arg(program)
+        .output()
+        .unwrap()
+}
+
+/// The lines of the crash report of `stderr`, from the signal on.
+fn report(stderr: &str) -> Vec<&str> {
+    let start = stderr
+        .find("SIGSEGV at 0x")
+        .unwrap_or_else(|| panic!("no crash report in {}", stderr));
+    stderr[start..].lines().collect()
+}
+
+fn check_report(stderr: &str) {
+    let report = report(stderr);
+    let first = report[0];
+    assert!(
+        first.starts_with("SIGSEGV at 0x")
+            && first.contains(" (widgets`frob_widget+0x")
+            && first.ends_with("), accessing 0x10"),
+        "{}",
+        stderr
+    );
+    assert!(
+        report[1].starts_with("  #1 0x") && report[1].contains(" (widgets`assemble+0x"),
+        "{}",
+        stderr
+    );
+    assert!(
+        report[2].starts_with("  #2 0x") && report[2].contains(" (widgets`_start+0x"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn a_fault_in_a_child_is_reported_in_the_function_it_hit() {
+    let dir = fixture_dir("crash-child");
+    let Some(program) = program(&dir) else {
+        return;
+    };
+    let output = drow(&["--fork"], &program);
+    assert_eq!(
+        output.status.code(),
+        Some(128 + libc::SIGSEGV),
+        "{:?}",
+        output
+    );
+    check_report(&String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn a_fault_in_the_same_process_is_reported_by_the_handler() {
+    let dir = fixture_dir("crash-same-process");
+    let Some(program) = program(&dir) else {
+        return;
+    };
+    let output = drow(&[], &program);
+    assert_eq!(output.status.signal(), Some(libc::SIGSEGV), "{:?}", output);
+    check_report(&String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn without_the_handler_only_the_signal_is_known() {
+    let dir = fixture_dir("crash-no-handler");
+    let Some(program) = program(&dir) else {
+        return;
+    };
+    let output = drow(&["--fork", "--no-crash-handler"], &program);
+    assert_eq!(
+        output.status.code(),
+        Some(128 + libc::SIGSEGV),
+        "{:?}",
+        output
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!("Process terminated by signal {}", libc::SIGSEGV)),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("frob_widget"), "{}", stderr);
+}
+
+#[test]
+fn an_exhausted_stack_is_still_reported() {
+    let dir = fixture_dir("crash-overflow");
+    let Some(program) = compile(
+        &dir,
+        "overflow",
+        FATAL_PROGRAM,
+        &["-nostdlib", "-O0", "-fno-omit-frame-pointer", "-DOVERFLOW"],
+    ) else {
+        return;
+    };
+    let output = drow(&[], &program);
+    assert_eq!(output.status.signal(), Some(libc::SIGSEGV), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let report = report(&stderr);
+    // The walk of an exhausted stack is best effort, only the instruction is certain.
+    assert!(report[0].contains(" (overflow`recurse+0x"), "{}", stderr);
+}
+
+#[test]
+fn an_abort_keeps_its_signal_and_is_reported() {
+    let dir = fixture_dir("crash-abort");
+    let Some(program) = compile(
+        &dir,
+        "aborting",
+        FATAL_PROGRAM,
+        &["-nostdlib", "-O0", "-fno-omit-frame-pointer"],
+    ) else {
+        return;
+    };
+    let output = drow(&[], &program);
+    assert_eq!(output.status.signal(), Some(libc::SIGABRT), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let start = stderr.find("SIGABRT at 0x").expect(&stderr);
+    let report: Vec<&str> = stderr[start..].lines().collect();
+    assert!(
+        report[0].contains(" (aborting`abort_widget+0x"),
+        "{}",
+        stderr
+    );
+    // Not a memory access.
+    assert!(!report[0].contains("accessing"), "{}", stderr);
+    assert!(report[1].contains(" (aborting`assemble+0x"), "{}", stderr);
+    assert!(report[2].contains(" (aborting`_start+0x"), "{}", stderr);
+}
+
+#[test]
+fn without_the_handler_the_same_process_dies_silently() {
+    let dir = fixture_dir("crash-same-process-no-handler");
+    let Some(program) = program(&dir) else {
+        return;
+    };
+    let output = drow(&["--no-crash-handler"], &program);
+    assert_eq!(output.status.signal(), Some(libc::SIGSEGV), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!stderr.contains("SIGSEGV at"), "{}", stderr);
+}
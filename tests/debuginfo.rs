@@ -0,0 +1,170 @@
+//! Detached debug files: found through .gnu_debuglink next to the file or by build-id under a
+//! debuginfo directory, verified, and their .symtab merged for inspection only.
+
+mod common;
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use common::{compile, fixture_dir};
+use drow::debuginfo;
+use drow::offset_reader::OffsetReader;
+use drow::summary::read_build_id;
+use drow::Elf64Metadata;
+
+/// `frob_widget` is local, so only the debug file names it once the library is stripped.
+const SOURCE: &str = "\
+static __attribute__((noinline)) int frob_widget(int x) { return x * 3 + 1; }
+int widget(int x) { return frob_widget(x); }
+";
+
+/// Runs the binutils `tool`, or returns false when it is not installed.
+fn binutils(tool: &str, arguments: &[&str]) -> bool {
+    match Command::new(tool).args(arguments).output() {
+        Ok(result) if result.status.success() => true,
+        Ok(result) => panic!(
+            "{} failed: {}",
+            tool,
+            String::from_utf8_lossy(&result.stderr)
+        ),
+        Err(err) => {
+            eprintln!("Skipping, no {}: {}", tool, err);
+            false
+        }
+    }
+}
+
+/// The pair a distribution ships: libwidget.so stripped and linked to libwidget.so.debug next to
+/// it, which holds the symbols. Returns both, or None without the tools to build them.
+fn fixtures(dir: &Path) -> Option<(String, String)> {
+    let full = compile(
+        dir,
+        "libwidget.full.so",
+        SOURCE,
+        &["-shared", "-fPIC", "-O1", "-Wl,--build-id"],
+    )?;
+    let debug = dir
+        .join("libwidget.so.debug")
+        .to_string_lossy()
+        .into_owned();
+    let stripped = dir.join("libwidget.so").to_string_lossy().into_owned();
+    let link = format!("--add-gnu-debuglink={}", debug);
+    if !binutils("objcopy", &["--only-keep-debug", &full, &debug])
+        || !binutils("objcopy", &["--strip-all", &link, &full, &stripped])
+    {
+        return None;
+    }
+    Some((stripped, debug))
+}
+
+fn metadata(path: &str) -> (Elf64Metadata, OffsetReader) {
+    let mut reader = OffsetReader::open(path).unwrap();
+    let metadata = Elf64Metadata::load(&path.to_string(), &mut reader).unwrap();
+    (metadata, reader)
+}
+
+fn has_symbol(elf_metadata: &Elf64Metadata, name: &str) -> bool {
+    elf_metadata
+        .symbol_table
+        .iter()
+        .any(|symbol| symbol.symbol_name == name)
+}
+
+#[test]
+fn the_linked_debug_file_next_to_the_library_is_merged() {
+    let dir = fixture_dir("debuginfo-link");
+    let Some((stripped, debug)) = fixtures(&dir) else {
+        return;
+    };
+    let (mut elf_metadata, mut reader) = metadata(&stripped);
+    let link = debuginfo::read_debug_link(&elf_metadata, &mut reader)
+        .unwrap()
+        .unwrap();
+    assert_eq!(link.name, "libwidget.so.debug");
+    assert!(!has_symbol(&elf_metadata, "frob_widget"));
+
+    // An empty debuginfo directory, so nothing of the host is found first.
+    let none = dir.join("none").to_string_lossy().into_owned();
+    let found = debuginfo::load_symbols(&mut elf_metadata, &mut reader, &[none]);
+    assert_eq!(found, Some(debug));
+    assert!(has_symbol(&elf_metadata, "frob_widget"));
+    assert!(has_symbol(&elf_metadata, "widget"));
+}
+
+#[test]
+fn a_debug_file_with_another_crc_is_ignored() {
+    let dir = fixture_dir("debuginfo-crc");
+    let Some((stripped, debug)) = fixtures(&dir) else {
+        return;
+    };
+    let mut bytes = fs::read(&debug).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xFF;
+    fs::write(&debug, bytes).unwrap();
+    let (mut elf_metadata, mut reader) = metadata(&stripped);
+    let none = dir.join("none").to_string_lossy().into_owned();
+    assert_eq!(
+        debuginfo::load_symbols(&mut elf_metadata, &mut reader, &[none]),
+        None
+    );
+    assert!(!has_symbol(&elf_metadata, "frob_widget"));
+}
+
+#[test]
+fn the_build_id_finds_the_debug_file_in_a_debuginfo_directory() {
+    let dir = fixture_dir("debuginfo-build-id");
+    let Some((stripped, debug)) = fixtures(&dir) else {
+        return;
+    };
+    let (elf_metadata, mut reader) = metadata(&stripped);
+    let build_id = read_build_id(&elf_metadata, &mut reader).unwrap().unwrap();
+    let debug_dir = dir.join("debug");
+    let by_id = debug_dir.join(".build-id").join(&build_id[..2]);
+    fs::create_dir_all(&by_id).unwrap();
+    let moved = by_id.join(format!("{}.debug", &build_id[2..]));
+    fs::rename(&debug, &moved).unwrap();
+
+    let debug_dir = debug_dir.to_string_lossy().into_owned();
+    assert_eq!(
+        debuginfo::find(&elf_metadata, &mut reader, std::slice::from_ref(&debug_dir)).unwrap(),
+        Some(moved.to_string_lossy().into_owned())
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args([
+            "inspect",
+            "--summary",
+            "--debuginfo-dir",
+            &debug_dir,
+            &stripped,
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let columns: Vec<&str> = stdout.trim_end().split(' ').collect();
+    assert_eq!(columns[9], build_id);
+    assert_eq!(columns[10], moved.to_string_lossy());
+}
+
+#[test]
+fn inspect_prints_the_merged_symbols() {
+    let dir = fixture_dir("debuginfo-inspect");
+    let Some((stripped, debug)) = fixtures(&dir) else {
+        return;
+    };
+    let none = dir.join("none").to_string_lossy().into_owned();
+    let output = Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(["inspect", "--debuginfo-dir", &none, &stripped])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(&format!("Debuginfo: {}\n", debug)),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("frob_widget"), "{}", stdout);
+}
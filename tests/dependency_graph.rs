@@ -0,0 +1,137 @@
+//! `drow resolve --dep-graph`: the DT_NEEDED graph of a fixture tree as DOT, with a cycle and an
+//! unresolved dependency.
+
+mod common;
+
+use std::collections::{BTreeSet, HashMap};
+use std::process::Command;
+
+use common::{data_library, fixture_dir, write_fixture};
+
+/// The first line of the label and the color of each node, by node id, and the edges between
+/// the first lines of their labels.
+struct Graph {
+    nodes: HashMap<String, (String, String)>,
+    edges: BTreeSet<(String, String)>,
+}
+
+fn attribute<'a>(line: &'a str, name: &str) -> &'a str {
+    let start = line.find(&format!("{}=", name)).unwrap() + name.len() + 1;
+    let value = &line[start..];
+    if let Some(quoted) = value.strip_prefix('"') {
+        &quoted[..quoted.find('"').unwrap()]
+    } else {
+        &value[..value.find([',', ']']).unwrap()]
+    }
+}
+
+fn parse(dot: &str) -> Graph {
+    let mut lines = dot.lines();
+    assert_eq!(lines.next(), Some("digraph dependencies {"), "{}", dot);
+    assert_eq!(dot.lines().last(), Some("}"), "{}", dot);
+    let mut nodes = HashMap::new();
+    let mut edges = Vec::new();
+    for line in lines.map(str::trim).filter(|line| *line != "}") {
+        if let Some((from, to)) = line.trim_end_matches(';').split_once(" -> ") {
+            edges.push((from.to_string(), to.to_string()));
+        } else {
+            let (id, _) = line.split_once(' ').unwrap();
+            let label = attribute(line, "label");
+            let name = label.split("\\n").next().unwrap();
+            nodes.insert(
+                id.to_string(),
+                (name.to_string(), attribute(line, "color").to_string()),
+            );
+        }
+    }
+    let edges = edges
+        .iter()
+        .map(|(from, to)| (nodes[from].0.clone(), nodes[to].0.clone()))
+        .collect();
+    Graph { nodes, edges }
+}
+
+#[test]
+fn the_graph_has_an_edge_per_needed_library_and_renders_cycles() {
+    let dir = fixture_dir("dependency-graph");
+    let root = write_fixture(
+        &dir,
+        "libroot.so",
+        &data_library("root_value", &[0; 8], 8)
+            .add_needed("libleft.so")
+            .add_needed("libright.so")
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    // libleft.so and libright.so need each other.
+    write_fixture(
+        &dir,
+        "libleft.so",
+        &data_library("left_value", &[0; 8], 8)
+            .add_needed("libright.so")
+            .add_needed("libabsent.so")
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    write_fixture(
+        &dir,
+        "libright.so",
+        &data_library("right_value", &[0; 8], 8)
+            .add_needed("libleft.so")
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    let dot_path = dir.join("dependencies.dot");
+    let output = Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(["resolve", "--offline", "--search-dir"])
+        .arg(&dir)
+        .arg("--dep-graph")
+        .arg(&dot_path)
+        .arg(&root)
+        .env_remove("LD_LIBRARY_PATH")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let dot = std::fs::read_to_string(&dot_path).unwrap();
+    let graph = parse(&dot);
+
+    let expected: BTreeSet<(String, String)> = [
+        ("libroot.so", "libleft.so"),
+        ("libroot.so", "libright.so"),
+        ("libleft.so", "libright.so"),
+        ("libleft.so", "libabsent.so"),
+        ("libright.so", "libleft.so"),
+    ]
+    .iter()
+    .map(|(from, to)| (from.to_string(), to.to_string()))
+    .collect();
+    assert_eq!(graph.edges, expected, "{}", dot);
+
+    // One node per object, each colored by where it was found.
+    let mut colors: Vec<(String, String)> = graph.nodes.into_values().collect();
+    colors.sort();
+    assert_eq!(
+        colors,
+        vec![
+            (String::from("libabsent.so"), String::from("red")),
+            (String::from("libleft.so"), String::from("darkcyan")),
+            (String::from("libright.so"), String::from("darkcyan")),
+            (String::from("libroot.so"), String::from("black")),
+        ],
+        "{}",
+        dot
+    );
+    assert!(
+        dot.contains(&format!(
+            "label=\"libleft.so\\n{}\"",
+            dir.join("libleft.so").display()
+        )),
+        "{}",
+        dot
+    );
+    assert!(
+        dot.contains("label=\"libabsent.so\\n(not found)\""),
+        "{}",
+        dot
+    );
+}
@@ -0,0 +1,178 @@
+//! Names read from the dynamic array through the dynamic string table: offsets at the edges of
+//! the table and past them, and a dynamic array without a string table.
+
+mod common;
+
+use std::io::Cursor;
+
+use common::data_library;
+use drow::string_tables::StrError;
+use drow::testutil::ElfBuilder;
+use drow::{DrowError, Elf64Metadata, ELF64_SECTION_HEADER_DYNAMIC};
+
+const DYNAMIC_TABLE_NEEDED: i64 = 1;
+const DYNAMIC_TABLE_STRING_TABLE: i64 = 5;
+const DYNAMIC_TABLE_SONAME: i64 = 14;
+const DYNAMIC_TABLE_RPATH: i64 = 15;
+
+/// The size of "\0value\0liblast.so\0", the .dynstr of `library`.
+const STRINGS_SIZE: u64 = 18;
+
+/// A library whose .dynstr holds the name of its symbol, then liblast.so.
+fn library() -> ElfBuilder {
+    data_library("value", &[0; 8], 8)
+        .add_needed("liblast.so")
+        .map_dynamic(0x3000)
+}
+
+fn parse(bytes: &[u8]) -> Result<Elf64Metadata, DrowError> {
+    Elf64Metadata::load(&String::from("fixture.so"), &mut Cursor::new(bytes))
+}
+
+fn invalid_string(result: Result<Elf64Metadata, DrowError>) -> (String, StrError) {
+    match result {
+        Err(DrowError::InvalidString { what, source }) => (what, source),
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+}
+
+fn malformed(result: Result<Elf64Metadata, DrowError>) -> String {
+    match result {
+        Err(DrowError::Malformed { what, .. }) => what,
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+}
+
+/// The address of .dynstr in `library`, which entries added to the dynamic array do not move.
+fn strings_address() -> u64 {
+    let metadata = parse(&library().finalize()).unwrap();
+    let dynamic = metadata
+        .section_headers
+        .iter()
+        .find(|section| section.sh_type == ELF64_SECTION_HEADER_DYNAMIC)
+        .unwrap();
+    let strings = &metadata.section_headers[dynamic.sh_link as usize];
+    assert_eq!(strings.sh_size, STRINGS_SIZE);
+    strings.sh_virtual_address
+}
+
+/// Points the link of .dynamic at the null section, so only DT_STRTAB can find its strings.
+fn unlink_dynamic(bytes: &mut [u8]) {
+    let metadata = parse(bytes).unwrap();
+    let index = metadata
+        .section_headers
+        .iter()
+        .position(|section| section.sh_type == ELF64_SECTION_HEADER_DYNAMIC)
+        .unwrap();
+    let header = metadata.elf_header.e_section_header_offset as usize + index * 64;
+    bytes[header + 40..header + 44].copy_from_slice(&0u32.to_le_bytes());
+}
+
+#[test]
+fn names_at_the_edges_of_the_table_are_read() {
+    let metadata = parse(
+        &library()
+            .add_dynamic(DYNAMIC_TABLE_STRING_TABLE, strings_address())
+            // The terminator of liblast.so, the last byte of the table.
+            .add_dynamic(DYNAMIC_TABLE_SONAME, STRINGS_SIZE - 1)
+            // Within liblast.so.
+            .add_dynamic(DYNAMIC_TABLE_RPATH, 10)
+            .finalize(),
+    )
+    .unwrap();
+    assert_eq!(
+        metadata.dynamic.required_libraries,
+        vec![String::from("liblast.so")]
+    );
+    assert_eq!(metadata.dynamic.soname, Some(String::new()));
+    assert_eq!(metadata.dynamic.rpath, Some(String::from("last.so")));
+}
+
+#[test]
+fn offsets_at_or_past_the_end_of_the_table_are_errors() {
+    let (what, source) = invalid_string(parse(
+        &library()
+            .add_dynamic(DYNAMIC_TABLE_NEEDED, STRINGS_SIZE)
+            .finalize(),
+    ));
+    assert_eq!(what, "required library name");
+    assert_eq!(
+        source,
+        StrError::OutOfBounds {
+            offset: STRINGS_SIZE as usize,
+            length: STRINGS_SIZE as usize
+        }
+    );
+    // Wider than any string table offset.
+    let (what, source) = invalid_string(parse(
+        &library()
+            .add_dynamic(DYNAMIC_TABLE_SONAME, 1 << 32)
+            .finalize(),
+    ));
+    assert_eq!(what, "soname");
+    assert_eq!(
+        source,
+        StrError::OutOfBounds {
+            offset: 1 << 32,
+            length: STRINGS_SIZE as usize
+        }
+    );
+}
+
+#[test]
+fn dt_strtab_finds_the_table_without_the_section_link() {
+    let mut bytes = library()
+        .add_dynamic(DYNAMIC_TABLE_STRING_TABLE, strings_address())
+        .finalize();
+    unlink_dynamic(&mut bytes);
+    let metadata = parse(&bytes).unwrap();
+    assert_eq!(
+        metadata.dynamic.required_libraries,
+        vec![String::from("liblast.so")]
+    );
+}
+
+#[test]
+fn the_section_link_is_used_when_dt_strtab_is_nowhere() {
+    let metadata = parse(
+        &library()
+            .add_dynamic(DYNAMIC_TABLE_STRING_TABLE, 0x9000)
+            .finalize(),
+    )
+    .unwrap();
+    assert_eq!(
+        metadata.dynamic.required_libraries,
+        vec![String::from("liblast.so")]
+    );
+}
+
+#[test]
+fn a_missing_string_table_is_an_error() {
+    let mut bytes = library().finalize();
+    unlink_dynamic(&mut bytes);
+    assert_eq!(
+        malformed(parse(&bytes)),
+        "dynamic section has no string table"
+    );
+
+    let mut bytes = library()
+        .add_dynamic(DYNAMIC_TABLE_STRING_TABLE, 0x9000)
+        .finalize();
+    unlink_dynamic(&mut bytes);
+    assert_eq!(
+        malformed(parse(&bytes)),
+        "DT_STRTAB 0x9000 is not the start of a string table"
+    );
+}
+
+#[test]
+fn a_dynamic_array_without_names_needs_no_string_table() {
+    let mut bytes = data_library("value", &[0; 8], 8)
+        .add_dynamic(DYNAMIC_TABLE_STRING_TABLE, 0x9000)
+        .map_dynamic(0x3000)
+        .finalize();
+    unlink_dynamic(&mut bytes);
+    let metadata = parse(&bytes).unwrap();
+    assert!(metadata.dynamic.required_libraries.is_empty());
+    assert_eq!(metadata.dynamic.soname, None);
+}
@@ -0,0 +1,722 @@
+//! Parsing and loading objects built with `ElfBuilder`.
+
+mod common;
+
+use std::io::{Cursor, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use common::{
+    data_library, fixture_dir, mapped_bytes, mapped_word, object_base, offline_loader,
+    write_fixture,
+};
+use drow::testutil::ElfBuilder;
+use drow::{
+    DrowError, Elf32Metadata, Elf64Metadata, Elf64ProgramHeader, PROGRAM_FLAG_READ,
+    PROGRAM_HEADER_TYPE_LOADABLE, PROGRAM_HEADER_TYPE_PHDR, PROGRAM_HEADER_TYPE_TLS,
+    RELOCATION_X86_64_32, RELOCATION_X86_64_64, RELOCATION_X86_64_COPY, RELOCATION_X86_64_DPTMOD64,
+    RELOCATION_X86_64_DTPOFF64, RELOCATION_X86_64_GLOB_DAT, RELOCATION_X86_64_IRELATIV,
+    RELOCATION_X86_64_JUMP_SLOT, RELOCATION_X86_64_PC32, RELOCATION_X86_64_RELATIVE,
+    RELOCATION_X86_64_TPOFF64, SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_OBJECT,
+};
+
+fn parse(bytes: &[u8]) -> Result<Elf64Metadata, DrowError> {
+    Elf64Metadata::load(&String::from("fixture.so"), &mut Cursor::new(bytes))
+}
+
+#[test]
+fn parses_segments_and_symbols() {
+    let metadata = parse(&data_library("value", &[1, 2, 3, 4], 0x20).finalize()).unwrap();
+    let segment = &metadata.program_headers[0];
+    assert_eq!(segment.p_type, PROGRAM_HEADER_TYPE_LOADABLE);
+    assert_eq!(segment.p_virtual_address, 0x1000);
+    assert_eq!(segment.p_file_size, 4);
+    assert_eq!(segment.p_memory_size, 0x20);
+    assert_eq!(segment.p_offset % 0x1000, 0);
+    let (_, symbol) = metadata.find_dynamic_symbol("value", |_, _| true).unwrap();
+    assert_eq!(symbol.value, 0x1000);
+    assert!(symbol.exported());
+}
+
+#[test]
+fn parses_needed_libraries() {
+    let bytes = data_library("value", &[0; 16], 16)
+        .add_needed("libone.so")
+        .add_needed("libtwo.so")
+        .finalize();
+    let metadata = parse(&bytes).unwrap();
+    assert_eq!(
+        metadata.dynamic.required_libraries,
+        vec![String::from("libone.so"), String::from("libtwo.so")]
+    );
+    assert!(metadata.dynamic.terminated);
+}
+
+#[test]
+fn parses_the_tls_segment() {
+    let bytes = data_library("value", &[0; 16], 16)
+        .add_segment(
+            PROGRAM_HEADER_TYPE_TLS,
+            PROGRAM_FLAG_READ,
+            0x1000,
+            &[5; 8],
+            0x20,
+        )
+        .finalize();
+    let metadata = parse(&bytes).unwrap();
+    assert_eq!(metadata.tls_segments.len(), 1);
+    let segment = &metadata.tls_segments[0];
+    assert_eq!(segment.virtual_address, 0x1000);
+    assert_eq!(segment.file_size, 8);
+    assert_eq!(segment.memory_size, 0x20);
+    assert_eq!(segment.module_id, None);
+}
+
+#[test]
+fn truncated_header_is_an_error() {
+    let bytes = data_library("value", &[0; 16], 16).finalize();
+    assert!(parse(&bytes[..40]).is_err());
+}
+
+#[test]
+fn foreign_class_is_an_error() {
+    let mut bytes = data_library("value", &[0; 16], 16).finalize();
+    bytes[4] = 1;
+    assert!(matches!(
+        parse(&bytes),
+        Err(DrowError::WrongClass { class: 1, .. })
+    ));
+}
+
+/// The header of a library built with `machine`, with `patch` written at `offset`.
+fn patched_header(machine: Option<u16>, offset: usize, patch: &[u8]) -> Vec<u8> {
+    let mut builder = data_library("value", &[0; 16], 16);
+    if let Some(machine) = machine {
+        builder = builder.machine(machine);
+    }
+    let mut bytes = builder.finalize();
+    bytes[offset..offset + patch.len()].copy_from_slice(patch);
+    bytes
+}
+
+#[test]
+fn each_rejected_header_field_has_its_own_error() {
+    /// EM_ARM, a machine drow does not load.
+    const MACHINE_ARM: u16 = 0x28;
+    let cases: Vec<(&str, Vec<u8>, &str)> = vec![
+        (
+            "magic",
+            patched_header(None, 1, b"X"),
+            "fixture.so is not an ELF file. 0x7F 0x58 0x4C 0x46",
+        ),
+        (
+            "class",
+            // Neither ELFCLASS32 nor ELFCLASS64.
+            patched_header(None, 4, &[3]),
+            "fixture.so: ELF64 required, found: 0x03",
+        ),
+        (
+            "encoding",
+            patched_header(None, 5, &[2]),
+            "fixture.so: Little Endian required, found: 0x02",
+        ),
+        (
+            "e_ident[EI_VERSION]",
+            patched_header(None, 6, &[0]),
+            "fixture.so: e_ident[EI_VERSION] 1 required, found: 0",
+        ),
+        (
+            "e_version",
+            patched_header(None, 20, &2u32.to_le_bytes()),
+            "fixture.so: e_version 1 required, found: 2",
+        ),
+        (
+            "e_machine",
+            patched_header(Some(MACHINE_ARM), 0, &[]),
+            "fixture.so: AMD64 or AArch64 expected, found: 0x28",
+        ),
+        (
+            "e_ehsize",
+            patched_header(None, 52, &56u16.to_le_bytes()),
+            "fixture.so: ELF header size 64 required, found: 56",
+        ),
+    ];
+    for (field, bytes, message) in cases {
+        let error = match parse(&bytes) {
+            Err(error) => error,
+            Ok(_) => panic!("a wrong {} is accepted", field),
+        };
+        let expected = match field {
+            "magic" => matches!(error, DrowError::NotElf { .. }),
+            "class" => matches!(error, DrowError::WrongClass { class: 3, .. }),
+            "encoding" => matches!(error, DrowError::WrongEncoding { encoding: 2, .. }),
+            "e_ident[EI_VERSION]" | "e_version" => matches!(
+                error,
+                DrowError::WrongVersion { field: found, .. } if found == field
+            ),
+            "e_machine" => matches!(error, DrowError::WrongMachine { machine: 0x28, .. }),
+            _ => matches!(error, DrowError::WrongHeaderSize { size: 56, .. }),
+        };
+        assert!(expected, "{}: {:?}", field, error);
+        assert_eq!(error.to_string(), message, "{}", field);
+    }
+}
+
+#[test]
+fn nonzero_ident_padding_is_only_a_warning() {
+    let bytes = patched_header(None, 9, b"packed!");
+    let metadata = parse(&bytes).unwrap();
+    assert_eq!(&metadata.elf_header.e_ident[9..], b"packed!");
+    let dir = fixture_dir("header-padding");
+    let path = write_fixture(&dir, "libpadding.so", &bytes);
+    let output = Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(["inspect", &path])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "{}: non-zero padding in e_ident: [70, 61, 63, 6B, 65, 64, 21]",
+            path
+        )),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn loading_maps_the_needed_libraries() {
+    let dir = fixture_dir("needed");
+    write_fixture(
+        &dir,
+        "libdependency.so",
+        &data_library("dependency_value", &[1; 8], 8).finalize(),
+    );
+    let path = write_fixture(
+        &dir,
+        "libroot.so",
+        &data_library("root_value", &[2; 8], 8)
+            .add_needed("libdependency.so")
+            .finalize(),
+    );
+    let loader = offline_loader(&dir);
+    loader.load_library(&path).unwrap();
+    let dependency = loader.lookup_symbol("dependency_value").unwrap();
+    assert_eq!(mapped_bytes(dependency, 8), vec![1; 8]);
+    assert_eq!(
+        mapped_bytes(loader.lookup_symbol("root_value").unwrap(), 8),
+        vec![2; 8]
+    );
+}
+
+#[test]
+fn missing_needed_library_is_reported() {
+    let dir = fixture_dir("missing-needed");
+    let path = write_fixture(
+        &dir,
+        "libroot.so",
+        &data_library("root_value", &[2; 8], 8)
+            .add_needed("libabsent.so")
+            .finalize(),
+    );
+    let loader = offline_loader(&dir);
+    match loader.load_library(&path) {
+        Err(DrowError::UnresolvedLibrary { name, trail }) => {
+            assert_eq!(name, "libabsent.so");
+            assert_eq!(trail, vec![path]);
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn parses_rela_entries() {
+    let bytes = data_library("value", &[0; 16], 16)
+        .add_rela(0x1000, RELOCATION_X86_64_64, Some("value"), 4)
+        .add_rela(0x1008, RELOCATION_X86_64_RELATIVE, None, 0x40)
+        .finalize();
+    let metadata = parse(&bytes).unwrap();
+    let relocations = &metadata.relocations;
+    assert_eq!(relocations.len(), 2);
+    assert_eq!(relocations[0].offset, 0x1000);
+    assert_eq!(relocations[0].relocation_type, RELOCATION_X86_64_64);
+    assert_eq!(relocations[0].symbol_name, "value");
+    assert_eq!(relocations[0].addend, 4);
+    assert_eq!(relocations[1].relocation_type, RELOCATION_X86_64_RELATIVE);
+    assert_eq!(relocations[1].symbol_index, 0);
+    assert_eq!(relocations[1].addend, 0x40);
+}
+
+#[test]
+fn rel_entries_take_their_addend_from_the_segment() {
+    let mut content = vec![0u8; 16];
+    content[8..].copy_from_slice(&0x1234u64.to_le_bytes());
+    let bytes = data_library("value", &content, 16)
+        .add_rel(0x1008, RELOCATION_X86_64_RELATIVE, None)
+        .add_rel(0x1000, RELOCATION_X86_64_64, Some("value"))
+        .finalize();
+    let metadata = parse(&bytes).unwrap();
+    let relocations = &metadata.relocations;
+    assert_eq!(relocations.len(), 2);
+    assert_eq!(relocations[0].offset, 0x1008);
+    assert_eq!(relocations[0].addend, 0x1234);
+    assert_eq!(relocations[1].symbol_name, "value");
+    assert_eq!(relocations[1].addend, 0);
+}
+
+#[test]
+fn mapping_rounds_segments_to_pages_and_the_base_to_their_alignment() {
+    let dir = fixture_dir("alignment");
+    let bytes = ElfBuilder::new()
+        .segment_alignment(0x20_0000)
+        .add_segment(
+            PROGRAM_HEADER_TYPE_LOADABLE,
+            PROGRAM_FLAG_READ,
+            0x1234,
+            &[7; 16],
+            16,
+        )
+        .finalize();
+    let path = write_fixture(&dir, "libaligned.so", &bytes);
+    let loader = offline_loader(&dir);
+    loader.load_library(&path).unwrap();
+    let entries: Vec<_> = loader
+        .memory_map_entries()
+        .into_iter()
+        .filter(|entry| entry.object == path)
+        .collect();
+    assert_eq!(entries.len(), 1);
+    let entry = &entries[0];
+    assert_eq!(entry.start % 0x1000, 0);
+    assert_eq!((entry.start - 0x1000) % 0x20_0000, 0);
+    assert_eq!(entry.end, entry.start + 0x1000);
+    assert_eq!(mapped_bytes(entry.start + 0x234, 16), vec![7; 16]);
+}
+
+#[test]
+fn memory_past_the_file_content_is_zeroed() {
+    let dir = fixture_dir("bss");
+    // The builder puts section contents right after the segment, so the rest of its last file
+    // page is not zero in the file.
+    let path = write_fixture(
+        &dir,
+        "libbss.so",
+        &data_library("value", &[0xAA; 16], 0x3000).finalize(),
+    );
+    let loader = offline_loader(&dir);
+    loader.load_library(&path).unwrap();
+    let address = loader.lookup_symbol("value").unwrap();
+    let bytes = mapped_bytes(address, 0x3000);
+    assert_eq!(bytes[..16], [0xAA; 16]);
+    assert!(bytes[16..].iter().all(|byte| *byte == 0));
+}
+
+fn program_header_table(loader: &drow::loader::Elf64Loader, path: &str) -> Vec<u8> {
+    let report = loader.load_report(false);
+    let object = report
+        .objects
+        .iter()
+        .find(|object| object.path == path)
+        .unwrap();
+    let table = object.program_headers;
+    mapped_bytes(
+        table.address,
+        table.count as usize * table.entry_size as usize,
+    )
+}
+
+#[test]
+fn program_headers_are_found_without_pt_phdr() {
+    let dir = fixture_dir("no-phdr");
+    let bytes = data_library("value", &[0; 16], 16).finalize();
+    let path = write_fixture(&dir, "libnophdr.so", &bytes);
+    let loader = offline_loader(&dir);
+    loader.load_library(&path).unwrap();
+    let metadata = parse(&bytes).unwrap();
+    let expected: Vec<u8> = metadata
+        .program_headers
+        .iter()
+        .flat_map(|header| drow::entry_bytes::<Elf64ProgramHeader>(header).to_vec())
+        .collect();
+    assert_eq!(program_header_table(&loader, &path), expected);
+}
+
+#[test]
+fn pt_phdr_locates_the_mapped_program_headers() {
+    let dir = fixture_dir("phdr");
+    let bytes = data_library("value", &[0; 16], 16)
+        .map_headers(0x10000)
+        .finalize();
+    let metadata = parse(&bytes).unwrap();
+    assert_eq!(metadata.program_headers[0].p_type, PROGRAM_HEADER_TYPE_PHDR);
+    let path = write_fixture(&dir, "libphdr.so", &bytes);
+    let loader = offline_loader(&dir);
+    loader.load_library(&path).unwrap();
+    let report = loader.load_report(false);
+    let object = report
+        .objects
+        .iter()
+        .find(|object| object.path == path)
+        .unwrap();
+    assert_eq!(object.program_headers.address, object.base + 0x10040);
+    assert_eq!(object.program_headers.count, 3);
+    assert_eq!(
+        program_header_table(&loader, &path),
+        bytes[0x40..0x40 + 3 * 56].to_vec()
+    );
+}
+
+#[test]
+fn program_headers_of_a_loadable_segment_are_found_without_pt_phdr() {
+    let dir = fixture_dir("mapped-no-phdr");
+    let mut bytes = data_library("value", &[0; 16], 16)
+        .map_headers(0x10000)
+        .finalize();
+    // Turns PT_PHDR into PT_NULL, leaving the table in the PT_LOAD covering the headers.
+    bytes[0x40..0x44].copy_from_slice(&0u32.to_le_bytes());
+    let path = write_fixture(&dir, "libmapped.so", &bytes);
+    let loader = offline_loader(&dir);
+    loader.load_library(&path).unwrap();
+    let report = loader.load_report(false);
+    let object = report
+        .objects
+        .iter()
+        .find(|object| object.path == path)
+        .unwrap();
+    assert_eq!(object.program_headers.address, object.base + 0x10040);
+    assert_eq!(
+        program_header_table(&loader, &path),
+        bytes[0x40..0x40 + 3 * 56].to_vec()
+    );
+}
+
+#[test]
+fn each_load_owns_its_copy_of_the_program_headers() {
+    let dir = fixture_dir("phdr-copy");
+    let bytes = data_library("value", &[0; 16], 16).finalize();
+    let path = write_fixture(&dir, "libcopy.so", &bytes);
+    let first = offline_loader(&dir);
+    first.load_library(&path).unwrap();
+    let expected = program_header_table(&first, &path);
+    for _ in 0..3 {
+        let loader = offline_loader(&dir);
+        loader.load_library(&path).unwrap();
+        assert_eq!(program_header_table(&loader, &path), expected);
+        assert!(loader.unload(&path).unwrap());
+        assert!(loader.load_report(false).objects.is_empty());
+    }
+    assert_eq!(program_header_table(&first, &path), expected);
+}
+
+/// libprovider.so defining `value`, and libgot.so needing it with its GOT slot at 0x1000.
+fn got_fixtures(test: &str) -> (PathBuf, String) {
+    let dir = fixture_dir(test);
+    write_fixture(
+        &dir,
+        "libprovider.so",
+        &data_library("value", &[7; 8], 8).finalize(),
+    );
+    let path = write_fixture(
+        &dir,
+        "libgot.so",
+        &data_library("slots", &[0; 8], 8)
+            .add_needed("libprovider.so")
+            .add_symbol("value", SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_OBJECT, 0, 0, 0)
+            .add_rela(0x1000, RELOCATION_X86_64_GLOB_DAT, Some("value"), 0)
+            .finalize(),
+    );
+    (dir, path)
+}
+
+#[test]
+fn the_got_slot_holds_the_address_lookup_finds() {
+    let (dir, path) = got_fixtures("got-slot");
+    let loader = offline_loader(&dir);
+    loader.load_library(&path).unwrap();
+    let value = loader.lookup_symbol("value").unwrap();
+    assert_eq!(mapped_word(object_base(&loader, &path) + 0x1000), value);
+    assert_eq!(mapped_bytes(value, 8), vec![7; 8]);
+}
+
+#[test]
+fn dump_got_prints_the_slot_as_resolved() {
+    let (dir, path) = got_fixtures("got-dump");
+    let output = Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args([
+            "run",
+            "--no-exec",
+            "--dump-got",
+            "--offline",
+            "--search-dir",
+        ])
+        .arg(&dir)
+        .arg(&path)
+        .env_remove("LD_LIBRARY_PATH")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let line = stdout
+        .lines()
+        .find(|line| line.contains("| R_X86_64_GLOB_DAT | value |"))
+        .unwrap_or_else(|| panic!("no slot of value in {}", stdout));
+    let fields: Vec<&str> = line.split(" | ").collect();
+    assert!(fields[0].starts_with("GOT slot "), "{}", line);
+    let expected = fields[3].trim_start_matches("expected: ");
+    let actual = fields[4].trim_start_matches("actual: ");
+    assert_eq!(expected, actual, "{}", line);
+    assert_eq!(fields[5], "OK", "{}", line);
+}
+
+#[test]
+fn program_headers_in_a_segment_at_address_0_are_mapped() {
+    let dir = fixture_dir("phdr-at-0");
+    let bytes = data_library("value", &[0; 16], 16)
+        .map_headers(0)
+        .finalize();
+    let metadata = parse(&bytes).unwrap();
+    assert_eq!(
+        metadata.program_headers[1].p_type,
+        PROGRAM_HEADER_TYPE_LOADABLE
+    );
+    assert_eq!(metadata.program_headers[1].p_virtual_address, 0);
+    let path = write_fixture(&dir, "libzero.so", &bytes);
+    let loader = offline_loader(&dir);
+    loader.load_library(&path).unwrap();
+    let base = object_base(&loader, &path);
+    assert_eq!(
+        loader.load_report(false).objects[0].program_headers.address,
+        base + 0x40
+    );
+    assert_eq!(mapped_bytes(base, 4), b"\x7fELF".to_vec());
+    assert_eq!(
+        program_header_table(&loader, &path),
+        bytes[0x40..0x40 + 3 * 56].to_vec()
+    );
+}
+
+/// One relocation of each type the printer names, against `value` or no symbol, with positive,
+/// negative and extreme addends, and a REL entry whose addend is in the segment.
+fn relocation_table_fixture() -> Vec<u8> {
+    let mut content = vec![0u8; 0x60];
+    content[0x38..0x40].copy_from_slice(&(-0x20i64).to_le_bytes());
+    data_library("value", &content, 0x60)
+        .add_rela(0x1000, RELOCATION_X86_64_64, Some("value"), 4)
+        .add_rela(0x1008, RELOCATION_X86_64_RELATIVE, None, -0x10)
+        .add_rela(0x1010, RELOCATION_X86_64_GLOB_DAT, Some("value"), 0)
+        .add_rela(0x1018, RELOCATION_X86_64_JUMP_SLOT, Some("value"), 0)
+        .add_rela(0x1020, RELOCATION_X86_64_PC32, Some("value"), -4)
+        .add_rela(0x1028, RELOCATION_X86_64_32, None, 0x7fff_ffff)
+        .add_rela(0x1030, RELOCATION_X86_64_IRELATIV, None, i64::MIN)
+        .add_rela(0x1040, RELOCATION_X86_64_COPY, Some("value"), 0)
+        .add_rela(0x1048, RELOCATION_X86_64_DPTMOD64, Some("value"), 0)
+        .add_rela(0x1050, RELOCATION_X86_64_DTPOFF64, Some("value"), -8)
+        .add_rela(0x1058, RELOCATION_X86_64_TPOFF64, Some("value"), 0x10)
+        .add_rel(0x1038, RELOCATION_X86_64_RELATIVE, None)
+        .finalize()
+}
+
+const RELOCATION_TABLES: &str = "\
+Relocation section '.rela.dyn' contains 11 entries
+Offset        Info          Type                Symbol  Addend
+000000001000  000100000001  R_X86_64_64         value   0x4
+000000001008  000000000008  R_X86_64_RELATIVE           -0x10
+000000001010  000100000006  R_X86_64_GLOB_DAT   value   0x0
+000000001018  000100000007  R_X86_64_JUMP_SLOT  value   0x0
+000000001020  000100000002  R_X86_64_PC32       value   -0x4
+000000001028  00000000000A  R_X86_64_32                 0x7FFFFFFF
+000000001030  000000000025  R_X86_64_IRELATIVE          -0x8000000000000000
+000000001040  000100000005  R_X86_64_COPY       value   0x0
+000000001048  000100000010  R_X86_64_DTPMOD64   value   0x0
+000000001050  000100000011  R_X86_64_DTPOFF64   value   -0x8
+000000001058  000100000012  R_X86_64_TPOFF64    value   0x10
+Relocation section '.rel.dyn' contains 1 entries
+Offset        Info          Type               Symbol  Addend
+000000001038  000000000008  R_X86_64_RELATIVE          -0x20
+";
+
+#[test]
+fn relocation_tables_are_printed_per_section() {
+    let dir = fixture_dir("relocation-tables");
+    let path = write_fixture(&dir, "librelocs.so", &relocation_table_fixture());
+    let output = Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(["inspect", &path])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let start = stdout
+        .find("Relocation section")
+        .unwrap_or_else(|| panic!("no relocations in {}", stdout));
+    let tables: String = stdout[start..]
+        .lines()
+        .take(RELOCATION_TABLES.lines().count())
+        .map(|line| format!("{}\n", line))
+        .collect();
+    assert_eq!(tables, RELOCATION_TABLES);
+}
+
+#[test]
+fn addends_are_signed_hex() {
+    assert_eq!(drow::format_addend(0), "0x0");
+    assert_eq!(drow::format_addend(0x7f), "0x7F");
+    assert_eq!(drow::format_addend(-1), "-0x1");
+    assert_eq!(drow::format_addend(i64::MIN), "-0x8000000000000000");
+    assert_eq!(drow::format_addend(i64::MAX), "0x7FFFFFFFFFFFFFFF");
+}
+
+/// A tag no dynamic linker knows, in the OS-specific range.
+const UNKNOWN_TAG: i64 = 0x6000_0042;
+
+/// DT_NULL, which ends the dynamic array.
+const DYNAMIC_TABLE_NULL: i64 = 0;
+
+#[test]
+fn the_dynamic_array_ends_at_the_first_dt_null() {
+    let metadata = parse(
+        &data_library("value", &[0; 8], 8)
+            .add_needed("libfirst.so")
+            .add_dynamic(UNKNOWN_TAG, 7)
+            .dynamic_terminator(true, 3)
+            .finalize(),
+    )
+    .unwrap();
+    assert!(metadata.dynamic.terminated);
+    assert_eq!(metadata.dynamic.required_libraries, vec!["libfirst.so"]);
+    assert_eq!(metadata.dynamic.unknown_tags, vec![(UNKNOWN_TAG, 7)]);
+
+    // Entries past an early DT_NULL are ignored, like the padding after it.
+    let metadata = parse(
+        &data_library("value", &[0; 8], 8)
+            .add_needed("libfirst.so")
+            .add_dynamic(DYNAMIC_TABLE_NULL, 0)
+            .add_dynamic(UNKNOWN_TAG, 7)
+            .finalize(),
+    )
+    .unwrap();
+    assert!(metadata.dynamic.terminated);
+    assert_eq!(metadata.dynamic.required_libraries, vec!["libfirst.so"]);
+    assert!(metadata.dynamic.unknown_tags.is_empty());
+}
+
+#[test]
+fn a_dynamic_array_without_dt_null_is_read_to_its_end() {
+    let dir = fixture_dir("elf-builder-unterminated-dynamic");
+    write_fixture(
+        &dir,
+        "libfirst.so",
+        &data_library("first", &[1; 8], 8).finalize(),
+    );
+    let bytes = data_library("value", &[0; 8], 8)
+        .add_needed("libfirst.so")
+        .add_dynamic(UNKNOWN_TAG, 7)
+        .dynamic_terminator(false, 0)
+        .finalize();
+    let metadata = parse(&bytes).unwrap();
+    assert!(!metadata.dynamic.terminated);
+    assert_eq!(metadata.dynamic.required_libraries, vec!["libfirst.so"]);
+    assert_eq!(metadata.dynamic.unknown_tags, vec![(UNKNOWN_TAG, 7)]);
+
+    let path = write_fixture(&dir, "libvalue.so", &bytes);
+    let loader = offline_loader(&dir);
+    loader.load_library(&path).unwrap();
+    assert_eq!(loader.load_report(false).objects.len(), 2);
+
+    let mut shell = Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(["shell", &path])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    shell
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"dynamic\nquit\n")
+        .unwrap();
+    let output = shell.wait_with_output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("(no NULL terminator)"), "{}", stdout);
+    assert!(stdout.contains("0x60000042 (unknown)"), "{}", stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("has no DT_NULL terminator"), "{}", stderr);
+}
+
+/// A 32-bit shared object for i386, whose only segment maps its first page at 0x1000.
+fn elf32_library() -> Vec<u8> {
+    let mut bytes = vec![0x7F, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    let half = |bytes: &mut Vec<u8>, value: u16| bytes.extend_from_slice(&value.to_le_bytes());
+    let word = |bytes: &mut Vec<u8>, value: u32| bytes.extend_from_slice(&value.to_le_bytes());
+    // e_type ET_DYN, e_machine EM_386, e_version.
+    half(&mut bytes, 3);
+    half(&mut bytes, 3);
+    word(&mut bytes, 1);
+    // e_entry, e_phoff right after the header, no section headers, e_flags.
+    for value in [0, 52, 0, 0] {
+        word(&mut bytes, value);
+    }
+    // e_ehsize, e_phentsize, e_phnum, e_shentsize, e_shnum, e_shstrndx.
+    for value in [52, 32, 1, 40, 0, 0] {
+        half(&mut bytes, value);
+    }
+    // PT_LOAD of the first 0x100 bytes, readable, page aligned.
+    for value in [1, 0, 0x1000, 0x1000, 0x100, 0x100, 4, 0x1000] {
+        word(&mut bytes, value);
+    }
+    bytes.resize(0x100, 0);
+    bytes
+}
+
+#[test]
+fn a_32_bit_object_is_inspected_but_not_loaded() {
+    let bytes = elf32_library();
+    let metadata =
+        Elf32Metadata::load(&String::from("lib32.so"), &mut Cursor::new(&bytes)).unwrap();
+    assert_eq!(metadata.elf_header.e_machine, 3);
+    assert_eq!(metadata.program_headers.len(), 1);
+    assert_eq!(metadata.program_headers[0].p_virtual_address, 0x1000);
+    assert!(matches!(
+        parse(&bytes),
+        Err(DrowError::WrongClass { class: 1, .. })
+    ));
+
+    let dir = fixture_dir("elf-builder-elf32");
+    let path = write_fixture(&dir, "lib32.so", &bytes);
+    match offline_loader(&dir).load_library(&path) {
+        Err(DrowError::WrongClass {
+            path: refused,
+            class,
+        }) => {
+            assert_eq!((refused, class), (path.clone(), 1));
+        }
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+    let drow = |arguments: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_drow"))
+            .args(arguments)
+            .arg(&path)
+            .env_remove("LD_LIBRARY_PATH")
+            .output()
+            .unwrap()
+    };
+    let output = drow(&["inspect"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("Class: ELF32\n"));
+    let output = drow(&[
+        "run",
+        "--no-exec",
+        "--offline",
+        "--search-dir",
+        dir.to_str().unwrap(),
+    ]);
+    assert!(!output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!("{}: ELF64 required, found: 0x01", path)),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,145 @@
+//! The exit status contract of the binary: the status of the loaded program as is, and 125 to
+//! 127 for the failures of drow itself.
+
+mod common;
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+use common::{compile, data_library, fixture_dir, write_fixture};
+
+const EXIT_USAGE: i32 = 125;
+const EXIT_LOAD_FAILED: i32 = 126;
+const EXIT_NOT_FOUND: i32 = 127;
+
+/// A relocation type x86-64 does not define.
+const RELOCATION_UNKNOWN: u64 = 200;
+
+fn drow(arguments: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(arguments)
+        .env_remove("LD_LIBRARY_PATH")
+        .output()
+        .unwrap()
+}
+
+/// `drow run --no-exec` searching `dir` only.
+fn load(dir: &Path, path: &str) -> Output {
+    let dir = dir.to_string_lossy();
+    drow(&["run", "--no-exec", "--offline", "--search-dir", &dir, path])
+}
+
+fn assert_status(output: &Output, status: i32) {
+    assert_eq!(output.status.code(), Some(status), "{:?}", output);
+}
+
+#[test]
+fn an_unknown_option_is_a_usage_error() {
+    let output = drow(&["run", "--no-such-option", "/bin/true"]);
+    assert_status(&output, EXIT_USAGE);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("run drow --help for usage"), "{}", stderr);
+}
+
+#[test]
+fn a_missing_path_is_a_usage_error() {
+    assert_status(&drow(&["run"]), EXIT_USAGE);
+}
+
+#[test]
+fn a_missing_file_is_not_found() {
+    let dir = fixture_dir("exit-missing-file");
+    let path = dir.join("libabsent.so");
+    assert_status(&load(&dir, &path.to_string_lossy()), EXIT_NOT_FOUND);
+}
+
+#[test]
+fn a_missing_dependency_is_not_found() {
+    let dir = fixture_dir("exit-missing-dependency");
+    let path = write_fixture(
+        &dir,
+        "libroot.so",
+        &data_library("root_value", &[1; 8], 8)
+            .add_needed("libabsent.so")
+            .finalize(),
+    );
+    let output = load(&dir, &path);
+    assert_status(&output, EXIT_NOT_FOUND);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("libabsent.so"), "{}", stderr);
+}
+
+#[test]
+fn a_file_that_is_not_elf_fails_to_load() {
+    let dir = fixture_dir("exit-not-elf");
+    let path = write_fixture(
+        &dir,
+        "libtext.so",
+        "not an object file\n".repeat(8).as_bytes(),
+    );
+    let output = load(&dir, &path);
+    assert_status(&output, EXIT_LOAD_FAILED);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("is not an ELF file"), "{}", stderr);
+    assert_status(&drow(&["inspect", &path]), EXIT_LOAD_FAILED);
+}
+
+#[test]
+fn an_unsupported_relocation_fails_to_load() {
+    let dir = fixture_dir("exit-relocation");
+    let path = write_fixture(
+        &dir,
+        "librelocation.so",
+        &data_library("value", &[0; 8], 8)
+            .add_rela(0x1000, RELOCATION_UNKNOWN, None, 0)
+            .finalize(),
+    );
+    let output = load(&dir, &path);
+    assert_status(&output, EXIT_LOAD_FAILED);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Unsupported relocation"), "{}", stderr);
+}
+
+/// Exits with `STATUS`, or dies of SIGSEGV with `-DCRASH`, with no libc.
+const PROGRAM: &str = "\
+void _start(void) {
+#ifdef CRASH
+    *(volatile int *)0 = 0;
+#endif
+    __asm__ volatile(\"syscall\" : : \"a\"(60), \"D\"(STATUS));
+    __builtin_unreachable();
+}
+";
+
+#[test]
+fn the_status_of_the_program_is_passed_through() {
+    let dir = fixture_dir("exit-passthrough");
+    // One past each reserved status, and one inside the range, which the program may use too.
+    for status in [1, 124, 126] {
+        let name = format!("exit{}", status);
+        let Some(program) = compile(
+            &dir,
+            &name,
+            PROGRAM,
+            &["-nostdlib", &format!("-DSTATUS={}", status)],
+        ) else {
+            return;
+        };
+        assert_status(&drow(&["run", &program]), status);
+        assert_status(&drow(&["run", "--fork", &program]), status);
+    }
+}
+
+#[test]
+fn a_program_killed_by_a_signal_exits_with_128_and_the_signal() {
+    let dir = fixture_dir("exit-signal");
+    let Some(program) = compile(
+        &dir,
+        "crash",
+        PROGRAM,
+        &["-nostdlib", "-DSTATUS=0", "-DCRASH"],
+    ) else {
+        return;
+    };
+    assert_status(&drow(&["run", "--fork", &program]), 128 + libc::SIGSEGV);
+}
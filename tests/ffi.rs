@@ -0,0 +1,103 @@
+//! The C API, used by examples/ffi_client.c built against the cdylib.
+#![cfg(feature = "ffi")]
+
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+
+use common::{compile, compile_file, fixture_dir};
+
+const EXAMPLE_LIBRARY: &str = "\
+unsigned long my_strlen(const char *string) {
+    unsigned long length = 0;
+    while (string[length] != 0) {
+        length++;
+    }
+    return length;
+}
+";
+
+/// The directory of a libdrow.so built with the ffi feature. Cargo names the cdylib the same for
+/// every feature set, so the one next to the test binaries may come from a build without it.
+fn cdylib_directory() -> &'static Path {
+    static DIRECTORY: OnceLock<PathBuf> = OnceLock::new();
+    DIRECTORY.get_or_init(|| {
+        let target = Path::new(env!("CARGO_TARGET_TMPDIR")).join("ffi");
+        let status = Command::new(env!("CARGO"))
+            .args(["build", "--lib", "--features", "ffi", "--target-dir"])
+            .arg(&target)
+            .current_dir(env!("CARGO_MANIFEST_DIR"))
+            .status()
+            .unwrap();
+        assert!(status.success(), "building libdrow.so failed: {}", status);
+        target.join("debug")
+    })
+}
+
+/// examples/ffi_client.c built in `dir` against libdrow.so.
+fn ffi_client(dir: &Path) -> Option<String> {
+    let library_directory = cdylib_directory();
+    compile_file(
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("examples/ffi_client.c"),
+        &dir.join("ffi_client"),
+        &[
+            &format!("-L{}", library_directory.display()),
+            &format!("-Wl,-rpath,{}", library_directory.display()),
+            "-ldrow",
+        ],
+    )
+}
+
+#[test]
+fn ffi_client_loads_a_library_and_calls_it() {
+    let dir = fixture_dir("ffi-client");
+    let Some(library) = compile(
+        &dir,
+        "libexample.so",
+        EXAMPLE_LIBRARY,
+        &["-shared", "-fPIC", "-O0"],
+    ) else {
+        return;
+    };
+    let Some(client) = ffi_client(&dir) else {
+        return;
+    };
+    // Cargo points LD_LIBRARY_PATH at its libdrow.so, which may lack the C API.
+    let output = Command::new(&client)
+        .arg(&library)
+        .env_remove("LD_LIBRARY_PATH")
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{:?}", output);
+    assert!(
+        stdout.starts_with(&format!("0: {} at 0x", library)),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("my_strlen(\"drow\") = 4"), "{}", stdout);
+}
+
+#[test]
+fn ffi_client_reports_a_missing_library() {
+    let dir = fixture_dir("ffi-client-missing");
+    let Some(client) = ffi_client(&dir) else {
+        return;
+    };
+    let output = Command::new(&client)
+        .arg(dir.join("libabsent.so"))
+        .env_remove("LD_LIBRARY_PATH")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr
+            .lines()
+            .any(|line| line.starts_with("drow_load: Unable to access")),
+        "{}",
+        stderr
+    );
+}
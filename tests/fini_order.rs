@@ -0,0 +1,162 @@
+//! The exit hook passed in rdx: registered first, as the C library does, it runs after the
+//! atexit handlers of the program, and runs the destructors of the program, then those of its
+//! dependencies backwards from the order they were loaded in, once.
+
+mod common;
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+use common::{compile, fixture_dir};
+
+/// Prints `text` with no libc.
+const PUT: &str = "\
+static void put(const char *text) {
+    long length = 0, result;
+    while (text[length])
+        length++;
+    __asm__ volatile(\"syscall\" : \"=a\"(result) : \"a\"(1), \"D\"(1), \"S\"(text), \"d\"(length)
+                     : \"rcx\", \"r11\", \"memory\");
+}
+";
+
+/// A library printing `NAME init` and `NAME fini` from its constructor and destructor.
+const LIBRARY: &str = "\
+__attribute__((constructor)) static void init(void) {
+    put(NAME \" init\\n\");
+}
+
+__attribute__((destructor)) static void fini(void) {
+    put(NAME \" fini\\n\");
+}
+";
+
+/// Does what the C library does with the hook it gets in rdx: registers it with atexit before
+/// main runs, so that it runs after the handlers main registers. Exits, or returns from the entry
+/// point without calling the hook with `-DRETURN`.
+const PROGRAM: &str = "\
+__attribute__((constructor)) static void init(void) {
+    put(\"program init\\n\");
+}
+
+__attribute__((destructor)) static void fini(void) {
+    put(\"program fini\\n\");
+}
+
+static void handler(void) {
+    put(\"atexit handler\\n\");
+}
+
+void start(void (*hook)(void)) {
+    put(\"main\\n\");
+#ifdef RETURN
+    (void)hook;
+#else
+    handler();
+    hook();
+    hook();
+    __asm__ volatile(\"syscall\" : : \"a\"(60), \"D\"(0));
+    __builtin_unreachable();
+#endif
+}
+
+__asm__(\".globl _start\\n\"
+        \"_start:\\n\"
+        \"  mov %rdx, %rdi\\n\"
+        \"  sub $8, %rsp\\n\"
+        \"  call start\\n\"
+        \"  add $8, %rsp\\n\"
+        \"  ret\\n\");
+";
+
+/// libdependency.so, libmiddle.so needing it, and the program needing libmiddle.so, built with
+/// `arguments`, or None without a C compiler. Nothing uses their symbols, hence --no-as-needed.
+fn build(dir: &Path, arguments: &[&str]) -> Option<String> {
+    let library_dir = dir.to_string_lossy();
+    let library = format!("{}{}", PUT, LIBRARY);
+    compile(
+        dir,
+        "libdependency.so",
+        &library,
+        &["-shared", "-fPIC", "-nostdlib", "-DNAME=\"dependency\""],
+    )?;
+    compile(
+        dir,
+        "libmiddle.so",
+        &library,
+        &[
+            "-shared",
+            "-fPIC",
+            "-nostdlib",
+            "-DNAME=\"middle\"",
+            "-Wl,--no-as-needed",
+            "-L",
+            &library_dir,
+            "-ldependency",
+        ],
+    )?;
+    let mut program_arguments = vec![
+        "-nostdlib",
+        "-fPIE",
+        "-pie",
+        "-Wl,--no-as-needed",
+        "-L",
+        &library_dir,
+        "-lmiddle",
+    ];
+    program_arguments.extend_from_slice(arguments);
+    compile(
+        dir,
+        "program",
+        &format!("{}{}", PUT, PROGRAM),
+        &program_arguments,
+    )
+}
+
+fn run(dir: &Path, program: &str, arguments: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_drow"))
+        .arg("run")
+        .args(arguments)
+        .arg("--offline")
+        .arg("--search-dir")
+        .arg(dir)
+        .arg(program)
+        .output()
+        .unwrap()
+}
+
+const INITS: &str = "dependency init\nmiddle init\nprogram init\nmain\n";
+const FINIS: &str = "program fini\nmiddle fini\ndependency fini\n";
+
+#[test]
+fn destructors_follow_the_atexit_handlers_in_reverse_load_order() {
+    let dir = fixture_dir("fini-order");
+    let Some(program) = build(&dir, &[]) else {
+        return;
+    };
+    for arguments in [&[][..], &["--fork"][..]] {
+        let output = run(&dir, &program, arguments);
+        assert!(output.status.success(), "{:?}: {:?}", arguments, output);
+        // Called twice, the hook runs the destructors once.
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            format!("{}atexit handler\n{}", INITS, FINIS),
+            "{:?}",
+            arguments
+        );
+    }
+}
+
+#[test]
+fn returning_from_the_entry_point_runs_the_destructors() {
+    let dir = fixture_dir("fini-return");
+    let Some(program) = build(&dir, &["-DRETURN"]) else {
+        return;
+    };
+    let output = run(&dir, &program, &["--fork"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        format!("{}{}", INITS, FINIS)
+    );
+}
@@ -0,0 +1,96 @@
+//! Section groups of a C++ object: each inline function and template instance is emitted in
+//! a COMDAT group of its own, named by its signature symbol.
+
+mod common;
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::process::Command;
+
+use common::{compile_file, fixture_dir};
+use drow::group::ELF64_SECTION_HEADER_GROUP;
+use drow::printer;
+use drow::Elf64Metadata;
+
+/// Without optimization, so the inline functions are emitted.
+const SOURCE: &str = "\
+inline int twice(int x) { return 2 * x; }
+template <typename T> T square(T x) { return x * x; }
+int use(int x) { return twice(x) + square(x) + (int)square<long>(x); }
+";
+
+const SIGNATURES: [&str; 3] = ["_Z5twicei", "_Z6squareIiET_S0_", "_Z6squareIlET_S0_"];
+
+/// The object compiled from `SOURCE` in `dir`, or None without a compiler.
+fn object(dir: &Path) -> Option<String> {
+    let source = dir.join("inline.cpp");
+    std::fs::write(&source, SOURCE).unwrap();
+    compile_file(&source, &dir.join("inline.o"), &["-c", "-O0"])
+}
+
+#[test]
+fn inline_functions_are_in_comdat_groups() {
+    let dir = fixture_dir("group-parse");
+    let Some(path) = object(&dir) else {
+        return;
+    };
+    let mut reader = BufReader::new(File::open(&path).unwrap());
+    let metadata = Elf64Metadata::load(&path, &mut reader).unwrap();
+    let strings = printer::string_tables_content(&metadata, &mut reader).unwrap();
+    let names = printer::section_names(&metadata, &strings);
+
+    let signatures: Vec<&str> = metadata
+        .groups
+        .iter()
+        .map(|group| group.signature.as_str())
+        .collect();
+    assert_eq!(signatures, SIGNATURES);
+    for group in metadata.groups.iter() {
+        assert!(group.comdat(), "{}", group.signature);
+        let header = &metadata.section_headers[group.section_index];
+        assert_eq!(header.sh_type, ELF64_SECTION_HEADER_GROUP);
+        assert_eq!(names[group.section_index], ".group");
+        // The flag word, then a word per member.
+        assert_eq!(header.sh_size, 4 * (1 + group.members.len() as u64));
+        let members: Vec<&str> = group
+            .members
+            .iter()
+            .map(|member| names[*member as usize].as_str())
+            .collect();
+        assert_eq!(members, vec![format!(".text.{}", group.signature)]);
+    }
+}
+
+#[test]
+fn inspect_prints_the_groups_like_readelf() {
+    let dir = fixture_dir("group-print");
+    let Some(path) = object(&dir) else {
+        return;
+    };
+    let output = Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(["inspect", "--color=never", &path])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines();
+    for (index, signature) in SIGNATURES.iter().enumerate() {
+        let heading = format!(
+            "COMDAT group section [{}] .group [{}] contains 1 sections",
+            index + 1,
+            signature
+        );
+        assert!(lines.any(|line| line == heading), "{}\n{}", heading, stdout);
+        assert_eq!(lines.next(), Some("Index  Name"));
+        let member: Vec<&str> = lines.next().unwrap().split_whitespace().collect();
+        assert_eq!(member[1], format!(".text.{}", signature));
+    }
+    let types: Vec<&str> = stdout
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>())
+        .filter(|row| row.get(1) == Some(&".group"))
+        .map(|row| row[2])
+        .collect();
+    assert_eq!(types, vec!["GROUP"; 3]);
+}
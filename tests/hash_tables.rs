@@ -0,0 +1,297 @@
+//! `.gnu.hash` and `.hash`: the symbols a lookup finds through them, the ones it does not, and
+//! what the bloom filter turns away before the buckets are read.
+
+mod common;
+
+use std::io::Cursor;
+
+use common::{compile, data_library, fixture_dir};
+use drow::gnu_hash::{gnu_hash, ELF64_SECTION_HEADER_GNU_HASH};
+use drow::sysv_hash::{sysv_hash, ELF64_SECTION_HEADER_HASH};
+use drow::testutil::ElfBuilder;
+use drow::{DrowError, Elf64Metadata, SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_OBJECT};
+
+/// The dynamic symbols of every hand built fixture, after the null symbol.
+const NAMES: [&str; 4] = ["value", "alpha", "beta", "gamma"];
+
+/// A name none of the fixtures define.
+const ABSENT: &str = "delta";
+
+/// Bits of a bloom filter word.
+const BLOOM_BITS: u32 = 64;
+
+/// Shift of the second bloom filter bit of each hash.
+const BLOOM_SHIFT: u32 = 6;
+
+/// A library defining `NAMES` in order, each an 8 byte variable of its .data.
+fn library() -> ElfBuilder {
+    NAMES[1..].iter().enumerate().fold(
+        data_library(NAMES[0], &[0; 0x20], 0x20),
+        |builder, (index, name)| {
+            builder.add_symbol(
+                name,
+                SYMBOL_BINDING_GLOBAL,
+                SYMBOL_TYPE_OBJECT,
+                1,
+                0x1008 + 8 * index as u64,
+                8,
+            )
+        },
+    )
+}
+
+fn parse(bytes: &[u8]) -> Result<Elf64Metadata, DrowError> {
+    Elf64Metadata::load(&String::from("fixture.so"), &mut Cursor::new(bytes))
+}
+
+/// The bloom filter bits of `hash`, in a filter of one word.
+fn bloom_bits(hash: u32) -> u64 {
+    (1 << (hash % BLOOM_BITS)) | (1 << ((hash >> BLOOM_SHIFT) % BLOOM_BITS))
+}
+
+/// `.gnu.hash` over `NAMES`, as the linker lays it out: one bucket holding every symbol from
+/// index 1 and a one word bloom filter, replaced with `bloom` when given.
+fn gnu_hash_section(bucket_count: u32, bloom: Option<u64>) -> Vec<u8> {
+    let hashes: Vec<u32> = NAMES.iter().map(|name| gnu_hash(name)).collect();
+    let bloom =
+        bloom.unwrap_or_else(|| hashes.iter().fold(0, |word, hash| word | bloom_bits(*hash)));
+    let mut words = vec![bucket_count, 1, 1, BLOOM_SHIFT];
+    words.extend([bloom as u32, (bloom >> 32) as u32]);
+    words.extend((0..bucket_count).map(|bucket| if bucket == 0 { 1 } else { 0 }));
+    words.extend(hashes.iter().enumerate().map(|(index, hash)| {
+        if index + 1 == hashes.len() {
+            hash | 1
+        } else {
+            hash & !1
+        }
+    }));
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+/// `.hash` over `NAMES` in `bucket_count` buckets, each symbol put first in its bucket as the
+/// linker does, with a chain for every dynamic symbol.
+fn sysv_hash_section(bucket_count: u32) -> Vec<u8> {
+    let mut buckets = vec![0; bucket_count.max(1) as usize];
+    let mut chains = vec![0; NAMES.len() + 1];
+    for (index, name) in NAMES.iter().enumerate() {
+        let bucket = sysv_hash(name) as usize % buckets.len();
+        chains[index + 1] = buckets[bucket];
+        buckets[bucket] = index as u32 + 1;
+    }
+    buckets.truncate(bucket_count as usize);
+    let mut words = vec![bucket_count, chains.len() as u32];
+    words.extend(buckets);
+    words.extend(chains);
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+fn with_sysv_hash(bucket_count: u32) -> Result<Elf64Metadata, DrowError> {
+    parse(
+        &library()
+            .add_section(
+                ".hash",
+                ELF64_SECTION_HEADER_HASH,
+                0,
+                &sysv_hash_section(bucket_count),
+            )
+            .finalize(),
+    )
+}
+
+fn with_gnu_hash(bloom: Option<u64>) -> Elf64Metadata {
+    parse(
+        &library()
+            .add_section(
+                ".gnu.hash",
+                ELF64_SECTION_HEADER_GNU_HASH,
+                0,
+                &gnu_hash_section(1, bloom),
+            )
+            .finalize(),
+    )
+    .unwrap()
+}
+
+#[test]
+fn gnu_hash_finds_the_symbols_it_holds() {
+    let metadata = with_gnu_hash(None);
+    let names: Vec<&str> = metadata
+        .dynamic_symbol_table
+        .iter()
+        .skip(1)
+        .map(|symbol| symbol.symbol_name.as_str())
+        .collect();
+    assert_eq!(names, NAMES);
+    let table = metadata.gnu_hash.as_ref().unwrap();
+    assert_eq!(table.exported(&metadata.dynamic_symbol_table).len(), 4);
+    for (index, name) in NAMES.iter().enumerate() {
+        let symbol = table.lookup(name, &metadata.dynamic_symbol_table).unwrap();
+        assert_eq!(symbol.symbol_name, *name);
+        let (found, _) = metadata.find_dynamic_symbol(name, |_, _| true).unwrap();
+        assert_eq!(found, index + 1);
+    }
+    // A symbol `accept` refuses is not found either.
+    assert!(table
+        .lookup_with("beta", &metadata.dynamic_symbol_table, |_, _| false)
+        .is_none());
+}
+
+#[test]
+fn gnu_hash_does_not_find_an_absent_symbol() {
+    let metadata = with_gnu_hash(None);
+    let table = metadata.gnu_hash.as_ref().unwrap();
+    assert!(table
+        .lookup(ABSENT, &metadata.dynamic_symbol_table)
+        .is_none());
+    assert!(metadata.find_dynamic_symbol(ABSENT, |_, _| true).is_none());
+    // A prefix of a name hashes differently.
+    assert!(table
+        .lookup("alph", &metadata.dynamic_symbol_table)
+        .is_none());
+}
+
+#[test]
+fn a_bloom_filter_miss_ends_the_lookup_before_the_buckets() {
+    // With every filter bit set but those of `alpha`, the buckets are never read for it.
+    let bloom = !bloom_bits(gnu_hash("alpha"));
+    let metadata = with_gnu_hash(Some(bloom));
+    let table = metadata.gnu_hash.as_ref().unwrap();
+    assert!(table
+        .lookup("alpha", &metadata.dynamic_symbol_table)
+        .is_none());
+    assert!(metadata.find_dynamic_symbol("alpha", |_, _| true).is_none());
+    // An empty filter turns every name away.
+    let metadata = with_gnu_hash(Some(0));
+    for name in NAMES.iter() {
+        assert!(metadata.find_dynamic_symbol(name, |_, _| true).is_none());
+    }
+}
+
+#[test]
+fn a_gnu_hash_table_without_buckets_is_malformed() {
+    let bytes = library()
+        .add_section(
+            ".gnu.hash",
+            ELF64_SECTION_HEADER_GNU_HASH,
+            0,
+            &gnu_hash_section(0, None),
+        )
+        .finalize();
+    match parse(&bytes) {
+        Err(DrowError::Malformed { what, .. }) => {
+            assert_eq!(what, "GNU hash table has 0 buckets and 1 bloom words")
+        }
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn a_library_linked_with_gnu_hash_finds_its_exports_through_it() {
+    let dir = fixture_dir("hash-tables-gnu");
+    let Some(path) = compile(
+        &dir,
+        "libgnu.so",
+        "int exported_value = 1;\nint exported_function(void) { return 2; }\n",
+        &["-shared", "-fPIC", "-Wl,--hash-style=gnu"],
+    ) else {
+        return;
+    };
+    let bytes = std::fs::read(&path).unwrap();
+    let metadata = parse(&bytes).unwrap();
+    let table = metadata.gnu_hash.as_ref().unwrap();
+    assert!(metadata.sysv_hash.is_none());
+    for name in ["exported_value", "exported_function"] {
+        let (index, symbol) = metadata.find_dynamic_symbol(name, |_, _| true).unwrap();
+        assert_eq!(symbol.symbol_name, name);
+        assert!(index >= table.symbol_offset as usize);
+    }
+    assert!(metadata.find_dynamic_symbol(ABSENT, |_, _| true).is_none());
+}
+
+#[test]
+fn sysv_hash_finds_the_symbols_it_holds() {
+    for bucket_count in [1, 2, 3] {
+        let metadata = with_sysv_hash(bucket_count).unwrap();
+        assert!(metadata.gnu_hash.is_none());
+        let table = metadata.sysv_hash.as_ref().unwrap();
+        assert_eq!(table.buckets.len(), bucket_count as usize);
+        assert_eq!(table.symbol_count(), metadata.dynamic_symbol_table.len());
+        for (index, name) in NAMES.iter().enumerate() {
+            let symbol = table.lookup(name, &metadata.dynamic_symbol_table).unwrap();
+            assert_eq!(symbol.symbol_name, *name);
+            let (found, _) = metadata.find_dynamic_symbol(name, |_, _| true).unwrap();
+            assert_eq!(found, index + 1, "{} in {} buckets", name, bucket_count);
+        }
+        assert!(table
+            .lookup_with("beta", &metadata.dynamic_symbol_table, |_, _| false)
+            .is_none());
+    }
+}
+
+#[test]
+fn sysv_hash_does_not_find_an_absent_symbol() {
+    for bucket_count in [1, 2, 3] {
+        let metadata = with_sysv_hash(bucket_count).unwrap();
+        let table = metadata.sysv_hash.as_ref().unwrap();
+        assert!(table
+            .lookup(ABSENT, &metadata.dynamic_symbol_table)
+            .is_none());
+        assert!(table
+            .lookup("alph", &metadata.dynamic_symbol_table)
+            .is_none());
+        assert!(metadata.find_dynamic_symbol(ABSENT, |_, _| true).is_none());
+    }
+}
+
+#[test]
+fn a_sysv_hash_table_without_buckets_is_malformed() {
+    match with_sysv_hash(0) {
+        Err(DrowError::Malformed { what, .. }) => {
+            assert_eq!(what, "SysV hash table has no buckets")
+        }
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn gnu_hash_is_looked_up_before_sysv_hash() {
+    let bytes = library()
+        .add_section(".hash", ELF64_SECTION_HEADER_HASH, 0, &sysv_hash_section(2))
+        .add_section(
+            ".gnu.hash",
+            ELF64_SECTION_HEADER_GNU_HASH,
+            0,
+            &gnu_hash_section(1, Some(0)),
+        )
+        .finalize();
+    let metadata = parse(&bytes).unwrap();
+    let table = metadata.sysv_hash.as_ref().unwrap();
+    assert!(table
+        .lookup("alpha", &metadata.dynamic_symbol_table)
+        .is_some());
+    // The empty bloom filter of .gnu.hash answers, .hash is not asked.
+    assert!(metadata.find_dynamic_symbol("alpha", |_, _| true).is_none());
+}
+
+#[test]
+fn a_library_linked_with_sysv_hash_finds_its_exports_through_it() {
+    let dir = fixture_dir("hash-tables-sysv");
+    let Some(path) = compile(
+        &dir,
+        "libsysv.so",
+        "int exported_value = 1;\nint exported_function(void) { return 2; }\n",
+        &["-shared", "-fPIC", "-Wl,--hash-style=sysv"],
+    ) else {
+        return;
+    };
+    let bytes = std::fs::read(&path).unwrap();
+    let metadata = parse(&bytes).unwrap();
+    assert!(metadata.gnu_hash.is_none());
+    let table = metadata.sysv_hash.as_ref().unwrap();
+    assert_eq!(table.symbol_count(), metadata.dynamic_symbol_table.len());
+    for name in ["exported_value", "exported_function"] {
+        let (_, symbol) = metadata.find_dynamic_symbol(name, |_, _| true).unwrap();
+        assert_eq!(symbol.symbol_name, name);
+    }
+    assert!(metadata.find_dynamic_symbol(ABSENT, |_, _| true).is_none());
+}
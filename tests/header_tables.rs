@@ -0,0 +1,210 @@
+//! The program and section header tables: read whole once they fit in the file, refused when
+//! their counts are inflated or their entries too short, and decoded from the start of entries
+//! larger than the structures.
+
+mod common;
+
+use std::convert::TryInto;
+use std::io::Cursor;
+
+use common::data_library;
+use drow::{DrowError, Elf64Metadata, Elf64ProgramHeader, Elf64SectionHeader};
+
+/// Offsets of e_phoff, e_shoff, e_phentsize, e_phnum, e_shentsize and e_shnum in the header.
+const PROGRAM_HEADER_OFFSET: usize = 0x20;
+const SECTION_HEADER_OFFSET: usize = 0x28;
+const PROGRAM_HEADER_ENTRY_SIZE: usize = 0x36;
+const PROGRAM_HEADER_ENTRIES: usize = 0x38;
+const SECTION_HEADER_ENTRY_SIZE: usize = 0x3A;
+const SECTION_HEADER_ENTRIES: usize = 0x3C;
+
+fn library() -> Vec<u8> {
+    data_library("value", &[0; 8], 8)
+        .add_needed("libneeded.so")
+        .map_dynamic(0x3000)
+        .finalize()
+}
+
+fn parse(bytes: &[u8]) -> Result<Elf64Metadata, DrowError> {
+    Elf64Metadata::load(&String::from("fixture.so"), &mut Cursor::new(bytes))
+}
+
+fn malformed(bytes: &[u8]) -> String {
+    match parse(bytes) {
+        Err(DrowError::Malformed { what, .. }) => what,
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+}
+
+fn half_word(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn set_half_word(bytes: &mut [u8], offset: usize, value: u16) {
+    bytes[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn word(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+fn program_header(header: &Elf64ProgramHeader) -> [u64; 8] {
+    [
+        header.p_type as u64,
+        header.p_flags as u64,
+        header.p_offset,
+        header.p_virtual_address,
+        header.p_physical_address,
+        header.p_file_size,
+        header.p_memory_size,
+        header.p_align,
+    ]
+}
+
+fn section_header(header: &Elf64SectionHeader) -> [u64; 10] {
+    [
+        header.sh_name as u64,
+        header.sh_type as u64,
+        header.sh_flags,
+        header.sh_virtual_address,
+        header.sh_offset,
+        header.sh_size,
+        header.sh_link as u64,
+        header.sh_info as u64,
+        header.sh_address_align,
+        header.sh_entry_size,
+    ]
+}
+
+/// Copies the table at `table_offset` to the end of the file with `padding` bytes after each
+/// entry, and points the header at the copy.
+fn pad_entries(
+    bytes: &mut Vec<u8>,
+    table_offset: usize,
+    entry_size_offset: usize,
+    entries_offset: usize,
+    padding: u16,
+) {
+    let offset = word(bytes, table_offset) as usize;
+    let entry_size = half_word(bytes, entry_size_offset) as usize;
+    let entries = half_word(bytes, entries_offset) as usize;
+    let table = bytes[offset..offset + entry_size * entries].to_vec();
+    bytes.resize((bytes.len() + 7) & !7, 0);
+    let copy = bytes.len() as u64;
+    for entry in table.chunks(entry_size) {
+        bytes.extend_from_slice(entry);
+        bytes.extend(std::iter::repeat_n(0xAA, padding as usize));
+    }
+    bytes[table_offset..table_offset + 8].copy_from_slice(&copy.to_le_bytes());
+    set_half_word(bytes, entry_size_offset, entry_size as u16 + padding);
+}
+
+#[test]
+fn the_tables_of_a_normal_file_are_read() {
+    let bytes = library();
+    let metadata = parse(&bytes).unwrap();
+    assert_eq!(
+        metadata.program_headers.len(),
+        half_word(&bytes, PROGRAM_HEADER_ENTRIES) as usize
+    );
+    assert_eq!(
+        metadata.section_headers.len(),
+        half_word(&bytes, SECTION_HEADER_ENTRIES) as usize
+    );
+    assert_eq!(half_word(&bytes, PROGRAM_HEADER_ENTRY_SIZE), 56);
+    assert_eq!(half_word(&bytes, SECTION_HEADER_ENTRY_SIZE), 64);
+    assert_eq!(
+        metadata.dynamic.required_libraries,
+        vec![String::from("libneeded.so")]
+    );
+}
+
+#[test]
+fn inflated_entry_counts_are_refused() {
+    let mut bytes = library();
+    set_half_word(&mut bytes, PROGRAM_HEADER_ENTRIES, u16::MAX);
+    assert_eq!(
+        malformed(&bytes),
+        "65535 program header entries of 56 bytes extend past the end of the file"
+    );
+
+    let mut bytes = library();
+    set_half_word(&mut bytes, SECTION_HEADER_ENTRIES, u16::MAX);
+    assert_eq!(
+        malformed(&bytes),
+        "65535 section header entries of 64 bytes extend past the end of the file"
+    );
+
+    // A table starting past the end of the file.
+    let mut bytes = library();
+    let past = (bytes.len() as u64 + 1).to_le_bytes();
+    bytes[SECTION_HEADER_OFFSET..SECTION_HEADER_OFFSET + 8].copy_from_slice(&past);
+    let entries = half_word(&bytes, SECTION_HEADER_ENTRIES);
+    assert_eq!(
+        malformed(&bytes),
+        format!(
+            "{} section header entries of 64 bytes extend past the end of the file",
+            entries
+        )
+    );
+}
+
+#[test]
+fn entries_shorter_than_the_structures_are_refused() {
+    let mut bytes = library();
+    set_half_word(&mut bytes, PROGRAM_HEADER_ENTRY_SIZE, 32);
+    assert_eq!(
+        malformed(&bytes),
+        "program header entries of 32 bytes, shorter than the 56 bytes of an entry"
+    );
+    let mut bytes = library();
+    set_half_word(&mut bytes, SECTION_HEADER_ENTRY_SIZE, 40);
+    assert_eq!(
+        malformed(&bytes),
+        "section header entries of 40 bytes, shorter than the 64 bytes of an entry"
+    );
+}
+
+#[test]
+fn oversized_entries_are_decoded_from_their_start() {
+    let bytes = library();
+    let expected = parse(&bytes).unwrap();
+    let mut padded = bytes.clone();
+    pad_entries(
+        &mut padded,
+        PROGRAM_HEADER_OFFSET,
+        PROGRAM_HEADER_ENTRY_SIZE,
+        PROGRAM_HEADER_ENTRIES,
+        8,
+    );
+    pad_entries(
+        &mut padded,
+        SECTION_HEADER_OFFSET,
+        SECTION_HEADER_ENTRY_SIZE,
+        SECTION_HEADER_ENTRIES,
+        24,
+    );
+    let metadata = parse(&padded).unwrap();
+    assert_eq!(half_word(&padded, PROGRAM_HEADER_ENTRY_SIZE), 64);
+    assert_eq!(half_word(&padded, SECTION_HEADER_ENTRY_SIZE), 88);
+    let programs = |metadata: &Elf64Metadata| -> Vec<[u64; 8]> {
+        metadata
+            .program_headers
+            .iter()
+            .map(program_header)
+            .collect()
+    };
+    let sections = |metadata: &Elf64Metadata| -> Vec<[u64; 10]> {
+        metadata
+            .section_headers
+            .iter()
+            .map(section_header)
+            .collect()
+    };
+    assert_eq!(programs(&metadata), programs(&expected));
+    assert_eq!(sections(&metadata), sections(&expected));
+    assert_eq!(
+        metadata.dynamic.required_libraries,
+        expected.dynamic.required_libraries
+    );
+}
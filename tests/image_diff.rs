@@ -0,0 +1,167 @@
+//! The bytes of a loaded object that differ from its file, each explained by the relocations
+//! writing it, and a write no relocation explains flagged.
+
+mod common;
+
+use std::path::Path;
+use std::process::Command;
+
+use common::{data_library, fixture_dir, object_base, offline_loader, write_fixture};
+use drow::loader::Elf64Loader;
+use drow::{
+    RELOCATION_X86_64_64, RELOCATION_X86_64_GLOB_DAT, RELOCATION_X86_64_RELATIVE,
+    SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_OBJECT,
+};
+
+/// Where the relocations of libdiff.so write, at link time addresses: a RELATIVE word, and,
+/// after 0x10 untouched bytes, a GLOB_DAT and a 64 relocation to `target`.
+const RELATIVE: u64 = 0x1000;
+const GLOB_DAT: u64 = 0x1018;
+const ABSOLUTE: u64 = 0x1020;
+/// A byte of its data no relocation writes.
+const UNTOUCHED: u64 = 0x1038;
+
+/// libdiff.so, whose relocations write to its data, and libtarget.so defining `target` for them.
+fn fixtures(dir: &Path) -> String {
+    write_fixture(
+        dir,
+        "libtarget.so",
+        &data_library("target", &[7; 8], 8)
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    write_fixture(
+        dir,
+        "libdiff.so",
+        &data_library("table", &[0; 0x40], 0x40)
+            .add_symbol("target", SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_OBJECT, 0, 0, 0)
+            .add_rela(RELATIVE, RELOCATION_X86_64_RELATIVE, None, 0x1030)
+            .add_rela(GLOB_DAT, RELOCATION_X86_64_GLOB_DAT, Some("target"), 0)
+            .add_rela(ABSOLUTE, RELOCATION_X86_64_64, Some("target"), 4)
+            .add_needed("libtarget.so")
+            .map_dynamic(0x3000)
+            .finalize(),
+    )
+}
+
+fn load(dir: &Path, path: &str) -> (Elf64Loader, u64) {
+    let loader = offline_loader(dir);
+    loader.load_library(path).unwrap();
+    let base = object_base(&loader, path);
+    (loader, base)
+}
+
+#[test]
+fn every_difference_is_a_relocation_target() {
+    let dir = fixture_dir("image-diff-explained");
+    let path = fixtures(&dir);
+    let (loader, base) = load(&dir, &path);
+    let diffs = loader.diff_image(64);
+    let object = diffs.iter().find(|object| object.path == path).unwrap();
+    assert_eq!(object.base, base);
+    assert_eq!(object.unexplained, 0);
+    assert_eq!(object.omitted, 0);
+    let targets = [(RELATIVE, 8), (GLOB_DAT, 8), (ABSOLUTE, 8)];
+    let ranges: Vec<(u64, usize, Vec<String>)> = object
+        .differences
+        .iter()
+        .map(|difference| {
+            (
+                difference.address - base,
+                difference.after.len(),
+                difference.relocations.clone(),
+            )
+        })
+        .collect();
+    // The 0x10 untouched bytes split the RELATIVE word from the two others.
+    assert_eq!(ranges.len(), 2, "{:?}", ranges);
+    for difference in object.differences.iter() {
+        assert!(!difference.unexplained, "{:?}", difference);
+        // Each changed byte, not only the range, is written by a relocation.
+        for (index, (before, after)) in difference.before.iter().zip(&difference.after).enumerate()
+        {
+            let address = difference.address - base + index as u64;
+            assert!(
+                before == after
+                    || targets
+                        .iter()
+                        .any(|(target, width)| (*target..target + width).contains(&address)),
+                "{:#x} is not a relocation target",
+                address
+            );
+        }
+    }
+    assert_eq!(ranges[0].2, vec!["R_X86_64_RELATIVE"]);
+    assert_eq!(
+        ranges[1].2,
+        vec!["R_X86_64_GLOB_DAT target", "R_X86_64_64 target"]
+    );
+    // Nothing of libtarget.so is relocated.
+    let target = diffs.iter().find(|object| object.path != path).unwrap();
+    assert!(target.differences.is_empty(), "{:?}", target);
+}
+
+#[test]
+fn a_write_no_relocation_explains_is_flagged_first() {
+    let dir = fixture_dir("image-diff-unexplained");
+    let path = fixtures(&dir);
+    let (loader, base) = load(&dir, &path);
+    unsafe { *((base + UNTOUCHED) as *mut u8) = 0xFF };
+    let diffs = loader.diff_image(64);
+    let object = diffs.iter().find(|object| object.path == path).unwrap();
+    assert_eq!(object.unexplained, 1);
+    let flagged: Vec<_> = object
+        .differences
+        .iter()
+        .filter(|difference| difference.unexplained)
+        .collect();
+    assert_eq!(flagged.len(), 1);
+    assert_eq!(flagged[0].address, base + UNTOUCHED);
+    assert_eq!(flagged[0].before, vec![0]);
+    assert_eq!(flagged[0].after, vec![0xFF]);
+    assert!(flagged[0].relocations.is_empty());
+
+    // With room for one difference only, the unexplained one is kept.
+    let diffs = loader.diff_image(1);
+    let object = diffs.iter().find(|object| object.path == path).unwrap();
+    assert_eq!(object.differences.len(), 1);
+    assert!(object.differences[0].unexplained);
+    assert_eq!(object.omitted, 2);
+    assert_eq!(object.unexplained, 1);
+}
+
+#[test]
+fn the_cli_prints_the_ranges_with_their_relocations() {
+    let dir = fixture_dir("image-diff-cli");
+    let path = fixtures(&dir);
+    let output = Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args([
+            "run",
+            "--no-exec",
+            "--diff-image",
+            "--offline",
+            "--search-dir",
+        ])
+        .arg(&dir)
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let start = stdout
+        .find(&format!("Image of {} (base: 0x", path))
+        .unwrap_or_else(|| panic!("no image of {} in {}", path, stdout));
+    let lines: Vec<&str> = stdout[start..].lines().take(3).collect();
+    assert!(lines[0].ends_with("): 2 changed range(s)"), "{}", stdout);
+    assert!(
+        lines[1].contains(" (file 0x1000, ") && lines[1].ends_with(" | R_X86_64_RELATIVE"),
+        "{}",
+        stdout
+    );
+    assert!(
+        lines[2].ends_with(" | R_X86_64_GLOB_DAT target, R_X86_64_64 target"),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("UNEXPLAINED"), "{}", stdout);
+}
@@ -0,0 +1,156 @@
+//! The imports of each loaded object: the global and weak symbols it refers to without defining
+//! them, which object each binds to, and which stay unresolved. Local symbols are neither
+//! imported nor exported.
+
+mod common;
+
+use std::io::Cursor;
+use std::path::Path;
+use std::process::Command;
+
+use common::{data_library, fixture_dir, mapped_word, object_base, offline_loader, write_fixture};
+use drow::{
+    Elf64Metadata, RELOCATION_X86_64_GLOB_DAT, SYMBOL_BINDING_GLOBAL, SYMBOL_BINDING_LOCAL,
+    SYMBOL_BINDING_WEAK, SYMBOL_TYPE_OBJECT,
+};
+
+/// libprovider.so defining `provided`, and libuser.so needing it, defining `user_value` and the
+/// local `local_helper`, and importing `provided`, the weak `weak_missing` and `missing` into
+/// the words at 0x1000, 0x1008 and 0x1010. Returns the paths of both.
+fn fixtures(dir: &Path) -> (String, String) {
+    let provider = write_fixture(
+        dir,
+        "libprovider.so",
+        &data_library("provided", &[7; 8], 8)
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    let user = write_fixture(
+        dir,
+        "libuser.so",
+        &data_library("user_value", &[1; 0x20], 0x20)
+            .add_needed("libprovider.so")
+            .add_symbol(
+                "local_helper",
+                SYMBOL_BINDING_LOCAL,
+                SYMBOL_TYPE_OBJECT,
+                1,
+                0x1018,
+                8,
+            )
+            .add_symbol(
+                "provided",
+                SYMBOL_BINDING_GLOBAL,
+                SYMBOL_TYPE_OBJECT,
+                0,
+                0,
+                0,
+            )
+            .add_symbol(
+                "weak_missing",
+                SYMBOL_BINDING_WEAK,
+                SYMBOL_TYPE_OBJECT,
+                0,
+                0,
+                0,
+            )
+            .add_symbol(
+                "missing",
+                SYMBOL_BINDING_GLOBAL,
+                SYMBOL_TYPE_OBJECT,
+                0,
+                0,
+                0,
+            )
+            .add_rela(0x1000, RELOCATION_X86_64_GLOB_DAT, Some("provided"), 0)
+            .add_rela(0x1008, RELOCATION_X86_64_GLOB_DAT, Some("weak_missing"), 0)
+            .add_rela(0x1010, RELOCATION_X86_64_GLOB_DAT, Some("missing"), 0)
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    (provider, user)
+}
+
+#[test]
+fn imports_and_exports_partition_the_global_symbols() {
+    let dir = fixture_dir("imports-partition");
+    let (_, user) = fixtures(&dir);
+    let metadata =
+        Elf64Metadata::load(&user, &mut Cursor::new(std::fs::read(&user).unwrap())).unwrap();
+    let imports: Vec<&str> = metadata
+        .imports()
+        .map(|symbol| symbol.symbol_name.as_str())
+        .collect();
+    assert_eq!(imports, vec!["provided", "weak_missing", "missing"]);
+    let exports: Vec<&str> = metadata
+        .dynamic_symbol_table
+        .iter()
+        .filter(|symbol| symbol.exported())
+        .map(|symbol| symbol.symbol_name.as_str())
+        .collect();
+    assert_eq!(exports, vec!["user_value"]);
+    let local = metadata
+        .dynamic_symbol_table
+        .iter()
+        .find(|symbol| symbol.symbol_name == "local_helper")
+        .unwrap();
+    assert!(!local.imported() && !local.exported());
+}
+
+#[test]
+fn imports_bind_to_the_object_defining_them_or_stay_unresolved() {
+    let dir = fixture_dir("imports-bound");
+    let (provider, user) = fixtures(&dir);
+    let loader = offline_loader(&dir);
+    loader.load_library(&user).unwrap();
+    let base = object_base(&loader, &user);
+    let provided = loader.lookup_symbol_in(&provider, "provided").unwrap();
+    assert_eq!(provided, object_base(&loader, &provider) + 0x1000);
+    assert_eq!(mapped_word(base + 0x1000), provided);
+    // Unresolved imports leave their words as they were.
+    assert_eq!(mapped_word(base + 0x1008), 0x0101_0101_0101_0101);
+    assert_eq!(mapped_word(base + 0x1010), 0x0101_0101_0101_0101);
+    let mut unresolved = loader.load_report(false).unresolved_symbols;
+    unresolved.sort();
+    assert_eq!(
+        unresolved,
+        vec![
+            (user.clone(), String::from("missing")),
+            (user.clone(), String::from("weak_missing")),
+        ]
+    );
+    // Neither the imports nor the local symbol are definitions others can bind to.
+    for name in ["missing", "weak_missing", "local_helper"] {
+        assert_eq!(loader.lookup_symbol(name), None, "{}", name);
+    }
+    assert_eq!(loader.lookup_symbol("provided"), Some(provided));
+}
+
+#[test]
+fn debug_symbols_prints_the_imports_of_each_object() {
+    let dir = fixture_dir("imports-debug");
+    let (provider, user) = fixtures(&dir);
+    let output = Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(["run", "--no-exec", "--offline", "--search-dir"])
+        .arg(&dir)
+        .args(["--debug", "symbols,bindings", &user])
+        .env_remove("LD_LIBRARY_PATH")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    for line in [
+        format!("[symbols] {} imports nothing", provider),
+        format!("[symbols] {} imports provided, weak_missing, missing", user),
+        format!(
+            "[bindings] binding file {} [0] to {} [0]: normal symbol `provided'",
+            user, provider
+        ),
+        format!("[bindings] {}: symbol `missing' not found", user),
+        format!("[bindings] {}: symbol `weak_missing' not found", user),
+    ] {
+        assert!(stderr.contains(&line), "{}\n{}", line, stderr);
+    }
+    assert!(!stderr.contains("local_helper"), "{}", stderr);
+    assert!(!stderr.contains("UNDEFINED"), "{}", stderr);
+}
@@ -0,0 +1,189 @@
+//! Reading the file from stdin, `-`, or from a descriptor left open by the caller, `--fd`.
+
+mod common;
+
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+use common::{data_library, fixture_dir, write_fixture};
+
+/// The descriptor the file is passed on with --fd.
+const DESCRIPTOR: i32 = 5;
+
+/// libprogram.so, needing libdependency.so, both in `dir`. Returns the bytes of libprogram.so.
+fn fixtures(dir: &Path) -> Vec<u8> {
+    write_fixture(
+        dir,
+        "libdependency.so",
+        &data_library("dependency_value", &[2; 8], 8)
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    let program = data_library("program_value", &[1; 8], 8)
+        .add_needed("libdependency.so")
+        .map_dynamic(0x3000)
+        .finalize();
+    write_fixture(dir, "libprogram.so", &program);
+    program
+}
+
+/// Runs drow with `arguments`, writing `input` to its stdin.
+fn with_stdin(arguments: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(arguments)
+        .env_remove("LD_LIBRARY_PATH")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// Runs drow with `arguments` and `file` as descriptor `DESCRIPTOR`.
+fn with_descriptor(arguments: &[&str], file: &File) -> Output {
+    let descriptor = file.as_raw_fd();
+    let mut command = Command::new(env!("CARGO_BIN_EXE_drow"));
+    command.args(arguments).env_remove("LD_LIBRARY_PATH");
+    unsafe {
+        command.pre_exec(move || {
+            // dup2 onto itself would keep the close-on-exec flag.
+            let result = if descriptor == DESCRIPTOR {
+                libc::fcntl(DESCRIPTOR, libc::F_SETFD, 0)
+            } else {
+                libc::dup2(descriptor, DESCRIPTOR)
+            };
+            if result < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    command.output().unwrap()
+}
+
+/// Asserts that drow loaded the program named `name` after libdependency.so of `dir`, without
+/// running it.
+fn assert_loaded(output: &Output, dir: &Path, name: &str) {
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let dependency = format!(
+        "Loading executable {}",
+        dir.join("libdependency.so").display()
+    );
+    let program = format!("Loading executable {}\n", name);
+    let dependency = stderr.find(&dependency).expect(&stderr);
+    let program = stderr.find(&program).expect(&stderr);
+    assert!(dependency < program, "{}", stderr);
+    assert!(
+        stderr.contains(&format!("Loaded {}, not running it", name)),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn a_file_piped_to_stdin_is_inspected() {
+    let dir = fixture_dir("input-stdin-inspect");
+    let program = fixtures(&dir);
+    let output = with_stdin(&["inspect", "-"], &program);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("program_value"), "{}", stdout);
+    assert!(stdout.contains("libdependency.so"), "{}", stdout);
+}
+
+#[test]
+fn a_file_piped_to_stdin_is_loaded_with_its_dependencies() {
+    let dir = fixture_dir("input-stdin-run");
+    let program = fixtures(&dir);
+    let search_dir = dir.to_string_lossy();
+    let output = with_stdin(
+        &[
+            "run",
+            "--no-exec",
+            "-v",
+            "--offline",
+            "--search-dir",
+            &search_dir,
+            "-",
+        ],
+        &program,
+    );
+    assert_loaded(&output, &dir, "memfd:stdin");
+}
+
+#[test]
+fn a_file_passed_as_a_descriptor_is_loaded_with_its_dependencies() {
+    let dir = fixture_dir("input-fd-run");
+    fixtures(&dir);
+    let path = dir.join("libprogram.so");
+    let file = File::open(&path).unwrap();
+    let search_dir = dir.to_string_lossy();
+    let descriptor = DESCRIPTOR.to_string();
+    let output = with_descriptor(
+        &[
+            "run",
+            "--no-exec",
+            "-v",
+            "--offline",
+            "--search-dir",
+            &search_dir,
+            "--fd",
+            &descriptor,
+        ],
+        &file,
+    );
+    // Named after the file the descriptor is open on.
+    assert_loaded(&output, &dir, &path.to_string_lossy());
+}
+
+#[test]
+fn a_pipe_passed_as_a_descriptor_is_copied_and_loaded() {
+    let dir = fixture_dir("input-fd-pipe");
+    let program = fixtures(&dir);
+    let search_dir = dir.to_string_lossy();
+    let output = with_stdin(
+        &[
+            "run",
+            "--no-exec",
+            "-v",
+            "--offline",
+            "--search-dir",
+            &search_dir,
+            "--fd",
+            "0",
+        ],
+        &program,
+    );
+    assert_loaded(&output, &dir, "memfd:fd:0");
+}
+
+#[test]
+fn a_path_and_a_descriptor_are_exclusive() {
+    let dir = fixture_dir("input-fd-path");
+    fixtures(&dir);
+    let path = dir.join("libprogram.so");
+    let file = File::open(&path).unwrap();
+    let output = with_descriptor(
+        &[
+            "inspect",
+            "--fd",
+            &DESCRIPTOR.to_string(),
+            &path.to_string_lossy(),
+        ],
+        &file,
+    );
+    assert!(!output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("--fd reads the file from a descriptor, not a path"),
+        "{}",
+        stderr
+    );
+}
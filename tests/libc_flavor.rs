@@ -0,0 +1,225 @@
+//! Which C library a program targets, from the metadata of fixtures: PT_INTERP, the sonames
+//! and dependencies of the objects, and glibc's version definitions. Nothing is executed.
+
+mod common;
+
+use std::path::Path;
+use std::process::Command;
+
+use common::{data_library, fixture_dir, write_fixture};
+use drow::libc_flavor::LibcFlavor;
+use drow::loader::Elf64Loader;
+use drow::offset_reader::OffsetReader;
+use drow::testutil::ElfBuilder;
+use drow::{
+    Elf64Metadata, ELF64_SECTION_HEADER_DYNAMIC, PROGRAM_FLAG_READ, PROGRAM_HEADER_TYPE_INTERPRETER,
+};
+
+const DYNAMIC_TABLE_SONAME: i64 = 14;
+
+const GLIBC_INTERPRETER: &str = "/lib64/ld-linux-x86-64.so.2";
+const MUSL_INTERPRETER: &str = "/lib/ld-musl-x86_64.so.1";
+
+fn library() -> ElfBuilder {
+    data_library("value", &[0; 8], 8).map_dynamic(0x3000)
+}
+
+fn with_interpreter(builder: ElfBuilder, interpreter: &str) -> ElfBuilder {
+    let mut content = interpreter.as_bytes().to_vec();
+    content.push(0);
+    builder.add_segment(
+        PROGRAM_HEADER_TYPE_INTERPRETER,
+        PROGRAM_FLAG_READ,
+        0x2000,
+        &content,
+        content.len() as u64,
+    )
+}
+
+/// The library `build` makes, with a DT_SONAME of `soname`, which must be one of the strings
+/// it puts in .dynstr. Adding the entry does not move them.
+fn with_soname(build: impl Fn() -> ElfBuilder, soname: &str) -> Vec<u8> {
+    let bytes = build().finalize();
+    let metadata = parse_bytes(&bytes);
+    let dynamic = metadata
+        .section_headers
+        .iter()
+        .find(|section| section.sh_type == ELF64_SECTION_HEADER_DYNAMIC)
+        .unwrap();
+    let strings = &metadata.section_headers[dynamic.sh_link as usize];
+    let table = &bytes[strings.sh_offset as usize..(strings.sh_offset + strings.sh_size) as usize];
+    let mut needle = soname.as_bytes().to_vec();
+    needle.push(0);
+    let offset = table
+        .windows(needle.len())
+        .position(|window| window == needle.as_slice())
+        .unwrap();
+    build()
+        .add_dynamic(DYNAMIC_TABLE_SONAME, offset as u64)
+        .finalize()
+}
+
+fn parse_bytes(bytes: &[u8]) -> Elf64Metadata {
+    Elf64Metadata::load(
+        &String::from("fixture.so"),
+        &mut std::io::Cursor::new(bytes),
+    )
+    .unwrap()
+}
+
+/// Parses the fixture from its file, which the version definitions are read from again.
+fn parse(dir: &Path, name: &str, bytes: &[u8]) -> Elf64Metadata {
+    let path = write_fixture(dir, name, bytes);
+    Elf64Metadata::load(&path, &mut OffsetReader::open(&path).unwrap()).unwrap()
+}
+
+fn detect(program: &Elf64Metadata, objects: &[&Elf64Metadata]) -> LibcFlavor {
+    LibcFlavor::detect(
+        program.interpreter.as_deref(),
+        std::iter::once(program).chain(objects.iter().copied()),
+    )
+}
+
+#[test]
+fn the_interpreter_decides_first() {
+    let dir = fixture_dir("libc-interpreter");
+    let glibc = parse(
+        &dir,
+        "glibc",
+        &with_interpreter(library(), GLIBC_INTERPRETER).finalize(),
+    );
+    assert_eq!(detect(&glibc, &[]), LibcFlavor::Glibc);
+    // Even against a dependency on the other C library.
+    let musl = parse(
+        &dir,
+        "musl",
+        &with_interpreter(library().add_needed("libc.so.6"), MUSL_INTERPRETER).finalize(),
+    );
+    assert_eq!(detect(&musl, &[]), LibcFlavor::Musl);
+}
+
+#[test]
+fn without_an_interpreter_the_dependencies_decide() {
+    let dir = fixture_dir("libc-dependencies");
+    let glibc = parse(
+        &dir,
+        "libglibc.so",
+        &library().add_needed("libc.so.6").finalize(),
+    );
+    assert_eq!(detect(&glibc, &[]), LibcFlavor::Glibc);
+    let musl = parse(
+        &dir,
+        "libmusl.so",
+        &library().add_needed("libc.so").finalize(),
+    );
+    assert_eq!(detect(&musl, &[]), LibcFlavor::Musl);
+    let neither = parse(
+        &dir,
+        "libneither.so",
+        &library().add_needed("libm.so.6").finalize(),
+    );
+    assert_eq!(detect(&neither, &[]), LibcFlavor::Unknown);
+    // Through another loaded object.
+    assert_eq!(detect(&neither, &[&musl]), LibcFlavor::Musl);
+}
+
+#[test]
+fn a_libc_of_another_name_is_glibc_by_its_version_definitions() {
+    let dir = fixture_dir("libc-versions");
+    let program = parse(&dir, "libprogram.so", &library().finalize());
+    let glibc = parse(
+        &dir,
+        "libc.so.7",
+        &with_soname(
+            || {
+                library()
+                    .add_version_definition("libc.so.7")
+                    .add_version_definition("GLIBC_2.2.5")
+            },
+            "libc.so.7",
+        ),
+    );
+    assert_eq!(glibc.dynamic.soname.as_deref(), Some("libc.so.7"));
+    assert_eq!(detect(&program, &[&glibc]), LibcFlavor::Glibc);
+    let other = parse(
+        &dir,
+        "libc.so.8",
+        &with_soname(
+            || {
+                library()
+                    .add_version_definition("libc.so.8")
+                    .add_version_definition("OTHER_1.0")
+            },
+            "libc.so.8",
+        ),
+    );
+    assert_eq!(detect(&program, &[&other]), LibcFlavor::Unknown);
+}
+
+#[test]
+fn the_flavor_decides_the_shims() {
+    assert!(LibcFlavor::Glibc.versioned_symbols());
+    assert!(LibcFlavor::Glibc.linker_symbols());
+    assert_eq!(
+        LibcFlavor::Glibc.left_out_interpreter(None),
+        Some("ld-linux-x86-64.so.2")
+    );
+    assert_eq!(
+        LibcFlavor::Glibc.left_out_interpreter(Some(GLIBC_INTERPRETER)),
+        Some(GLIBC_INTERPRETER)
+    );
+    for flavor in [LibcFlavor::Musl, LibcFlavor::Unknown] {
+        assert!(!flavor.versioned_symbols());
+        assert!(!flavor.linker_symbols());
+        assert_eq!(flavor.left_out_interpreter(Some(MUSL_INTERPRETER)), None);
+    }
+}
+
+#[test]
+fn the_override_replaces_the_detection() {
+    assert_eq!(LibcFlavor::parse("glibc"), Ok(LibcFlavor::Glibc));
+    assert_eq!(LibcFlavor::parse("musl"), Ok(LibcFlavor::Musl));
+    assert_eq!(LibcFlavor::parse("none"), Ok(LibcFlavor::Unknown));
+    assert_eq!(
+        LibcFlavor::parse("bionic"),
+        Err(String::from(
+            "Unknown libc: bionic, expected glibc, musl or none"
+        ))
+    );
+
+    let dir = fixture_dir("libc-override");
+    let path = write_fixture(&dir, "libprogram.so", &library().finalize());
+    let offline = [dir.to_string_lossy().into_owned()];
+    let loader = Elf64Loader::builder().offline(&offline).build().unwrap();
+    loader.load_library(&path).unwrap();
+    assert_eq!(loader.libc_flavor(), LibcFlavor::Unknown);
+    let loader = Elf64Loader::builder()
+        .offline(&offline)
+        .libc(LibcFlavor::Musl)
+        .build()
+        .unwrap();
+    loader.load_library(&path).unwrap();
+    assert_eq!(loader.libc_flavor(), LibcFlavor::Musl);
+}
+
+#[test]
+fn the_summary_reports_the_flavor() {
+    let dir = fixture_dir("libc-summary");
+    let musl = write_fixture(
+        &dir,
+        "musl",
+        &with_interpreter(library(), MUSL_INTERPRETER).finalize(),
+    );
+    let neither = write_fixture(&dir, "libneither.so", &library().finalize());
+    let output = Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(["inspect", "--summary", &musl, &neither])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let flavors: Vec<&str> = stdout
+        .lines()
+        .map(|line| line.split(' ').nth(6).unwrap())
+        .collect();
+    assert_eq!(flavors, vec!["musl", "unknown"], "{}", stdout);
+}
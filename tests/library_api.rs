@@ -0,0 +1,392 @@
+//! Parsing, resolving and loading through the API the crate root documents as supported, the
+//! way an embedder without the `drow` binary uses it.
+
+mod common;
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use common::{
+    data_library, fixture_dir, library_cache, log_library, mapped_bytes, offline_loader, recorded,
+    recording_library, write_fixture,
+};
+use drow::cache::LibraryCache;
+use drow::dynamic::{DYNAMIC_FLAGS_1_NODELETE, DYNAMIC_FLAGS_1_NOOPEN};
+use drow::ld_path_loader::LdPathLoader;
+use drow::loader::{DependenciesResolver, Elf64Loader};
+use drow::object_rules::{ObjectRule, ObjectRules};
+use drow::offset_reader::OffsetReader;
+use drow::progress::Progress;
+use drow::{DrowError, Elf64Metadata};
+
+/// The flags of a 64-bit x86 libc6 entry of ld.so.cache.
+const CACHE_FLAGS_X86_64: i32 = 0x0303;
+
+/// The DT_FLAGS_1 tag of the dynamic section.
+const DYNAMIC_TABLE_FLAGS_1: i64 = 0x6fff_fffb;
+
+/// libroot.so needing libcached.so, listed in a cache only, and libsearched.so, found in an
+/// LD_LIBRARY_PATH directory only. Returns the cache path, the search directory and the root.
+fn fixtures(test: &str) -> (String, String, String) {
+    let dir = fixture_dir(test);
+    let cached_dir = dir.join("cached");
+    let searched_dir = dir.join("searched");
+    std::fs::create_dir_all(&cached_dir).unwrap();
+    std::fs::create_dir_all(&searched_dir).unwrap();
+    let cached = write_fixture(
+        &cached_dir,
+        "libcached.so",
+        &data_library("cached_value", &[1; 8], 8).finalize(),
+    );
+    write_fixture(
+        &searched_dir,
+        "libsearched.so",
+        &data_library("searched_value", &[2; 8], 8).finalize(),
+    );
+    let cache = write_fixture(
+        &dir,
+        "ld.so.cache",
+        &library_cache(&[("libcached.so", &cached, CACHE_FLAGS_X86_64)]),
+    );
+    let root = write_fixture(
+        &dir,
+        "libroot.so",
+        &data_library("root_value", &[3; 8], 8)
+            .add_needed("libcached.so")
+            .add_needed("libsearched.so")
+            .finalize(),
+    );
+    (cache, searched_dir.to_string_lossy().into_owned(), root)
+}
+
+fn resolver(cache: &str, search_directory: &str) -> DependenciesResolver {
+    DependenciesResolver::new(
+        LibraryCache::load(cache).unwrap(),
+        Some(LdPathLoader::new(search_directory)),
+    )
+}
+
+fn parse(path: &str) -> Result<Elf64Metadata, DrowError> {
+    let mut reader = OffsetReader::open(path).unwrap();
+    Elf64Metadata::load(&path.to_string(), &mut reader)
+}
+
+fn file_name(metadata: &Elf64Metadata) -> &str {
+    metadata.file_path.rsplit('/').next().unwrap()
+}
+
+#[test]
+fn resolves_dependencies_from_the_cache_and_the_search_path() {
+    let (cache, search_directory, root) = fixtures("api-resolve");
+    let metadata = parse(&root).unwrap();
+    assert_eq!(
+        metadata.dynamic.required_libraries,
+        vec![String::from("libcached.so"), String::from("libsearched.so")]
+    );
+    let mut resolver = resolver(&cache, &search_directory);
+    let direct = resolver.resolve_direct_dependencies(&metadata).unwrap();
+    let names: Vec<&str> = direct.iter().map(|file| file_name(file)).collect();
+    assert_eq!(names, vec!["libcached.so", "libsearched.so"]);
+    let order = resolver
+        .resolve_in_loading_order(&Arc::new(metadata))
+        .unwrap();
+    let names: Vec<&str> = order.iter().map(|file| file_name(file)).collect();
+    assert_eq!(names, vec!["libcached.so", "libsearched.so", "libroot.so"]);
+}
+
+#[test]
+fn a_library_missing_from_both_is_an_error() {
+    let (cache, _, root) = fixtures("api-missing");
+    let metadata = parse(&root).unwrap();
+    let mut resolver = resolver(&cache, "");
+    match resolver.resolve_direct_dependencies(&metadata) {
+        Err(DrowError::UnresolvedLibrary { name, .. }) => assert_eq!(name, "libsearched.so"),
+        other => panic!("unexpected result {:?}", other.map(|files| files.len())),
+    }
+}
+
+/// Records every event, shared with the test through the mutex.
+struct Recorder(Arc<Mutex<Vec<String>>>);
+
+impl Progress for Recorder {
+    fn mapping(&mut self, path: &str, _size: u64) {
+        self.0.lock().unwrap().push(format!("mapping {}", path));
+    }
+
+    fn finished(&mut self, objects: usize, _relocations: usize) {
+        self.0.lock().unwrap().push(format!("finished {}", objects));
+    }
+}
+
+#[test]
+fn built_and_assembled_loaders_map_the_library_and_its_dependencies() {
+    let (cache, search_directory, root) = fixtures("api-load");
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let loader = Elf64Loader::builder()
+        .cache_path(&cache)
+        .ld_library_path(Some(&search_directory))
+        .progress(Recorder(events.clone()))
+        .build()
+        .unwrap();
+    assert_eq!(loader.load_library(&root).unwrap(), root);
+    for (symbol, byte) in [
+        ("cached_value", 1),
+        ("searched_value", 2),
+        ("root_value", 3),
+    ] {
+        let address = loader.lookup_symbol(symbol).unwrap();
+        assert_eq!(mapped_bytes(address, 8), vec![byte; 8], "{}", symbol);
+    }
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 4, "{:?}", events);
+    assert!(events[..3]
+        .iter()
+        .all(|event| event.starts_with("mapping ")));
+    assert_eq!(events[3], "finished 3");
+
+    let plain = Elf64Loader::new(resolver(&cache, &search_directory));
+    plain.load_library(&root).unwrap();
+    assert_eq!(plain.load_report(false).objects.len(), 3);
+    assert!(plain.unload(&root).unwrap());
+    assert!(plain.load_report(false).objects.is_empty());
+}
+
+fn references(loader: &Elf64Loader, path: &str) -> Option<usize> {
+    loader
+        .object_references()
+        .into_iter()
+        .find(|(object, _)| object == path)
+        .map(|(_, references)| references)
+}
+
+#[test]
+fn an_object_stays_mapped_until_its_last_reference_is_dropped() {
+    let (cache, search_directory, root) = fixtures("api-references");
+    let loader = Elf64Loader::new(resolver(&cache, &search_directory));
+    assert_eq!(loader.load_library(&root).unwrap(), root);
+    assert_eq!(loader.load_library(&root).unwrap(), root);
+    assert_eq!(references(&loader, &root), Some(2));
+    let address = loader.lookup_symbol("root_value").unwrap();
+
+    assert!(!loader.unload(&root).unwrap());
+    assert_eq!(references(&loader, &root), Some(1));
+    assert_eq!(loader.lookup_symbol("root_value"), Some(address));
+    assert_eq!(mapped_bytes(address, 8), vec![3; 8]);
+    assert_eq!(loader.load_report(false).objects.len(), 3);
+
+    assert!(loader.unload(&root).unwrap());
+    assert_eq!(references(&loader, &root), None);
+    assert_eq!(loader.lookup_symbol("root_value"), None);
+    assert!(loader.load_report(false).objects.is_empty());
+}
+
+#[test]
+fn a_dependency_shared_by_two_libraries_stays_mapped_for_the_other() {
+    let (cache, search_directory, root) = fixtures("api-shared-dependency");
+    let other = write_fixture(
+        Path::new(&root).parent().unwrap(),
+        "libother.so",
+        &data_library("other_value", &[4; 8], 8)
+            .add_needed("libcached.so")
+            .finalize(),
+    );
+    let loader = Elf64Loader::new(resolver(&cache, &search_directory));
+    loader.load_library(&root).unwrap();
+    loader.load_library(&other).unwrap();
+    let cached = loader
+        .load_report(false)
+        .objects
+        .into_iter()
+        .map(|object| object.path)
+        .find(|path| path.ends_with("/libcached.so"))
+        .unwrap();
+    assert_eq!(references(&loader, &cached), Some(2));
+
+    assert!(loader.unload(&root).unwrap());
+    assert_eq!(references(&loader, &cached), Some(1));
+    let address = loader.lookup_symbol("cached_value").unwrap();
+    assert_eq!(mapped_bytes(address, 8), vec![1; 8]);
+
+    assert!(loader.unload(&other).unwrap());
+    assert_eq!(references(&loader, &cached), None);
+    assert!(loader.load_report(false).objects.is_empty());
+}
+
+#[test]
+fn a_nodelete_object_is_never_unmapped() {
+    let dir = fixture_dir("api-nodelete");
+    let path = write_fixture(
+        &dir,
+        "libnodelete.so",
+        &data_library("kept_value", &[5; 8], 8)
+            .add_dynamic(DYNAMIC_TABLE_FLAGS_1, DYNAMIC_FLAGS_1_NODELETE)
+            .finalize(),
+    );
+    let cache = write_fixture(&dir, "ld.so.cache", &library_cache(&[]));
+    let loader = Elf64Loader::new(resolver(&cache, ""));
+    loader.load_library(&path).unwrap();
+    let address = loader.lookup_symbol("kept_value").unwrap();
+    assert!(!loader.unload(&path).unwrap());
+    assert_eq!(references(&loader, &path), Some(0));
+    assert!(!loader.unload(&path).unwrap());
+    assert!(!loader.close_library(&path).unwrap());
+    assert_eq!(loader.lookup_symbol("kept_value"), Some(address));
+    assert_eq!(mapped_bytes(address, 8), vec![5; 8]);
+    assert!(loader
+        .memory_map_entries()
+        .iter()
+        .any(|entry| entry.object == path));
+}
+
+#[test]
+fn a_noopen_object_is_refused() {
+    let dir = fixture_dir("api-noopen");
+    let path = write_fixture(
+        &dir,
+        "libnoopen.so",
+        &data_library("hidden_value", &[6; 8], 8)
+            .add_dynamic(DYNAMIC_TABLE_FLAGS_1, DYNAMIC_FLAGS_1_NOOPEN)
+            .finalize(),
+    );
+    let cache = write_fixture(&dir, "ld.so.cache", &library_cache(&[]));
+    let loader = Elf64Loader::new(resolver(&cache, ""));
+    match loader.load_library(&path) {
+        Err(DrowError::NotLoadable {
+            path: refused,
+            reason,
+        }) => {
+            assert_eq!(refused, path);
+            assert_eq!(reason, "it is marked DF_1_NOOPEN");
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert!(loader.load_report(false).objects.is_empty());
+}
+
+/// liblog.so, libmid.so recording M, libtop.so needing it and recording T, and libalt.so
+/// recording A. Returns the paths of liblog.so, libtop.so and libalt.so.
+fn recording_fixtures(dir: &Path) -> (String, String, String) {
+    let log = write_fixture(dir, "liblog.so", &log_library().finalize());
+    write_fixture(
+        dir,
+        "libmid.so",
+        &recording_library("mid_value", b'M', &[]).finalize(),
+    );
+    let top = write_fixture(
+        dir,
+        "libtop.so",
+        &recording_library("top_value", b'T', &["libmid.so"]).finalize(),
+    );
+    let alt = write_fixture(
+        dir,
+        "libalt.so",
+        &recording_library("alt_value", b'A', &[]).finalize(),
+    );
+    (log, top, alt)
+}
+
+fn rules_loader(dir: &Path, rules: &[&str]) -> Elf64Loader {
+    let mut object_rules = ObjectRules::default();
+    for rule in rules {
+        object_rules.push(ObjectRule::parse(rule).unwrap());
+    }
+    Elf64Loader::builder()
+        .offline(&[dir.to_string_lossy().into_owned()])
+        .object_rules(object_rules)
+        .build()
+        .unwrap()
+}
+
+fn loaded_paths(loader: &Elf64Loader) -> Vec<String> {
+    loader
+        .load_report(false)
+        .objects
+        .into_iter()
+        .map(|object| object.path)
+        .collect()
+}
+
+#[test]
+fn a_replace_rule_loads_the_other_file_in_place_of_the_object() {
+    let dir = fixture_dir("library-api-replace");
+    let (log, top, alt) = recording_fixtures(&dir);
+    let rule = format!("libmid.so:replace={}", alt);
+    let loader = rules_loader(&dir, &[&rule]);
+    loader.open_library(&top).unwrap();
+    assert_eq!(loaded_paths(&loader), vec![log, alt.clone(), top]);
+    assert!(loader.lookup_symbol("alt_value").is_some());
+    assert_eq!(loader.lookup_symbol("mid_value"), None);
+    assert_eq!(recorded(&loader), "AT");
+    assert_eq!(loader.object_rules_applied(), vec![(alt, vec![rule])]);
+}
+
+#[test]
+fn a_skip_init_rule_skips_the_init_functions_of_the_object_only() {
+    let dir = fixture_dir("library-api-skip-init");
+    let (log, top, alt) = recording_fixtures(&dir);
+    let loader = rules_loader(&dir, &["libmid.so:skip-init"]);
+    // Held so the log outlives libtop.so.
+    loader.load_library(&log).unwrap();
+    loader.open_library(&top).unwrap();
+    assert_eq!(recorded(&loader), "T");
+    // Fini functions still run, the last object initialized first.
+    assert!(loader.close_library(&top).unwrap());
+    assert_eq!(recorded(&loader), "Ttm");
+
+    // A replacement is matched by the name it replaces, and skipped the same way.
+    let rule = format!("libmid.so:replace={},skip-init", alt);
+    let loader = rules_loader(&dir, &[&rule]);
+    loader.open_library(&top).unwrap();
+    assert!(loaded_paths(&loader).contains(&alt));
+    assert_eq!(recorded(&loader), "T");
+}
+
+#[test]
+fn open_runs_init_functions_dependencies_first_and_close_runs_fini_functions_in_reverse() {
+    let dir = fixture_dir("library-api-open-close");
+    let (log, top, _) = recording_fixtures(&dir);
+    let side = write_fixture(
+        &dir,
+        "libside.so",
+        &recording_library("side_value", b'S', &["libmid.so"]).finalize(),
+    );
+    let loader = offline_loader(&dir);
+    // Held so the log outlives the libraries recording in it.
+    loader.load_library(&log).unwrap();
+    loader.open_library(&top).unwrap();
+    assert_eq!(recorded(&loader), "MT");
+    // libmid.so is already initialized.
+    loader.open_library(&side).unwrap();
+    assert_eq!(recorded(&loader), "MTS");
+    // libmid.so stays for libside.so.
+    assert!(loader.close_library(&top).unwrap());
+    assert_eq!(recorded(&loader), "MTSt");
+    // Then goes with it, finalized after it.
+    assert!(loader.close_library(&side).unwrap());
+    assert_eq!(recorded(&loader), "MTStsm");
+    assert_eq!(loaded_paths(&loader), vec![log]);
+}
+
+#[test]
+fn a_fini_function_runs_once_its_object_is_no_longer_needed() {
+    let dir = fixture_dir("library-api-close-order");
+    let (log, top, _) = recording_fixtures(&dir);
+    let loader = offline_loader(&dir);
+    loader.load_library(&log).unwrap();
+    let mid = loader
+        .open_library(&dir.join("libmid.so").to_string_lossy())
+        .unwrap();
+    assert_eq!(recorded(&loader), "M");
+    // Opened again as a dependency, libmid.so is not initialized again.
+    loader.open_library(&top).unwrap();
+    assert_eq!(recorded(&loader), "MT");
+    assert!(loader.close_library(&top).unwrap());
+    assert_eq!(recorded(&loader), "MTt");
+    assert!(loader.close_library(&mid).unwrap());
+    assert_eq!(recorded(&loader), "MTtm");
+    match loader.close_library(&top) {
+        Err(DrowError::NotLoaded { path }) => assert_eq!(path, top),
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert_eq!(recorded(&loader), "MTtm");
+}
@@ -0,0 +1,144 @@
+//! Objects whose fields are near u64::MAX, and objects larger than --max-object-size.
+
+mod common;
+
+use std::io::Cursor;
+use std::process::Command;
+
+use common::{data_library, fixture_dir, offline_loader, write_fixture};
+use drow::loader::{Elf64Loader, LoadOptions};
+use drow::{DrowError, Elf64Metadata, RELOCATION_X86_64_RELATIVE};
+
+/// Overwrites the u64 at `offset` of `bytes`.
+fn patch(bytes: &mut [u8], offset: usize, value: u64) {
+    bytes[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Parses and then loads `bytes`, returning the first error.
+fn parse_and_load(name: &str, bytes: &[u8]) -> Result<(), DrowError> {
+    Elf64Metadata::load(&String::from(name), &mut Cursor::new(bytes))?;
+    let dir = fixture_dir(name);
+    let path = write_fixture(&dir, &format!("{}.so", name), bytes);
+    offline_loader(&dir).load_library(&path).map(|_| ())
+}
+
+fn assert_refused(name: &str, result: Result<(), DrowError>) {
+    match result {
+        Err(DrowError::Malformed { .. }) | Err(DrowError::NotLoadable { .. }) => {}
+        other => panic!("{}: unexpected result {:?}", name, other),
+    }
+}
+
+#[test]
+fn program_header_fields_near_u64_max_are_refused() {
+    // p_offset, p_vaddr, p_filesz and p_memsz of the only program header.
+    for (name, field) in [
+        ("offset", 0x08),
+        ("vaddr", 0x10),
+        ("filesz", 0x20),
+        ("memsz", 0x28),
+    ] {
+        for value in [u64::MAX, u64::MAX - 0xFFF, 1 << 63] {
+            let mut bytes = data_library("value", &[0; 16], 16).finalize();
+            patch(&mut bytes, 0x40 + field, value);
+            let name = format!("huge-{}-{:x}", name, value);
+            assert_refused(&name, parse_and_load(&name, &bytes));
+        }
+    }
+}
+
+#[test]
+fn header_table_offsets_near_u64_max_are_refused() {
+    // e_phoff and e_shoff.
+    for (name, field) in [("phoff", 0x20), ("shoff", 0x28)] {
+        for value in [u64::MAX, u64::MAX - 0x3F] {
+            let mut bytes = data_library("value", &[0; 16], 16).finalize();
+            patch(&mut bytes, field, value);
+            let name = format!("huge-{}-{:x}", name, value);
+            assert_refused(&name, parse_and_load(&name, &bytes));
+        }
+    }
+}
+
+#[test]
+fn relocation_offsets_near_u64_max_are_refused() {
+    for offset in [u64::MAX, u64::MAX - 7, 0x1000 - 4] {
+        let bytes = data_library("value", &[0; 16], 16)
+            .add_rela(offset, RELOCATION_X86_64_RELATIVE, None, 0)
+            .finalize();
+        let name = format!("huge-rela-{:x}", offset);
+        match parse_and_load(&name, &bytes) {
+            Err(DrowError::NotLoadable { reason, .. }) => {
+                assert!(reason.contains("outside the segments"), "{}", reason)
+            }
+            other => panic!("{}: unexpected result {:?}", name, other),
+        }
+    }
+}
+
+/// A library of `pages` pages, most of them zero filled.
+fn library_of_pages(pages: u64) -> Vec<u8> {
+    data_library("value", &[1; 16], pages * 0x1000).finalize()
+}
+
+fn loader_with_max_object_size(dir: &std::path::Path, max_object_size: u64) -> Elf64Loader {
+    Elf64Loader::builder()
+        .offline(&[dir.to_string_lossy().into_owned()])
+        .options(LoadOptions {
+            max_object_size,
+            ..LoadOptions::default()
+        })
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn objects_larger_than_max_object_size_are_refused() {
+    let dir = fixture_dir("max-object-size");
+    let path = write_fixture(&dir, "libfour.so", &library_of_pages(4));
+    match loader_with_max_object_size(&dir, 0x3000).load_library(&path) {
+        Err(DrowError::NotLoadable {
+            path: refused,
+            reason,
+        }) => {
+            assert_eq!(refused, path);
+            assert!(reason.contains("--max-object-size"), "{}", reason);
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+    loader_with_max_object_size(&dir, 0x4000)
+        .load_library(&path)
+        .unwrap();
+}
+
+#[test]
+fn default_max_object_size_refuses_a_terabyte_segment() {
+    let dir = fixture_dir("terabyte");
+    let path = write_fixture(&dir, "libhuge.so", &library_of_pages(1 << 28));
+    match offline_loader(&dir).load_library(&path) {
+        Err(DrowError::NotLoadable { reason, .. }) => {
+            assert!(reason.contains("--max-object-size"), "{}", reason)
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn max_object_size_option_refuses_larger_objects() {
+    let dir = fixture_dir("max-object-size-cli");
+    let path = write_fixture(&dir, "libfour.so", &library_of_pages(4));
+    let run = |max_object_size: &str| {
+        Command::new(env!("CARGO_BIN_EXE_drow"))
+            .args(["run", "--no-exec", "--max-object-size", max_object_size])
+            .arg(&path)
+            .env_remove("LD_LIBRARY_PATH")
+            .output()
+            .unwrap()
+    };
+    let refused = run("12288");
+    assert!(!refused.status.success());
+    let stderr = String::from_utf8_lossy(&refused.stderr);
+    assert!(stderr.contains("--max-object-size"), "{}", stderr);
+    let loaded = run("16384");
+    assert!(loaded.status.success(), "{:?}", loaded);
+}
@@ -0,0 +1,217 @@
+//! Leveled diagnostics: what each level lets through, the module each line is tagged with, and
+//! `-q`, `-v` and `--log-file` on the command line.
+
+mod common;
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+use std::sync::Mutex;
+
+use common::{data_library, fixture_dir, write_fixture};
+use drow::cache::LibraryCache;
+use drow::log::{self, Level};
+use drow::RELOCATION_X86_64_32;
+
+/// A relocation type x86-64 does not define, which drow warns about and then fails on.
+const RELOCATION_UNKNOWN: u64 = 200;
+
+/// The level and the log file are global, so the tests setting them take turns.
+static LOGGER: Mutex<()> = Mutex::new(());
+
+/// The lines logged while running `emit` at `level`.
+fn capture(test: &str, level: Level, emit: impl FnOnce()) -> Vec<String> {
+    let _guard = LOGGER.lock().unwrap_or_else(|err| err.into_inner());
+    let path = fixture_dir(test).join("log");
+    log::set_log_file(&path.to_string_lossy()).unwrap();
+    log::set_level(level);
+    emit();
+    log::set_level(Level::Warn);
+    fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+fn emit_every_level() {
+    drow::error!("error {}", 1);
+    drow::warn!("warn {}", 2);
+    drow::info!("info {}", 3);
+    drow::debug!("debug {}", 4);
+    drow::trace!("trace {}", 5);
+}
+
+#[test]
+fn each_level_lets_through_itself_and_the_levels_above() {
+    let expected = [
+        "[ERROR log] error 1",
+        "[WARN log] warn 2",
+        "[INFO log] info 3",
+        "[DEBUG log] debug 4",
+        "[TRACE log] trace 5",
+    ];
+    for (index, level) in ["error", "warn", "info", "debug", "trace"]
+        .iter()
+        .enumerate()
+    {
+        let level = Level::parse(level).unwrap();
+        let lines = capture(&format!("log-level-{}", level), level, || {
+            assert!(log::enabled(level));
+            assert!(log::enabled(Level::Error));
+            emit_every_level();
+        });
+        assert_eq!(lines, expected[..=index], "{}", level);
+    }
+}
+
+#[test]
+fn debug_categories_are_written_whatever_the_level() {
+    let lines = capture("log-category", Level::Error, || {
+        log::write_category("bindings", format_args!("value => libvalue.so"));
+        drow::warn!("hidden");
+    });
+    assert_eq!(lines, vec!["[bindings] value => libvalue.so"]);
+}
+
+#[test]
+fn lines_are_tagged_with_the_module_logging_them() {
+    let dir = fixture_dir("log-module");
+    let path = dir.join("absent.cache").to_string_lossy().into_owned();
+    let lines = capture("log-module-lines", Level::Info, || {
+        assert!(LibraryCache::load(&path).is_err());
+    });
+    assert_eq!(
+        lines,
+        vec![format!("[INFO drow::cache] Loading cache file: {}", path)]
+    );
+    let lines = capture("log-module-hidden", Level::Warn, || {
+        assert!(LibraryCache::load(&path).is_err());
+    });
+    assert!(lines.is_empty(), "{:?}", lines);
+}
+
+#[test]
+fn verbosity_moves_from_the_default_warn_level() {
+    assert_eq!(Level::from_verbosity(-1), Level::Error);
+    assert_eq!(Level::from_verbosity(0), Level::Warn);
+    assert_eq!(Level::from_verbosity(1), Level::Info);
+    assert_eq!(Level::from_verbosity(2), Level::Debug);
+    assert_eq!(Level::from_verbosity(3), Level::Trace);
+    assert_eq!(Level::from_verbosity(9), Level::Trace);
+    assert_eq!(Level::parse("DeBuG").unwrap(), Level::Debug);
+    assert!(Level::parse("loud").is_err());
+}
+
+/// A library whose relocation is traced, then one drow warns about and fails to load, both
+/// loaded by one run with `--each`.
+fn fixtures(test: &str) -> (String, Vec<String>) {
+    let dir = fixture_dir(test);
+    let relocated = write_fixture(
+        &dir,
+        "librelocated.so",
+        &data_library("value", &[0; 8], 8)
+            .add_rela(0x1000, RELOCATION_X86_64_32, None, 0x10)
+            .finalize(),
+    );
+    let unsupported = write_fixture(
+        &dir,
+        "libunsupported.so",
+        &data_library("value", &[0; 8], 8)
+            .add_rela(0x1000, RELOCATION_UNKNOWN, None, 0)
+            .finalize(),
+    );
+    (
+        dir.to_string_lossy().into_owned(),
+        vec![relocated, unsupported],
+    )
+}
+
+fn drow(dir: &str, arguments: &[&str], paths: &[String]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args([
+            "run",
+            "--no-exec",
+            "--each",
+            "--offline",
+            "--search-dir",
+            dir,
+        ])
+        .args(arguments)
+        .args(paths)
+        .env_remove("LD_LIBRARY_PATH")
+        .env_remove("DROW_LOG")
+        .env_remove("DROW_LOG_FILE")
+        .env_remove("DROW_CONFIG")
+        .env("XDG_CONFIG_HOME", dir)
+        .output()
+        .unwrap()
+}
+
+/// The lines of `output` that are not the count of loaded and failed files.
+fn diagnostics(output: &Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let summary = "1 ok, 1 failed\n";
+    assert!(stderr.ends_with(summary), "{}", stderr);
+    stderr[..stderr.len() - summary.len()].to_string()
+}
+
+/// The levels and modules of the lines in `log`, each of which must be tagged.
+fn levels_and_modules(log: &str) -> (BTreeSet<String>, BTreeSet<String>) {
+    let mut levels = BTreeSet::new();
+    let mut modules = BTreeSet::new();
+    for line in log.lines() {
+        let tag = line
+            .strip_prefix('[')
+            .and_then(|line| line.split_once("] "))
+            .map(|(tag, _)| tag)
+            .unwrap_or_else(|| panic!("untagged line {}", line));
+        let (level, module) = tag.split_once(' ').unwrap();
+        assert!(module == "drow" || module.starts_with("drow::"), "{}", line);
+        levels.insert(level.to_string());
+        modules.insert(module.to_string());
+    }
+    (levels, modules)
+}
+
+#[test]
+fn verbosity_flags_select_the_level_on_the_command_line() {
+    let (dir, paths) = fixtures("log-flags");
+    let cases: &[(&[&str], &[&str])] = &[
+        (&["-q"], &["ERROR"]),
+        (&[], &["ERROR", "WARN"]),
+        (&["-v"], &["ERROR", "INFO", "WARN"]),
+        (&["-vv"], &["DEBUG", "ERROR", "INFO", "WARN"]),
+        (
+            &["-v", "-v", "-v"],
+            &["DEBUG", "ERROR", "INFO", "TRACE", "WARN"],
+        ),
+    ];
+    for (arguments, expected) in cases {
+        let output = drow(&dir, arguments, &paths);
+        assert_eq!(output.status.code(), Some(126), "{:?}", output);
+        let (levels, modules) = levels_and_modules(&diagnostics(&output));
+        let expected: BTreeSet<String> = expected.iter().map(|level| level.to_string()).collect();
+        assert_eq!(levels, expected, "{:?}", arguments);
+        if levels.contains("DEBUG") {
+            assert!(modules.contains("drow::elf"), "{:?}", modules);
+            assert!(modules.contains("drow::loader"), "{:?}", modules);
+        }
+    }
+}
+
+#[test]
+fn the_log_file_takes_every_diagnostic_off_stderr() {
+    let (dir, paths) = fixtures("log-file");
+    let log = Path::new(&dir).join("drow.log");
+    let output = drow(&dir, &["-v", "--log-file", &log.to_string_lossy()], &paths);
+    assert_eq!(output.status.code(), Some(126), "{:?}", output);
+    assert!(diagnostics(&output).is_empty(), "{:?}", output);
+    let (levels, _) = levels_and_modules(&fs::read_to_string(&log).unwrap());
+    assert!(
+        levels.contains("ERROR") && levels.contains("INFO"),
+        "{:?}",
+        levels
+    );
+}
@@ -0,0 +1,118 @@
+//! Libraries pinned by a manifest, and files that do not have the digest it lists.
+
+mod common;
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+use common::{data_library, fixture_dir, library_cache, write_fixture};
+use drow::cache::LibraryCache;
+use drow::loader::{DependenciesResolver, LibraryOrigin};
+use drow::manifest::Manifest;
+use drow::{DrowError, MACHINE_X86_64};
+
+/// libroot.so needing libvalue.so, and a manifest pinning libvalue.so with `digest`, if any.
+fn fixtures(dir: &Path, digest: Option<&str>) -> (String, String, String) {
+    let value = write_fixture(
+        dir,
+        "libvalue.so",
+        &data_library("value", &[1; 8], 8).finalize(),
+    );
+    let root = write_fixture(
+        dir,
+        "libroot.so",
+        &data_library("root_value", &[2; 8], 8)
+            .add_needed("libvalue.so")
+            .finalize(),
+    );
+    let mut line = format!("libvalue.so = {}", value);
+    if let Some(digest) = digest {
+        line.push_str(&format!(" sha256={}", digest));
+    }
+    let manifest = write_fixture(dir, "drow.manifest", format!("{}\n", line).as_bytes());
+    (manifest, value, root)
+}
+
+fn resolver(dir: &Path, manifest: &str) -> DependenciesResolver {
+    let cache = write_fixture(dir, "ld.so.cache", &library_cache(&[]));
+    DependenciesResolver::new(LibraryCache::load(&cache).unwrap(), None)
+        .with_manifest(Manifest::load(manifest).unwrap(), false)
+}
+
+#[test]
+fn a_pinned_library_is_resolved_from_the_manifest() {
+    let dir = fixture_dir("manifest-pinned");
+    let (manifest, value, _) = fixtures(&dir, None);
+    let mut resolver = resolver(&dir, &manifest);
+    let (paths, origin) = resolver
+        .resolve_path_with_origin(&String::from("libvalue.so"), MACHINE_X86_64)
+        .unwrap();
+    assert_eq!(paths, vec![value]);
+    assert_eq!(origin, LibraryOrigin::Manifest);
+    let (paths, origin) = resolver
+        .resolve_path_with_origin(&String::from("libother.so"), MACHINE_X86_64)
+        .unwrap();
+    assert!(paths.is_empty());
+    assert_eq!(origin, LibraryOrigin::Missing);
+}
+
+#[test]
+fn a_digest_mismatch_is_an_error() {
+    let dir = fixture_dir("manifest-mismatch");
+    let (manifest, value, _) = fixtures(&dir, Some(&"0".repeat(64)));
+    let mut resolver = resolver(&dir, &manifest);
+    match resolver.resolve_path_with_origin(&String::from("libvalue.so"), MACHINE_X86_64) {
+        Err(DrowError::DigestMismatch {
+            manifest: path,
+            soname,
+            path: file,
+            actual,
+            expected,
+        }) => {
+            assert_eq!(path, manifest);
+            assert_eq!(soname, "libvalue.so");
+            assert_eq!(file, value);
+            assert_eq!(expected, "0".repeat(64));
+            assert_eq!(actual.len(), 64);
+            assert_ne!(actual, expected);
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+fn drow(arguments: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(arguments)
+        .env_remove("LD_LIBRARY_PATH")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn run_and_resolve_fail_on_a_digest_mismatch() {
+    let dir = fixture_dir("manifest-cli");
+    let (manifest, value, root) = fixtures(&dir, Some(&"0".repeat(64)));
+    for command in ["run", "resolve"] {
+        let mut arguments = vec![command, "--manifest", &manifest];
+        if command == "run" {
+            arguments.push("--no-exec");
+        }
+        arguments.push(&root);
+        let output = drow(&arguments);
+        assert_eq!(output.status.code(), Some(126), "{:?}", output);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains(&format!(
+                "Manifest {}: {} for libvalue.so has SHA-256 ",
+                manifest, value
+            )),
+            "{}",
+            stderr
+        );
+        assert!(
+            stderr.contains(&format!(", expected {}", "0".repeat(64))),
+            "{}",
+            stderr
+        );
+    }
+}
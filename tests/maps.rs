@@ -0,0 +1,137 @@
+//! The memory layout after loading, as `--maps` prints it: one entry per mapped segment, its
+//! zero-filled tail and drow's stack, sorted by address.
+
+mod common;
+
+use std::path::Path;
+use std::process::Command;
+
+use common::{data_library, fixture_dir, offline_loader, write_fixture};
+use drow::loader::MapEntry;
+use drow::{PROGRAM_FLAG_EXECUTE, PROGRAM_FLAG_READ, PROGRAM_HEADER_TYPE_LOADABLE};
+
+/// libmaps.so: a data segment at 0x1000 with a page of file contents and a page zero-filled,
+/// the generated sections at 0x3000 and a text segment at 0x5000.
+fn fixture(dir: &Path) -> String {
+    write_fixture(
+        dir,
+        "libmaps.so",
+        &data_library("value", &[1; 0x1000], 0x2000)
+            .map_dynamic(0x3000)
+            .add_segment(
+                PROGRAM_HEADER_TYPE_LOADABLE,
+                PROGRAM_FLAG_READ | PROGRAM_FLAG_EXECUTE,
+                0x5000,
+                &[0xC3; 0x10],
+                0x10,
+            )
+            .finalize(),
+    )
+}
+
+fn assert_sorted_and_disjoint(entries: &[MapEntry]) {
+    for entry in entries.iter() {
+        assert!(entry.start < entry.end, "{:#x}", entry.start);
+    }
+    for pair in entries.windows(2) {
+        assert!(
+            pair[0].end <= pair[1].start,
+            "{:#x}-{:#x} and {:#x}-{:#x}",
+            pair[0].start,
+            pair[0].end,
+            pair[1].start,
+            pair[1].end
+        );
+    }
+}
+
+#[test]
+fn entries_are_sorted_disjoint_and_cover_every_segment() {
+    let dir = fixture_dir("maps-entries");
+    let path = fixture(&dir);
+    let loader = offline_loader(&dir);
+    loader.load_library(&path).unwrap();
+    loader.allocate_stack().unwrap();
+    let entries = loader.memory_map_entries();
+    assert_sorted_and_disjoint(&entries);
+
+    let object: Vec<(u64, &str, bool)> = entries
+        .iter()
+        .filter(|entry| entry.object == path)
+        .map(|entry| {
+            (
+                entry.end - entry.start,
+                entry.role,
+                entry.file_offset.is_some(),
+            )
+        })
+        .collect();
+    // Only the zero-filled tail has no file contents.
+    assert_eq!(
+        object,
+        vec![
+            (0x1000, "data", true),
+            (0x1000, "bss", false),
+            (0x1000, "rodata", true),
+            (0x1000, "text", true),
+        ]
+    );
+    let stacks: Vec<&MapEntry> = entries
+        .iter()
+        .filter(|entry| entry.role == "stack")
+        .collect();
+    assert_eq!(stacks.len(), 1);
+    assert_eq!(stacks[0].object, "[drow stack]");
+    assert_eq!(entries.len(), 5);
+}
+
+#[test]
+fn maps_prints_one_row_per_entry() {
+    let dir = fixture_dir("maps-printed");
+    let path = fixture(&dir);
+    let output = Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(["run", "--no-exec", "--maps", "--offline", "--search-dir"])
+        .arg(&dir)
+        .arg(&path)
+        .env_remove("LD_LIBRARY_PATH")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines();
+    assert_eq!(
+        lines
+            .next()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>()),
+        Some(vec!["Start-End", "Perms", "Offset", "Object", "Role"])
+    );
+    let rows: Vec<Vec<&str>> = lines
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+    let object: Vec<(&str, &str)> = rows
+        .iter()
+        .filter(|row| row[3] == path)
+        .map(|row| (row[1], row[4]))
+        .collect();
+    assert_eq!(
+        object,
+        vec![
+            ("rw-p", "data"),
+            ("rw-p", "bss"),
+            ("r--p", "rodata"),
+            ("r-xp", "text"),
+        ]
+    );
+    assert!(
+        rows.iter()
+            .any(|row| row[1..] == ["rwxp", "-", "[drow", "stack]", "stack"]),
+        "{}",
+        stdout
+    );
+    assert_eq!(rows.len(), 5, "{}", stdout);
+    // Sorted by start address.
+    let starts: Vec<&str> = rows.iter().map(|row| row[0]).collect();
+    let mut sorted = starts.clone();
+    sorted.sort();
+    assert_eq!(starts, sorted);
+}
@@ -0,0 +1,66 @@
+//! Loading an image from bytes, through a sealed memfd, without a file on disk.
+
+mod common;
+
+use common::{
+    data_library, fixture_dir, mapped_bytes, mapped_word, object_base, offline_loader,
+    write_fixture,
+};
+use drow::memory_elf::MemoryBackedElf;
+use drow::{
+    RELOCATION_X86_64_GLOB_DAT, RELOCATION_X86_64_RELATIVE, SYMBOL_BINDING_GLOBAL,
+    SYMBOL_TYPE_OBJECT,
+};
+
+#[test]
+fn a_library_loaded_from_bytes_resolves_its_symbols() {
+    let dir = fixture_dir("memory-elf-load");
+    // Its dependency is still found on disk.
+    let dependency = write_fixture(
+        &dir,
+        "libdependency.so",
+        &data_library("dependency_value", &[4; 8], 8)
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    let bytes = data_library("memory_value", &[9; 0x18], 0x18)
+        .add_needed("libdependency.so")
+        .add_rela(0x1008, RELOCATION_X86_64_RELATIVE, None, 0x4)
+        .add_rela(
+            0x1010,
+            RELOCATION_X86_64_GLOB_DAT,
+            Some("dependency_value"),
+            0,
+        )
+        .add_symbol(
+            "dependency_value",
+            SYMBOL_BINDING_GLOBAL,
+            SYMBOL_TYPE_OBJECT,
+            0,
+            0,
+            0,
+        )
+        .map_dynamic(0x3000)
+        .finalize();
+    let image = MemoryBackedElf::from_bytes("libmemory.so", &bytes).unwrap();
+    let loader = offline_loader(&dir);
+    loader.load_from_bytes(&image).unwrap();
+
+    let path = "memfd:libmemory.so";
+    let base = object_base(&loader, path);
+    let value = loader.lookup_symbol("memory_value").unwrap();
+    assert_eq!(value, base + 0x1000);
+    assert_eq!(loader.lookup_symbol_in(path, "memory_value"), Some(value));
+    assert_eq!(mapped_bytes(value, 8), vec![9; 8]);
+    assert_eq!(mapped_word(base + 0x1008), base + 0x4);
+    let dependency_value = loader.lookup_symbol_in(&dependency, "dependency_value");
+    assert_eq!(Some(mapped_word(base + 0x1010)), dependency_value);
+    let objects: Vec<String> = loader
+        .load_report(false)
+        .objects
+        .iter()
+        .map(|object| object.path.clone())
+        .collect();
+    assert!(objects.contains(&String::from(path)), "{:?}", objects);
+    assert!(objects.contains(&dependency), "{:?}", objects);
+}
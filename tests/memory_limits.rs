@@ -0,0 +1,141 @@
+//! Loads needing more memory than the process may map: refused before anything is mapped when
+//! RLIMIT_AS is too low, and unwound when a mapping fails half way.
+
+mod common;
+
+use std::fs;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Output};
+use std::sync::{Arc, Mutex};
+
+use common::{data_library, fixture_dir, write_fixture};
+use drow::loader::Elf64Loader;
+use drow::testutil::fail_executable_file_mappings;
+use drow::{DrowError, PROGRAM_FLAG_EXECUTE, PROGRAM_FLAG_READ, PROGRAM_HEADER_TYPE_LOADABLE};
+
+const GIB: u64 = 1 << 30;
+const TEXT: u64 = 0x10000;
+
+/// Runs `drow run --no-exec` on `library` with its address space limited to `limit` bytes.
+fn run_limited(dir: &Path, library: &str, limit: u64) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_drow"));
+    command
+        .args(["run", "--no-exec", "--offline", "--search-dir"])
+        .arg(dir)
+        .arg(library);
+    unsafe {
+        command.pre_exec(move || {
+            let rlimit = libc::rlimit {
+                rlim_cur: limit,
+                rlim_max: limit,
+            };
+            if libc::setrlimit(libc::RLIMIT_AS, &rlimit) < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    command.output().unwrap()
+}
+
+#[test]
+fn a_load_past_rlimit_as_fails_before_mapping() {
+    let dir = fixture_dir("memory-limits-rlimit");
+    // 1 GiB of zero filled data.
+    let library = write_fixture(
+        &dir,
+        "libhuge.so",
+        &data_library("huge", &[0; 8], GIB).finalize(),
+    );
+    let output = run_limited(&dir, &library, GIB / 2);
+    assert!(!output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "Loading needs {} bytes of address space (RLIMIT_AS), only ",
+            GIB
+        )),
+        "{}",
+        stderr
+    );
+    // Refused upfront rather than by a failing mmap.
+    assert!(!stderr.contains("Unable to map"), "{}", stderr);
+
+    let output = run_limited(&dir, &library, 4 * GIB);
+    assert!(output.status.success(), "{:?}", output);
+}
+
+/// The mappings of the process backed by a file of `dir`.
+fn mapped_from(dir: &Path) -> Vec<String> {
+    let dir = dir.to_string_lossy();
+    fs::read_to_string("/proc/self/maps")
+        .unwrap()
+        .lines()
+        .filter(|line| line.contains(dir.as_ref()))
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn a_failed_mapping_unmaps_what_the_load_mapped() {
+    let dir = fixture_dir("memory-limits-unwind");
+    write_fixture(
+        &dir,
+        "libdependency.so",
+        &data_library("dependency", &[1; 8], 8)
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    // Mapped after its dependency, its code fails to map.
+    let app = write_fixture(
+        &dir,
+        "libapp.so",
+        &data_library("app", &[2; 8], 8)
+            .add_needed("libdependency.so")
+            .map_dynamic(0x3000)
+            .add_segment(
+                PROGRAM_HEADER_TYPE_LOADABLE,
+                PROGRAM_FLAG_READ | PROGRAM_FLAG_EXECUTE,
+                TEXT,
+                &[0xC3],
+                1,
+            )
+            .finalize(),
+    );
+    let relocated = Arc::new(Mutex::new(Vec::new()));
+    let audited = relocated.clone();
+    let loader = Elf64Loader::builder()
+        .offline(&[dir.to_string_lossy().into_owned()])
+        .audit(move |object| {
+            audited
+                .lock()
+                .unwrap()
+                .push(object.metadata.file_path.clone())
+        })
+        .build()
+        .unwrap();
+
+    fail_executable_file_mappings(Some(libc::ENOMEM));
+    let result = loader.load_library(&app);
+    fail_executable_file_mappings(None);
+    match result {
+        Err(DrowError::MapFailed { source, .. }) => {
+            assert_eq!(source.raw_os_error(), Some(libc::ENOMEM))
+        }
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+    // The dependency was mapped and relocated first, then unmapped.
+    assert_eq!(
+        *relocated.lock().unwrap(),
+        vec![dir.join("libdependency.so").to_string_lossy().into_owned()]
+    );
+    assert_eq!(mapped_from(&dir), Vec::<String>::new());
+    assert!(loader.memory_map_entries().is_empty());
+    assert!(loader.load_report(false).objects.is_empty());
+    assert!(loader.lookup_symbol("dependency").is_none());
+
+    // Nothing of the failed load is left to get in the way of the next.
+    loader.load_library(&app).unwrap();
+    assert!(loader.lookup_symbol("dependency").is_some());
+}
@@ -0,0 +1,166 @@
+//! Several input files in one invocation: each in turn, under its own heading, and a count of
+//! the files that succeeded and failed, the first failure deciding the exit status.
+
+mod common;
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+use common::{data_library, fixture_dir, write_fixture};
+
+const EXIT_USAGE: i32 = 125;
+const EXIT_LOAD_FAILED: i32 = 126;
+const EXIT_NOT_FOUND: i32 = 127;
+
+fn drow(dir: &Path, arguments: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(arguments)
+        .current_dir(dir)
+        .env_remove("LD_LIBRARY_PATH")
+        .output()
+        .unwrap()
+}
+
+/// libfirst.so and libsecond.so, the second needing the first, and a file that is not ELF.
+fn fixtures(dir: &Path) -> (String, String, String) {
+    let first = write_fixture(
+        dir,
+        "libfirst.so",
+        &data_library("first_value", &[1; 8], 8)
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    let second = write_fixture(
+        dir,
+        "libsecond.so",
+        &data_library("second_value", &[2; 8], 8)
+            .add_needed("libfirst.so")
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    let broken = write_fixture(dir, "broken.so", b"not an ELF file at all");
+    (first, second, broken)
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn summary_mode_goes_on_past_a_broken_file() {
+    let dir = fixture_dir("multiple-summary");
+    let (first, second, broken) = fixtures(&dir);
+    let output = drow(&dir, &["inspect", "--summary", &first, &broken, &second]);
+    assert_eq!(output.status.code(), Some(EXIT_LOAD_FAILED), "{:?}", output);
+    let stdout = stdout(&output);
+    let paths: Vec<&str> = stdout
+        .lines()
+        .map(|line| line.split(' ').next().unwrap())
+        .collect();
+    assert_eq!(
+        paths,
+        vec![first.as_str(), broken.as_str(), second.as_str()]
+    );
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert!(
+        lines[1].starts_with(&format!("{} ERROR ", broken)),
+        "{}",
+        stdout
+    );
+    assert!(lines[2].contains(" DYN x86_64 "), "{}", stdout);
+    assert!(
+        stderr(&output).ends_with("2 ok, 1 failed\n"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn each_file_is_printed_under_its_own_heading() {
+    let dir = fixture_dir("multiple-headings");
+    let (first, second, _) = fixtures(&dir);
+    let output = drow(&dir, &["inspect", &first, &second]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = stdout(&output);
+    let first_heading = format!("==> {} <==\n", first);
+    let second_heading = format!("\n\n==> {} <==\n", second);
+    assert!(stdout.starts_with(&first_heading), "{}", stdout);
+    let second_at = stdout.find(&second_heading).expect(&stdout);
+    assert!(stdout[..second_at].contains("first_value"), "{}", stdout);
+    assert!(!stdout[..second_at].contains("second_value"), "{}", stdout);
+    assert!(stdout[second_at..].contains("second_value"), "{}", stdout);
+    assert!(
+        stderr(&output).ends_with("2 ok, 0 failed\n"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn the_first_failure_decides_the_exit_status() {
+    let dir = fixture_dir("multiple-status");
+    let (first, _, broken) = fixtures(&dir);
+    let missing = dir.join("libmissing.so").to_string_lossy().into_owned();
+    let search_dir = dir.to_string_lossy();
+    let output = drow(
+        &dir,
+        &[
+            "resolve",
+            "--offline",
+            "--search-dir",
+            &search_dir,
+            &missing,
+            &first,
+            &broken,
+        ],
+    );
+    assert_eq!(output.status.code(), Some(EXIT_NOT_FOUND), "{:?}", output);
+    assert!(
+        stderr(&output).ends_with("1 ok, 2 failed\n"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn running_several_files_takes_each() {
+    let dir = fixture_dir("multiple-run");
+    let (first, second, _) = fixtures(&dir);
+    let output = drow(&dir, &["run", "--no-exec", &first, &second]);
+    assert_eq!(output.status.code(), Some(EXIT_USAGE), "{:?}", output);
+    assert!(
+        stderr(&output).contains("run accepts several files only with --each"),
+        "{}",
+        stderr(&output)
+    );
+
+    let search_dir = dir.to_string_lossy();
+    let output = drow(
+        &dir,
+        &[
+            "run",
+            "--each",
+            "--no-exec",
+            "-v",
+            "--offline",
+            "--search-dir",
+            &search_dir,
+            &first,
+            &second,
+        ],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = stderr(&output);
+    for path in [&first, &second] {
+        assert!(
+            stderr.contains(&format!("Loaded {}, not running it", path)),
+            "{}",
+            stderr
+        );
+    }
+    assert!(stderr.ends_with("2 ok, 0 failed\n"), "{}", stderr);
+}
@@ -0,0 +1,114 @@
+//! Files on a noexec mount, simulated by failing their executable mappings: they are mapped from
+//! an in-memory copy instead, unless `noexec_fallback` is off.
+
+mod common;
+
+use std::path::Path;
+
+use common::{data_library, fixture_dir, mapped_bytes, object_base, write_fixture};
+use drow::loader::{Elf64Loader, LoadOptions};
+use drow::testutil::fail_executable_file_mappings;
+use drow::{DrowError, PROGRAM_FLAG_EXECUTE, PROGRAM_FLAG_READ, PROGRAM_HEADER_TYPE_LOADABLE};
+
+/// Address of an executable segment, above the data and the generated sections.
+const TEXT: u64 = 0x10000;
+const TEXT_CONTENT: [u8; 4] = [0x0F, 0x1F, 0x40, 0xC3];
+
+fn fixture(dir: &Path) -> String {
+    write_fixture(
+        dir,
+        "libnoexec.so",
+        &data_library("value", &[3; 8], 8)
+            .map_dynamic(0x3000)
+            .add_segment(
+                PROGRAM_HEADER_TYPE_LOADABLE,
+                PROGRAM_FLAG_READ | PROGRAM_FLAG_EXECUTE,
+                TEXT,
+                &TEXT_CONTENT,
+                TEXT_CONTENT.len() as u64,
+            )
+            .finalize(),
+    )
+}
+
+fn loader(dir: &Path, noexec_fallback: bool) -> Elf64Loader {
+    Elf64Loader::builder()
+        .offline(&[dir.to_string_lossy().into_owned()])
+        .options(LoadOptions {
+            noexec_fallback,
+            ..LoadOptions::default()
+        })
+        .build()
+        .unwrap()
+}
+
+/// The file backing the mapping of /proc/self/maps that contains `address`.
+fn backing_file(address: u64) -> String {
+    let maps = std::fs::read_to_string("/proc/self/maps").unwrap();
+    for line in maps.lines() {
+        let range = line.split(' ').next().unwrap();
+        let (start, end) = range.split_once('-').unwrap();
+        let start = u64::from_str_radix(start, 16).unwrap();
+        let end = u64::from_str_radix(end, 16).unwrap();
+        if (start..end).contains(&address) {
+            return line
+                .split_whitespace()
+                .skip(5)
+                .collect::<Vec<&str>>()
+                .join(" ");
+        }
+    }
+    panic!("no mapping contains {:#x}", address);
+}
+
+#[test]
+fn a_file_refused_for_execution_is_mapped_from_a_copy() {
+    let dir = fixture_dir("noexec-fallback");
+    let path = fixture(&dir);
+    for errno in [libc::EPERM, libc::EACCES] {
+        let loader = loader(&dir, true);
+        fail_executable_file_mappings(Some(errno));
+        let result = loader.load_library(&path);
+        fail_executable_file_mappings(None);
+        result.unwrap();
+
+        let base = object_base(&loader, &path);
+        assert_eq!(
+            mapped_bytes(base + TEXT, TEXT_CONTENT.len()),
+            TEXT_CONTENT.to_vec()
+        );
+        assert_eq!(mapped_bytes(base + 0x1000, 8), vec![3; 8]);
+        assert!(
+            backing_file(base + TEXT).starts_with("/memfd:libnoexec.so"),
+            "{}",
+            backing_file(base + TEXT)
+        );
+        assert_eq!(loader.lookup_symbol_in(&path, "value"), Some(base + 0x1000));
+    }
+}
+
+#[test]
+fn without_the_fallback_the_mapping_error_is_returned() {
+    let dir = fixture_dir("noexec-disabled");
+    let path = fixture(&dir);
+    let loader = loader(&dir, false);
+    fail_executable_file_mappings(Some(libc::EPERM));
+    let result = loader.load_library(&path);
+    fail_executable_file_mappings(None);
+    match result {
+        Err(DrowError::MapFailed { source, .. }) => {
+            assert_eq!(source.raw_os_error(), Some(libc::EPERM))
+        }
+        result => panic!("expected the mapping to fail, got {:?}", result),
+    }
+}
+
+#[test]
+fn files_on_an_ordinary_mount_are_mapped_directly() {
+    let dir = fixture_dir("noexec-direct");
+    let path = fixture(&dir);
+    let loader = loader(&dir, true);
+    loader.load_library(&path).unwrap();
+    let base = object_base(&loader, &path);
+    assert_eq!(backing_file(base + TEXT), path);
+}
@@ -0,0 +1,258 @@
+//! ET_DYN objects told apart: a PIE is started at its entry point, a shared library without one
+//! is refused, and a library with an entry point and an interpreter, like libc.so.6, runs when
+//! it is the program but never when it is a dependency.
+
+mod common;
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+use common::{compile, data_library, fixture_dir, write_fixture};
+use drow::dynamic::DYNAMIC_FLAGS_1_PIE;
+use drow::loader::{Elf64Loader, LoadOptions};
+use drow::offset_reader::OffsetReader;
+use drow::{DrowError, Elf64Metadata, ObjectKind};
+
+/// The DT_FLAGS_1 tag of the dynamic section.
+const DYNAMIC_TABLE_FLAGS_1: i64 = 0x6fff_fffb;
+
+/// Prints `text` and exits with no libc.
+const PUT: &str = "\
+static void put(const char *text) {
+    long length = 0, result;
+    while (text[length])
+        length++;
+    __asm__ volatile(\"syscall\" : \"=a\"(result) : \"a\"(1), \"D\"(1), \"S\"(text), \"d\"(length)
+                     : \"rcx\", \"r11\", \"memory\");
+}
+
+static void leave(void) {
+    __asm__ volatile(\"syscall\" : : \"a\"(60), \"D\"(0));
+    __builtin_unreachable();
+}
+";
+
+/// Prints its version when run, with the PT_INTERP libc.so.6 gets from its own .interp section.
+const VERSION_LIBRARY: &str = "\
+const char interpreter[] __attribute__((section(\".interp\"))) = \"/lib64/ld-linux-x86-64.so.2\";
+
+int version(void) {
+    return 1;
+}
+
+void print_version(void) {
+    put(\"libversion 1\\n\");
+    leave();
+}
+";
+
+const PLAIN_LIBRARY: &str = "\
+int plain(void) {
+    return 2;
+}
+";
+
+/// `-DNAME` prints its name, and with `-DVERSION` calls into libversion.so.
+const PROGRAM: &str = "\
+#ifdef VERSION
+int version(void);
+#endif
+
+void _start(void) {
+    put(NAME \"\\n\");
+#ifdef VERSION
+    if (version() != 1)
+        put(\"wrong version\\n\");
+#endif
+    leave();
+}
+";
+
+/// A PIE, a shared library, a shared library with an entry point, and a PIE needing the last.
+struct Fixtures {
+    pie: String,
+    plain: String,
+    version: String,
+    program: String,
+}
+
+/// Builds the fixtures in `dir`, or returns None without a C compiler.
+fn fixtures(dir: &Path) -> Option<Fixtures> {
+    let library_dir = dir.to_string_lossy();
+    let library = ["-shared", "-fPIC", "-nostdlib"];
+    let pie = compile(
+        dir,
+        "pie",
+        &format!("{}{}", PUT, PROGRAM),
+        &["-nostdlib", "-fPIE", "-pie", "-DNAME=\"pie\""],
+    )?;
+    let plain = compile(dir, "libplain.so", PLAIN_LIBRARY, &library)?;
+    let version = compile(
+        dir,
+        "libversion.so",
+        &format!("{}{}", PUT, VERSION_LIBRARY),
+        &[
+            &library[..],
+            &["-Wl,-soname,libversion.so", "-Wl,-e,print_version"],
+        ]
+        .concat(),
+    )?;
+    let program = compile(
+        dir,
+        "program",
+        &format!("{}{}", PUT, PROGRAM),
+        &[
+            "-nostdlib",
+            "-fPIE",
+            "-pie",
+            "-DNAME=\"program\"",
+            "-DVERSION",
+            "-L",
+            &library_dir,
+            "-lversion",
+        ],
+    )?;
+    Some(Fixtures {
+        pie,
+        plain,
+        version,
+        program,
+    })
+}
+
+fn object_kind(path: &str) -> ObjectKind {
+    let mut reader = OffsetReader::open(path).unwrap();
+    Elf64Metadata::load(&path.to_string(), &mut reader)
+        .unwrap()
+        .object_kind()
+}
+
+fn drow(dir: &Path, arguments: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(arguments)
+        .arg("--offline")
+        .arg("--search-dir")
+        .arg(dir)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn et_dyn_objects_are_classified() {
+    let dir = fixture_dir("object-kind-classified");
+    let Some(fixtures) = fixtures(&dir) else {
+        return;
+    };
+    assert_eq!(
+        object_kind(&fixtures.pie),
+        ObjectKind::PositionIndependentExecutable
+    );
+    assert_eq!(object_kind(&fixtures.plain), ObjectKind::SharedLibrary);
+    assert_eq!(
+        object_kind(&fixtures.version),
+        ObjectKind::ExecutableLibrary
+    );
+    for (path, kind) in [
+        (&fixtures.pie, "pie"),
+        (&fixtures.plain, "shared-library"),
+        (&fixtures.version, "executable-library"),
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_drow"))
+            .args(["inspect", path])
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains(&format!("\nkind: {}\n", kind)),
+            "{}",
+            stdout
+        );
+    }
+}
+
+#[test]
+fn programs_with_an_entry_point_run() {
+    let dir = fixture_dir("object-kind-run");
+    let Some(fixtures) = fixtures(&dir) else {
+        return;
+    };
+    for (path, expected) in [
+        (&fixtures.pie, "pie\n"),
+        (&fixtures.version, "libversion 1\n"),
+    ] {
+        let output = drow(&dir, &["run", path]);
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+    }
+}
+
+#[test]
+fn a_shared_library_without_an_entry_point_is_not_started() {
+    let dir = fixture_dir("object-kind-library");
+    let Some(fixtures) = fixtures(&dir) else {
+        return;
+    };
+    for arguments in [&["run"][..], &["run", "--fork"][..]] {
+        let output = drow(&dir, &[arguments, &[fixtures.plain.as_str()]].concat());
+        assert!(!output.status.success(), "{:?}", output);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(
+            stderr.contains(&format!(
+                "Unable to load {}: it is a shared-library without an entry point",
+                fixtures.plain
+            )),
+            "{}",
+            stderr
+        );
+    }
+}
+
+#[test]
+fn the_entry_point_of_a_dependency_is_never_used() {
+    let dir = fixture_dir("object-kind-dependency");
+    let Some(fixtures) = fixtures(&dir) else {
+        return;
+    };
+    let output = drow(&dir, &["run", &fixtures.program]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "program\n");
+
+    let loader = Elf64Loader::builder()
+        .offline(&[dir.to_string_lossy().into_owned()])
+        .build()
+        .unwrap();
+    loader.load_library(&fixtures.version).unwrap();
+    let report = loader.load_report(false);
+    assert_eq!(report.objects[0].kind, ObjectKind::ExecutableLibrary);
+}
+
+#[test]
+fn a_pie_flag_without_an_entry_point_is_refused_when_strict() {
+    let dir = fixture_dir("object-kind-pie-flag");
+    let path = write_fixture(
+        &dir,
+        "libflagged.so",
+        &data_library("flagged", &[0; 8], 8)
+            .add_dynamic(DYNAMIC_TABLE_FLAGS_1, DYNAMIC_FLAGS_1_PIE)
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    let loader = |strict| {
+        Elf64Loader::builder()
+            .offline(&[dir.to_string_lossy().into_owned()])
+            .options(LoadOptions {
+                strict,
+                ..LoadOptions::default()
+            })
+            .build()
+            .unwrap()
+    };
+    // Warned about only.
+    loader(false).load_library(&path).unwrap();
+    match loader(true).load_library(&path) {
+        Err(DrowError::NotLoadable { reason, .. }) => {
+            assert_eq!(reason, "DF_1_PIE is set but there is no entry point")
+        }
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+}
@@ -0,0 +1,264 @@
+//! Offline mode: libraries are found only below the `--search-dir` directories, through soname
+//! links that stay inside them, and every missing one is reported, the host never consulted.
+
+mod common;
+
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::Arc;
+
+use common::{data_library, fixture_dir, object_base, write_fixture};
+use drow::loader::{Elf64Loader, LibraryOrigin};
+use drow::{DrowError, Elf64Metadata, MACHINE_X86_64};
+
+fn library(symbol: &str, needed: &[&str]) -> Vec<u8> {
+    let mut builder = data_library(symbol, &[1; 8], 8);
+    for library in needed {
+        builder = builder.add_needed(library);
+    }
+    builder.map_dynamic(0x3000).finalize()
+}
+
+/// An image tree in `dir/image`, with a stub libc.so.6 in lib/x86_64-linux-gnu, libapp.so.1 in
+/// usr/lib with its soname link libapp.so, and a program outside of it needing both.
+/// Returns the canonical image root and the program.
+fn fixtures(dir: &Path) -> (PathBuf, String) {
+    let image = dir.join("image");
+    let libc_dir = image.join("lib/x86_64-linux-gnu");
+    let usr_lib = image.join("usr/lib");
+    fs::create_dir_all(&libc_dir).unwrap();
+    fs::create_dir_all(&usr_lib).unwrap();
+    write_fixture(&libc_dir, "libc.so.6", &library("stub_libc", &[]));
+    write_fixture(
+        &usr_lib,
+        "libapp.so.1",
+        &library("app_value", &["libc.so.6"]),
+    );
+    symlink("libapp.so.1", usr_lib.join("libapp.so")).unwrap();
+    let program = write_fixture(
+        dir,
+        "libprogram.so",
+        &library("program_value", &["libapp.so", "libc.so.6"]),
+    );
+    (fs::canonicalize(image).unwrap(), program)
+}
+
+fn loader(directories: &[&Path]) -> Elf64Loader {
+    let directories: Vec<String> = directories
+        .iter()
+        .map(|directory| directory.to_string_lossy().into_owned())
+        .collect();
+    Elf64Loader::builder()
+        .offline(&directories)
+        .build()
+        .unwrap()
+}
+
+fn metadata(path: &str) -> Arc<Elf64Metadata> {
+    let mut file = fs::File::open(path).unwrap();
+    Arc::new(Elf64Metadata::load(&path.to_string(), &mut file).unwrap())
+}
+
+fn inside(image: &Path, path: &str) -> String {
+    image.join(path).to_string_lossy().into_owned()
+}
+
+fn drow(arguments: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(arguments)
+        .env_remove("LD_LIBRARY_PATH")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn libraries_resolve_below_the_search_directories_only() {
+    let dir = fixture_dir("offline-resolve");
+    let (image, program) = fixtures(&dir);
+    let mut resolver = loader(&[&image]).into_dependencies_resolver();
+    let libc = inside(&image, "lib/x86_64-linux-gnu/libc.so.6");
+    let app = inside(&image, "usr/lib/libapp.so.1");
+    for (library, path) in [("libc.so.6", &libc), ("libapp.so", &app)] {
+        assert_eq!(
+            resolver
+                .resolve_path_with_origin(&library.to_string(), MACHINE_X86_64)
+                .unwrap(),
+            (vec![path.clone()], LibraryOrigin::SearchDirectory),
+            "{}",
+            library
+        );
+    }
+    // On the host, libm.so.6 is in every cache and default directory.
+    assert_eq!(
+        resolver
+            .resolve_path_with_origin(&String::from("libm.so.6"), MACHINE_X86_64)
+            .unwrap(),
+        (Vec::new(), LibraryOrigin::Missing)
+    );
+
+    let paths: Vec<String> = resolver
+        .resolve_in_loading_order(&metadata(&program))
+        .unwrap()
+        .iter()
+        .map(|object| object.file_path.clone())
+        .collect();
+    assert_eq!(paths, vec![libc, app, program]);
+}
+
+#[test]
+fn a_link_leaving_the_search_directories_is_ignored() {
+    let dir = fixture_dir("offline-escape");
+    let (image, _) = fixtures(&dir);
+    let outside = write_fixture(&dir, "libhost.so.1", &library("host_value", &[]));
+    symlink(&outside, image.join("usr/lib/libhost.so")).unwrap();
+    let mut resolver = loader(&[&image]).into_dependencies_resolver();
+    assert_eq!(
+        resolver
+            .resolve_path_with_origin(&String::from("libhost.so"), MACHINE_X86_64)
+            .unwrap(),
+        (Vec::new(), LibraryOrigin::Missing)
+    );
+}
+
+#[test]
+fn earlier_directories_win() {
+    let dir = fixture_dir("offline-order");
+    let (image, _) = fixtures(&dir);
+    let overlay = dir.join("overlay");
+    fs::create_dir(&overlay).unwrap();
+    let patched = write_fixture(&overlay, "libc.so.6", &library("patched_libc", &[]));
+    let mut resolver = loader(&[&overlay, &image]).into_dependencies_resolver();
+    assert_eq!(
+        resolver
+            .resolve_path_with_origin(&String::from("libc.so.6"), MACHINE_X86_64)
+            .unwrap(),
+        (vec![patched], LibraryOrigin::SearchDirectory)
+    );
+}
+
+#[test]
+fn the_stub_libc_is_the_one_loaded() {
+    let dir = fixture_dir("offline-load");
+    let (image, program) = fixtures(&dir);
+    let loader = loader(&[&image]);
+    loader.load_library(&program).unwrap();
+    let libc = inside(&image, "lib/x86_64-linux-gnu/libc.so.6");
+    assert_eq!(
+        loader.lookup_symbol("stub_libc"),
+        Some(object_base(&loader, &libc) + 0x1000)
+    );
+    let objects: Vec<String> = loader
+        .load_report(false)
+        .objects
+        .iter()
+        .map(|object| object.path.clone())
+        .collect();
+    let outside: Vec<&String> = objects
+        .iter()
+        .filter(|path| **path != program && !Path::new(path).starts_with(&image))
+        .collect();
+    assert!(outside.is_empty(), "{:?}", objects);
+}
+
+#[test]
+fn every_missing_library_is_reported() {
+    let dir = fixture_dir("offline-missing");
+    let (image, _) = fixtures(&dir);
+    let program = write_fixture(
+        &dir,
+        "libincomplete.so",
+        &library(
+            "incomplete_value",
+            &["libapp.so", "libfirst.so", "libsecond.so"],
+        ),
+    );
+    let loader = loader(&[&image]);
+    match loader.load_library(&program) {
+        Err(DrowError::UnresolvedLibraries { unresolved }) => {
+            let names: Vec<&str> = unresolved.iter().map(|(name, _)| name.as_str()).collect();
+            assert_eq!(names, vec!["libfirst.so", "libsecond.so"]);
+            assert_eq!(unresolved[0].1, vec![program.clone()]);
+        }
+        result => panic!("expected the unresolved libraries, got {:?}", result),
+    }
+
+    let search_dir = image.to_string_lossy();
+    let output = drow(&[
+        "resolve",
+        "--offline",
+        "--search-dir",
+        &search_dir,
+        &program,
+    ]);
+    assert_eq!(output.status.code(), Some(127), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let missing: Vec<Vec<&str>> = stdout
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>())
+        .filter(|row| row.last() == Some(&"Missing"))
+        .collect();
+    assert_eq!(
+        missing,
+        vec![
+            vec!["libfirst.so", "-", "Missing"],
+            vec!["libsecond.so", "-", "Missing"],
+        ]
+    );
+
+    let output = drow(&[
+        "run",
+        "--no-exec",
+        "--offline",
+        "--search-dir",
+        &search_dir,
+        &program,
+    ]);
+    assert_eq!(output.status.code(), Some(127), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "Unable to resolve libfirst.so required by {0}, libsecond.so required by {0}",
+            program
+        )),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn resolve_lists_the_search_directories() {
+    let dir = fixture_dir("offline-cli");
+    let (image, program) = fixtures(&dir);
+    let search_dir = image.to_string_lossy();
+    let output = drow(&[
+        "resolve",
+        "--offline",
+        "--search-dir",
+        &search_dir,
+        &program,
+    ]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with(&format!("Search directory: {}\n", search_dir)),
+        "{}",
+        stdout
+    );
+    let rows: Vec<Vec<&str>> = stdout
+        .lines()
+        .skip(2)
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+    let libc = inside(&image, "lib/x86_64-linux-gnu/libc.so.6");
+    let app = inside(&image, "usr/lib/libapp.so.1");
+    assert_eq!(
+        rows,
+        vec![
+            vec!["libprogram.so", program.as_str(), "Input"],
+            vec!["libapp.so", app.as_str(), "SearchDirectory"],
+            vec!["libc.so.6", libc.as_str(), "SearchDirectory"],
+        ]
+    );
+}
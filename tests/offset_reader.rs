@@ -0,0 +1,169 @@
+//! Positioned reads: metadata parsed through `OffsetReader` matches a buffered reader's, reads
+//! past the end of the file come up short, and threads share one reader without a position.
+
+mod common;
+
+use std::fs::File;
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom};
+use std::sync::Arc;
+use std::thread;
+
+use common::{compile, data_library, fixture_dir, write_fixture};
+use drow::offset_reader::OffsetReader;
+use drow::{Elf64Metadata, RELOCATION_X86_64_GLOB_DAT, RELOCATION_X86_64_RELATIVE};
+
+/// What parsing a file found, to compare two parses of it.
+fn parsed(metadata: &Elf64Metadata) -> Vec<String> {
+    let mut parsed = vec![
+        format!(
+            "{} program headers, {} section headers, interpreter {:?}",
+            metadata.program_headers.len(),
+            metadata.section_headers.len(),
+            metadata.interpreter
+        ),
+        format!("needed {:?}", metadata.dynamic.required_libraries),
+    ];
+    for symbol in metadata
+        .symbol_table
+        .iter()
+        .chain(metadata.dynamic_symbol_table.iter())
+    {
+        parsed.push(format!(
+            "symbol {} {:#x} {} {} {} {}",
+            symbol.versioned_name(),
+            symbol.value,
+            symbol.size,
+            symbol.binding,
+            symbol.symbol_type,
+            symbol.section_index
+        ));
+    }
+    for relocation in metadata.relocations.iter() {
+        parsed.push(format!(
+            "relocation {:#x} {} {} {}",
+            relocation.offset,
+            relocation.relocation_type,
+            relocation.symbol_name,
+            relocation.addend
+        ));
+    }
+    parsed
+}
+
+fn assert_same_parse(path: &str) {
+    let through_offsets =
+        Elf64Metadata::load(&path.to_string(), &mut OffsetReader::open(path).unwrap()).unwrap();
+    let buffered = Elf64Metadata::load(
+        &path.to_string(),
+        &mut BufReader::new(File::open(path).unwrap()),
+    )
+    .unwrap();
+    assert!(parsed(&through_offsets).len() > 2, "{}", path);
+    assert_eq!(parsed(&through_offsets), parsed(&buffered), "{}", path);
+}
+
+#[test]
+fn positioned_reads_parse_what_a_buffered_reader_parses() {
+    let dir = fixture_dir("offset-reader-parse");
+    let fixture = write_fixture(
+        &dir,
+        "libparsed.so",
+        &data_library("value", &[0; 0x10], 0x10)
+            .add_needed("libdependency.so")
+            .add_rela(0x1000, RELOCATION_X86_64_RELATIVE, None, 0x8)
+            .add_rela(0x1008, RELOCATION_X86_64_GLOB_DAT, Some("value"), 0)
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    assert_same_parse(&fixture);
+    // With a symbol table and versions, as a linker writes them.
+    if let Some(library) = compile(
+        &dir,
+        "libcompiled.so",
+        "#include <stdio.h>\nint value = 1;\nint print(void) { return puts(\"value\"); }\n",
+        &["-shared", "-fPIC"],
+    ) {
+        assert_same_parse(&library);
+    }
+}
+
+#[test]
+fn a_truncated_file_is_refused_the_same_way() {
+    let dir = fixture_dir("offset-reader-truncated");
+    let bytes = data_library("value", &[0; 8], 8)
+        .map_dynamic(0x3000)
+        .finalize();
+    let path = write_fixture(&dir, "libtruncated.so", &bytes[..bytes.len() - 0x20]);
+    let through_offsets = Elf64Metadata::load(&path, &mut OffsetReader::open(&path).unwrap());
+    let buffered = Elf64Metadata::load(&path, &mut BufReader::new(File::open(&path).unwrap()));
+    let (Err(through_offsets), Err(buffered)) = (through_offsets, buffered) else {
+        panic!("{} was parsed", path);
+    };
+    assert_eq!(through_offsets.to_string(), buffered.to_string());
+    assert!(
+        through_offsets
+            .to_string()
+            .contains("extend past the end of the file"),
+        "{}",
+        through_offsets
+    );
+}
+
+#[test]
+fn reads_past_the_end_of_the_file_come_up_short() {
+    let dir = fixture_dir("offset-reader-short");
+    let path = write_fixture(&dir, "bytes", &[1, 2, 3, 4, 5, 6, 7, 8]);
+    let mut reader = OffsetReader::open(&path).unwrap();
+
+    let mut buffer = [0; 6];
+    assert_eq!(reader.read_at(&mut buffer, 4).unwrap(), 4);
+    assert_eq!(buffer[..4], [5, 6, 7, 8]);
+    assert_eq!(reader.read_at(&mut buffer, 8).unwrap(), 0);
+    assert_eq!(reader.read_at(&mut buffer, 100).unwrap(), 0);
+
+    reader.read_exact_at(&mut buffer, 2).unwrap();
+    assert_eq!(buffer, [3, 4, 5, 6, 7, 8]);
+    let err = reader.read_exact_at(&mut buffer, 4).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    assert_eq!(err.to_string(), "Unexpected end of file at offset 8");
+
+    // Read and Seek keep their own position, which positioned reads leave alone.
+    assert_eq!(reader.seek(SeekFrom::End(-3)).unwrap(), 5);
+    reader.read_exact_at(&mut buffer[..2], 0).unwrap();
+    let mut tail = Vec::new();
+    reader.read_to_end(&mut tail).unwrap();
+    assert_eq!(tail, vec![6, 7, 8]);
+    assert_eq!(reader.seek(SeekFrom::Current(-8)).unwrap(), 0);
+    assert_eq!(
+        reader.seek(SeekFrom::Current(-1)).unwrap_err().kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(
+        reader.read_exact(&mut [0; 9]).unwrap_err().kind(),
+        ErrorKind::UnexpectedEof
+    );
+}
+
+#[test]
+fn threads_read_through_one_reader_without_sharing_a_position() {
+    let dir = fixture_dir("offset-reader-threads");
+    let bytes: Vec<u8> = (0..=255).collect();
+    let path = write_fixture(&dir, "bytes", &bytes);
+    let reader = Arc::new(OffsetReader::open(&path).unwrap());
+    let threads: Vec<_> = (0..4u8)
+        .map(|thread| {
+            let reader = Arc::clone(&reader);
+            thread::spawn(move || {
+                for round in 0..1000u32 {
+                    let offset = (thread as u32 * 64 + round % 60) as u8;
+                    let mut buffer = [0; 4];
+                    reader.read_exact_at(&mut buffer, offset as u64).unwrap();
+                    assert_eq!(buffer, [offset, offset + 1, offset + 2, offset + 3]);
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+//! `--pause-after-load`: the program is mapped and held before it runs, and goes on unchanged.
+
+mod common;
+
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::process::{Child, ChildStderr, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use common::{compile, fixture_dir};
+use drow::pause::PauseMode;
+
+/// Creates `MARKER`, then exits with `STATUS`, with no libc.
+const MARKING_PROGRAM: &str = "\
+void _start(void) {
+    long result;
+    __asm__ volatile(\"syscall\" : \"=a\"(result) : \"a\"(2), \"D\"(MARKER), \"S\"(0101), \"d\"(0644)
+                     : \"rcx\", \"r11\", \"memory\");
+    __asm__ volatile(\"syscall\" : : \"a\"(60), \"D\"(STATUS));
+    __builtin_unreachable();
+}
+";
+
+/// Exits with 41 when it starts with SIGUSR1 blocked, 40 when not.
+const MASK_PROGRAM: &str = "\
+void _start(void) {
+    unsigned long mask = 0;
+    register long size __asm__(\"r10\") = sizeof(mask);
+    long result;
+    __asm__ volatile(\"syscall\" : \"=a\"(result) : \"a\"(14), \"D\"(0), \"S\"(0), \"d\"(&mask), \"r\"(size)
+                     : \"rcx\", \"r11\", \"memory\");
+    __asm__ volatile(\"syscall\" : : \"a\"(60), \"D\"(40 + ((mask >> 9) & 1)));
+    __builtin_unreachable();
+}
+";
+
+fn spawn(mode: &str, arguments: &[&str], program: &str) -> Child {
+    Command::new(env!("CARGO_BIN_EXE_drow"))
+        .arg("run")
+        .arg(format!("--pause-after-load={}", mode))
+        .args(arguments)
+        .arg(program)
+        .env_remove("LD_LIBRARY_PATH")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap()
+}
+
+/// Reads stderr up to the pause banner and returns the paused pid and the banner.
+fn wait_for_banner(stderr: &mut BufReader<ChildStderr>) -> (i32, String) {
+    let mut line = String::new();
+    loop {
+        line.clear();
+        assert!(stderr.read_line(&mut line).unwrap() > 0, "no pause banner");
+        if let Some(rest) = line.trim_end().strip_prefix("Paused process ") {
+            let pid = rest.split(' ').next().unwrap().parse().unwrap();
+            return (pid, line.trim_end().to_string());
+        }
+    }
+}
+
+fn marking_program(dir: &Path, marker: &Path) -> Option<String> {
+    compile(
+        dir,
+        "marking",
+        MARKING_PROGRAM,
+        &[
+            "-nostdlib",
+            "-DSTATUS=3",
+            &format!("-DMARKER=\"{}\"", marker.display()),
+        ],
+    )
+}
+
+#[test]
+fn stdin_pause_holds_the_program_until_a_line_arrives() {
+    let dir = fixture_dir("pause-stdin");
+    for (index, arguments) in [&[][..], &["--fork"]].iter().enumerate() {
+        let marker = dir.join(format!("ran-{}", index));
+        let Some(program) = marking_program(&dir, &marker) else {
+            return;
+        };
+        let mut child = spawn("stdin", arguments, &program);
+        let mut stderr = BufReader::new(child.stderr.take().unwrap());
+        let (pid, banner) = wait_for_banner(&mut stderr);
+        assert_eq!(
+            banner,
+            format!("Paused process {} after load, press Enter to continue", pid)
+        );
+        if arguments.is_empty() {
+            assert_eq!(pid, child.id() as i32);
+        }
+        thread::sleep(Duration::from_millis(200));
+        assert!(!marker.exists(), "{:?} ran before the line", arguments);
+        child.stdin.take().unwrap().write_all(b"\n").unwrap();
+        let status = child.wait().unwrap();
+        assert_eq!(status.code(), Some(3), "{:?}", arguments);
+        assert!(marker.exists(), "{:?}", arguments);
+    }
+}
+
+fn sigusr1_blocked() -> bool {
+    unsafe {
+        let mut mask: libc::sigset_t = std::mem::zeroed();
+        assert_eq!(
+            libc::pthread_sigmask(libc::SIG_BLOCK, std::ptr::null(), &mut mask),
+            0
+        );
+        libc::sigismember(&mask, libc::SIGUSR1) == 1
+    }
+}
+
+#[test]
+fn hold_and_release_restore_the_signal_mask() {
+    // The mask is per thread, and the test has this one to itself.
+    thread::spawn(|| {
+        assert!(!sigusr1_blocked());
+        PauseMode::Signal.hold().unwrap();
+        assert!(sigusr1_blocked());
+        PauseMode::Signal.release().unwrap();
+        assert!(!sigusr1_blocked());
+        PauseMode::Stdin.hold().unwrap();
+        PauseMode::Stdin.release().unwrap();
+        assert!(!sigusr1_blocked());
+    })
+    .join()
+    .unwrap();
+}
+
+#[test]
+fn signal_pause_leaves_the_program_signal_mask_unchanged() {
+    let dir = fixture_dir("pause-mask");
+    let Some(program) = compile(&dir, "mask", MASK_PROGRAM, &["-nostdlib"]) else {
+        return;
+    };
+    for arguments in [&[][..], &["--fork"]] {
+        let mut child = spawn("signal", arguments, &program);
+        let mut stderr = BufReader::new(child.stderr.take().unwrap());
+        let (pid, banner) = wait_for_banner(&mut stderr);
+        assert!(
+            banner.ends_with(&format!("send SIGUSR1 to {} to continue", pid)),
+            "{}",
+            banner
+        );
+        assert_eq!(unsafe { libc::kill(pid, libc::SIGUSR1) }, 0);
+        let status = child.wait().unwrap();
+        assert_eq!(status.code(), Some(40), "{:?}", arguments);
+    }
+}
@@ -0,0 +1,207 @@
+//! Calling back into the loader from code it runs: init functions load libraries as nested
+//! loads, indirect function resolvers are refused.
+
+mod common;
+
+use std::io::Read;
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use common::{compile, fixture_dir};
+
+const INNER: &str = "int inner_value = 40;\n";
+
+/// Its indirect function resolver runs while the library is relocated and tries to load
+/// `INNER_PATH`, keeping the error.
+const RESOLVER: &str = "\
+#include <dlfcn.h>
+
+char resolver_error[512];
+
+static int answer_implementation(void) { return 1; }
+
+static void *resolve_answer(void) {
+    if (dlopen(INNER_PATH, RTLD_NOW) == 0) {
+        const char *error = dlerror();
+        unsigned long length = 0;
+        while (error != 0 && error[length] != 0 && length + 1 < sizeof(resolver_error)) {
+            resolver_error[length] = error[length];
+            length++;
+        }
+    }
+    return (void *)answer_implementation;
+}
+
+static int answer(void) __attribute__((ifunc(\"resolve_answer\")));
+int (*answer_pointer)(void) = answer;
+";
+
+/// Its constructor opens `INNER_PATH` and `RESOLVER_PATH` through the interposed dlopen.
+const OUTER: &str = "\
+#include <dlfcn.h>
+
+static int status = 1;
+static const char *message = \"no message\";
+
+__attribute__((constructor)) static void open_libraries(void) {
+    void *inner = dlopen(INNER_PATH, RTLD_NOW);
+    if (inner == 0) {
+        message = dlerror();
+        return;
+    }
+    int *value = dlsym(inner, \"inner_value\");
+    void *resolver = dlopen(RESOLVER_PATH, RTLD_NOW);
+    if (value == 0 || resolver == 0) {
+        message = dlerror();
+        return;
+    }
+    message = dlsym(resolver, \"resolver_error\");
+    status = *value + 2;
+}
+
+int outer_status(void) { return status; }
+const char *outer_message(void) { return message; }
+";
+
+/// Writes the message of libouter.so and exits with its status, without libc.
+const PROGRAM: &str = "\
+int outer_status(void);
+const char *outer_message(void);
+
+static long system_call(long number, long first, long second, long third) {
+    long result;
+    __asm__ volatile(\"syscall\"
+                     : \"=a\"(result)
+                     : \"a\"(number), \"D\"(first), \"S\"(second), \"d\"(third)
+                     : \"rcx\", \"r11\", \"memory\");
+    return result;
+}
+
+void _start(void) {
+    const char *message = outer_message();
+    long length = 0;
+    while (message[length] != 0) {
+        length++;
+    }
+    system_call(1, 1, (long)message, length);
+    system_call(60, outer_status(), 0, 0);
+    __builtin_unreachable();
+}
+";
+
+/// The program linked against libouter.so, with the libraries in `dir`. Without `no_plt`, the
+/// resolver calls dlopen through a PLT slot that is not bound yet when it runs, and crashes.
+fn fixtures(dir: &Path, no_plt: bool) -> Option<String> {
+    let define =
+        |name: &str, library: &str| format!("-D{}=\"{}\"", name, dir.join(library).display());
+    let library = ["-shared", "-fPIC", "-nostdlib"];
+    compile(dir, "libinner.so", INNER, &library)?;
+    let inner_path = define("INNER_PATH", "libinner.so");
+    let resolver_path = define("RESOLVER_PATH", "libresolver.so");
+    let defines = [library.as_slice(), &[inner_path.as_str()]].concat();
+    let plt = if no_plt { "-fno-plt" } else { "-fplt" };
+    let resolver = [defines.as_slice(), &[plt]].concat();
+    compile(dir, "libresolver.so", RESOLVER, &resolver)?;
+    let defines = [defines.as_slice(), &[resolver_path.as_str()]].concat();
+    compile(dir, "libouter.so", OUTER, &defines)?;
+    compile(
+        dir,
+        "program",
+        PROGRAM,
+        &[
+            "-nostdlib",
+            // drow defines the dl functions libouter.so calls.
+            "-Wl,--allow-shlib-undefined",
+            &format!("-L{}", dir.display()),
+            "-louter",
+        ],
+    )
+}
+
+/// Runs the program, killing drow if it takes more than a minute, as a hang is a failure too.
+fn run(dir: &Path, program: &str, fork: bool) -> (ExitStatus, String, String) {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_drow"));
+    command
+        .args(["run", "--offline", "--search-dir"])
+        .arg(dir)
+        .env_remove("LD_LIBRARY_PATH")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if fork {
+        command.arg("--fork");
+    }
+    let mut child = command.arg(program).spawn().unwrap();
+    let started = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if started.elapsed() > Duration::from_secs(60) {
+            child.kill().unwrap();
+            panic!("drow run {} hangs", program);
+        }
+        thread::sleep(Duration::from_millis(20));
+    };
+    let mut stdout = String::new();
+    let mut stderr = String::new();
+    child.stdout.unwrap().read_to_string(&mut stdout).unwrap();
+    child.stderr.unwrap().read_to_string(&mut stderr).unwrap();
+    (status, stdout, stderr)
+}
+
+fn check_nested_loads(test: &str, fork: bool) {
+    let dir = fixture_dir(test);
+    let Some(program) = fixtures(&dir, true) else {
+        return;
+    };
+    let (status, stdout, stderr) = run(&dir, &program, fork);
+    // 40 from libinner.so, loaded by the constructor of libouter.so, which got as far as
+    // loading libresolver.so too.
+    assert_eq!(status.code(), Some(42), "{}", stderr);
+    assert!(
+        stdout.starts_with("open_library called while relocating objects on the same thread"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn constructor_loads_libraries_and_resolver_is_refused() {
+    check_nested_loads("phase-same-process", false);
+}
+
+#[test]
+fn constructor_loads_libraries_and_resolver_is_refused_in_a_child() {
+    check_nested_loads("phase-child", true);
+}
+
+fn check_crash_in_resolver(test: &str, fork: bool) {
+    let dir = fixture_dir(test);
+    let Some(program) = fixtures(&dir, false) else {
+        return;
+    };
+    let (status, _, stderr) = run(&dir, &program, fork);
+    if fork {
+        assert_eq!(status.code(), Some(128 + libc::SIGSEGV), "{}", stderr);
+    } else {
+        assert_eq!(status.signal(), Some(libc::SIGSEGV), "{}", stderr);
+    }
+    assert!(
+        stderr.contains("SIGSEGV at ") && stderr.contains(" while relocating objects"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn crash_in_a_resolver_of_a_nested_load_is_reported() {
+    check_crash_in_resolver("phase-crash-same-process", false);
+}
+
+#[test]
+fn crash_in_a_resolver_of_a_nested_load_is_reported_from_a_child() {
+    check_crash_in_resolver("phase-crash-child", true);
+}
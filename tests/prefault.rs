@@ -0,0 +1,111 @@
+//! `--prefault` and `--advise-sequential`, checked against /proc/self/smaps of the loading
+//! process: prefaulted pages are resident, advised mappings carry the advice.
+
+mod common;
+
+use std::path::Path;
+
+use common::{data_library, fixture_dir, object_base, write_fixture};
+use drow::loader::{Elf64Loader, LoadOptions};
+use drow::{PROGRAM_FLAG_READ, PROGRAM_HEADER_TYPE_LOADABLE};
+
+/// Address and size of a read-only segment nothing reads while loading.
+const RODATA: u64 = 0x10000;
+const RODATA_SIZE: u64 = 0x20000;
+
+const PAGE_SIZE: u64 = 0x1000;
+
+fn fixture(dir: &Path) -> String {
+    write_fixture(
+        dir,
+        "libprefault.so",
+        &data_library("value", &[0; 8], 8)
+            .map_dynamic(0x3000)
+            .add_segment(
+                PROGRAM_HEADER_TYPE_LOADABLE,
+                PROGRAM_FLAG_READ,
+                RODATA,
+                &[0x5A; RODATA_SIZE as usize],
+                RODATA_SIZE,
+            )
+            .finalize(),
+    )
+}
+
+fn loader(dir: &Path, options: LoadOptions) -> Elf64Loader {
+    Elf64Loader::builder()
+        .offline(&[dir.to_string_lossy().into_owned()])
+        .options(options)
+        .build()
+        .unwrap()
+}
+
+/// The Rss and VmFlags of the mapping of /proc/self/smaps starting at `address`.
+fn smaps(address: u64) -> (u64, Vec<String>) {
+    let smaps = std::fs::read_to_string("/proc/self/smaps").unwrap();
+    let mut lines = smaps.lines().skip_while(|line| {
+        let start = line.split('-').next().unwrap();
+        u64::from_str_radix(start, 16) != Ok(address)
+    });
+    lines.next().expect("no mapping at the address");
+    let mut resident = None;
+    for line in lines {
+        if let Some(kilobytes) = line.strip_prefix("Rss:") {
+            let kilobytes = kilobytes.trim().trim_end_matches(" kB");
+            resident = Some(kilobytes.parse::<u64>().unwrap() * 1024);
+        } else if let Some(flags) = line.strip_prefix("VmFlags:") {
+            let flags = flags.split_whitespace().map(String::from).collect();
+            return (resident.unwrap(), flags);
+        }
+    }
+    panic!("no VmFlags for the mapping at {:#x}", address);
+}
+
+#[test]
+fn prefaulted_segments_are_resident_and_counted() {
+    let dir = fixture_dir("prefault-on");
+    let path = fixture(&dir);
+    let loader = loader(
+        &dir,
+        LoadOptions {
+            prefault: true,
+            ..LoadOptions::default()
+        },
+    );
+    loader.load_library(&path).unwrap();
+    let (resident, _) = smaps(object_base(&loader, &path) + RODATA);
+    assert_eq!(resident, RODATA_SIZE);
+    // The data page, the generated sections and all of the read-only segment.
+    assert_eq!(
+        loader.prefaulted_pages(),
+        vec![(path.clone(), (2 + RODATA_SIZE / PAGE_SIZE) as usize)]
+    );
+}
+
+#[test]
+fn without_prefault_untouched_pages_stay_out_of_memory() {
+    let dir = fixture_dir("prefault-off");
+    let path = fixture(&dir);
+    let loader = loader(&dir, LoadOptions::default());
+    loader.load_library(&path).unwrap();
+    let (resident, flags) = smaps(object_base(&loader, &path) + RODATA);
+    assert!(resident < RODATA_SIZE, "{} bytes resident", resident);
+    assert!(!flags.contains(&String::from("sr")), "{:?}", flags);
+    assert!(loader.prefaulted_pages().is_empty());
+}
+
+#[test]
+fn advised_segments_are_read_sequentially() {
+    let dir = fixture_dir("prefault-sequential");
+    let path = fixture(&dir);
+    let loader = loader(
+        &dir,
+        LoadOptions {
+            advise_sequential: true,
+            ..LoadOptions::default()
+        },
+    );
+    loader.load_library(&path).unwrap();
+    let (_, flags) = smaps(object_base(&loader, &path) + RODATA);
+    assert!(flags.contains(&String::from("sr")), "{:?}", flags);
+}
@@ -0,0 +1,130 @@
+//! Replaying symbol relocations from the prelink cache, and invalidating the records of files
+//! whose content changed.
+
+mod common;
+
+use std::convert::TryInto;
+use std::ffi::CString;
+use std::fs;
+use std::path::Path;
+
+use common::{data_library, fixture_dir, mapped_bytes, write_fixture};
+use drow::loader::{Elf64Loader, LoadOptions};
+use drow::{RELOCATION_X86_64_64, SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_OBJECT};
+
+/// Stands in for the address of `target` in the records, so a replay is told from a lookup.
+const SENTINEL: u64 = 0x4141_4141_4141_4141;
+
+/// `libtarget.so` defining `target`, and `libroot.so` needing it and holding its address in
+/// `slot`. Returns the path of the latter.
+fn write_libraries(dir: &Path, target_content: u8) -> String {
+    write_fixture(
+        dir,
+        "libtarget.so",
+        &data_library("target", &[target_content; 8], 8).finalize(),
+    );
+    write_fixture(
+        dir,
+        "libroot.so",
+        &data_library("slot", &[0; 8], 8)
+            .add_symbol("target", SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_OBJECT, 0, 0, 0)
+            .add_needed("libtarget.so")
+            .add_rela(0x1000, RELOCATION_X86_64_64, Some("target"), 0)
+            .finalize(),
+    )
+}
+
+/// Loads `root` with the prelink cache in `cache`, returning the address of `target` and the
+/// value of `slot`.
+fn load(dir: &Path, cache: &Path, base_address: u64, root: &str) -> (u64, u64) {
+    let loader = Elf64Loader::builder()
+        .offline(&[dir.to_string_lossy().into_owned()])
+        .prelink_cache(&cache.to_string_lossy())
+        .options(LoadOptions {
+            base_address,
+            ..LoadOptions::default()
+        })
+        .build()
+        .unwrap();
+    loader.load_library(root).unwrap();
+    let target = loader.lookup_symbol("target").unwrap();
+    let slot = mapped_bytes(loader.lookup_symbol("slot").unwrap(), 8);
+    let slot = u64::from_le_bytes(slot[..].try_into().unwrap());
+    loader.shutdown();
+    (target, slot)
+}
+
+/// Replaces the recorded address of `target` in the only record file of `cache`.
+fn plant_sentinel(cache: &Path, target: u64) {
+    let record = fs::read_dir(cache).unwrap().next().unwrap().unwrap().path();
+    let mut bytes = fs::read(&record).unwrap();
+    let needle = target.to_le_bytes();
+    let position = bytes
+        .windows(8)
+        .position(|window| window == needle)
+        .expect("address of target not recorded");
+    bytes[position..position + 8].copy_from_slice(&SENTINEL.to_le_bytes());
+    fs::write(&record, bytes).unwrap();
+}
+
+/// Sets the modification time of `path` to `seconds`.
+fn set_mtime(path: &str, seconds: i64) {
+    let path = CString::new(path).unwrap();
+    let time = libc::timespec {
+        tv_sec: seconds,
+        tv_nsec: 0,
+    };
+    let times = [time, time];
+    assert_eq!(
+        unsafe { libc::utimensat(libc::AT_FDCWD, path.as_ptr(), times.as_ptr(), 0) },
+        0
+    );
+}
+
+/// A cache prepared to replay `SENTINEL` into `slot`, once the libraries were loaded at
+/// `base_address`. Returns the path of `libtarget.so`.
+fn planted_cache(dir: &Path, cache: &Path, base_address: u64) -> String {
+    let root = write_libraries(dir, 1);
+    let target = dir.join("libtarget.so").to_string_lossy().into_owned();
+    set_mtime(&target, 1_000_000);
+    let (address, slot) = load(dir, cache, base_address, &root);
+    assert_eq!(slot, address);
+    plant_sentinel(cache, address);
+    assert_eq!(load(dir, cache, base_address, &root).1, SENTINEL);
+    target
+}
+
+#[test]
+fn records_of_unchanged_content_are_replayed_whatever_the_metadata() {
+    let dir = fixture_dir("prelink-touched");
+    let cache = dir.join("cache");
+    let base_address = 0x3000_0000;
+    let target = planted_cache(&dir, &cache, base_address);
+    set_mtime(&target, 2_000_000);
+    let root = dir.join("libroot.so").to_string_lossy().into_owned();
+    assert_eq!(load(&dir, &cache, base_address, &root).1, SENTINEL);
+}
+
+#[test]
+fn records_of_changed_content_are_invalidated_despite_the_same_metadata() {
+    let dir = fixture_dir("prelink-rewritten");
+    let cache = dir.join("cache");
+    let base_address = 0x4000_0000;
+    let target = planted_cache(&dir, &cache, base_address);
+    // Same size, inode and modification time, other content.
+    let mut bytes = fs::read(&target).unwrap();
+    let content = bytes
+        .windows(8)
+        .position(|window| window == [1; 8])
+        .unwrap();
+    bytes[content] = 2;
+    fs::OpenOptions::new()
+        .write(true)
+        .open(&target)
+        .and_then(|mut file| std::io::Write::write_all(&mut file, &bytes))
+        .unwrap();
+    set_mtime(&target, 1_000_000);
+    let root = dir.join("libroot.so").to_string_lossy().into_owned();
+    let (address, slot) = load(&dir, &cache, base_address, &root);
+    assert_eq!(slot, address);
+}
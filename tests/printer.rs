@@ -0,0 +1,224 @@
+//! The tables `drow inspect` prints: aligned columns, and color only when asked for or on a
+//! terminal.
+
+mod common;
+
+use std::process::{Command, Output};
+
+use common::{compile, data_library, fixture_dir, write_fixture};
+use drow::table::Table;
+use drow::{RELOCATION_X86_64_RELATIVE, SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_OBJECT};
+
+/// Control sequence introducing every color.
+const ESCAPE: char = '\x1b';
+
+fn drow(arguments: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(arguments)
+        .env_remove("LD_LIBRARY_PATH")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    output
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn fixture(test: &str) -> String {
+    write_fixture(
+        &fixture_dir(test),
+        "libprinted.so",
+        &data_library("value", &[0; 16], 16)
+            .add_needed("libdependency.so")
+            .add_rela(0x1008, RELOCATION_X86_64_RELATIVE, None, 0x10)
+            .map_dynamic(0x3000)
+            .finalize(),
+    )
+}
+
+/// What `inspect` prints of the fixture from its program headers on.
+const GOLDEN_TABLES: &str = "\
+Program headers\n\
+Type     Offset    VirtAddr          FileSize  MemSize  Flags  Align\n\
+LOAD     0x001000  0x00000000001000  0x10      0x10     RW     0x1000\n\
+LOAD     0x002000  0x00000000003000  0x80      0x80     R      0x1000\n\
+DYNAMIC  0x002060  0x00000000003060  0x20      0x20     R      0x8\n\
+String table .dynstr [3]\n\
+Offset  Hex  String\n\
+1       0x1  value\n\
+7       0x7  libdependency.so\n\
+String table .shstrtab [6]\n\
+Offset  Hex   String\n\
+1       0x1   .data\n\
+7       0x7   .dynsym\n\
+15      0xF   .dynstr\n\
+23      0x17  .rela.dyn\n\
+33      0x21  .dynamic\n\
+42      0x2A  .shstrtab\n\
+Section headers\n\
+Nr  Name       Type      Address           Offset    Size  Flags  Link\n\
+0              NULL      0000000000000000  00000000  0x0          0\n\
+1   .data      PROGBITS  0000000000001000  00001000  0x10  AW     0\n\
+2   .dynsym    DYNSYM    0000000000003000  00002000  0x30  A      3\n\
+3   .dynstr    STRTAB    0000000000003030  00002030  0x18  A      0\n\
+4   .rela.dyn  RELA      0000000000003048  00002048  0x18  A      2\n\
+5   .dynamic   DYNAMIC   0000000000003060  00002060  0x20  A      3\n\
+6   .shstrtab  STRTAB    0000000000000000  00002080  0x34         0\n\
+Dynamic symbol table\n\
+Num  Value             Size  Type    Bind    Ndx  Name\n\
+0    0000000000000000  0     NOTYPE  LOCAL   UND  \n\
+1    0000000000001000  16    OBJECT  GLOBAL  1    value\n\
+Relocation section '.rela.dyn' contains 1 entries\n\
+Offset        Info          Type               Symbol  Addend\n\
+000000001008  000000000008  R_X86_64_RELATIVE          0x10\n\
+";
+
+#[test]
+fn inspect_prints_aligned_tables_without_color() {
+    let path = fixture("printer-golden");
+    let output = stdout(&drow(&["inspect", "--color=never", &path]));
+    assert!(output.ends_with(GOLDEN_TABLES), "{}", output);
+    assert!(!output.contains(ESCAPE), "{}", output);
+}
+
+#[test]
+fn piped_output_has_no_color_by_default() {
+    let path = fixture("printer-piped");
+    let output = stdout(&drow(&["inspect", &path]));
+    assert!(output.ends_with(GOLDEN_TABLES), "{}", output);
+    assert_eq!(output, stdout(&drow(&["inspect", "--color=auto", &path])));
+}
+
+/// `text` without its color sequences.
+fn strip_color(text: &str) -> String {
+    let mut result = String::new();
+    let mut escape = false;
+    for character in text.chars() {
+        match character {
+            ESCAPE => escape = true,
+            'm' if escape => escape = false,
+            _ if escape => {}
+            _ => result.push(character),
+        }
+    }
+    result
+}
+
+#[test]
+fn colored_tables_keep_their_alignment() {
+    let path = fixture("printer-color");
+    let output = stdout(&drow(&["inspect", "--color=always", &path]));
+    // Headers in bold cyan, the flags of the writable segment each in its color.
+    assert!(
+        output.contains("\x1b[1;36mType     Offset    VirtAddr"),
+        "{:?}",
+        output
+    );
+    assert!(
+        output.contains("\x1b[32mR\x1b[0m\x1b[33mW\x1b[0m"),
+        "{:?}",
+        output
+    );
+    assert!(
+        strip_color(&output).ends_with(GOLDEN_TABLES),
+        "{:?}",
+        output
+    );
+}
+
+#[test]
+fn columns_are_as_wide_as_their_widest_cell() {
+    let mut table = Table::new(&["Name", "Value", "Note"]);
+    table.add_row(vec![
+        String::from("a"),
+        String::from("0x123456"),
+        String::new(),
+    ]);
+    table.add_row(vec![
+        String::from("longer name"),
+        String::from("1"),
+        String::from("last"),
+    ]);
+    assert_eq!(
+        table.render(false),
+        "\
+Name         Value     Note\n\
+a            0x123456  \n\
+longer name  1         last\n\
+"
+    );
+    assert_eq!(strip_color(&table.render(true)), table.render(false));
+    assert!(Table::new(&["Empty"]).is_empty());
+}
+
+#[test]
+fn json_is_never_colored() {
+    let dir = fixture_dir("printer-json");
+    let Some(program) = compile(&dir, "program", "int main(void) { return 0; }\n", &[]) else {
+        return;
+    };
+    let output = stdout(&drow(&[
+        "bench",
+        "--iterations",
+        "1",
+        "--json",
+        "--color=always",
+        &program,
+    ]));
+    assert!(output.starts_with('{'), "{}", output);
+    assert!(!output.contains(ESCAPE), "{:?}", output);
+}
+
+/// The lines of the string table of section `name` in the output of `inspect`.
+fn string_table<'a>(output: &'a str, name: &str) -> Vec<&'a str> {
+    output
+        .lines()
+        .skip_while(|line| !line.starts_with(&format!("String table {} [", name)))
+        .skip(1)
+        .take_while(|line| !line.starts_with("String table ") && *line != "Section headers")
+        .collect()
+}
+
+#[test]
+fn empty_strings_are_printed_only_when_verbose() {
+    let path = fixture("printer-strings-verbose");
+    let output = stdout(&drow(&["inspect", "-v", "--color=never", &path]));
+    assert_eq!(
+        string_table(&output, ".dynstr"),
+        vec![
+            "Offset  Hex  String",
+            "0       0x0  ",
+            "1       0x1  value",
+            "7       0x7  libdependency.so",
+        ]
+    );
+}
+
+#[test]
+fn long_string_tables_are_cut_with_a_note() {
+    let dir = fixture_dir("printer-strings-cut");
+    let mut builder = data_library("value", &[0; 8], 8);
+    for index in 0..2100 {
+        builder = builder.add_symbol(
+            &format!("symbol{:04}", index),
+            SYMBOL_BINDING_GLOBAL,
+            SYMBOL_TYPE_OBJECT,
+            1,
+            0x1000,
+            0,
+        );
+    }
+    let path = write_fixture(&dir, "libstrings.so", &builder.finalize());
+    let output = stdout(&drow(&["inspect", "--color=never", &path]));
+    let lines = string_table(&output, ".dynstr");
+    // The header, then 2000 strings, `value` and the first 1999 symbols.
+    assert_eq!(lines.len(), 2002, "{}", output);
+    assert_eq!(lines[1], "1       0x1     value");
+    assert_eq!(lines[2000], "21985   0x55E1  symbol1998");
+    assert_eq!(
+        lines[2001],
+        "... 101 more string(s) not shown, the first 2000 only are printed"
+    );
+}
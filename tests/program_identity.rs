@@ -0,0 +1,127 @@
+//! The program name and environment libc reads from `__progname`, `program_invocation_name`
+//! and `environ` are those of the loaded program, whether it copies them or reads them through
+//! the GOT.
+
+mod common;
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+use common::{compile, fixture_dir};
+
+/// Defines the variables, as the C library would, with values drow must not leave in place.
+const LIBRARY: &str = "\
+char *program_invocation_name = \"stub\";
+char *program_invocation_short_name = \"stub\";
+char **environ;
+";
+
+/// Prints its short and full name and the IDENTITY variable of its environment, with no libc.
+const PROGRAM: &str = "\
+extern char *program_invocation_name;
+extern char *program_invocation_short_name;
+extern char **environ;
+
+static long length(const char *text) {
+    long n = 0;
+    while (text[n])
+        n++;
+    return n;
+}
+
+static int starts_with(const char *text, const char *prefix) {
+    while (*prefix)
+        if (*text++ != *prefix++)
+            return 0;
+    return 1;
+}
+
+static void put(const char *text) {
+    long result;
+    __asm__ volatile(\"syscall\" : \"=a\"(result) : \"a\"(1), \"D\"(1), \"S\"(text), \"d\"(length(text))
+                     : \"rcx\", \"r11\", \"memory\");
+}
+
+void _start(void) {
+    put(program_invocation_short_name);
+    put(\"\\n\");
+    put(program_invocation_name);
+    put(\"\\n\");
+    for (char **entry = environ; *entry; entry++) {
+        if (starts_with(*entry, \"IDENTITY=\")) {
+            put(*entry);
+            put(\"\\n\");
+        }
+    }
+    __asm__ volatile(\"syscall\" : : \"a\"(60), \"D\"(0));
+    __builtin_unreachable();
+}
+";
+
+/// Builds the library and the program with `code_model`, -fPIE for COPY relocations against
+/// the variables and -fPIC for GLOB_DAT ones, or returns None without a C compiler.
+fn build(dir: &Path, code_model: &str) -> Option<String> {
+    compile(
+        dir,
+        "libidentity.so",
+        LIBRARY,
+        &["-shared", "-fPIC", "-nostdlib"],
+    )?;
+    let library_dir = dir.to_string_lossy();
+    compile(
+        dir,
+        "identity",
+        PROGRAM,
+        &[
+            "-nostdlib",
+            code_model,
+            "-pie",
+            "-L",
+            &library_dir,
+            "-lidentity",
+        ],
+    )
+}
+
+fn run(dir: &Path, program: &str, arguments: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_drow"))
+        .arg("run")
+        .args(arguments)
+        .arg("--offline")
+        .arg("--search-dir")
+        .arg(dir)
+        .arg(program)
+        .env("IDENTITY", "loaded")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn the_program_sees_its_own_name_and_environment() {
+    for code_model in ["-fPIE", "-fPIC"] {
+        let dir = fixture_dir(&format!("program-identity{}", code_model));
+        let Some(program) = build(&dir, code_model) else {
+            return;
+        };
+        let output = run(&dir, &program, &[]);
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            format!("identity\n{}\nIDENTITY=loaded\n", program)
+        );
+    }
+}
+
+#[test]
+fn argv0_renames_the_program() {
+    let dir = fixture_dir("program-identity-argv0");
+    let Some(program) = build(&dir, "-fPIE") else {
+        return;
+    };
+    let output = run(&dir, &program, &["--argv0", "/opt/renamed"]);
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "renamed\n/opt/renamed\nIDENTITY=loaded\n"
+    );
+}
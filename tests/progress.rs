@@ -0,0 +1,147 @@
+//! `Progress`: the events a sink set on the loader receives for a tree of libraries, from
+//! resolving to the totals of the load, whether it succeeds or not.
+
+mod common;
+
+use std::fs::File;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use common::{data_library, fixture_dir, write_fixture};
+use drow::loader::Elf64Loader;
+use drow::progress::Progress;
+use drow::{Elf64Metadata, PROGRAM_HEADER_TYPE_LOADABLE, RELOCATION_X86_64_RELATIVE};
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    Resolving(usize, usize),
+    Mapping(String, u64),
+    Relocating(usize, usize),
+    Finished(usize, usize),
+}
+
+/// Records every event, shared with the test through the returned list.
+struct RecordingProgress(Arc<Mutex<Vec<Event>>>);
+
+impl RecordingProgress {
+    fn new() -> (RecordingProgress, Arc<Mutex<Vec<Event>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        (RecordingProgress(Arc::clone(&events)), events)
+    }
+
+    fn record(&mut self, event: Event) {
+        self.0.lock().unwrap().push(event);
+    }
+}
+
+impl Progress for RecordingProgress {
+    fn resolving(&mut self, resolved: usize, found: usize) {
+        self.record(Event::Resolving(resolved, found));
+    }
+
+    fn mapping(&mut self, path: &str, size: u64) {
+        self.record(Event::Mapping(path.to_string(), size));
+    }
+
+    fn relocating(&mut self, done: usize, total: usize) {
+        self.record(Event::Relocating(done, total));
+    }
+
+    fn finished(&mut self, objects: usize, relocations: usize) {
+        self.record(Event::Finished(objects, relocations));
+    }
+}
+
+/// A library needing `needed`, with `relocations` RELATIVE relocations.
+fn library(dir: &Path, name: &str, needed: &[&str], relocations: u64) -> String {
+    let mut builder = data_library("value", &[0; 0x40], 0x40);
+    for library in needed {
+        builder = builder.add_needed(library);
+    }
+    for index in 0..relocations {
+        builder = builder.add_rela(0x1000 + index * 8, RELOCATION_X86_64_RELATIVE, None, 0);
+    }
+    write_fixture(dir, name, &builder.map_dynamic(0x3000).finalize())
+}
+
+/// The bytes the loadable segments of `path` span, as reported while mapping it.
+fn mapped_size(path: &str) -> u64 {
+    let metadata = Elf64Metadata::load(&path.to_string(), &mut File::open(path).unwrap()).unwrap();
+    metadata
+        .program_headers
+        .iter()
+        .filter(|header| header.p_type == PROGRAM_HEADER_TYPE_LOADABLE)
+        .map(|header| header.p_memory_size)
+        .sum()
+}
+
+fn loader(dir: &Path, progress: RecordingProgress) -> Elf64Loader {
+    Elf64Loader::builder()
+        .offline(&[dir.to_string_lossy().into_owned()])
+        .progress(progress)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn a_tree_of_libraries_goes_through_every_phase() {
+    let dir = fixture_dir("progress-tree");
+    let leaf = library(&dir, "libleaf.so", &[], 1);
+    let middle = library(&dir, "libmiddle.so", &["libleaf.so"], 2);
+    let top = library(&dir, "libtop.so", &["libmiddle.so", "libleaf.so"], 3);
+    let (progress, events) = RecordingProgress::new();
+    let loader = loader(&dir, progress);
+    loader.load_library(&top).unwrap();
+
+    let events = events.lock().unwrap();
+    assert_eq!(
+        *events,
+        vec![
+            Event::Resolving(1, 3),
+            Event::Resolving(2, 3),
+            Event::Resolving(3, 3),
+            Event::Mapping(leaf.clone(), mapped_size(&leaf)),
+            Event::Relocating(1, 6),
+            Event::Mapping(middle.clone(), mapped_size(&middle)),
+            Event::Relocating(3, 6),
+            Event::Mapping(top.clone(), mapped_size(&top)),
+            Event::Relocating(6, 6),
+            Event::Finished(3, 6),
+        ]
+    );
+}
+
+#[test]
+fn each_load_reports_its_own_totals() {
+    let dir = fixture_dir("progress-totals");
+    let first = library(&dir, "libfirst.so", &[], 2);
+    let second = library(&dir, "libsecond.so", &["libfirst.so"], 1);
+    let (progress, events) = RecordingProgress::new();
+    let loader = loader(&dir, progress);
+    loader.load_library(&first).unwrap();
+    events.lock().unwrap().clear();
+    // libfirst.so is already loaded, so only libsecond.so is mapped.
+    loader.load_library(&second).unwrap();
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.last(), Some(&Event::Finished(1, 1)), "{:?}", events);
+    assert!(
+        !events
+            .iter()
+            .any(|event| matches!(event, Event::Mapping(path, _) if *path == first)),
+        "{:?}",
+        events
+    );
+}
+
+#[test]
+fn a_failed_load_still_finishes() {
+    let dir = fixture_dir("progress-failed");
+    let broken = library(&dir, "libbroken.so", &["libabsent.so"], 1);
+    let (progress, events) = RecordingProgress::new();
+    let loader = loader(&dir, progress);
+    assert!(loader.load_library(&broken).is_err());
+
+    let events = events.lock().unwrap();
+    assert_eq!(*events, vec![Event::Resolving(1, 1), Event::Finished(0, 0)]);
+}
@@ -0,0 +1,462 @@
+//! What relocations write into the objects drow maps, the ones it refuses to apply, and the
+//! symbol lookups they take.
+
+mod common;
+
+use std::convert::TryInto;
+use std::io::Cursor;
+use std::mem::size_of;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use common::{
+    data_library, fixture_dir, mapped_bytes, mapped_word, object_base, offline_loader,
+    write_fixture,
+};
+use drow::loader::{Elf64Loader, LoadOptions};
+use drow::{
+    DrowError, Elf64Metadata, Elf64RelocationAddend, ELF64_SECTION_HEADER_RELOCATION,
+    ELF64_SECTION_HEADER_RELOCATION_ADDEND, PROGRAM_FLAG_READ, PROGRAM_FLAG_WRITE,
+    PROGRAM_HEADER_TYPE_LOADABLE, RELOCATION_X86_64_32, RELOCATION_X86_64_32S,
+    RELOCATION_X86_64_64, RELOCATION_X86_64_COPY, RELOCATION_X86_64_GLOB_DAT,
+    RELOCATION_X86_64_JUMP_SLOT, RELOCATION_X86_64_RELATIVE, SYMBOL_BINDING_GLOBAL,
+    SYMBOL_TYPE_OBJECT,
+};
+
+fn drow(dir: &Path, arguments: &[&str], path: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(["run", "--no-exec", "--offline", "--search-dir"])
+        .arg(dir)
+        .args(arguments)
+        .arg(path)
+        .env_remove("LD_LIBRARY_PATH")
+        .output()
+        .unwrap()
+}
+
+/// libprovider.so defining the 16 bytes `table`, and libcopier.so needing it, reserving 8 bytes
+/// for `table` at 0x1010 and copying it there. Returns the path of libcopier.so.
+fn copy_fixtures(dir: &Path) -> String {
+    let table: Vec<u8> = (1..=16).collect();
+    write_fixture(
+        dir,
+        "libprovider.so",
+        &data_library("table", &table, 16)
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    write_fixture(
+        dir,
+        "libcopier.so",
+        &data_library("copier_value", &[0x55; 0x20], 0x20)
+            .add_needed("libprovider.so")
+            .add_symbol(
+                "table",
+                SYMBOL_BINDING_GLOBAL,
+                SYMBOL_TYPE_OBJECT,
+                1,
+                0x1010,
+                8,
+            )
+            .add_rela(0x1010, RELOCATION_X86_64_COPY, Some("table"), 0)
+            .map_dynamic(0x3000)
+            .finalize(),
+    )
+}
+
+#[test]
+fn a_copy_larger_than_the_reserved_room_is_clamped() {
+    let dir = fixture_dir("relocations-copy");
+    let path = copy_fixtures(&dir);
+    let loader = offline_loader(&dir);
+    loader.load_library(&path).unwrap();
+    let base = object_base(&loader, &path);
+    let mut expected = vec![0x55; 0x10];
+    expected.extend(1..=8);
+    expected.extend([0x55; 8]);
+    assert_eq!(mapped_bytes(base + 0x1000, 0x20), expected);
+
+    let output = drow(&dir, &[], &path);
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let provider = dir.join("libprovider.so");
+    assert!(
+        stderr.contains(&format!(
+            "{}: COPY relocation of table reserves 8 bytes, but {} defines it with 16 bytes, \
+             copying only 8 bytes",
+            path,
+            provider.display()
+        )),
+        "{}",
+        stderr
+    );
+
+    let output = drow(&dir, &["--strict"], &path);
+    assert_eq!(output.status.code(), Some(126), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("COPY relocation of table reserves 8 bytes"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("copying only"), "{}", stderr);
+}
+
+fn parse(bytes: &[u8]) -> Elf64Metadata {
+    Elf64Metadata::load(&String::from("fixture.so"), &mut Cursor::new(bytes)).unwrap()
+}
+
+const RELA_SIZE: u64 = size_of::<Elf64RelocationAddend>() as u64;
+
+/// A library with a relocation without a symbol at 0x1000, one against `last`, the last of its
+/// three dynamic symbols, at 0x1008, and one against `value` at 0x1010 whose symbol index is then
+/// replaced with `index`.
+fn symbol_index_fixture(index: u64) -> Vec<u8> {
+    let mut bytes = data_library("value", &[0; 0x18], 0x18)
+        .add_symbol(
+            "last",
+            SYMBOL_BINDING_GLOBAL,
+            SYMBOL_TYPE_OBJECT,
+            1,
+            0x1008,
+            8,
+        )
+        .add_rela(0x1000, RELOCATION_X86_64_RELATIVE, None, 0x10)
+        .add_rela(0x1008, RELOCATION_X86_64_64, Some("last"), 0)
+        .add_rela(0x1010, RELOCATION_X86_64_64, Some("value"), 0)
+        .finalize();
+    let section = parse(&bytes)
+        .section_headers
+        .into_iter()
+        .find(|header| header.sh_type == ELF64_SECTION_HEADER_RELOCATION_ADDEND)
+        .unwrap();
+    let info = (section.sh_offset + 2 * RELA_SIZE + 8) as usize;
+    bytes[info..info + 8].copy_from_slice(&(index << 32 | RELOCATION_X86_64_64).to_le_bytes());
+    bytes
+}
+
+#[test]
+fn symbol_index_0_and_the_last_index_are_valid() {
+    let metadata = parse(&symbol_index_fixture(1));
+    assert_eq!(metadata.dynamic_symbol_table.len(), 3);
+    assert!(metadata.malformed_relocations.is_empty());
+    let symbols: Vec<(u64, &str)> = metadata
+        .relocations
+        .iter()
+        .map(|rela| (rela.symbol_index, rela.symbol_name.as_str()))
+        .collect();
+    assert_eq!(symbols, vec![(0, ""), (2, "last"), (1, "value")]);
+
+    let dir = fixture_dir("relocations-symbol-index");
+    let path = write_fixture(&dir, "libindex.so", &symbol_index_fixture(1));
+    let loader = offline_loader(&dir);
+    loader.load_library(&path).unwrap();
+    let base = object_base(&loader, &path);
+    assert_eq!(mapped_word(base + 0x1000), base + 0x10);
+    assert_eq!(mapped_word(base + 0x1008), base + 0x1008);
+    assert_eq!(mapped_word(base + 0x1010), base + 0x1000);
+}
+
+#[test]
+fn a_symbol_index_past_the_table_is_malformed() {
+    let bytes = symbol_index_fixture(3);
+    let metadata = parse(&bytes);
+    assert_eq!(metadata.relocations.len(), 2);
+    assert_eq!(metadata.malformed_relocations.len(), 1);
+    let malformed = &metadata.malformed_relocations[0];
+    let section = metadata
+        .section_headers
+        .iter()
+        .position(|header| header.sh_type == ELF64_SECTION_HEADER_RELOCATION_ADDEND)
+        .unwrap();
+    assert_eq!(malformed.section_index, section);
+    assert_eq!(malformed.position, 2);
+    assert_eq!(malformed.symbol_index, 3);
+    assert_eq!(malformed.symbol_count, 3);
+    assert_eq!(
+        malformed.file_offset,
+        metadata.section_headers[section].sh_offset + 2 * RELA_SIZE
+    );
+    let what = format!(
+        "relocation 2 (R_X86_64_64) of section [{}] refers to symbol 3, past the 3 dynamic symbols",
+        section
+    );
+    match malformed.to_error() {
+        DrowError::Malformed {
+            what: message,
+            offset,
+        } => {
+            assert_eq!(message, what);
+            assert_eq!(offset, Some(malformed.file_offset));
+        }
+        other => panic!("unexpected error {:?}", other),
+    }
+
+    let dir = fixture_dir("relocations-symbol-index-malformed");
+    let path = write_fixture(&dir, "libindex.so", &bytes);
+    match offline_loader(&dir).load_library(&path) {
+        Err(DrowError::NotLoadable {
+            path: refused,
+            reason,
+        }) => {
+            assert_eq!(refused, path);
+            assert_eq!(
+                reason,
+                format!(
+                    "Malformed ELF: {} at {:#X}, 1 malformed relocation(s) in total \
+                     (--force loads it anyway)",
+                    what, malformed.file_offset
+                )
+            );
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+
+    let loader = Elf64Loader::builder()
+        .offline(&[dir.to_string_lossy().into_owned()])
+        .options(LoadOptions {
+            force: true,
+            ..LoadOptions::default()
+        })
+        .build()
+        .unwrap();
+    loader.load_library(&path).unwrap();
+    let base = object_base(&loader, &path);
+    assert_eq!(mapped_word(base + 0x1008), base + 0x1008);
+    assert_eq!(mapped_word(base + 0x1010), 0);
+}
+
+#[test]
+fn an_object_with_32_bit_relocations_is_placed_below_2_gib() {
+    let dir = fixture_dir("relocations-32-low");
+    let path = write_fixture(
+        &dir,
+        "liblow.so",
+        &data_library("value", &[0xFF; 0x10], 0x10)
+            .add_rela(0x1000, RELOCATION_X86_64_32, Some("value"), 8)
+            .add_rela(0x1004, RELOCATION_X86_64_32S, Some("value"), -8)
+            .add_rela(0x1008, RELOCATION_X86_64_32, None, 0xFFFF_FFFF)
+            .add_rela(0x100C, RELOCATION_X86_64_32S, None, -1)
+            .finalize(),
+    );
+    let loader = offline_loader(&dir);
+    loader.load_library(&path).unwrap();
+    let base = object_base(&loader, &path);
+    let value = loader.lookup_symbol("value").unwrap();
+    assert!(value + 0x10 <= 1 << 31, "{:#x}", value);
+    let words: Vec<u32> = mapped_bytes(base + 0x1000, 0x10)
+        .chunks(4)
+        .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+        .collect();
+    assert_eq!(
+        words,
+        vec![(value + 8) as u32, (value - 8) as u32, u32::MAX, u32::MAX]
+    );
+    let entries = loader.memory_map_entries();
+    let entry = entries
+        .iter()
+        .find(|entry| entry.object == path && entry.start <= value && value < entry.end)
+        .unwrap();
+    assert_eq!(entry.constraint, Some("below 2 GiB"));
+}
+
+#[test]
+fn a_32_bit_relocation_that_overflows_is_an_error() {
+    let dir = fixture_dir("relocations-32-overflow");
+    let cases: &[(u64, i64, &str)] = &[
+        (
+            RELOCATION_X86_64_32,
+            0x1_0000_0000,
+            "R_X86_64_32 relocation of <no symbol> at 0x1000 overflows, 0x100000000 does not fit \
+             in 32 bits",
+        ),
+        (
+            RELOCATION_X86_64_32S,
+            0x8000_0000,
+            "R_X86_64_32S relocation of <no symbol> at 0x1000 overflows, 0x80000000 does not fit \
+             in 32 bits",
+        ),
+    ];
+    for (index, (relocation_type, addend, expected)) in cases.iter().enumerate() {
+        let path = write_fixture(
+            &dir,
+            &format!("liboverflow{}.so", index),
+            &data_library("value", &[0; 8], 8)
+                .add_rela(0x1000, *relocation_type, None, *addend)
+                .finalize(),
+        );
+        let loader = offline_loader(&dir);
+        match loader.load_library(&path) {
+            Err(DrowError::NotLoadable {
+                path: refused,
+                reason,
+            }) => {
+                assert_eq!(refused, path);
+                assert_eq!(reason, *expected);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(loader.load_report(false).objects.is_empty());
+    }
+}
+
+/// libprovider.so defining `provided`, and libuser.so needing it, with five relocations against
+/// `provided` and its own `user_value` and a RELATIVE one. Returns the paths of both.
+fn lookup_fixtures(dir: &Path) -> (String, String) {
+    let provider = write_fixture(
+        dir,
+        "libprovider.so",
+        &data_library("provided", &[7; 16], 16)
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    let user = write_fixture(
+        dir,
+        "libuser.so",
+        &data_library("user_value", &[0; 0x30], 0x30)
+            .add_needed("libprovider.so")
+            .add_symbol(
+                "provided",
+                SYMBOL_BINDING_GLOBAL,
+                SYMBOL_TYPE_OBJECT,
+                0,
+                0,
+                0,
+            )
+            .add_rela(0x1000, RELOCATION_X86_64_GLOB_DAT, Some("provided"), 0)
+            .add_rela(0x1008, RELOCATION_X86_64_64, Some("provided"), 8)
+            .add_rela(0x1010, RELOCATION_X86_64_64, Some("provided"), 0)
+            .add_rela(0x1018, RELOCATION_X86_64_JUMP_SLOT, Some("provided"), 0)
+            .add_rela(0x1020, RELOCATION_X86_64_64, Some("user_value"), 4)
+            .add_rela(0x1028, RELOCATION_X86_64_RELATIVE, None, 0x10)
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    (provider, user)
+}
+
+#[test]
+fn each_symbol_is_looked_up_once_per_object() {
+    let dir = fixture_dir("relocation-lookups");
+    let (provider, user) = lookup_fixtures(&dir);
+    let loader = offline_loader(&dir);
+    loader.load_library(&user).unwrap();
+    let lookups = loader.symbol_lookups();
+    assert_eq!((lookups.lookups, lookups.saved), (2, 3));
+
+    let provided = object_base(&loader, &provider) + 0x1000;
+    let base = object_base(&loader, &user);
+    assert_eq!(mapped_word(base + 0x1000), provided);
+    assert_eq!(mapped_word(base + 0x1008), provided + 8);
+    assert_eq!(mapped_word(base + 0x1010), provided);
+    assert_eq!(mapped_word(base + 0x1018), provided);
+    assert_eq!(mapped_word(base + 0x1020), base + 0x1000 + 4);
+    assert_eq!(mapped_word(base + 0x1028), base + 0x10);
+
+    let output = drow(&dir, &["--stats"], &user);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("2 symbol lookup(s) for relocations, 3 saved\n"),
+        "{}",
+        stdout
+    );
+}
+
+/// Relocation time of many relocations against a few symbols. Only printed, as timings vary.
+#[test]
+fn lookups_scale_with_symbols_not_relocations() {
+    const RELOCATIONS: u64 = 4000;
+    let dir = fixture_dir("relocation-lookups-benchmark");
+    let names = ["first", "second", "third", "fourth"];
+    let mut builder = data_library("first", &[0; 8], 8);
+    for name in names.iter().skip(1) {
+        builder = builder.add_symbol(
+            name,
+            SYMBOL_BINDING_GLOBAL,
+            SYMBOL_TYPE_OBJECT,
+            1,
+            0x1000,
+            8,
+        );
+    }
+    let size = RELOCATIONS * 8;
+    // Far above the generated sections, which the relocations make long.
+    builder = builder.add_segment(
+        PROGRAM_HEADER_TYPE_LOADABLE,
+        PROGRAM_FLAG_READ | PROGRAM_FLAG_WRITE,
+        0x100000,
+        &vec![0; size as usize],
+        size,
+    );
+    for index in 0..RELOCATIONS {
+        let name = names[index as usize % names.len()];
+        builder = builder.add_rela(0x100000 + index * 8, RELOCATION_X86_64_64, Some(name), 0);
+    }
+    let path = write_fixture(&dir, "libmany.so", &builder.map_dynamic(0x3000).finalize());
+    let loader = offline_loader(&dir);
+    loader.load_library(&path).unwrap();
+    let lookups = loader.symbol_lookups();
+    assert_eq!(lookups.lookups, names.len());
+    assert_eq!(lookups.saved, RELOCATIONS as usize - names.len());
+    let base = object_base(&loader, &path);
+    assert_eq!(
+        mapped_word(base + 0x100000 + (RELOCATIONS - 1) * 8),
+        base + 0x1000
+    );
+    eprintln!(
+        "{} relocations against {} symbols in {:?}",
+        RELOCATIONS,
+        names.len(),
+        loader.phase_times().relocate
+    );
+}
+
+/// librel.so, relocated by `.rel.dyn` only: a RELATIVE word and a 64 relocation against `target`
+/// of librelprovider.so with their addends in place, then a GLOB_DAT against `target`.
+fn rel_fixtures(dir: &Path) -> String {
+    write_fixture(
+        dir,
+        "librelprovider.so",
+        &data_library("target", &[9; 8], 8)
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    let mut content = vec![0u8; 0x18];
+    content[..8].copy_from_slice(&0x1010u64.to_le_bytes());
+    content[8..0x10].copy_from_slice(&(-8i64).to_le_bytes());
+    write_fixture(
+        dir,
+        "librel.so",
+        &data_library("words", &content, 0x18)
+            .add_symbol("target", SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_OBJECT, 0, 0, 0)
+            .add_rel(0x1000, RELOCATION_X86_64_RELATIVE, None)
+            .add_rel(0x1008, RELOCATION_X86_64_64, Some("target"))
+            .add_rel(0x1010, RELOCATION_X86_64_GLOB_DAT, Some("target"))
+            .add_needed("librelprovider.so")
+            .map_dynamic(0x3000)
+            .finalize(),
+    )
+}
+
+#[test]
+fn rel_entries_are_applied_with_the_addend_in_place() {
+    let dir = fixture_dir("relocations-rel");
+    let path = rel_fixtures(&dir);
+    let metadata = parse(&std::fs::read(&path).unwrap());
+    let types: Vec<u32> = metadata
+        .section_headers
+        .iter()
+        .map(|header| header.sh_type)
+        .collect();
+    assert!(types.contains(&ELF64_SECTION_HEADER_RELOCATION));
+    assert!(!types.contains(&ELF64_SECTION_HEADER_RELOCATION_ADDEND));
+
+    let loader = offline_loader(&dir);
+    loader.load_library(&path).unwrap();
+    let base = object_base(&loader, &path);
+    let target = loader.lookup_symbol("target").unwrap();
+    assert_eq!(mapped_word(base + 0x1000), base + 0x1010);
+    assert_eq!(mapped_word(base + 0x1008), target - 8);
+    assert_eq!(mapped_word(base + 0x1010), target);
+}
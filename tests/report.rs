@@ -0,0 +1,70 @@
+//! The JSON load report of `drow run --report`, written to inherited descriptors.
+
+mod common;
+
+use std::fs::File;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Output};
+
+use common::{data_library, fixture_dir, write_fixture};
+
+/// Runs `drow run --no-exec` on `path` with `arguments` and `file` as descriptor 3.
+fn run_with_descriptor_3(path: &str, arguments: &[&str], file: &File) -> Output {
+    let descriptor = file.as_raw_fd();
+    let mut command = Command::new(env!("CARGO_BIN_EXE_drow"));
+    command
+        .arg("run")
+        .arg("--no-exec")
+        .args(arguments)
+        .arg(path)
+        .env_remove("LD_LIBRARY_PATH");
+    unsafe {
+        command.pre_exec(move || {
+            // dup2 onto itself would keep the close-on-exec flag.
+            let result = if descriptor == 3 {
+                libc::fcntl(3, libc::F_SETFD, 0)
+            } else {
+                libc::dup2(descriptor, 3)
+            };
+            if result < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    command.output().unwrap()
+}
+
+#[test]
+fn report_is_written_to_descriptor_3() {
+    let dir = fixture_dir("report-fd3");
+    let path = write_fixture(
+        &dir,
+        "libreport.so",
+        &data_library("value", &[1; 8], 8).finalize(),
+    );
+    let report_path = dir.join("report.json");
+    let file = File::create(&report_path).unwrap();
+    let output = run_with_descriptor_3(&path, &["--report", "fd:3"], &file);
+    assert!(output.status.success(), "{:?}", output);
+    let report = std::fs::read_to_string(&report_path).unwrap();
+    assert!(report.starts_with('{'), "{}", report);
+    assert!(report.contains(&path), "{}", report);
+}
+
+#[test]
+fn stdout_stays_open_after_a_report_to_it() {
+    let dir = fixture_dir("report-stdout");
+    let path = write_fixture(
+        &dir,
+        "libreport.so",
+        &data_library("value", &[1; 8], 8).finalize(),
+    );
+    let file = File::create(dir.join("unused")).unwrap();
+    let output = run_with_descriptor_3(&path, &["--report", "fd:1", "--dump-got"], &file);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let got = stdout.find("GOT of").expect("no GOT dump after the report");
+    assert!(stdout[..got].contains(&path), "{}", stdout);
+}
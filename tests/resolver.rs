@@ -0,0 +1,323 @@
+//! Choosing among the ld.so.cache entries of a library, and the order and identity of the
+//! objects a dependency tree resolves to.
+
+mod common;
+
+use std::path::Path;
+use std::sync::Arc;
+
+use common::{
+    data_library, fixture_dir, library_cache, mapped_bytes, offline_loader, write_fixture,
+};
+use drow::cache::LibraryCache;
+use drow::error::DrowError;
+use drow::ld_path_loader::LdPathLoader;
+use drow::loader::{DependenciesResolver, Elf64Loader, ObjectKey};
+use drow::offset_reader::OffsetReader;
+use drow::Elf64Metadata;
+
+/// Flags of a 64-bit x86 libc6 entry, of a 32-bit one, and of a 64-bit Arm one.
+const FLAGS_X86_64: i32 = 0x0303;
+const FLAGS_I386: i32 = 0x0003;
+const FLAGS_AARCH64: i32 = 0x0A03;
+
+/// A loader finding libraries through the cache at `cache` only.
+fn cache_loader(cache: &str) -> Elf64Loader {
+    Elf64Loader::builder()
+        .cache_path(cache)
+        .ld_library_path(None)
+        .build()
+        .unwrap()
+}
+
+/// A library needing `libvalue.so`, with the cache listing `candidates` for it.
+fn root_with_candidates(dir: &Path, candidates: &[(&str, i32)]) -> (Elf64Loader, String) {
+    let entries: Vec<(&str, &str, i32)> = candidates
+        .iter()
+        .map(|(path, flags)| ("libvalue.so", *path, *flags))
+        .collect();
+    let cache = write_fixture(dir, "ld.so.cache", &library_cache(&entries));
+    let root = write_fixture(
+        dir,
+        "libroot.so",
+        &data_library("root_value", &[2; 8], 8)
+            .add_needed("libvalue.so")
+            .finalize(),
+    );
+    (cache_loader(&cache), root)
+}
+
+fn i386_library() -> Vec<u8> {
+    let mut bytes = data_library("value", &[3; 8], 8).finalize();
+    bytes[4] = 1;
+    bytes
+}
+
+#[test]
+fn entries_of_other_abis_are_filtered_by_flags() {
+    let dir = fixture_dir("cache-flags");
+    let cache = write_fixture(
+        &dir,
+        "ld.so.cache",
+        &library_cache(&[
+            ("libc.so.6", "/lib/i386-linux-gnu/libc.so.6", FLAGS_I386),
+            ("libc.so.6", "/lib/x86_64-linux-gnu/libc.so.6", FLAGS_X86_64),
+        ]),
+    );
+    let cache = LibraryCache::load(&cache).unwrap();
+    assert_eq!(
+        cache.find_for_machine("libc.so.6", drow::MACHINE_X86_64),
+        vec!["/lib/x86_64-linux-gnu/libc.so.6".to_string()]
+    );
+    assert!(cache
+        .find_for_machine("libc.so.6", drow::MACHINE_AARCH64)
+        .is_empty());
+}
+
+#[test]
+fn the_entry_for_the_machine_is_loaded() {
+    let dir = fixture_dir("cache-machine");
+    let i386 = write_fixture(&dir, "libvalue-32.so", &i386_library());
+    let native = write_fixture(
+        &dir,
+        "libvalue-64.so",
+        &data_library("value", &[4; 8], 8).finalize(),
+    );
+    let (loader, root) =
+        root_with_candidates(&dir, &[(&i386, FLAGS_I386), (&native, FLAGS_X86_64)]);
+    loader.load_library(&root).unwrap();
+    assert_eq!(
+        mapped_bytes(loader.lookup_symbol("value").unwrap(), 8),
+        vec![4; 8]
+    );
+    let paths: Vec<String> = loader
+        .load_report(false)
+        .objects
+        .iter()
+        .map(|object| object.path.clone())
+        .collect();
+    assert!(paths.contains(&native));
+    assert!(!paths.contains(&i386));
+}
+
+#[test]
+fn candidates_that_do_not_fit_are_skipped() {
+    let dir = fixture_dir("cache-skip");
+    let mislabeled = write_fixture(&dir, "libvalue-32.so", &i386_library());
+    let native = write_fixture(
+        &dir,
+        "libvalue-64.so",
+        &data_library("value", &[5; 8], 8).finalize(),
+    );
+    let (loader, root) = root_with_candidates(
+        &dir,
+        &[(&mislabeled, FLAGS_X86_64), (&native, FLAGS_X86_64)],
+    );
+    loader.load_library(&root).unwrap();
+    assert_eq!(
+        mapped_bytes(loader.lookup_symbol("value").unwrap(), 8),
+        vec![5; 8]
+    );
+}
+
+#[test]
+fn a_single_candidate_of_another_class_is_an_error() {
+    let dir = fixture_dir("cache-class");
+    let mislabeled = write_fixture(&dir, "libvalue.so", &i386_library());
+    let (loader, root) = root_with_candidates(&dir, &[(&mislabeled, FLAGS_X86_64)]);
+    match loader.load_library(&root) {
+        Err(DrowError::WrongClass { path, class }) => {
+            assert_eq!(path, mislabeled);
+            assert_eq!(class, 1);
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn a_single_candidate_of_another_machine_is_an_error() {
+    let dir = fixture_dir("cache-machine-mismatch");
+    let foreign = write_fixture(
+        &dir,
+        "libvalue.so",
+        &data_library("value", &[6; 8], 8)
+            .machine(drow::MACHINE_AARCH64)
+            .finalize(),
+    );
+    let (loader, root) = root_with_candidates(&dir, &[(&foreign, FLAGS_X86_64)]);
+    match loader.load_library(&root) {
+        Err(DrowError::WrongMachine { path, machine }) => {
+            assert_eq!(path, foreign);
+            assert_eq!(machine, drow::MACHINE_AARCH64);
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn a_single_candidate_that_is_not_elf_is_an_error() {
+    let dir = fixture_dir("cache-not-elf");
+    let script = write_fixture(
+        &dir,
+        "libvalue.so",
+        b"/* GNU ld script, which the loader does not follow. */\nGROUP ( libvalue.so.1 )\n",
+    );
+    let (loader, root) = root_with_candidates(&dir, &[(&script, FLAGS_X86_64)]);
+    match loader.load_library(&root) {
+        Err(DrowError::NotElf { path, .. }) => assert_eq!(path, script),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn a_preload_is_found_for_the_machine_of_the_program() {
+    let dir = fixture_dir("cache-preload-machine");
+    let aarch64 = |symbol: &str, byte| {
+        data_library(symbol, &[byte; 8], 8)
+            .machine(drow::MACHINE_AARCH64)
+            .finalize()
+    };
+    let native = write_fixture(
+        &dir,
+        "libpreload-x86_64.so",
+        &data_library("value", &[7; 8], 8).finalize(),
+    );
+    let foreign = write_fixture(&dir, "libpreload-aarch64.so", &aarch64("value", 8));
+    let cache = write_fixture(
+        &dir,
+        "ld.so.cache",
+        &library_cache(&[
+            ("libpreload.so", &native, FLAGS_X86_64),
+            ("libpreload.so", &foreign, FLAGS_AARCH64),
+        ]),
+    );
+    let program = write_fixture(&dir, "program", &aarch64("program_value", 9));
+    let loader = cache_loader(&cache);
+    loader.preload("libpreload.so").unwrap();
+    loader.load_file(&program).unwrap();
+    let paths: Vec<String> = loader
+        .load_report(false)
+        .objects
+        .iter()
+        .map(|object| object.path.clone())
+        .collect();
+    assert_eq!(paths, vec![foreign, program]);
+}
+
+/// libroot.so needing liba.so, libb.so and liba.so again, liba.so needing libshared.so, libb.so
+/// needing libd.so and libshared.so, and libd.so needing libshared.so too.
+fn diamond(dir: &Path) -> String {
+    let library = |name: &str, needed: &[&str]| {
+        let symbol = format!("{}_value", name.trim_end_matches(".so"));
+        let builder = needed
+            .iter()
+            .fold(data_library(&symbol, &[1; 8], 8), |builder, library| {
+                builder.add_needed(library)
+            });
+        write_fixture(dir, name, &builder.finalize())
+    };
+    library("libshared.so", &[]);
+    library("libd.so", &["libshared.so"]);
+    library("liba.so", &["libshared.so"]);
+    library("libb.so", &["libd.so", "libshared.so"]);
+    library("libroot.so", &["liba.so", "libb.so", "liba.so"])
+}
+
+fn file_name(path: &str) -> String {
+    path.rsplit('/').next().unwrap().to_string()
+}
+
+#[test]
+fn a_diamond_is_loaded_once_per_object_deepest_first() {
+    let dir = fixture_dir("order-diamond");
+    let root = diamond(&dir);
+    let cache = write_fixture(&dir, "ld.so.cache", &library_cache(&[]));
+    let mut resolver = DependenciesResolver::new(
+        LibraryCache::load(&cache).unwrap(),
+        Some(LdPathLoader::new(&dir.to_string_lossy())),
+    );
+    let mut reader = OffsetReader::open(&root).unwrap();
+    let metadata = Elf64Metadata::load(&root, &mut reader).unwrap();
+    let direct: Vec<String> = resolver
+        .resolve_direct_dependencies(&metadata)
+        .unwrap()
+        .iter()
+        .map(|file| file_name(&file.file_path))
+        .collect();
+    assert_eq!(direct, vec!["liba.so", "libb.so"]);
+    // libshared.so is three entries deep through libb.so and libd.so, libd.so two.
+    let order: Vec<String> = resolver
+        .resolve_in_loading_order(&Arc::new(metadata))
+        .unwrap()
+        .iter()
+        .map(|file| file_name(&file.file_path))
+        .collect();
+    assert_eq!(
+        order,
+        vec![
+            "libshared.so",
+            "libd.so",
+            "liba.so",
+            "libb.so",
+            "libroot.so"
+        ]
+    );
+
+    let loader = offline_loader(&dir);
+    loader.load_library(&root).unwrap();
+    let loaded: Vec<String> = loader
+        .load_report(false)
+        .objects
+        .iter()
+        .map(|object| file_name(&object.path))
+        .collect();
+    assert_eq!(loaded, order);
+}
+
+#[test]
+fn a_file_reached_under_two_names_is_one_object() {
+    let dir = fixture_dir("order-alias");
+    let library = |name: &str, needed: &[&str]| {
+        let builder = needed.iter().fold(
+            data_library(&format!("{}_value", name), &[1; 8], 8),
+            |builder, library| builder.add_needed(library),
+        );
+        write_fixture(&dir, name, &builder.finalize())
+    };
+    let shared = library("libshared.so", &[]);
+    let alias = dir.join("libalias.so");
+    std::os::unix::fs::symlink(&shared, &alias).unwrap();
+    let alias = alias.to_string_lossy().into_owned();
+    library("liba.so", &["libshared.so"]);
+    library("libb.so", &["libalias.so"]);
+    let root = library("libroot.so", &["liba.so", "libb.so"]);
+
+    assert_eq!(ObjectKey::new(&alias), ObjectKey::new(&shared));
+    assert_ne!(ObjectKey::new(&root), ObjectKey::new(&shared));
+    // A path through `..` names the same file.
+    let relative = ObjectKey::new(&format!(
+        "{}/../{}/libshared.so",
+        dir.display(),
+        file_name(&dir.to_string_lossy())
+    ));
+    assert_eq!(relative, ObjectKey::new(&shared));
+
+    let cache = write_fixture(&dir, "ld.so.cache", &library_cache(&[]));
+    let mut resolver = DependenciesResolver::new(
+        LibraryCache::load(&cache).unwrap(),
+        Some(LdPathLoader::new(&dir.to_string_lossy())),
+    );
+    let mut reader = OffsetReader::open(&root).unwrap();
+    let metadata = Arc::new(Elf64Metadata::load(&root, &mut reader).unwrap());
+    let order = resolver.resolve_in_loading_order(&metadata).unwrap();
+    let names: Vec<String> = order
+        .iter()
+        .map(|file| file_name(&file.file_path))
+        .collect();
+    assert_eq!(
+        names,
+        vec!["libshared.so", "liba.so", "libb.so", "libroot.so"]
+    );
+    // The file itself is shared, not copied.
+    assert!(Arc::ptr_eq(order.last().unwrap(), &metadata));
+}
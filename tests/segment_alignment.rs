@@ -0,0 +1,108 @@
+//! p_align larger than a page: the base of the object is a multiple of the largest alignment of
+//! its segments, so each segment keeps the low bits of its address in the file.
+
+mod common;
+
+use std::convert::TryInto;
+use std::path::Path;
+
+use common::{data_library, fixture_dir, mapped_bytes, mapped_word, offline_loader, write_fixture};
+use drow::loader::Elf64Loader;
+use drow::testutil::ElfBuilder;
+use drow::{
+    PROGRAM_FLAG_READ, PROGRAM_FLAG_WRITE, PROGRAM_HEADER_TYPE_LOADABLE, RELOCATION_X86_64_RELATIVE,
+};
+
+const HUGE_PAGE: u64 = 0x20_0000;
+
+/// Where the program header table starts, and the offset of p_align in an entry of it.
+const PROGRAM_HEADERS: usize = 0x40;
+const PROGRAM_HEADER_SIZE: usize = 0x38;
+const P_ALIGN: usize = 0x30;
+
+/// A huge-page friendly layout: read-only data at 0x40, then writable data in the next 2 MiB,
+/// holding a pointer to the read-only data.
+fn huge_library() -> ElfBuilder {
+    ElfBuilder::new()
+        .segment_alignment(HUGE_PAGE)
+        .add_segment(
+            PROGRAM_HEADER_TYPE_LOADABLE,
+            PROGRAM_FLAG_READ,
+            0x40,
+            &[0x11; 0x40],
+            0x40,
+        )
+        .add_segment(
+            PROGRAM_HEADER_TYPE_LOADABLE,
+            PROGRAM_FLAG_READ | PROGRAM_FLAG_WRITE,
+            HUGE_PAGE + 0x80,
+            &[0; 16],
+            16,
+        )
+        .add_rela(HUGE_PAGE + 0x80, RELOCATION_X86_64_RELATIVE, None, 0x40)
+}
+
+/// The base of `path`, from the mapping of its lowest segment, at `address` in the file.
+fn base_of_first_segment(loader: &Elf64Loader, path: &str, address: u64) -> u64 {
+    let entry = loader
+        .memory_map_entries()
+        .into_iter()
+        .filter(|entry| entry.object == path)
+        .min_by_key(|entry| entry.start)
+        .unwrap();
+    entry.start - (address & !0xFFF)
+}
+
+fn load_after_another(dir: &Path, bytes: &[u8]) -> (Elf64Loader, String) {
+    // Loaded first, so the next free address is not a multiple of the alignment.
+    let other = write_fixture(
+        dir,
+        "libother.so",
+        &data_library("other", &[0; 8], 8)
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    let path = write_fixture(dir, "libaligned.so", bytes);
+    let loader = offline_loader(dir);
+    loader.load_library(&other).unwrap();
+    loader.load_library(&path).unwrap();
+    (loader, path)
+}
+
+#[test]
+fn every_segment_keeps_its_address_modulo_the_alignment() {
+    let dir = fixture_dir("segment-alignment-huge");
+    let (loader, path) = load_after_another(&dir, &huge_library().finalize());
+    let base = base_of_first_segment(&loader, &path, 0x40);
+    assert_eq!(base % HUGE_PAGE, 0, "{:#x}", base);
+    for address in [0x40, HUGE_PAGE + 0x80] {
+        assert_eq!((base + address) % HUGE_PAGE, address % HUGE_PAGE);
+    }
+    assert_eq!(mapped_bytes(base + 0x40, 0x40), vec![0x11; 0x40]);
+    // The relocated pointer lands on the read-only data of the same mapping.
+    assert_eq!(mapped_word(base + HUGE_PAGE + 0x80), base + 0x40);
+}
+
+#[test]
+fn the_largest_alignment_of_the_segments_wins() {
+    let dir = fixture_dir("segment-alignment-largest");
+    let mut bytes = data_library("value", &[5; 8], 8)
+        .add_segment(
+            PROGRAM_HEADER_TYPE_LOADABLE,
+            PROGRAM_FLAG_READ,
+            0x10000,
+            &[6; 8],
+            8,
+        )
+        .finalize();
+    // The data segment stays page aligned, the second one asks for 64 KiB.
+    let second = PROGRAM_HEADERS + PROGRAM_HEADER_SIZE + P_ALIGN;
+    let align = u64::from_le_bytes(bytes[second..second + 8].try_into().unwrap());
+    assert_eq!(align, 0x1000);
+    bytes[second..second + 8].copy_from_slice(&0x10000u64.to_le_bytes());
+    let (loader, path) = load_after_another(&dir, &bytes);
+    let value = loader.lookup_symbol_in(&path, "value").unwrap();
+    let base = value - 0x1000;
+    assert_eq!(base % 0x10000, 0, "{:#x}", base);
+    assert_eq!(mapped_bytes(base + 0x10000, 8), vec![6; 8]);
+}
@@ -0,0 +1,106 @@
+//! `drow shell`: a scripted session piped to stdin, checked against golden output, with the
+//! failing commands reported on stderr and the shell carrying on past them.
+
+mod common;
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+use common::{data_library, fixture_dir, write_fixture};
+use drow::RELOCATION_X86_64_RELATIVE;
+
+const SCRIPT: &str = "\
+sections .d*
+sym value
+symbol
+sym missing
+addr 0x1004
+sym
+addr 0x9000
+relocs RELATIVE
+relocs JUMP_SLOT
+quit
+header
+";
+
+const GOLDEN: &str = "\
+Section headers
+Nr  Name      Type      Address           Offset    Size  Flags  Link
+1   .data     PROGBITS  0000000000001000  00001000  0x10  AW     0
+2   .dynsym   DYNSYM    0000000000003000  00002000  0x30  A      3
+3   .dynstr   STRTAB    0000000000003030  00002030  0x7   A      0
+5   .dynamic  DYNAMIC   0000000000003050  00002050  0x10  A      3
+Table   Num  Value             Size  Type    Bind    Ndx  Version
+dynsym  1    0000000000001000  16    OBJECT  GLOBAL  1    -
+No symbol named missing
+Address  Symbol     Section
+0x1004   value+0x4  .data
+0x9000 is not in any section or symbol
+Relocation section '.rela.dyn' contains 1 entries
+Offset        Info          Type               Symbol  Addend
+000000001008  000000000008  R_X86_64_RELATIVE          0x1000
+No relocations of type JUMP_SLOT
+";
+
+fn fixture(dir: &Path) -> String {
+    write_fixture(
+        dir,
+        "libshell.so",
+        &data_library("value", &[7; 16], 16)
+            .add_rela(0x1008, RELOCATION_X86_64_RELATIVE, None, 0x1000)
+            .map_dynamic(0x3000)
+            .finalize(),
+    )
+}
+
+fn shell(path: &str, script: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(["shell", "--color=never", path])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(script.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn a_scripted_session_matches_the_golden_output() {
+    let dir = fixture_dir("shell-session");
+    let path = fixture(&dir);
+    let output = shell(&path, SCRIPT);
+    assert!(output.status.success(), "{:?}", output);
+    // No prompt when stdin is not a terminal, and nothing run after quit.
+    assert_eq!(String::from_utf8_lossy(&output.stdout), GOLDEN);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let errors: Vec<&str> = stderr.lines().collect();
+    assert_eq!(
+        errors,
+        vec![
+            "[ERROR drow::shell] Unknown command symbol, did you mean symbols?",
+            "[ERROR drow::shell] sym expects an argument",
+        ]
+    );
+}
+
+#[test]
+fn the_end_of_input_leaves_the_shell() {
+    let dir = fixture_dir("shell-end");
+    let path = fixture(&dir);
+    let output = shell(&path, "bogus\nsym value");
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("Table   Num  Value"), "{}", stdout);
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Unknown command bogus"),
+        "{:?}",
+        output
+    );
+}
@@ -0,0 +1,60 @@
+//! Repeated load and shutdown cycles leave the address space as they found it. The only test of
+//! its binary, so no other test maps memory while it counts the mappings.
+
+mod common;
+
+use std::fs;
+
+use common::{data_library, fixture_dir, offline_loader, write_fixture};
+
+const CYCLES: usize = 20;
+
+fn mappings() -> Vec<String> {
+    fs::read_to_string("/proc/self/maps")
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn load_and_shutdown_cycles_keep_the_mapping_count() {
+    let dir = fixture_dir("shutdown-cycles");
+    write_fixture(
+        &dir,
+        "libdependency.so",
+        &data_library("dependency_value", &[1; 8], 0x3000).finalize(),
+    );
+    let path = write_fixture(
+        &dir,
+        "libroot.so",
+        &data_library("root_value", &[2; 8], 8)
+            .add_needed("libdependency.so")
+            .finalize(),
+    );
+    let cycle = || {
+        let loader = offline_loader(&dir);
+        loader.load_library(&path).unwrap();
+        assert!(loader.lookup_symbol("dependency_value").is_some());
+        assert!(mappings().iter().any(|line| line.ends_with(path.as_str())));
+        loader.shutdown()
+    };
+    // The first cycle sets up what stays for the process, like the logger and the allocator.
+    cycle();
+    let before = mappings();
+    for _ in 0..CYCLES {
+        let report = cycle();
+        assert_eq!(report.objects_unmapped, 2);
+        assert!(report.bytes_released >= 0x4000, "{:?}", report);
+        assert_eq!(report.handlers_run, 0);
+        let after = mappings();
+        assert!(
+            !after
+                .iter()
+                .any(|line| line.contains(dir.to_str().unwrap())),
+            "{:#?}",
+            after
+        );
+        assert_eq!(after.len(), before.len(), "{:#?}\n{:#?}", before, after);
+    }
+}
@@ -0,0 +1,171 @@
+//! `--fuzzy-soname`: a library missing under its exact name is substituted by another version
+//! of it, in both directions of the mismatch, checked against its DT_SONAME.
+
+mod common;
+
+use std::path::Path;
+use std::process::Command;
+
+use common::{data_library, fixture_dir, library_cache, write_fixture};
+use drow::loader::{DependenciesResolver, Elf64Loader, LibraryOrigin};
+use drow::{MACHINE_X86_64, SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_OBJECT};
+
+const DYNAMIC_TABLE_SONAME: i64 = 14;
+
+/// A library with DT_SONAME `soname`. The builder only puts the names of symbols and needed
+/// libraries in .dynstr, so the soname is that of an undefined symbol, at offset 7 after
+/// "\0value\0".
+fn library_named(soname: &str) -> Vec<u8> {
+    data_library("value", &[0; 8], 8)
+        .add_symbol(soname, SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_OBJECT, 0, 0, 0)
+        .add_dynamic(DYNAMIC_TABLE_SONAME, 7)
+        .map_dynamic(0x3000)
+        .finalize()
+}
+
+fn library() -> Vec<u8> {
+    data_library("value", &[0; 8], 8)
+        .map_dynamic(0x3000)
+        .finalize()
+}
+
+/// A resolver searching `dir` through LD_LIBRARY_PATH, with an empty cache.
+fn ld_path_resolver(dir: &Path, fuzzy_soname: bool) -> DependenciesResolver {
+    let cache = write_fixture(dir, "ld.so.cache", &library_cache(&[]));
+    Elf64Loader::builder()
+        .cache_path(&cache)
+        .ld_library_path(Some(&dir.to_string_lossy()))
+        .fuzzy_soname(fuzzy_soname)
+        .dependencies_resolver()
+}
+
+fn resolve(resolver: &mut DependenciesResolver, library: &str) -> (Vec<String>, LibraryOrigin) {
+    resolver
+        .resolve_path_with_origin(&library.to_string(), MACHINE_X86_64)
+        .unwrap()
+}
+
+#[test]
+fn an_unversioned_name_takes_the_highest_version() {
+    let dir = fixture_dir("soname-unversioned");
+    write_fixture(&dir, "libfoo.so.2", &library());
+    let three = write_fixture(&dir, "libfoo.so.3", &library());
+    write_fixture(&dir, "libfoobar.so.4", &library());
+    let mut resolver = ld_path_resolver(&dir, true);
+    assert_eq!(
+        resolve(&mut resolver, "libfoo.so"),
+        (vec![three], LibraryOrigin::Substituted)
+    );
+}
+
+#[test]
+fn a_more_specific_version_takes_the_closest_one() {
+    let dir = fixture_dir("soname-specific");
+    let two = write_fixture(&dir, "libbar.so.2", &library());
+    write_fixture(&dir, "libbar.so.1", &library());
+    write_fixture(&dir, "libbar.so.3", &library());
+    let mut resolver = ld_path_resolver(&dir, true);
+    // libbar.so.2 shares the major version, the higher libbar.so.3 does not.
+    assert_eq!(
+        resolve(&mut resolver, "libbar.so.2.1"),
+        (vec![two], LibraryOrigin::Substituted)
+    );
+}
+
+#[test]
+fn a_versioned_name_takes_the_unversioned_library() {
+    let dir = fixture_dir("soname-plugin");
+    let plugin = write_fixture(&dir, "libplugin.so", &library());
+    let mut resolver = ld_path_resolver(&dir, true);
+    assert_eq!(
+        resolve(&mut resolver, "libplugin.so.1"),
+        (vec![plugin], LibraryOrigin::Substituted)
+    );
+}
+
+#[test]
+fn a_candidate_naming_another_library_is_skipped() {
+    let dir = fixture_dir("soname-mismatch");
+    write_fixture(&dir, "libbaz.so.2", &library_named("libother.so.2"));
+    let one = write_fixture(&dir, "libbaz.so.1", &library_named("libbaz.so.1"));
+    let mut resolver = ld_path_resolver(&dir, true);
+    assert_eq!(
+        resolve(&mut resolver, "libbaz.so"),
+        (vec![one], LibraryOrigin::Substituted)
+    );
+}
+
+#[test]
+fn the_exact_name_wins_and_nothing_is_substituted_unless_asked() {
+    let dir = fixture_dir("soname-exact");
+    let exact = write_fixture(&dir, "libfoo.so.1", &library());
+    write_fixture(&dir, "libfoo.so.3", &library());
+    let mut resolver = ld_path_resolver(&dir, true);
+    assert_eq!(
+        resolve(&mut resolver, "libfoo.so.1"),
+        (vec![exact], LibraryOrigin::LdPath)
+    );
+    assert_eq!(
+        resolve(&mut resolver, "libother.so"),
+        (Vec::new(), LibraryOrigin::Missing)
+    );
+
+    let mut resolver = ld_path_resolver(&dir, false);
+    assert_eq!(
+        resolve(&mut resolver, "libfoo.so"),
+        (Vec::new(), LibraryOrigin::Missing)
+    );
+}
+
+#[test]
+fn resolve_warns_about_the_substitution() {
+    let dir = fixture_dir("soname-cli");
+    let root = dir.join("rootfs");
+    std::fs::create_dir_all(root.join("etc")).unwrap();
+    std::fs::create_dir_all(root.join("usr/lib")).unwrap();
+    write_fixture(&root.join("etc"), "ld.so.cache", &library_cache(&[]));
+    let substitute = write_fixture(&root.join("usr/lib"), "libfoo.so.3", &library());
+    let program = write_fixture(
+        &dir,
+        "libprogram.so",
+        &data_library("program_value", &[0; 8], 8)
+            .add_needed("libfoo.so")
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    let drow = |fuzzy: bool| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_drow"));
+        command.arg("resolve").arg("--sysroot").arg(&root);
+        if fuzzy {
+            command.arg("--fuzzy-soname");
+        }
+        command
+            .arg(&program)
+            .env_remove("LD_LIBRARY_PATH")
+            .output()
+            .unwrap()
+    };
+
+    let output = drow(true);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let row: Vec<&str> = stdout
+        .lines()
+        .find(|line| line.starts_with("libfoo.so "))
+        .expect(&stdout)
+        .split_whitespace()
+        .collect();
+    assert_eq!(row, vec!["libfoo.so", substitute.as_str(), "Substituted"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "libfoo.so not found, substituting another version of it: libfoo.so.3 from {}",
+            substitute
+        )),
+        "{}",
+        stderr
+    );
+
+    let output = drow(false);
+    assert!(!output.status.success(), "{:?}", output);
+}
@@ -0,0 +1,57 @@
+//! `Elf64Loader::execute`: the program runs in a child cloned with clone3, whose status is
+//! collected through its pidfd.
+
+mod common;
+
+use std::path::Path;
+
+use common::{compile, fixture_dir};
+use drow::loader::{ChildStatus, Elf64Loader};
+
+/// Exits with `STATUS`, or dies of SIGSEGV with `-DCRASH`, with no libc.
+const PROGRAM: &str = "\
+void _start(void) {
+#ifdef CRASH
+    *(volatile int *)0 = 0;
+#endif
+    __asm__ volatile(\"syscall\" : : \"a\"(60), \"D\"(STATUS));
+    __builtin_unreachable();
+}
+";
+
+/// Compiles `PROGRAM` with `arguments`, loads it and runs it in a child, or returns None
+/// without a C compiler.
+fn execute(dir: &Path, name: &str, arguments: &[&str]) -> Option<ChildStatus> {
+    let mut arguments = arguments.to_vec();
+    arguments.push("-nostdlib");
+    let program = compile(dir, name, PROGRAM, &arguments)?;
+    let loader = Elf64Loader::builder()
+        .offline(&[dir.to_string_lossy().into_owned()])
+        .build()
+        .unwrap();
+    loader.load_file(&program).unwrap();
+    Some(loader.execute().unwrap())
+}
+
+#[test]
+fn the_exit_status_of_the_child_is_collected() {
+    let dir = fixture_dir("spawn-exit");
+    for status in [0, 3, 255] {
+        let define = format!("-DSTATUS={}", status);
+        let Some(result) = execute(&dir, &format!("exit{}", status), &[&define]) else {
+            return;
+        };
+        assert_eq!(result, ChildStatus::Exited(status));
+        assert_eq!(result.exit_code(), status);
+    }
+}
+
+#[test]
+fn the_signal_killing_the_child_is_collected() {
+    let dir = fixture_dir("spawn-signal");
+    let Some(result) = execute(&dir, "crash", &["-DSTATUS=0", "-DCRASH"]) else {
+        return;
+    };
+    assert_eq!(result, ChildStatus::Killed(libc::SIGSEGV));
+    assert_eq!(result.exit_code(), 128 + libc::SIGSEGV);
+}
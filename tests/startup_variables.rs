@@ -0,0 +1,98 @@
+//! Variables the dynamic loader sets for glibc before the program starts: `__libc_stack_end`
+//! at the top of the program stack and `__stack_chk_guard` from the AT_RANDOM bytes.
+
+mod common;
+
+use std::path::Path;
+
+use common::{data_library, fixture_dir, mapped_word, object_base, write_fixture};
+use drow::libc_flavor::LibcFlavor;
+use drow::loader::Elf64Loader;
+use drow::{RELOCATION_X86_64_GLOB_DAT, SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_OBJECT};
+
+fn loader(dir: &Path, libc: LibcFlavor) -> Elf64Loader {
+    Elf64Loader::builder()
+        .offline(&[dir.to_string_lossy().into_owned()])
+        .libc(libc)
+        .build()
+        .unwrap()
+}
+
+/// A library reading `symbol` through its GOT, at 0x1008.
+fn using(dir: &Path, symbol: &str) -> String {
+    write_fixture(
+        dir,
+        "libusing.so",
+        &data_library("value", &[0; 0x10], 0x10)
+            .add_symbol(symbol, SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_OBJECT, 0, 0, 0)
+            .add_rela(0x1008, RELOCATION_X86_64_GLOB_DAT, Some(symbol), 0)
+            .map_dynamic(0x3000)
+            .finalize(),
+    )
+}
+
+#[test]
+fn libc_stack_end_is_the_top_of_the_prepared_stack() {
+    let dir = fixture_dir("startup-stack-end");
+    let path = using(&dir, "__libc_stack_end");
+    let loader = loader(&dir, LibcFlavor::Glibc);
+    loader.load_library(&path).unwrap();
+    let stack_end = loader.lookup_symbol("__libc_stack_end").unwrap();
+    assert_eq!(mapped_word(object_base(&loader, &path) + 0x1008), stack_end);
+    assert_eq!(loader.stack_top(), None);
+    assert_eq!(mapped_word(stack_end), 0);
+
+    loader.allocate_stack().unwrap();
+    let top = loader.stack_top().unwrap();
+    assert_ne!(top, 0);
+    assert_eq!(mapped_word(stack_end), top);
+}
+
+#[test]
+fn the_stack_guard_is_seeded_from_at_random() {
+    let dir = fixture_dir("startup-stack-guard");
+    let path = using(&dir, "__stack_chk_guard");
+    let loader = loader(&dir, LibcFlavor::Glibc);
+    loader.load_library(&path).unwrap();
+    let guard = loader.lookup_symbol("__stack_chk_guard").unwrap();
+    assert_eq!(mapped_word(object_base(&loader, &path) + 0x1008), guard);
+    let random = unsafe { libc::getauxval(libc::AT_RANDOM) };
+    let expected = mapped_word(random) & !0xFF;
+    assert_eq!(mapped_word(guard), expected);
+    // The low byte stops string functions reading past a buffer.
+    assert_eq!(mapped_word(guard) & 0xFF, 0);
+}
+
+#[test]
+fn a_stack_guard_defined_by_an_object_is_kept() {
+    let dir = fixture_dir("startup-own-guard");
+    let path = write_fixture(
+        &dir,
+        "libguard.so",
+        &data_library("__stack_chk_guard", &[7; 8], 8)
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    let loader = loader(&dir, LibcFlavor::Glibc);
+    loader.load_library(&path).unwrap();
+    assert_eq!(
+        loader.lookup_symbol("__stack_chk_guard"),
+        Some(object_base(&loader, &path) + 0x1000)
+    );
+}
+
+#[test]
+fn only_glibc_gets_the_variables() {
+    let dir = fixture_dir("startup-musl");
+    let path = write_fixture(
+        &dir,
+        "libprogram.so",
+        &data_library("value", &[0; 8], 8)
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    let loader = loader(&dir, LibcFlavor::Musl);
+    loader.load_library(&path).unwrap();
+    assert_eq!(loader.lookup_symbol("__libc_stack_end"), None);
+    assert_eq!(loader.lookup_symbol("__stack_chk_guard"), None);
+}
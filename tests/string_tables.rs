@@ -0,0 +1,439 @@
+//! String tables: what an offset reads, at the edges of the table and past them, and bytes that
+//! are not UTF-8.
+
+mod common;
+
+use std::borrow::Cow;
+use std::io::Cursor;
+use std::process::Command;
+
+use common::{data_library, fixture_dir, write_fixture};
+use drow::string_tables::{
+    by_offset, by_virtual_address, file_offset, get_string_tables_content, StrError, StringTable,
+    StringTableCache,
+};
+use drow::{
+    DrowError, Elf64Metadata, ELF64_SECTION_HEADER_DYNAMIC_SYMBOL_TABLE,
+    ELF64_SECTION_HEADER_STRING_TABLE,
+};
+
+/// SHT_STRTAB, as the builder takes section types.
+const SECTION_TYPE_STRING_TABLE: u32 = 3;
+
+/// A deterministic xorshift generator, so a failing table can be reproduced from its seed.
+struct Bytes(u64);
+
+impl Bytes {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Up to `max` bytes, NULs and bytes invalid in UTF-8 among them.
+    fn table(&mut self, max: u64) -> Vec<u8> {
+        let length = self.next() % (max + 1);
+        (0..length)
+            .map(|_| match self.next() % 8 {
+                0 => 0,
+                1 => 0xFF,
+                2 => 0xC3,
+                _ => b'a' + (self.next() % 26) as u8,
+            })
+            .collect()
+    }
+}
+
+fn parse(bytes: &[u8]) -> Result<Elf64Metadata, DrowError> {
+    Elf64Metadata::load(&String::from("fixture.so"), &mut Cursor::new(bytes))
+}
+
+#[test]
+fn strings_end_at_their_terminator() {
+    let table = StringTable::from_bytes(b"\0first\0\0second\0");
+    assert_eq!(table.get(0), Ok(""));
+    assert_eq!(table.get(1), Ok("first"));
+    assert_eq!(table.get(4), Ok("st"));
+    // An empty string between two others.
+    assert_eq!(table.get(7), Ok(""));
+    assert_eq!(table.get(8), Ok("second"));
+    // The terminator of the last string is the last byte of the table.
+    assert_eq!(table.get(14), Ok(""));
+}
+
+#[test]
+fn an_offset_at_or_past_the_end_is_out_of_bounds() {
+    let table = StringTable::from_bytes(b"\0name\0");
+    for offset in [6, 7, u32::MAX] {
+        assert_eq!(
+            table.get(offset),
+            Err(StrError::OutOfBounds {
+                offset: offset as usize,
+                length: 6
+            })
+        );
+    }
+    assert_eq!(
+        StrError::OutOfBounds {
+            offset: 6,
+            length: 6
+        }
+        .to_string(),
+        "String offset 6 out of bounds of table with size 6"
+    );
+}
+
+#[test]
+fn an_unterminated_last_string_is_an_error() {
+    let table = StringTable::from_bytes(b"\0name\0last");
+    assert_eq!(table.get(1), Ok("name"));
+    assert_eq!(table.get(6), Err(StrError::Unterminated { offset: 6 }));
+    assert_eq!(table.get(9), Err(StrError::Unterminated { offset: 9 }));
+    assert_eq!(
+        StrError::Unterminated { offset: 6 }.to_string(),
+        "String at offset 6 is not NUL terminated"
+    );
+}
+
+#[test]
+fn a_file_whose_dynamic_string_table_is_unterminated_is_refused() {
+    let mut bytes = data_library("value", &[0; 8], 8).finalize();
+    let metadata = parse(&bytes).unwrap();
+    let strings = metadata
+        .section_headers
+        .iter()
+        .find(|section| section.sh_type == ELF64_SECTION_HEADER_STRING_TABLE)
+        .unwrap();
+    let last = (strings.sh_offset + strings.sh_size - 1) as usize;
+    assert_eq!(bytes[last], 0);
+    bytes[last] = b'x';
+    match parse(&bytes) {
+        Err(DrowError::InvalidString { what, source }) => {
+            assert_eq!(what, "symbol name");
+            assert_eq!(source, StrError::Unterminated { offset: 1 });
+        }
+        other => panic!("unexpected result {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn invalid_utf8_is_an_error_and_replaced_when_lossy() {
+    let table = StringTable::from_bytes(b"\0ok\0bad\xFF\0");
+    assert_eq!(table.get(4), Err(StrError::InvalidUtf8 { offset: 4 }));
+    assert_eq!(
+        StrError::InvalidUtf8 { offset: 4 }.to_string(),
+        "String at offset 4 is not valid UTF-8"
+    );
+    assert!(matches!(table.get_lossy(1), Ok(Cow::Borrowed("ok"))));
+    match table.get_lossy(4) {
+        Ok(Cow::Owned(value)) => assert_eq!(value, "bad\u{FFFD}"),
+        other => panic!("unexpected result {:?}", other),
+    }
+    let strings: Vec<(u32, bool, String)> = table
+        .iter()
+        .map(|(offset, value)| (offset, matches!(value, Cow::Owned(_)), value.into_owned()))
+        .collect();
+    assert_eq!(
+        strings,
+        vec![
+            (0, false, String::new()),
+            (1, false, String::from("ok")),
+            (4, true, String::from("bad\u{FFFD}")),
+        ]
+    );
+}
+
+#[test]
+fn no_table_of_invalid_bytes_makes_the_accessors_panic() {
+    let mut bytes = Bytes(0x2545_F491_4F6C_DD1D);
+    for _ in 0..300 {
+        let content = bytes.table(24);
+        let table = StringTable::from_bytes(&content);
+        for offset in 0..=content.len() as u32 + 1 {
+            match table.get_lossy(offset) {
+                Ok(value) => {
+                    let start = offset as usize;
+                    let end = start + content[start..].iter().position(|b| *b == 0).unwrap_or(0);
+                    assert_eq!(
+                        value,
+                        String::from_utf8_lossy(&content[start..end]),
+                        "{:?} at {}",
+                        content,
+                        offset
+                    );
+                    assert_eq!(table.get(offset).is_ok(), matches!(value, Cow::Borrowed(_)));
+                }
+                Err(_) => assert!(table.get(offset).is_err(), "{:?} at {}", content, offset),
+            }
+        }
+    }
+}
+
+#[test]
+fn a_symbol_name_that_is_not_utf8_is_read_lossily() {
+    let mut bytes = data_library("value", &[0; 8], 8).finalize();
+    let strings = parse(&bytes)
+        .unwrap()
+        .section_headers
+        .into_iter()
+        .find(|section| section.sh_type == ELF64_SECTION_HEADER_STRING_TABLE)
+        .unwrap();
+    let table = strings.sh_offset as usize..(strings.sh_offset + strings.sh_size) as usize;
+    let name = bytes[table.clone()]
+        .windows(6)
+        .position(|window| window == b"value\0")
+        .unwrap()
+        + table.start;
+    bytes[name + 2] = 0xFF;
+    let metadata = parse(&bytes).unwrap();
+    assert_eq!(metadata.dynamic_symbol_table[1].symbol_name, "va\u{FFFD}ue");
+}
+
+#[test]
+fn inspect_marks_the_strings_it_read_lossily() {
+    let dir = fixture_dir("string-tables-inspect");
+    let path = write_fixture(
+        &dir,
+        "libcomment.so",
+        &data_library("value", &[0; 8], 8)
+            .add_section(
+                ".strings",
+                SECTION_TYPE_STRING_TABLE,
+                0,
+                b"\0toolchain \xFE\xFF\0clean\0",
+            )
+            .finalize(),
+    );
+    let output = Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(["inspect", &path])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("toolchain \u{FFFD}\u{FFFD} (lossy)"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("clean"), "{}", stdout);
+    assert!(!stdout.contains("clean (lossy)"), "{}", stdout);
+}
+
+#[test]
+fn an_empty_table_reads_offset_0_as_the_empty_string() {
+    let table = StringTable::from_bytes(&[]);
+    assert!(table.is_empty());
+    assert_eq!(table.len(), 0);
+    assert_eq!(table.get(0), Ok(""));
+    assert!(matches!(table.get_lossy(0), Ok(Cow::Borrowed(""))));
+    assert_eq!(
+        table.get(1),
+        Err(StrError::OutOfBounds {
+            offset: 1,
+            length: 0
+        })
+    );
+    assert_eq!(table.iter().count(), 0);
+}
+
+#[test]
+fn iter_yields_every_string_at_the_offset_get_reads_it_from() {
+    let mut bytes = Bytes(0x9E37_79B9_7F4A_7C15);
+    for _ in 0..300 {
+        let count = bytes.next() % 6;
+        let strings: Vec<String> = (0..count)
+            .map(|_| {
+                let length = bytes.next() % 5;
+                (0..length)
+                    .map(|_| (b'a' + (bytes.next() % 26) as u8) as char)
+                    .collect()
+            })
+            .collect();
+        let mut content = Vec::new();
+        let mut offsets = Vec::new();
+        for string in strings.iter() {
+            offsets.push(content.len() as u32);
+            content.extend_from_slice(string.as_bytes());
+            content.push(0);
+        }
+        let table = StringTable::from_bytes(&content);
+        assert_eq!(table.len(), content.len());
+        let read: Vec<(u32, String)> = table
+            .iter()
+            .map(|(offset, value)| (offset, value.into_owned()))
+            .collect();
+        let expected: Vec<(u32, String)> = offsets.iter().copied().zip(strings.clone()).collect();
+        assert_eq!(read, expected);
+        for (offset, string) in expected.iter() {
+            assert_eq!(table.get(*offset), Ok(string.as_str()));
+            // Any offset into a string reads its tail.
+            for skip in 0..string.len() as u32 {
+                assert_eq!(table.get(offset + skip), Ok(&string[skip as usize..]));
+            }
+        }
+        assert!(table.get(content.len() as u32).is_err() || content.is_empty());
+    }
+}
+
+#[test]
+fn the_cache_reads_each_table_of_a_file_once() {
+    let bytes = data_library("value", &[0; 8], 8).finalize();
+    let metadata = parse(&bytes).unwrap();
+    let index = metadata
+        .section_headers
+        .iter()
+        .position(|section| section.sh_type == ELF64_SECTION_HEADER_STRING_TABLE)
+        .unwrap();
+    let section = &metadata.section_headers[index];
+    let mut reader = Cursor::new(&bytes);
+    let table = StringTable::load(section, &mut reader).unwrap();
+    assert_eq!(table.len() as u64, section.sh_size);
+    assert_eq!(table.get(1), Ok("value"));
+
+    let mut cache = StringTableCache::new();
+    for _ in 0..2 {
+        let cached = cache
+            .get(&metadata.section_headers, index, &mut reader)
+            .unwrap();
+        assert_eq!(cached.get(1), Ok("value"));
+    }
+    assert_eq!(cache.reads(), vec![(index, 1)]);
+    assert!(matches!(
+        cache.get(&metadata.section_headers, 99, &mut reader),
+        Err(DrowError::Malformed { .. })
+    ));
+}
+
+/// A library needing libneeded.so, its generated sections mapped at 0x3000.
+fn needing_library() -> Vec<u8> {
+    data_library("value", &[0; 8], 8)
+        .add_needed("libneeded.so")
+        .map_dynamic(0x3000)
+        .finalize()
+}
+
+#[test]
+fn string_tables_are_keyed_by_section_index_and_found_by_address_or_offset() {
+    let bytes = needing_library();
+    let metadata = parse(&bytes).unwrap();
+    let headers = &metadata.section_headers;
+    let mut expected: Vec<usize> = (0..headers.len())
+        .filter(|index| headers[*index].sh_type == ELF64_SECTION_HEADER_STRING_TABLE)
+        .collect();
+    let section_names = metadata.elf_header.e_section_name_string_table_index as usize;
+    assert_eq!(expected.len(), 2);
+    assert!(expected.contains(&section_names));
+
+    let tables = get_string_tables_content(headers, &mut Cursor::new(&bytes)).unwrap();
+    let mut indices: Vec<usize> = tables.keys().copied().collect();
+    indices.sort_unstable();
+    expected.sort_unstable();
+    assert_eq!(indices, expected);
+
+    let dynamic_strings = *expected
+        .iter()
+        .find(|index| **index != section_names)
+        .unwrap();
+    let header = &headers[dynamic_strings];
+    assert_eq!(tables[&dynamic_strings].get(1), Ok("value"));
+    assert_eq!(
+        by_virtual_address(headers, header.sh_virtual_address),
+        Some(dynamic_strings)
+    );
+    assert_eq!(by_offset(headers, header.sh_offset), Some(dynamic_strings));
+    assert_eq!(
+        file_offset(&metadata.program_headers, header.sh_virtual_address),
+        Some(header.sh_offset)
+    );
+    // Addresses and offsets of no string table.
+    assert_eq!(
+        by_virtual_address(headers, header.sh_virtual_address + 1),
+        None
+    );
+    assert_eq!(by_offset(headers, header.sh_offset + 1), None);
+    // The section names are not mapped, the address of 0 in their header is not theirs.
+    assert_eq!(by_virtual_address(headers, 0), None);
+    assert_eq!(file_offset(&metadata.program_headers, 0x9000), None);
+}
+
+#[test]
+fn dt_strtab_not_matching_a_section_address_is_found_through_its_file_offset() {
+    let mut bytes = needing_library();
+    let metadata = parse(&bytes).unwrap();
+    let dynamic_strings = metadata
+        .section_headers
+        .iter()
+        .find(|section| section.sh_type == ELF64_SECTION_HEADER_DYNAMIC_SYMBOL_TABLE)
+        .map(|section| section.sh_link as usize)
+        .unwrap();
+    // Moves the address of .dynstr, as prelinking relocates the section headers but not
+    // DT_STRTAB.
+    let header = metadata.elf_header.e_section_header_offset as usize + dynamic_strings * 64;
+    bytes[header + 16..header + 24].copy_from_slice(&0x9000u64.to_le_bytes());
+    let metadata = parse(&bytes).unwrap();
+    assert_eq!(
+        metadata.section_headers[dynamic_strings].sh_virtual_address,
+        0x9000
+    );
+    assert_eq!(
+        metadata.dynamic.required_libraries,
+        vec![String::from("libneeded.so")]
+    );
+}
+
+#[test]
+fn each_string_table_is_read_once_per_load() {
+    let bytes = needing_library();
+    // .dynstr is the table of both .dynsym and .dynamic.
+    let metadata = parse(&bytes).unwrap();
+    let dynamic_strings = metadata
+        .section_headers
+        .iter()
+        .find(|section| section.sh_type == ELF64_SECTION_HEADER_DYNAMIC_SYMBOL_TABLE)
+        .map(|section| section.sh_link as usize)
+        .unwrap();
+    assert_eq!(metadata.string_table_reads, vec![(dynamic_strings, 1)]);
+    assert!(metadata.string_tables.is_none());
+
+    // Kept for printing, the section names are read as well, still once.
+    let section_names = metadata.elf_header.e_section_name_string_table_index as usize;
+    let metadata = Elf64Metadata::load_with_string_tables(
+        &String::from("fixture.so"),
+        &mut Cursor::new(&bytes),
+        true,
+    )
+    .unwrap();
+    assert_eq!(
+        metadata.string_table_reads,
+        vec![(dynamic_strings, 1), (section_names, 1)]
+    );
+    let tables = metadata.string_tables.unwrap();
+    assert_eq!(tables.reads(), metadata.string_table_reads);
+    assert_eq!(tables.tables()[&section_names].get(1), Ok(".data"));
+}
+
+#[test]
+fn stats_shows_each_string_table_read_once() {
+    let dir = fixture_dir("string-tables-stats");
+    let path = write_fixture(&dir, "libneeding.so", &needing_library());
+    let output = Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(["inspect", "--stats", &path])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let reads: Vec<&str> = stdout
+        .lines()
+        .filter(|line| line.starts_with("String table in section "))
+        .collect();
+    assert_eq!(
+        reads,
+        vec![
+            "String table in section 3 read 1 time(s)",
+            "String table in section 5 read 1 time(s)",
+        ],
+        "{}",
+        stdout
+    );
+}
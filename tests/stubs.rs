@@ -0,0 +1,149 @@
+//! `--stub`: the imports of the objects needing a stubbed library are bound to traps and zero
+//! filled variables, while the other objects still have to find theirs.
+
+mod common;
+
+use std::path::Path;
+
+use common::{data_library, fixture_dir, mapped_bytes, mapped_word, object_base, write_fixture};
+use drow::loader::{Elf64Loader, LoadOptions};
+use drow::{
+    DrowError, RELOCATION_X86_64_GLOB_DAT, RELOCATION_X86_64_JUMP_SLOT, SYMBOL_BINDING_GLOBAL,
+    SYMBOL_BINDING_WEAK, SYMBOL_TYPE_FUNCTION, SYMBOL_TYPE_OBJECT,
+};
+
+/// The first bytes of every trap, `mov rdi, imm64`.
+const TRAP_PREFIX: [u8; 2] = [0x48, 0xBF];
+
+/// libuser.so needing the absent libgone.so, importing the function `gone_function` into the
+/// word at 0x1000, the variable `gone_variable` at 0x1008 and the weak `gone_weak` at 0x1010.
+fn user(dir: &Path) -> String {
+    write_fixture(
+        dir,
+        "libuser.so",
+        &data_library("user_value", &[1; 24], 24)
+            .add_needed("libgone.so")
+            .add_symbol(
+                "gone_function",
+                SYMBOL_BINDING_GLOBAL,
+                SYMBOL_TYPE_FUNCTION,
+                0,
+                0,
+                0,
+            )
+            .add_symbol(
+                "gone_variable",
+                SYMBOL_BINDING_GLOBAL,
+                SYMBOL_TYPE_OBJECT,
+                0,
+                0,
+                0,
+            )
+            .add_symbol(
+                "gone_weak",
+                SYMBOL_BINDING_WEAK,
+                SYMBOL_TYPE_FUNCTION,
+                0,
+                0,
+                0,
+            )
+            .add_rela(
+                0x1000,
+                RELOCATION_X86_64_JUMP_SLOT,
+                Some("gone_function"),
+                0,
+            )
+            .add_rela(0x1008, RELOCATION_X86_64_GLOB_DAT, Some("gone_variable"), 0)
+            .add_rela(0x1010, RELOCATION_X86_64_GLOB_DAT, Some("gone_weak"), 0)
+            .map_dynamic(0x3000)
+            .finalize(),
+    )
+}
+
+/// libplain.so, needing no stubbed library, importing `missing_function`, which nothing defines.
+fn plain(dir: &Path) -> String {
+    write_fixture(
+        dir,
+        "libplain.so",
+        &data_library("plain_value", &[2; 8], 8)
+            .add_symbol(
+                "missing_function",
+                SYMBOL_BINDING_GLOBAL,
+                SYMBOL_TYPE_FUNCTION,
+                0,
+                0,
+                0,
+            )
+            .add_rela(
+                0x1000,
+                RELOCATION_X86_64_GLOB_DAT,
+                Some("missing_function"),
+                0,
+            )
+            .map_dynamic(0x3000)
+            .finalize(),
+    )
+}
+
+fn stub_loader(dir: &Path, strict: bool) -> Elf64Loader {
+    Elf64Loader::builder()
+        .offline(&[dir.to_string_lossy().into_owned()])
+        .stub("libgone.so")
+        .options(LoadOptions {
+            strict,
+            ..LoadOptions::default()
+        })
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn imports_of_a_stubbed_library_are_bound_to_stubs() {
+    let dir = fixture_dir("stubs-bound");
+    let path = user(&dir);
+    for strict in [false, true] {
+        let loader = stub_loader(&dir, strict);
+        loader.load_library(&path).unwrap();
+        let base = object_base(&loader, &path);
+        let function = loader.lookup_symbol("gone_function").unwrap();
+        assert_eq!(mapped_word(base + 0x1000), function);
+        assert_eq!(mapped_bytes(function, 2), TRAP_PREFIX);
+        let variable = loader.lookup_symbol("gone_variable").unwrap();
+        assert_eq!(mapped_word(base + 0x1008), variable);
+        assert_eq!(mapped_bytes(variable, 8), vec![0; 8]);
+        assert_eq!(mapped_word(base + 0x1010), 0x0101_0101_0101_0101);
+        assert_eq!(loader.lookup_symbol("gone_weak"), None);
+        let report = loader.load_report(false);
+        assert_eq!(report.objects.len(), 1);
+        assert_eq!(
+            report.unresolved_symbols,
+            vec![(path.clone(), String::from("gone_weak"))]
+        );
+    }
+}
+
+#[test]
+fn an_unstubbed_symbol_still_fails_under_strict_mode() {
+    let dir = fixture_dir("stubs-strict");
+    let path = plain(&dir);
+    let loader = stub_loader(&dir, false);
+    loader.load_library(&path).unwrap();
+    assert_eq!(loader.lookup_symbol("missing_function"), None);
+    assert_eq!(
+        loader.load_report(false).unresolved_symbols,
+        vec![(path.clone(), String::from("missing_function"))]
+    );
+
+    let loader = stub_loader(&dir, true);
+    match loader.load_library(&path) {
+        Err(DrowError::NotLoadable {
+            path: refused,
+            reason,
+        }) => {
+            assert_eq!(refused, path);
+            assert_eq!(reason, "no loaded object defines missing_function");
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert!(loader.load_report(false).objects.is_empty());
+}
@@ -0,0 +1,182 @@
+//! `drow inspect --summary`: one line per file, for an executable, a PIE and a shared library,
+//! with an ERROR line in place of a file that does not parse.
+
+mod common;
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+use common::{data_library, fixture_dir, write_fixture};
+use drow::dynamic::DYNAMIC_FLAGS_1_PIE;
+use drow::testutil::ElfBuilder;
+use drow::{
+    ELF_TYPE_EXECUTABLE, PROGRAM_FLAG_READ, PROGRAM_HEADER_TYPE_INTERPRETER,
+    PROGRAM_HEADER_TYPE_NOTE,
+};
+
+/// The DT_FLAGS_1 tag of the dynamic section.
+const DYNAMIC_TABLE_FLAGS_1: i64 = 0x6fff_fffb;
+
+const INTERPRETER: &str = "/lib64/ld-linux-x86-64.so.2";
+
+/// NT_GNU_BUILD_ID.
+const NOTE_GNU_BUILD_ID: u32 = 3;
+
+const BUILD_ID: [u8; 8] = [0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x01, 0x02, 0x03];
+
+fn summary(dir: &Path, arguments: &[&str], paths: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(["inspect", "--debuginfo-dir"])
+        .arg(dir)
+        .args(arguments)
+        .args(paths)
+        .env_remove("LD_LIBRARY_PATH")
+        .output()
+        .unwrap()
+}
+
+fn with_interpreter(builder: ElfBuilder) -> ElfBuilder {
+    let mut content = INTERPRETER.as_bytes().to_vec();
+    content.push(0);
+    builder.add_segment(
+        PROGRAM_HEADER_TYPE_INTERPRETER,
+        PROGRAM_FLAG_READ,
+        0x2000,
+        &content,
+        content.len() as u64,
+    )
+}
+
+/// A PT_NOTE holding the GNU build-id `BUILD_ID`.
+fn with_build_id(builder: ElfBuilder) -> ElfBuilder {
+    let mut note = Vec::new();
+    note.extend_from_slice(&4u32.to_le_bytes());
+    note.extend_from_slice(&(BUILD_ID.len() as u32).to_le_bytes());
+    note.extend_from_slice(&NOTE_GNU_BUILD_ID.to_le_bytes());
+    note.extend_from_slice(b"GNU\0");
+    note.extend_from_slice(&BUILD_ID);
+    builder.add_segment(
+        PROGRAM_HEADER_TYPE_NOTE,
+        PROGRAM_FLAG_READ,
+        0x2100,
+        &note,
+        note.len() as u64,
+    )
+}
+
+/// An executable with a build-id, a PIE and a library needing two others, in `dir`.
+fn fixtures(dir: &Path) -> (String, String, String) {
+    let executable = write_fixture(
+        dir,
+        "executable",
+        &with_build_id(with_interpreter(
+            data_library("executable_value", &[0; 8], 8)
+                .elf_type(ELF_TYPE_EXECUTABLE)
+                .entry(0x1000)
+                .add_needed("libc.so.6"),
+        ))
+        .map_dynamic(0x3000)
+        .finalize(),
+    );
+    let pie = write_fixture(
+        dir,
+        "pie",
+        &with_interpreter(
+            data_library("pie_value", &[0; 8], 8)
+                .entry(0x1000)
+                .add_dynamic(DYNAMIC_TABLE_FLAGS_1, DYNAMIC_FLAGS_1_PIE),
+        )
+        .map_dynamic(0x3000)
+        .finalize(),
+    );
+    let library = write_fixture(
+        dir,
+        "libsummary.so",
+        &data_library("library_value", &[0; 8], 8)
+            .add_needed("libfirst.so")
+            .add_needed("libsecond.so")
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    (executable, pie, library)
+}
+
+#[test]
+fn each_file_gets_one_line_in_a_fixed_order() {
+    let dir = fixture_dir("summary-plain");
+    let (executable, pie, library) = fixtures(&dir);
+    let output = summary(&dir, &["--summary"], &[&executable, &pie, &library]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(
+        lines,
+        vec![
+            format!(
+                "{} EXEC x86_64 no-pie executable {} glibc 0x1000 1 deadbeef00010203 -",
+                executable, INTERPRETER
+            ),
+            format!(
+                "{} PIE x86_64 pie pie {} glibc 0x1000 0 - -",
+                pie, INTERPRETER
+            ),
+            format!(
+                "{} DYN x86_64 no-pie shared-library - unknown 0x0 2 - -",
+                library
+            ),
+        ]
+    );
+}
+
+#[test]
+fn an_unparsable_file_is_an_error_line_and_the_rest_are_still_summarized() {
+    let dir = fixture_dir("summary-error");
+    let (executable, _, library) = fixtures(&dir);
+    let broken = write_fixture(&dir, "broken", b"not an ELF file");
+    let output = summary(
+        &dir,
+        &["--summary-format", "csv"],
+        &[&executable, &broken, &library],
+    );
+    assert!(!output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 4, "{}", stdout);
+    assert_eq!(
+        lines[0],
+        "path,type,machine,pie,kind,interpreter,libc,entry,needed,build_id,debuginfo"
+    );
+    assert!(
+        lines[1].starts_with(&format!("{},EXEC,x86_64,no-pie,", executable)),
+        "{}",
+        stdout
+    );
+    assert!(
+        lines[2].starts_with(&format!("{},ERROR,", broken)),
+        "{}",
+        stdout
+    );
+    assert!(
+        lines[3].starts_with(&format!("{},DYN,x86_64,", library)),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn tsv_separates_fields_with_tabs() {
+    let dir = fixture_dir("summary-tsv");
+    let (_, pie, _) = fixtures(&dir);
+    let output = summary(&dir, &["--summary-format=tsv"], &[&pie]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0].split('\t').count(), 11, "{}", stdout);
+    assert_eq!(
+        lines[1],
+        format!(
+            "{}\tPIE\tx86_64\tpie\tpie\t{}\tglibc\t0x1000\t0\t-\t-",
+            pie, INTERPRETER
+        )
+    );
+}
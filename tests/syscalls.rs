@@ -0,0 +1,139 @@
+//! Failing system calls: each error names what failed and carries the errno of the call, the
+//! same whether the calls go through inline assembly or, with `libc-syscalls`, through libc.
+
+mod common;
+
+use std::fs::File;
+
+use common::{data_library, fixture_dir, write_fixture};
+use drow::cache::LibraryCache;
+use drow::loader::{Elf64Loader, LoadOptions};
+use drow::memory_elf::MemoryBackedElf;
+use drow::offset_reader::OffsetReader;
+use drow::DrowError;
+
+#[test]
+fn opening_a_missing_file_is_an_io_error_naming_the_path() {
+    let dir = fixture_dir("syscalls-open");
+    let path = dir.join("ld.so.cache").to_string_lossy().into_owned();
+    let Err(err) = LibraryCache::load(&path) else {
+        panic!("{} does not exist", path);
+    };
+    let DrowError::Io { source, .. } = &err else {
+        panic!("{}", err);
+    };
+    assert_eq!(source.raw_os_error(), Some(libc::ENOENT));
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "Unable to access {}: No such file or directory (os error 2)",
+            path
+        )
+    );
+}
+
+#[test]
+fn mapping_nothing_or_a_directory_is_a_map_error() {
+    let dir = fixture_dir("syscalls-mmap");
+    // mmap refuses a length of 0, and files it cannot map.
+    let empty = write_fixture(&dir, "empty.cache", &[]);
+    let cases = [
+        (empty.as_str(), libc::EINVAL, "Invalid argument"),
+        (dir.to_str().unwrap(), libc::ENODEV, "No such device"),
+    ];
+    for (path, errno, message) in cases.iter() {
+        let Err(err) = LibraryCache::load(path) else {
+            panic!("{} was mapped", path);
+        };
+        let DrowError::MapFailed {
+            address, source, ..
+        } = &err
+        else {
+            panic!("{}", err);
+        };
+        assert_eq!(*address, 0);
+        assert_eq!(source.raw_os_error(), Some(*errno), "{}", path);
+        assert!(err.to_string().starts_with("Unable to map "), "{}", err);
+        assert!(
+            err.to_string()
+                .ends_with(&format!("{} (os error {})", message, errno)),
+            "{}",
+            err
+        );
+    }
+}
+
+#[test]
+fn reading_a_directory_carries_the_errno_of_pread() {
+    let dir = fixture_dir("syscalls-pread");
+    let reader = OffsetReader::new(File::open(&dir).unwrap());
+    let err = reader.read_at(&mut [0; 8], 0).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EISDIR));
+}
+
+#[test]
+fn a_stack_larger_than_the_address_space_is_a_map_error() {
+    let dir = fixture_dir("syscalls-stack");
+    let size = 1 << 62;
+    let loader = Elf64Loader::builder()
+        .offline(&[dir.to_string_lossy().into_owned()])
+        .options(LoadOptions {
+            stack_size: size,
+            ..LoadOptions::default()
+        })
+        .build()
+        .unwrap();
+    let err = loader.allocate_stack().unwrap_err();
+    let DrowError::MapFailed { length, source, .. } = &err else {
+        panic!("{}", err);
+    };
+    assert_eq!(*length, size as u64);
+    assert_eq!(source.raw_os_error(), Some(libc::ENOMEM));
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "Unable to map {} bytes at 0x0: Cannot allocate memory (os error 12)",
+            size
+        )
+    );
+}
+
+#[test]
+fn an_error_returned_by_the_kernel_is_its_errno() {
+    // memfd_create takes names of up to 249 bytes, the kernel returns -EINVAL past that.
+    let name = "a".repeat(250);
+    let Err(err) = MemoryBackedElf::from_bytes(&name, b"") else {
+        panic!("a {}-byte name was accepted", name.len());
+    };
+    let DrowError::Syscall { call, source } = &err else {
+        panic!("{}", err);
+    };
+    assert_eq!(call, &format!("memfd_create({})", name));
+    assert_eq!(source.raw_os_error(), Some(libc::EINVAL));
+    assert!(
+        err.to_string()
+            .ends_with(" failed: Invalid argument (os error 22)"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn a_sealed_copy_is_created_and_read_back() {
+    let bytes = data_library("value", &[3; 8], 8).finalize();
+    let image = MemoryBackedElf::from_bytes("sealed", &bytes).unwrap();
+    assert_eq!(image.name(), "memfd:sealed");
+    let metadata = image.metadata().unwrap();
+    assert_eq!(metadata.file_path, "memfd:sealed");
+    assert!(metadata
+        .dynamic_symbol_table
+        .iter()
+        .any(|symbol| symbol.symbol_name == "value"));
+    let mut content = vec![0; bytes.len()];
+    image
+        .reader()
+        .unwrap()
+        .read_exact_at(&mut content, 0)
+        .unwrap();
+    assert_eq!(content, bytes);
+}
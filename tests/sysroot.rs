@@ -0,0 +1,164 @@
+//! `--sysroot`: libraries resolved against a miniature root filesystem with its own cache,
+//! default directories and absolute symbolic links, the input file opened where it is.
+
+mod common;
+
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use common::{data_library, fixture_dir, library_cache, object_base, write_fixture};
+use drow::loader::{Elf64Loader, LibraryOrigin};
+use drow::MACHINE_X86_64;
+
+/// The flags of a 64-bit x86 libc6 entry of ld.so.cache.
+const CACHE_FLAGS_X86_64: i32 = 0x0303;
+
+fn library(symbol: &str) -> Vec<u8> {
+    data_library(symbol, &[1; 8], 8)
+        .map_dynamic(0x3000)
+        .finalize()
+}
+
+/// A root filesystem in `dir/rootfs`, with libcached.so listed in its /etc/ld.so.cache,
+/// libdefault.so in /lib64 and liblinked.so in /usr/lib, an absolute link to libtarget.so.1,
+/// and a program needing the three outside of it. Returns the root and the program.
+fn fixtures(dir: &Path) -> (PathBuf, String) {
+    let root = dir.join("rootfs");
+    for directory in ["etc", "lib64", "usr/lib"] {
+        std::fs::create_dir_all(root.join(directory)).unwrap();
+    }
+    write_fixture(
+        &root.join("etc"),
+        "ld.so.cache",
+        &library_cache(&[("libcached.so", "/usr/lib/libcached.so", CACHE_FLAGS_X86_64)]),
+    );
+    let usr_lib = root.join("usr/lib");
+    write_fixture(&usr_lib, "libcached.so", &library("cached_value"));
+    write_fixture(
+        &root.join("lib64"),
+        "libdefault.so",
+        &library("default_value"),
+    );
+    write_fixture(&usr_lib, "libtarget.so.1", &library("linked_value"));
+    // Absolute, as on the target, where it does not leave the root.
+    symlink("/usr/lib/libtarget.so.1", usr_lib.join("liblinked.so")).unwrap();
+    let program = write_fixture(
+        dir,
+        "libprogram.so",
+        &data_library("program_value", &[0; 8], 8)
+            .add_needed("libcached.so")
+            .add_needed("libdefault.so")
+            .add_needed("liblinked.so")
+            .map_dynamic(0x3000)
+            .finalize(),
+    );
+    (root, program)
+}
+
+fn loader(root: &Path) -> Elf64Loader {
+    Elf64Loader::builder()
+        .sysroot(&root.to_string_lossy())
+        .ld_library_path(Some("/usr/lib"))
+        .build()
+        .unwrap()
+}
+
+fn host(root: &Path, path: &str) -> String {
+    root.join(path).to_string_lossy().into_owned()
+}
+
+#[test]
+fn every_search_path_is_inside_the_sysroot() {
+    let dir = fixture_dir("sysroot-resolve");
+    let (root, _) = fixtures(&dir);
+    let mut resolver = loader(&root).into_dependencies_resolver();
+    assert_eq!(
+        resolver.sysroot().map(|sysroot| sysroot.root()),
+        Some(root.as_path())
+    );
+    let cases = [
+        (
+            "libcached.so",
+            vec![host(&root, "usr/lib/libcached.so")],
+            LibraryOrigin::Cache,
+        ),
+        (
+            "libdefault.so",
+            vec![host(&root, "lib64/libdefault.so")],
+            LibraryOrigin::Default,
+        ),
+        (
+            "liblinked.so",
+            vec![host(&root, "usr/lib/libtarget.so.1")],
+            LibraryOrigin::LdPath,
+        ),
+        ("libabsent.so", Vec::new(), LibraryOrigin::Missing),
+    ];
+    for (library, paths, origin) in cases.iter() {
+        let resolved = resolver
+            .resolve_path_with_origin(&library.to_string(), MACHINE_X86_64)
+            .unwrap();
+        assert_eq!(&resolved, &(paths.clone(), *origin), "{}", library);
+    }
+}
+
+#[test]
+fn a_program_outside_the_sysroot_loads_its_libraries_from_it() {
+    let dir = fixture_dir("sysroot-load");
+    let (root, program) = fixtures(&dir);
+    let loader = loader(&root);
+    loader.load_library(&program).unwrap();
+    for (path, symbol) in [
+        ("usr/lib/libcached.so", "cached_value"),
+        ("lib64/libdefault.so", "default_value"),
+        ("usr/lib/libtarget.so.1", "linked_value"),
+    ] {
+        let path = host(&root, path);
+        assert_eq!(
+            loader.lookup_symbol_in(&path, symbol),
+            Some(object_base(&loader, &path) + 0x1000),
+            "{}",
+            path
+        );
+    }
+}
+
+#[test]
+fn resolve_names_the_sysroot() {
+    let dir = fixture_dir("sysroot-cli");
+    let (root, program) = fixtures(&dir);
+    let output = Command::new(env!("CARGO_BIN_EXE_drow"))
+        .arg("resolve")
+        .arg("--sysroot")
+        .arg(&root)
+        .arg(&program)
+        .env_remove("LD_LIBRARY_PATH")
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with(&format!("Sysroot: {}\n", root.display())),
+        "{}",
+        stdout
+    );
+    let rows: Vec<Vec<&str>> = stdout
+        .lines()
+        .skip(2)
+        .map(|line| line.split_whitespace().collect())
+        .collect();
+    let cached = host(&root, "usr/lib/libcached.so");
+    let default = host(&root, "lib64/libdefault.so");
+    // Without LD_LIBRARY_PATH, /usr/lib of the root is searched as a default directory.
+    let linked = host(&root, "usr/lib/libtarget.so.1");
+    assert_eq!(
+        rows,
+        vec![
+            vec!["libprogram.so", program.as_str(), "Input"],
+            vec!["libcached.so", cached.as_str(), "Cache"],
+            vec!["libdefault.so", default.as_str(), "Default"],
+            vec!["liblinked.so", linked.as_str(), "Default"],
+        ]
+    );
+}
@@ -0,0 +1,296 @@
+//! TLS modules: the IDs and static blocks the registry gives them, and what TLS relocations
+//! write from them.
+
+mod common;
+
+use std::path::Path;
+use std::process::Command;
+
+use common::{
+    compile, data_library, fixture_dir, mapped_bytes, mapped_word, object_base, offline_loader,
+    write_fixture,
+};
+use drow::loader::Elf64Loader;
+use drow::testutil::ElfBuilder;
+use drow::tls::{tls_get_addr, TlsIndex, TlsModule};
+use drow::DrowError;
+use drow::{
+    PROGRAM_FLAG_READ, PROGRAM_HEADER_TYPE_TLS, RELOCATION_X86_64_DPTMOD64,
+    RELOCATION_X86_64_DTPOFF64, RELOCATION_X86_64_TPOFF64, SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_TLS,
+};
+
+/// Exits with its initialized thread-local variable plus its zero filled one, with no libc.
+const THREAD_LOCAL_PROGRAM: &str = "\
+__thread int initialized = 42;
+__thread int zeroed;
+void _start(void) {
+    __asm__ volatile(\"syscall\" : : \"a\"(60), \"D\"(initialized + zeroed));
+    __builtin_unreachable();
+}
+";
+
+/// Bytes of the TLS block of every fixture, the first half of them initialized.
+const BLOCK_SIZE: u64 = 0x20;
+
+/// A library whose writable segment at 0x1000 starts with its TLS image, 16 bytes of `byte`,
+/// followed by words for relocations from 0x1020. It defines the thread-local `symbol` at
+/// offset 8 of its block.
+fn tls_library(symbol: &str, byte: u8) -> ElfBuilder {
+    let mut content = vec![0u8; 0x60];
+    content[..0x10].fill(byte);
+    data_library(&format!("{}_value", symbol), &content, 0x60)
+        .add_segment(
+            PROGRAM_HEADER_TYPE_TLS,
+            PROGRAM_FLAG_READ,
+            0x1000,
+            &content[..0x10],
+            BLOCK_SIZE,
+        )
+        .add_symbol(symbol, SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_TLS, 1, 8, 8)
+}
+
+/// libfirst.so and libsecond.so, each defining a thread-local variable named after it, and
+/// libuser.so needing both, defining `user` and importing `second`, with one relocation of each
+/// of `relocations`, as (type, symbol, addend), in consecutive words from 0x1020. Returns the
+/// path of libuser.so.
+fn tls_fixtures(dir: &Path, relocations: &[(u64, Option<&str>, i64)]) -> String {
+    write_fixture(dir, "libfirst.so", &tls_library("first", 1).finalize());
+    write_fixture(dir, "libsecond.so", &tls_library("second", 2).finalize());
+    let user = tls_library("user", 3)
+        .add_needed("libfirst.so")
+        .add_needed("libsecond.so")
+        .add_symbol("second", SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_TLS, 0, 0, 0);
+    let user = relocations.iter().enumerate().fold(
+        user,
+        |builder, (index, (relocation_type, symbol, addend))| {
+            builder.add_rela(
+                0x1020 + 8 * index as u64,
+                *relocation_type,
+                *symbol,
+                *addend,
+            )
+        },
+    );
+    write_fixture(dir, "libuser.so", &user.finalize())
+}
+
+/// The paths of the objects `loader` loaded, in loading order.
+fn loaded_paths(loader: &Elf64Loader) -> Vec<String> {
+    loader
+        .load_report(false)
+        .objects
+        .into_iter()
+        .map(|object| object.path)
+        .collect()
+}
+
+fn words(address: u64, count: usize) -> Vec<u64> {
+    (0..count as u64)
+        .map(|index| mapped_word(address + 8 * index))
+        .collect()
+}
+
+#[test]
+fn modules_get_ids_in_loading_order_and_disjoint_static_blocks() {
+    let dir = fixture_dir("tls-modules");
+    let user = tls_fixtures(&dir, &[]);
+    let mut loader = offline_loader(&dir);
+    loader.load_library(&user).unwrap();
+    let paths = loaded_paths(&loader);
+    let bases: Vec<u64> = paths
+        .iter()
+        .map(|path| object_base(&loader, path))
+        .collect();
+    let registry = loader.tls_registry();
+    let modules: Vec<&TlsModule> = paths
+        .iter()
+        .map(|path| registry.module_of(path).unwrap())
+        .collect();
+    assert_eq!(registry.modules().len(), 3);
+    assert!(modules.windows(2).all(|pair| pair[0].id < pair[1].id));
+    assert_eq!(registry.max_id(), modules[2].id);
+    for (module, base) in modules.iter().zip(bases.iter()) {
+        assert_eq!(module.segment.module_id, Some(module.id));
+        assert_eq!(module.segment.memory_size, BLOCK_SIZE);
+        assert_eq!(module.image_address, base + 0x1000);
+        assert!(!module.dynamic());
+        assert_eq!(registry.module(module.id).unwrap().id, module.id);
+    }
+    // Blocks are placed down from the thread pointer, each below the ones registered before it.
+    let offsets: Vec<u64> = modules
+        .iter()
+        .map(|module| module.static_offset.unwrap())
+        .collect();
+    assert!(offsets
+        .iter()
+        .all(|offset| offset % modules[0].segment.alignment == 0));
+    assert!(offsets[0] >= BLOCK_SIZE);
+    assert!(offsets
+        .windows(2)
+        .all(|pair| pair[1] >= pair[0] + BLOCK_SIZE));
+    assert_eq!(registry.static_size(), offsets[2]);
+    for (name, module) in ["first", "second", "user"].iter().zip(modules.iter()) {
+        let (defining, offset) = registry.symbol(name).unwrap();
+        assert_eq!((defining.id, offset), (module.id, 8), "{}", name);
+    }
+}
+
+#[test]
+fn dtpmod_and_dtpoff_are_the_module_id_and_the_offset_in_its_block() {
+    let dir = fixture_dir("tls-dynamic-relocations");
+    let user = tls_fixtures(
+        &dir,
+        &[
+            (RELOCATION_X86_64_DPTMOD64, Some("second"), 0),
+            (RELOCATION_X86_64_DTPOFF64, Some("second"), 4),
+            (RELOCATION_X86_64_DPTMOD64, None, 0),
+            (RELOCATION_X86_64_DTPOFF64, Some("user"), -8),
+        ],
+    );
+    let mut loader = offline_loader(&dir);
+    loader.load_library(&user).unwrap();
+    let base = object_base(&loader, &user);
+    let second = loaded_paths(&loader)[1].clone();
+    let registry = loader.tls_registry();
+    let second = registry.module_of(&second).unwrap().id;
+    let own = registry.module_of(&user).unwrap().id;
+    assert_ne!(second, own);
+    assert_eq!(
+        words(base + 0x1020, 4),
+        vec![second as u64, 12, own as u64, 0]
+    );
+}
+
+#[test]
+fn tls_get_addr_allocates_the_block_of_a_module_loaded_after_the_program() {
+    let dir = fixture_dir("tls-get-addr");
+    let program = write_fixture(
+        &dir,
+        "program",
+        &data_library("program_value", &[0; 8], 8).finalize(),
+    );
+    let user = tls_fixtures(
+        &dir,
+        &[
+            (RELOCATION_X86_64_DPTMOD64, Some("second"), 0),
+            (RELOCATION_X86_64_DTPOFF64, Some("second"), 0),
+            (RELOCATION_X86_64_DPTMOD64, None, 0),
+            (RELOCATION_X86_64_DTPOFF64, None, 0x18),
+        ],
+    );
+    let mut loader = offline_loader(&dir);
+    loader.load_file(&program).unwrap();
+    loader.load_library(&user).unwrap();
+    let function: unsafe extern "C" fn(*const TlsIndex) -> *mut u8 = tls_get_addr;
+    assert_eq!(
+        loader.lookup_symbol("__tls_get_addr"),
+        Some(function as usize as u64)
+    );
+    let base = object_base(&loader, &user);
+    let second_base = object_base(&loader, &loaded_paths(&loader)[2]);
+    let registry = loader.tls_registry();
+    assert!(registry.modules().iter().all(TlsModule::dynamic));
+
+    let second = unsafe { tls_get_addr((base + 0x1020) as *const TlsIndex) } as u64;
+    assert_ne!(second, 0);
+    assert_eq!(mapped_bytes(second, 8), vec![2; 8]);
+    let own = unsafe { tls_get_addr((base + 0x1030) as *const TlsIndex) } as u64;
+    assert_ne!(own, 0);
+    assert_eq!(
+        mapped_bytes(own - 0x18, 0x20),
+        [[3; 0x10], [0; 0x10]].concat()
+    );
+
+    // The block is allocated once, later calls see what was written to it.
+    unsafe { *(second as *mut u64) = 0x1234 };
+    assert_eq!(
+        unsafe { tls_get_addr((base + 0x1020) as *const TlsIndex) } as u64,
+        second
+    );
+    assert_eq!(mapped_word(second), 0x1234);
+    assert_eq!(mapped_bytes(second_base + 0x1008, 8), vec![2; 8]);
+
+    let absent = TlsIndex {
+        module: 0,
+        offset: 0,
+    };
+    assert!(unsafe { tls_get_addr(&absent) }.is_null());
+}
+
+#[test]
+fn tpoff_is_the_offset_from_the_thread_pointer_with_the_program_first() {
+    let dir = fixture_dir("tls-static-relocations");
+    let first = write_fixture(&dir, "libfirst.so", &tls_library("first", 1).finalize());
+    let program = write_fixture(
+        &dir,
+        "program",
+        &tls_library("program", 4)
+            .add_needed("libfirst.so")
+            .add_symbol("first", SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_TLS, 0, 0, 0)
+            .add_rela(0x1020, RELOCATION_X86_64_TPOFF64, Some("program"), 0)
+            .add_rela(0x1028, RELOCATION_X86_64_TPOFF64, Some("first"), 4)
+            .add_rela(0x1030, RELOCATION_X86_64_TPOFF64, None, 0x10)
+            .finalize(),
+    );
+    let mut loader = offline_loader(&dir);
+    loader.load_file(&program).unwrap();
+    let base = object_base(&loader, &program);
+    assert_eq!(loaded_paths(&loader), vec![first.clone(), program.clone()]);
+    let registry = loader.tls_registry();
+    let own = registry.module_of(&program).unwrap();
+    let dependency = registry.module_of(&first).unwrap();
+    // The program is registered last but keeps the block right below the thread pointer.
+    assert!(own.id > dependency.id);
+    assert_eq!(own.static_offset, Some(BLOCK_SIZE));
+    assert_eq!(dependency.static_offset, Some(2 * BLOCK_SIZE));
+    assert_eq!(registry.static_size(), 2 * BLOCK_SIZE);
+    let own = own.thread_pointer_offset().unwrap();
+    let dependency = dependency.thread_pointer_offset().unwrap();
+    assert_eq!(
+        words(base + 0x1020, 3),
+        vec![
+            (own + 8) as u64,
+            (dependency + 8 + 4) as u64,
+            (own + 0x10) as u64
+        ]
+    );
+}
+
+#[test]
+fn tpoff_to_a_module_loaded_after_the_program_is_an_error() {
+    let dir = fixture_dir("tls-late-static");
+    let program = write_fixture(
+        &dir,
+        "program",
+        &data_library("program_value", &[0; 8], 8).finalize(),
+    );
+    let user = tls_fixtures(&dir, &[(RELOCATION_X86_64_TPOFF64, Some("second"), 0)]);
+    let loader = offline_loader(&dir);
+    loader.load_file(&program).unwrap();
+    match loader.load_library(&user) {
+        Err(DrowError::NotLoadable { path, reason }) => {
+            assert_eq!(path, user);
+            assert!(
+                reason.starts_with("second uses static TLS of ")
+                    && reason.ends_with("libsecond.so, which was loaded after the program"),
+                "{}",
+                reason
+            );
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn a_program_reads_its_thread_local_variables() {
+    let dir = fixture_dir("tls-program");
+    let Some(program) = compile(&dir, "thread-local", THREAD_LOCAL_PROGRAM, &["-nostdlib"]) else {
+        return;
+    };
+    let output = Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(["run", &program])
+        .env_remove("LD_LIBRARY_PATH")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(42), "{:?}", output);
+}
@@ -0,0 +1,441 @@
+//! Symbol versions required from dependencies: missing ones, `--best-effort` and exact binding.
+
+mod common;
+
+use std::convert::TryInto;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use common::{data_library, fixture_dir, mapped_bytes, write_fixture, SECTION_TYPE_PROGRAM_BITS};
+use drow::libc_flavor::LibcFlavor;
+use drow::loader::{Elf64Loader, LoadOptions};
+use drow::testutil::ElfBuilder;
+use drow::versions::{dynamic_symbol_versions, version_definitions};
+use drow::{
+    DrowError, Elf64Metadata, PROGRAM_FLAG_READ, PROGRAM_FLAG_WRITE, PROGRAM_HEADER_TYPE_LOADABLE,
+    RELOCATION_X86_64_64, SECTION_FLAG_ALLOCATED, SECTION_FLAG_WRITE, SYMBOL_BINDING_GLOBAL,
+    SYMBOL_TYPE_OBJECT,
+};
+
+/// Defines `value` under DROW_1.0, DROW_2.0 and, as the default, DROW_10.0, 8 bytes apart from
+/// 0x1000, `foreign` under OTHER_1.0 only and `retired` under the hidden DROW_1.0 only.
+fn provider() -> Vec<u8> {
+    let object = |builder: ElfBuilder, name: &str, value: u64| {
+        builder.add_symbol(name, SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_OBJECT, 1, value, 8)
+    };
+    let builder = data_library("unversioned", &[0; 32], 32)
+        .add_version_definition("libprovider.so")
+        .add_version_definition("DROW_1.0")
+        .add_version_definition("DROW_2.0")
+        .add_version_definition("DROW_10.0")
+        .add_version_definition("OTHER_1.0");
+    let builder = object(builder, "value", 0x1000).symbol_version("DROW_1.0", true);
+    let builder = object(builder, "value", 0x1008).symbol_version("DROW_2.0", true);
+    let builder = object(builder, "value", 0x1010).symbol_version("DROW_10.0", false);
+    let builder = object(builder, "foreign", 0x1018).symbol_version("OTHER_1.0", false);
+    let builder = object(builder, "retired", 0x1000).symbol_version("DROW_1.0", true);
+    builder.map_dynamic(0x3000).finalize()
+}
+
+/// Needs `symbols` of `library` at `version`, or unversioned, each written to a word of its data
+/// at 0x1000.
+fn requester(library: &str, version: Option<&str>, symbols: &[&str]) -> Vec<u8> {
+    let content = vec![0u8; 8 * symbols.len()];
+    let mut builder = ElfBuilder::new()
+        .add_segment(
+            PROGRAM_HEADER_TYPE_LOADABLE,
+            PROGRAM_FLAG_READ | PROGRAM_FLAG_WRITE,
+            0x1000,
+            &content,
+            content.len() as u64,
+        )
+        .add_segment_section(
+            ".data",
+            SECTION_TYPE_PROGRAM_BITS,
+            SECTION_FLAG_ALLOCATED | SECTION_FLAG_WRITE,
+            0x1000,
+            content.len() as u64,
+        )
+        .add_needed(library);
+    if let Some(version) = version {
+        builder = builder.add_version_requirement(library, version);
+    }
+    for (index, symbol) in symbols.iter().enumerate() {
+        builder = builder.add_symbol(symbol, SYMBOL_BINDING_GLOBAL, SYMBOL_TYPE_OBJECT, 0, 0, 0);
+        if let Some(version) = version {
+            builder = builder.symbol_version(version, false);
+        }
+        builder = builder.add_rela(
+            0x1000 + 8 * index as u64,
+            RELOCATION_X86_64_64,
+            Some(symbol),
+            0,
+        );
+    }
+    builder.map_dynamic(0x3000).finalize()
+}
+
+/// Writes the provider and a requester of `value` and `foreign` at DROW_9.0, newer than any
+/// version the provider defines, and returns the path of the requester.
+fn future_fixtures(dir: &Path) -> String {
+    write_fixture(dir, "libprovider.so", &provider());
+    write_fixture(
+        dir,
+        "libfuture.so",
+        &requester("libprovider.so", Some("DROW_9.0"), &["value", "foreign"]),
+    )
+}
+
+fn loader(dir: &Path, options: LoadOptions) -> Elf64Loader {
+    Elf64Loader::builder()
+        .offline(&[dir.to_string_lossy().into_owned()])
+        .options(options)
+        .build()
+        .unwrap()
+}
+
+/// A loader applying glibc's symbol versioning, whatever the fixtures are named.
+fn glibc_loader(dir: &Path, strict: bool) -> Elf64Loader {
+    Elf64Loader::builder()
+        .offline(&[dir.to_string_lossy().into_owned()])
+        .libc(LibcFlavor::Glibc)
+        .options(LoadOptions {
+            strict,
+            ..LoadOptions::default()
+        })
+        .build()
+        .unwrap()
+}
+
+fn base_of(loader: &Elf64Loader, path: &str) -> u64 {
+    loader
+        .load_report(false)
+        .objects
+        .iter()
+        .find(|object| object.path == path)
+        .unwrap()
+        .base
+}
+
+fn word(address: u64) -> u64 {
+    u64::from_le_bytes(mapped_bytes(address, 8).try_into().unwrap())
+}
+
+fn drow(dir: &Path, arguments: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_drow"))
+        .args(["run", "--no-exec", "--offline", "--search-dir"])
+        .arg(dir)
+        .args(arguments)
+        .env_remove("LD_LIBRARY_PATH")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn versions_are_read_from_the_fixture() {
+    let bytes = provider();
+    let metadata =
+        Elf64Metadata::load(&String::from("libprovider.so"), &mut Cursor::new(&bytes)).unwrap();
+    let mut reader = Cursor::new(&bytes);
+    assert_eq!(
+        version_definitions(&metadata, &mut reader).unwrap(),
+        vec![
+            "libprovider.so",
+            "DROW_1.0",
+            "DROW_2.0",
+            "DROW_10.0",
+            "OTHER_1.0"
+        ]
+    );
+    let versions: Vec<Option<(String, bool)>> = dynamic_symbol_versions(&metadata, &mut reader)
+        .unwrap()
+        .into_iter()
+        .map(|version| version.map(|version| (version.name, version.hidden)))
+        .collect();
+    let version = |name: &str, hidden| Some((String::from(name), hidden));
+    assert_eq!(
+        versions,
+        vec![
+            None,
+            None,
+            version("DROW_1.0", true),
+            version("DROW_2.0", true),
+            version("DROW_10.0", false),
+            version("OTHER_1.0", false),
+            version("DROW_1.0", true),
+        ]
+    );
+
+    let bytes = requester("libprovider.so", Some("DROW_9.0"), &["value", "foreign"]);
+    let metadata =
+        Elf64Metadata::load(&String::from("libfuture.so"), &mut Cursor::new(&bytes)).unwrap();
+    let requirements = &metadata.version_requirements;
+    assert_eq!(requirements.len(), 1);
+    assert_eq!(requirements[0].file, "libprovider.so");
+    assert_eq!(requirements[0].version, "DROW_9.0");
+    assert_eq!(requirements[0].symbols, vec!["value", "foreign"]);
+    assert_eq!(
+        metadata.dynamic_symbol_table[1].versioned_name(),
+        "value@DROW_9.0"
+    );
+}
+
+#[test]
+fn missing_future_version_is_reported_with_its_symbols() {
+    let dir = fixture_dir("versions-missing");
+    let path = future_fixtures(&dir);
+    let loader = loader(&dir, LoadOptions::default());
+    let missing = match loader.load_library(&path) {
+        Err(DrowError::MissingVersions { missing }) => missing,
+        other => panic!("unexpected result {:?}", other),
+    };
+    assert_eq!(missing.len(), 1);
+    let missing = &missing[0];
+    assert_eq!(missing.requester, path);
+    assert_eq!(missing.library, "libprovider.so");
+    assert_eq!(
+        missing.provider,
+        dir.join("libprovider.so").to_str().unwrap()
+    );
+    assert_eq!(missing.version, "DROW_9.0");
+    assert_eq!(missing.symbols, vec!["value", "foreign"]);
+    // DROW_10.0 is newer, and foreign has no DROW_* version at all.
+    assert_eq!(missing.fallbacks.len(), 1);
+    assert_eq!(missing.fallbacks[0].symbol, "value");
+    assert_eq!(missing.fallbacks[0].version, "DROW_2.0");
+    assert_eq!(missing.fallbacks[0].value, 0x1008);
+    assert_eq!(
+        missing.describe(),
+        format!(
+            "{} requires DROW_9.0 from libprovider.so, which {} does not define, for value, \
+             foreign",
+            missing.requester, missing.provider
+        )
+    );
+    assert!(loader.load_report(false).objects.is_empty());
+}
+
+#[test]
+fn best_effort_binds_the_closest_older_version() {
+    let dir = fixture_dir("versions-best-effort");
+    let path = future_fixtures(&dir);
+    let provider_path = dir.join("libprovider.so").to_string_lossy().into_owned();
+    let loader = loader(
+        &dir,
+        LoadOptions {
+            best_effort: true,
+            ..LoadOptions::default()
+        },
+    );
+    loader.load_library(&path).unwrap();
+    let base = base_of(&loader, &path);
+    let provider_base = base_of(&loader, &provider_path);
+    assert_eq!(word(base + 0x1000), provider_base + 0x1008);
+    assert_eq!(word(base + 0x1008), provider_base + 0x1018);
+    let report = loader.load_report(false);
+    assert_eq!(report.missing_versions.len(), 1);
+    assert_eq!(report.missing_versions[0].version, "DROW_9.0");
+}
+
+#[test]
+fn run_reports_the_missing_version_and_binds_it_with_best_effort() {
+    let dir = fixture_dir("versions-cli");
+    let path = future_fixtures(&dir);
+    let output = drow(&dir, &[&path]);
+    assert!(!output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("requires DROW_9.0 from libprovider.so"),
+        "{}",
+        stderr
+    );
+
+    let output = drow(&dir, &["--best-effort", &path]);
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "Binding value@DROW_9.0 to value@DROW_2.0, it may not behave as {} expects",
+            path
+        )),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("has no version of foreign older than DROW_9.0"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn json_report_lists_the_missing_version() {
+    let dir = fixture_dir("versions-report");
+    let path = future_fixtures(&dir);
+    let report = dir.join("report.json");
+    let output = drow(
+        &dir,
+        &["--best-effort", "--report", report.to_str().unwrap(), &path],
+    );
+    assert!(output.status.success(), "{:?}", output);
+    let report = fs::read_to_string(&report).unwrap();
+    assert!(report.contains("\"DROW_9.0\""), "{}", report);
+    assert!(report.contains("\"DROW_2.0\""), "{}", report);
+}
+
+/// A libc.so.6 defining `memcpy` under GLIBC_2.2.5 at 0x1000 and, as the default, under
+/// GLIBC_2.14 at 0x1008, as glibc does since memcpy stopped allowing overlaps.
+fn libc() -> Vec<u8> {
+    data_library("unversioned", &[0; 16], 16)
+        .add_version_definition("libc.so.6")
+        .add_version_definition("GLIBC_2.2.5")
+        .add_version_definition("GLIBC_2.14")
+        .add_symbol(
+            "memcpy",
+            SYMBOL_BINDING_GLOBAL,
+            SYMBOL_TYPE_OBJECT,
+            1,
+            0x1000,
+            8,
+        )
+        .symbol_version("GLIBC_2.2.5", true)
+        .add_symbol(
+            "memcpy",
+            SYMBOL_BINDING_GLOBAL,
+            SYMBOL_TYPE_OBJECT,
+            1,
+            0x1008,
+            8,
+        )
+        .symbol_version("GLIBC_2.14", false)
+        .finalize()
+}
+
+/// The address `memcpy@version` binds to, as an offset into the libc fixture.
+fn memcpy_binding(test: &str, version: &str) -> u64 {
+    let dir = fixture_dir(test);
+    let libc_path = write_fixture(&dir, "libc.so.6", &libc());
+    let path = write_fixture(
+        &dir,
+        "libmemcpy.so",
+        &requester("libc.so.6", Some(version), &["memcpy"]),
+    );
+    let loader = loader(&dir, LoadOptions::default());
+    loader.load_library(&path).unwrap();
+    assert_eq!(loader.libc_flavor(), LibcFlavor::Glibc);
+    word(base_of(&loader, &path) + 0x1000) - base_of(&loader, &libc_path)
+}
+
+#[test]
+fn versioned_reference_binds_the_exact_version() {
+    assert_eq!(memcpy_binding("versions-memcpy-2.14", "GLIBC_2.14"), 0x1008);
+}
+
+#[test]
+fn versioned_reference_binds_a_hidden_version_over_the_default() {
+    assert_eq!(
+        memcpy_binding("versions-memcpy-2.2.5", "GLIBC_2.2.5"),
+        0x1000
+    );
+}
+
+/// Loads a requester of `symbol` at `version` from the provider and returns where the reference
+/// was bound, as an offset into the provider, or None when it stayed unresolved.
+fn provider_binding(dir: &Path, version: Option<&str>, symbol: &str) -> Option<u64> {
+    let provider_path = write_fixture(dir, "libprovider.so", &provider());
+    let name = format!("lib{}-{}.so", symbol, version.unwrap_or("unversioned"));
+    let path = write_fixture(dir, &name, &requester("libprovider.so", version, &[symbol]));
+    let loader = glibc_loader(dir, false);
+    loader.load_library(&path).unwrap();
+    let unresolved = loader
+        .load_report(false)
+        .unresolved_symbols
+        .contains(&(path.clone(), symbol.to_string()));
+    let bound = word(base_of(&loader, &path) + 0x1000);
+    (!unresolved).then(|| bound - base_of(&loader, &provider_path))
+}
+
+#[test]
+fn references_bind_by_requested_and_defined_version() {
+    let dir = fixture_dir("versions-matrix");
+    let cases: &[(Option<&str>, &str, Option<u64>)] = &[
+        // An unversioned reference binds the default version, never a hidden one.
+        (None, "value", Some(0x1010)),
+        (None, "foreign", Some(0x1018)),
+        (None, "retired", None),
+        // A versioned reference binds its very version, hidden or default.
+        (Some("DROW_1.0"), "value", Some(0x1000)),
+        (Some("DROW_2.0"), "value", Some(0x1008)),
+        (Some("DROW_10.0"), "value", Some(0x1010)),
+        (Some("OTHER_1.0"), "foreign", Some(0x1018)),
+        (Some("DROW_1.0"), "retired", Some(0x1000)),
+    ];
+    for (version, symbol, expected) in cases {
+        assert_eq!(
+            provider_binding(&dir, *version, symbol),
+            *expected,
+            "{}@{:?}",
+            symbol,
+            version
+        );
+    }
+}
+
+#[test]
+fn another_version_of_the_name_is_bound_with_a_warning_and_refused_when_strict() {
+    let dir = fixture_dir("versions-other");
+    // The provider defines DROW_1.0, so the requirement is met, but foreign only has OTHER_1.0.
+    assert_eq!(
+        provider_binding(&dir, Some("DROW_1.0"), "foreign"),
+        Some(0x1018)
+    );
+    let path = dir.join("libforeign-DROW_1.0.so");
+    let path = path.to_str().unwrap();
+    let output = drow(&dir, &["--libc", "glibc", path]);
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!(
+            "{}: no loaded object defines foreign@DROW_1.0, binding foreign@@OTHER_1.0",
+            path
+        )),
+        "{}",
+        stderr
+    );
+
+    let loader = glibc_loader(&dir, true);
+    match loader.load_library(path) {
+        Err(DrowError::NotLoadable {
+            path: refused,
+            reason,
+        }) => {
+            assert_eq!(refused, path);
+            assert_eq!(reason, "no loaded object defines foreign@DROW_1.0");
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert!(loader.load_report(false).objects.is_empty());
+}
+
+#[test]
+fn a_versioned_reference_binds_an_unversioned_definition() {
+    let dir = fixture_dir("versions-unversioned-provider");
+    let provider_path = write_fixture(
+        &dir,
+        "libprovider.so",
+        &data_library("value", &[0; 8], 8).finalize(),
+    );
+    let path = write_fixture(
+        &dir,
+        "libvalue.so",
+        &requester("libprovider.so", Some("DROW_1.0"), &["value"]),
+    );
+    let loader = glibc_loader(&dir, false);
+    loader.load_library(&path).unwrap();
+    assert_eq!(
+        word(base_of(&loader, &path) + 0x1000),
+        base_of(&loader, &provider_path) + 0x1000
+    );
+}
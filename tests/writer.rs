@@ -0,0 +1,206 @@
+//! Writing parsed files back, unchanged and after edits.
+
+mod common;
+
+use std::io::Cursor;
+use std::process::Command;
+
+use common::{compile, data_library, fixture_dir, SECTION_TYPE_PROGRAM_BITS};
+use drow::writer::{self, Elf64Writer, SectionData};
+use drow::{entry_bytes, DrowError, Elf64Metadata, Elf64ProgramHeader, Elf64SectionHeader};
+
+const HELLO: &str = "#include <stdio.h>\nint main(void) { puts(\"patched\"); return 7; }\n";
+
+fn parse(name: &str, bytes: &[u8]) -> (Elf64Metadata, SectionData) {
+    let mut reader = Cursor::new(bytes);
+    let metadata = Elf64Metadata::load(&String::from(name), &mut reader).unwrap();
+    let sections = SectionData::load(&metadata, &mut reader).unwrap();
+    (metadata, sections)
+}
+
+fn write(metadata: &Elf64Metadata, sections: &SectionData) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    Elf64Writer::write(metadata, sections, &mut bytes).unwrap();
+    bytes
+}
+
+/// A library with a symbol, a needed library, spare dynamic entries and a section that is not
+/// mapped.
+fn fixture() -> Vec<u8> {
+    data_library("value", &[7; 32], 64)
+        .add_needed("libc.so.6")
+        .dynamic_terminator(true, 2)
+        .add_section(".comment", SECTION_TYPE_PROGRAM_BITS, 0, b"drow tests\0")
+        .finalize()
+}
+
+/// Fails unless `written` parses to the same headers, sections and symbols as `original`. Only
+/// the sections that are not mapped may move in the file.
+fn assert_same(original: &[u8], written: &[u8]) {
+    let (before, before_sections) = parse("original", original);
+    let (after, after_sections) = parse("written", written);
+    let program_headers = |metadata: &Elf64Metadata| -> Vec<u8> {
+        metadata
+            .program_headers
+            .iter()
+            .flat_map(|header| entry_bytes::<Elf64ProgramHeader>(header).to_vec())
+            .collect()
+    };
+    assert_eq!(program_headers(&before), program_headers(&after));
+    assert_eq!(before.section_headers.len(), after.section_headers.len());
+    for (index, (old, new)) in before
+        .section_headers
+        .iter()
+        .zip(after.section_headers.iter())
+        .enumerate()
+    {
+        let mut moved = old.clone();
+        if !old.allocated_in_memory() {
+            moved.sh_offset = new.sh_offset;
+        }
+        assert_eq!(
+            entry_bytes::<Elf64SectionHeader>(&moved),
+            entry_bytes::<Elf64SectionHeader>(new),
+            "section {}",
+            index
+        );
+        assert_eq!(
+            before_sections.section(index),
+            after_sections.section(index),
+            "content of section {}",
+            index
+        );
+    }
+    for index in 0..before.program_headers.len() {
+        assert_eq!(
+            before_sections.segment(index),
+            after_sections.segment(index)
+        );
+    }
+    assert_eq!(
+        before.dynamic.required_libraries,
+        after.dynamic.required_libraries
+    );
+    let symbols = |metadata: &Elf64Metadata| -> Vec<(String, u64)> {
+        metadata
+            .dynamic_symbol_table
+            .iter()
+            .map(|symbol| (symbol.symbol_name.clone(), symbol.value))
+            .collect()
+    };
+    assert_eq!(symbols(&before), symbols(&after));
+}
+
+#[test]
+fn unchanged_fixture_round_trips() {
+    let original = fixture();
+    let (metadata, sections) = parse("fixture", &original);
+    let written = write(&metadata, &sections);
+    assert_same(&original, &written);
+    // Writing the copy again changes nothing more.
+    let (metadata, sections) = parse("written", &written);
+    assert_eq!(write(&metadata, &sections), written);
+}
+
+#[test]
+fn set_rpath_needs_a_mapped_string_table() {
+    // The builder leaves .dynstr out of the segments, where there is nothing to move it into.
+    let (mut metadata, mut sections) = parse("fixture", &fixture());
+    match writer::set_rpath(&mut metadata, &mut sections, "/opt/drow/lib") {
+        Err(DrowError::Edit { reason, .. }) => {
+            assert!(reason.contains("not in a loadable segment"), "{}", reason)
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn set_rpath_round_trips() {
+    let dir = fixture_dir("writer-rpath");
+    let Some(program) = compile(&dir, "hello", HELLO, &[]) else {
+        return;
+    };
+    let original = std::fs::read(&program).unwrap();
+    let (unedited, _) = parse(&program, &original);
+    for rpath in [
+        "/opt/x",
+        "/opt/a/path/longer/than/the/dynamic/string/table/has/room/for",
+    ] {
+        let (mut metadata, mut sections) = parse(&program, &original);
+        writer::set_rpath(&mut metadata, &mut sections, rpath).unwrap();
+        let (written, _) = parse("written", &write(&metadata, &sections));
+        assert_eq!(written.dynamic.runpath.as_deref(), Some(rpath));
+        assert_eq!(
+            written.dynamic.required_libraries,
+            unedited.dynamic.required_libraries
+        );
+    }
+}
+
+#[test]
+fn remove_section_round_trips() {
+    let original = fixture();
+    let (mut metadata, mut sections) = parse("fixture", &original);
+    let count = metadata.section_headers.len();
+    writer::remove_section(&mut metadata, &mut sections, ".comment").unwrap();
+    let written = write(&metadata, &sections);
+    let (after, _) = parse("written", &written);
+    assert_eq!(after.section_headers.len(), count - 1);
+    assert!(!written.windows(10).any(|window| window == b"drow tests"));
+}
+
+/// Runs `path` natively, returning its output and status.
+fn run(path: &str) -> (String, Option<i32>) {
+    let output = Command::new(path).output().unwrap();
+    (
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+        output.status.code(),
+    )
+}
+
+#[test]
+fn compiled_program_round_trips() {
+    let dir = fixture_dir("writer-compiled");
+    let Some(program) = compile(&dir, "hello", HELLO, &[]) else {
+        return;
+    };
+    let original = std::fs::read(&program).unwrap();
+    let (metadata, sections) = parse(&program, &original);
+    assert_same(&original, &write(&metadata, &sections));
+}
+
+#[test]
+fn edited_program_still_runs() {
+    let dir = fixture_dir("writer-edit");
+    let Some(program) = compile(&dir, "hello", HELLO, &[]) else {
+        return;
+    };
+    for (name, arguments) in [
+        ("short-rpath", vec!["--set-rpath=/opt/x"]),
+        (
+            "long-rpath",
+            vec!["--set-rpath=/opt/a/path/longer/than/the/dynamic/string/table/has/room/for"],
+        ),
+        ("no-comment", vec!["--remove-section=.comment"]),
+        (
+            "both",
+            vec!["--set-rpath=/opt/x", "--remove-section=.comment"],
+        ),
+    ] {
+        let output = dir.join(name).to_string_lossy().into_owned();
+        let status = Command::new(env!("CARGO_BIN_EXE_drow"))
+            .arg("edit")
+            .args(&arguments)
+            .arg(format!("--output={}", output))
+            .arg(&program)
+            .status()
+            .unwrap();
+        assert!(status.success(), "{}: {}", name, status);
+        assert_eq!(
+            run(&output),
+            (String::from("patched\n"), Some(7)),
+            "{}",
+            name
+        );
+    }
+}